@@ -0,0 +1,114 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use crate::cmd::txt2seq_cmps;
+
+//*******************************************************************
+//          Chroma Estimator Struct
+//*******************************************************************
+//  オーディオ入力から 12音の強さ(chromagram)を求め、一番近い和音を推定する
+//  推定結果: (root: 0-11 の絶対音, table: CHORD_TABLE の番号)
+pub struct ChromaEstimator {
+    sample_rate: f32,
+    buf: Vec<f32>,
+    chroma: [f32; 12],
+    templates: Vec<(i16, i16, [f32; 12])>, // root, table, template
+    last_chord: Option<(i16, i16)>,
+}
+#[cfg_attr(not(feature = "audio"), allow(dead_code))]
+impl ChromaEstimator {
+    const BUF_SIZE: usize = 4096;
+    const LOWEST_NOTE: i32 = 40; // E2
+    const HIGHEST_NOTE: i32 = 88; // E6
+    const CONFIDENCE: f32 = 0.7; // これより似ていなければ採用しない
+    const MARGIN: f32 = 0.05; // 二番目の候補との差
+    const TABLES: [&'static str; 4] = ["_", "_m", "_7", "_m7"];
+
+    pub fn new(sample_rate: u32) -> Self {
+        let mut templates = Vec::new();
+        for name in Self::TABLES.iter() {
+            let tbl = txt2seq_cmps::get_table_num(name);
+            let (ptn, _) = txt2seq_cmps::get_table(tbl as usize);
+            for root in 0..12 {
+                let mut tmpl = [0.0; 12];
+                ptn.iter()
+                    .for_each(|x| tmpl[((root + *x) % 12) as usize] = 1.0);
+                templates.push((root, tbl, tmpl));
+            }
+        }
+        Self {
+            sample_rate: sample_rate as f32,
+            buf: Vec::with_capacity(Self::BUF_SIZE),
+            chroma: [0.0; 12],
+            templates,
+            last_chord: None,
+        }
+    }
+    /// サンプルを入れ、推定した和音が変わったら返す
+    pub fn put_block(&mut self, smpl: &[f32]) -> Option<(i16, i16)> {
+        let mut new_chord = None;
+        for s in smpl.iter() {
+            self.buf.push(*s);
+            if self.buf.len() >= Self::BUF_SIZE {
+                self.update_chroma();
+                self.buf.clear();
+                if let Some(chord) = self.estimate() {
+                    if self.last_chord != Some(chord) {
+                        self.last_chord = Some(chord);
+                        new_chord = Some(chord);
+                    }
+                }
+            }
+        }
+        new_chord
+    }
+    fn update_chroma(&mut self) {
+        let mut crnt = [0.0; 12];
+        for nt in Self::LOWEST_NOTE..=Self::HIGHEST_NOTE {
+            let freq = 440.0 * 2f32.powf((nt - 69) as f32 / 12.0);
+            crnt[(nt % 12) as usize] += Self::goertzel(&self.buf, freq, self.sample_rate);
+        }
+        // 前回値と平均して、ばらつきを抑える
+        for (c, n) in self.chroma.iter_mut().zip(crnt.iter()) {
+            *c = *c * 0.5 + *n * 0.5;
+        }
+    }
+    fn goertzel(buf: &[f32], freq: f32, sample_rate: f32) -> f32 {
+        let coef = 2.0 * (2.0 * std::f32::consts::PI * freq / sample_rate).cos();
+        let (mut s1, mut s2) = (0.0, 0.0);
+        for x in buf.iter() {
+            let s0 = *x + coef * s1 - s2;
+            s2 = s1;
+            s1 = s0;
+        }
+        (s1 * s1 + s2 * s2 - coef * s1 * s2).max(0.0).sqrt()
+    }
+    /// template とのコサイン類似度で和音を決める
+    fn estimate(&self) -> Option<(i16, i16)> {
+        let norm = self.chroma.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm < 1e-6 {
+            return None;
+        }
+        let mut scores: Vec<(f32, i16, i16)> = self
+            .templates
+            .iter()
+            .map(|(root, tbl, tmpl)| {
+                let dot: f32 = self
+                    .chroma
+                    .iter()
+                    .zip(tmpl.iter())
+                    .map(|(a, b)| a * b)
+                    .sum();
+                let tnorm = tmpl.iter().sum::<f32>().sqrt();
+                (dot / (norm * tnorm), *root, *tbl)
+            })
+            .collect();
+        scores.sort_by(|a, b| b.0.total_cmp(&a.0));
+        if scores[0].0 >= Self::CONFIDENCE && scores[0].0 - scores[1].0 >= Self::MARGIN {
+            Some((scores[0].1, scores[0].2))
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::time::Instant;
+
+#[cfg(feature = "audio")]
+use super::chroma::ChromaEstimator;
+use crate::lpnlib::*;
+
+//*******************************************************************
+//          Onset Detector Struct
+//*******************************************************************
+//  オーディオ入力の音量の立ち上がりから、onset(発音の瞬間)を検出する
+#[cfg_attr(not(feature = "audio"), allow(dead_code))]
+pub struct OnsetDetector {
+    threshold: f32,   // 平均エネルギーの何倍で onset とみなすか
+    avg_energy: f32,  // 直近の平均エネルギー
+    refractory: u64,  // onset 直後に検出しないサンプル数
+    since_onset: u64, // 前回 onset からのサンプル数
+}
+#[cfg_attr(not(feature = "audio"), allow(dead_code))]
+impl OnsetDetector {
+    const MIN_ENERGY: f32 = 0.0001;
+    const REFRACTORY_MSEC: u64 = 100;
+
+    /// sensitivity: 0-100 (大きいほど小さな立ち上がりにも反応する)
+    pub fn new(sample_rate: u32, sensitivity: i16) -> Self {
+        let sens = sensitivity.clamp(0, 100) as f32 / 100.0;
+        Self {
+            threshold: 1.5 + (1.0 - sens) * 3.0,
+            avg_energy: 0.0,
+            refractory: sample_rate as u64 * Self::REFRACTORY_MSEC / 1000,
+            since_onset: u64::MAX / 2,
+        }
+    }
+    /// 1ブロック分のサンプルを入れ、onset があれば true を返す
+    pub fn put_block(&mut self, smpl: &[f32]) -> bool {
+        if smpl.is_empty() {
+            return false;
+        }
+        let energy = smpl.iter().map(|x| x * x).sum::<f32>() / smpl.len() as f32;
+        self.since_onset += smpl.len() as u64;
+        let onset = energy > Self::MIN_ENERGY
+            && energy > self.avg_energy * self.threshold
+            && self.since_onset > self.refractory;
+        if onset {
+            self.since_onset = 0;
+        }
+        self.avg_energy = self.avg_energy * 0.9 + energy * 0.1;
+        onset
+    }
+}
+
+//*******************************************************************
+//          Tempo Follower Struct
+//*******************************************************************
+//  onset と拍のずれから、位相とテンポの補正量を決める
+pub struct TempoFollower {
+    err_hist: VecDeque<i32>,
+}
+impl TempoFollower {
+    const HIST_NUM: usize = 4;
+
+    #[cfg_attr(not(feature = "audio"), allow(dead_code))]
+    pub fn new() -> Self {
+        Self {
+            err_hist: VecDeque::new(),
+        }
+    }
+    /// err_tick: 一番近い拍からのずれ(+:onset が拍より後)
+    /// 戻り値: (位相を進める tick 数, bpm の増減)
+    pub fn follow(&mut self, err_tick: i32, tick_for_beat: i32) -> (i32, i16) {
+        if err_tick.abs() > tick_for_beat / 4 {
+            // 拍から遠い onset は信頼しない
+            return (0, 0);
+        }
+        self.err_hist.push_back(err_tick);
+        if self.err_hist.len() > Self::HIST_NUM {
+            self.err_hist.pop_front();
+        }
+        let mut bpm_diff = 0;
+        if self.err_hist.len() == Self::HIST_NUM {
+            // ずれが同じ向きに続いたら、テンポ自体が違うとみなす
+            let late = self.err_hist.iter().all(|x| *x > 0);
+            let early = self.err_hist.iter().all(|x| *x < 0);
+            let avg = self.err_hist.iter().sum::<i32>() / Self::HIST_NUM as i32;
+            if (late || early) && avg.abs() > tick_for_beat / 16 {
+                bpm_diff = if late { -1 } else { 1 };
+                self.err_hist.clear();
+            }
+        }
+        // 位相はずれの 1/4 だけ寄せる
+        (-err_tick / 4, bpm_diff)
+    }
+}
+
+//*******************************************************************
+//          Listener Struct
+//*******************************************************************
+//  オーディオ入力を監視し、onset の時刻や推定した和音を ElapseStack に渡す
+pub struct Listener {
+    onset_rx: mpsc::Receiver<Instant>,
+    chord_rx: mpsc::Receiver<(i16, i16)>,
+    follower: TempoFollower,
+    mode: i16,
+    hold: bool,
+    #[cfg(feature = "audio")]
+    _stream: cpal::Stream,
+}
+impl Listener {
+    #[cfg(feature = "audio")]
+    pub fn start(sensitivity: i16, mode: i16) -> Option<Self> {
+        use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+        let device = cpal::default_host().default_input_device()?;
+        let config = device.default_input_config().ok()?;
+        let sample_rate = config.sample_rate().0;
+        let (tx, onset_rx) = mpsc::channel();
+        let (chord_tx, chord_rx) = mpsc::channel();
+        let mut odt = OnsetDetector::new(sample_rate, sensitivity);
+        let mut cest = ChromaEstimator::new(sample_rate);
+        let mut put_block = move |smpl: &[f32]| {
+            if mode & LISTEN_BEAT != 0 && odt.put_block(smpl) {
+                let _ = tx.send(Instant::now());
+            }
+            if mode & LISTEN_CHORD != 0 {
+                if let Some(chord) = cest.put_block(smpl) {
+                    let _ = chord_tx.send(chord);
+                }
+            }
+        };
+        let err_fn = |e| println!("Audio Input Error! {}", e);
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => device.build_input_stream(
+                &config.into(),
+                move |data: &[f32], _: &cpal::InputCallbackInfo| put_block(data),
+                err_fn,
+                None,
+            ),
+            cpal::SampleFormat::I16 => device.build_input_stream(
+                &config.into(),
+                move |data: &[i16], _: &cpal::InputCallbackInfo| {
+                    let smpl: Vec<f32> = data.iter().map(|x| *x as f32 / 32768.0).collect();
+                    put_block(&smpl)
+                },
+                err_fn,
+                None,
+            ),
+            _ => {
+                println!("Audio Input: Unsupported sample format!");
+                return None;
+            }
+        }
+        .ok()?;
+        stream.play().ok()?;
+        println!("Audio Input: {:?}", device.name());
+        Some(Self {
+            onset_rx,
+            chord_rx,
+            follower: TempoFollower::new(),
+            mode,
+            hold: false,
+            _stream: stream,
+        })
+    }
+    #[cfg(not(feature = "audio"))]
+    pub fn start(_sensitivity: i16, _mode: i16) -> Option<Self> {
+        println!("Audio Input is not available! Build with --features audio");
+        None
+    }
+    pub fn try_onset(&self) -> Option<Instant> {
+        self.onset_rx.try_recv().ok()
+    }
+    /// hold 中は推定結果を捨てる(手動で指定した和音を優先する)
+    pub fn try_chord(&self) -> Option<(i16, i16)> {
+        let chord = self.chord_rx.try_iter().last();
+        if self.hold {
+            None
+        } else {
+            chord
+        }
+    }
+    pub fn follow(&mut self, err_tick: i32, tick_for_beat: i32) -> (i32, i16) {
+        self.follower.follow(err_tick, tick_for_beat)
+    }
+    pub fn is_chord_mode(&self) -> bool {
+        self.mode & LISTEN_CHORD != 0
+    }
+    pub fn toggle_hold(&mut self) -> bool {
+        self.hold = !self.hold;
+        self.hold
+    }
+}
@@ -0,0 +1,2 @@
+pub mod chroma;
+pub mod listen;
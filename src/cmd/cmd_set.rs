@@ -14,44 +14,49 @@ impl LoopianCmd {
                 if self.change_key(prm) {
                     "Key has changed!".to_string()
                 } else {
-                    "what?".to_string()
+                    self.wrong("what?")
                 }
             } else if cmd == "oct" {
                 let part_num = self.get_input_part();
                 if self.change_oct(prm, part_num) {
                     "Octave has changed!".to_string()
                 } else {
-                    "what?".to_string()
+                    self.wrong("what?")
                 }
             } else if cmd == "bpm" {
                 match prm.parse::<i16>() {
-                    Ok(msg) => {
+                    Ok(msg) if (MIN_BPM_VALUE..=MAX_BPM_VALUE).contains(&msg) => {
                         self.change_bpm(msg);
                         "BPM has changed!".to_string()
                     }
+                    Ok(_) => self.wrong("Number is wrong."),
                     Err(e) => {
                         println!("{:?}", e);
-                        "Number is wrong.".to_string()
+                        self.wrong("Number is wrong.")
                     }
                 }
             } else if cmd == "beat" || cmd == "meter" {
                 let numvec = split_by('/', prm.to_string());
                 if numvec.len() < 2 {
-                    "Number is wrong.".to_string()
+                    self.wrong("Number is wrong.")
                 } else {
+                    // 分母は 1,2,4,8,16 のみ(一拍の tick が割り切れること)
                     match (numvec[0].parse::<i16>(), numvec[1].parse::<i16>()) {
-                        (Ok(numerator), Ok(denomirator)) => {
+                        (Ok(numerator), Ok(denomirator))
+                            if (1..=32).contains(&numerator)
+                                && [1, 2, 4, 8, 16].contains(&denomirator) =>
+                        {
                             self.change_meter(numerator, denomirator);
                             "Meter has changed!".to_string()
                         }
-                        _ => "Number is wrong.".to_string(),
+                        _ => self.wrong("Number is wrong."),
                     }
                 }
             } else if cmd == "msr" {
                 match prm.parse::<i16>() {
                     Ok(msr) => {
                         if msr < 1 {
-                            return "Number is wrong.".to_string();
+                            return self.wrong("Number is wrong.");
                         }
                         self.set_measure(msr - 1);
                         "Measure has changed!".to_string()
@@ -62,31 +67,752 @@ impl LoopianCmd {
                 if self.change_input_mode(prm) {
                     "Input mode has changed!".to_string()
                 } else {
-                    "what?".to_string()
+                    self.wrong("what?")
                 }
             } else if cmd == "samenote" {
-                "what?".to_string()
+                self.wrong("what?")
             } else if cmd == "turnnote" {
                 if self.change_turnnote(prm) {
                     "Turn note has changed!".to_string()
                 } else {
-                    "what?".to_string()
+                    self.wrong("what?")
                 }
+            } else if cmd == "roll" {
+                match prm.parse::<i16>() {
+                    Ok(tick) if (0..=ROLL_MAX_TICK).contains(&tick) => {
+                        self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                            self.get_input_part() as i16,
+                            [MSG_SETPT_ROLL, tick],
+                        ));
+                        "Roll has changed!".to_string()
+                    }
+                    _ => self.wrong("Number is wrong."),
+                }
+            } else if cmd == "velcc" {
+                if self.change_vel_cc(prm) {
+                    "Velocity CC has changed!".to_string()
+                } else {
+                    self.wrong("what?")
+                }
+            } else if cmd == "trigger" {
+                if self.change_trigger(prm) {
+                    "Trigger has changed!".to_string()
+                } else {
+                    self.wrong("what?")
+                }
+            } else if cmd == "pan" {
+                if self.change_pan(prm) {
+                    "Pan has changed!".to_string()
+                } else {
+                    self.wrong("what?")
+                }
+            } else if cmd == "keysw" {
+                if self.change_keysw(prm) {
+                    "Keyswitch has changed!".to_string()
+                } else {
+                    self.wrong("what?")
+                }
+            } else if cmd == "tune" {
+                if self.change_tuning(prm) {
+                    "Tuning has changed!".to_string()
+                } else {
+                    self.wrong("what?")
+                }
+            } else if cmd == "bend" {
+                match prm.parse::<i16>() {
+                    Ok(range) if (1..=24).contains(&range) => {
+                        self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                            self.get_input_part() as i16,
+                            [MSG_SETPT_BEND_RANGE, range],
+                        ));
+                        "Bend range has changed!".to_string()
+                    }
+                    _ => self.wrong("Number is wrong."),
+                }
+            } else if cmd == "split" {
+                if self.change_split(prm) {
+                    "Range split has changed!".to_string()
+                } else {
+                    self.wrong("what?")
+                }
+            } else if cmd == "sustain" {
+                // ex. set.sustain(on) / set.sustain(legato,120) / set.sustain(off)
+                let prms = split_by(',', prm.to_string());
+                let pad = match prms[0].as_str() {
+                    "on" => PAD_REATTACK,
+                    "off" => PAD_OFF,
+                    "legato" => match prms.get(1).map(|x| x.parse::<i16>()) {
+                        None => 60, // 32分音符ほど重ねる
+                        Some(Ok(tick)) if (0..=DEFAULT_TICK_FOR_QUARTER as i16).contains(&tick) => {
+                            tick
+                        }
+                        _ => return self.wrong("Number is wrong."),
+                    },
+                    _ => return self.wrong("what?"),
+                };
+                self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                    self.get_input_part() as i16,
+                    [MSG_SETPT_PAD, pad],
+                ));
+                "Chord sustain has changed!".to_string()
+            } else if cmd == "chordout" {
+                // ex. set.chordout(16) / set.chordout(off)
+                let ch = match prm {
+                    "off" => 0,
+                    _ => match prm.parse::<i16>() {
+                        Ok(ch) if (1..=16).contains(&ch) => ch,
+                        _ => return self.wrong("Number is wrong."),
+                    },
+                };
+                self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                    self.get_input_part() as i16,
+                    [MSG_SETPT_CHORD_OUT, ch],
+                ));
+                "Chord output has changed!".to_string()
+            } else if cmd == "evolve" {
+                // ex. set.evolve(4) : Loop ごとに Cluster の声部を一つずつ、4つまで増やす
+                let value = match prm {
+                    "off" => 0,
+                    "reset" => EVOLVE_RESET,
+                    _ => match prm.parse::<i16>() {
+                        Ok(n) if (1..=16).contains(&n) => n,
+                        _ => return self.wrong("Number is wrong."),
+                    },
+                };
+                self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                    self.get_input_part() as i16,
+                    [MSG_SETPT_EVOLVE, value],
+                ));
+                "Evolve has changed!".to_string()
+            } else if cmd == "ramp" {
+                // ex. set.ramp(50,120) : Loop ごとに velocity を 50% から 120% へ
+                //     set.ramp(50,120,4) : 4 Loop かけて変化させる
+                let prms = split_by(',', prm.to_string());
+                let part = self.get_input_part() as i16;
+                if prms[0] == "off" {
+                    self.sndr
+                        .send_msg_to_elapse(ElpsMsg::SetPart(part, [MSG_SETPT_RAMP, -1]));
+                    return "Velocity ramp has stopped!".to_string();
+                }
+                if prms.len() < 2 || prms.len() > 3 {
+                    return self.wrong("what?");
+                }
+                let mut nums = Vec::new();
+                for (i, p) in prms.iter().enumerate() {
+                    let range = if i < 2 { 1..=200 } else { 1..=64 };
+                    match p.parse::<i16>() {
+                        Ok(n) if range.contains(&n) => nums.push(n),
+                        _ => return self.wrong("Number is wrong."),
+                    }
+                }
+                let loops = nums.get(2).copied().unwrap_or(1);
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::SetPart(part, [MSG_SETPT_RAMP_END, nums[1]]));
+                self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                    part,
+                    [MSG_SETPT_RAMP, nums[0] + (loops << 8)],
+                ));
+                "Velocity ramp has changed!".to_string()
+            } else if cmd == "phase" {
+                let global = match prm {
+                    "global" => 1,
+                    "restart" => 0,
+                    _ => return self.wrong("what?"),
+                };
+                self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                    self.get_input_part() as i16,
+                    [MSG_SETPT_GLOBAL_PHASE, global],
+                ));
+                "Loop phase has changed!".to_string()
+            } else if cmd == "songlen" {
+                match prm.parse::<i16>() {
+                    Ok(msr) => {
+                        self.sndr
+                            .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_SONG_MSR, msr.max(0)]));
+                        "Song length has changed!".to_string()
+                    }
+                    Err(_) => "Number is wrong.".to_string(),
+                }
+            } else if cmd == "disptrns" {
+                let trns = if prm == "off" {
+                    DISP_TRNS_OFF
+                } else {
+                    match prm.parse::<i16>() {
+                        Ok(t) => t.clamp(-11, 11),
+                        Err(_) => return "Number is wrong.".to_string(),
+                    }
+                };
+                self.set_disp_trns(trns);
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_DISP_TRNS, trns]));
+                "Display transpose has changed!".to_string()
+            } else if cmd == "spell" {
+                let spell = match prm {
+                    "auto" => SPELL_AUTO,
+                    "sharp" | "#" => SPELL_SHARP,
+                    "flat" | "b" => SPELL_FLAT,
+                    _ => return self.wrong("what?"),
+                };
+                self.set_spell(spell);
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_SPELL, spell]));
+                "Spelling has changed!".to_string()
+            } else if cmd == "pedrec" {
+                let rec = match prm {
+                    "on" => PEDREC_ON,
+                    "off" => PEDREC_OFF,
+                    "clear" => PEDREC_CLEAR,
+                    _ => return self.wrong("what?"),
+                };
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_PEDREC, rec]));
+                "Pedal recording has changed!".to_string()
+            } else if cmd == "pedrhythm" {
+                // ex. set.pedrhythm(on) : 和音が変わる速さに合わせて、ペダルの長さを変える
+                let rhythm = match prm {
+                    "on" => 1,
+                    "off" => 0,
+                    _ => return self.wrong("what?"),
+                };
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_PED_RHYTHM, rhythm]));
+                "Pedal rhythm has changed!".to_string()
+            } else if cmd == "tone" {
+                let emph = match prm {
+                    "on" => 0,
+                    "off" => TONE_OFF,
+                    _ => match prm.parse::<i16>() {
+                        Ok(n) if (0..=30).contains(&n) => n,
+                        _ => return self.wrong("Number is wrong."),
+                    },
+                };
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_TONE, emph]));
+                "Tone analysis has changed!".to_string()
+            } else if cmd == "chordfix" {
+                let tick = if prm == "off" {
+                    Ok(0)
+                } else {
+                    prm.parse::<i16>()
+                };
+                match tick {
+                    Ok(tick) if (0..=DEFAULT_TICK_FOR_QUARTER as i16).contains(&tick) => {
+                        self.sndr
+                            .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_CHORD_FIX, tick]));
+                        "Chord fix has changed!".to_string()
+                    }
+                    _ => self.wrong("Number is wrong."),
+                }
+            } else if cmd == "monitor" {
+                // ex. set.monitor(out,note,R1)
+                let mut filter = 0;
+                for fl in split_by(',', prm.to_string()).iter() {
+                    filter |= match fl.as_str() {
+                        "on" | "all" => 0,
+                        "off" => {
+                            filter = MONITOR_OFF;
+                            break;
+                        }
+                        "in" => MON_IN,
+                        "out" => MON_OUT,
+                        "note" => MON_NOTE,
+                        "cc" => MON_CC,
+                        "other" => MON_OTHER,
+                        "L1" => MON_PART << LEFT1,
+                        "L2" => MON_PART << LEFT2,
+                        "R1" => MON_PART << RIGHT1,
+                        "R2" => MON_PART << RIGHT2,
+                        "flow" => MON_PART << FLOW_PART,
+                        "dmp" => MON_PART << DAMPER_PEDAL_PART,
+                        _ => return self.wrong("what?"),
+                    };
+                }
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_MONITOR, filter]));
+                "MIDI monitor has changed!".to_string()
+            } else if cmd == "notename" {
+                // ex. set.notename(doremi)
+                let mode = match prm {
+                    "name" => NOTENAME_NAME,
+                    "doremi" => NOTENAME_DOREMI,
+                    "fixed" => NOTENAME_FIXED,
+                    "degree" => NOTENAME_DEGREE,
+                    "off" => NOTENAME_OFF,
+                    _ => return self.wrong("what?"),
+                };
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_NOTENAME, mode]));
+                "Note name display has changed!".to_string()
+            } else if cmd == "doubling" {
+                // ex. set.doubling(R1,L1) : R1 と同じ高さの L1 の音を省く
+                //     set.doubling(oct,R1,L1) : 省かずにオクターブをずらす
+                let mut value = 0;
+                let mut num = 0;
+                for pt in split_by(',', prm.to_string()).iter() {
+                    let part = match pt.as_str() {
+                        "off" => {
+                            value = -1;
+                            break;
+                        }
+                        "oct" => {
+                            value |= DOUBLING_OCTAVE;
+                            continue;
+                        }
+                        "L1" => LEFT1,
+                        "L2" => LEFT2,
+                        "R1" => RIGHT1,
+                        "R2" => RIGHT2,
+                        _ => return self.wrong("what?"),
+                    };
+                    if num >= MAX_KBD_PART {
+                        return self.wrong("what?");
+                    }
+                    value |= ((part + 1) as i16) << (num * 3);
+                    num += 1;
+                }
+                if value >= 0 && num < 2 {
+                    return self.wrong("what?"); // 2パート以上を指定する
+                }
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_DOUBLING, value]));
+                "Doubling filter has changed!".to_string()
+            } else if cmd == "subst" {
+                // ex. set.subst(30) : Loop ごとに 30% の確率で和音を置き換える
+                //     set.subst(30,tritone) : 裏コードへの置き換えだけ行う
+                let mut prms = split_by(',', prm.to_string()).into_iter();
+                let prob = match prms.next().as_deref() {
+                    Some("off") => {
+                        self.sndr
+                            .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_SUBST, -1]));
+                        return "Chord substitution has stopped!".to_string();
+                    }
+                    Some(num) => match num.parse::<i16>() {
+                        Ok(p) if (1..=100).contains(&p) => p,
+                        _ => return self.wrong("Number is wrong."),
+                    },
+                    None => return self.wrong("what?"),
+                };
+                let mut rules = 0;
+                for rule in prms {
+                    match rule.as_str() {
+                        "tritone" => rules |= SUBST_TRITONE,
+                        "relative" => rules |= SUBST_RELATIVE,
+                        _ => return self.wrong("what?"),
+                    }
+                }
+                if rules == 0 {
+                    rules = SUBST_TRITONE | SUBST_RELATIVE;
+                }
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_SUBST, prob + (rules << 8)]));
+                "Chord substitution has changed!".to_string()
+            } else if cmd == "quantize" {
+                // ex. set.quantize(50) : 全パートの音を 16分音符の grid に 50% 近づける
+                //     set.quantize(R1,100,e) : R1 だけ 8分音符の grid にそろえる
+                let mut prms = split_by(',', prm.to_string());
+                let part = Self::detect_part(&prms[0]);
+                if part.is_some() {
+                    prms.remove(0);
+                }
+                let value = match prms.first().map(|x| x.as_str()) {
+                    Some("off") => 0,
+                    Some("global") if part.is_some() => QUANTIZE_GLOBAL,
+                    Some(num) => {
+                        let strength = match num.parse::<i16>() {
+                            Ok(n) if (0..=100).contains(&n) => n,
+                            _ => return self.wrong("Number is wrong."),
+                        };
+                        let grid = match prms.get(1).map(|x| x.as_str()) {
+                            None | Some("v") => 2,
+                            Some("w") => 1,
+                            Some("e") => 4,
+                            Some("q") => 8,
+                            _ => return self.wrong("what?"),
+                        };
+                        strength + (grid << 8)
+                    }
+                    None => return self.wrong("what?"),
+                };
+                match part {
+                    Some(pt) => self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                        pt as i16,
+                        [MSG_SETPT_QUANTIZE, value],
+                    )),
+                    None => self
+                        .sndr
+                        .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_QUANTIZE, value])),
+                }
+                "Quantize has changed!".to_string()
+            } else if cmd == "fade" {
+                // ex. set.fade(in,8) : 8秒かけて全パートの音量を上げる
+                //     set.fade(out,8) : 8秒かけて下げ、終わったら止める
+                let prms = split_by(',', prm.to_string());
+                if prms[0] == "off" {
+                    self.sndr
+                        .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_FADE, 0]));
+                    return "Fade has been canceled!".to_string();
+                }
+                let sec = match prms.get(1).map(|x| x.parse::<i16>()) {
+                    Some(Ok(n)) if (1..=600).contains(&n) => n,
+                    _ => return self.wrong("Number is wrong."),
+                };
+                let value = match prms[0].as_str() {
+                    "in" => sec,
+                    "out" => -sec,
+                    _ => return self.wrong("what?"),
+                };
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_FADE, value]));
+                "Fade has started!".to_string()
+            } else if cmd == "clock" {
+                // ex. set.clock(on) : MIDI Clock と Start/Stop/Continue を送る
+                //     set.clock(ext) : 受信した MIDI Clock に合わせて演奏する
+                let mode = match prm {
+                    "on" => CLOCK_MASTER,
+                    "ext" => CLOCK_SLAVE,
+                    "off" => CLOCK_OFF,
+                    _ => return self.wrong("what?"),
+                };
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_CLOCK, mode]));
+                "MIDI Clock has changed!".to_string()
+            } else if cmd == "thru" {
+                // ex. set.thru(on) : Flow への入力を、和音で変換せずにすぐ出力する
+                let thru = match prm {
+                    "on" => 1,
+                    "off" => 0,
+                    _ => return self.wrong("what?"),
+                };
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_THRU, thru]));
+                "Direct thru has changed!".to_string()
+            } else if cmd == "conduct" {
+                // ex. set.conduct(80) : 今のテンポの 80% に滑らかに変える、set.conduct(100) で戻す
+                //     set.conduct(cc=20) : CC#20 で揺らす(64:100%)、set.conduct(off) : 止めて戻す
+                if prm == "off" {
+                    self.sndr
+                        .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_CONDUCT_CC, -1]));
+                    self.sndr.send_msg_to_elapse(ElpsMsg::TempoCtrl(100));
+                    return "Conduct has stopped!".to_string();
+                }
+                if let Some(cc) = prm.strip_prefix("cc=") {
+                    return match cc.parse::<i16>() {
+                        Ok(c) if (0..120).contains(&c) => {
+                            self.sndr
+                                .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_CONDUCT_CC, c]));
+                            "Conduct CC has changed!".to_string()
+                        }
+                        _ => self.wrong("Number is wrong."),
+                    };
+                }
+                match prm.parse::<i16>() {
+                    Ok(r) if (50..=200).contains(&r) => {
+                        self.sndr.send_msg_to_elapse(ElpsMsg::TempoCtrl(r));
+                        "Conduct has changed!".to_string()
+                    }
+                    _ => self.wrong("Number is wrong."),
+                }
+            } else if cmd == "lock" {
+                // ex. set.lock(1) : 小節の最後の1拍に入力した Phrase/Composition は、次の小節から
+                let beats = match prm {
+                    "off" => Some(0),
+                    "on" => Some(1),
+                    _ => prm.parse::<i16>().ok().filter(|b| (1..=8).contains(b)),
+                };
+                match beats {
+                    Some(b) => {
+                        self.sndr
+                            .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_EDIT_LOCK, b]));
+                        "Edit lock has changed!".to_string()
+                    }
+                    None => self.wrong("Number is wrong."),
+                }
+            } else if cmd == "route" {
+                // ex. set.route(R1,ch=3,port=IAC2) : R1 を ch.3 で、名前に IAC2 を含む port に出す
+                //     set.route(flow,ch=2) / set.route(damper,port=IAC2) / set.route(L1,off)
+                let mut prms = split_by(',', prm.to_string());
+                let part = match prms[0].as_str() {
+                    "flow" => Some(FLOW_PART),
+                    "damper" => Some(DAMPER_PEDAL_PART),
+                    "click" => Some(CLICK_PART),
+                    pt => Self::detect_part(pt),
+                };
+                if part.is_some() {
+                    prms.remove(0);
+                }
+                let part = part.unwrap_or(self.get_input_part()) as i16;
+                let mut ch = 0;
+                let mut port = String::new();
+                if prms.first().map(|x| x.as_str()) != Some("off") {
+                    for item in prms.iter() {
+                        match item.split_once('=') {
+                            Some(("ch", c)) => match c.parse::<u8>() {
+                                Ok(c) if (1..=16).contains(&c) => ch = c,
+                                _ => return self.wrong("Number is wrong."),
+                            },
+                            Some(("port", p)) if !p.is_empty() => port = p.to_string(),
+                            _ => return self.wrong("what?"),
+                        }
+                    }
+                    if ch == 0 && port.is_empty() {
+                        return self.wrong("what?");
+                    }
+                }
+                self.sndr.send_msg_to_elapse(ElpsMsg::Route(part, ch, port));
+                "Part routing has changed!".to_string()
+            } else if cmd == "click" {
+                // ex. set.click(ch=10,note=77,accent=76,port=IAC2) : click の ch、音、出力先
+                let mut ch = None;
+                let mut note = None;
+                let mut accent = None;
+                let mut port = None;
+                for item in split_by(',', prm.to_string()).iter() {
+                    let num = |n: &str, max: u8| n.parse::<u8>().ok().filter(|x| *x <= max);
+                    match item.split_once('=') {
+                        Some(("ch", c)) => ch = num(c, 16).filter(|c| *c > 0).or(Some(0)),
+                        Some(("note", n)) => note = num(n, 127).or(Some(128)),
+                        Some(("accent", n)) => accent = num(n, 127).or(Some(128)),
+                        Some(("port", p)) if !p.is_empty() => port = Some(p.to_string()),
+                        _ => return self.wrong("what?"),
+                    }
+                }
+                if ch == Some(0) || note == Some(128) || accent == Some(128) {
+                    return self.wrong("Number is wrong.");
+                }
+                if ch.is_none() && note.is_none() && accent.is_none() && port.is_none() {
+                    return self.wrong("what?");
+                }
+                if let Some(c) = ch {
+                    self.sndr
+                        .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_CLICK_CH, c as i16]));
+                }
+                if note.is_some() || accent.is_some() {
+                    let nt = note.unwrap_or(77) as i16;
+                    let ac = accent.or(note).unwrap_or(76) as i16;
+                    self.sndr
+                        .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_CLICK_NOTE, nt + (ac << 8)]));
+                }
+                if let Some(p) = port {
+                    self.sndr
+                        .send_msg_to_elapse(ElpsMsg::Route(CLICK_PART as i16, 0, p));
+                }
+                "Click has changed!".to_string()
+            } else if cmd == "autovari" {
+                // ex. set.autovari(dom=2,fill=3) : 属和音の小節は @2、Composition の最後の小節は @3 を鳴らす
+                //     set.autovari(L1,sub=4) : L1 の下属和音の小節は @4
+                let mut prms = split_by(',', prm.to_string());
+                let part = Self::detect_part(&prms[0]);
+                if part.is_some() {
+                    prms.remove(0);
+                }
+                let part = part.unwrap_or(self.get_input_part()) as i16;
+                let mut value = 0;
+                if prms[0] != "off" {
+                    for rule in prms.iter() {
+                        let (name, vari) = match rule.split_once('=') {
+                            Some((n, v)) => (n, v),
+                            None => return self.wrong("what?"),
+                        };
+                        let shift = match name {
+                            "dom" => 0,
+                            "sub" => 4,
+                            "fill" => 8,
+                            _ => return self.wrong("what?"),
+                        };
+                        match vari.parse::<i16>() {
+                            Ok(v) if v >= 1 && (v as usize) < MAX_VARIATION => value |= v << shift,
+                            _ => return self.wrong("Number is wrong."),
+                        }
+                    }
+                }
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::SetPart(part, [MSG_SETPT_AUTO_VARI, value]));
+                "Auto variation has changed!".to_string()
+            } else if cmd == "speed" {
+                // ex. set.speed(x2) : 現在のパートを double-time、set.speed(/2) : half-time
+                //     set.speed(R1,x1.5) : R1 を 1.5倍速、set.speed(x1) : 元に戻す
+                let mut prms = split_by(',', prm.to_string());
+                let part = Self::detect_part(&prms[0]);
+                if part.is_some() {
+                    prms.remove(0);
+                }
+                let part = part.unwrap_or(self.get_input_part()) as i16;
+                let (num, den) = match prms.first().map(|x| x.as_str()) {
+                    Some("x1.5") => (3, 2),
+                    Some(p) if p.starts_with('x') => (p[1..].parse::<i16>().unwrap_or(0), 1),
+                    Some(p) if p.starts_with('/') => (1, p[1..].parse::<i16>().unwrap_or(0)),
+                    _ => return self.wrong("what?"),
+                };
+                if !(1..=8).contains(&num) || !(1..=8).contains(&den) || prms.len() != 1 {
+                    return self.wrong("Number is wrong.");
+                }
+                self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                    part,
+                    [MSG_SETPT_SPEED, num + (den << 4)],
+                ));
+                "Speed has changed!".to_string()
+            } else if cmd == "filler" {
+                // ex. set.filler(chord) : Phrase が無い間、Composition の和音を全音符で鳴らす
+                //     set.filler(R1,arp) : R1 を四分音符の Arpeggio で、set.filler(off) : 止める
+                let mut prms = split_by(',', prm.to_string());
+                let part = Self::detect_part(&prms[0]);
+                if part.is_some() {
+                    prms.remove(0);
+                }
+                let part = part.unwrap_or(self.get_input_part()) as i16;
+                let filler = match prms.first().map(|x| x.as_str()) {
+                    Some("chord") | Some("on") => FILLER_CHORD,
+                    Some("arp") => FILLER_ARP,
+                    Some("off") => FILLER_OFF,
+                    _ => return self.wrong("what?"),
+                };
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::SetPart(part, [MSG_SETPT_FILLER, filler]));
+                "Filler has changed!".to_string()
+            } else if cmd == "vellimit" {
+                // ex. set.vellimit(30,110) : velocity を 30 から 110 の間に、端は柔らかく収める
+                //     set.vellimit(R1,30,110) : R1 を指定する
+                let mut prms = split_by(',', prm.to_string());
+                let part = Self::detect_part(&prms[0]);
+                if part.is_some() {
+                    prms.remove(0);
+                }
+                let part = part.unwrap_or(self.get_input_part()) as i16;
+                if prms[0] == "off" {
+                    self.sndr
+                        .send_msg_to_elapse(ElpsMsg::SetPart(part, [MSG_SETPT_VEL_LIMIT, -1]));
+                    self.vel_limit_stock(part as usize, None);
+                    return "Velocity limit has stopped!".to_string();
+                }
+                if prms.len() != 2 {
+                    return self.wrong("what?");
+                }
+                let (floor, ceiling) = match (prms[0].parse::<i16>(), prms[1].parse::<i16>()) {
+                    (Ok(f), Ok(c)) if 1 <= f && f <= c && c <= 127 => (f, c),
+                    _ => return self.wrong("Number is wrong."),
+                };
+                self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                    part,
+                    [MSG_SETPT_VEL_LIMIT, floor + (ceiling << 7)],
+                ));
+                self.vel_limit_stock(part as usize, Some([floor, ceiling]));
+                "Velocity limit has changed!".to_string()
+            } else if cmd == "riser" {
+                // ex. set.riser(4) : 4小節ごとに半音ずつ上げ、12半音上げたら元に戻る
+                //     set.riser(R1,2,5) : R1 を 2小節ごとに、5半音まで上げる
+                //     set.riser(4,0/5/7/12) : 4小節ごとに、指定した移調量を順にたどる
+                let mut prms = split_by(',', prm.to_string());
+                let part = Self::detect_part(&prms[0]);
+                if part.is_some() {
+                    prms.remove(0);
+                }
+                let part = part.unwrap_or(self.get_input_part());
+                let (msrs, offsets) = match prms.first().map(|x| x.as_str()) {
+                    Some("off") => (1, Vec::new()),
+                    Some("reset") => (0, Vec::new()),
+                    Some(num) => {
+                        let msrs = match num.parse::<i16>() {
+                            Ok(n) if (1..=64).contains(&n) => n,
+                            _ => return self.wrong("Number is wrong."),
+                        };
+                        let offsets = match prms.get(1) {
+                            None => (0..=12).collect(),
+                            Some(seq) if seq.contains('/') => {
+                                let mut offsets = Vec::new();
+                                for ofs in seq.split('/') {
+                                    match ofs.trim().parse::<i16>() {
+                                        Ok(n) if (-24..=24).contains(&n) => offsets.push(n),
+                                        _ => return self.wrong("Number is wrong."),
+                                    }
+                                }
+                                offsets
+                            }
+                            Some(limit) => match limit.parse::<i16>() {
+                                Ok(n) if (1..=24).contains(&n) => (0..=n).collect(),
+                                _ => return self.wrong("Number is wrong."),
+                            },
+                        };
+                        (msrs, offsets)
+                    }
+                    None => return self.wrong("what?"),
+                };
+                let answer = if msrs == 0 {
+                    "Riser has been reset!"
+                } else if offsets.is_empty() {
+                    "Riser has stopped!"
+                } else {
+                    "Riser has changed!"
+                };
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Riser(part as i16, msrs, offsets));
+                answer.to_string()
+            } else if cmd == "rate" {
+                // ex. set.rate(50) : 練習用に半分の速さで再生
+                let rate = if prm == "off" {
+                    Ok(100)
+                } else {
+                    prm.parse::<i16>()
+                };
+                match rate {
+                    Ok(rate) if (10..=200).contains(&rate) => {
+                        self.sndr
+                            .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_RATE, rate]));
+                        "Playback rate has changed!".to_string()
+                    }
+                    _ => self.wrong("Number is wrong."),
+                }
+            } else if cmd == "stats" {
+                let stats = match prm {
+                    "on" => 1,
+                    "off" => 0,
+                    _ => return self.wrong("what?"),
+                };
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_STATS, stats]));
+                "Stats report has changed!".to_string()
+            } else if cmd == "pad" {
+                let pad = match prm {
+                    "on" => 1,
+                    "off" => 0,
+                    _ => return self.wrong("what?"),
+                };
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_FLOW_PAD, pad]));
+                "Chord pad mode has changed!".to_string()
             } else if cmd == "path" {
                 if self.change_path(prm) {
                     "Path has changed!".to_string()
                 } else {
-                    "what?".to_string()
+                    self.wrong("what?")
                 }
             } else {
-                "what?".to_string()
+                self.wrong("what?")
             }
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
     //*************************************************************************
     pub fn change_key(&mut self, key_text: &str) -> bool {
+        if let Some((key, oct)) = Self::key_number(key_text) {
+            #[cfg(feature = "verbose")]
+            println!("CHANGE KEY: {}, {}", key, oct);
+            // phrase 再生成(新oct込み)
+            if oct != 0 && self.dtstk.change_oct(oct, false, self.get_input_part()) {
+                self.sndr
+                    .send_all_vari_and_phrase(self.get_input_part(), &self.dtstk);
+            }
+            // elapse に key を送る
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_KEY, key as i16]));
+            self.indicator_key_stock(key_text.to_string());
+            true
+        } else {
+            false
+        }
+    }
+    /// C / F# / Bb3 を (key:0-11, oct) にする
+    pub fn key_number(key_text: &str) -> Option<(i32, i32)> {
         let mut key = END_OF_DATA;
         let length = key_text.len();
         match key_text.chars().nth(0) {
@@ -128,20 +854,9 @@ impl LoopianCmd {
             } else if key >= 12 {
                 key -= 12;
             }
-            #[cfg(feature = "verbose")]
-            println!("CHANGE KEY: {}, {}", key, oct);
-            // phrase 再生成(新oct込み)
-            if oct != 0 && self.dtstk.change_oct(oct, false, self.get_input_part()) {
-                self.sndr
-                    .send_all_vari_and_phrase(self.get_input_part(), &self.dtstk);
-            }
-            // elapse に key を送る
-            self.sndr
-                .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_KEY, key as i16]));
-            self.indicator_key_stock(key_text.to_string());
-            true
+            Some((key, oct))
         } else {
-            false
+            None
         }
     }
     pub fn change_oct(&mut self, oct_txt: &str, part_num: usize) -> bool {
@@ -185,11 +900,275 @@ impl LoopianCmd {
         if let Ok(turn_note) = ntnum.parse::<i16>() {
             self.sndr
                 .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_TURN, turn_note]));
+            self.turnnote_stock(turn_note);
             true
         } else {
             false
         }
     }
+    /// velcc(74) / velcc(74,exp,50) / velcc(off)
+    fn change_vel_cc(&mut self, prm: &str) -> bool {
+        let part = self.get_input_part() as i16;
+        let prms = split_by(',', prm.to_string());
+        let cc_num = if prms[0] == "off" {
+            -1
+        } else if let Ok(num) = prms[0].parse::<i16>() {
+            if !(0..=119).contains(&num) {
+                return false;
+            }
+            num
+        } else {
+            return false;
+        };
+        let mut msgs = vec![[MSG_SETPT_VELCC, cc_num]];
+        if prms.len() >= 2 {
+            let curve = match prms[1].as_str() {
+                "lin" => 0,
+                "exp" => 1,
+                "log" => 2,
+                _ => return false,
+            };
+            msgs.push([MSG_SETPT_VELCC_CURVE, curve]);
+        }
+        if prms.len() >= 3 {
+            if let Ok(smooth) = prms[2].parse::<i16>() {
+                msgs.push([MSG_SETPT_VELCC_SMOOTH, smooth]);
+            } else {
+                return false;
+            }
+        }
+        for msg in msgs.into_iter() {
+            self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(part, msg));
+        }
+        true
+    }
+    /// trigger(dominant,C#2) / trigger(loopend,49,10,100) / trigger(section,off) / trigger(off)
+    /// note, MIDI ch(default:10), velocity(default:100)
+    fn change_trigger(&mut self, prm: &str) -> bool {
+        let prms = split_by(',', prm.to_string());
+        let event = match prms[0].as_str() {
+            "off" => {
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Trigger(-1, [-1, 0, 0]));
+                return true;
+            }
+            "section" => TRIGGER_SECTION,
+            "dominant" => TRIGGER_DOMINANT,
+            "loopend" => TRIGGER_LOOP_END,
+            _ => return false,
+        };
+        let note = match prms.get(1).map(|n| n.as_str()) {
+            Some("off") => -1,
+            Some(n) => match Self::note_number(n) {
+                Some(nt) => nt,
+                None => return false,
+            },
+            None => return false,
+        };
+        let ch = match prms.get(2).map(|c| c.parse::<i16>()) {
+            None => 10,
+            Some(Ok(c)) if (1..=16).contains(&c) => c,
+            _ => return false,
+        };
+        let vel = match prms.get(3).map(|v| v.parse::<i16>()) {
+            None => 100,
+            Some(Ok(v)) if (1..=127).contains(&v) => v,
+            _ => return false,
+        };
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::Trigger(event, [note, ch, vel]));
+        true
+    }
+    /// pan(40) / pan(R1,100) / pan(pitch) / pan(pitch,48,exp,C4) / pan(off)
+    fn change_pan(&mut self, prm: &str) -> bool {
+        let mut prms = split_by(',', prm.to_string());
+        let part = Self::detect_part(&prms[0]);
+        if part.is_some() {
+            prms.remove(0);
+        }
+        let part = part.unwrap_or(self.get_input_part()) as i16;
+        let msgs = match prms.first().map(|x| x.as_str()) {
+            Some("off") => vec![[MSG_SETPT_PAN, -1], [MSG_SETPT_PAN_PITCH, -1]],
+            Some("pitch") => {
+                // 音高で広げる幅(1-64), curve, 真ん中の note
+                let width = match prms.get(1).map(|w| w.parse::<i16>()) {
+                    None => 48,
+                    Some(Ok(w)) if (1..=64).contains(&w) => w,
+                    _ => return false,
+                };
+                let curve = match prms.get(2).map(|c| c.as_str()) {
+                    None | Some("lin") => 0,
+                    Some("exp") => 1,
+                    Some("log") => 2,
+                    _ => return false,
+                };
+                let center = match prms.get(3) {
+                    None => 60,
+                    Some(c) => match Self::note_number(c) {
+                        Some(nt) => nt,
+                        None => return false,
+                    },
+                };
+                vec![
+                    [MSG_SETPT_PAN_PITCH, width + (curve << 7)],
+                    [MSG_SETPT_PAN_CENTER, center],
+                ]
+            }
+            Some(num) => match num.parse::<i16>() {
+                Ok(pan) if (0..=127).contains(&pan) => vec![[MSG_SETPT_PAN, pan]],
+                _ => return false,
+            },
+            None => return false,
+        };
+        for msg in msgs.into_iter() {
+            self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(part, msg));
+        }
+        true
+    }
+    /// split(C3,2,1) : C3 より下を ch.2、C3 以上を ch.1 で出力する / split(off)
+    /// ex. set.split(C3,2,1) / set.split(C3,2@Bass,1@Piano) : ch の後に @port で出力先も分ける
+    fn change_split(&mut self, prm: &str) -> bool {
+        let prms = split_by(',', prm.to_string());
+        let mut ports = [String::new(), String::new()];
+        let (value, border) = if prms[0] == "off" {
+            (-1, -1)
+        } else if prms.len() == 3 {
+            let note = match Self::note_number(&prms[0]) {
+                Some(nt) => nt,
+                None => return false,
+            };
+            let mut ch = [0; 2];
+            for (i, p) in prms[1..].iter().enumerate() {
+                let (c, port) = p.split_once('@').unwrap_or((p, ""));
+                ch[i] = match c.parse::<i16>() {
+                    Ok(c) if (1..=16).contains(&c) => c,
+                    _ => return false,
+                };
+                ports[i] = port.to_string();
+            }
+            (note + (ch[0] - 1) * 128 + (ch[1] - 1) * 2048, note)
+        } else {
+            return false;
+        };
+        let part = self.get_input_part() as i16;
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::SetPart(part, [MSG_SETPT_SPLIT, value]));
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::SplitPort(part, border, ports));
+        true
+    }
+    /// 48 / C3 / F#2 / Bb1 を note number にする(C4=60)
+    fn note_number(txt: &str) -> Option<i16> {
+        if let Ok(nt) = txt.parse::<i16>() {
+            return (0..=127).contains(&nt).then_some(nt);
+        }
+        let mut chars = txt.chars();
+        let mut pc = match chars.next()? {
+            'C' => 0,
+            'D' => 2,
+            'E' => 4,
+            'F' => 5,
+            'G' => 7,
+            'A' => 9,
+            'B' => 11,
+            _ => return None,
+        };
+        let mut oct_txt: String = chars.collect();
+        if let Some(rest) = oct_txt.strip_prefix('#') {
+            pc += 1;
+            oct_txt = rest.to_string();
+        } else if let Some(rest) = oct_txt.strip_prefix('b') {
+            pc -= 1;
+            oct_txt = rest.to_string();
+        }
+        let oct = oct_txt.parse::<i16>().ok()?;
+        let nt = (oct + 1) * 12 + pc;
+        (0..=127).contains(&nt).then_some(nt)
+    }
+    /// keysw(stacc,24) / keysw(legato,cc32,5) / keysw(off)
+    fn change_keysw(&mut self, prm: &str) -> bool {
+        let part = self.get_input_part() as i16;
+        let prms = split_by(',', prm.to_string());
+        if prms[0] == "off" {
+            for kind in MSG_SETPT_KEYSW_NORM..=MSG_SETPT_KEYSW_LEGATO {
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::SetPart(part, [kind, -1]));
+            }
+            return true;
+        }
+        let kind = match prms[0].as_str() {
+            "norm" => MSG_SETPT_KEYSW_NORM,
+            "stacc" => MSG_SETPT_KEYSW_STACC,
+            "legato" => MSG_SETPT_KEYSW_LEGATO,
+            _ => return false,
+        };
+        let value = if prms.len() < 2 || prms[1] == "off" {
+            -1
+        } else if let Some(cc) = prms[1].strip_prefix("cc") {
+            // CC(UACC など) : cc番号, 値
+            let val = prms.get(2).and_then(|v| v.parse::<i16>().ok());
+            match (cc.parse::<i16>(), val) {
+                (Ok(cc), Some(val)) if (0..=119).contains(&cc) && (0..=127).contains(&val) => {
+                    128 + cc * 128 + val
+                }
+                _ => return false,
+            }
+        } else if let Ok(note) = prms[1].parse::<i16>() {
+            if !(0..=127).contains(&note) {
+                return false;
+            }
+            note
+        } else {
+            return false;
+        };
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::SetPart(part, [kind, value]));
+        true
+    }
+    /// tune(meantone) / tune(0,-24,-7,10,-14,3,-21,-3,-17,-10,7,-17) / tune(equal)
+    /// key からの度数ごとの cent を、Part に送る
+    fn change_tuning(&mut self, prm: &str) -> bool {
+        const PRESET: [(&str, [i16; 12]); 4] = [
+            (
+                "meantone",
+                [0, -24, -7, 10, -14, 3, -21, -3, -17, -10, 7, -17],
+            ),
+            ("just", [0, 12, 4, 16, -14, -2, -10, 2, 14, -16, -4, -12]),
+            ("pythag", [0, -10, 4, -6, 8, -2, 12, 2, -8, 6, -4, 10]),
+            ("werck", [0, -10, -8, -6, -10, -2, -12, -4, -8, -12, -4, -8]),
+        ];
+        let part = self.get_input_part() as i16;
+        let prms = split_by(',', prm.to_string());
+        let cents: Vec<i16> = if prms[0] == "equal" || prms[0] == "off" {
+            Vec::new()
+        } else if let Some((_, c)) = PRESET.iter().find(|x| x.0 == prms[0]) {
+            c.to_vec()
+        } else if prms.len() == 12 {
+            let mut c = Vec::new();
+            for p in prms.iter() {
+                match p.parse::<i16>() {
+                    Ok(n) if (-100..=100).contains(&n) => c.push(n),
+                    _ => return false,
+                }
+            }
+            c
+        } else {
+            return false;
+        };
+        if cents.is_empty() {
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::SetPart(part, [MSG_SETPT_TUNE, -1]));
+        }
+        for (degree, cent) in cents.iter().enumerate() {
+            let value = degree as i16 * 256 + cent + 128;
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::SetPart(part, [MSG_SETPT_TUNE, value]));
+        }
+        // 最後に、Synth に pitch bend range などを送る
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::SetPart(part, [MSG_SETPT_BEND_RANGE, 0]));
+        true
+    }
     fn change_path(&mut self, path: &str) -> bool {
         self.path(path.to_string());
         true
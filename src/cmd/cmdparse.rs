@@ -3,11 +3,15 @@
 //  Released under the MIT license
 //  https://opensource.org/licenses/mit-license.php
 //
+use std::cell::Cell;
 use std::sync::mpsc;
 
 use super::send_msg::*;
 use super::seq_stock::*;
+use super::txt2seq_phr::gen_phrase_text;
 use super::txt_common::*;
+use crate::file::session::{Session, SessionMsr, SessionPart};
+use crate::file::set_list_file::SetListFile;
 use crate::lpnlib::*;
 
 //  LoopianCmd の責務
@@ -17,9 +21,19 @@ use crate::lpnlib::*;
 pub struct LoopianCmd {
     during_play: bool,
     recursive: bool,
+    dry_run: bool, // ; の batch を試すための複製(ファイルに書かない)
     indicator_key_stock: String,
+    disp_trns: i16,
+    spell: i16,
     input_part: usize,
     path: Option<String>,
+    turnnote: i16,
+    vari_link: [bool; MAX_KBD_PART], // Phrase と Composition の Variation を連動させるか
+    vari_sel: [usize; MAX_KBD_PART], // vari(n) で選んだ Variation(0:Normal)
+    vel_limit: [Option<[i16; 2]>; MAX_COMPOSITION_PART], // set.vellimit の (下限, 上限)
+    scenes: Vec<(String, Session)>,  // scene save で保存した名前と状態
+    crnt_scene: Option<usize>,
+    failed: Cell<bool>, // 直前のコマンドが失敗した
     pub dtstk: SeqDataStock,
     pub sndr: MessageSender,
 }
@@ -28,22 +42,79 @@ impl LoopianCmd {
         Self {
             during_play: false,
             recursive: false,
+            dry_run: false,
             indicator_key_stock: "C".to_string(),
+            disp_trns: DISP_TRNS_OFF,
+            spell: SPELL_AUTO,
             input_part: RIGHT1,
             path: None,
+            turnnote: DEFAULT_TURNNOTE,
+            vari_link: [true; MAX_KBD_PART],
+            vari_sel: [0; MAX_KBD_PART],
+            vel_limit: [None; MAX_COMPOSITION_PART],
+            scenes: Vec::new(),
+            crnt_scene: None,
+            failed: Cell::new(false),
             dtstk: SeqDataStock::new(),
             sndr: MessageSender::new(msg_hndr),
         }
     }
+    /// 表示用の移調/綴りが指定されていれば、それを反映した key を返す
     pub fn get_indicator_key_stock(&self) -> String {
-        self.indicator_key_stock.clone()
+        let kstk = &self.indicator_key_stock;
+        let trns = if self.disp_trns == DISP_TRNS_OFF {
+            0
+        } else {
+            self.disp_trns
+        };
+        if trns == 0 && self.spell == SPELL_AUTO {
+            return kstk.clone();
+        }
+        let mut chars = kstk.chars();
+        let pc = match chars
+            .next()
+            .and_then(|c| NOTE_NAME.iter().position(|x| x.chars().eq([c])))
+        {
+            Some(pc) => pc as i16,
+            None => return kstk.clone(),
+        };
+        let tail = chars.as_str();
+        let (pc, rest) = if let Some(r) = tail.strip_prefix('#') {
+            (pc + 1, r)
+        } else if let Some(r) = tail.strip_prefix('b') {
+            (pc - 1, r)
+        } else {
+            (pc, tail)
+        };
+        let key = pc + trns;
+        note_name(key, key, self.spell).to_string() + rest
+    }
+    pub fn set_disp_trns(&mut self, trns: i16) {
+        self.disp_trns = trns;
+    }
+    pub fn set_spell(&mut self, spell: i16) {
+        self.spell = spell;
     }
     pub fn indicator_key_stock(&mut self, kstk: String) {
         self.indicator_key_stock = kstk;
     }
+    pub fn vel_limit_stock(&mut self, part: usize, limit: Option<[i16; 2]>) {
+        if let Some(vl) = self.vel_limit.get_mut(part) {
+            *vl = limit;
+        }
+    }
+    pub fn turnnote_stock(&mut self, tn: i16) {
+        self.turnnote = tn;
+    }
     pub fn get_input_part(&self) -> usize {
         self.input_part
     }
+    /// 他の奏者のコマンドを、その人のパートで実行する時に使う
+    pub fn set_input_part(&mut self, part: usize) {
+        if part < MAX_KBD_PART {
+            self.input_part = part;
+        }
+    }
     pub fn get_path(&self) -> Option<String> {
         self.path.clone()
     }
@@ -59,7 +130,495 @@ impl LoopianCmd {
     }
     pub fn send_clear(&self) {
         self.sndr.send_msg_to_elapse(ElpsMsg::Ctrl(MSG_CTRL_CLEAR));
-        println!("*** All data has been erased at Elapse thread! ***");
+        if !self.dry_run {
+            println!("*** All data has been erased at Elapse thread! ***");
+        }
+    }
+    /// 複数のコマンドをまとめて実行する
+    /// 先に複製で全て試し、失敗があれば何も実行せず、最初に失敗した位置と返事を返す
+    pub fn put_batch(&mut self, cmds: &[String]) -> Result<Vec<Option<CmndRtn>>, (usize, String)> {
+        match self.validate_batch(cmds).into_iter().next() {
+            Some(err) => Err(err),
+            None => self.apply_batch(cmds),
+        }
+    }
+    /// 試した後に順に実行する
+    /// 試した時と結果が違って途中で失敗したら、実行前の Phrase/Composition に戻す
+    fn apply_batch(&mut self, cmds: &[String]) -> Result<Vec<Option<CmndRtn>>, (usize, String)> {
+        let good = self.dtstk.clone();
+        let key = self.indicator_key_stock.clone();
+        let (input_part, during_play) = (self.input_part, self.during_play);
+        let mut answers = Vec::new();
+        for (i, cmd) in cmds.iter().enumerate() {
+            match self.try_command(cmd) {
+                Ok(answer) => answers.push(answer),
+                Err(reply) => {
+                    self.rollback(good, &key, input_part, during_play);
+                    return Err((i, reply));
+                }
+            }
+        }
+        Ok(answers)
+    }
+    /// `[d,r,m]; L1.{I/IV}; set.bpm(120)` : ; で区切った全てのコマンドを先に試し、
+    /// 全て通った時だけ実行する。失敗したら何も実行せず、失敗した全てのコマンドを返す
+    fn put_semicolon_batch(&mut self, input_text: &str) -> CmndRtn {
+        let cmds: Vec<String> = input_text
+            .split(';')
+            .map(|c| c.trim_matches(['.', ' ']).to_string()) // space は . になっている
+            .filter(|c| !c.is_empty())
+            .collect();
+        let mut errors = self.validate_batch(&cmds);
+        if errors.is_empty() {
+            match self.apply_batch(&cmds) {
+                Ok(answers) => {
+                    let mut graphic = GraphicMsg::NoMsg;
+                    let mut replies = Vec::new();
+                    for ans in answers.into_iter().flatten() {
+                        if ans.1 != GraphicMsg::NoMsg {
+                            graphic = ans.1;
+                        }
+                        replies.push(ans.0);
+                    }
+                    return CmndRtn(replies.join(" / "), graphic);
+                }
+                Err(err) => errors.push(err), // 試した時と結果が違った(実行前に戻した)
+            }
+        }
+        let txt: Vec<String> = errors
+            .iter()
+            .map(|(i, reply)| format!("{}:{} ({})", i + 1, cmds[*i], reply))
+            .collect();
+        CmndRtn(
+            self.wrong(&format!(
+                "Batch failed! Nothing applied: {}",
+                txt.join(", ")
+            )),
+            GraphicMsg::What,
+        )
+    }
+    /// 複製した状態で順に実行し、失敗したコマンドの位置と返事を全て返す
+    /// 複製は Elapse へメッセージを送らず、ファイルにも書かない
+    pub fn validate_batch(&self, cmds: &[String]) -> Vec<(usize, String)> {
+        let mut trial = Self {
+            during_play: self.during_play,
+            recursive: false,
+            dry_run: true,
+            indicator_key_stock: self.indicator_key_stock.clone(),
+            disp_trns: self.disp_trns,
+            spell: self.spell,
+            input_part: self.input_part,
+            path: self.path.clone(),
+            turnnote: self.turnnote,
+            vari_link: self.vari_link,
+            vari_sel: self.vari_sel,
+            vel_limit: self.vel_limit,
+            scenes: self.scenes.clone(),
+            crnt_scene: self.crnt_scene,
+            failed: Cell::new(false),
+            dtstk: self.dtstk.clone(),
+            sndr: MessageSender::dry_run(),
+        };
+        cmds.iter()
+            .enumerate()
+            .filter_map(|(i, cmd)| trial.try_command(cmd).err().map(|reply| (i, reply)))
+            .collect()
+    }
+    /// 一つのコマンドを実行し、失敗したら Err で返事を返す
+    pub fn try_command(&mut self, input_text: &str) -> Result<Option<CmndRtn>, String> {
+        let answer = self.put_and_get_responce(input_text);
+        if self.failed.get() {
+            Err(answer.map_or(String::new(), |a| a.0))
+        } else {
+            Ok(answer)
+        }
+    }
+    /// 失敗した時の返事。batch で失敗したことが分かるように印を付ける
+    pub fn wrong(&self, reply: &str) -> String {
+        self.failed.set(true);
+        reply.to_string()
+    }
+    /// Elapse 側を消去し、保存しておいた Data と bpm/拍子/key を送り直す
+    fn rollback(&mut self, good: SeqDataStock, key: &str, input_part: usize, during_play: bool) {
+        if self.during_play && !during_play {
+            self.sndr.send_msg_to_elapse(ElpsMsg::Ctrl(MSG_CTRL_STOP));
+            self.during_play = false;
+        }
+        self.dtstk = good;
+        self.input_part = input_part;
+        self.send_clear();
+        let (numerator, denomirator) = self.dtstk.get_meter();
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::SetMeter([numerator, denomirator]));
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_BPM, self.dtstk.get_bpm()]));
+        if let Some((knum, _)) = Self::key_number(key) {
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_KEY, knum as i16]));
+        }
+        self.indicator_key_stock(key.to_string());
+        self.send_all_data();
+    }
+    /// SeqDataStock の Phrase/Composition を全て Elapse に送る
+    fn send_all_data(&self) {
+        for part in 0..MAX_COMPOSITION_PART {
+            self.sndr.send_composition_to_elapse(part, &self.dtstk);
+        }
+        for part in 0..MAX_KBD_PART {
+            self.sndr.send_all_vari_and_phrase(part, &self.dtstk);
+            for vari in 1..MAX_VARIATION {
+                if !self.dtstk.get_cvdstk(part, vari).is_empty() {
+                    self.sndr.send_cmps_vari_to_elapse(part, vari, &self.dtstk);
+                }
+            }
+        }
+    }
+    /// save session foo : 演奏の状態全体を session/foo.toml に保存する
+    fn save_session(&self, fname: &str) -> String {
+        if self.dry_run {
+            return "Session has been saved!".to_string();
+        }
+        match self.gen_session().write(fname) {
+            Ok(_) => "Session has been saved!".to_string(),
+            Err(e) => {
+                println!("{}", e);
+                self.wrong("Session save failed!")
+            }
+        }
+    }
+    /// load session foo : 保存した状態に置き換え、全ての Data を Elapse に送り直す
+    fn load_session(&mut self, fname: &str) -> String {
+        match Session::read(fname).and_then(|s| self.apply_session(s)) {
+            Ok(_) => "Session has been loaded!".to_string(),
+            Err(e) => {
+                println!("{}", e);
+                self.wrong("Session load failed!")
+            }
+        }
+    }
+    pub fn gen_session(&self) -> Session {
+        let dt = &self.dtstk;
+        let (numerator, denomirator) = dt.get_meter();
+        let part = (0..MAX_COMPOSITION_PART)
+            .map(|pt| {
+                let mut sp = SessionPart {
+                    name: ["L1", "L2", "R1", "R2", "Flow"][pt].to_string(),
+                    composition: vec![dt.get_cdstk(pt).get_raw().to_string()],
+                    vari_link: true,
+                    ..Default::default()
+                };
+                if pt < MAX_KBD_PART {
+                    let normal = dt.get_pdstk(pt, PhraseAs::Normal);
+                    sp.oct = normal.get_base_note() / 12 - 1;
+                    sp.phrase = (0..MAX_VARIATION)
+                        .map(|v| {
+                            let vari = if v == 0 {
+                                PhraseAs::Normal
+                            } else {
+                                PhraseAs::Variation(v)
+                            };
+                            dt.get_pdstk(pt, vari).get_raw().to_string()
+                        })
+                        .collect();
+                    for v in 1..MAX_VARIATION {
+                        sp.composition
+                            .push(dt.get_cvdstk(pt, v).get_raw().to_string());
+                    }
+                    sp.vari_link = self.vari_link[pt];
+                    sp.vari = self.vari_sel[pt];
+                    sp.measure = dt
+                        .get_msr_raw(pt)
+                        .iter()
+                        .map(|(msr, raw)| SessionMsr {
+                            msr: *msr,
+                            phrase: raw.clone(),
+                        })
+                        .collect();
+                }
+                sp.vellimit = self.vel_limit[pt];
+                sp
+            })
+            .collect();
+        Session {
+            bpm: dt.get_bpm(),
+            meter: [numerator, denomirator],
+            // key の後の octave の数字は、既に Phrase に反映されている
+            key: self
+                .indicator_key_stock
+                .chars()
+                .take_while(|c| c.is_ascii_alphabetic() || *c == '#')
+                .collect(),
+            turnnote: self.turnnote,
+            input_mode: match dt.get_input_mode() {
+                InputMode::Fixed => "fixed".to_string(),
+                InputMode::Closer => "closer".to_string(),
+            },
+            part,
+        }
+    }
+    /// Session の内容で SeqDataStock を作り直し、Elapse 側を消去して送り直す
+    pub fn apply_session(&mut self, sess: Session) -> Result<(), String> {
+        let dt = Self::gen_data_stock(&sess)?;
+
+        // 読み込めたら置き換える
+        self.dtstk = dt;
+        self.send_clear();
+        let (numerator, denomirator) = self.dtstk.get_meter();
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::SetMeter([numerator, denomirator]));
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_BPM, self.dtstk.get_bpm()]));
+        if !sess.key.is_empty() {
+            self.change_key(&sess.key);
+        }
+        self.turnnote = sess.turnnote;
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_TURN, sess.turnnote]));
+        for (pt, sp) in sess.part.iter().enumerate().take(MAX_KBD_PART) {
+            self.vari_link[pt] = sp.vari_link;
+            self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                pt as i16,
+                [MSG_SETPT_VARI_LINK, sp.vari_link as i16],
+            ));
+        }
+        self.send_all_data();
+        for msg in self.dtstk.gen_msr_phrase_msgs() {
+            self.sndr.send_msg_to_elapse(msg);
+        }
+        self.vari_sel = [0; MAX_KBD_PART];
+        self.vel_limit = [None; MAX_COMPOSITION_PART];
+        for (pt, sp) in sess.part.iter().enumerate().take(MAX_COMPOSITION_PART) {
+            if pt < MAX_KBD_PART && (1..MAX_VARIATION).contains(&sp.vari) {
+                self.vari_sel[pt] = sp.vari;
+                self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                    pt as i16,
+                    [MSG_SETPT_VARI, sp.vari as i16],
+                ));
+            }
+            self.vel_limit[pt] = sp.vellimit;
+            self.sndr.send_msg_to_elapse(ElpsMsg::SetPart(
+                pt as i16,
+                [MSG_SETPT_VEL_LIMIT, Self::vel_limit_value(sp.vellimit)],
+            ));
+        }
+        Ok(())
+    }
+    /// set.vellimit の msg の値(-1:off)
+    fn vel_limit_value(limit: Option<[i16; 2]>) -> i16 {
+        limit.map_or(-1, |[floor, ceiling]| floor + (ceiling << 7))
+    }
+    /// scene save verse : 今の全パートの状態を、名前を付けて覚える(同じ名前なら上書き)
+    /// scene verse / scene 2 / scene next : 全パートを次の小節の頭で揃えて切り替える
+    /// scene : 覚えている scene の一覧
+    fn apply_scene(&mut self, input_text: &str) -> String {
+        let prm = input_text.trim_matches(['.', ' ']);
+        if prm.is_empty() {
+            if self.scenes.is_empty() {
+                return self.wrong("No scene!");
+            }
+            let names: Vec<String> = self
+                .scenes
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| format!("{}:{}", i + 1, name))
+                .collect();
+            return format!("Scenes: {}", names.join(" "));
+        }
+        // save の後は区切りが要る(scene saved は saved という名前の scene)
+        let save = prm
+            .strip_prefix("save")
+            .filter(|n| n.is_empty() || n.starts_with(['.', ' ']));
+        if let Some(name) = save {
+            let name = name.trim_matches(['.', ' ']);
+            if name.is_empty() || name == "next" || name.parse::<usize>().is_ok() {
+                return self.wrong("Scene name is wrong.");
+            }
+            let sess = self.gen_session();
+            match self.scenes.iter().position(|(n, _)| n == name) {
+                Some(idx) => self.scenes[idx].1 = sess,
+                None => self.scenes.push((name.to_string(), sess)),
+            }
+            return format!("Scene {} has been saved!", name);
+        }
+        let idx = if prm == "next" {
+            match self.crnt_scene {
+                Some(crnt) if crnt + 1 >= self.scenes.len() => return self.wrong("No next scene."),
+                Some(crnt) => crnt + 1,
+                None => 0,
+            }
+        } else if let Ok(num) = prm.parse::<usize>() {
+            num.wrapping_sub(1)
+        } else {
+            match self.scenes.iter().position(|(n, _)| n == prm) {
+                Some(idx) => idx,
+                None => return self.wrong("Scene name is wrong."),
+            }
+        };
+        if idx >= self.scenes.len() {
+            return self.wrong("Scene number is wrong.");
+        }
+        let (dt, msgs) = match Self::gen_scene_msgs(&self.scenes[idx].1) {
+            Ok(dm) => dm,
+            Err(e) => return self.wrong(&e),
+        };
+
+        // 全パートの Data と bpm/key を一つの msg で送り、Elapse 側で揃えて切り替える
+        self.sndr.send_msg_to_elapse(ElpsMsg::Scene(msgs));
+        self.follow_scene(idx, dt);
+        format!("Scene {} will start at next measure!", self.scenes[idx].0)
+    }
+    /// scene に切り替えるための msg を作る
+    fn gen_scene_msgs(sess: &Session) -> Result<(SeqDataStock, Vec<ElpsMsg>), String> {
+        let mut dt = Self::gen_data_stock(sess)?;
+        let (numerator, denomirator) = dt.get_meter();
+        let mut msgs = vec![
+            ElpsMsg::SetMeter([numerator, denomirator]),
+            ElpsMsg::Set([MSG_SET_BPM, dt.get_bpm()]),
+            ElpsMsg::Set([MSG_SET_TURN, sess.turnnote]),
+        ];
+        if let Some((key, _)) = Self::key_number(&sess.key) {
+            msgs.push(ElpsMsg::Set([MSG_SET_KEY, key as i16]));
+        }
+        for (pt, sp) in sess.part.iter().enumerate().take(MAX_KBD_PART) {
+            msgs.push(ElpsMsg::SetPart(
+                pt as i16,
+                [MSG_SETPT_VARI_LINK, sp.vari_link as i16],
+            ));
+        }
+        msgs.extend(MessageSender::gen_all_data_msg(&dt));
+        msgs.extend(dt.gen_msr_phrase_msgs());
+        for (pt, sp) in sess.part.iter().enumerate().take(MAX_COMPOSITION_PART) {
+            if pt < MAX_KBD_PART && (1..MAX_VARIATION).contains(&sp.vari) {
+                msgs.push(ElpsMsg::SetPart(
+                    pt as i16,
+                    [MSG_SETPT_VARI, sp.vari as i16],
+                ));
+            }
+            msgs.push(ElpsMsg::SetPart(
+                pt as i16,
+                [MSG_SETPT_VEL_LIMIT, Self::vel_limit_value(sp.vellimit)],
+            ));
+        }
+        Ok((dt, msgs))
+    }
+    /// Elapse 側で切り替えた scene に、入力側の状態を合わせる
+    fn follow_scene(&mut self, idx: usize, dt: SeqDataStock) {
+        let sess = self.scenes[idx].1.clone();
+        if Self::key_number(&sess.key).is_some() {
+            self.indicator_key_stock(sess.key);
+        }
+        self.turnnote = sess.turnnote;
+        self.vari_sel = [0; MAX_KBD_PART];
+        for (pt, sp) in sess.part.iter().enumerate().take(MAX_COMPOSITION_PART) {
+            if pt < MAX_KBD_PART {
+                self.vari_link[pt] = sp.vari_link;
+                if (1..MAX_VARIATION).contains(&sp.vari) {
+                    self.vari_sel[pt] = sp.vari;
+                }
+            }
+            self.vel_limit[pt] = sp.vellimit;
+        }
+        self.dtstk = dt;
+        self.crnt_scene = Some(idx);
+    }
+    /// set list が scene を切り替えた時(既に同じ scene なら何もしない)
+    pub fn set_list_scene(&mut self, idx: usize) {
+        if self.crnt_scene == Some(idx) || idx >= self.scenes.len() {
+            return;
+        }
+        if let Ok(dt) = Self::gen_data_stock(&self.scenes[idx].1) {
+            self.follow_scene(idx, dt);
+        }
+    }
+    /// load setlist foo : setlist/foo.txt の順に scene を演奏する
+    fn load_set_list(&mut self, fname: &str) -> String {
+        match SetListFile::read(fname) {
+            Ok(file) => self.apply_set_list_file(file),
+            Err(e) => {
+                println!("{}", e);
+                self.wrong("File name is wrong.")
+            }
+        }
+    }
+    /// set list の scene 名を、scene save で覚えた scene に対応させて Elapse に送る
+    pub fn apply_set_list_file(&mut self, file: SetListFile) -> String {
+        let mut entries = Vec::new();
+        for (name, msrs) in file.entries.into_iter() {
+            let idx = match self.scenes.iter().position(|(n, _)| *n == name) {
+                Some(idx) => idx,
+                None => return self.wrong(&format!("Scene name is wrong: {}", name)),
+            };
+            match Self::gen_scene_msgs(&self.scenes[idx].1) {
+                Ok((_, msgs)) => entries.push((idx, name, msrs, msgs)),
+                Err(e) => return self.wrong(&e),
+            }
+        }
+        let num = entries.len();
+        self.sndr.send_msg_to_elapse(ElpsMsg::SetList(entries));
+        format!("Set list has been loaded! ({} scenes)", num)
+    }
+    /// setlist next / setlist 3 : set list の scene を手動で移る
+    /// setlist off : set list を止める(今の scene はそのまま)
+    fn apply_set_list(&mut self, input_text: &str) -> String {
+        let prm = input_text.trim_matches(['.', ' ']);
+        let msg = match prm {
+            "next" => ElpsMsg::SetListCtrl(SETLIST_NEXT),
+            "off" => ElpsMsg::SetList(Vec::new()),
+            _ => match prm.parse::<i16>() {
+                Ok(num) if num >= 1 => ElpsMsg::SetListCtrl(num - 1),
+                _ => return self.wrong("what?"),
+            },
+        };
+        self.sndr.send_msg_to_elapse(msg);
+        "Set list has changed!".to_string()
+    }
+    /// Session の入力から SeqDataStock を作る
+    fn gen_data_stock(sess: &Session) -> Result<SeqDataStock, String> {
+        let mut dt = SeqDataStock::new();
+        dt.change_input_mode(if sess.input_mode == "fixed" {
+            InputMode::Fixed
+        } else {
+            InputMode::Closer
+        });
+        if sess.meter[0] > 0 && sess.meter[1] > 0 {
+            dt.change_beat(sess.meter[0], sess.meter[1]);
+        }
+        if sess.bpm > 0 {
+            dt.change_bpm(sess.bpm);
+        }
+        for (pt, sp) in sess.part.iter().enumerate().take(MAX_COMPOSITION_PART) {
+            if pt < MAX_KBD_PART {
+                dt.change_oct(sp.oct, false, pt);
+                for (v, raw) in sp.phrase.iter().enumerate().take(MAX_VARIATION) {
+                    let vari = if v == 0 {
+                        PhraseAs::Normal
+                    } else {
+                        PhraseAs::Variation(v)
+                    };
+                    if !raw.is_empty() && dt.set_raw_phrase(pt, vari, raw.clone()).is_none() {
+                        return Err(format!("{} Phrase is wrong: {}", sp.name, raw));
+                    }
+                }
+                for m in sp.measure.iter().filter(|m| m.msr >= 1) {
+                    let vari = PhraseAs::Measure(m.msr);
+                    if dt.set_raw_phrase(pt, vari, m.phrase.clone()).is_none() {
+                        return Err(format!("{} Phrase is wrong: {}", sp.name, m.phrase));
+                    }
+                }
+            }
+            for (v, raw) in sp.composition.iter().enumerate().take(MAX_VARIATION) {
+                let ok = raw.is_empty()
+                    || if v == 0 {
+                        dt.set_raw_composition(pt, raw.clone())
+                    } else {
+                        dt.set_raw_cmps_vari(pt, v, raw.clone())
+                    };
+                if !ok {
+                    return Err(format!("{} Composition is wrong: {}", sp.name, raw));
+                }
+            }
+        }
+        Ok(dt)
     }
     #[cfg(feature = "raspi")]
     pub fn send_reconnect(&self) {
@@ -68,10 +627,18 @@ impl LoopianCmd {
     }
     //*************************************************************************
     pub fn put_and_get_responce(&mut self, input_text: &str) -> Option<CmndRtn> {
+        self.failed.set(false);
         if input_text.is_empty() {
             return None;
         }
         println!("Set Text: {}", input_text);
+        if !input_text.is_ascii() && !Self::accept_non_ascii(input_text) {
+            // 歌詞やパス以外で ASCII 以外の文字は使わない
+            return Some(CmndRtn(self.wrong("what?"), GraphicMsg::What));
+        }
+        if input_text.contains(';') {
+            return Some(self.put_semicolon_batch(input_text));
+        }
         let first_letter = &input_text[0..1];
         if first_letter == "@" {
             Some(CmndRtn(self.letter_at(input_text), GraphicMsg::NoMsg))
@@ -81,6 +648,10 @@ impl LoopianCmd {
             Some(CmndRtn(self.letter_brace(input_text), GraphicMsg::NoMsg))
         } else if first_letter == "." {
             Some(CmndRtn(self.letter_dot(input_text), GraphicMsg::NoMsg))
+        } else if first_letter == "a" {
+            Some(CmndRtn(self.letter_a(input_text), GraphicMsg::NoMsg))
+        } else if first_letter == "b" {
+            Some(CmndRtn(self.letter_b(input_text), GraphicMsg::NoMsg))
         } else if first_letter == "c" {
             Some(CmndRtn(self.letter_c(input_text), GraphicMsg::NoMsg))
         } else if first_letter == "e" {
@@ -89,24 +660,89 @@ impl LoopianCmd {
             Some(CmndRtn(self.letter_f(input_text), GraphicMsg::NoMsg))
         } else if first_letter == "g" {
             Some(self.letter_g(input_text))
+        } else if first_letter == "k" {
+            Some(CmndRtn(self.letter_k(input_text), GraphicMsg::NoMsg))
         } else if first_letter == "l" {
             Some(CmndRtn(self.letter_l(input_text), GraphicMsg::NoMsg))
+        } else if first_letter == "m" {
+            Some(CmndRtn(self.letter_m(input_text), GraphicMsg::NoMsg))
+        } else if first_letter == "n" {
+            Some(CmndRtn(self.letter_n(input_text), GraphicMsg::NoMsg))
         } else if first_letter == "p" {
             Some(CmndRtn(self.letter_p(input_text), GraphicMsg::NoMsg))
         } else if first_letter == "r" {
             Some(CmndRtn(self.letter_r(input_text), GraphicMsg::NoMsg))
         } else if first_letter == "s" {
             Some(CmndRtn(self.letter_s(input_text), GraphicMsg::NoMsg))
+        } else if first_letter == "u" {
+            Some(CmndRtn(self.letter_u(input_text), GraphicMsg::NoMsg))
         } else if first_letter == "L"
             || first_letter == "R"
             || first_letter == "F"
             || first_letter == "A"
         {
             Some(CmndRtn(self.letter_part(input_text), GraphicMsg::NoMsg))
+        } else if first_letter == "v" {
+            Some(CmndRtn(self.letter_v(input_text), GraphicMsg::NoMsg))
         } else if first_letter == "h" {
             Some(CmndRtn(self.letter_h(input_text), GraphicMsg::NoMsg))
         } else {
-            Some(CmndRtn("what?".to_string(), GraphicMsg::NoMsg))
+            Some(CmndRtn(self.wrong("what?"), GraphicMsg::NoMsg))
+        }
+    }
+    /// space か . で区切った最初の二語が w1 w2 か
+    fn first_words(input_text: &str, w1: &str, w2: &str) -> bool {
+        matches!(split_words(input_text).as_slice(), [a, b, ..] if a == w1 && b == w2)
+    }
+    /// export setup foo のように、二語の後に一語だけ書いたファイル名
+    fn file_name_of(words: &[String]) -> Option<&str> {
+        match words {
+            [_, _, fname] if !fname.contains(['/', '\\']) => Some(fname.as_str()),
+            _ => None,
+        }
+    }
+    fn accept_non_ascii(input_text: &str) -> bool {
+        let words = split_words(input_text);
+        (words.len() >= 2 && words[0].starts_with("@m") && words[1] == "text")
+            || input_text.starts_with("set.path(")
+    }
+    fn letter_a(&mut self, input_text: &str) -> String {
+        if input_text.starts_with("accel.") || input_text.starts_with("accel ") {
+            self.apply_rit(input_text)
+        } else {
+            self.wrong("what?")
+        }
+    }
+    /// bpm@9 140 : 9小節目の頭から bpm を 140 にする(tempo map)
+    /// bpm@9 off : 9小節目の変化を消す、bpm@off : 全て消す
+    fn letter_b(&mut self, input_text: &str) -> String {
+        let prm = match input_text.strip_prefix("bpm@") {
+            Some(prm) => prm,
+            None => return self.wrong("what?"),
+        };
+        if prm == "off" {
+            self.sndr.send_msg_to_elapse(ElpsMsg::TempoMap(-1, 0));
+            return "Tempo map has been cleared!".to_string();
+        }
+        let words = split_words(prm);
+        if words.len() != 2 {
+            return self.wrong("what?");
+        }
+        let msr = match words[0].parse::<i16>() {
+            Ok(m) if m >= 1 => m,
+            _ => return self.wrong("Measure is wrong."),
+        };
+        if words[1] == "off" {
+            self.sndr.send_msg_to_elapse(ElpsMsg::TempoMap(msr - 1, 0));
+            return format!("Tempo change at M{} has been removed!", msr);
+        }
+        match words[1].parse::<i16>() {
+            Ok(bpm) if (1..=400).contains(&bpm) => {
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::TempoMap(msr - 1, bpm));
+                format!("BPM will change to {} at M{}!", bpm, msr)
+            }
+            _ => self.wrong("Number is wrong."),
         }
     }
     fn letter_c(&mut self, input_text: &str) -> String {
@@ -146,11 +782,23 @@ impl LoopianCmd {
                     }
                     "Environment data erased!".to_string()
                 } else {
-                    "what?".to_string()
+                    self.wrong("what?")
                 }
             }
+        } else if Self::first_words(input_text, "click", "on")
+            || Self::first_words(input_text, "click", "off")
+        {
+            // 拍ごとの click(metronome)、再生中も切り替えられる
+            let on = Self::first_words(input_text, "click", "on");
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_CLICK, on as i16]));
+            if on {
+                "Click on!".to_string()
+            } else {
+                "Click off!".to_string()
+            }
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
     fn letter_e(&mut self, input_text: &str) -> String {
@@ -160,6 +808,20 @@ impl LoopianCmd {
             self.sndr.send_msg_to_elapse(ElpsMsg::Ctrl(MSG_CTRL_STOP));
             self.during_play = false;
             "Fine.".to_string()
+        } else if input_text.starts_with("export") {
+            // export setup stage : 今の設定の一覧を setup/stage.md に書き出す
+            let words = split_words(input_text);
+            if words.get(1).is_none_or(|w| w != "setup") {
+                return self.wrong("what?");
+            }
+            match Self::file_name_of(&words) {
+                Some(fname) => {
+                    self.sndr
+                        .send_msg_to_elapse(ElpsMsg::ExportSetup(fname.to_string()));
+                    "Export setup!".to_string()
+                }
+                None => self.wrong("File name is wrong."),
+            }
         } else if len >= 5 && &input_text[0..5] == "efct." {
             let efct = &input_text[5..];
             if efct.contains("dmp(") {
@@ -168,7 +830,7 @@ impl LoopianCmd {
                         .send_msg_to_elapse(ElpsMsg::Efct([MSG_EFCT_DMP, dmp as i16]));
                     format!("Set Damper Value: {}", dmp)
                 } else {
-                    "No Value!".to_string()
+                    self.wrong("No Value!")
                 }
             } else if efct.contains("cc70(") {
                 if let Some(cc70) = extract_number_from_parentheses(efct) {
@@ -176,13 +838,15 @@ impl LoopianCmd {
                         .send_msg_to_elapse(ElpsMsg::Efct([MSG_EFCT_CC70, cc70 as i16]));
                     format!("Set CC70 Value: {}", cc70)
                 } else {
-                    "No Value!".to_string()
+                    self.wrong("No Value!")
                 }
             } else {
-                "what?".to_string()
+                self.wrong("what?")
             }
+        } else if len >= 6 && &input_text[0..6] == "enter." {
+            self.apply_enter(&input_text[6..])
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
     fn letter_f(&mut self, input_text: &str) -> String {
@@ -197,8 +861,11 @@ impl LoopianCmd {
             self.sndr
                 .send_msg_to_elapse(ElpsMsg::Rit([MSG_RIT_NRM, MSG2_RIT_FERMATA]));
             "Will stop!".to_string()
+        } else if input_text == "freeze" || input_text.starts_with("freeze ") {
+            // freeze R1@3
+            self.apply_freeze(input_text[6..].trim())
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
     fn letter_g(&mut self, input_text: &str) -> CmndRtn {
@@ -223,6 +890,82 @@ impl LoopianCmd {
                     "Changed Graphic Note Pattern!".to_string(),
                     GraphicMsg::LissajousPattern,
                 )
+            } else if len == 14 && &input_text[6..14] == "particle" {
+                CmndRtn(
+                    "Changed Graphic Note Pattern!".to_string(),
+                    GraphicMsg::ParticlePattern,
+                )
+            } else if len >= 11 && &input_text[6..11] == "full(" {
+                let prm = extract_texts_from_parentheses(&input_text[6..]);
+                let monitor = if prm == "off" {
+                    Some(0)
+                } else {
+                    prm.parse::<i32>().ok().filter(|x| *x >= 1)
+                };
+                match monitor {
+                    Some(m) => CmndRtn(
+                        "Changed Window Size!".to_string(),
+                        GraphicMsg::FullScreen(m),
+                    ),
+                    None => CmndRtn(self.wrong("what?"), GraphicMsg::What),
+                }
+            } else if len >= 15 && &input_text[6..15] == "audience(" {
+                let prm = extract_texts_from_parentheses(&input_text[6..]);
+                let monitor = match prm {
+                    "on" => Some(0),
+                    "off" => Some(-1),
+                    _ => prm.parse::<i32>().ok().filter(|x| *x >= 1),
+                };
+                match monitor {
+                    Some(m) => CmndRtn(
+                        "Changed Audience Window!".to_string(),
+                        GraphicMsg::AudienceWin(m),
+                    ),
+                    None => CmndRtn(self.wrong("what?"), GraphicMsg::What),
+                }
+            } else if len == 10 && &input_text[6..10] == "shot" {
+                CmndRtn("Captured the screen!".to_string(), GraphicMsg::Screenshot)
+            } else if len >= 10 && &input_text[6..10] == "rec(" {
+                let prm = extract_texts_from_parentheses(&input_text[6..]);
+                let every = match prm {
+                    "on" => Some(1),
+                    "off" => Some(0),
+                    _ => prm.parse::<u32>().ok().filter(|x| *x >= 1),
+                };
+                match every {
+                    Some(0) => CmndRtn("Recording Stopped!".to_string(), GraphicMsg::FrameRec(0)),
+                    Some(n) => CmndRtn("Recording Frames!".to_string(), GraphicMsg::FrameRec(n)),
+                    None => CmndRtn(self.wrong("what?"), GraphicMsg::What),
+                }
+            } else if len == 15 && &input_text[6..15] == "metro(on)" {
+                CmndRtn(
+                    "Visual Metronome On!".to_string(),
+                    GraphicMsg::Metronome(true),
+                )
+            } else if len == 16 && &input_text[6..16] == "metro(off)" {
+                CmndRtn(
+                    "Visual Metronome Off!".to_string(),
+                    GraphicMsg::Metronome(false),
+                )
+            } else if len == 14 && &input_text[6..14] == "roll(on)" {
+                CmndRtn("Piano Roll On!".to_string(), GraphicMsg::PianoRoll(true))
+            } else if len == 15 && &input_text[6..15] == "roll(off)" {
+                CmndRtn("Piano Roll Off!".to_string(), GraphicMsg::PianoRoll(false))
+            } else if len >= 12 && &input_text[6..12] == "score(" {
+                let prm = extract_texts_from_parentheses(&input_text[6..]);
+                let part = match prm {
+                    "off" => Some(-1),
+                    "L1" => Some(LEFT1 as i32),
+                    "L2" => Some(LEFT2 as i32),
+                    "R1" => Some(RIGHT1 as i32),
+                    "R2" => Some(RIGHT2 as i32),
+                    _ => None,
+                };
+                match part {
+                    Some(-1) => CmndRtn("Score Off!".to_string(), GraphicMsg::Notation(-1)),
+                    Some(p) => CmndRtn("Score On!".to_string(), GraphicMsg::Notation(p)),
+                    None => CmndRtn(self.wrong("what?"), GraphicMsg::What),
+                }
             } else if len >= 16 && &input_text[6..16] == "beatlissa(" {
                 let cmd = &input_text[15..];
                 if let Some(blmd) = extract_number_from_parentheses(cmd) {
@@ -231,13 +974,25 @@ impl LoopianCmd {
                         GraphicMsg::BeatLissaPattern(blmd as i32),
                     )
                 } else {
-                    CmndRtn("what?".to_string(), GraphicMsg::What)
+                    CmndRtn(self.wrong("what?"), GraphicMsg::What)
                 }
             } else {
-                CmndRtn("what?".to_string(), GraphicMsg::What)
+                CmndRtn(self.wrong("what?"), GraphicMsg::What)
             }
         } else {
-            CmndRtn("what?".to_string(), GraphicMsg::What)
+            CmndRtn(self.wrong("what?"), GraphicMsg::What)
+        }
+    }
+    fn letter_k(&mut self, input_text: &str) -> String {
+        let len = input_text.chars().count();
+        if len == 9 && &input_text[0..9] == "keep.that" {
+            // 直近の演奏を SMF に書き出す
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::Ctrl(MSG_CTRL_KEEP_THAT));
+            // 書き出せたかどうかは、Elapse から返ってくる
+            "Keeping that...".to_string()
+        } else {
+            self.wrong("what?")
         }
     }
     fn letter_l(&mut self, input_text: &str) -> String {
@@ -248,10 +1003,87 @@ impl LoopianCmd {
         } else if len == 5 && &input_text[0..5] == "left2" {
             self.input_part = LEFT2;
             "Changed current part to left2.".to_string()
+        } else if len >= 6 && &input_text[0..6] == "listen" {
+            self.apply_listen(&input_text[6..])
+        } else if len >= 4 && &input_text[0..4] == "loop" {
+            self.apply_loop(&input_text[4..])
+        } else if Self::first_words(input_text, "load", "session") {
+            // load session foo : session/foo.toml から演奏の状態を復元する
+            match Self::file_name_of(&split_words(input_text)) {
+                Some(f) => self.load_session(f),
+                None => self.wrong("File name is wrong."),
+            }
+        } else if Self::first_words(input_text, "load", "setlist") {
+            // load setlist foo : setlist/foo.txt の順に scene を演奏する
+            match Self::file_name_of(&split_words(input_text)) {
+                Some(f) => self.load_set_list(f),
+                None => self.wrong("File name is wrong."),
+            }
+        } else {
+            self.wrong("what?")
+        }
+    }
+    fn letter_m(&mut self, input_text: &str) -> String {
+        let len = input_text.chars().count();
+        if len >= 5 && &input_text[0..5] == "mute." {
+            if let Some(pnum) = Self::detect_part(&input_text[5..]) {
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Mute(pnum as i16, true));
+                "Muted!".to_string()
+            } else {
+                self.wrong("what?")
+            }
+        } else if len >= 5 && (&input_text[0..5] == "mask." || &input_text[0..5] == "mask ") {
+            self.apply_mask(&input_text[5..])
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
+    /// nudge +1% : 一拍の間だけ 1% 速くして、拍の位相を少し前へずらす
+    /// nudge -1% : 一拍の間だけ 1% 遅くする(1-10%)
+    fn letter_n(&mut self, input_text: &str) -> String {
+        let words = split_words(input_text);
+        if words.len() != 2 || words[0] != "nudge" {
+            return self.wrong("what?");
+        }
+        let pct = words[1].trim_end_matches('%');
+        let pct = pct.strip_prefix('+').unwrap_or(pct);
+        match pct.parse::<i16>() {
+            Ok(p) if p != 0 && (-10..=10).contains(&p) => {
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_NUDGE, p]));
+                format!("Nudge {:+}%!", p)
+            }
+            _ => self.wrong("Number is wrong."),
+        }
+    }
+    /// mask L1 1100 : Loop の1,2小節目は鳴らし、3,4小節目は鳴らさない
+    /// mask L1 off : 解除、パート省略時は入力中のパート
+    fn apply_mask(&mut self, mask_text: &str) -> String {
+        let text = mask_text.replace(' ', ".");
+        let words: Vec<&str> = text.split('.').filter(|w| !w.is_empty()).collect();
+        let (part, ptn) = match words[..] {
+            [ptn] => (Some(self.input_part), ptn),
+            [pt, ptn] => (Self::detect_part(pt), ptn),
+            _ => return self.wrong("what?"),
+        };
+        let part = match part {
+            Some(p) if p < MAX_KBD_PART => p,
+            _ => return self.wrong("what?"),
+        };
+        if ptn == "off" {
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::Mask(part as i16, Vec::new()));
+            return "Mask off!".to_string();
+        }
+        if ptn.is_empty() || ptn.len() > MAX_MASK_MSR || ptn.chars().any(|c| c != '0' && c != '1') {
+            return self.wrong("Mask is wrong.");
+        }
+        let mask = ptn.chars().map(|c| c == '1').collect();
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::Mask(part as i16, mask));
+        "Masked!".to_string()
+    }
     fn letter_p(&mut self, input_text: &str) -> String {
         let len = input_text.chars().count();
         if (len == 4 && &input_text[0..4] == "play") || (len == 1 && &input_text[0..1] == "p") {
@@ -263,12 +1095,28 @@ impl LoopianCmd {
             } else {
                 "Playing now!".to_string()
             }
+        } else if split_words(input_text) == ["play", "from", "-1"] {
+            // 弱起やカウントのための一小節を置いてから始める
+            if !self.during_play {
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Ctrl(MSG_CTRL_COUNT_IN));
+                self.during_play = true;
+                "Phrase will start after one measure!".to_string()
+            } else {
+                "Playing now!".to_string()
+            }
         } else if len == 5 && &input_text[0..5] == "panic" {
             // panic
             self.sndr.send_msg_to_elapse(ElpsMsg::Ctrl(MSG_CTRL_PANIC));
             "All Sound Off!".to_string()
+        } else if len >= 8 && &input_text[0..8] == "practice" {
+            self.apply_practice(&input_text[8..])
+        } else if len >= 5 && &input_text[0..5] == "punch" {
+            self.apply_punch(&input_text[5..])
+        } else if len >= 3 && &input_text[0..3] == "pad" {
+            self.apply_chord_pad(&input_text[3..])
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
     fn letter_r(&mut self, input_text: &str) -> String {
@@ -285,12 +1133,14 @@ impl LoopianCmd {
             "Changed current part to right2.".to_string()
         } else if len >= 4 && &input_text[0..4] == "rit." {
             self.apply_rit(input_text)
+        } else if len >= 6 && &input_text[0..6] == "reroll" {
+            self.apply_reroll(&input_text[6..])
         } else if len >= 9 && &input_text[0..9] == "reconnect" {
             self.sndr
                 .send_msg_to_elapse(ElpsMsg::Ctrl(MSG_CTRL_MIDI_RECONNECT));
             "Send reconnect".to_string()
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
     fn letter_s(&mut self, input_text: &str) -> String {
@@ -307,6 +1157,16 @@ impl LoopianCmd {
         } else if len >= 4 && &input_text[0..4] == "set." {
             // set
             self.parse_set_command(input_text)
+        } else if len >= 7 && &input_text[0..7] == "setlist" {
+            self.apply_set_list(&input_text[7..])
+        } else if len >= 5 && &input_text[0..5] == "scene" {
+            self.apply_scene(&input_text[5..])
+        } else if Self::first_words(input_text, "save", "session") {
+            // save session foo : 演奏の状態を session/foo.toml に保存する
+            match Self::file_name_of(&split_words(input_text)) {
+                Some(f) => self.save_session(f),
+                None => self.wrong("File name is wrong."),
+            }
         } else if len >= 4 && &input_text[0..4] == "sync" {
             if len == 4 {
                 self.sndr
@@ -324,13 +1184,76 @@ impl LoopianCmd {
                     self.sndr.send_msg_to_elapse(ElpsMsg::Sync(MSG_SYNC_ALL));
                     "All Part Synchronized!".to_string()
                 } else {
-                    "what?".to_string()
+                    self.wrong("what?")
                 }
             } else {
-                "what?".to_string()
+                self.wrong("what?")
+            }
+        } else {
+            self.wrong("what?")
+        }
+    }
+    fn letter_u(&mut self, input_text: &str) -> String {
+        let len = input_text.chars().count();
+        if len >= 7 && &input_text[0..7] == "unmute." {
+            if let Some(pnum) = Self::detect_part(&input_text[7..]) {
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Mute(pnum as i16, false));
+                "Unmuted!".to_string()
+            } else {
+                self.wrong("what?")
+            }
+        } else {
+            self.wrong("what?")
+        }
+    }
+    /// vari(n) : 次の小節から Variation n を再生する
+    /// vari.beat(n) : 次の拍から Variation n を、Loop 内の同じ位置から再生する
+    /// vari.cmps(n) : Composition の Variation のみ切り替える
+    /// vari.link(on/off) : Phrase と Composition の Variation を連動させるか
+    fn letter_v(&mut self, input_text: &str) -> String {
+        let part = self.input_part as i16;
+        if part as usize >= MAX_KBD_PART {
+            return self.wrong("what?");
+        }
+        let kind = if input_text.starts_with("vari.cmps(") {
+            MSG_SETPT_CMPS_VARI
+        } else if input_text.starts_with("vari.link(") {
+            MSG_SETPT_VARI_LINK
+        } else if input_text.starts_with("vari.beat(") {
+            MSG_SETPT_VARI_BEAT
+        } else if input_text.starts_with("vari(") {
+            MSG_SETPT_VARI
+        } else {
+            return self.wrong("what?");
+        };
+        let prm = extract_texts_from_parentheses(input_text);
+        let value = if kind == MSG_SETPT_VARI_LINK {
+            match prm {
+                "on" => Some(1),
+                "off" => Some(0),
+                _ => None,
             }
         } else {
-            "what?".to_string()
+            prm.parse::<i16>()
+                .ok()
+                .filter(|x| *x >= 1 && (*x as usize) < MAX_VARIATION)
+        };
+        match value {
+            Some(v) => {
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::SetPart(part, [kind, v]));
+                if kind == MSG_SETPT_VARI_LINK {
+                    self.vari_link[part as usize] = v != 0;
+                    "Set Variation Mode!".to_string()
+                } else {
+                    if kind == MSG_SETPT_VARI || kind == MSG_SETPT_VARI_BEAT {
+                        self.vari_sel[part as usize] = v as usize;
+                    }
+                    "Variation Reserved!".to_string()
+                }
+            }
+            None => self.wrong("what?"),
         }
     }
     fn letter_h(&mut self, input_text: &str) -> String {
@@ -338,10 +1261,21 @@ impl LoopianCmd {
         if len == 5 && &input_text[0..5] == "hello" {
             "Hello,World!".to_string()
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
     fn letter_at(&mut self, input_text: &str) -> String {
+        // 入力画面では space が . になるので、どちらでも区切る
+        let words = split_words(input_text);
+        if words.len() >= 2 && words[0].starts_with("@m") {
+            if words[1] == "key" {
+                return self.apply_modulation(&words);
+            } else if words[1] == "graph" {
+                return self.apply_graph_reserve(&words);
+            } else if words[1] == "text" {
+                return self.apply_msr_text(input_text);
+            }
+        }
         let split_txt = split_by('=', input_text.to_string());
         if split_txt.len() == 2 {
             let len = split_txt[0].chars().count();
@@ -361,7 +1295,7 @@ impl LoopianCmd {
                         "Set Phrase!".to_string()
                     }
                 } else {
-                    "what?".to_string()
+                    self.wrong("what?")
                 }
             } else if len == 2 {
                 let ltr = split_txt[0].chars().nth(1).unwrap_or('x');
@@ -369,6 +1303,22 @@ impl LoopianCmd {
                 if ltr == 'c' {
                     self.dtstk.set_cluster_memory(split_txt[1].to_string());
                     return "Set a cluster memory!".to_string();
+                } else if vari > 0 && split_txt[1].starts_with('{') {
+                    // Composition の Variation
+                    if self.dtstk.set_raw_cmps_vari(
+                        self.input_part,
+                        vari as usize,
+                        split_txt[1].to_string(),
+                    ) {
+                        self.sndr.send_cmps_vari_to_elapse(
+                            self.input_part,
+                            vari as usize,
+                            &self.dtstk,
+                        );
+                        "Set Composition!".to_string()
+                    } else {
+                        self.wrong("what?")
+                    }
                 } else if vari > 0 {
                     if let Some(additional) = self.put_phrase(
                         self.input_part,
@@ -381,16 +1331,16 @@ impl LoopianCmd {
                             "Set Phrase!".to_string()
                         }
                     } else {
-                        "what?".to_string()
+                        self.wrong("what?")
                     }
                 } else {
-                    "what?".to_string()
+                    self.wrong("what?")
                 }
             } else {
-                "what?".to_string()
+                self.wrong("what?")
             }
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
     fn letter_bracket(&mut self, input_text: &str) -> String {
@@ -401,7 +1351,7 @@ impl LoopianCmd {
                 "Set Phrase!".to_string()
             }
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
     fn letter_brace(&mut self, input_text: &str) -> String {
@@ -413,7 +1363,7 @@ impl LoopianCmd {
                 .send_composition_to_elapse(self.input_part, &self.dtstk);
             "Set Composition!".to_string()
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
     fn letter_dot(&mut self, input_text: &str) -> String {
@@ -429,7 +1379,7 @@ impl LoopianCmd {
                 "Phrase has started!".to_string()
             }
         } else {
-            "what?".to_string()
+            self.wrong("what?")
         }
     }
     fn letter_part(&mut self, input_text: &str) -> String {
@@ -440,7 +1390,7 @@ impl LoopianCmd {
                 LEFT2 => "Changed current part to left2.".to_string(),
                 RIGHT1 => "Changed current part to right1.".to_string(),
                 RIGHT2 => "Changed current part to right2.".to_string(),
-                _ => "what?".to_string(),
+                _ => self.wrong("what?"),
             }
         } else {
             self.shortcut_input(input_text)
@@ -448,53 +1398,58 @@ impl LoopianCmd {
     }
     fn shortcut_input(&mut self, input_text: &str) -> String {
         // shortcut input
-        let mut rtn_str = "what?".to_string();
-        for (i, ltr) in input_text.chars().enumerate() {
+        let mut rtn_str = None;
+        for (i, ltr) in input_text.char_indices() {
             if ltr == '.' {
-                let first_letter = &input_text[i + 1..i + 2]; // '{' '['
+                let first_letter = input_text.get(i + 1..i + 2).unwrap_or(""); // '{' '['
                 let part_str = &input_text[0..i];
                 let rest_text = &input_text[i + 1..];
                 match part_str {
-                    "L1" => rtn_str = self.call_bracket_brace(LEFT1, first_letter, rest_text),
-                    "L2" => rtn_str = self.call_bracket_brace(LEFT2, first_letter, rest_text),
+                    "L1" => rtn_str = Some(self.call_bracket_brace(LEFT1, first_letter, rest_text)),
+                    "L2" => rtn_str = Some(self.call_bracket_brace(LEFT2, first_letter, rest_text)),
                     "L12" => {
                         self.call_bracket_brace(LEFT1, first_letter, rest_text);
-                        rtn_str = self.call_bracket_brace(LEFT2, first_letter, rest_text);
+                        rtn_str = Some(self.call_bracket_brace(LEFT2, first_letter, rest_text));
                     }
                     "L1!" => {
                         self.call_bracket_brace(LEFT2, first_letter, rest_text);
                         self.call_bracket_brace(RIGHT1, first_letter, rest_text);
-                        rtn_str = self.call_bracket_brace(RIGHT2, first_letter, rest_text);
+                        rtn_str = Some(self.call_bracket_brace(RIGHT2, first_letter, rest_text));
                     }
                     "L2!" => {
                         self.call_bracket_brace(LEFT1, first_letter, rest_text);
                         self.call_bracket_brace(RIGHT1, first_letter, rest_text);
-                        rtn_str = self.call_bracket_brace(RIGHT2, first_letter, rest_text);
+                        rtn_str = Some(self.call_bracket_brace(RIGHT2, first_letter, rest_text));
+                    }
+                    "R1" => {
+                        rtn_str = Some(self.call_bracket_brace(RIGHT1, first_letter, rest_text))
+                    }
+                    "R2" => {
+                        rtn_str = Some(self.call_bracket_brace(RIGHT2, first_letter, rest_text))
                     }
-                    "R1" => rtn_str = self.call_bracket_brace(RIGHT1, first_letter, rest_text),
-                    "R2" => rtn_str = self.call_bracket_brace(RIGHT2, first_letter, rest_text),
                     "R12" => {
                         self.call_bracket_brace(RIGHT1, first_letter, rest_text);
-                        rtn_str = self.call_bracket_brace(RIGHT2, first_letter, rest_text);
+                        rtn_str = Some(self.call_bracket_brace(RIGHT2, first_letter, rest_text));
                     }
                     "R1!" => {
                         self.call_bracket_brace(LEFT1, first_letter, rest_text);
                         self.call_bracket_brace(LEFT2, first_letter, rest_text);
-                        rtn_str = self.call_bracket_brace(RIGHT2, first_letter, rest_text);
+                        rtn_str = Some(self.call_bracket_brace(RIGHT2, first_letter, rest_text));
                     }
                     "R2!" => {
                         self.call_bracket_brace(LEFT1, first_letter, rest_text);
                         self.call_bracket_brace(LEFT2, first_letter, rest_text);
-                        rtn_str = self.call_bracket_brace(RIGHT1, first_letter, rest_text);
+                        rtn_str = Some(self.call_bracket_brace(RIGHT1, first_letter, rest_text));
                     }
                     "FLOW" => {
                         if first_letter == "{" {
-                            rtn_str = self.call_bracket_brace(FLOW_PART, first_letter, rest_text);
+                            rtn_str =
+                                Some(self.call_bracket_brace(FLOW_PART, first_letter, rest_text));
                         }
                     }
                     "ALL" => {
                         for i in 0..MAX_KBD_PART {
-                            rtn_str = self.call_bracket_brace(i, first_letter, rest_text);
+                            rtn_str = Some(self.call_bracket_brace(i, first_letter, rest_text));
                         }
                     }
                     _ => println!("No Part!"),
@@ -502,12 +1457,63 @@ impl LoopianCmd {
                 break;
             }
         }
-        rtn_str
+        rtn_str.unwrap_or_else(|| self.wrong("what?"))
+    }
+    /// 直前の Loop で鳴った音を Variation(指定がなければ @9)に書き出すよう、Elapse に頼む
+    fn apply_freeze(&mut self, prm: &str) -> String {
+        let (part_str, vari_str) = prm.split_once('@').unwrap_or((prm, "9"));
+        let part = if part_str.is_empty() {
+            Some(self.input_part)
+        } else {
+            Self::detect_part(part_str)
+        };
+        match (part, vari_str.parse::<usize>()) {
+            (Some(pt), Ok(vari)) if (1..=9).contains(&vari) => {
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Freeze(pt as i16, vari as i16));
+                "Freeze!".to_string()
+            }
+            _ => self.wrong("what?"),
+        }
+    }
+    /// punch で弾いた音を差し替えた音から、Normal Phrase を入力するコマンドの文字列を作る
+    pub fn gen_punch_cmd(&self, part: usize, evts: &[PhrEvt], whole_tick: i32) -> String {
+        let (name, phr) = self.gen_played_phrase(part, evts, whole_tick);
+        format!("{}.{}", name, phr)
+    }
+    /// freeze で受け取った音から、Variation を入力するコマンドの文字列を作る
+    pub fn gen_freeze_cmd(
+        &self,
+        part: usize,
+        vari: i16,
+        evts: &[PhrEvt],
+        whole_tick: i32,
+    ) -> String {
+        let (name, phr) = self.gen_played_phrase(part, evts, whole_tick);
+        format!("{}.@{}={}", name, vari, phr)
+    }
+    /// Elapse で鳴った音から、Part 名と Phrase の文字列を作る
+    fn gen_played_phrase(
+        &self,
+        part: usize,
+        evts: &[PhrEvt],
+        whole_tick: i32,
+    ) -> (&'static str, String) {
+        let name = ["L1", "L2", "R1", "R2"][part.min(MAX_KBD_PART - 1)];
+        let base_note = self.dtstk.get_pdstk(part, PhraseAs::Normal).get_base_note();
+        let phr = gen_phrase_text(
+            &self.dtstk.remove_beat_accent(evts),
+            base_note,
+            self.dtstk.get_input_mode(),
+            self.dtstk.get_tick_for_onemsr(),
+            whole_tick,
+        );
+        (name, phr)
     }
-    fn detect_part(part_str: &str) -> Option<usize> {
+    pub fn detect_part(part_str: &str) -> Option<usize> {
         let len = part_str.chars().count();
         if len == 5 {
-            let pt = &part_str[0..5];
+            let pt = part_str.get(0..5)?;
             if pt == "left1" {
                 Some(LEFT1)
             } else if pt == "left2" {
@@ -516,7 +1522,7 @@ impl LoopianCmd {
                 None
             }
         } else if len == 6 {
-            let pt = &part_str[0..6];
+            let pt = part_str.get(0..6)?;
             if pt == "right1" {
                 Some(RIGHT1)
             } else if pt == "right2" {
@@ -525,7 +1531,7 @@ impl LoopianCmd {
                 None
             }
         } else if len == 2 {
-            let pt = &part_str[0..2];
+            let pt = part_str.get(0..2)?;
             if pt == "L1" {
                 Some(LEFT1)
             } else if pt == "L2" {
@@ -556,16 +1562,16 @@ impl LoopianCmd {
             itx = rs;
             input_text = &itx;
         } else {
-            return "Invalid Syntax!".to_string();
+            return self.wrong("Invalid Syntax!");
         }
 
-        let mut rtn_str = "what?".to_string();
         let org_part = self.input_part;
         self.recursive = true;
         self.input_part = part_num;
-        if let Some(ans) = self.put_and_get_responce(input_text) {
-            rtn_str = ans.0;
-        }
+        let rtn_str = match self.put_and_get_responce(input_text) {
+            Some(ans) => ans.0,
+            None => self.wrong("what?"),
+        };
         self.input_part = org_part;
         self.recursive = false;
 
@@ -591,6 +1597,10 @@ impl LoopianCmd {
                 // additional なので、elapse にはまだ送らない
                 Some(true)
             } else {
+                if vari == PhraseAs::Normal {
+                    // 新しい Phrase を入力すると、Variation から Normal に戻る
+                    self.vari_sel[part_num] = 0;
+                }
                 self.sndr.send_phrase_to_elapse(part_num, vari, &self.dtstk);
                 Some(false)
             }
@@ -601,56 +1611,429 @@ impl LoopianCmd {
     fn clear_part(&mut self, part_num: usize) {
         // seq stock のデータを消去
         self.dtstk.del_raw_phrase(part_num);
+        if part_num < MAX_KBD_PART {
+            self.vari_sel[part_num] = 0;
+        }
 
         // Phrase を消去する message を送る
         self.sndr.clear_phrase_to_elapse(part_num);
 
         let empty_cmp = "{}".to_string();
-        if self.dtstk.set_raw_composition(part_num, empty_cmp) {
+        if self.dtstk.set_raw_composition(part_num, empty_cmp.clone()) {
             self.sndr.send_composition_to_elapse(part_num, &self.dtstk);
         }
+        for vari in 1..MAX_VARIATION {
+            if !self.dtstk.get_cvdstk(part_num, vari).is_empty()
+                && self
+                    .dtstk
+                    .set_raw_cmps_vari(part_num, vari, empty_cmp.clone())
+            {
+                self.sndr
+                    .send_cmps_vari_to_elapse(part_num, vari, &self.dtstk);
+            }
+        }
         self.dtstk.change_oct(0, true, part_num);
     }
+    /// rit.poco.bar(2).then.accel.bpm(132).bar(4) : then で rit./accel. を連結する
+    /// rit.poco.2bars.then.accel.to.132.over.4bars とも書ける
     fn apply_rit(&self, input_text: &str) -> String {
-        let mut aft_rit: i16 = MSG2_RIT_ATMP;
-        let mut strength_value: i16 = MSG_RIT_NRM;
+        let text = input_text.replace(' ', ".");
+        if let Some(type_txt) = text.strip_prefix("rit.type.") {
+            return self.apply_rit_type(type_txt);
+        }
+        let segments: Vec<&str> = text.split(".then.").collect();
+        let mut msgs = Vec::new();
+        let mut from = Some(self.dtstk.get_bpm()); // rit./accel. を始めるテンポ
+        for (i, seg) in segments.iter().enumerate() {
+            let msg = match Self::rit_segment(seg, i + 1 == segments.len()) {
+                Ok(msg) => msg,
+                Err(e) => return self.wrong(&e),
+            };
+            if msg[1] < MSG2_RIT_HOLD {
+                // accel. は速く、rit. は遅くなるテンポにしか向かえない
+                let accel = msg[0] % 10 == MSG_RIT_ACCEL;
+                if from.is_some_and(|b| if accel { msg[1] <= b } else { msg[1] >= b }) {
+                    return self.wrong("what?");
+                }
+                from = Some(msg[1]);
+            } else {
+                from = None;
+            }
+            msgs.push(msg);
+        }
+        if msgs.len() == 1 {
+            self.sndr.send_msg_to_elapse(ElpsMsg::Rit(msgs[0]));
+            if msgs[0][0] % 10 == MSG_RIT_ACCEL {
+                "accel. has started!".to_string()
+            } else {
+                "rit. has started!".to_string()
+            }
+        } else {
+            self.sndr.send_msg_to_elapse(ElpsMsg::RitChain(msgs));
+            "Tempo gestures have started!".to_string()
+        }
+    }
+    /// rit.type.sigmoid : rit. の曲線を変える(linear/precise/sigmoid)
+    /// rit.type.linear.min(40) : 40 より遅くしない、rit.type.sigmoid.steep(200) : 急峻さ[%]
+    fn apply_rit_type(&self, type_txt: &str) -> String {
+        let words = split_by('.', type_txt.to_string());
+        let tp = match words[0].as_str() {
+            "linear" => RIT_TYPE_LINEAR,
+            "precise" => RIT_TYPE_PRECISE,
+            "sigmoid" => RIT_TYPE_SIGMOID,
+            _ => return self.wrong("what?"),
+        };
+        let mut msgs = vec![[MSG_SET_RIT_TYPE, tp]];
+        for word in words[1..].iter() {
+            let msg = match separate_cmnd_and_str(word) {
+                Some(("min", prm)) => match prm.parse::<i16>() {
+                    Ok(bpm) if (0..=300).contains(&bpm) => [MSG_SET_RIT_MIN, bpm],
+                    _ => return self.wrong("Number is wrong."),
+                },
+                Some(("steep", prm)) => match prm.parse::<i16>() {
+                    Ok(steep) if (100..=400).contains(&steep) => [MSG_SET_RIT_STEEP, steep],
+                    _ => return self.wrong("Number is wrong."),
+                },
+                _ => return self.wrong("what?"),
+            };
+            msgs.push(msg);
+        }
+        for msg in msgs.into_iter() {
+            self.sndr.send_msg_to_elapse(ElpsMsg::Set(msg));
+        }
+        "rit. type has changed!".to_string()
+    }
+    /// rit./accel. 一つ分を ElpsMsg::Rit の形にする
+    /// last: 連結の最後か(途中の rit. は、到達したテンポのまま次へ続ける)
+    fn rit_segment(seg: &str, last: bool) -> Result<[i16; 2], String> {
+        let accel;
+        let rit_txt;
+        if seg == "rit" || seg.starts_with("rit.") {
+            accel = false;
+            rit_txt = seg.get(4..).unwrap_or("");
+        } else if let Some(txt) = seg.strip_prefix("accel.") {
+            accel = true;
+            rit_txt = txt;
+        } else {
+            return Err("what?".to_string());
+        }
+        let mut rit_txt = split_by('.', rit_txt.to_string());
+        let mut aft_rit: i16 = if last { MSG2_RIT_ATMP } else { MSG2_RIT_HOLD };
+        let mut strength_value: i16 = if accel { MSG_RIT_ACCEL } else { MSG_RIT_NRM };
         let mut bar_num: i16 = 0;
-        let mut rit_txt = split_by('.', input_text[4..].to_string());
+        let set_bar = |num: i16| if num >= 1 { num - 1 } else { 0 }; // 入力値は、内部値より1大きい
 
         while !rit_txt.is_empty() {
-            if rit_txt[0].chars().any(|x| x == '(') {
-                if let Some((cmd, prm)) = separate_cmnd_and_str(&rit_txt[0]) {
+            let txt = rit_txt[0].as_str();
+            if txt.chars().any(|x| x == '(') {
+                if let Some((cmd, prm)) = separate_cmnd_and_str(txt) {
                     if cmd == "bar" {
-                        bar_num = prm.parse::<i16>().unwrap_or(0);
-                        if bar_num >= 1 {
-                            // 入力値は、内部値より1大きい
-                            bar_num -= 1;
-                        }
+                        bar_num = set_bar(prm.parse::<i16>().unwrap_or(0));
                     } else if cmd == "bpm" {
                         if let Ok(tmp) = prm.parse::<i16>() {
                             aft_rit = tmp;
                         } else {
-                            return "Number is wrong.".to_string();
+                            return Err("Number is wrong.".to_string());
                         }
                     }
                 }
-            } else if rit_txt[0] == "molto" {
+            } else if let Some(num) = txt.strip_suffix("bars").or(txt.strip_suffix("bar")) {
+                bar_num = set_bar(num.parse::<i16>().unwrap_or(0));
+            } else if let Ok(tmp) = txt.parse::<i16>() {
+                aft_rit = tmp; // to.132 (to/over は読み飛ばす)
+            } else if txt == "molto" && !accel {
                 strength_value = MSG_RIT_MLT;
-            } else if rit_txt[0] == "poco" {
+            } else if txt == "poco" && !accel {
                 strength_value = MSG_RIT_POCO;
-            } else if rit_txt[0] == "fermata" {
+            } else if txt == "fermata" && !accel && last {
                 aft_rit = MSG2_RIT_FERMATA;
             }
             rit_txt.remove(0);
         }
+        if accel && (aft_rit == MSG2_RIT_ATMP || aft_rit == MSG2_RIT_HOLD) {
+            // accel. は到達テンポが必要
+            return Err("Number is wrong.".to_string());
+        }
 
         println!(
             "Rit,strength:{}, bar:{}, after:{}",
             strength_value, bar_num, aft_rit
         );
+        Ok([strength_value + bar_num * 10, aft_rit])
+    }
+    /// enter.L1@m1.L2@m5.R1@m9 : 各パートを指定小節から鳴らし始める
+    /// 指定小節までは mute し、指定小節の頭で sync + unmute する
+    fn apply_enter(&self, input_text: &str) -> String {
+        let mut entries: Vec<(usize, i16)> = Vec::new();
+        for txt in split_by('.', input_text.to_string()).iter() {
+            let pt_msr = split_by('@', txt.to_string());
+            if pt_msr.len() != 2 || !pt_msr[1].starts_with('m') {
+                return self.wrong("what?");
+            }
+            let msr = pt_msr[1][1..].parse::<i16>().unwrap_or(0);
+            if msr < 1 {
+                return self.wrong("Measure is wrong.");
+            }
+            if let Some(pnum) = Self::detect_part(&pt_msr[0]) {
+                entries.push((pnum, msr));
+            } else {
+                return self.wrong("Part is wrong.");
+            }
+        }
+        for (pnum, msr) in entries.iter() {
+            if *msr > 1 {
+                let pt = *pnum as i16;
+                self.sndr.send_msg_to_elapse(ElpsMsg::Mute(pt, true));
+                self.sndr
+                    .send_msg_to_elapse(ElpsMsg::Reserve(msr - 1, Box::new(ElpsMsg::Sync(pt))));
+                self.sndr.send_msg_to_elapse(ElpsMsg::Reserve(
+                    msr - 1,
+                    Box::new(ElpsMsg::Mute(pt, false)),
+                ));
+            }
+        }
+        "Entry has been scheduled!".to_string()
+    }
+    /// listen : オーディオ入力の発音タイミングに追従する
+    /// listen.chord : オーディオ入力から和音を推定し、Flow に設定する
+    /// listen.all : 両方, listen.hold : 和音推定の固定/解除, listen.off : 終了
+    fn apply_listen(&self, input_text: &str) -> String {
+        if input_text == ".off" {
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::Listen([-1, LISTEN_BEAT]));
+            return "Stopped listening.".to_string();
+        } else if input_text == ".hold" {
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::Listen([0, LISTEN_HOLD]));
+            return "Toggled chord hold.".to_string();
+        }
+        let (mode, prm) = if let Some(p) = input_text.strip_prefix(".chord") {
+            (LISTEN_CHORD, p)
+        } else if let Some(p) = input_text.strip_prefix(".all") {
+            (LISTEN_BEAT | LISTEN_CHORD, p)
+        } else {
+            (LISTEN_BEAT, input_text)
+        };
+        let sens = if prm.is_empty() {
+            50
+        } else if let Some(sens) = extract_number_from_parentheses(prm) {
+            sens.min(100) as i16
+        } else {
+            return self.wrong("what?");
+        };
+        self.sndr.send_msg_to_elapse(ElpsMsg::Listen([sens, mode]));
+        format!("Listening! Sensitivity: {}", sens)
+    }
+    /// @m17 key E : 17小節目の頭で key を E に変える
+    /// @m17 key E pivot : Composition の key は、17小節目以降の最初の和音の変わり目で変える
+    fn apply_modulation(&mut self, prms: &[String]) -> String {
+        if prms.len() < 3 {
+            return self.wrong("what?");
+        }
+        let msr = prms[0][2..].parse::<i16>().unwrap_or(0);
+        if msr < 1 {
+            return self.wrong("Measure is wrong.");
+        }
+        let pivot = prms.len() >= 4 && prms[3] == "pivot";
+        let kname = prms[2].as_str();
+        let pc = match kname
+            .get(0..1)
+            .and_then(|l| NOTE_NAME.iter().position(|x| *x == l))
+        {
+            Some(pc) => pc as i16,
+            None => return self.wrong("what?"),
+        };
+        let key = match &kname[1..] {
+            "#" => pc + 1,
+            "b" => pc - 1,
+            "" => pc,
+            _ => return self.wrong("what?"),
+        }
+        .rem_euclid(12);
+        let value = if pivot { key + MSG2_KEY_PIVOT } else { key };
+        self.sndr.send_msg_to_elapse(ElpsMsg::Reserve(
+            msr - 1,
+            Box::new(ElpsMsg::Set([MSG_SET_MODULATE, value])),
+        ));
+        format!("Key will change to {} at M{}!", kname, msr)
+    }
+    /// @m17 graph voice dark : 17小節目の頭で Graphic の pattern/mode を変える
+    fn apply_graph_reserve(&mut self, prms: &[String]) -> String {
+        if prms.len() < 3 {
+            return self.wrong("what?");
+        }
+        let msr = prms[0][2..].parse::<i16>().unwrap_or(0);
+        if msr < 1 {
+            return self.wrong("Measure is wrong.");
+        }
+        let mut gmsgs = Vec::new();
+        for gname in prms[2..].iter() {
+            let CmndRtn(_, gmsg) = self.letter_g(&format!("graph.{}", gname));
+            if gmsg == GraphicMsg::What || gmsg == GraphicMsg::NoMsg {
+                return self.wrong("what?");
+            }
+            gmsgs.push(gmsg);
+        }
+        for gmsg in gmsgs.into_iter() {
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::Reserve(msr - 1, Box::new(ElpsMsg::Graph(gmsg))));
+        }
+        format!("Graphic will change at M{}!", msr)
+    }
+    /// @m9 text Hello : 9小節目から表示する歌詞やメモ
+    /// @m9 text : 削除
+    fn apply_msr_text(&mut self, input_text: &str) -> String {
+        let prms: Vec<&str> = input_text.splitn(3, [' ', '.']).collect();
+        if prms.len() < 2 {
+            return self.wrong("what?");
+        }
+        let msr = prms[0][2..].parse::<i16>().unwrap_or(0);
+        if msr < 1 {
+            return self.wrong("Measure is wrong.");
+        }
+        let txt = prms.get(2).map_or("", |x| x.trim()).replace('.', " ");
         self.sndr
-            .send_msg_to_elapse(ElpsMsg::Rit([strength_value + bar_num * 10, aft_rit]));
-
-        "rit. has started!".to_string()
+            .send_msg_to_elapse(ElpsMsg::MsrText(msr - 1, txt.clone()));
+        if txt.is_empty() {
+            format!("Text of M{} has been deleted!", msr)
+        } else {
+            format!("Set Text to M{}!", msr)
+        }
+    }
+    /// loop m9 m17 : 9小節目から演奏し、17小節目の頭で 9小節目に戻る
+    /// loop off : 解除
+    fn apply_loop(&self, input_text: &str) -> String {
+        let prms = split_words(input_text);
+        if prms.len() == 1 && prms[0] == "off" {
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::LoopRgn([MSG_LOOP_RGN_OFF, 0]));
+            return "Loop has been released!".to_string();
+        } else if prms.len() != 2 {
+            return self.wrong("what?");
+        }
+        let mut msr: [i16; 2] = [0, 0];
+        for (i, p) in prms.iter().enumerate() {
+            msr[i] = p.trim_start_matches('m').parse::<i16>().unwrap_or(0);
+        }
+        if msr[0] < 1 || msr[1] <= msr[0] {
+            return self.wrong("Measure is wrong.");
+        }
+        self.sndr.send_msg_to_elapse(ElpsMsg::LoopRgn(msr));
+        format!("Loop M{}-M{}!", msr[0], msr[1])
+    }
+    /// punch m5 m9 : ペダルの録音を 5小節目から 9小節目の頭までにし、その小節だけを差し替える
+    /// punch off : 解除
+    fn apply_punch(&self, input_text: &str) -> String {
+        let prms = split_words(input_text);
+        if prms.len() == 1 && prms[0] == "off" {
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::Punch([MSG_PUNCH_OFF, 0, 0]));
+            return "Punch has been released!".to_string();
+        } else if prms.len() != 2 {
+            return self.wrong("what?");
+        }
+        let mut msr: [i16; 2] = [0, 0];
+        for (i, p) in prms.iter().enumerate() {
+            msr[i] = p.trim_start_matches('m').parse::<i16>().unwrap_or(0);
+        }
+        if msr[0] < 1 || msr[1] <= msr[0] {
+            return self.wrong("Measure is wrong.");
+        }
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::Punch([msr[0], msr[1], self.input_part as i16]));
+        format!("Punch M{}-M{}!", msr[0], msr[1])
+    }
+    /// reroll : 次の小節の頭から、全 Part の乱数の種を新しくする
+    /// reroll R1 : R1 だけ / reroll 1234, reroll R1 1234 : 以前の種を使う
+    fn apply_reroll(&self, input_text: &str) -> String {
+        let mut prms = split_words(input_text);
+        let part = match prms.first().and_then(|p| Self::detect_part(p)) {
+            Some(pt) => {
+                prms.remove(0);
+                Some(pt)
+            }
+            None => None,
+        };
+        let seed = match prms.as_slice() {
+            [] => rand::random::<u32>(),
+            [num] => match num.parse::<u32>() {
+                Ok(s) => s,
+                Err(_) => return "Number is wrong.".to_string(),
+            },
+            _ => return self.wrong("what?"),
+        };
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::Reroll(part.map_or(-1, |pt| pt as i16), seed));
+        match part {
+            Some(pt) => {
+                let name = ["L1", "L2", "R1", "R2"][pt.min(MAX_KBD_PART - 1)];
+                format!("Reroll {} at the next bar! seed: {}", name, seed)
+            }
+            None => format!("Reroll at the next bar! seed: {}", seed),
+        }
+    }
+    /// pad store 3 : Flow の今の和音と voicing を pad 3 に記憶する
+    /// pad 3 : pad 3 を呼び出す、pad off : 呼び出した voicing を消す
+    fn apply_chord_pad(&self, input_text: &str) -> String {
+        let prms = split_words(input_text);
+        let (store, num) = match prms.as_slice() {
+            [off] if off == "off" => {
+                self.sndr.send_msg_to_elapse(ElpsMsg::ChordPad(false, 0));
+                return "Chord pad off!".to_string();
+            }
+            [num] => (false, num),
+            [cmd, num] if cmd == "store" => (true, num),
+            _ => return self.wrong("what?"),
+        };
+        match num.parse::<i16>() {
+            Ok(n) if (1..=MAX_CHORD_PAD as i16).contains(&n) => {
+                self.sndr.send_msg_to_elapse(ElpsMsg::ChordPad(store, n));
+                if store {
+                    format!("Chord pad {} has been stored!", n)
+                } else {
+                    format!("Chord pad {}!", n)
+                }
+            }
+            _ => self.wrong("Number is wrong."),
+        }
+    }
+    /// practice.msr(5,8).bpm(4).rpt(2) : 5-8小節を繰り返し、2回ごとに bpm を 4 上げる
+    /// practice.off : 終了
+    fn apply_practice(&self, input_text: &str) -> String {
+        if input_text == ".off" {
+            self.sndr
+                .send_msg_to_elapse(ElpsMsg::Practice([MSG_PRACTICE_OFF, 0, 0, 0]));
+            return "Practice has finished!".to_string();
+        }
+        let mut msr: [i16; 2] = [0, 0];
+        let mut inc_bpm: i16 = 0;
+        let mut rpt: i16 = 1;
+        let prc_txt = split_by('.', input_text.to_string());
+        for txt in prc_txt.iter() {
+            if let Some((cmd, prm)) = separate_cmnd_and_str(txt) {
+                if cmd == "msr" {
+                    let msrs = split_by(',', prm.to_string());
+                    if msrs.len() != 2 {
+                        return self.wrong("Measure is wrong.");
+                    }
+                    for (i, m) in msrs.iter().enumerate() {
+                        msr[i] = m.parse::<i16>().unwrap_or(0);
+                    }
+                } else if cmd == "bpm" {
+                    inc_bpm = prm.parse::<i16>().unwrap_or(0);
+                } else if cmd == "rpt" {
+                    rpt = prm.parse::<i16>().unwrap_or(1).max(1);
+                }
+            }
+        }
+        if msr[0] < 1 || msr[1] < msr[0] {
+            return self.wrong("Measure is wrong.");
+        }
+        self.sndr
+            .send_msg_to_elapse(ElpsMsg::Practice([msr[0], msr[1], inc_bpm, rpt]));
+        format!(
+            "Practice M{}-M{}, bpm +{} every {} pass!",
+            msr[0], msr[1], inc_bpm, rpt
+        )
     }
 }
@@ -8,15 +8,21 @@ use crate::lpnlib::*;
 use std::sync::mpsc;
 
 pub struct MessageSender {
-    msg_hndr: mpsc::Sender<ElpsMsg>,
+    msg_hndr: Option<mpsc::Sender<ElpsMsg>>, // None: 何も送らない
 }
 
 impl MessageSender {
     pub fn new(msg_hndr: mpsc::Sender<ElpsMsg>) -> Self {
-        Self { msg_hndr }
+        Self {
+            msg_hndr: Some(msg_hndr),
+        }
+    }
+    /// batch を試すための複製で使う、何も送らない sender
+    pub fn dry_run() -> Self {
+        Self { msg_hndr: None }
     }
     pub fn send_msg_to_elapse(&self, msg: ElpsMsg) {
-        if let Err(e) = self.msg_hndr.send(msg) {
+        if let Some(Err(e)) = self.msg_hndr.as_ref().map(|h| h.send(msg)) {
             println!("Something happened on MPSC for Elps! {}", e)
         }
     }
@@ -42,7 +48,7 @@ impl MessageSender {
         self.send_msg_to_elapse(ElpsMsg::PhrX(part as i16));
     }
     pub fn send_composition_to_elapse(&self, part: usize, gdt: &SeqDataStock) {
-        let cdt = gdt.get_cdstk(part).get_final(part as i16);
+        let cdt = gdt.get_cdstk(part).get_final(part as i16, 0);
         let cmsg = cdt.clone();
         if let ElpsMsg::Cmp(_c0, cv) = &cdt {
             if cv.evts.is_empty() {
@@ -53,4 +59,40 @@ impl MessageSender {
             }
         }
     }
+    /// scene 用: 全パートの Phrase/Composition を、空のものも含めて msg にする
+    /// 空の Phrase/Composition は、次の Loop の頭から鳴らなくなる
+    pub fn gen_all_data_msg(gdt: &SeqDataStock) -> Vec<ElpsMsg> {
+        let mut msgs = Vec::new();
+        for part in 0..MAX_COMPOSITION_PART {
+            let cdt = gdt.get_cdstk(part).get_final(part as i16, 0);
+            match &cdt {
+                ElpsMsg::Cmp(_c0, cv) if cv.evts.is_empty() => {
+                    msgs.push(ElpsMsg::CmpX(part as i16))
+                }
+                _ => msgs.push(cdt),
+            }
+        }
+        for part in 0..MAX_KBD_PART {
+            for i in 0..MAX_VARIATION {
+                let vari = if i == 0 {
+                    PhraseAs::Normal
+                } else {
+                    PhraseAs::Variation(i)
+                };
+                msgs.push(
+                    gdt.get_pdstk(part, vari.clone())
+                        .get_final(part as i16, vari),
+                );
+            }
+            for vari in 1..MAX_VARIATION {
+                msgs.push(gdt.get_cvdstk(part, vari).get_final(part as i16, vari));
+            }
+        }
+        msgs
+    }
+    /// Composition の Variation を送る。空なら Elapse 側で削除される
+    pub fn send_cmps_vari_to_elapse(&self, part: usize, vari: usize, gdt: &SeqDataStock) {
+        let cdt = gdt.get_cvdstk(part, vari).get_final(part as i16, vari);
+        self.send_msg_to_elapse(cdt);
+    }
 }
@@ -5,6 +5,7 @@
 //
 use super::txt2seq_ana::*;
 use super::txt2seq_cmps::*;
+use super::txt2seq_dp::*;
 use super::txt2seq_phr::*;
 use super::txt_common::*;
 use crate::lpnlib::*;
@@ -14,10 +15,12 @@ use crate::lpnlib::*;
 //*******************************************************************
 // SeqDataStock の責務
 //  入力された Phrase/Composition Data の変換と保持
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SeqDataStock {
     pdt: Vec<Vec<PhraseDataStock>>,
     cdt: [CompositionDataStock; MAX_COMPOSITION_PART],
+    cvdt: Vec<Vec<CompositionDataStock>>, // Composition の Variation(0 は使わない)
+    msr_raw: Vec<Vec<(usize, String)>>,   // @msr(n) で入力した小節と Phrase
     input_mode: InputMode,
     cluster_memory: String,
     raw_additional: String,
@@ -36,9 +39,18 @@ impl SeqDataStock {
             }
             pd.push(vari);
         }
+        let cvdt = (0..MAX_KBD_PART)
+            .map(|_| {
+                (0..MAX_VARIATION)
+                    .map(|_| CompositionDataStock::default())
+                    .collect()
+            })
+            .collect();
         Self {
             pdt: pd,
             cdt: Default::default(),
+            cvdt,
+            msr_raw: vec![Vec::new(); MAX_KBD_PART],
             input_mode: InputMode::Closer,
             cluster_memory: "".to_string(),
             raw_additional: "".to_string(),
@@ -55,9 +67,26 @@ impl SeqDataStock {
         };
         &self.pdt[part][num]
     }
+    pub fn get_tick_for_onemsr(&self) -> i32 {
+        self.tick_for_onemsr
+    }
+    pub fn get_bpm(&self) -> i16 {
+        self.bpm
+    }
+    /// (分子, 分母)
+    pub fn get_meter(&self) -> (i16, i16) {
+        (
+            (self.tick_for_onemsr / self.tick_for_beat) as i16,
+            (DEFAULT_TICK_FOR_QUARTER * 4 / self.tick_for_beat) as i16,
+        )
+    }
     pub fn get_cdstk(&self, part: usize) -> &CompositionDataStock {
         &self.cdt[part]
     }
+    /// vari: 1-9
+    pub fn get_cvdstk(&self, part: usize, vari: usize) -> &CompositionDataStock {
+        &self.cvdt[part][vari]
+    }
     pub fn set_cluster_memory(&mut self, word: String) {
         self.cluster_memory = word;
     }
@@ -78,6 +107,10 @@ impl SeqDataStock {
                 PhraseAs::Variation(v) => v,
                 PhraseAs::Measure(_m) => MAX_VARIATION,
             };
+            if let PhraseAs::Measure(msr) = vari {
+                self.msr_raw[part].retain(|(m, _)| *m != msr);
+                self.msr_raw[part].push((msr, input_text.clone()));
+            }
             if self.pdt[part][num].set_raw(input_text, &self.cluster_memory) {
                 self.pdt[part][num].set_recombined(
                     self.input_mode,
@@ -92,6 +125,7 @@ impl SeqDataStock {
     }
     pub fn del_raw_phrase(&mut self, part: usize) {
         if part < MAX_KBD_PART {
+            self.msr_raw[part].clear();
             for i in 0..(MAX_VARIATION + 1) {
                 if self.pdt[part][i].set_raw("[]".to_string(), &self.cluster_memory) {
                     self.pdt[part][i].set_recombined(
@@ -104,6 +138,26 @@ impl SeqDataStock {
             }
         }
     }
+    /// @msr(n) で入力した (小節, Phrase)
+    pub fn get_msr_raw(&self, part: usize) -> &[(usize, String)] {
+        &self.msr_raw[part]
+    }
+    /// @msr(n) の Phrase を変換し直し、Elapse に送る msg にする
+    pub fn gen_msr_phrase_msgs(&mut self) -> Vec<ElpsMsg> {
+        let mut msgs = Vec::new();
+        for part in 0..MAX_KBD_PART {
+            for (msr, raw) in self.msr_raw[part].clone() {
+                let vari = PhraseAs::Measure(msr);
+                if self.set_raw_phrase(part, vari.clone(), raw) == Some(false) {
+                    msgs.push(
+                        self.get_pdstk(part, vari.clone())
+                            .get_final(part as i16, vari),
+                    );
+                }
+            }
+        }
+        msgs
+    }
     pub fn set_raw_composition(&mut self, part: usize, input_text: String) -> bool {
         if part < MAX_COMPOSITION_PART && self.cdt[part].set_raw(input_text) {
             self.cdt[part].set_recombined(self.tick_for_onemsr, self.tick_for_beat);
@@ -111,6 +165,17 @@ impl SeqDataStock {
         }
         false
     }
+    /// Composition の Variation(vari:1-9) を設定する
+    pub fn set_raw_cmps_vari(&mut self, part: usize, vari: usize, input_text: String) -> bool {
+        if part < MAX_KBD_PART
+            && (1..MAX_VARIATION).contains(&vari)
+            && self.cvdt[part][vari].set_raw(input_text)
+        {
+            self.cvdt[part][vari].set_recombined(self.tick_for_onemsr, self.tick_for_beat);
+            return true;
+        }
+        false
+    }
     pub fn change_beat(&mut self, numerator: i16, denomirator: i16) {
         #[cfg(feature = "verbose")]
         println!("beat: {}/{}", numerator, denomirator);
@@ -165,6 +230,28 @@ impl SeqDataStock {
     pub fn change_input_mode(&mut self, input_mode: InputMode) {
         self.input_mode = input_mode;
     }
+    pub fn get_input_mode(&self) -> InputMode {
+        self.input_mode
+    }
+    /// beat_filter で加わる拍ごとの強弱を取り除く(Phrase に戻すと再び加わるため)
+    pub fn remove_beat_accent(&self, evts: &[PhrEvt]) -> Vec<PhrEvt> {
+        const FLAT_VEL: i16 = 64;
+        let flat: Vec<PhrEvt> = evts
+            .iter()
+            .map(|ev| PhrEvt {
+                vel: FLAT_VEL,
+                ..*ev
+            })
+            .collect();
+        let accent = beat_filter(&flat, self.bpm, self.tick_for_onemsr, self.tick_for_beat);
+        evts.iter()
+            .zip(accent.iter())
+            .map(|(ev, ac)| PhrEvt {
+                vel: ev.vel - (ac.vel - FLAT_VEL),
+                ..*ev
+            })
+            .collect()
+    }
     pub fn check_if_additional_phrase(&mut self, raw: String) -> Option<String> {
         let strlen = raw.len();
         if strlen >= 9 && raw.contains("].rpt(") && &raw[(strlen - 2)..] == ")+" {
@@ -231,6 +318,9 @@ impl SeqDataStock {
                 );
             }
             self.cdt[i].set_recombined(self.tick_for_onemsr, self.tick_for_beat);
+            for ecd in self.cvdt[i].iter_mut() {
+                ecd.set_recombined(self.tick_for_onemsr, self.tick_for_beat);
+            }
         }
     }
     fn default_base_note(part_num: usize) -> i32 {
@@ -241,7 +331,7 @@ impl SeqDataStock {
 //*******************************************************************
 //          Phrase Data Stock Struct
 //*******************************************************************
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PhraseDataStock {
     base_note: i32,
     raw: String,
@@ -249,8 +339,9 @@ pub struct PhraseDataStock {
     cmpl_ex: Vec<String>,
     phr: Vec<PhrEvt>,
     ana: Vec<AnaEvt>,
-    atrb: Vec<bool>,
+    atrb: Vec<i16>,
     do_loop: bool,
+    tie_loop: bool,
     whole_tick: i32,
 }
 impl PhraseDataStock {
@@ -262,8 +353,9 @@ impl PhraseDataStock {
             cmpl_ex: vec!["".to_string()],
             phr: Vec::new(),
             ana: Vec::new(),
-            atrb: vec![false, false],
+            atrb: vec![0, 0],
             do_loop: true,
+            tie_loop: false,
             whole_tick: 0,
         }
     }
@@ -274,6 +366,15 @@ impl PhraseDataStock {
     pub fn get_phr(&self) -> &Vec<PhrEvt> {
         &self.phr
     }
+    pub fn get_base_note(&self) -> i32 {
+        self.base_note
+    }
+    pub fn get_raw(&self) -> &str {
+        &self.raw
+    }
+    pub fn get_whole_tick(&self) -> i32 {
+        self.whole_tick
+    }
     pub fn get_final(&self, part: i16, vari: PhraseAs) -> ElpsMsg {
         let do_loop = vari == PhraseAs::Normal && self.do_loop;
         ElpsMsg::Phr(
@@ -284,16 +385,29 @@ impl PhraseDataStock {
                 evts: self.phr.clone(),
                 ana: self.ana.clone(),
                 vari,
-                auftakt: if self.atrb[0] { 1 } else { 0 },
+                auftakt: self.atrb[0],
+                tie_loop: do_loop && self.tie_loop,
             },
         )
     }
     pub fn set_raw(&mut self, input_text: String, cluster_word: &str) -> bool {
-        // 1.raw
-        self.raw = input_text.clone();
+        // 1.raw (移調や Pattern の書き方が間違っていれば受け付けない)
+        let cmpl = complement_phrase(input_text.clone(), cluster_word);
+        if get_entry_shift(&cmpl.1).is_none() {
+            println!("Phrase transpose is wrong!");
+            return false;
+        }
+        if !cmpl.0.iter().all(|nt| dp_is_valid(nt)) {
+            println!("Pattern is wrong!");
+            return false;
+        }
+        if !cmpl.0.iter().all(|nt| note_marks_are_valid(nt)) {
+            println!("Note mark is wrong!");
+            return false;
+        }
+        self.raw = input_text;
 
         // 2.complement data
-        let cmpl = complement_phrase(input_text, cluster_word);
         self.cmpl_nt = cmpl.0.clone();
         self.cmpl_ex = cmpl.1.clone();
         self.atrb = cmpl.2.clone();
@@ -321,7 +435,7 @@ impl PhraseDataStock {
         }
 
         // 3.recombined data
-        let (whole_tick, do_loop, rcmb) = recombine_to_internal_format(
+        let (whole_tick, do_loop, tie_loop, rcmb) = recombine_to_internal_format(
             &self.cmpl_nt,
             &self.cmpl_ex,
             input_mode,
@@ -330,6 +444,7 @@ impl PhraseDataStock {
         );
         self.phr = rcmb;
         self.do_loop = do_loop;
+        self.tie_loop = tie_loop;
         self.whole_tick = whole_tick;
 
         // 4.analysed data
@@ -352,7 +467,7 @@ impl PhraseDataStock {
 //*******************************************************************
 //          Composition Data Stock Struct
 //*******************************************************************
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CompositionDataStock {
     raw: String,
     cmpl_cd: Vec<String>,
@@ -372,7 +487,7 @@ impl Default for CompositionDataStock {
     }
 }
 impl CompositionDataStock {
-    pub fn get_final(&self, part: i16) -> ElpsMsg {
+    pub fn get_final(&self, part: i16, vari: usize) -> ElpsMsg {
         ElpsMsg::Cmp(
             part,
             ChordData {
@@ -380,9 +495,16 @@ impl CompositionDataStock {
                 do_loop: self.do_loop,
                 evts: self.chord.clone(),
                 measure: NOTHING,
+                vari,
             },
         )
     }
+    pub fn is_empty(&self) -> bool {
+        self.chord.is_empty()
+    }
+    pub fn get_raw(&self) -> &str {
+        &self.raw
+    }
     pub fn set_raw(&mut self, input_text: String) -> bool {
         // 1.raw
         self.raw = input_text.clone();
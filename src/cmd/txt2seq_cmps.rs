@@ -155,6 +155,27 @@ pub fn get_table_name(mut idx_num: usize) -> &'static str {
     assert!(idx_num < MAX_CHORD_TABLE);
     CHORD_TABLE[idx_num].name
 }
+/// 和音の table から種類を判定する。scale や para 用の table は None
+/// 短七度と長三度があれば Dominant、減五度があり完全五度が無ければ Diminished
+pub fn get_chord_quality(idx_num: usize) -> ChordQuality {
+    let name = get_table_name(idx_num);
+    let (table, _) = get_table(idx_num);
+    if !name.starts_with('_') || table.len() > 6 {
+        return ChordQuality::None;
+    }
+    let has = |nt: i16| table.contains(&nt);
+    if has(4) && has(10) {
+        ChordQuality::Dominant
+    } else if has(3) && has(6) && !has(7) {
+        ChordQuality::Diminished
+    } else if has(3) {
+        ChordQuality::Minor
+    } else if has(4) {
+        ChordQuality::Major
+    } else {
+        ChordQuality::None
+    }
+}
 pub fn get_table_num(kind: &str) -> i16 {
     let mut table: i16 = (MAX_CHORD_TABLE - 2) as i16;
 
@@ -296,8 +317,8 @@ pub fn recombine_to_chord_loop(
                     tbl: 0,
                 })
             }
-            if !msgs_sp[1][1..].is_empty() {
-                let rest = msgs_sp[1][1..].to_string();
+            let rest = msgs_sp[1].get(1..).unwrap_or("");
+            if !rest.is_empty() {
                 msgs = format!("{}{}", msgs_sp[0], rest);
             } else {
                 msgs = msgs_sp[0].to_string();
@@ -345,7 +366,9 @@ pub fn recombine_to_chord_loop(
 fn divide_chord_and_dur(mut chord: String) -> (String, i32) {
     let mut dur: i32 = 1;
     let mut ltr_count = chord.len();
-    assert!(ltr_count != 0);
+    if ltr_count == 0 {
+        return (chord, dur);
+    }
 
     let last_ltr = chord.chars().last().unwrap_or(' ');
     let mut msr_line: bool = false;
@@ -11,7 +11,10 @@ pub fn available_for_dp(text: &str) -> bool {
     !(!text.contains("C(")
         && !text.contains("Cls(")
         && !text.contains("A(")
-        && !text.contains("Arp("))
+        && !text.contains("Arp(")
+        && !text.contains("S(")
+        && !text.contains("Str(")
+        && !text.contains("E("))
 }
 /// Note のときの fn break_up_nt_dur_vel() と同様の処理
 pub fn treat_dp(
@@ -22,11 +25,16 @@ pub fn treat_dp(
     rest_tick: i32, // 小節内の残り tick
     exp_vel: i32,   // dynなどを反省した velocity
 ) -> (PhrEvt, i32) {
-    // Cluster or Arpeggio?
-    let mut case_arp = true;
-    if text.contains("C") {
-        case_arp = false;
-    }
+    // Cluster, Arpeggio, Strum or Euclid?
+    let mtype = if text.contains("E(") {
+        TYPE_EUCLID
+    } else if text.contains("S(") || text.contains("Str(") {
+        TYPE_STRUM
+    } else if text.contains("C") {
+        TYPE_CLS
+    } else {
+        TYPE_ARP
+    };
 
     // タイを探して追加する tick を算出
     let (tie_dur, bdur_tie, ntext2) = decide_tie_dur(text.clone());
@@ -40,11 +48,15 @@ pub fn treat_dp(
     let (ntext4, diff_vel) = gen_diff_vel(ntext3);
 
     let mut ev = PhrEvt::default();
-    let dp_pattern = gen_dp_pattern(&ntext4, case_arp);
+    let dp_pattern = gen_dp_pattern(&ntext4, mtype);
     ev.mtype = dp_pattern[0];
     ev.tick = crnt_tick as i16;
     ev.each_dur = dp_pattern[3];
-    ev.note = dp_pattern[1] + base_note as i16;
+    ev.note = if mtype == TYPE_STRUM {
+        dp_pattern[1] // ポジション(fret)なので octave は関係しない
+    } else {
+        dp_pattern[1] + base_note as i16
+    };
     ev.trns = dp_pattern[2];
     ev.vel = velo_limits(exp_vel + diff_vel, 1);
     ev.dur = duration as i16;
@@ -54,17 +66,23 @@ pub fn treat_dp(
     }
     (ev, bdur)
 }
-fn gen_dp_pattern(nt: &str, case_arp: bool) -> Vec<i16> {
+fn gen_dp_pattern(nt: &str, mtype: i16) -> Vec<i16> {
     let params = extract_texts_from_parentheses(nt);
     let param = split_by('@', params.to_string());
     let pnum = param.len();
-    let mut mtype = TYPE_CLS;
-    if case_arp {
-        mtype = TYPE_ARP;
+    if mtype == TYPE_EUCLID {
+        // 読めない E() は鳴らさない(入力時に dp_is_valid() で弾いている)
+        return gen_euclid_pattern(&param)
+            .unwrap_or_else(|| vec![TYPE_EUCLID, 0, 0, DEFAULT_TICK_FOR_QUARTER as i16]);
     }
+    let case_arp = mtype == TYPE_ARP;
 
     let mut note = 0;
-    let mut trns = 4;
+    let mut trns = if mtype == TYPE_STRUM {
+        strum_pattern("d")
+    } else {
+        4
+    };
     let mut each_dur = DEFAULT_TICK_FOR_QUARTER as i16;
     if pnum > 0 {
         each_dur = calc_dur(&param[0]);
@@ -72,19 +90,74 @@ fn gen_dp_pattern(nt: &str, case_arp: bool) -> Vec<i16> {
     if pnum > 1 {
         if case_arp {
             trns = arp_pattern(&param[1]);
+        } else if mtype == TYPE_STRUM {
+            trns = strum_pattern(&param[1]);
         } else {
             trns = param[1].parse::<i16>().unwrap_or(4);
         }
     }
     if pnum > 2 {
         note = param[2].parse::<i16>().unwrap_or(0);
-        if case_arp && ((trns % 2) == 1) {
+        if case_arp && ((trns & ARP_TYPE_MASK) % 2 == 1) {
             note += 12; // - note;
         }
+        if mtype == TYPE_STRUM {
+            note = note.clamp(0, STRUM_MAX_POSITION);
+        }
+    }
+    if pnum > 3 && case_arp {
+        // 一巡する音数
+        let vce = param[3].parse::<i16>().unwrap_or(0).clamp(0, ARP_MAX_VCE);
+        trns += vce << ARP_VCE_SHIFT;
     }
 
     vec![mtype, note, trns, each_dur]
 }
+/// Dynamic Pattern の引数が読めるか(今は E() のみ調べる)
+pub fn dp_is_valid(nt: &str) -> bool {
+    if !nt.contains("E(") {
+        return true;
+    }
+    let params = extract_texts_from_parentheses(nt);
+    gen_euclid_pattern(&split_by('@', params.to_string())).is_some()
+}
+/// E(k,n,r,src,a,low=f) : n step に k 個の打点、r step 回転、src の音、一 step の音価 a、最低音の位置 f
+/// k,n 以外は省略でき、音価は dur=e とも書ける。範囲外や読めない値があれば None
+/// 小文字の c は Phrase の中で展開されるので、src は R/C/A で指定する
+fn gen_euclid_pattern(param: &[String]) -> Option<Vec<i16>> {
+    let num = |i: usize| param.get(i).and_then(|p| p.parse::<i16>().ok());
+    let steps = num(1).filter(|n| (1..=EUC_MAX_STEP).contains(n))?;
+    let pulses = num(0).filter(|k| (0..=steps).contains(k))?;
+    let mut rot = 0;
+    let mut src = EUC_SRC_CLS;
+    let mut each_dur = DEFAULT_TICK_FOR_QUARTER as i16;
+    let mut low = 0;
+    for (i, p) in param.iter().enumerate().skip(2) {
+        if let Some(d) = p.strip_prefix("dur=") {
+            each_dur = euclid_dur(d)?;
+        } else if let Some(l) = p.strip_prefix("low=") {
+            low = l.parse::<i16>().ok().filter(|l| (-6..=7).contains(l))?;
+        } else if i == 2 && p.parse::<i16>().is_ok() {
+            rot = num(2).filter(|r| (0..steps).contains(r))?;
+        } else {
+            match p.as_str() {
+                "R" | "r" => src = EUC_SRC_ROOT,
+                "C" => src = EUC_SRC_CLS,
+                "A" | "a" => src = EUC_SRC_ARP,
+                _ => each_dur = euclid_dur(p)?,
+            }
+        }
+    }
+    let trns =
+        (steps - 1) | (pulses << EUC_PULSE_SHIFT) | (rot << EUC_ROT_SHIFT) | (src << EUC_SRC_SHIFT);
+    Some(vec![TYPE_EUCLID, low, trns, each_dur])
+}
+fn euclid_dur(durstr: &str) -> Option<i16> {
+    match durstr {
+        "h" | "q" | "e" | "v" | "h'" | "q'" | "e'" | "v'" => Some(calc_dur(durstr)),
+        _ => None,
+    }
+}
 fn calc_dur(durstr: &str) -> i16 {
     let mut dur = 480;
     let ch0 = durstr.chars().next().unwrap_or(' ');
@@ -111,10 +184,29 @@ fn calc_dur(durstr: &str) -> i16 {
 }
 fn arp_pattern(ptn: &str) -> i16 {
     match ptn {
-        "u" => 0,
-        "d" => 1,
-        "ux" => 2,
-        "ud" => 3,
-        _ => 0,
+        "u" | "ux" => ARP_UP,
+        "d" | "dx" => ARP_DOWN,
+        "ud" => ARP_UPDOWN,
+        "du" => ARP_DOWNUP,
+        "r" => ARP_RANDOM,
+        _ => ARP_UP,
+    }
+}
+/// d:down, u:up, -:休み を一文字 2bit にして、最大 STRUM_MAX_STEP 文字まで詰める
+fn strum_pattern(ptn: &str) -> i16 {
+    let mut code = 0;
+    for (i, c) in ptn.chars().take(STRUM_MAX_STEP).enumerate() {
+        let step = match c {
+            'd' => STRUM_DOWN,
+            'u' => STRUM_UP,
+            '-' | 'x' => STRUM_REST,
+            _ => break,
+        };
+        code |= step << (i * 2);
+    }
+    if code == 0 {
+        STRUM_DOWN
+    } else {
+        code
     }
 }
@@ -13,7 +13,7 @@ use crate::lpnlib::*;
 pub fn complement_phrase(
     input_text: String,
     cluster_word: &str,
-) -> (Vec<String>, Vec<String>, Vec<bool>) {
+) -> (Vec<String>, Vec<String>, Vec<i16>) {
     // 1. space 削除
     let phr = input_text.trim().to_string();
 
@@ -27,8 +27,13 @@ pub fn complement_phrase(
     nev.retain(|nt| !nt.is_empty());
     let (nmvec, nevec) = divide_notemod_and_musicex(nev);
 
-    // 4. <> の検出と、囲まれた要素へのコマンド追加と cluster の展開
-    let nttmp = divide_arrow_bracket(nt);
+    // 4. <> の検出と、囲まれた要素へのコマンド追加、連符の展開と cluster の展開
+    //    @first/@last/@every(n) は !first などと同じ
+    let nt = nt
+        .replace("@first", "!first")
+        .replace("@last", "!last")
+        .replace("@every(", "!every(");
+    let nttmp = expand_tuplet(divide_arrow_bracket(nt));
     let nt2 = nttmp.replace('c', cluster_word);
 
     // 5. ,| 重複による休符指示の補填、()内の ',' を '_' に変換。音符のVector化
@@ -46,7 +51,7 @@ pub fn complement_phrase(
 
     // 7. 音符変調関数の適用
     for ne in nmvec.iter() {
-        if &ne[0..3] == "rpt" {
+        if ne.starts_with("rpt") {
             ntvec = repeat_ntimes(ntvec, ne);
         }
     }
@@ -59,8 +64,8 @@ fn divide_brackets(input_text: String) -> (String, String) {
 
     // 中身と、その後の文字列を ninfo/minfo に入れる
     let mut isx: &str = &input_text;
-    if let Some(n2) = isx.find(']') {
-        ninfo = isx[1..n2].to_string();
+    if let Some(n2) = closing_bracket(isx) {
+        ninfo = isx.get(1..n2).unwrap_or("").to_string();
         isx = &isx[n2 + 1..];
         if !isx.is_empty() {
             minfo = isx.to_string();
@@ -68,6 +73,22 @@ fn divide_brackets(input_text: String) -> (String, String) {
     }
     (ninfo, minfo)
 }
+/// 先頭の [ に対応する ] の位置を返す
+/// 中に連符の [ が無ければ、従来通り最初の ] になる
+fn closing_bracket(isx: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, ltr) in isx.char_indices() {
+        if ltr == '[' {
+            depth += 1;
+        } else if ltr == ']' {
+            depth -= 1;
+            if depth <= 0 {
+                return Some(i);
+            }
+        }
+    }
+    isx.find(']')
+}
 fn divide_arrow_bracket(nt: String) -> String {
     let mut ret_str: String = "".to_string();
     let mut i = 0;
@@ -78,6 +99,24 @@ fn divide_arrow_bracket(nt: String) -> String {
                 let end_arrow = i + 1 + loc;
                 let mut omit = false;
                 let mut mark = nt.chars().nth(end_arrow + 1).unwrap_or('~');
+                if mark == '!' {
+                    // <d,r,m>!2 : ! 以降を、囲まれた全ての音符に付ける
+                    let suffix: String = nt
+                        .chars()
+                        .skip(end_arrow + 1)
+                        .take_while(|c| *c != ',' && *c != '|' && *c != '/')
+                        .collect();
+                    for j in i + 1..end_arrow {
+                        let nx = nt.chars().nth(j).unwrap_or(' ');
+                        if nx == ',' || nx == '|' || nx == '/' {
+                            ret_str += &suffix;
+                        }
+                        ret_str.push(nx);
+                    }
+                    ret_str += &suffix;
+                    i = end_arrow + suffix.chars().count() + 1;
+                    continue;
+                }
                 let mut comma = ',';
                 if mark == ',' || mark == '/' || mark == '|' {
                     comma = mark;
@@ -113,11 +152,31 @@ fn divide_arrow_bracket(nt: String) -> String {
     //println!("$$$Divided letter in <>: {}", ret_str);
     ret_str
 }
-fn div_atrb(mut ntdiv: Vec<String>) -> (String, Vec<bool>) {
+/// 3[d,r,m] のような連符を、$TPL3,d,r,m,$END に展開する
+fn expand_tuplet(nt: String) -> String {
+    let mut ret_str: String = "".to_string();
+    let mut note_top = 0; // ret_str 内の、今の音符の先頭位置
+    for ltr in nt.chars() {
+        if ltr == ',' || ltr == '|' || ltr == '/' {
+            ret_str.push(ltr);
+            note_top = ret_str.len();
+        } else if ltr == '[' {
+            ret_str.insert_str(note_top, "$TPL");
+            ret_str.push(',');
+            note_top = ret_str.len();
+        } else if ltr == ']' {
+            ret_str += ",$END";
+        } else {
+            ret_str.push(ltr);
+        }
+    }
+    ret_str
+}
+fn div_atrb(mut ntdiv: Vec<String>) -> (String, Vec<i16>) {
     let dnum = ntdiv.len();
     let mut nt = "".to_string();
     let mut ntatrb = vec!["".to_string()];
-    let mut atrb = vec![false, false];
+    let mut atrb = vec![0, 0]; // 0: auftakt の小節数, 1: RT
     if dnum >= 2 {
         nt = ntdiv.pop().unwrap_or("".to_string());
         ntatrb = ntdiv;
@@ -129,10 +188,18 @@ fn div_atrb(mut ntdiv: Vec<String>) -> (String, Vec<bool>) {
     for a in ntatrb.iter() {
         if a.contains('A') {
             let beat = a.chars().nth(1).unwrap_or('0').to_digit(10).unwrap_or(0);
+            // A3.2 : 2小節前の3拍目から始まる弱起
+            let msrs = a
+                .chars()
+                .nth(3)
+                .filter(|_| a.chars().nth(2) == Some('.'))
+                .and_then(|c| c.to_digit(10))
+                .unwrap_or(1)
+                .max(1);
             #[cfg(feature = "verbose")]
-            println!("Auftakt Start Beat: {}", beat);
+            println!("Auftakt Start Beat: {}, Measures: {}", beat, msrs);
             if beat > 0 {
-                atrb[0] = true;
+                atrb[0] = msrs as i16;
                 if beat > 1 {
                     let mut rest = String::from("qx");
                     for _ in 0..beat - 2 {
@@ -142,7 +209,7 @@ fn div_atrb(mut ntdiv: Vec<String>) -> (String, Vec<bool>) {
                 }
             }
         } else if a == "RT" {
-            atrb[1] = true;
+            atrb[1] = 1;
         }
     }
 
@@ -200,7 +267,7 @@ fn divide_notemod_and_musicex(nev: Vec<String>) -> (Vec<String>, Vec<String>) {
     let mut ne: Vec<String> = Vec::new();
 
     for nx in nev.iter() {
-        if nx.len() >= 3 && &nx[0..3] == "rpt" {
+        if nx.starts_with("rpt") {
             nm.push(nx.to_string());
         } else {
             ne.push(nx.to_string());
@@ -217,7 +284,7 @@ fn note_repeat(nv: Vec<String>) -> (Vec<String>, bool) {
     for (i, one) in nv.iter().enumerate() {
         if one.contains("*") {
             no_exist = false;
-            for (j, ltr) in one.chars().enumerate() {
+            for (j, ltr) in one.char_indices() {
                 if ltr == '*' {
                     new_vec[i] = one[..j].to_string();
                     let number: i32 = one[j + 1..].parse().unwrap_or(0);
@@ -261,6 +328,9 @@ struct AddNoteParam {
     vel: i16,
     trns: i16,
     artic: i16,
+    orna: i16,
+    ch: i16,
+    cond: i16,
 }
 impl Default for AddNoteParam {
     fn default() -> Self {
@@ -270,8 +340,54 @@ impl Default for AddNoteParam {
             vel: 0,
             trns: 0,
             artic: DEFAULT_ARTIC,
+            orna: ORNA_NONE,
+            ch: 0,
+            cond: COND_ALWAYS,
+        }
+    }
+}
+/// 装飾音符(時間を持たず、次の音符の前に追加される)
+const GRACE_ON_BEAT: i16 = 10; // !g : 次の音符の時間を使う
+const GRACE_BEFORE_BEAT: i16 = 11; // !G : 前の音符の時間を使う
+const GRACE_TICK: i32 = DEFAULT_TICK_FOR_QUARTER / 8;
+struct GraceNote {
+    notes: Vec<u8>,
+    vel: i16,
+    trns: i16,
+    ch: i16,
+    cond: i16,
+    before: bool,
+}
+/// 連符の情報
+struct Tuplet {
+    start: i32, // 連符の開始 tick
+    total: i32, // 連符全体の tick 数
+    num: i32,   // 連符の音符数
+    idx: i32,   // 次の音符の番号
+}
+impl Tuplet {
+    /// $TPL の後ろの文字列から生成する。入れ子の場合は、外側の連符の一つ分の長さになる
+    fn new(txt: &str, base_dur: i32, crnt_tick: i32, outer: Option<&Tuplet>) -> Self {
+        let num_len = txt.chars().take_while(|c| c.is_ascii_digit()).count();
+        let num = txt[0..num_len].parse::<i32>().unwrap_or(1).max(1);
+        let total = if let Some(otpl) = outer {
+            otpl.next_dur(crnt_tick)
+        } else if num_len < txt.len() {
+            decide_dur(txt[num_len..].to_string(), base_dur).1
+        } else {
+            base_dur
+        };
+        Self {
+            start: crnt_tick,
+            total,
+            num,
+            idx: 0,
         }
     }
+    /// 次の音符の tick 数(端数は全体の長さが変わらないように振り分ける)
+    fn next_dur(&self, crnt_tick: i32) -> i32 {
+        self.start + self.total * (self.idx + 1) / self.num - crnt_tick
+    }
 }
 //*******************************************************************
 pub fn recombine_to_internal_format(
@@ -280,7 +396,7 @@ pub fn recombine_to_internal_format(
     imd: InputMode,
     base_note: i32,
     tick_for_onemsr: i32,
-) -> (i32, bool, Vec<PhrEvt>) {
+) -> (i32, bool, bool, Vec<PhrEvt>) {
     let (exp_vel, _exp_others) = get_dyn_info(expvec.to_vec());
     let mut read_ptr = 0;
     let mut last_nt: i32 = 0;
@@ -291,6 +407,9 @@ pub fn recombine_to_internal_format(
     let mut mes_top: bool = false;
     let (max_read_ptr, do_loop) = judge_no_loop(ntvec);
     let mut whole_msr_tick = tick_for_onemsr;
+    let mut tie_from: Option<Vec<u8>> = None; // & で次の音にタイで繋ぐ音
+    let mut tuplet: Vec<Tuplet> = Vec::new(); // 入れ子の連符
+    let mut grace: Vec<GraceNote> = Vec::new(); // 次の音符の前に追加する装飾音符
 
     while read_ptr < max_read_ptr {
         let nt_origin = ntvec[read_ptr].clone();
@@ -306,8 +425,24 @@ pub fn recombine_to_internal_format(
 
         // イベント抽出
         let (note_text, trns) = extract_trans_info(nt_origin);
+        let (note_text, tie_next) = extract_tie_mark(note_text);
+        let (note_text, cond) = extract_condition(note_text);
+        let (note_text, orna, ch) = extract_ornament(note_text);
+        let mut next_tie = None;
         let rest_tick = whole_msr_tick - crnt_tick;
-        if note_text == "$RPT" {
+        if let Some(tpl) = note_text.strip_prefix("$TPL") {
+            // 連符の開始
+            let tp = Tuplet::new(tpl, base_dur, crnt_tick, tuplet.last());
+            tuplet.push(tp);
+        } else if note_text == "$END" {
+            // 連符の終了: 外側の連符の次の音符に進む
+            if let Some(tp) = tuplet.pop() {
+                crnt_tick = tp.start + tp.total;
+                if let Some(otpl) = tuplet.last_mut() {
+                    otpl.idx += 1;
+                }
+            }
+        } else if note_text == "$RPT" {
             // complement時に入れた、繰り返しを表す特殊マーク$
             let nt_data = PhrEvt::gen_repeat(crnt_tick as i16);
             rcmb.push(nt_data);
@@ -325,31 +460,76 @@ pub fn recombine_to_internal_format(
             base_dur = bdur;
             if crnt_tick < whole_msr_tick {
                 crnt_tick += ca_ev.dur as i32;
-                rcmb.push(ca_ev);
+                rcmb.push(PhrEvt { cond, ..ca_ev });
             }
         } else {
             // Note 処理
-            let (notes, note_dur, diff_vel, bdur, lnt, artic) =
+            let (notes, mut note_dur, diff_vel, bdur, lnt, artic) =
                 break_up_nt_dur_vel(note_text, base_note, base_dur, last_nt, rest_tick, imd);
             last_nt = lnt; // 次回の音程の上下判断のため
-            base_dur = bdur;
-            if crnt_tick < whole_msr_tick {
-                // add to recombined data (NO_NOTE 含む(タイの時に使用))
-                let prm = AddNoteParam {
-                    mes_top,
-                    dur: get_note_dur(note_dur, whole_msr_tick, crnt_tick),
+            if orna == GRACE_ON_BEAT || orna == GRACE_BEFORE_BEAT {
+                // 装飾音符は、次の音符と一緒に追加する
+                grace.push(GraceNote {
+                    notes,
                     vel: velo_limits(exp_vel + diff_vel, 1),
                     trns,
-                    artic,
-                };
-                rcmb = add_note(rcmb, crnt_tick, notes, prm);
-                crnt_tick += note_dur;
+                    ch,
+                    cond,
+                    before: orna == GRACE_BEFORE_BEAT,
+                });
+                next_tie = tie_from.take();
+            } else {
+                if let Some(tp) = tuplet.last_mut() {
+                    // 連符の中では、音価の指定によらず等分する
+                    note_dur = tp.next_dur(crnt_tick);
+                    tp.idx += 1;
+                } else {
+                    base_dur = bdur;
+                }
+                if crnt_tick < whole_msr_tick {
+                    // add to recombined data (NO_NOTE 含む(タイの時に使用))
+                    let mut prm = AddNoteParam {
+                        mes_top,
+                        dur: get_note_dur(note_dur, whole_msr_tick, crnt_tick),
+                        vel: velo_limits(exp_vel + diff_vel, 1),
+                        trns,
+                        artic,
+                        orna,
+                        ch,
+                        cond,
+                    };
+                    if tie_from.as_ref() == Some(&notes) {
+                        // 前の音と同じ音なら、発音せず前の音の音価を増やす
+                        prm.mes_top = true;
+                        rcmb = add_note(rcmb, crnt_tick, vec![NO_NOTE], prm);
+                    } else {
+                        let mut note_tick = crnt_tick;
+                        if !grace.is_empty() {
+                            (rcmb, note_tick, prm.dur) =
+                                add_grace(rcmb, &grace, crnt_tick, prm.dur);
+                        }
+                        rcmb = add_note(rcmb, note_tick, notes.clone(), prm);
+                    }
+                    crnt_tick += note_dur;
+                }
+                grace.clear();
+                next_tie = if tie_next { Some(notes) } else { None };
             }
         }
+        tie_from = next_tie;
         mes_top = false;
         read_ptr += 1; // out from repeat
     }
-    (crnt_tick, do_loop, rcmb)
+    // [...]^+5, [...]^toKeyD : 入力時に Phrase 全体を移調する
+    let shift = get_entry_shift(expvec).unwrap_or(0);
+    if shift != 0 {
+        rcmb.iter_mut()
+            .filter(|ev| ev.mtype == TYPE_NOTE && ev.note != NO_NOTE as i16)
+            .for_each(|ev| ev.note += shift);
+    }
+    // 最後の音に & があれば、次の Loop の最初の音にタイで繋ぐ
+    let tie_loop = do_loop && tie_from.is_some();
+    (crnt_tick, do_loop, tie_loop, rcmb)
 }
 fn judge_no_loop(ntvec: &[String]) -> (usize, bool) {
     let mut max_read_ptr = ntvec.len();
@@ -364,7 +544,7 @@ fn get_dyn_info(expvec: Vec<String>) -> (i32, Vec<String>) {
     let mut vel = END_OF_DATA;
     let mut retvec = expvec.clone();
     for (i, txt) in expvec.iter().enumerate() {
-        if txt.len() >= 3 && &txt[0..3] == "dyn" {
+        if txt.starts_with("dyn") {
             let dyntxt = extract_texts_from_parentheses(txt);
             vel = convert_exp2vel(dyntxt);
             if vel != END_OF_DATA {
@@ -378,15 +558,167 @@ fn get_dyn_info(expvec: Vec<String>) -> (i32, Vec<String>) {
     }
     (vel, retvec)
 }
+/// ^+5 / ^-3 : 半音単位、^toKeyD : C で書いた Phrase を D に(近い方へ)移調する
+/// 書き方が間違っていれば None
+pub fn get_entry_shift(expvec: &[String]) -> Option<i16> {
+    let prm = match expvec.iter().find_map(|txt| txt.strip_prefix('^')) {
+        Some(p) => p,
+        None => return Some(0),
+    };
+    if let Some(key) = prm.strip_prefix("toKey") {
+        let mut ltrs = key.chars();
+        let mut shift: i16 = match ltrs.next() {
+            Some('C') => 0,
+            Some('D') => 2,
+            Some('E') => 4,
+            Some('F') => 5,
+            Some('G') => 7,
+            Some('A') => 9,
+            Some('B') => 11,
+            _ => return None,
+        };
+        match ltrs.next() {
+            Some('#') => shift += 1,
+            Some('b') => shift -= 1,
+            None => (),
+            _ => return None,
+        }
+        if ltrs.next().is_some() {
+            return None;
+        }
+        shift = shift.rem_euclid(12);
+        Some(if shift > 6 { shift - 12 } else { shift })
+    } else {
+        // 符号は一つだけ(^+-3 などは間違い)
+        let num = prm
+            .strip_prefix('+')
+            .filter(|n| !n.starts_with(['+', '-']))
+            .unwrap_or(prm);
+        num.parse::<i16>().ok().map(|n| n.clamp(-24, 24))
+    }
+}
 fn extract_trans_info(origin: String) -> (String, i16) {
-    if origin.len() > 2 && &origin[0..2] == ">>" {
+    if origin.len() > 2 && origin.starts_with(">>") {
         (origin[2..].to_string(), TRNS_NONE)
-    } else if &origin[0..1] == ">" {
-        (origin[1..].to_string(), TRNS_PARA)
+    } else if let Some(rest) = origin.strip_prefix('>') {
+        (rest.to_string(), TRNS_PARA)
     } else {
         (origin, TRNS_COM)
     }
 }
+/// ! 以降の装飾記号と MIDI ch 指定を抽出 (例: d!tr!2)
+fn extract_ornament(note_text: String) -> (String, i16, i16) {
+    let mut marks = note_text.split('!');
+    let nt = marks.next().unwrap_or("").to_string();
+    let mut orna = ORNA_NONE;
+    let mut ch = 0;
+    for mark in marks {
+        match note_mark(mark) {
+            Some(NoteMark::Orna(o)) => orna = o,
+            Some(NoteMark::Ch(num)) => ch = num,
+            None => (),
+        }
+    }
+    (nt, orna, ch)
+}
+/// 音符の ! の後に書く記号
+enum NoteMark {
+    Orna(i16),
+    Ch(i16),
+}
+fn note_mark(mark: &str) -> Option<NoteMark> {
+    match mark {
+        "tr" => Some(NoteMark::Orna(ORNA_TRILL)),
+        "mr" => Some(NoteMark::Orna(ORNA_MORDENT)),
+        "tn" => Some(NoteMark::Orna(ORNA_TURN)),
+        "g" => Some(NoteMark::Orna(GRACE_ON_BEAT)),
+        "G" => Some(NoteMark::Orna(GRACE_BEFORE_BEAT)),
+        // 0 は Part の ch
+        _ => mark
+            .parse::<i16>()
+            .ok()
+            .filter(|num| (0..=16).contains(num))
+            .map(NoteMark::Ch),
+    }
+}
+/// 音符に付いた ! の記号が、全て知っているものか
+pub fn note_marks_are_valid(note_text: &str) -> bool {
+    let (nt, _) = extract_trans_info(note_text.to_string());
+    let (nt, _) = extract_tie_mark(nt);
+    let (nt, _) = extract_condition(nt);
+    nt.split('!').skip(1).all(|mark| note_mark(mark).is_some())
+}
+/// !first/!last/!every(n) を抽出(Loop の何回目に鳴らすか)
+fn extract_condition(note_text: String) -> (String, i16) {
+    let mut marks = note_text.split('!');
+    let mut nt = marks.next().unwrap_or("").to_string();
+    let mut cond = COND_ALWAYS;
+    for mark in marks {
+        let every = mark
+            .strip_prefix("every(")
+            .and_then(|m| m.strip_suffix(')'))
+            .and_then(|m| m.parse::<i16>().ok());
+        match (mark, every) {
+            ("first", _) => cond = COND_FIRST,
+            ("last", _) => cond = COND_LAST,
+            (_, Some(num)) if num > 0 => cond = num,
+            _ => {
+                nt.push('!');
+                nt.push_str(mark);
+            }
+        }
+    }
+    (nt, cond)
+}
+/// 装飾音符を追加し、本来の音符の tick と音価を返す
+fn add_grace(
+    rcmb: Vec<PhrEvt>,
+    grace: &[GraceNote],
+    tick: i32,
+    dur: i32,
+) -> (Vec<PhrEvt>, i32, i32) {
+    let mut return_rcmb = rcmb;
+    let total = GRACE_TICK * grace.len() as i32;
+    // 前の音符の時間を使う場合でも、Phrase の冒頭では次の音符の時間を使う
+    let before = grace[0].before && tick >= total;
+    let (mut gtick, note_tick, note_dur) = if before {
+        (tick - total, tick, dur)
+    } else {
+        (tick, tick + total, (dur - total).max(1))
+    };
+    if before {
+        // 前の音符の音価を短くする
+        for ev in return_rcmb.iter_mut() {
+            let evtick = ev.tick as i32;
+            if ev.mtype == TYPE_NOTE && evtick < gtick && evtick + ev.dur as i32 > gtick {
+                ev.dur = (gtick - evtick) as i16;
+            }
+        }
+    }
+    for g in grace.iter() {
+        let prm = AddNoteParam {
+            dur: GRACE_TICK,
+            vel: g.vel,
+            trns: g.trns,
+            ch: g.ch,
+            cond: g.cond,
+            ..Default::default()
+        };
+        return_rcmb = add_note(return_rcmb, gtick, g.notes.clone(), prm);
+        gtick += GRACE_TICK;
+    }
+    return_rcmb.sort_by_key(|x| x.tick);
+    (return_rcmb, note_tick, note_dur)
+}
+/// 最後の & を抽出
+fn extract_tie_mark(mut note_text: String) -> (String, bool) {
+    if note_text.len() > 1 && note_text.ends_with('&') {
+        note_text.pop();
+        (note_text, true)
+    } else {
+        (note_text, false)
+    }
+}
 /// カンマで区切られた単位の文字列を解析し、ノート番号、tick、velocity を確定する
 fn break_up_nt_dur_vel(
     note_text: String, // 分析対象のテキスト
@@ -621,7 +953,7 @@ pub fn decide_dur(ntext: String, mut base_dur: i32) -> (String, i32) {
         base_dur = (base_dur * 2) / triplet as i32;
         idx = 2;
     }
-    let nt = ntext[idx..].to_string();
+    let nt = ntext.get(idx..).unwrap_or("").to_string();
     (nt, base_dur)
 }
 pub fn gen_diff_vel(nt: String) -> (String, i32) {
@@ -690,6 +1022,9 @@ fn add_note(rcmb: Vec<PhrEvt>, tick: i32, notes: Vec<u8>, prm: AddNoteParam) ->
                 vel: prm.vel,
                 trns: prm.trns,
                 artic: prm.artic,
+                orna: prm.orna,
+                ch: prm.ch,
+                cond: prm.cond,
                 ..Default::default()
             };
             return_rcmb.push(nt_data);
@@ -717,7 +1052,7 @@ fn convert_doremi_upper_closer(doremi: String, last_nt: i32) -> i32 {
 
     let mut oct_pitch = 0;
     let mut pure_doremi = String::from("");
-    for (i, ltr) in doremi.chars().enumerate() {
+    for (i, ltr) in doremi.char_indices() {
         if ltr == 'x' {
             return REST as i32;
         } else if ltr == '+' {
@@ -743,7 +1078,7 @@ fn convert_doremi_closer(doremi: String, last_nt: i32) -> i32 {
 
     let mut oct_pitch = 0;
     let mut pure_doremi = String::from("");
-    for (i, ltr) in doremi.chars().enumerate() {
+    for (i, ltr) in doremi.char_indices() {
         if ltr == 'x' {
             return REST as i32;
         } else if ltr == '+' {
@@ -772,7 +1107,7 @@ fn convert_doremi_fixed(doremi: String) -> i32 {
     }
     let mut base_note: i32 = 0;
     let mut pure_doremi = String::from("");
-    for (i, ltr) in doremi.chars().enumerate() {
+    for (i, ltr) in doremi.char_indices() {
         if ltr == 'x' {
             return REST as i32;
         } else if ltr == '+' {
@@ -830,3 +1165,171 @@ pub fn split_notes(txt: String) -> Vec<String> {
     set_vec(txt.len());
     splitted
 }
+
+//*******************************************************************
+//          gen_phrase_text (for punch/freeze)
+//*******************************************************************
+/// 実際に鳴った音(tick は Loop の頭から、note は key を含まない)を Phrase の文字列にする
+/// tick は三十二分音符に揃え、和音で変換されないように >> を付ける
+pub fn gen_phrase_text(
+    evts: &[PhrEvt],
+    base_note: i32,
+    imd: InputMode,
+    tick_for_onemsr: i32,
+    whole_tick: i32,
+) -> String {
+    const GRID: i32 = 60; // 三十二分音符
+    let quantize = |tick: i32| ((tick + GRID / 2) / GRID) * GRID;
+    let mut notes: Vec<&PhrEvt> = evts.iter().filter(|x| x.mtype == TYPE_NOTE).collect();
+    notes.sort_by_key(|x| (x.tick, x.note));
+
+    // 同じ tick の音をまとめる (tick, notes, dur, vel)
+    let mut groups: Vec<(i32, Vec<i32>, i32, i16)> = Vec::new();
+    for ev in notes.iter() {
+        let tick = quantize(ev.tick as i32);
+        if tick >= whole_tick {
+            continue;
+        }
+        let note = ev.note as i32 - base_note;
+        let dur = quantize(ev.dur as i32).max(GRID);
+        match groups.last_mut() {
+            Some(g) if g.0 == tick => {
+                if !g.1.contains(&note) {
+                    g.1.push(note);
+                }
+                g.2 = g.2.max(dur);
+                g.3 = g.3.max(ev.vel);
+            }
+            _ => groups.push((tick, vec![note], dur, ev.vel)),
+        }
+    }
+    if groups.is_empty() {
+        return "[]".to_string();
+    }
+
+    // 全体の音量は中央の velocity に近い dyn() とし、差を ^ % で表す
+    let mut vels: Vec<i16> = groups.iter().map(|g| g.3).collect();
+    vels.sort();
+    let center = vels[vels.len() / 2] as i32;
+    let dyn_txt = ["ff", "f", "mf", "mp", "p", "pp", "ppp"]
+        .into_iter()
+        .min_by_key(|x| (convert_exp2vel(x) - center).abs())
+        .unwrap_or("p");
+    let dyn_vel = convert_exp2vel(dyn_txt);
+
+    let mut msrs: Vec<Vec<String>> = vec![Vec::new()];
+    let mut crnt = 0;
+    let mut last_nt = 0;
+    for (i, (tick, nts, dur, vel)) in groups.iter().enumerate() {
+        if *tick > crnt {
+            push_span(&mut msrs, crnt, *tick - crnt, tick_for_onemsr, None);
+        }
+        let next = groups.get(i + 1).map_or(whole_tick, |g| g.0);
+        let len = next - tick;
+        // 休符を挟むほど短い音は、残りを休符にする
+        let note_len = if len - dur >= GRID * 2 { *dur } else { len };
+        let diff = *vel as i32 - dyn_vel;
+        let vel_txt = if diff >= VEL_UP {
+            "^".repeat(((diff + VEL_UP / 2) / VEL_UP) as usize)
+        } else if diff <= VEL_DOWN {
+            "%".repeat(((diff + VEL_DOWN / 2) / VEL_DOWN) as usize)
+        } else {
+            "".to_string()
+        };
+        let mut sorted = nts.clone();
+        sorted.sort();
+        let (nt_txt, lnt) = doremi_text(&sorted, last_nt, imd);
+        last_nt = lnt;
+        push_span(
+            &mut msrs,
+            *tick,
+            note_len,
+            tick_for_onemsr,
+            Some((nt_txt, vel_txt, &sorted, imd)),
+        );
+        crnt = tick + note_len;
+    }
+    if whole_tick > crnt {
+        push_span(&mut msrs, crnt, whole_tick - crnt, tick_for_onemsr, None);
+    }
+    let body: Vec<String> = msrs.iter().map(|m| m.join(",")).collect();
+    if dyn_txt == "p" {
+        format!("[{}]", body.join("/"))
+    } else {
+        format!("[{}].dyn({})", body.join("/"), dyn_txt)
+    }
+}
+/// [start, start+len) の音符/休符を、小節線で分けて msrs に加える
+/// 小節を跨ぐ音は & で次の小節の同じ音に繋ぐ
+fn push_span(
+    msrs: &mut Vec<Vec<String>>,
+    start: i32,
+    len: i32,
+    tick_for_onemsr: i32,
+    note: Option<(String, String, &Vec<i32>, InputMode)>,
+) {
+    let mut tick = start;
+    let end = start + len;
+    let mut first = true;
+    while tick < end {
+        let msr = (tick / tick_for_onemsr) as usize;
+        while msrs.len() <= msr {
+            msrs.push(Vec::new());
+        }
+        let piece = end.min((msr as i32 + 1) * tick_for_onemsr) - tick;
+        let (unit, ltr) = [(960, 'h'), (480, 'q'), (240, 'e'), (120, 'v'), (60, 'w')]
+            .into_iter()
+            .find(|(u, _)| piece % u == 0)
+            .unwrap_or((60, 'w'));
+        let dots = ".".repeat((piece / unit - 1) as usize);
+        let txt = match &note {
+            None => format!("{}x{}", ltr, dots),
+            Some((nt_txt, vel_txt, nts, imd)) => {
+                // 繋いだ先の音は、前の音と同じ高さで書く
+                let nt_txt = if first {
+                    nt_txt.clone()
+                } else {
+                    doremi_text(nts, *nts.last().unwrap_or(&0), *imd).0
+                };
+                let (oct, pure) = nt_txt.split_at(
+                    nt_txt
+                        .find(|c: char| c != '+' && c != '-')
+                        .unwrap_or(nt_txt.len()),
+                );
+                let tie = if tick + piece < end { "&" } else { "" };
+                format!(">>{}{}{}{}{}{}", oct, ltr, pure, vel_txt, dots, tie)
+            }
+        };
+        msrs[msr].push(txt);
+        tick += piece;
+        first = false;
+    }
+}
+/// 音高(base_note からの差)を、入力モードに合わせた階名(+,- 付き)にする
+fn doremi_text(nts: &[i32], mut last_nt: i32, imd: InputMode) -> (String, i32) {
+    const DOREMI: [&str; 12] = [
+        "d", "di", "r", "ri", "m", "f", "fi", "s", "si", "l", "li", "t",
+    ];
+    const PREFIX: [&str; 9] = ["", "+", "-", "++", "--", "+++", "---", "++++", "----"];
+    let mut txt = String::new();
+    for (i, nt) in nts.iter().enumerate() {
+        let name = DOREMI[nt.rem_euclid(12) as usize];
+        let found = PREFIX.iter().find(|pf| {
+            let cand = format!("{}{}", pf, name);
+            let conv = if imd == InputMode::Fixed {
+                convert_doremi_fixed(cand)
+            } else if i == 0 {
+                convert_doremi_closer(cand, last_nt)
+            } else if pf.contains('-') {
+                return false; // 和音の二音目以降は上にしか積めない
+            } else {
+                convert_doremi_upper_closer(cand, last_nt)
+            };
+            conv == *nt
+        });
+        txt += found.unwrap_or(&"");
+        txt += name;
+        last_nt = *nt;
+    }
+    (txt, last_nt)
+}
@@ -26,7 +26,7 @@ pub fn convert_exp2vel(vel_text: &str) -> i32 {
 pub fn split_by(splitter: char, txt: String) -> Vec<String> {
     let mut splited: Vec<String> = Vec::new();
     let mut old_locate: usize = 0;
-    for (i, ltr) in txt.chars().enumerate() {
+    for (i, ltr) in txt.char_indices() {
         if ltr == splitter {
             splited.push(txt[old_locate..i].to_string());
             old_locate = i + 1;
@@ -35,11 +35,17 @@ pub fn split_by(splitter: char, txt: String) -> Vec<String> {
     splited.push(txt[old_locate..txt.len()].to_string());
     splited
 }
-#[allow(dead_code)]
+/// space と . のどちらでも区切り、空の語は除く
+pub fn split_words(txt: &str) -> Vec<String> {
+    split_by_by(' ', '.', txt.to_string())
+        .into_iter()
+        .filter(|x| !x.is_empty())
+        .collect()
+}
 pub fn split_by_by(sp1: char, sp2: char, txt: String) -> Vec<String> {
     let mut splited: Vec<String> = Vec::new();
     let mut old_locate: usize = 0;
-    for (i, ltr) in txt.chars().enumerate() {
+    for (i, ltr) in txt.char_indices() {
         if ltr == sp1 || ltr == sp2 {
             splited.push(txt[old_locate..i].to_string());
             old_locate = i + 1;
@@ -0,0 +1,74 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use super::note_translation::ROOT2NTNUM;
+use crate::cmd::txt2seq_cmps;
+use crate::lpnlib::*;
+use rand::rngs::StdRng;
+use rand::Rng;
+
+//*******************************************************************
+//          Chord Substitution Struct
+//*******************************************************************
+//  Composition Loop が始まるたびに、prob[%] の確率で和音を機能の近い和音に置き換える
+//  SUBST_TRITONE : 属七の和音を、裏コード(増四度上の属七)にする
+//  SUBST_RELATIVE : 長三和音と短三和音を、平行調の和音(長:短三度下, 短:短三度上)にする
+pub struct ChordSubst {
+    prob: i16,  // 0-100[%], 0:off
+    rules: i16, // SUBST_* の組み合わせ
+}
+impl ChordSubst {
+    /// 半音(0-11)から root の番号へ(1:Ib,2:I,3:I#...)
+    const PC2ROOT: [i16; 12] = [2, 4, 5, 7, 8, 11, 13, 14, 16, 17, 19, 20];
+
+    pub fn new() -> Self {
+        Self { prob: 0, rules: 0 }
+    }
+    /// value: MSG_SET_SUBST の形式, 負なら off
+    pub fn set(&mut self, value: i16) {
+        if value < 0 {
+            self.prob = 0;
+            self.rules = 0;
+        } else {
+            self.prob = value & 0x7f;
+            self.rules = value >> 8;
+        }
+    }
+    pub fn apply(&self, evts: Vec<ChordEvt>, rng: &mut StdRng) -> Vec<ChordEvt> {
+        if self.prob == 0 || self.rules == 0 {
+            return evts;
+        }
+        evts.into_iter()
+            .map(|ev| {
+                if ev.mtype == TYPE_CHORD && rng.random_range(0..100) < self.prob {
+                    self.substitute(ev)
+                } else {
+                    ev
+                }
+            })
+            .collect()
+    }
+    /// 規則に当てはまれば置き換えた和音を、当てはまらなければそのまま返す
+    pub fn substitute(&self, ev: ChordEvt) -> ChordEvt {
+        if ev.root == NO_ROOT || ev.root as usize >= ROOT2NTNUM.len() || ev.tbl == NO_TABLE {
+            return ev;
+        }
+        let upper = if ev.tbl > UPPER { UPPER } else { 0 };
+        let name = txt2seq_cmps::get_table_name(ev.tbl as usize);
+        let (shift, kind) = match name {
+            "_7" if self.rules & SUBST_TRITONE != 0 => (6, "_7"),
+            "_" if self.rules & SUBST_RELATIVE != 0 => (9, "_m"),
+            "_M7" if self.rules & SUBST_RELATIVE != 0 => (9, "_m7"),
+            "_m" if self.rules & SUBST_RELATIVE != 0 => (3, "_"),
+            "_m7" if self.rules & SUBST_RELATIVE != 0 => (3, "_M7"),
+            _ => return ev,
+        };
+        let pc = (ROOT2NTNUM[ev.root as usize] + shift).rem_euclid(12);
+        ChordEvt {
+            root: Self::PC2ROOT[pc as usize],
+            tbl: txt2seq_cmps::get_table_num(kind) + upper,
+            ..ev
+        }
+    }
+}
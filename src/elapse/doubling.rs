@@ -0,0 +1,85 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use crate::lpnlib::*;
+
+//*******************************************************************
+//          Doubling Filter Struct
+//*******************************************************************
+//  指定したパート間で、同じ高さの音が同時に鳴るのを防ぐ
+//  parts の前にあるパートほど優先し、後のパートの音を省くか、オクターブをずらす
+pub struct DoublingFilter {
+    parts: Vec<usize>, // 優先順(空なら off)
+    octave: bool,      // true:オクターブずらす, false:省く
+    sounding: [[u8; 128]; MAX_KBD_PART],
+}
+impl DoublingFilter {
+    pub fn new() -> Self {
+        Self {
+            parts: Vec::new(),
+            octave: false,
+            sounding: [[0; 128]; MAX_KBD_PART],
+        }
+    }
+    /// value: MSG_SET_DOUBLING の形式, 負なら off
+    pub fn set(&mut self, value: i16) {
+        self.parts.clear();
+        self.octave = value >= 0 && (value & DOUBLING_OCTAVE) != 0;
+        if value < 0 {
+            return;
+        }
+        for i in 0..MAX_KBD_PART {
+            let pt = ((value >> (i * 3)) & 0x07) as usize;
+            if pt == 0 || pt > MAX_KBD_PART {
+                break;
+            }
+            self.parts.push(pt - 1);
+        }
+    }
+    /// 優先順位(0が最優先)、Note の処理順に使う
+    pub fn rank(&self, part: u32) -> u32 {
+        self.parts
+            .iter()
+            .position(|&x| x == part as usize)
+            .unwrap_or(0) as u32
+    }
+    /// 鳴らしてよい note を返す(None:省く)
+    pub fn check(&self, part: u32, note: u8) -> Option<u8> {
+        let rank = match self.parts.iter().position(|&x| x == part as usize) {
+            Some(r) if note <= MAX_NOTE_NUMBER => r,
+            _ => return Some(note),
+        };
+        let doubled = |nt: u8| {
+            self.parts[..rank]
+                .iter()
+                .any(|&p| self.sounding[p][nt as usize] > 0)
+        };
+        if !doubled(note) {
+            Some(note)
+        } else if !self.octave {
+            None
+        } else if note >= MIN_NOTE_NUMBER + 12 && !doubled(note - 12) {
+            Some(note - 12)
+        } else if note + 12 <= MAX_NOTE_NUMBER && !doubled(note + 12) {
+            Some(note + 12)
+        } else {
+            None
+        }
+    }
+    pub fn note_on(&mut self, part: u32, note: u8) {
+        if let Some(cnt) = self.count_mut(part, note) {
+            *cnt = cnt.saturating_add(1);
+        }
+    }
+    pub fn note_off(&mut self, part: u32, note: u8) {
+        if let Some(cnt) = self.count_mut(part, note) {
+            *cnt = cnt.saturating_sub(1);
+        }
+    }
+    fn count_mut(&mut self, part: u32, note: u8) -> Option<&mut u8> {
+        self.sounding
+            .get_mut(part as usize)
+            .and_then(|pt| pt.get_mut(note as usize))
+    }
+}
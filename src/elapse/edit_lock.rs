@@ -0,0 +1,62 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use super::tickgen::CrntMsrTick;
+use crate::lpnlib::{ElpsMsg::*, *};
+
+//*******************************************************************
+//          Edit Lock Struct
+//*******************************************************************
+//  小節の最後の beats 拍の間に来た、Phrase/Composition を変える msg を預かり、
+//  次の小節の頭で、パートがその小節を始める前に実行する
+//  小節の境目で、一部のパートだけ新しいデータに変わるのを防ぐ
+pub struct EditLock {
+    beats: i32, // 小節の最後の何拍を lock するか, 0:off
+    deferred: Vec<ElpsMsg>,
+}
+impl EditLock {
+    pub fn new() -> Self {
+        Self {
+            beats: 0,
+            deferred: Vec::new(),
+        }
+    }
+    pub fn set(&mut self, beats: i16) {
+        self.beats = beats.max(0) as i32;
+    }
+    /// Phrase/Composition の構造を変える msg か
+    pub fn is_structural(msg: &ElpsMsg) -> bool {
+        matches!(
+            msg,
+            Phr(..)
+                | PhrX(_)
+                | Cmp(..)
+                | CmpX(_)
+                | Sync(_)
+                | Scene(_)
+                | SetList(_)
+                | SetListCtrl(_)
+        )
+    }
+    /// lock の区間なら msg を預かって None、そうでなければそのまま返す
+    pub fn check(
+        &mut self,
+        msg: ElpsMsg,
+        crnt_: &CrntMsrTick,
+        tick_for_beat: i32,
+    ) -> Option<ElpsMsg> {
+        let window = self.beats * tick_for_beat;
+        if window <= 0 || !Self::is_structural(&msg) || crnt_.tick < crnt_.tick_for_onemsr - window
+        {
+            return Some(msg);
+        }
+        self.deferred.push(msg);
+        None
+    }
+    /// 小節の頭や止めた時に、預かった msg を返す
+    /// 小節番号では比べないので、loop などで前の小節に戻っても次の小節の頭で返す
+    pub fn flush(&mut self) -> Vec<ElpsMsg> {
+        std::mem::take(&mut self.deferred)
+    }
+}
@@ -15,6 +15,7 @@ pub const PRI_PHR_LOOP: u32 = 300;
 pub const PRI_DYNPTN: u32 = 350;
 pub const PRI_NOTE: u32 = 400;
 pub const PRI_DMPR: u32 = 500;
+pub const PRI_CLICK: u32 = 550;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum ElapseType {
@@ -26,6 +27,7 @@ pub enum ElapseType {
     TpDynamicPattern,
     TpNote,
     TpFlow,
+    TpClick,
     _TpDamper,
 }
 
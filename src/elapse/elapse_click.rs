@@ -0,0 +1,133 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::elapse_base::*;
+use super::stack_elapse::ElapseStack;
+use super::tickgen::CrntMsrTick;
+
+//*******************************************************************
+//          Click Struct
+//*******************************************************************
+//  拍の頭ごとに click の音を鳴らす(metronome)
+//  一拍目は accent の音、拍の長さの半分で消す
+//  拍の長さはその都度 TickGen から得るので、拍子が変わっても追従する
+pub struct Click {
+    id: ElapseId,
+    priority: u32,
+    during_play: bool,
+    next_msr: i32,
+    next_tick: i32,
+
+    enable: bool,
+    ch: u8,               // 0-15
+    note: u8,             // 二拍目以降の音
+    accent: u8,           // 一拍目の音
+    sounding: Option<u8>, // 鳴らしている音
+}
+impl Click {
+    const VEL: u8 = 70;
+    const ACCENT_VEL: u8 = 110;
+    const DEFAULT_CH: u8 = 9; // ch.10
+    const DEFAULT_NOTE: u8 = 77; // Low Wood Block
+    const DEFAULT_ACCENT: u8 = 76; // Hi Wood Block
+
+    pub fn new(num: u32) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            id: ElapseId {
+                pid: 0,
+                sid: num,
+                elps_type: ElapseType::TpClick,
+            },
+            priority: PRI_CLICK,
+            during_play: false,
+            next_msr: 0,
+            next_tick: 0,
+            enable: false,
+            ch: Self::DEFAULT_CH,
+            note: Self::DEFAULT_NOTE,
+            accent: Self::DEFAULT_ACCENT,
+            sounding: None,
+        }))
+    }
+    pub fn set_enable(&mut self, enable: bool, estk: &mut ElapseStack) {
+        self.enable = enable;
+        if !enable {
+            self.note_off(estk);
+        }
+    }
+    /// ch: 1-16
+    pub fn set_ch(&mut self, ch: u8, estk: &mut ElapseStack) {
+        self.note_off(estk);
+        self.ch = ch.clamp(1, 16) - 1;
+    }
+    pub fn set_note(&mut self, note: u8, accent: u8) {
+        self.note = note.min(127);
+        self.accent = accent.min(127);
+    }
+    fn note_off(&mut self, estk: &mut ElapseStack) {
+        if let Some(nt) = self.sounding.take() {
+            estk.click_out(0x90 | self.ch, nt, 0);
+        }
+    }
+}
+impl Elapse for Click {
+    /// id を得る
+    fn id(&self) -> ElapseId {
+        self.id
+    }
+    /// priority を得る
+    fn prio(&self) -> u32 {
+        self.priority
+    }
+    /// 次に呼ばれる小節番号、Tick数を返す
+    fn next(&self) -> (i32, i32) {
+        (self.next_msr, self.next_tick)
+    }
+    /// User による start/play 時にコールされる
+    fn start(&mut self, msr: i32) {
+        self.during_play = true;
+        self.next_msr = msr;
+        self.next_tick = 0;
+    }
+    /// User による stop 時にコールされる
+    fn stop(&mut self, estk: &mut ElapseStack) {
+        self.during_play = false;
+        self.note_off(estk);
+    }
+    /// 再生データを消去
+    fn clear(&mut self, _estk: &mut ElapseStack) {}
+    /// 再生 msr/tick に達したらコールされる
+    fn process(&mut self, crnt_: &CrntMsrTick, estk: &mut ElapseStack) {
+        let (_, tick_for_beat) = estk.tg().get_beat_tick();
+        let beat_top = self.next_tick % tick_for_beat == 0;
+        self.note_off(estk);
+        if beat_top && self.enable && self.during_play {
+            let (note, vel) = if self.next_tick == 0 {
+                (self.accent, Self::ACCENT_VEL)
+            } else {
+                (self.note, Self::VEL)
+            };
+            estk.click_out(0x90 | self.ch, note, vel);
+            self.sounding = Some(note);
+        }
+        // 拍の頭と、拍の半分(音を消す)で呼ばれる
+        let next = (self.next_tick / (tick_for_beat / 2) + 1) * (tick_for_beat / 2);
+        if next >= crnt_.tick_for_onemsr {
+            self.next_msr = crnt_.msr + 1;
+            self.next_tick = 0;
+        } else {
+            self.next_msr = crnt_.msr;
+            self.next_tick = next;
+        }
+    }
+    /// 特定 elapse に message を送る
+    fn rcv_sp(&mut self, _msg: ElapseMsg, _msg_data: u8) {}
+    /// 自クラスが役割を終えた時に True を返す
+    fn destroy_me(&self) -> bool {
+        false
+    }
+}
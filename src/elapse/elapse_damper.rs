@@ -27,6 +27,17 @@ pub struct DamperPart {
     evt: Vec<DmprEvt>,
     play_counter: usize,
     whole_tick: i32,
+
+    // 入力された cc64 の録音
+    rec_start: Option<(i32, i32)>, // 録音開始の小節, tick_for_onemsr
+    rec_evt: Vec<(i32, u8)>,       // 録音開始からの tick, cc64 の値
+    ped_loop: Vec<Vec<DmprEvt>>,   // 小節ごとの録音したペダル(空なら自動 Damper)
+    ped_loop_top: i32,             // ped_loop の先頭の小節
+    punch: Option<(i32, i32)>,     // 録音する小節区間 [in, out), 0origin
+
+    // 和音の変わる速さ(harmonic rhythm)に合わせたペダル
+    rhythm: bool,
+    hold_over: bool, // 前の小節からペダルを踏み続けている
 }
 impl DamperPart {
     pub fn new(num: u32) -> Rc<RefCell<DamperPart>> {
@@ -47,11 +58,129 @@ impl DamperPart {
             evt: Vec::new(),
             play_counter: 0,
             whole_tick: 0,
+
+            rec_start: None,
+            rec_evt: Vec::new(),
+            ped_loop: Vec::new(),
+            ped_loop_top: 0,
+            punch: None,
+
+            rhythm: false,
+            hold_over: false,
         }))
     }
     pub fn set_position(&mut self, pos: i16) {
         self.position = pos;
     }
+    pub fn set_rhythm(&mut self, rhythm: bool) {
+        self.rhythm = rhythm;
+        self.hold_over = false;
+    }
+    /// punch 区間を設定する(None:解除)
+    pub fn set_punch(&mut self, punch: Option<(i32, i32)>) {
+        self.punch = punch;
+    }
+    /// 次の小節頭から cc64 の録音を始める
+    /// punch 区間があれば、区間の先頭(過ぎていれば次の小節頭)から録音する
+    pub fn start_rec(&mut self, crnt_: &CrntMsrTick) {
+        let mut msr = if crnt_.tick == 0 {
+            crnt_.msr
+        } else {
+            crnt_.msr + 1
+        };
+        if let Some((pin, _)) = self.punch {
+            msr = msr.max(pin);
+        }
+        self.rec_start = Some((msr, crnt_.tick_for_onemsr));
+        self.rec_evt.clear();
+    }
+    /// msr が録音する小節か
+    pub fn is_recording(&self, msr: i32) -> bool {
+        self.rec_start.is_some_and(|(start, _)| msr >= start)
+            && self.punch.is_none_or(|(_, pout)| msr < pout)
+    }
+    pub fn rec_pedal(&mut self, crnt_: &CrntMsrTick, val: u8) {
+        if let Some((msr, tfo)) = self.rec_start {
+            if self.is_recording(crnt_.msr) {
+                self.rec_evt
+                    .push(((crnt_.msr - msr) * tfo + crnt_.tick, val));
+            }
+        }
+    }
+    /// punch 区間の終わりに来ていれば録音を止める
+    pub fn punch_out(&mut self, crnt_: &CrntMsrTick) {
+        if self.rec_start.is_some() && self.punch.is_some_and(|(_, pout)| crnt_.msr >= pout) {
+            self.stop_rec(crnt_);
+        }
+    }
+    /// 録音を終え、最後に過ぎた小節線までを loop にする(途中の小節は捨てる)
+    /// punch 区間があり、既に録音したペダルがあれば、録音した小節だけを差し替える
+    pub fn stop_rec(&mut self, crnt_: &CrntMsrTick) {
+        let (msr, tfo) = match self.rec_start.take() {
+            Some(rs) => rs,
+            None => return,
+        };
+        let mut end_msr = crnt_.msr;
+        if let Some((_, pout)) = self.punch {
+            // punch 中は、ペダルを踏まなかった小節も録音したものとする
+            end_msr = end_msr.min(pout);
+        } else if self.rec_evt.is_empty() {
+            return;
+        }
+        if end_msr <= msr {
+            self.rec_evt.clear();
+            return;
+        }
+        let msrs = end_msr - msr;
+        let loop_tick = msrs * tfo;
+        let mut ped_loop: Vec<Vec<DmprEvt>> = vec![Vec::new(); msrs as usize];
+        let mut on_at: Option<(i32, u8)> = None;
+        let mut put_evt = |on: i32, off: i32, val: u8| {
+            if on < loop_tick {
+                ped_loop[(on / tfo) as usize].push(DmprEvt {
+                    mtype: TYPE_DAMPER,
+                    tick: (on % tfo) as i16,
+                    dur: (off.min(loop_tick) - on).clamp(1, i16::MAX as i32) as i16,
+                    position: val as i16,
+                    release: 0,
+                });
+            }
+        };
+        for (tick, val) in self.rec_evt.iter() {
+            match on_at {
+                None if *val >= 64 => on_at = Some((*tick, *val)),
+                Some((on, on_val)) if *val < 64 => {
+                    put_evt(on, *tick, on_val);
+                    on_at = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some((on, on_val)) = on_at {
+            put_evt(on, loop_tick, on_val);
+        }
+        if self.punch.is_some() && !self.ped_loop.is_empty() {
+            self.merge_punch(msr, ped_loop);
+        } else {
+            self.ped_loop = ped_loop;
+            self.ped_loop_top = msr;
+        }
+        self.rec_evt.clear();
+    }
+    /// punch で録音した小節を、今の ped_loop の同じ位置に差し替える
+    fn merge_punch(&mut self, msr: i32, rec: Vec<Vec<DmprEvt>>) {
+        let len = self.ped_loop.len() as i32;
+        for (i, evts) in rec.into_iter().enumerate() {
+            let idx = (msr + i as i32 - self.ped_loop_top).rem_euclid(len);
+            self.ped_loop[idx as usize] = evts;
+        }
+    }
+    /// 録音したペダルを消し、自動 Damper に戻す
+    pub fn clear_rec(&mut self) {
+        self.rec_start = None;
+        self.rec_evt.clear();
+        self.ped_loop.clear();
+    }
     /// 次回イベントの小節、tickを算出する
     fn gen_next_msr_tick(&self, crnt_: &CrntMsrTick, srtick: i32) -> (i32, i32) {
         if srtick == END_OF_DATA {
@@ -107,6 +236,17 @@ impl DamperPart {
         self.whole_tick = tick_for_onemsr;
         self.play_counter = 0;
 
+        self.punch_out(crnt_);
+        if self.is_recording(crnt_.msr) {
+            // 録音中は、自動 Damper を出さない
+            self.evt = Vec::new();
+            return NO_DATA;
+        } else if !self.ped_loop.is_empty() {
+            // 録音したペダルを loop する
+            let idx = (crnt_.msr - self.ped_loop_top).rem_euclid(self.ped_loop.len() as i32);
+            self.evt = self.ped_loop[idx as usize].clone();
+            return self.evt.first().map_or(NO_DATA, |x| x.tick as i32);
+        }
         let mut chord_map = vec![false; beat_num];
         if let Some(_fl) = estk.get_flow() {
             chord_map = DamperPart::merge_chord_map(
@@ -139,9 +279,108 @@ impl DamperPart {
             }
         }
         let tick;
-        (self.evt, tick) = self.gen_real_damper_track(chord_map, tick_for_onebeat, beat_num);
+        if self.rhythm {
+            let noped = chord_map.iter().all(|x| !x);
+            let (change_map, continues) =
+                DamperPart::harmonic_rhythm(crnt_, estk, tick_for_onemsr, tick_for_onebeat);
+            (self.evt, tick) = self.gen_rhythm_damper_track(
+                chord_map,
+                change_map,
+                continues && !noped,
+                tick_for_onebeat,
+                beat_num,
+            );
+        } else {
+            (self.evt, tick) = self.gen_real_damper_track(chord_map, tick_for_onebeat, beat_num);
+        }
         tick
     }
+    /// 今の小節で和音が本当に変わる拍と、次の小節頭で和音が変わらず続くかを返す
+    fn harmonic_rhythm(
+        crnt_: &CrntMsrTick,
+        estk: &mut ElapseStack,
+        tick_for_onemsr: i32,
+        tick_for_onebeat: i32,
+    ) -> (Vec<bool>, bool) {
+        let beat_num = (tick_for_onemsr / tick_for_onebeat) as usize;
+        let mut change_map = vec![false; beat_num];
+        let mut next_in_loop = false;
+        let mut next_change = false;
+        for i in 0..MAX_KBD_PART {
+            let cmps = match estk.get_cmps(i) {
+                Some(c) => c,
+                None => continue,
+            };
+            let cmps = cmps.borrow();
+            let chord = cmps.get_chord_map(crnt_.msr, tick_for_onemsr, tick_for_onebeat);
+            if let Some(repeat) = cmps.get_repeat_map(crnt_.msr, tick_for_onemsr, tick_for_onebeat)
+            {
+                for (j, x) in change_map.iter_mut().enumerate() {
+                    *x |= chord[j] && !repeat[j];
+                }
+            }
+            let next = crnt_.msr + 1;
+            if let Some(repeat) = cmps.get_repeat_map(next, tick_for_onemsr, tick_for_onebeat) {
+                let chord = cmps.get_chord_map(next, tick_for_onemsr, tick_for_onebeat);
+                next_in_loop = true;
+                next_change |= chord[0] && !repeat[0];
+            }
+        }
+        (change_map, next_in_loop && !next_change)
+    }
+    /// 和音が速く変わる所は短く踏み、同じ和音が続く所は長く踏んで、
+    /// 小節線や長い和音の途中では少しだけペダルを上げる(partial change)
+    fn gen_rhythm_damper_track(
+        &mut self,
+        chord_map: Vec<bool>,
+        change_map: Vec<bool>,
+        continues: bool,
+        tick_for_onebeat: i32,
+        beat_num: usize,
+    ) -> (Vec<DmprEvt>, i32) {
+        const PDL_MARGIN_TICK: i32 = 60;
+        let partial = self.position / 2;
+        // (拍, 和音が変わるか)
+        let mut points: Vec<(usize, bool)> = chord_map
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| **x)
+            .map(|(i, _)| (i, change_map[i]))
+            .collect();
+        if self.hold_over && !chord_map[0] {
+            points.insert(0, (0, false));
+        }
+        // 一小節以上同じ和音が続く所は、途中でも partial change する
+        if let [(0, _)] = points[..] {
+            if self.hold_over || continues {
+                points.push((beat_num / 2, false));
+            }
+        }
+        let mut dmpr_evt: Vec<DmprEvt> = Vec::new();
+        for (i, &(start, _)) in points.iter().enumerate() {
+            let (end, next_full) = match points.get(i + 1) {
+                Some(&(b, full)) => (b, full),
+                None => (beat_num, !continues),
+            };
+            let tick = (start as i32) * tick_for_onebeat + PDL_MARGIN_TICK;
+            let dur = if end - start == 1 && next_full {
+                // 和音が速く変わる所は、短く踏む
+                tick_for_onebeat / 2
+            } else {
+                (end as i32) * tick_for_onebeat - tick
+            };
+            dmpr_evt.push(DmprEvt {
+                mtype: TYPE_DAMPER,
+                tick: tick as i16,
+                dur: dur as i16,
+                position: self.position,
+                release: if next_full { 0 } else { partial },
+            });
+        }
+        self.hold_over = continues && !dmpr_evt.is_empty();
+        let first_tick = dmpr_evt.first().map_or(NO_DATA, |x| x.tick as i32);
+        (dmpr_evt, first_tick)
+    }
     /// 各パートのChord情報より、Damper 情報を beat にどんどん足していく
     fn merge_chord_map(
         crnt_: &CrntMsrTick,
@@ -180,6 +419,7 @@ impl DamperPart {
                         tick,
                         dur: (((j - keep) as i32) * tick_for_onebeat - PDL_MARGIN_TICK) as i16,
                         position: self.position,
+                        release: 0,
                     });
                     if first_tick == NO_DATA {
                         first_tick = tick as i32
@@ -195,6 +435,7 @@ impl DamperPart {
                 tick,
                 dur: (((beat_num - keep) as i32) * tick_for_onebeat - PDL_MARGIN_TICK) as i16,
                 position: self.position,
+                release: 0,
             });
             if first_tick == NO_DATA {
                 first_tick = tick as i32
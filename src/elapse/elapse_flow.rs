@@ -11,6 +11,7 @@ use super::note_translation::*;
 use super::stack_elapse;
 use super::stack_elapse::ElapseStack;
 use super::tickgen::CrntMsrTick;
+use crate::cmd::txt2seq_cmps;
 use crate::lpnlib::*;
 
 //*******************************************************************
@@ -26,7 +27,7 @@ use crate::lpnlib::*;
 //      0  -  71 : 対応するノート番号
 //
 //  ・Event Stock
-//      GenStock (note:u8, vel:u8, org_locate:u8) : 実際に鳴っている原因のイベントを保持する
+//      GenStock (note:u8, vel:u8, org_locate:u8, msr, tick) : 実際に鳴っている原因のイベントを保持する
 //      gen_stock: Vec<GenStock>
 //
 //  ・Event State
@@ -35,9 +36,13 @@ use crate::lpnlib::*;
 pub const LOCATION_ALL: usize = 96;
 pub const _FLOWNOTE_ALL: usize = 72;
 pub const TICK_RESOLUTION: i32 = 120;
+const PAD_NOTES: usize = 4; // chord pad mode で一度に鳴らす最大音数
+const CHORD_MEMORY_VEL: u8 = 80; // 記憶した voicing を鳴らす velocity
 
 struct RawEv(i32, i32, u8, u8, u8); //  0:msr, 1:tick, 2:status, 3:locate, 4: vel
-struct GenStock(u8, u8, u8); // 0:note, 1:vel, 2:locate
+struct GenStock(u8, u8, u8, i32, i32); // 0:note, 1:vel, 2:locate, 3:msr, 4:tick(発音した時)
+#[derive(Clone)]
+struct ChordMemory(i16, i16, u8, Vec<u8>); // 0:root(0-11), 1:table, 2:keynote, 3:鳴っていた音(voicing)
 
 pub struct Flow {
     id: ElapseId,
@@ -50,6 +55,12 @@ pub struct Flow {
     keynote: u8,
     root: i16,
     translation_tbl: i16,
+    audio_chord: Option<(i16, i16)>, // オーディオ入力から推定した和音(root: 0-11, table)
+    pad_mode: bool,                  // 一つの鍵盤で和音全体を鳴らす
+    live_chord: Option<(i16, i16)>,  // 再生中に外部から来た和音(root: 0-11, table)
+    fix_window: i32, // 拍頭からこの tick 数以内に来た和音で、鳴っている音を直す(0:off)
+    chord_memory: Vec<Option<ChordMemory>>, // pad ごとに記憶した和音と voicing
+    memory_voicing: Vec<u8>, // 呼び出した pad で鳴らしている音
 
     // for super's member
     during_play: bool,
@@ -78,6 +89,12 @@ impl Flow {
             keynote: 0,
             root: 0,
             translation_tbl: NO_TABLE,
+            audio_chord: None,
+            pad_mode: false,
+            live_chord: None,
+            fix_window: 0,
+            chord_memory: vec![None; MAX_CHORD_PAD],
+            memory_voicing: Vec::new(),
 
             // for super's member
             during_play,
@@ -105,12 +122,20 @@ impl Flow {
     ) {
         #[cfg(feature = "verbose")]
         println!("MIDI IN >> {:x}-{:x}-{:x}", status, locate, vel);
+        if estk_.direct_thru() {
+            // MIDI Rx thread がそのまま出力済みなので、変換した音は鳴らさない
+            // 出力以外(記録、LED など)は、thru した音で行う
+            if (status & 0xe0) == 0x80 && (4..92).contains(&locate) {
+                estk_.midi_thru_flow(status, locate + 17, vel);
+            }
+            return;
+        }
         if !self.during_play {
             // ORBIT 自身の Pattern が鳴っていない時
-            if self.translation_tbl != NO_TABLE {
+            if self.translation_tbl != NO_TABLE || self.audio_chord.is_some() {
                 if status & 0xf0 == 0x90 {
                     if vel != 0 {
-                        self.flow_note_on(estk_, locate, vel);
+                        self.flow_note_on(estk_, locate, vel, (crnt_.msr, crnt_.tick));
                     } else {
                         self.flow_note_off(estk_, locate);
                     }
@@ -142,7 +167,6 @@ impl Flow {
     ///  off なら、この音を鳴らしたイベントを locate から探し、その音を消す
     fn convert_evt(&mut self, estk: &mut ElapseStack) {
         while let Some(ev) = self.raw_ev.pop() {
-            let _ = ev.0; // warning 対策
             let ch_status = ev.2 & 0xf0;
             let locate_idx = if (ev.3 as usize) < LOCATION_ALL {
                 ev.3 as usize
@@ -155,7 +179,7 @@ impl Flow {
                     break;
                 }
                 self.raw_state[locate_idx] = ev.1;
-                self.flow_note_on(estk, ev.3, ev.4);
+                self.flow_note_on(estk, ev.3, ev.4, (ev.0, ev.1));
             } else if ch_status == 0x80 || (ch_status == 0x90 && ev.4 == 0x00) {
                 // off
                 self.raw_state[locate_idx] = NO_DATA;
@@ -164,22 +188,36 @@ impl Flow {
         }
         self.next_msr = FULL; // process() は呼ばれないようになる
     }
-    fn flow_note_on(&mut self, estk: &mut ElapseStack, locate: u8, vel: u8) {
-        let rnote = self.detect_real_note(estk, locate as i16);
-        if let Some(idx) = self.same_note_index(rnote) {
-            self.gen_stock[idx].2 = locate; // locate 差し替え
-        } else {
-            estk.inc_key_map(rnote, vel, self.id.pid as u8);
-            estk.midi_out_flow(0x90, rnote, vel);
-            #[cfg(feature = "verbose")]
-            println!("MIDI OUT<< 0x90:{:x}:{:x}", rnote, vel);
-            self.gen_stock.push(GenStock(rnote, vel, locate));
+    /// mt: 触った時の (msr, tick)
+    fn flow_note_on(&mut self, estk: &mut ElapseStack, locate: u8, vel: u8, mt: (i32, i32)) {
+        let rnotes = self.gen_real_notes(estk, locate);
+        let chord = self.crnt_chord(estk);
+        for rnote in rnotes.into_iter() {
+            if let Some(idx) = self.same_note_index(rnote) {
+                self.gen_stock[idx].2 = locate; // locate 差し替え
+            } else {
+                let mut vel = vel;
+                if let Some((root, ctbl)) = chord {
+                    // 入力された旋律の構成音の分析と強調
+                    let emph = estk.put_tone(self.id.pid, root, ctbl, rnote as i16, self.keynote);
+                    vel = (vel as i16 + emph).clamp(1, 127) as u8;
+                }
+                estk.inc_key_map(rnote, vel, self.id.pid as u8, 0);
+                estk.midi_out_flow(0x90, rnote, vel);
+                estk.punch_note(rnote, vel);
+                #[cfg(feature = "verbose")]
+                println!("MIDI OUT<< 0x90:{:x}:{:x}", rnote, vel);
+                self.gen_stock
+                    .push(GenStock(rnote, vel, locate, mt.0, mt.1));
+            }
         }
     }
     fn flow_note_off(&mut self, estk: &mut ElapseStack, locate: u8) {
-        if let Some(idx) = self.same_locate_index(locate) {
+        // chord pad mode では、一つの locate に複数の音がある
+        while let Some(idx) = self.same_locate_index(locate) {
             let rnote = self.gen_stock[idx].0;
-            let snk = estk.dec_key_map(rnote);
+            let snk = estk.dec_key_map(rnote, 0);
+            estk.punch_note(rnote, 0);
             if snk == stack_elapse::SameKeyState::Last {
                 estk.midi_out_flow(0x90, rnote, 0); // test
             }
@@ -188,6 +226,32 @@ impl Flow {
             self.gen_stock.remove(idx);
         }
     }
+    fn gen_real_notes(&mut self, estk: &mut ElapseStack, locate: u8) -> Vec<u8> {
+        if self.pad_mode {
+            self.gen_pad_notes(estk, locate as i16)
+        } else {
+            vec![self.detect_real_note(estk, locate as i16)]
+        }
+    }
+    /// 今の和音の root(0-11) と table
+    fn crnt_chord(&self, estk: &ElapseStack) -> Option<(i16, i16)> {
+        if self.during_play {
+            if self.live_chord.is_some() {
+                // 再生中に外部から来た和音を優先する
+                return self.live_chord;
+            }
+            if let Some(cmps) = estk.get_cmps(self.id.pid as usize) {
+                // Composition の指定がある時は、そちらを優先する
+                let (rt, ctbl) = cmps.borrow().get_chord();
+                return Some((ROOT2NTNUM[rt as usize], ctbl));
+            }
+            self.audio_chord
+        } else if self.audio_chord.is_some() {
+            self.audio_chord
+        } else {
+            Some((ROOT2NTNUM[self.root as usize], self.translation_tbl))
+        }
+    }
     fn detect_real_note(&mut self, estk: &mut ElapseStack, locate: i16) -> u8 {
         let mut temp_note = (locate * 12) / 16;
         //if self.id.pid / 2 == 0 {
@@ -199,21 +263,43 @@ impl Flow {
             temp_note = 127;
         }
         let mut real_note: u8 = temp_note as u8;
-        if self.during_play {
-            if let Some(cmps) = estk.get_cmps(self.id.pid as usize) {
-                let (rt, ctbl) = cmps.borrow().get_chord();
-                let root: i16 = ROOT2NTNUM[rt as usize];
-                real_note = translate_note_com(root, ctbl, temp_note) as u8;
-            }
-        } else {
-            let root: i16 = ROOT2NTNUM[self.root as usize];
-            real_note = translate_note_com(root, self.translation_tbl, temp_note) as u8;
+        if let Some((root, ctbl)) = self.crnt_chord(estk) {
+            real_note = translate_note_com(root, ctbl, temp_note) as u8;
         }
 
         real_note += self.keynote;
         let _ = real_note.clamp(MIN_NOTE_NUMBER, MAX_NOTE_NUMBER);
         real_note
     }
+    /// chord pad mode : 触った位置以上で一番近い構成音から、和音を積み上げる
+    /// 鍵盤の位置によって、転回形と音域が変わる
+    fn gen_pad_notes(&mut self, estk: &mut ElapseStack, locate: i16) -> Vec<u8> {
+        let chord = self.crnt_chord(estk);
+        let (root, ctbl) = match chord {
+            Some((root, ctbl)) if ctbl != NO_TABLE => (root, ctbl),
+            _ => return vec![self.detect_real_note(estk, locate)],
+        };
+        let (tbl, _) = txt2seq_cmps::get_table(ctbl as usize);
+        let mut pcs: Vec<i16> = if tbl.len() > 5 {
+            // Scale の場合は、三度ずつ重ねる
+            tbl.iter().step_by(2).take(3).copied().collect()
+        } else {
+            tbl.to_vec()
+        };
+        pcs.iter_mut().for_each(|x| *x = (*x + root).rem_euclid(12));
+        let temp_note = ((locate * 12) / 16 + 36).min(127 - 24);
+        let mut notes = Vec::new();
+        for nt in temp_note..temp_note + 24 {
+            if notes.len() >= PAD_NOTES.min(pcs.len()) {
+                break;
+            }
+            if pcs.contains(&nt.rem_euclid(12)) {
+                notes.push((nt + self.keynote as i16) as u8);
+            }
+        }
+        notes.retain(|x| (MIN_NOTE_NUMBER..=MAX_NOTE_NUMBER).contains(x));
+        notes
+    }
     fn same_note_index(&self, rnote: u8) -> Option<usize> {
         for (i, x) in self.gen_stock.iter().enumerate() {
             if x.0 == rnote && x.1 != 0 {
@@ -231,9 +317,123 @@ impl Flow {
         None
     }
     pub fn set_chord_for_noplay(&mut self, root: u8, tblnum: u8, keynote: u8) {
-        self.root = root as i16;
-        self.translation_tbl = tblnum as i16;
+        self.set_chord(root as i16, tblnum as i16, keynote);
+    }
+    /// root: ROOT2NTNUM の index
+    fn set_chord(&mut self, root: i16, tblnum: i16, keynote: u8) {
+        self.root = root;
+        self.translation_tbl = tblnum;
         self.keynote = keynote;
+        self.audio_chord = None;
+    }
+    /// 今の和音と、Flow で鳴っている音(voicing)を pad(1-16) に記憶する
+    pub fn store_chord_memory(&mut self, estk: &ElapseStack, num: usize) -> bool {
+        if !(1..=MAX_CHORD_PAD).contains(&num) {
+            return false;
+        }
+        let (root, tbl) = match self.crnt_chord(estk) {
+            Some((root, tbl)) if tbl != NO_TABLE => (root, tbl),
+            _ => return false,
+        };
+        let mut voicing: Vec<u8> = self
+            .gen_stock
+            .iter()
+            .filter(|x| x.1 != 0)
+            .map(|x| x.0)
+            .collect();
+        voicing.sort();
+        voicing.dedup();
+        self.chord_memory[num - 1] =
+            Some(ChordMemory(root.rem_euclid(12), tbl, self.keynote, voicing));
+        true
+    }
+    /// pad(1-16) に記憶した和音を設定し、その voicing を次の呼び出しまで鳴らす
+    pub fn recall_chord_memory(&mut self, estk: &mut ElapseStack, num: usize) -> bool {
+        let memory = match num
+            .checked_sub(1)
+            .and_then(|i| self.chord_memory.get(i).cloned())
+            .flatten()
+        {
+            Some(memory) => memory,
+            None => return false,
+        };
+        self.release_chord_memory(estk);
+        // root(0-11) を、ROOT2NTNUM の index に戻す
+        let root = ROOT2NTNUM
+            .iter()
+            .skip(2)
+            .position(|x| *x == memory.0)
+            .map_or(0, |i| i + 2);
+        self.set_chord(root as i16, memory.1, memory.2);
+        for nt in memory.3.iter() {
+            estk.inc_key_map(*nt, CHORD_MEMORY_VEL, self.id.pid as u8, 0);
+            estk.midi_out_flow(0x90, *nt, CHORD_MEMORY_VEL);
+        }
+        self.memory_voicing = memory.3;
+        true
+    }
+    /// 呼び出した pad で鳴らしている音を消す
+    pub fn release_chord_memory(&mut self, estk: &mut ElapseStack) {
+        for nt in std::mem::take(&mut self.memory_voicing) {
+            if estk.dec_key_map(nt, 0) == stack_elapse::SameKeyState::Last {
+                estk.midi_out_flow(0x90, nt, 0);
+            }
+        }
+    }
+    pub fn set_fix_window(&mut self, tick: i32) {
+        self.fix_window = tick.max(0);
+    }
+    /// 再生中に外部から来た和音を設定する(fix_window が 0 の時は受け付けない)
+    /// 拍頭から fix_window 以内なら、その拍で既に鳴らした音を新しい和音で鳴らし直す
+    pub fn set_live_chord(
+        &mut self,
+        estk: &mut ElapseStack,
+        crnt_: &CrntMsrTick,
+        root: u8,
+        tblnum: u8,
+        keynote: u8,
+    ) {
+        if self.fix_window == 0 || root as usize >= ROOT2NTNUM.len() {
+            return;
+        }
+        self.live_chord = Some((ROOT2NTNUM[root as usize], tblnum as i16));
+        self.keynote = keynote;
+
+        let tick_for_beat = DEFAULT_TICK_FOR_ONE_MEASURE / estk.tg().get_meter().1;
+        let beat_top = crnt_.tick - crnt_.tick % tick_for_beat;
+        if crnt_.tick - beat_top > self.fix_window {
+            return;
+        }
+        let mut late: Vec<(u8, u8, i32, i32)> = Vec::new(); // locate, vel, msr, tick
+        for gs in self.gen_stock.iter() {
+            if gs.3 == crnt_.msr && gs.4 >= beat_top && !late.iter().any(|x| x.0 == gs.2) {
+                late.push((gs.2, gs.1, gs.3, gs.4));
+            }
+        }
+        for (locate, vel, msr, tick) in late.into_iter() {
+            let mut old: Vec<u8> = self
+                .gen_stock
+                .iter()
+                .filter(|x| x.2 == locate)
+                .map(|x| x.0)
+                .collect();
+            let mut new = self.gen_real_notes(estk, locate);
+            old.sort();
+            new.sort();
+            if old != new {
+                #[cfg(feature = "verbose")]
+                println!("Flow chord fix: {:?} -> {:?}", old, new);
+                self.flow_note_off(estk, locate);
+                self.flow_note_on(estk, locate, vel, (msr, tick));
+            }
+        }
+    }
+    pub fn set_pad_mode(&mut self, pad: bool) {
+        self.pad_mode = pad;
+    }
+    /// root: 絶対音(0-11), None で推定和音を解除
+    pub fn set_audio_chord(&mut self, chord: Option<(i16, i16)>) {
+        self.audio_chord = chord.map(|(root, tbl)| ((root - self.keynote as i16 + 12) % 12, tbl));
     }
 }
 
@@ -255,11 +455,15 @@ impl Elapse for Flow {
         self.during_play = true;
     }
     /// User による stop 時にコールされる
-    fn stop(&mut self, _estk: &mut ElapseStack) {
+    fn stop(&mut self, estk: &mut ElapseStack) {
         self.during_play = false;
+        self.live_chord = None;
+        self.release_chord_memory(estk);
     }
     /// 再生データを消去
-    fn clear(&mut self, _estk: &mut ElapseStack) {}
+    fn clear(&mut self, estk: &mut ElapseStack) {
+        self.release_chord_memory(estk);
+    }
     /// 再生 msr/tick に達したらコールされる
     fn process(&mut self, crnt_: &CrntMsrTick, estk: &mut ElapseStack) {
         if (crnt_.msr == self.next_msr
@@ -7,6 +7,8 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use super::elapse_base::*;
+use super::elapse_note::{Note, NoteParam};
+use super::note_translation::ROOT2NTNUM;
 use super::stack_elapse::ElapseStack;
 use super::tickgen::CrntMsrTick;
 use crate::cmd::txt2seq_cmps::{self, NO_LOOP};
@@ -21,6 +23,7 @@ pub struct CompositionLoop {
 
     cmps_dt: Vec<ChordEvt>,
     keynote: u8,
+    next_keynote: Option<u8>, // 次の和音から使う keynote
     play_counter: usize,
     next_tick_in_cmps: i32,
 
@@ -32,6 +35,7 @@ pub struct CompositionLoop {
     already_end: bool,
     no_loop: bool,
     vari_num: i16,
+    pad_notes: Vec<i16>, // pad mode で鳴らしている和音
 
     // for super's member
     whole_tick: i32,
@@ -41,6 +45,8 @@ pub struct CompositionLoop {
     next_tick: i32, //   次に呼ばれるTick数が保持される
 }
 impl CompositionLoop {
+    const PAD_VEL: i16 = 64;
+
     pub fn new(
         sid: u32,
         pid: u32,
@@ -58,6 +64,7 @@ impl CompositionLoop {
             priority: PRI_CMPS_LOOP,
             cmps_dt: msg,
             keynote: knt,
+            next_keynote: None,
             play_counter: 0,
             next_tick_in_cmps: 0,
 
@@ -68,6 +75,7 @@ impl CompositionLoop {
             already_end: false,
             no_loop: false,
             vari_num: 0,
+            pad_notes: Vec::new(),
 
             // for super's member
             whole_tick,
@@ -80,11 +88,63 @@ impl CompositionLoop {
     pub fn get_chord(&self) -> (i16, i16) {
         (self.root, self.translation_tbl)
     }
+    /// 今から after tick 後に鳴っている和音(root, table)、Loop の繰り返しも含む
+    /// それまでに和音が変わらなければ、今の和音
+    pub fn get_chord_after(&self, crnt_: &CrntMsrTick, after: i32) -> (i16, i16) {
+        let now = (crnt_.msr - self.first_msr_num) * crnt_.tick_for_onemsr + crnt_.tick;
+        if self.no_loop || self.whole_tick <= 0 || now < 0 || after <= 0 {
+            return self.get_chord();
+        }
+        let target = now + after;
+        // target 以前で、最後に和音が変わった位置
+        self.cmps_dt
+            .iter()
+            .filter(|ev| ev.mtype == TYPE_CHORD && ev.tick as i32 <= target)
+            .map(|ev| {
+                let tick = ev.tick as i32;
+                (
+                    tick + (target - tick) / self.whole_tick * self.whole_tick,
+                    ev,
+                )
+            })
+            .max_by_key(|(at, _)| *at)
+            .filter(|(at, _)| *at > now)
+            .map_or(self.get_chord(), |(_, ev)| (ev.root, ev.tbl))
+    }
+    pub fn set_keynote(&mut self, knt: u8, pivot: bool) {
+        if pivot {
+            self.next_keynote = Some(knt);
+        } else {
+            self.keynote = knt;
+        }
+    }
     pub fn get_vari_num(&self) -> i16 {
         self.vari_num
     }
-    pub fn get_chord_name(&self) -> String {
-        self.chord_name.clone()
+    /// disp: None なら度数表示、Some((移調, 綴り)) なら key と移調を反映した音名表示
+    pub fn get_chord_name(&self, disp: Option<(i16, i16)>) -> String {
+        match disp {
+            Some((trns, spell)) if self.root != NO_ROOT => {
+                let tbl_name = txt2seq_cmps::get_table_name(self.translation_tbl as usize);
+                if let Some(kind) = tbl_name.strip_prefix('_') {
+                    let root = ROOT2NTNUM[self.root as usize];
+                    let pc = (self.keynote as i16 + root + trns).rem_euclid(12);
+                    let key = self.keynote as i16 + trns;
+                    note_name(pc, key, spell).to_string() + kind
+                } else {
+                    self.chord_name.clone()
+                }
+            }
+            _ => self.chord_name.clone(),
+        }
+    }
+    /// 今の和音の種類(UI で色分けする)
+    pub fn get_chord_quality(&self) -> ChordQuality {
+        if self.root == NO_ROOT || self.translation_tbl == NO_TABLE {
+            ChordQuality::None
+        } else {
+            txt2seq_cmps::get_chord_quality(self.translation_tbl as usize)
+        }
     }
     pub fn get_chord_map(
         &self,
@@ -121,6 +181,49 @@ impl CompositionLoop {
         }
         chord_map
     }
+    /// 小節の頭で鳴っている和音(root, table)と、その小節が Loop の最後の小節か
+    /// msr が Loop の外なら None
+    pub fn get_msr_chord(&self, msr: i32, tick_for_onemsr: i32) -> Option<(i16, i16, bool)> {
+        let first_tick = (msr - self.first_msr_num) * tick_for_onemsr;
+        if self.no_loop || first_tick < 0 || first_tick >= self.whole_tick {
+            return None;
+        }
+        let last = first_tick + tick_for_onemsr >= self.whole_tick;
+        let (root, tbl) = self
+            .cmps_dt
+            .iter()
+            .rfind(|ev| ev.mtype == TYPE_CHORD && ev.tick as i32 <= first_tick)
+            .map_or((NO_ROOT, NO_TABLE), |ev| (ev.root, ev.tbl));
+        Some((root, tbl, last))
+    }
+    /// for Damper: 前と同じ和音が続く拍(和音の変わり目ではない拍)を返す
+    /// msr が Loop の外なら None
+    pub fn get_repeat_map(
+        &self,
+        msr: i32,
+        tick_for_onemsr: i32,
+        tick_for_onebeat: i32,
+    ) -> Option<Vec<bool>> {
+        let first_tick = (msr - self.first_msr_num) * tick_for_onemsr;
+        if self.no_loop || first_tick < 0 || first_tick >= self.whole_tick {
+            return None;
+        }
+        let end_tick = first_tick + tick_for_onemsr;
+        let beat_num = tick_for_onemsr / tick_for_onebeat;
+        let mut repeat_map: Vec<bool> = vec![false; beat_num as usize];
+        for (i, ev) in self.cmps_dt.iter().enumerate() {
+            let tick = ev.tick as i32;
+            if tick >= end_tick {
+                break;
+            } else if i > 0 && tick >= first_tick {
+                let prev = &self.cmps_dt[i - 1];
+                if prev.root == ev.root && prev.tbl == ev.tbl {
+                    repeat_map[((tick % tick_for_onemsr) / tick_for_onebeat) as usize] = true;
+                }
+            }
+        }
+        Some(repeat_map)
+    }
     fn generate_event(
         &mut self,
         _crnt_: &CrntMsrTick,
@@ -145,7 +248,13 @@ impl CompositionLoop {
                         self.no_loop = true;
                     }
                 } else if cd.mtype == TYPE_CHORD {
-                    self.prepare_note_translation(cd, _estk);
+                    if let Some(pad) = _estk.get_pad(self.id.pid as usize) {
+                        self.gen_pad(_crnt_, _estk, trace, pad);
+                    }
+                    if let Some(ch) = _estk.get_chord_out(self.id.pid as usize) {
+                        self.gen_chord_out(_crnt_, _estk, trace, ch);
+                    }
+                    self.prepare_note_translation(cd, _crnt_, _estk);
                 } else if cd.mtype == TYPE_VARI {
                     _estk.set_phrase_vari(self.id.pid as usize, cd.root as usize);
                     self.vari_num = cd.root;
@@ -158,7 +267,15 @@ impl CompositionLoop {
         self.play_counter = trace;
         next_tick
     }
-    fn prepare_note_translation(&mut self, cd: ChordEvt, _estk: &mut ElapseStack) {
+    fn prepare_note_translation(
+        &mut self,
+        cd: ChordEvt,
+        crnt_: &CrntMsrTick,
+        _estk: &mut ElapseStack,
+    ) {
+        if let Some(knt) = self.next_keynote.take() {
+            self.keynote = knt; // pivot 転調
+        }
         self.root = cd.root;
         self.translation_tbl = cd.tbl;
 
@@ -178,6 +295,20 @@ impl CompositionLoop {
         } else {
             self.chord_name = cname;
         }
+        if self.root != NO_ROOT && (self.root as usize) < ROOT2NTNUM.len() {
+            _estk.light_chord(
+                self.id.pid,
+                &self.chord_name,
+                ROOT2NTNUM[self.root as usize],
+            );
+        }
+        // 小節頭の和音は前の小節の最後に処理されるので、和音の位置を渡す
+        let tfm = crnt_.tick_for_onemsr;
+        let at = (
+            self.first_msr_num + cd.tick as i32 / tfm,
+            cd.tick as i32 % tfm,
+        );
+        _estk.trigger_chord(self.id.pid, self.root, self.translation_tbl, at);
         if self.id.pid == FLOW_PART as u32 {
             // MIDI Out (keynoteも一緒に送る)
             _estk.midi_out_ext(0xa0, 0x7f, self.keynote);
@@ -192,6 +323,124 @@ impl CompositionLoop {
             println!("Chord Data: {}, {}, {}", self.chord_name, cd.root, cd.tbl);
         }
     }
+    /// pad mode : 和音を、次に和音が変わるまでの持続音で鳴らす
+    /// pad: PAD_REATTACK なら全音を弾き直し、0以上なら共通音はそのまま伸ばし、
+    ///      変わる音は pad tick だけ次の和音と重ねる
+    fn gen_pad(&mut self, crnt_: &CrntMsrTick, estk: &mut ElapseStack, idx: usize, pad: i16) {
+        let cd = &self.cmps_dt[idx];
+        let notes = self.pad_voicing(cd);
+        let start = cd.tick as i32;
+        let chords: Vec<(i32, Vec<i16>)> = self.cmps_dt[idx + 1..]
+            .iter()
+            .filter(|c| c.mtype == TYPE_CHORD)
+            .map(|c| (c.tick as i32, self.pad_voicing(c)))
+            .collect();
+        for &note in notes.iter() {
+            let end = if pad == PAD_REATTACK {
+                chords.first().map_or(self.whole_tick, |c| c.0)
+            } else if self.pad_notes.contains(&note) {
+                continue; // 前の和音から伸ばしている
+            } else {
+                chords
+                    .iter()
+                    .find(|c| !c.1.contains(&note))
+                    .map_or(self.whole_tick, |c| c.0 + pad as i32)
+            };
+            let ev = PhrEvt {
+                mtype: TYPE_PAD,
+                dur: (end - start).min(i16::MAX as i32) as i16,
+                note,
+                vel: Self::PAD_VEL,
+                ..PhrEvt::default()
+            };
+            self.add_held_note(crnt_, estk, idx, &ev, start);
+        }
+        self.pad_notes = notes;
+    }
+    /// 外部の harmonizer 等のため、和音を基本形で積んだ Note On として、指定 ch に出力する
+    /// 次の和音まで(無ければ Loop の最後まで)押さえたままにする
+    fn gen_chord_out(&mut self, crnt_: &CrntMsrTick, estk: &mut ElapseStack, idx: usize, ch: i16) {
+        let cd = &self.cmps_dt[idx];
+        let start = cd.tick as i32;
+        let end = self.cmps_dt[idx + 1..]
+            .iter()
+            .find(|c| c.mtype == TYPE_CHORD)
+            .map_or(self.whole_tick, |c| c.tick as i32);
+        for note in Self::chord_out_voicing(cd).into_iter() {
+            let ev = PhrEvt {
+                mtype: TYPE_PAD,
+                dur: (end - start).min(i16::MAX as i32) as i16,
+                note,
+                vel: Self::PAD_VEL,
+                ch,
+                ..PhrEvt::default()
+            };
+            self.add_held_note(crnt_, estk, idx, &ev, start);
+        }
+    }
+    fn add_held_note(
+        &self,
+        crnt_: &CrntMsrTick,
+        estk: &mut ElapseStack,
+        idx: usize,
+        ev: &PhrEvt,
+        start: i32,
+    ) {
+        let (msr, tick) = self.gen_msr_tick(crnt_, start);
+        let nt: Rc<RefCell<dyn Elapse>> = Note::new(
+            idx as u32,
+            self.id.sid,
+            NoteParam::new(
+                estk,
+                ev,
+                self.keynote,
+                format!("pad / Pt:{} Lp:{}", &self.id.pid, &self.id.sid),
+                msr,
+                tick,
+                self.id.pid,
+            ),
+        );
+        estk.add_elapse(Rc::clone(&nt));
+    }
+    /// 根音を最低音(C3-B3)とし、構成音を上に積む(keynote を含まない音)
+    fn chord_out_voicing(cd: &ChordEvt) -> Vec<i16> {
+        if cd.root == NO_ROOT || cd.tbl == NO_TABLE {
+            return Vec::new();
+        }
+        let (tblptr, _upper) = txt2seq_cmps::get_table(cd.tbl as usize);
+        let root = DEFAULT_NOTE_NUMBER as i16 - 12 + ROOT2NTNUM[cd.root as usize].rem_euclid(12);
+        let mut notes: Vec<i16> = if tblptr.len() > 5 {
+            // Scale の場合は、三度ずつ重ねた音を構成音とする
+            tblptr.iter().step_by(2).take(4).map(|x| root + x).collect()
+        } else {
+            tblptr.iter().map(|x| root + x).collect()
+        };
+        notes.sort();
+        notes.dedup();
+        notes
+    }
+    /// 和音の構成音を、Part ごとの最低音から1オクターブ内に並べる(keynote を含まない音)
+    fn pad_voicing(&self, cd: &ChordEvt) -> Vec<i16> {
+        if cd.root == NO_ROOT || cd.tbl == NO_TABLE {
+            return Vec::new();
+        }
+        let (tblptr, _upper) = txt2seq_cmps::get_table(cd.tbl as usize);
+        let root = ROOT2NTNUM[cd.root as usize];
+        let lowest = DEFAULT_NOTE_NUMBER as i16 + 12 * (self.id.pid as i16 - 2);
+        let tones: Vec<i16> = if tblptr.len() > 5 {
+            // Scale の場合は、三度ずつ重ねた音を構成音とする
+            tblptr.iter().step_by(2).take(3).copied().collect()
+        } else {
+            tblptr.to_vec()
+        };
+        let mut notes: Vec<i16> = tones
+            .iter()
+            .map(|x| lowest + (x + root - lowest).rem_euclid(12))
+            .collect();
+        notes.sort();
+        notes.dedup();
+        notes
+    }
     fn _reset_note_translation(&mut self) { /*<<DoItLater>>*/
     }
 }
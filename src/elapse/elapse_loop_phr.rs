@@ -25,6 +25,7 @@ pub struct PhraseLoopParam {
     ana: Vec<AnaEvt>,
     whole_tick: i32,
     turnnote: i16,
+    tie: (bool, bool), // 0: 最後の音を次の Loop に繋ぐ, 1: 前の Loop から繋がっている
 }
 impl PhraseLoopParam {
     pub fn new(
@@ -34,6 +35,7 @@ impl PhraseLoopParam {
         ana: Vec<AnaEvt>,
         whole_tick: i32,
         turnnote: i16,
+        tie: (bool, bool),
     ) -> Self {
         Self {
             keynote,
@@ -42,6 +44,7 @@ impl PhraseLoopParam {
             ana,
             whole_tick,
             turnnote,
+            tie,
         }
     }
 }
@@ -96,7 +99,7 @@ impl PhraseLoop {
                 elps_type: ElapseType::TpPhraseLoop,
             },
             priority: PRI_PHR_LOOP,
-            phrase: prm.phr,
+            phrase: Self::tie_seam(prm.phr, prm.tie),
             analys: prm.ana,
             keynote: prm.keynote,
             play_counter: 0,
@@ -113,13 +116,169 @@ impl PhraseLoop {
             whole_tick: prm.whole_tick,
             destroy: false,
             first_msr_num: prm.msr,
-            next_msr: 0,
+            next_msr: prm.msr.min(0), // play from -1 の最初の小節でも、すぐ呼ばれるように
             next_tick: 0,
         }))
     }
+    /// Loopの途中(elapsed_tick)から再生する。拍の途中からの切り替えに使う
+    pub fn set_forward_tick(&mut self, crnt_: &CrntMsrTick, elapsed_tick: i32) {
+        let mut next_tick: i32;
+        let mut trace: usize = self.play_counter;
+        let phr = self.phrase.to_vec();
+        let max_ev = self.phrase.len();
+        loop {
+            if max_ev <= trace {
+                next_tick = END_OF_DATA; // means sequence finished
+                break;
+            }
+            next_tick = phr[trace].tick as i32;
+            if next_tick >= elapsed_tick {
+                break;
+            }
+            trace += 1;
+        }
+        self.play_counter = trace;
+        self.next_tick_in_phrase = next_tick;
+        let (msr, tick) = self.gen_msr_tick(crnt_, self.next_tick_in_phrase);
+        self.next_msr = msr;
+        self.next_tick = tick;
+        #[cfg(feature = "verbose")]
+        println!("### Forwarded to: {}, {}", self.next_msr, self.next_tick);
+    }
+    /// 現在の位置から、これから鳴る num 個の音を返す(Loop の繰り返しも含む)
+    /// chord_after: 今から何 tick 後かを渡すと、その時の和音 (root, table) を返す
+    ///              それぞれの音は、鳴る時の和音で変換する
+    pub fn get_upcoming(
+        &self,
+        crnt_: &CrntMsrTick,
+        chord_after: impl Fn(i32) -> (i16, i16),
+        num: usize,
+    ) -> Vec<UpcomingEv> {
+        let notes: Vec<&PhrEvt> = self
+            .phrase
+            .iter()
+            .filter(|ev| ev.mtype == TYPE_NOTE)
+            .collect();
+        if notes.is_empty() || self.whole_tick <= 0 {
+            return Vec::new();
+        }
+        let elapsed_tick = (crnt_.msr - self.first_msr_num) * crnt_.tick_for_onemsr + crnt_.tick;
+        let translated = |nt: i16, tick: i32| {
+            let (rt, ctbl) = chord_after(tick);
+            if rt == NO_ROOT || rt as usize >= ROOT2NTNUM.len() || ctbl == NO_TABLE {
+                nt
+            } else {
+                translate_note_com(ROOT2NTNUM[rt as usize], ctbl, nt)
+            }
+        };
+        let mut upcoming = Vec::new();
+        let mut lp = 0;
+        while upcoming.len() < num && lp < num {
+            for ev in notes.iter() {
+                let tick = ev.tick as i32 + lp as i32 * self.whole_tick - elapsed_tick;
+                if tick < 0 {
+                    continue;
+                }
+                let note = translated(ev.note, tick) + self.keynote as i16;
+                upcoming.push(UpcomingEv {
+                    note: note.clamp(0, 127) as u8,
+                    tick,
+                    vel: ev.vel.clamp(0, 127) as u8,
+                });
+                if upcoming.len() >= num {
+                    break;
+                }
+            }
+            lp += 1;
+        }
+        upcoming
+    }
     pub fn get_noped(&self) -> bool {
         self.noped
     }
+    /// 音の tick を grid に近づける(value: MSG_SET_QUANTIZE の形式)
+    /// 強さ 100% なら grid ちょうどに、50% なら grid との差の半分だけ動かす
+    pub fn quantize(mut phr: Vec<PhrEvt>, value: i16, whole_tick: i32) -> Vec<PhrEvt> {
+        if Self::quantize_off(value) {
+            return phr;
+        }
+        for ev in phr.iter_mut().filter(|x| x.mtype == TYPE_NOTE) {
+            ev.tick = Self::quantize_tick(ev.tick, value, whole_tick);
+        }
+        phr.sort_by_key(|x| x.tick);
+        phr
+    }
+    /// 音符ごとの解析データも、音と同じ tick に動かす
+    pub fn quantize_ana(mut ana: Vec<AnaEvt>, value: i16, whole_tick: i32) -> Vec<AnaEvt> {
+        if Self::quantize_off(value) {
+            return ana;
+        }
+        for ev in ana.iter_mut().filter(|x| x.mtype == TYPE_BEAT) {
+            ev.tick = Self::quantize_tick(ev.tick, value, whole_tick);
+        }
+        ana.sort_by_key(|x| x.tick);
+        ana
+    }
+    fn quantize_off(value: i16) -> bool {
+        value <= 0 || (value & 0xff) == 0 || (value >> 8) == 0
+    }
+    fn quantize_tick(tick: i16, value: i16, whole_tick: i32) -> i16 {
+        let strength = (value & 0xff).clamp(0, 100) as i32;
+        let grid = (value >> 8) as i32 * QUANTIZE_GRID;
+        let tick = tick as i32;
+        let target = ((tick + grid / 2) / grid) * grid;
+        let moved = tick + (target - tick) * strength / 100;
+        moved.clamp(0, (whole_tick - 1).max(0)) as i16
+    }
+    /// Loop の継ぎ目のタイ処理
+    /// 最後の音と最初の音が同じなら、最後の音を伸ばし、次の Loop の最初の音は鳴らさない
+    fn tie_seam(mut phr: Vec<PhrEvt>, tie: (bool, bool)) -> Vec<PhrEvt> {
+        let last_tick = match phr
+            .iter()
+            .filter(|x| x.mtype == TYPE_NOTE)
+            .map(|x| x.tick)
+            .max()
+        {
+            Some(t) if t > 0 => t,
+            _ => return phr, // 一音だけの Phrase は繋がない
+        };
+        let first: Vec<PhrEvt> = phr
+            .iter()
+            .filter(|x| x.mtype == TYPE_NOTE && x.tick == 0)
+            .cloned()
+            .collect();
+        let last: Vec<i16> = phr
+            .iter()
+            .filter(|x| x.mtype == TYPE_NOTE && x.tick == last_tick)
+            .map(|x| x.note)
+            .collect();
+        if tie.0 {
+            for ev in phr
+                .iter_mut()
+                .filter(|x| x.mtype == TYPE_NOTE && x.tick == last_tick)
+            {
+                if let Some(f) = first.iter().find(|f| f.note == ev.note) {
+                    ev.dur += f.dur;
+                }
+            }
+        }
+        if tie.1 {
+            phr.retain(|x| !(x.mtype == TYPE_NOTE && x.tick == 0 && last.contains(&x.note)));
+        }
+        phr
+    }
+    /// @first/@last/@every(n) の条件を、Loop の回数で判断する
+    fn match_cond(&self, estk: &ElapseStack, cond: i16) -> bool {
+        if cond == COND_ALWAYS {
+            return true;
+        }
+        let (cnt, last) = estk.loop_iteration(self.id.pid as usize);
+        match cond {
+            COND_FIRST => cnt == 0,
+            COND_LAST => last,
+            n => (cnt + 1) % (n as u32) == 0,
+        }
+    }
     fn generate_event(
         &mut self,
         crnt_: &CrntMsrTick,
@@ -139,25 +298,36 @@ impl PhraseLoop {
             if next_tick <= elapsed_tick {
                 let (msr, tick) = self.gen_msr_tick(crnt_, self.next_tick_in_phrase);
                 let tp = self.phrase[trace].mtype;
-                if tp == TYPE_NOTE {
+                if !self.match_cond(estk, phr[trace].cond)
+                    || estk.is_masked(self.id.pid as usize, next_tick / crnt_.tick_for_onemsr)
+                    || estk.get_chord_out(self.id.pid as usize).is_some()
+                {
+                    // Loop の回数の条件に合わない、mask された小節、または和音出力中なので鳴らさない
+                } else if tp == TYPE_NOTE {
                     if self.same_note_msr != msr || self.same_note_tick != tick {
                         // 設定されているタイミングが少しでも違えば、同タイミング重複音検出をクリア
                         self.same_note_stuck = Vec::new();
                         self.same_note_msr = msr;
                         self.same_note_tick = tick;
                     }
-                    self.note_event(estk, trace, phr[trace].clone(), next_tick, msr, tick);
-                } else if tp == TYPE_CLS || tp == TYPE_ARP {
+                    if phr[trace].orna != ORNA_NONE {
+                        self.ornament_event(crnt_, estk, trace, phr[trace].clone(), next_tick);
+                    } else {
+                        self.note_event(estk, trace, phr[trace].clone(), next_tick, msr, tick);
+                    }
+                } else if tp == TYPE_CLS || tp == TYPE_ARP || tp == TYPE_STRUM || tp == TYPE_EUCLID
+                {
                     let mut ptn = self.phrase[trace].clone();
                     while ptn.tick >= crnt_.tick_for_onemsr as i16 {
                         // pattern は１小節内で完結
                         ptn.tick -= crnt_.tick_for_onemsr as i16;
                     }
+                    let riser = estk.get_riser(self.id.pid as usize, msr);
                     let ptn: Rc<RefCell<dyn Elapse>> = DynamicPattern::new(
                         crnt_.msr as u32, //  read pointer
                         self.id.sid,      //  loop.sid -> note.pid
                         self.id.pid,      //  part
-                        self.keynote,
+                        (self.keynote as i16 + riser).clamp(0, 127) as u8,
                         msr,
                         ptn,
                         self.analys.to_vec(),
@@ -194,6 +364,19 @@ impl PhraseLoop {
         if rt != NO_ROOT || ctbl != NO_TABLE {
             (crnt_ev.note, deb_txt) = self.translate_note(rt, ctbl, ev, next_tick);
         }
+        //  構成音の分析と強調
+        let emph = estk.put_tone(
+            self.id.pid,
+            ROOT2NTNUM[rt as usize],
+            ctbl,
+            crnt_ev.note + self.keynote as i16,
+            self.keynote,
+        );
+        if emph != 0 {
+            crnt_ev.vel = (crnt_ev.vel + emph).clamp(1, 127);
+        }
+        //  riser による移調
+        crnt_ev.note += estk.get_riser(self.id.pid as usize, msr);
 
         //  同タイミング重複音を鳴らさない
         if self.same_note_stuck.iter().any(|x| *x == crnt_ev.note) {
@@ -209,6 +392,7 @@ impl PhraseLoop {
         } else if (self.staccato_rate as i16) != DEFAULT_ARTIC {
             let calc = (crnt_ev.dur as i32) * self.staccato_rate;
             crnt_ev.dur = (calc / DEFAULT_ARTIC as i32) as i16;
+            crnt_ev.artic = self.staccato_rate as i16; // keyswitch 用
         }
         //  Generate Note Struct
         let nt: Rc<RefCell<dyn Elapse>> = Note::new(
@@ -226,6 +410,121 @@ impl PhraseLoop {
         );
         estk.add_elapse(Rc::clone(&nt));
     }
+    /// trill/mordent/turn を、演奏時のテンポと和音に合わせて展開する
+    fn ornament_event(
+        &mut self,
+        crnt_: &CrntMsrTick,
+        estk: &mut ElapseStack,
+        trace: usize,
+        ev: PhrEvt,
+        next_tick: i32,
+    ) {
+        let (mut rt, mut ctbl) = (NO_ROOT, NO_TABLE);
+        if let Some(cmps) = estk.get_cmps(self.id.pid as usize) {
+            (rt, ctbl) = cmps.borrow().get_chord();
+        }
+        let mut main_ev = ev.clone();
+        if rt != NO_ROOT || ctbl != NO_TABLE {
+            (main_ev.note, _) = self.translate_note(rt, ctbl, ev.clone(), next_tick);
+        }
+        let bpm = estk.tg().get_bpm();
+        let (msr, _) = self.gen_msr_tick(crnt_, next_tick);
+        let riser = estk.get_riser(self.id.pid as usize, msr);
+        let scale = Self::ornament_scale(rt, ctbl);
+        for (ofs, note, dur) in Self::expand_ornament(&main_ev, bpm, &scale) {
+            let orn_ev = PhrEvt {
+                note: note + riser,
+                dur: dur as i16,
+                orna: ORNA_NONE,
+                ..main_ev.clone()
+            };
+            let (msr, tick) = self.gen_msr_tick(crnt_, next_tick + ofs);
+            let nt: Rc<RefCell<dyn Elapse>> = Note::new(
+                trace as u32, //  read pointer
+                self.id.sid,  //  loop.sid -> note.pid
+                NoteParam::new(
+                    estk,
+                    &orn_ev,
+                    self.keynote,
+                    format!("orna / Pt:{} Lp:{}", &self.id.pid, &self.id.sid),
+                    msr,
+                    tick,
+                    self.id.pid,
+                ),
+            );
+            estk.add_elapse(Rc::clone(&nt));
+        }
+    }
+    /// 装飾音の展開 (開始 tick, note, duration)
+    /// 装飾音が ORNA_MIN_TICK より短くなる音符は、装飾しない
+    fn expand_ornament(ev: &PhrEvt, bpm: i16, scale: &[i16]) -> Vec<(i32, i16, i32)> {
+        const ORNA_MIN_TICK: i32 = DEFAULT_TICK_FOR_QUARTER / 16;
+        let dur = ev.dur as i32;
+        let upper = Self::scale_neighbor(ev.note, true, scale);
+        let lower = Self::scale_neighbor(ev.note, false, scale);
+        // テンポが速い時は、trill を十六分音符にする
+        let step = if bpm > 120 {
+            DEFAULT_TICK_FOR_QUARTER / 4
+        } else {
+            DEFAULT_TICK_FOR_QUARTER / 8
+        };
+        let mut orn = Vec::new();
+        match ev.orna {
+            ORNA_TRILL => {
+                let num = (dur / step).max(1);
+                for i in 0..num {
+                    let nt = if i % 2 == 0 { ev.note } else { upper };
+                    let each = if i == num - 1 { dur - step * i } else { step };
+                    orn.push((step * i, nt, each));
+                }
+            }
+            ORNA_MORDENT if dur / 3 >= ORNA_MIN_TICK => {
+                let step = step.min(dur / 3);
+                orn.push((0, ev.note, step));
+                orn.push((step, upper, step));
+                orn.push((step * 2, ev.note, dur - step * 2));
+            }
+            ORNA_TURN if dur / 4 >= ORNA_MIN_TICK => {
+                let qdur = dur / 4;
+                orn.push((0, upper, qdur));
+                orn.push((qdur, ev.note, qdur));
+                orn.push((qdur * 2, lower, qdur));
+                orn.push((qdur * 3, ev.note, dur - qdur * 3));
+            }
+            _ => orn.push((0, ev.note, dur)),
+        }
+        orn
+    }
+    /// 装飾音に使う音階(keynote からの音程)
+    /// 今の和音が Scale ならその音階、それ以外は長音階とする
+    fn ornament_scale(rt: i16, ctbl: i16) -> Vec<i16> {
+        const MAJOR_SCALE: [i16; 7] = [0, 2, 4, 5, 7, 9, 11];
+        if ctbl == NO_TABLE {
+            return MAJOR_SCALE.to_vec();
+        }
+        let root = ROOT2NTNUM[rt as usize];
+        let (tbl, _) = txt2seq_cmps::get_table(ctbl as usize);
+        let (movable_scale, para_note) = txt2seq_cmps::is_movable_scale(ctbl, root);
+        let base = if movable_scale {
+            para_note
+        } else if tbl.len() > 5 && tbl.len() < 12 {
+            root
+        } else {
+            return MAJOR_SCALE.to_vec();
+        };
+        tbl.iter().map(|x| (x + base).rem_euclid(12)).collect()
+    }
+    /// 音階の上下の隣の音
+    fn scale_neighbor(note: i16, up: bool, scale: &[i16]) -> i16 {
+        let mut nt = note;
+        for _ in 0..12 {
+            nt += if up { 1 } else { -1 };
+            if scale.contains(&nt.rem_euclid(12)) {
+                return nt;
+            }
+        }
+        note
+    }
     fn translate_note(&mut self, rt: i16, ctbl: i16, ev: PhrEvt, next_tick: i32) -> (i16, String) {
         let deb_txt: String;
         let trans_note: i16;
@@ -353,28 +652,6 @@ impl Loop for PhraseLoop {
     }
     /// Loopの途中から再生するための小節数を設定
     fn set_forward(&mut self, crnt_: &CrntMsrTick, elapsed_msr: i32) {
-        let elapsed_tick = elapsed_msr * crnt_.tick_for_onemsr;
-        let mut next_tick: i32;
-        let mut trace: usize = self.play_counter;
-        let phr = self.phrase.to_vec();
-        let max_ev = self.phrase.len();
-        loop {
-            if max_ev <= trace {
-                next_tick = END_OF_DATA; // means sequence finished
-                break;
-            }
-            next_tick = phr[trace].tick as i32;
-            if next_tick >= elapsed_tick {
-                break;
-            }
-            trace += 1;
-        }
-        self.play_counter = trace;
-        self.next_tick_in_phrase = next_tick;
-        let (msr, tick) = self.gen_msr_tick(crnt_, self.next_tick_in_phrase);
-        self.next_msr = msr;
-        self.next_tick = tick;
-        #[cfg(feature = "verbose")]
-        println!("### Forwarded to: {}, {}", self.next_msr, self.next_tick);
+        self.set_forward_tick(crnt_, elapsed_msr * crnt_.tick_for_onemsr);
     }
 }
@@ -3,8 +3,8 @@
 //  Released under the MIT license
 //  https://opensource.org/licenses/mit-license.php
 //
-use rand;
 use rand::prelude::Distribution;
+use rand::rngs::StdRng;
 use rand_distr::Normal;
 use std::cell::RefCell;
 use std::rc::Rc;
@@ -61,6 +61,10 @@ pub struct Note {
     next_msr: i32,
     next_tick: i32,
     part: u32,
+    ch: u8,         // status の下位4bit (0: ch.1)
+    ch_fixed: bool, // Phrase で ch が指定されている
+    sustain: bool,  // pad の持続音(音価を自動で縮めない)
+    artic: i16,
     _deb_txt: String,
 }
 impl Note {
@@ -82,7 +86,7 @@ impl Note {
                 sid,
                 elps_type: ElapseType::TpNote,
             },
-            priority: PRI_NOTE,
+            priority: PRI_NOTE + prm._estk.doubling_rank(prm.part), // 優先するパートの音から処理する
             note_num: prm.ev.note as u8,
             velocity: prm.ev.vel as u8,
             duration: prm.ev.dur as i32,
@@ -93,19 +97,66 @@ impl Note {
             next_msr: prm.msr,
             next_tick: prm.tick,
             part: prm.part,
+            ch: if prm.ev.ch > 0 {
+                (prm.ev.ch - 1) as u8
+            } else {
+                0
+            },
+            ch_fixed: prm.ev.ch > 0,
+            sustain: prm.ev.mtype == TYPE_PAD,
+            artic: prm.ev.artic,
             _deb_txt: prm._deb_txt,
         }))
     }
     fn note_on(&mut self, estk: &mut ElapseStack) -> bool {
-        let num = self.note_num + self.keynote;
-        let bpm = estk.tg().get_bpm();
+        if estk.is_muted(self.part) {
+            // mute 中の Part は発音しない
+            return false;
+        }
+        let num = match estk.check_doubling(self.part, self.note_num + self.keynote) {
+            Some(nt) => nt,
+            None => return false, // 他のパートと同じ音なので省く
+        };
+        // rit./accel. 中は、その時点の実際のテンポで音の長さを決める
+        let bpm = estk.tg().get_real_bpm();
         let beat = estk.tg().get_meter();
-        self.duration = Self::auto_duration(bpm, beat, self.duration);
+        let written_dur = self.duration;
+        if !self.sustain {
+            self.duration = Self::auto_duration(bpm, beat, self.duration);
+        }
         if Note::note_limit_available(num, MIN_NOTE_NUMBER, MAX_NOTE_NUMBER) {
             self.real_note = num;
-            let vel = self.random_velocity(self.velocity);
-            estk.inc_key_map(num, vel, self.part as u8);
-            estk.midi_out(0x90, self.real_note, vel);
+            let vel = if estk.humanize() {
+                Self::random_velocity(estk.rng(self.part), self.velocity)
+            } else {
+                self.velocity
+            };
+            let vel = estk.ramp_vel(self.part, self.next_msr, self.next_tick, vel);
+            let vel = estk.limit_vel(self.part, vel);
+            let vel = estk.fade_vel(vel); // velocity の処理の最後
+            let played = PhrEvt {
+                mtype: TYPE_NOTE,
+                dur: written_dur as i16,
+                note: num as i16 - self.keynote as i16,
+                vel: vel as i16,
+                ..Default::default()
+            };
+            estk.rec_freeze(self.part, self.next_msr, self.next_tick, played);
+            estk.doubling_on(self.part, num);
+            if !self.ch_fixed {
+                self.ch = estk.split_ch(self.part, self.real_note, self.ch);
+            }
+            self.ch = estk.tuning_out(self.part, self.real_note, self.keynote, self.ch);
+            estk.inc_key_map(num, vel, self.part as u8, self.ch);
+            estk.keysw_out(self.part, self.artic, self.ch);
+            estk.vel_cc_out(self.part, vel);
+            estk.pan_out(self.part, self.real_note, self.ch);
+            estk.midi_out_from(
+                Some(self.part as usize),
+                0x90 | self.ch,
+                self.real_note,
+                vel,
+            );
             #[cfg(feature = "verbose")]
             println!(
                 "On: N{} V{} D{} Trns: {}, ",
@@ -121,9 +172,11 @@ impl Note {
         self.destroy = true;
         self.next_msr = FULL;
         // midi note off
-        let snk = estk.dec_key_map(self.real_note);
+        let snk = estk.dec_key_map(self.real_note, self.ch);
+        estk.doubling_off(self.part, self.real_note);
+        estk.tuning_off(self.part, self.ch, self.real_note);
         if snk == stack_elapse::SameKeyState::Last {
-            estk.midi_out(0x90, self.real_note, 0);
+            estk.midi_out_from(Some(self.part as usize), 0x90 | self.ch, self.real_note, 0);
             #[cfg(feature = "verbose")]
             println!("Off: N{}, ", self.real_note);
         }
@@ -131,11 +184,10 @@ impl Note {
     fn note_limit_available(num: u8, min_value: u8, max_value: u8) -> bool {
         (min_value..=max_value).contains(&num)
     }
-    fn random_velocity(&self, input_vel: u8) -> u8 {
-        let mut rng = rand::rng();
+    fn random_velocity(rng: &mut StdRng, input_vel: u8) -> u8 {
         // std_dev: 標準偏差
         let dist = Normal::<f64>::new(0.0, 3.0).unwrap();
-        let diff = dist.sample(&mut rng) as i32;
+        let diff = dist.sample(rng) as i32;
         if input_vel as i32 + diff > 0 && input_vel as i32 + diff < 128 {
             (input_vel as i32 + diff) as u8
         } else {
@@ -228,6 +280,7 @@ pub struct Damper {
     id: ElapseId,
     priority: u32,
     position: u8,
+    release: u8, // 終わりに送る値
     duration: i32,
     damper_started: bool,
     destroy: bool,
@@ -251,6 +304,7 @@ impl Damper {
             },
             priority: PRI_NOTE,
             position: ev.position as u8,
+            release: ev.release.clamp(0, 127) as u8,
             duration: ev.dur as i32,
             damper_started: false,
             destroy: false,
@@ -264,15 +318,15 @@ impl Damper {
         } else {
             self.position
         };
-        estk.midi_out(0xb0, 0x40, pos);
+        estk.midi_out_from(Some(DAMPER_PEDAL_PART), 0xb0, 0x40, pos);
         #[cfg(feature = "verbose")]
         println!("Damper-On: {}", self.position);
     }
     fn damper_off(&mut self, estk: &mut ElapseStack) {
         self.destroy = true;
         self.next_msr = FULL;
-        // midi damper off
-        estk.midi_out(0xb0, 0x40, 0);
+        // midi damper off (partial change なら、少しだけ上げる)
+        estk.midi_out_from(Some(DAMPER_PEDAL_PART), 0xb0, 0x40, self.release);
         #[cfg(feature = "verbose")]
         println!("Damper-Off");
     }
@@ -295,12 +349,14 @@ impl Elapse for Damper {
     /// User による stop 時にコールされる
     fn stop(&mut self, estk: &mut ElapseStack) {
         if self.damper_started {
+            self.release = 0;
             self.damper_off(estk);
         }
     }
     /// 再生データを消去
     fn clear(&mut self, estk: &mut ElapseStack) {
         if self.damper_started {
+            self.release = 0;
             self.damper_off(estk);
         }
         self.destroy = true;
@@ -10,8 +10,11 @@ use std::rc::Rc;
 use super::elapse_base::*;
 use super::elapse_loop_cmp::*;
 use super::elapse_loop_phr::*;
+use super::note_translation::ROOT2NTNUM;
+use super::punch_rec::PunchNote;
 use super::stack_elapse::ElapseStack;
 use super::tickgen::CrntMsrTick;
+use super::trigger;
 use crate::elapse::elapse_flow::Flow;
 use crate::lpnlib::*;
 
@@ -20,6 +23,7 @@ pub struct PartBasicPrm {
     pub part_num: u32,
     pub keynote: u8,
     pub sync_flag: bool,
+    pub has_cmps: bool, // Composition が再生中
 }
 
 //*******************************************************************
@@ -36,8 +40,20 @@ struct PhrLoopManager {
     vari_reserve: usize, // 0:no rsv, 1-9: rsv
     state_reserve: bool,
     turnnote: i16,
+    tie_in: bool,                    // 前の Loop の最後の音から、タイで繋がっている
+    global_phase: bool, // 新しい Phrase を、小節0から鳴っていたかのような位置から再生する
+    loop_cnt: u32,      // 同じ Phrase を繰り返した回数(0:一回目)
+    mask: Vec<bool>,    // Loop の小節ごとに鳴らすか(空なら全て鳴らす)
+    mask_reserve: Option<Vec<bool>>, // 次の Loop の頭から有効にする mask
+    evolve_cnt: i16,    // evolve を reset してから生成した Loop の数
+    quantize: Option<i16>, // MSG_SETPT_QUANTIZE の値(None:全体の設定に従う)
+    speed: (i32, i32),  // 全体の clock に対する速さの倍率(分子, 分母) (2,1):double-time
+    filler: i16,        // Phrase が無い時に鳴らす伴奏 FILLER_*
 }
 impl PhrLoopManager {
+    const MAX_SPEED_REPEAT: i32 = 4;
+    const FILLER_VEL: i16 = 64;
+
     pub fn new() -> Self {
         Self {
             first_msr_num: 0,
@@ -50,12 +66,31 @@ impl PhrLoopManager {
             vari_reserve: 0,
             state_reserve: false,
             turnnote: DEFAULT_TURNNOTE,
+            tie_in: false,
+            global_phase: false,
+            loop_cnt: 0,
+            mask: Vec::new(),
+            mask_reserve: None,
+            evolve_cnt: 0,
+            quantize: None,
+            speed: (1, 1),
+            filler: FILLER_OFF,
         }
     }
     pub fn start(&mut self) {
         self.clear_phr_prm();
         self.state_reserve = true;
     }
+    /// 再生中の Phrase が、Normal/Variation/Measure のどれか
+    fn active_vari(&self) -> PhraseAs {
+        self.new_data_stock
+            .get(self.active_phr)
+            .map_or(PhraseAs::Normal, |dt| dt.vari.clone())
+    }
+    /// 一小節の弱起で始まる Phrase か
+    fn has_pickup(&self) -> bool {
+        self.new_data_stock[0].auftakt == 1
+    }
     /// Phrase Loop の処理 (Phrase Loop の生成、更新)
     /// 小節先頭でコールされる
     pub fn process(&mut self, crnt_: &CrntMsrTick, estk: &mut ElapseStack, pbp: PartBasicPrm) {
@@ -78,7 +113,10 @@ impl PhrLoopManager {
         } else if self.state_reserve {
             // User による Phrase 入力があった場合
             self.active_phr = 0;
-            if crnt_.msr == 0 {
+            if self.global_phase {
+                // 長さの違う Phrase でも、全体の位相を保って次の小節から差し替える
+                self.proc_phase_loop(crnt_, estk, pbp);
+            } else if crnt_.msr == 0 {
                 // 今回 start したとき
                 self.proc_new_loop_by_evt(crnt_, estk, pbp);
             } else if self.max_loop_msr == 0 {
@@ -94,7 +132,7 @@ impl PhrLoopManager {
                 // 現在の Loop Obj が終了していない時
                 // 現在の Phrase より新しい Phrase の whole_tick が大きい場合、
                 // 新しい Phrase を早送りして更新する
-                if self.new_data_stock[0].whole_tick as i32 >= self.whole_tick {
+                if self.speed_tick(0, crnt_.tick_for_onemsr).0 >= self.whole_tick {
                     self.proc_forward_by_evt(crnt_, estk, pbp);
                 }
             }
@@ -177,11 +215,263 @@ impl PhrLoopManager {
     pub fn set_turnnote(&mut self, tn: i16) {
         self.turnnote = tn;
     }
+    /// mask は次の Loop の頭から有効、Loop が無ければすぐ有効
+    pub fn set_mask(&mut self, mask: Vec<bool>) {
+        if self.loop_phrase.is_some() {
+            self.mask_reserve = Some(mask);
+        } else {
+            self.mask = mask;
+        }
+    }
+    pub fn is_masked(&self, msr_in_loop: i32) -> bool {
+        if self.mask.is_empty() {
+            false
+        } else {
+            !self.mask[msr_in_loop as usize % self.mask.len()]
+        }
+    }
+    fn update_mask(&mut self) {
+        if let Some(mask) = self.mask_reserve.take() {
+            self.mask = mask;
+        }
+    }
+    pub fn set_quantize(&mut self, value: i16) {
+        self.quantize = (value != QUANTIZE_GLOBAL).then_some(value);
+    }
+    /// 速さの倍率は、次の Loop の頭から有効
+    /// 倍率で伸ばした Loop の長さが i16 に収まらない Phrase があれば、変えずに false を返す
+    pub fn set_speed(&mut self, speed: (i32, i32), tick_for_onemsr: i32) -> bool {
+        let fits = self.new_data_stock.iter().all(|dt| {
+            Self::speed_len(dt.whole_tick as i32, speed, tick_for_onemsr).0 <= i16::MAX as i32
+        });
+        if !fits {
+            return false;
+        }
+        if speed != self.speed {
+            self.speed = speed;
+            self.state_reserve = true;
+        }
+        true
+    }
+    pub fn set_filler(&mut self, filler: i16) {
+        self.filler = filler;
+    }
+    /// 速さの倍率を反映した、i 番目の Phrase の Loop の長さ(tick)と、一回分の長さ
+    /// 倍率で小節線からずれる時は、小節線で揃うまで(最大 MAX_SPEED_REPEAT 回)繰り返す長さにする
+    fn speed_tick(&self, i: usize, tick_for_onemsr: i32) -> (i32, i32) {
+        Self::speed_len(
+            self.new_data_stock[i].whole_tick as i32,
+            self.speed,
+            tick_for_onemsr,
+        )
+    }
+    fn speed_len(whole_tick: i32, speed: (i32, i32), tick_for_onemsr: i32) -> (i32, i32) {
+        if speed == (1, 1) || whole_tick == 0 {
+            return (whole_tick, whole_tick);
+        }
+        let one = whole_tick * speed.1 / speed.0;
+        let rpt = (1..=Self::MAX_SPEED_REPEAT)
+            .find(|n| (one * n) % tick_for_onemsr.max(1) == 0)
+            .unwrap_or(1);
+        (one * rpt, one)
+    }
+    /// 速さの倍率で tick/duration を伸び縮みさせる
+    fn stretch(&self, tick: i16) -> i16 {
+        (tick as i32 * self.speed.1 / self.speed.0).min(i16::MAX as i32) as i16
+    }
+    /// 伸び縮みさせたイベントを、Loop の長さまで繰り返す
+    /// scale: イベントの tick を伸び縮みさせ、二つ目の引数の tick だけずらす
+    fn speed_evts<T: Clone>(
+        &self,
+        evts: &[T],
+        tick_for_onemsr: i32,
+        scale: impl Fn(&mut T, i16),
+    ) -> Vec<T> {
+        let (whole, one) = self.speed_tick(self.active_phr, tick_for_onemsr);
+        if self.speed == (1, 1) || one == 0 {
+            return evts.to_vec();
+        }
+        let mut rated = Vec::new();
+        for n in 0..whole / one {
+            for ev in evts.iter() {
+                let mut ev = ev.clone();
+                scale(&mut ev, (one * n) as i16);
+                rated.push(ev);
+            }
+        }
+        rated
+    }
+    /// 次の Loop で再生する Phrase(速さの倍率と quantize を反映する)
+    fn loop_evts(&self, estk: &ElapseStack, tick_for_onemsr: i32) -> Vec<PhrEvt> {
+        let value = self.quantize.unwrap_or(estk.get_quantize());
+        let evts = self.speed_evts(
+            &self.new_data_stock[self.active_phr].evts,
+            tick_for_onemsr,
+            |ev, ofs| {
+                ev.tick = self.stretch(ev.tick) + ofs;
+                ev.dur = self.stretch(ev.dur);
+                ev.each_dur = self.stretch(ev.each_dur);
+            },
+        );
+        PhraseLoop::quantize(evts, value, self.whole_tick)
+    }
+    /// 次の Loop の解析データ(Phrase と同じく、速さの倍率と quantize を反映する)
+    fn loop_ana(&self, estk: &ElapseStack, tick_for_onemsr: i32) -> Vec<AnaEvt> {
+        let value = self.quantize.unwrap_or(estk.get_quantize());
+        let ana = self.speed_evts(
+            &self.new_data_stock[self.active_phr].ana,
+            tick_for_onemsr,
+            |ev, ofs| {
+                ev.tick = self.stretch(ev.tick) + ofs;
+                ev.dur = self.stretch(ev.dur);
+            },
+        );
+        PhraseLoop::quantize_ana(ana, value, self.whole_tick)
+    }
+    /// evolve で増やす声部の数(reset 後の最初の Loop は 0)
+    pub fn evolve_level(&self, cap: i16) -> i16 {
+        (self.evolve_cnt - 1).clamp(0, cap)
+    }
+    pub fn reset_evolve(&mut self) {
+        self.evolve_cnt = 0;
+    }
+    /// 今の Loop の先頭小節と、Loop 頭からの tick、Loop の長さ(tick)
+    pub fn loop_tick(&self, msr: i32, tick: i32, tick_for_onemsr: i32) -> Option<(i32, i32, i32)> {
+        if self.loop_phrase.is_none() || self.max_loop_msr == 0 {
+            return None;
+        }
+        let msr_in_loop = (msr - self.first_msr_num).rem_euclid(self.max_loop_msr);
+        Some((
+            msr - msr_in_loop,
+            msr_in_loop * tick_for_onemsr + tick,
+            self.whole_tick,
+        ))
+    }
+    /// punch 区間 [in, out) の音を rec に差し替えた Normal Phrase の音と、その長さ
+    /// rec: (msr, tick, note(key を含まない), dur, vel)
+    fn merge_punch(
+        &self,
+        region: (i32, i32),
+        rec: &[(i32, i32, i16, i32, u8)],
+        tick_for_onemsr: i32,
+    ) -> Option<(Vec<PhrEvt>, i32)> {
+        let phr = &self.new_data_stock[0];
+        if phr.evts.iter().any(|e| e.mtype == TYPE_INFO) {
+            return None; // 繰り返しのある Phrase は、位置が合わない
+        }
+        let (_, in_tick, whole) = self.loop_tick(region.0, 0, tick_for_onemsr)?;
+        let out_tick = (in_tick + (region.1 - region.0) * tick_for_onemsr).min(whole);
+        let mut evts: Vec<PhrEvt> = phr
+            .evts
+            .iter()
+            .filter(|e| e.mtype != TYPE_NOTE || !(in_tick..out_tick).contains(&(e.tick as i32)))
+            .cloned()
+            .collect();
+        for (msr, tick, note, dur, vel) in rec.iter() {
+            if let Some((_, t, _)) = self.loop_tick(*msr, *tick, tick_for_onemsr) {
+                evts.push(PhrEvt {
+                    mtype: TYPE_NOTE,
+                    tick: t as i16,
+                    dur: (*dur).min(out_tick - t).max(1) as i16,
+                    note: *note,
+                    vel: *vel as i16,
+                    ..Default::default()
+                });
+            }
+        }
+        evts.sort_by_key(|e| e.tick);
+        Some((evts, whole))
+    }
+    /// 今の Loop が何回目か(0origin)と、Loop 内の位置(0.0-1.0)
+    pub fn loop_position(&self, msr: i32, tick: i32, tick_for_onemsr: i32) -> Option<(u32, f32)> {
+        if self.loop_phrase.is_none() || self.max_loop_msr == 0 {
+            return None;
+        }
+        let msr_in_loop = (msr - self.first_msr_num).rem_euclid(self.max_loop_msr);
+        let whole = (self.max_loop_msr * tick_for_onemsr) as f32;
+        Some((
+            self.loop_cnt,
+            (msr_in_loop * tick_for_onemsr + tick) as f32 / whole,
+        ))
+    }
+    /// export setup 用: Phrase の長さ(小節数)と、入力されている Variation
+    fn setup_summary(&self, tick_for_onemsr: i32) -> Option<String> {
+        let phr = &self.new_data_stock[0];
+        let mut txt = if phr.evts.is_empty() {
+            "-".to_string()
+        } else {
+            format!(
+                "{} msr{}",
+                (phr.whole_tick as i32 + tick_for_onemsr - 1) / tick_for_onemsr.max(1),
+                if phr.do_loop { "" } else { " (no loop)" }
+            )
+        };
+        let varis: Vec<String> = self
+            .new_data_stock
+            .iter()
+            .filter_map(|x| match x.vari {
+                PhraseAs::Variation(v) => Some(format!("@{}", v)),
+                PhraseAs::Measure(m) => Some(format!("@msr{}", m)),
+                PhraseAs::Normal => None,
+            })
+            .collect();
+        if !varis.is_empty() {
+            txt += &format!(", vari: {}", varis.join(" "));
+        }
+        (txt != "-").then_some(txt)
+    }
+    /// 今の Loop が何回目か(0origin)と、次に別の Phrase に移る最後の回か
+    pub fn loop_iteration(&self) -> (u32, bool) {
+        let next_msr = self.first_msr_num + self.max_loop_msr;
+        let last = self.state_reserve
+            || self.vari_reserve != 0
+            || !self.new_data_stock[self.active_phr].do_loop
+            || self
+                .new_data_stock
+                .iter()
+                .any(|phr| phr.vari == PhraseAs::Measure(next_msr as usize));
+        (self.loop_cnt, last)
+    }
     pub fn reserve_vari(&mut self, vari_num: usize) {
         if vari_num != 0 {
             self.vari_reserve = vari_num; // 1-9
         }
     }
+    /// 拍頭で Variation に切り替え、今の Loop 内の位置に相当する所から途中再生する
+    pub fn switch_vari_at_beat(
+        &mut self,
+        vari_num: usize,
+        crnt_: &CrntMsrTick,
+        estk: &mut ElapseStack,
+        pbp: PartBasicPrm,
+    ) {
+        let i = match self.exist_vari_phr(vari_num) {
+            Some(i) => i,
+            None => return,
+        };
+        let tick_for_onemsr = crnt_.tick_for_onemsr;
+        let whole_tick = self.speed_tick(i, tick_for_onemsr).0;
+        if whole_tick == 0 || self.new_data_stock[i].evts.is_empty() {
+            return;
+        }
+        // 新しい Variation の中で、今の Loop と同じ小節目を探す
+        let vari_msr = (whole_tick + tick_for_onemsr - 1) / tick_for_onemsr;
+        let msr_in_loop = if self.max_loop_msr != 0 {
+            (crnt_.msr - self.first_msr_num).rem_euclid(vari_msr)
+        } else {
+            0
+        };
+        self.del_loop_phrase();
+        self.active_phr = i;
+        self.vari_reserve = 0;
+        self.tie_in = false;
+        self.first_msr_num = crnt_.msr - msr_in_loop;
+        self.gen_new_loop((self.first_msr_num, tick_for_onemsr), estk, pbp);
+        if let Some(phr) = self.loop_phrase.as_mut() {
+            let elapsed_tick = msr_in_loop * tick_for_onemsr + crnt_.tick;
+            phr.borrow_mut().set_forward_tick(crnt_, elapsed_tick);
+        }
+    }
     fn exists_same_vari(&self, vari: PhraseAs) -> Option<usize> {
         let mut num = MAX_VARIATION;
         for (i, phr) in self.new_data_stock.iter().enumerate() {
@@ -237,17 +527,20 @@ impl PhrLoopManager {
     ) -> bool {
         let mut phr = &self.new_data_stock[0]; // Normal Phrase
 
-        let auftakt_cond_vari = || -> bool {
+        let auftakt_cond_vari = |ph: &PhrData| -> bool {
             // variation 再生時の弱起auftaktの条件
+            let pickup = ph.auftakt as i32;
             self.max_loop_msr != 0 &&
-            (crnt_.msr - self.first_msr_num)%(self.max_loop_msr) < self.max_loop_msr && // 残り１小節以上
-            phr.whole_tick as i32 >= crnt_.tick_for_onemsr*2 // 新しい Phrase が２小節以上
+            (crnt_.msr - self.first_msr_num)%(self.max_loop_msr) <= self.max_loop_msr - pickup && // 残り弱起の小節数以上
+            ph.whole_tick as i32 >= crnt_.tick_for_onemsr*(pickup + 1) // 新しい Phrase が弱起の小節数+1小節以上
         };
-        let auftakt_cond = || -> bool {
+        let auftakt_cond = |ph: &PhrData| -> bool {
             // 通常の弱起auftaktの条件
+            let pickup = ph.auftakt as i32;
             self.max_loop_msr != 0
-                && (crnt_.msr - self.first_msr_num) % (self.max_loop_msr) == self.max_loop_msr - 1
-                && phr.whole_tick as i32 >= crnt_.tick_for_onemsr * 2 // 新しい Phrase が２小節以上
+                && (crnt_.msr - self.first_msr_num) % (self.max_loop_msr)
+                    == self.max_loop_msr - pickup
+                && ph.whole_tick as i32 >= crnt_.tick_for_onemsr * (pickup + 1) // 新しい Phrase が弱起の小節数+1小節以上
         };
 
         if self.vari_reserve != 0 {
@@ -257,8 +550,8 @@ impl PhrLoopManager {
                 if phr.auftakt == 0 {
                     return false;
                 }
-                // variation : 今再生している Phrase が残り１小節以上
-                if auftakt_cond_vari() {
+                // variation : 今再生している Phrase が残り弱起の小節数以上
+                if auftakt_cond_vari(phr) {
                     let prm = (crnt_.msr, crnt_.tick_for_onemsr);
                     self.active_phr = self.vari_reserve;
                     self.new_loop(prm, estk, pbp);
@@ -267,11 +560,11 @@ impl PhrLoopManager {
             }
             self.vari_reserve = 0;
         } else if self.state_reserve {
-            // User input (Normal Phrase) : 今再生している Phrase が残り１小節
+            // User input (Normal Phrase) : 今再生している Phrase が残り弱起の小節数
             if phr.auftakt == 0 {
                 return false;
             }
-            if auftakt_cond() {
+            if auftakt_cond(phr) {
                 self.state_reserve = false;
                 let prm = (crnt_.msr, crnt_.tick_for_onemsr);
                 self.vari_reserve = 0;
@@ -279,7 +572,7 @@ impl PhrLoopManager {
                 return true;
             }
         } else {
-            // repeat : 今再生している Phrase が残り１小節 かつ loop設定の場合
+            // repeat : 今再生している Phrase が残り弱起の小節数 かつ loop設定の場合
             if self.new_data_stock.len() <= self.active_phr {
                 return false;
             }
@@ -287,15 +580,39 @@ impl PhrLoopManager {
             if phr.auftakt == 0 {
                 return false;
             }
-            if auftakt_cond() && phr.do_loop {
+            if auftakt_cond(phr) && phr.do_loop {
                 let prm = (crnt_.msr, crnt_.tick_for_onemsr);
+                let cnt = self.loop_cnt + 1;
                 self.vari_reserve = 0;
                 self.new_loop(prm, estk, pbp);
+                self.loop_cnt = cnt;
                 return true;
             }
         }
         false
     }
+    fn proc_phase_loop(&mut self, crnt_: &CrntMsrTick, estk: &mut ElapseStack, pbp: PartBasicPrm) {
+        self.state_reserve = false;
+        let tick_for_onemsr = crnt_.tick_for_onemsr;
+        let whole_tick = self.speed_tick(0, tick_for_onemsr).0;
+        if whole_tick == 0 || self.new_data_stock[0].evts.is_empty() {
+            let prm = (crnt_.msr, tick_for_onemsr);
+            self.new_loop(prm, estk, pbp);
+            return;
+        }
+        let max_msr = (whole_tick + tick_for_onemsr - 1) / tick_for_onemsr;
+        let msr_in_loop = crnt_.msr.rem_euclid(max_msr);
+        self.del_loop_phrase();
+        self.tie_in = false;
+        self.vari_reserve = 0;
+        self.first_msr_num = crnt_.msr - msr_in_loop;
+        self.gen_new_loop((self.first_msr_num, tick_for_onemsr), estk, pbp);
+        if msr_in_loop != 0 {
+            if let Some(phr) = self.loop_phrase.as_mut() {
+                phr.borrow_mut().set_forward(crnt_, msr_in_loop);
+            }
+        }
+    }
     fn proc_new_loop_by_evt(
         &mut self,
         crnt_: &CrntMsrTick,
@@ -313,7 +630,10 @@ impl PhrLoopManager {
         pbp: PartBasicPrm,
     ) {
         let prm = (crnt_.msr, crnt_.tick_for_onemsr);
+        let cnt = self.loop_cnt + 1;
+        self.tie_in = self.new_data_stock[self.active_phr].tie_loop;
         self.new_loop(prm, estk, pbp);
+        self.loop_cnt = cnt;
     }
     fn proc_replace_loop(
         &mut self,
@@ -336,8 +656,8 @@ impl PhrLoopManager {
         self.del_loop_phrase();
 
         // その時の beat 情報で、whole_tick を loop_measure に換算
-        self.whole_tick = self.new_data_stock[self.active_phr].whole_tick as i32;
         let tick_for_onemsr = crnt_.tick_for_onemsr;
+        self.whole_tick = self.speed_tick(self.active_phr, tick_for_onemsr).0;
         let plus_one = if self.whole_tick % tick_for_onemsr == 0 {
             0
         } else {
@@ -347,6 +667,8 @@ impl PhrLoopManager {
 
         // Phrase の新規生成
         self.loop_id += 1;
+        self.loop_cnt = 0;
+        self.update_mask();
 
         let lp = PhraseLoop::new(
             self.loop_id,
@@ -354,10 +676,11 @@ impl PhrLoopManager {
             PhraseLoopParam::new(
                 pbp.keynote,
                 self.first_msr_num,
-                self.new_data_stock[self.active_phr].evts.to_vec(),
-                self.new_data_stock[self.active_phr].ana.to_vec(),
+                self.loop_evts(estk, tick_for_onemsr),
+                self.loop_ana(estk, tick_for_onemsr),
                 self.whole_tick,
                 self.turnnote,
+                (self.new_data_stock[self.active_phr].tie_loop, false),
             ),
         );
 
@@ -380,6 +703,9 @@ impl PhrLoopManager {
         let phrlen = self.new_data_stock[self.active_phr].evts.len();
         if phrlen != 0 {
             self.gen_new_loop(prm, estk, pbp);
+        } else if self.filler != FILLER_OFF && pbp.has_cmps {
+            // Phrase が来るまで、Composition の和音で一小節の伴奏を鳴らす
+            self.gen_filler_loop(prm, estk, pbp);
         } else {
             // 1小節分の値を入れておき、次の小節で new_loop に入るようにする
             self.whole_tick = prm.1;
@@ -387,10 +713,11 @@ impl PhrLoopManager {
             self.loop_phrase = None;
         }
         self.vari_reserve = 0;
+        self.tie_in = false;
     }
     fn gen_new_loop(&mut self, prm: (i32, i32), estk: &mut ElapseStack, pbp: PartBasicPrm) {
         // 新しいデータが来ていれば、新たに Loop Obj.を生成
-        self.whole_tick = self.new_data_stock[self.active_phr].whole_tick as i32;
+        self.whole_tick = self.speed_tick(self.active_phr, prm.1).0;
         if self.whole_tick == 0 {
             self.state_reserve = true; // 次小節冒頭で呼ばれるように
             self.loop_phrase = None;
@@ -403,16 +730,20 @@ impl PhrLoopManager {
         self.max_loop_msr = self.whole_tick / prm.1 + plus_one;
 
         self.loop_id += 1;
+        self.loop_cnt = 0;
+        self.evolve_cnt = self.evolve_cnt.saturating_add(1);
+        self.update_mask();
         let lp = PhraseLoop::new(
             self.loop_id,
             pbp.part_num,
             PhraseLoopParam::new(
                 pbp.keynote,
                 prm.0,
-                self.new_data_stock[self.active_phr].evts.to_vec(),
-                self.new_data_stock[self.active_phr].ana.to_vec(),
+                self.loop_evts(estk, prm.1),
+                self.loop_ana(estk, prm.1),
                 self.whole_tick,
                 self.turnnote,
+                (self.new_data_stock[self.active_phr].tie_loop, self.tie_in),
             ),
         );
 
@@ -421,6 +752,42 @@ impl PhrLoopManager {
         #[cfg(feature = "verbose")]
         println!("New Phrase Loop! --whole tick: {}", self.whole_tick);
     }
+    /// 一小節の Cluster か Arpeggio だけの Loop を生成
+    fn gen_filler_loop(&mut self, prm: (i32, i32), estk: &mut ElapseStack, pbp: PartBasicPrm) {
+        self.whole_tick = prm.1;
+        self.max_loop_msr = 1;
+        self.loop_id += 1;
+        let (mtype, each_dur) = if self.filler == FILLER_ARP {
+            (TYPE_ARP, DEFAULT_TICK_FOR_QUARTER as i16)
+        } else {
+            (TYPE_CLS, prm.1 as i16)
+        };
+        let evt = PhrEvt {
+            mtype,
+            tick: 0,
+            dur: prm.1 as i16,
+            note: DEFAULT_NOTE_NUMBER as i16 + 12 * (pbp.part_num as i16 - 2),
+            vel: Self::FILLER_VEL,
+            trns: if mtype == TYPE_ARP { ARP_UP } else { 4 },
+            each_dur,
+            ..Default::default()
+        };
+        let lp = PhraseLoop::new(
+            self.loop_id,
+            pbp.part_num,
+            PhraseLoopParam::new(
+                pbp.keynote,
+                prm.0,
+                vec![evt],
+                Vec::new(),
+                self.whole_tick,
+                self.turnnote,
+                (false, false),
+            ),
+        );
+        self.loop_phrase = Some(Rc::clone(&lp));
+        estk.add_elapse(lp);
+    }
 }
 
 //*******************************************************************
@@ -432,6 +799,10 @@ struct CmpsLoopManager {
     whole_tick: i32,
     loop_id: u32, // loop sid
     new_data_stock: ChordData,
+    vari_stock: Vec<ChordData>, // Composition Variation(0 は使わない)
+    active_vari: usize,         // 0: Normal
+    vari_reserve: usize,        // 0:no rsv, 1-9: rsv
+    link: bool,                 // Phrase の Variation と連動する
     loop_cmps: Option<Rc<RefCell<CompositionLoop>>>,
     state_reserve: bool,
     do_loop: bool,
@@ -444,6 +815,10 @@ impl CmpsLoopManager {
             whole_tick: 0,
             loop_id: 0,
             new_data_stock: ChordData::empty(),
+            vari_stock: vec![ChordData::empty(); MAX_VARIATION],
+            active_vari: 0,
+            vari_reserve: 0,
+            link: true,
             loop_cmps: None,
             state_reserve: false,
             do_loop: true,
@@ -451,11 +826,18 @@ impl CmpsLoopManager {
     }
     pub fn start(&mut self) {
         self.clear_cmp_prm();
+        self.active_vari = 0;
+        self.vari_reserve = 0;
         self.state_reserve = true;
     }
     pub fn process(&mut self, crnt_: &CrntMsrTick, estk: &mut ElapseStack, pbp: PartBasicPrm) {
+        if crnt_.msr < 0 {
+            // play from -1 の最初の小節(弱起)では、和音はまだ始めない
+            return;
+        }
         if self.state_reserve {
             // 前小節にて phrase/pattern 指定された時
+            self.active_vari = 0;
             if crnt_.msr == 0 {
                 // 今回 start したとき
                 self.state_reserve = false;
@@ -489,7 +871,11 @@ impl CmpsLoopManager {
         } else if self.max_loop_msr != 0
             && (crnt_.msr - self.first_msr_num) % self.max_loop_msr == 0
         {
-            if self.do_loop {
+            if self.active_vari != 0 {
+                // Variation が終わったら Normal に戻る
+                self.active_vari = 0;
+                self.new_loop(crnt_, estk, pbp);
+            } else if self.do_loop {
                 // 同じ Loop.Obj を生成する
                 self.new_loop(crnt_, estk, pbp);
             } else {
@@ -498,6 +884,13 @@ impl CmpsLoopManager {
         }
     }
     pub fn rcv_cmp(&mut self, msg: ChordData) {
+        if msg.vari != 0 {
+            let vari = msg.vari;
+            if vari < MAX_VARIATION {
+                self.vari_stock[vari] = msg;
+            }
+            return;
+        }
         self.do_loop = msg.do_loop;
         if msg.evts.is_empty() && msg.whole_tick == 0 {
             self.new_data_stock = ChordData::empty();
@@ -510,7 +903,35 @@ impl CmpsLoopManager {
     pub fn get_cmps(&self) -> Option<Rc<RefCell<CompositionLoop>>> {
         self.loop_cmps.clone() // 重いclone()?
     }
-    pub fn gen_chord_name(&self) -> String {
+    /// Composition の Variation を予約する。データが無いか、既に再生中なら無視
+    pub fn reserve_vari(&mut self, vari_num: usize) {
+        if vari_num < MAX_VARIATION
+            && vari_num != self.active_vari
+            && !self.vari_stock[vari_num].evts.is_empty()
+        {
+            self.vari_reserve = vari_num;
+        }
+    }
+    /// 小節先頭でコールされ、予約された Variation に差し替える
+    pub fn proc_vari(&mut self, crnt_: &CrntMsrTick, estk: &mut ElapseStack, pbp: PartBasicPrm) {
+        if self.vari_reserve == 0 {
+            return;
+        }
+        if let Some(cmps) = self.loop_cmps.as_mut() {
+            cmps.borrow_mut().set_destroy();
+        }
+        self.active_vari = self.vari_reserve;
+        self.vari_reserve = 0;
+        self.new_loop(crnt_, estk, pbp);
+    }
+    fn crnt_data(&self) -> &ChordData {
+        if self.active_vari != 0 {
+            &self.vari_stock[self.active_vari]
+        } else {
+            &self.new_data_stock
+        }
+    }
+    pub fn gen_chord_name(&self, disp: Option<(i16, i16)>) -> String {
         if let Some(cmps) = &self.loop_cmps {
             let num = cmps.borrow().get_vari_num();
             let num_str = if num == 0 {
@@ -518,11 +939,42 @@ impl CmpsLoopManager {
             } else {
                 "@".to_string() + cmps.borrow().get_vari_num().to_string().as_str()
             };
-            cmps.borrow().get_chord_name() + &num_str
+            cmps.borrow().get_chord_name(disp) + &num_str
         } else {
             String::from("")
         }
     }
+    pub fn gen_chord_quality(&self) -> ChordQuality {
+        match &self.loop_cmps {
+            Some(cmps) => cmps.borrow().get_chord_quality(),
+            None => ChordQuality::None,
+        }
+    }
+    /// export setup 用: Composition の長さ(小節数)と、入力されている Variation
+    fn setup_summary(&self, tick_for_onemsr: i32) -> Option<String> {
+        let msrs = |cmp: &ChordData| {
+            (cmp.whole_tick as i32 + tick_for_onemsr - 1) / tick_for_onemsr.max(1)
+        };
+        let mut txt = if self.new_data_stock.evts.is_empty() {
+            "-".to_string()
+        } else {
+            format!("{} msr", msrs(&self.new_data_stock))
+        };
+        let varis: Vec<String> = self
+            .vari_stock
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| !x.evts.is_empty())
+            .map(|(i, x)| format!("@{}({} msr)", i, msrs(x)))
+            .collect();
+        if !varis.is_empty() {
+            txt += &format!(", vari: {}", varis.join(" "));
+        }
+        if !self.link {
+            txt += ", unlinked";
+        }
+        (txt != "-").then_some(txt)
+    }
     fn clear_cmp_prm(&mut self) {
         self.first_msr_num = 0;
         self.max_loop_msr = 0;
@@ -532,11 +984,11 @@ impl CmpsLoopManager {
     }
     fn new_loop(&mut self, crnt_: &CrntMsrTick, estk: &mut ElapseStack, pbp: PartBasicPrm) {
         // 新たに Loop Obj.を生成
-        if !self.new_data_stock.evts.is_empty() {
+        if !self.crnt_data().evts.is_empty() {
             #[cfg(feature = "verbose")]
             println!("New Composition Loop! M:{:?},T:{:?}", crnt_.msr, crnt_.tick);
             self.first_msr_num = crnt_.msr; // 計測開始の更新
-            self.whole_tick = self.new_data_stock.whole_tick as i32;
+            self.whole_tick = self.crnt_data().whole_tick as i32;
 
             // その時の beat 情報で、whole_tick を loop_measure に換算
             let plus_one = if self.whole_tick % crnt_.tick_for_onemsr == 0 {
@@ -558,7 +1010,7 @@ impl CmpsLoopManager {
                 pbp.part_num,
                 pbp.keynote,
                 crnt_.msr,
-                self.new_data_stock.evts.to_vec(),
+                estk.substitute_chords(pbp.part_num, self.crnt_data().evts.to_vec()),
                 self.whole_tick,
             );
             self.loop_cmps = Some(Rc::clone(&cmplp));
@@ -621,6 +1073,444 @@ impl CmpsLoopManager {
     }
 }
 //*******************************************************************
+//          Velocity to CC Struct
+//*******************************************************************
+//  Note On の velocity から CC を生成し、Synth の音色をコントロールする
+#[derive(Debug, Copy, Clone)]
+struct VelocityCc {
+    cc_num: i16, // -1: off
+    curve: i16,  // 0:linear, 1:exp, 2:log
+    smooth: i16, // 0-99[%]
+    last_val: f32,
+}
+impl VelocityCc {
+    fn new() -> Self {
+        Self {
+            cc_num: -1,
+            curve: 0,
+            smooth: 0,
+            last_val: 64.0,
+        }
+    }
+    fn gen_cc(&mut self, vel: u8) -> Option<(u8, u8)> {
+        if !(0..=119).contains(&self.cc_num) {
+            return None;
+        }
+        let x = vel as f32 / 127.0;
+        let y = match self.curve {
+            1 => x * x,
+            2 => x.sqrt(),
+            _ => x,
+        };
+        let rate = self.smooth as f32 / 100.0;
+        self.last_val = self.last_val * rate + y * 127.0 * (1.0 - rate);
+        Some((
+            self.cc_num as u8,
+            self.last_val.round().clamp(0.0, 127.0) as u8,
+        ))
+    }
+}
+//*******************************************************************
+//          Note Pan Struct
+//*******************************************************************
+//  Note On の前に CC10(pan) を送る
+//  音高から pan を決めると、低い音を左、高い音を右に広げる
+const PAN_PITCH_RANGE: f32 = 24.0; // center からこの半音数で、幅いっぱいになる
+struct NotePan {
+    pan: i16,    // 0-127, -1: off
+    width: i16,  // 音高で広げる幅(1-64), -1: off
+    curve: i16,  // 0:linear, 1:exp, 2:log
+    center: i16, // pan が真ん中になる note
+}
+impl NotePan {
+    fn new() -> Self {
+        Self {
+            pan: -1,
+            width: -1,
+            curve: 0,
+            center: 60,
+        }
+    }
+    fn set(&mut self, kind: i16, value: i16) {
+        if kind == MSG_SETPT_PAN {
+            self.pan = value.min(127);
+        } else if kind == MSG_SETPT_PAN_PITCH {
+            if value < 0 {
+                self.width = -1;
+            } else {
+                self.width = (value % 128).clamp(1, 64);
+                self.curve = (value / 128).clamp(0, 2);
+            }
+        } else if kind == MSG_SETPT_PAN_CENTER {
+            self.center = value.clamp(0, 127);
+        }
+    }
+    /// 他のパートと同じ ch でも崩れないよう、毎回送る
+    fn gen_cc(&self, note: u8) -> Option<u8> {
+        if self.pan < 0 && self.width < 0 {
+            return None;
+        }
+        let base = if self.pan < 0 { 64.0 } else { self.pan as f32 };
+        let val = if self.width < 0 {
+            base
+        } else {
+            let x = ((note as f32 - self.center as f32) / PAN_PITCH_RANGE).clamp(-1.0, 1.0);
+            let y = match self.curve {
+                1 => x * x.abs(),
+                2 => x.signum() * x.abs().sqrt(),
+                _ => x,
+            };
+            base + y * self.width as f32
+        };
+        Some(val.round().clamp(0.0, 127.0) as u8)
+    }
+}
+//*******************************************************************
+//          Key Switch Struct
+//*******************************************************************
+//  Articulation に応じて、音符の前に keyswitch(Note/CC) を送る
+struct KeySwitch {
+    map: [Option<(u8, u8, u8)>; 3], // 0:normal, 1:staccato, 2:legato / status, data1, data2
+    last: Option<usize>,            // 最後に送った keyswitch
+}
+impl KeySwitch {
+    fn new() -> Self {
+        Self {
+            map: [None; 3],
+            last: None,
+        }
+    }
+    fn set(&mut self, kind: usize, value: i16) {
+        self.map[kind] = if value < 0 {
+            None
+        } else if value < 128 {
+            Some((0x90, value as u8, 100))
+        } else {
+            let cc = (value - 128) / 128;
+            Some((0xb0, cc as u8, ((value - 128) % 128) as u8))
+        };
+        self.last = None;
+    }
+    /// 前回と違う articulation の時だけ keyswitch を返す
+    fn gen_ks(&mut self, artic: i16) -> Option<(u8, u8, u8)> {
+        let kind = match artic.cmp(&DEFAULT_ARTIC) {
+            Ordering::Less => 1,
+            Ordering::Greater => 2,
+            Ordering::Equal => 0,
+        };
+        let ks = self.map[kind]?;
+        if self.last == Some(kind) {
+            return None;
+        }
+        self.last = Some(kind);
+        Some(ks)
+    }
+}
+//*******************************************************************
+//          Micro Tuning Struct
+//*******************************************************************
+//  平均律以外の音律を、Note ごとに ch を変えた pitch bend で実現する
+//  Part ごとに CH_PER_PART 個の ch を順番に使う(MPE の Member Channel として扱える)
+pub struct MicroTuning {
+    cents: Option<[i16; 12]>, // key からの度数ごとの cent, None:平均律
+    bend_range: i16,          // 半音
+    part: u32,
+    next: u8,
+    sounding: [Option<(u8, u32)>; 3], // ch ごとに鳴っている (note, 鳴らした順番)
+    order: u32,
+}
+impl MicroTuning {
+    const CH_PER_PART: u8 = 3;
+    const DRUM_CH: u8 = 9; // 0origin
+    const MPE_MEMBER_CH: u8 = Self::CH_PER_PART * MAX_KBD_PART as u8 + 1; // 飛ばす ch.10 も含む
+
+    fn new(part: u32) -> Self {
+        Self {
+            cents: None,
+            bend_range: 2,
+            part,
+            next: 0,
+            sounding: [None; Self::CH_PER_PART as usize],
+            order: 0,
+        }
+    }
+    fn set_cent(&mut self, value: i16) {
+        if value < 0 {
+            self.cents = None;
+            return;
+        }
+        let degree = (value / 256) as usize;
+        let cents = self.cents.get_or_insert([0; 12]);
+        if degree < 12 {
+            cents[degree] = (value % 256) - 128;
+        }
+    }
+    /// 空いている ch を順番に選び、(ch, pitch bend lsb, msb, 止める音) を返す
+    /// 全ての ch で音が鳴っていたら、一番古い音を止めてその ch を使う
+    fn gen_pb(&mut self, note: u8, keynote: u8) -> Option<(u8, u8, u8, Option<u8>)> {
+        let cents = self.cents?;
+        let degree = (note as i16 - keynote as i16).rem_euclid(12) as usize;
+        let idx = (0..Self::CH_PER_PART)
+            .map(|i| ((self.next + i) % Self::CH_PER_PART) as usize)
+            .find(|i| self.sounding[*i].is_none())
+            .unwrap_or_else(|| {
+                (0..Self::CH_PER_PART as usize)
+                    .min_by_key(|i| self.sounding[*i].map_or(0, |s| s.1))
+                    .unwrap_or(0)
+            });
+        let stolen = self.sounding[idx].map(|s| s.0);
+        self.sounding[idx] = Some((note, self.order));
+        self.order = self.order.wrapping_add(1);
+        self.next = (idx as u8 + 1) % Self::CH_PER_PART;
+        let ch = Self::member_ch(self.part, idx as u8);
+        // bend range を超える cent は、range の端に丸める
+        let pb =
+            (8192 + cents[degree] as i32 * 8192 / (self.bend_range as i32 * 100)).clamp(0, 16383);
+        Some((ch, (pb & 0x7f) as u8, (pb >> 7) as u8, stolen))
+    }
+    /// Note Off した ch を空ける
+    fn note_off(&mut self, ch: u8, note: u8) {
+        let idx = (0..Self::CH_PER_PART).position(|i| Self::member_ch(self.part, i) == ch);
+        if let Some(idx) = idx {
+            if self.sounding[idx].is_some_and(|s| s.0 == note) {
+                self.sounding[idx] = None;
+            }
+        }
+    }
+    /// part の idx 番目の ch(0origin)。ch.1 は MPE の Manager、ch.10 は drum なので使わない
+    pub fn member_ch(part: u32, idx: u8) -> u8 {
+        let ch = 1 + (part as u8 % MAX_KBD_PART as u8) * Self::CH_PER_PART + idx;
+        if ch >= Self::DRUM_CH {
+            ch + 1
+        } else {
+            ch
+        }
+    }
+    /// MPE Configuration と pitch bend range(RPN0) を Synth に送る
+    /// 平均律に戻した時は、使っていた ch の pitch bend を中央に戻す
+    fn gen_setup(&self) -> Vec<(u8, u8, u8)> {
+        let mut msgs = Vec::new();
+        let chs = (0..Self::CH_PER_PART).map(|i| Self::member_ch(self.part, i));
+        if self.cents.is_none() {
+            chs.for_each(|ch| msgs.push((0xe0 | ch, 0, 64)));
+            return msgs;
+        }
+        msgs.extend([
+            (0xb0, 101, 0),
+            (0xb0, 100, 6),
+            (0xb0, 6, Self::MPE_MEMBER_CH),
+        ]);
+        for ch in chs {
+            let sts = 0xb0 | ch;
+            msgs.extend([
+                (sts, 101, 0),
+                (sts, 100, 0),
+                (sts, 6, self.bend_range as u8),
+                (sts, 38, 0),
+                (sts, 101, 127), // RPN null
+                (sts, 100, 127),
+            ]);
+        }
+        msgs
+    }
+}
+//*******************************************************************
+//          Velocity Ramp Struct
+//*******************************************************************
+//  Loop の頭から終わりに向けて、velocity を start[%] から end[%] に変化させる
+//  loops が 2 以上なら、その回数の Loop をかけて変化させる
+struct VelocityRamp {
+    start: i16, // 0: off
+    end: i16,
+    loops: i16,
+}
+impl VelocityRamp {
+    fn new() -> Self {
+        Self {
+            start: 0,
+            end: 100,
+            loops: 1,
+        }
+    }
+    fn set(&mut self, kind: i16, value: i16) {
+        if kind == MSG_SETPT_RAMP_END {
+            self.end = value.clamp(1, 200);
+        } else if value < 0 {
+            self.start = 0;
+        } else {
+            self.start = (value & 0xff).clamp(1, 200);
+            self.loops = (value >> 8).clamp(1, 64);
+        }
+    }
+    /// cnt: Loop の回数, pos: Loop 内の位置(0.0-1.0)
+    fn gen_vel(&self, cnt: u32, pos: f32, vel: u8) -> u8 {
+        if self.start == 0 {
+            return vel;
+        }
+        let loops = self.loops as u32;
+        let progress = ((cnt % loops) as f32 + pos) / loops as f32;
+        let rate = self.start as f32 + (self.end - self.start) as f32 * progress;
+        (vel as f32 * rate / 100.0).round().clamp(1.0, 127.0) as u8
+    }
+}
+//*******************************************************************
+//          Velocity Limit Struct
+//*******************************************************************
+//  velocity を floor から ceiling の間に収める
+//  範囲の端から knee の幅では、端に近づくほど変化を緩やかにする(soft clipping)
+struct VelocityLimit {
+    floor: i16, // 0: off
+    ceiling: i16,
+}
+impl VelocityLimit {
+    fn new() -> Self {
+        Self {
+            floor: 0,
+            ceiling: 127,
+        }
+    }
+    fn set(&mut self, value: i16) {
+        if value < 0 {
+            self.floor = 0;
+        } else {
+            self.floor = (value & 0x7f).clamp(1, 127);
+            self.ceiling = (value >> 7).clamp(self.floor, 127);
+        }
+    }
+    fn gen_vel(&self, vel: u8) -> u8 {
+        if self.floor == 0 {
+            return vel;
+        }
+        let (floor, ceiling, vel) = (self.floor as f32, self.ceiling as f32, vel as f32);
+        let knee = ((ceiling - floor) / 4.0).max(1.0);
+        let out = if vel > ceiling - knee {
+            ceiling - knee + knee * ((vel - ceiling + knee) / knee).tanh()
+        } else if vel < floor + knee {
+            floor + knee - knee * ((floor + knee - vel) / knee).tanh()
+        } else {
+            vel
+        };
+        out.round().clamp(floor, ceiling) as u8
+    }
+}
+//*******************************************************************
+//          Auto Variation Struct
+//*******************************************************************
+//  小節の和音の機能と Composition の区切りから、その小節で鳴らす Variation を選ぶ
+//  (例: Drum のパートで、属和音の小節は細かいパターン、Loop の最後の小節は fill にする)
+//  Loop の最後の小節 > 属和音 > 下属和音 の順に優先する
+pub struct AutoVari {
+    dominant: usize,    // V, VII, 属七の和音の小節(0:使わない)
+    subdominant: usize, // IV, II の小節
+    fill: usize,        // Composition の Loop の最後の小節
+}
+impl AutoVari {
+    pub fn new() -> Self {
+        Self {
+            dominant: 0,
+            subdominant: 0,
+            fill: 0,
+        }
+    }
+    pub fn set(&mut self, value: i16) {
+        self.dominant = (value & 0x0f) as usize;
+        self.subdominant = ((value >> 4) & 0x0f) as usize;
+        self.fill = ((value >> 8) & 0x0f) as usize;
+    }
+    /// chord: 小節の頭の和音(root, table)と、Loop の最後の小節か
+    pub fn select(&self, chord: Option<(i16, i16, bool)>) -> Option<usize> {
+        let (root, tbl, last) = chord?;
+        if last && self.fill != 0 {
+            return Some(self.fill);
+        }
+        if root == NO_ROOT || root as usize >= ROOT2NTNUM.len() || tbl == NO_TABLE {
+            return None;
+        }
+        let degree = ROOT2NTNUM[root as usize].rem_euclid(12);
+        if self.dominant != 0 && trigger::is_dominant(root, tbl) {
+            Some(self.dominant)
+        } else if self.subdominant != 0 && (degree == 5 || degree == 2) {
+            Some(self.subdominant)
+        } else {
+            None
+        }
+    }
+}
+//*******************************************************************
+//          Transpose Riser Struct
+//*******************************************************************
+//  msrs 小節ごとに、offsets を一つずつ進めて移調する(最後まで行ったら最初に戻る)
+//  新しい Phrase や Variation が来たら、最初からやり直す
+struct TransposeRiser {
+    msrs: i32,
+    offsets: Vec<i16>,    // 半音単位, 空なら off
+    top_msr: Option<i32>, // 数え始めの小節
+}
+impl TransposeRiser {
+    fn new() -> Self {
+        Self {
+            msrs: 1,
+            offsets: Vec::new(),
+            top_msr: None,
+        }
+    }
+    fn set(&mut self, msrs: i16, offsets: Vec<i16>) {
+        if msrs > 0 {
+            self.msrs = msrs as i32;
+            self.offsets = offsets;
+        }
+        self.top_msr = None;
+    }
+    fn reset(&mut self) {
+        self.top_msr = None;
+    }
+    /// msr で鳴らす音の移調量
+    fn get(&mut self, msr: i32) -> i16 {
+        if self.offsets.is_empty() {
+            return 0;
+        }
+        let top = *self.top_msr.get_or_insert(msr);
+        let step = (msr - top).max(0) / self.msrs;
+        self.offsets[step as usize % self.offsets.len()]
+    }
+}
+//*******************************************************************
+//          Freeze Record Struct
+//*******************************************************************
+//  Loop ごとに実際に鳴らした音を記録し、freeze で Phrase に書き出せるようにする
+//  note は key を含まず、tick は Loop の頭から
+struct FreezeRec {
+    top_msr: i32, // 記録中の Loop の先頭小節
+    rec: (Vec<PhrEvt>, i32),
+    last: (Vec<PhrEvt>, i32), // 一つ前の Loop
+}
+impl FreezeRec {
+    fn new() -> Self {
+        Self {
+            top_msr: i32::MIN,
+            rec: (Vec::new(), 0),
+            last: (Vec::new(), 0),
+        }
+    }
+    fn put(&mut self, top_msr: i32, whole_tick: i32, ev: PhrEvt) {
+        if top_msr != self.top_msr {
+            // 新しい Loop になったら、記録していた Loop を一つ前の Loop にする
+            self.last = std::mem::replace(&mut self.rec, (Vec::new(), whole_tick));
+            self.top_msr = top_msr;
+        }
+        self.rec.0.push(ev);
+    }
+    /// 鳴り終わった最後の Loop の記録
+    fn get(&self, crnt_top_msr: Option<i32>) -> Option<(Vec<PhrEvt>, i32)> {
+        let done = if crnt_top_msr == Some(self.top_msr) {
+            &self.last
+        } else {
+            &self.rec
+        };
+        (!done.0.is_empty()).then(|| done.clone())
+    }
+}
+//*******************************************************************
 //          Part Struct
 //*******************************************************************
 pub struct Part {
@@ -636,6 +1526,21 @@ pub struct Part {
     flow: Option<Rc<RefCell<Flow>>>,
     sync_next_msr_flag: bool,
     start_flag: bool,
+    roll_tick: i16,
+    vel_cc: VelocityCc,
+    pan: NotePan,
+    keysw: KeySwitch,
+    tuning: MicroTuning,
+    split: Option<(u8, u8, u8)>, // 音域で ch を分ける(境目の note, 下の ch, 上の ch)
+    pad: i16,                    // 和音を持続音で鳴らす(MSG_SETPT_PAD の値)
+    chord_out: i16,              // 和音を出力する MIDI ch(1-16), 0:off
+    ramp: VelocityRamp,
+    vel_limit: VelocityLimit,
+    auto_vari: AutoVari,
+    evolve: i16, // Loop ごとに Cluster の声部を増やす上限, 0:off
+    riser: TransposeRiser,
+    freeze: FreezeRec,
+    vari_beat: Option<(usize, i32, i32)>, // 拍頭で切り替える Variation(番号, msr, tick)
 }
 impl Part {
     pub fn new(num: u32, flow: Option<Rc<RefCell<Flow>>>) -> Rc<RefCell<Part>> {
@@ -656,8 +1561,30 @@ impl Part {
             flow,
             sync_next_msr_flag: false,
             start_flag: false,
+            roll_tick: 0,
+            vel_cc: VelocityCc::new(),
+            pan: NotePan::new(),
+            keysw: KeySwitch::new(),
+            tuning: MicroTuning::new(num),
+            split: None,
+            pad: PAD_OFF,
+            chord_out: 0,
+            ramp: VelocityRamp::new(),
+            vel_limit: VelocityLimit::new(),
+            auto_vari: AutoVari::new(),
+            evolve: 0,
+            riser: TransposeRiser::new(),
+            freeze: FreezeRec::new(),
+            vari_beat: None,
         }))
     }
+    pub fn get_keynote(&self) -> u8 {
+        self.keynote
+    }
+    /// play from -1 の時、一小節前から始める Part か
+    pub fn has_pickup(&self) -> bool {
+        self.pm.has_pickup()
+    }
     pub fn change_key(&mut self, knt: u8) {
         self.keynote = knt; // 0-11
         if let Some(fl) = &self.flow {
@@ -665,7 +1592,16 @@ impl Part {
         }
         self.pm.state_reserve = true;
     }
+    /// 演奏中の転調。再生中の Composition の key も合わせる
+    /// pivot の時は、次の和音の変わり目で Composition の key を変える
+    pub fn modulate(&mut self, knt: u8, pivot: bool) {
+        self.change_key(knt);
+        if let Some(cmps) = self.cm.get_cmps() {
+            cmps.borrow_mut().set_keynote(knt, pivot);
+        }
+    }
     pub fn rcv_phr_msg(&mut self, msg: PhrData) {
+        self.riser.reset();
         self.pm.rcv_phr(msg);
     }
     pub fn del_phr(&mut self) {
@@ -680,22 +1616,347 @@ impl Part {
     pub fn get_cmps(&self) -> Option<Rc<RefCell<CompositionLoop>>> {
         self.cm.get_cmps()
     }
+    fn basic_prm(&self) -> PartBasicPrm {
+        PartBasicPrm {
+            part_num: self.id.sid,
+            keynote: self.keynote,
+            sync_flag: self.sync_next_msr_flag,
+            has_cmps: self.cm.loop_cmps.is_some(),
+        }
+    }
     pub fn get_flow(&self) -> Option<Rc<RefCell<Flow>>> {
         self.flow.clone()
     }
     pub fn set_turnnote(&mut self, tn: i16) {
         self.pm.set_turnnote(tn);
     }
+    pub fn set_roll(&mut self, tick: i16) {
+        self.roll_tick = tick;
+    }
+    pub fn get_roll(&self) -> i16 {
+        self.roll_tick
+    }
+    pub fn set_vel_cc(&mut self, kind: i16, value: i16) {
+        if kind == MSG_SETPT_VELCC {
+            self.vel_cc.cc_num = value;
+        } else if kind == MSG_SETPT_VELCC_CURVE {
+            self.vel_cc.curve = value;
+        } else if kind == MSG_SETPT_VELCC_SMOOTH {
+            self.vel_cc.smooth = value.clamp(0, 99);
+        }
+    }
+    pub fn set_pan(&mut self, kind: i16, value: i16) {
+        self.pan.set(kind, value);
+    }
+    pub fn set_keysw(&mut self, kind: i16, value: i16) {
+        let idx = (kind - MSG_SETPT_KEYSW_NORM) as usize;
+        if idx < 3 {
+            self.keysw.set(idx, value);
+        }
+    }
+    /// 音律の設定。Synth に送るべき設定(status, data1, data2)を返す
+    pub fn set_tuning(&mut self, kind: i16, value: i16) -> Vec<(u8, u8, u8)> {
+        if kind == MSG_SETPT_TUNE {
+            self.tuning.set_cent(value);
+            Vec::new()
+        } else {
+            if value > 0 {
+                self.tuning.bend_range = value.clamp(1, 24);
+            }
+            self.tuning.gen_setup()
+        }
+    }
+    /// 音律の設定がある時、Note On の前に送る (ch, pitch bend lsb, msb, 止める音) を返す
+    pub fn gen_tuning(&mut self, note: u8, keynote: u8) -> Option<(u8, u8, u8, Option<u8>)> {
+        self.tuning.gen_pb(note, keynote)
+    }
+    pub fn tuning_off(&mut self, ch: u8, note: u8) {
+        self.tuning.note_off(ch, note);
+    }
+    /// value: MSG_SETPT_SPLIT の形式, 負なら off
+    pub fn set_split(&mut self, value: i16) {
+        self.split = if value < 0 {
+            None
+        } else {
+            Some((
+                (value & 0x7f) as u8,
+                ((value >> 7) & 0x0f) as u8,
+                ((value >> 11) & 0x0f) as u8,
+            ))
+        };
+    }
+    /// 音域で ch を分ける設定がある時、note を出力する ch を返す
+    pub fn gen_split_ch(&self, note: u8) -> Option<u8> {
+        self.split
+            .map(|(border, low, high)| if note < border { low } else { high })
+    }
+    pub fn set_pad(&mut self, pad: i16) {
+        self.pad = pad;
+    }
+    pub fn set_chord_out(&mut self, ch: i16) {
+        self.chord_out = ch;
+    }
+    pub fn get_chord_out(&self) -> Option<i16> {
+        (self.chord_out != 0).then_some(self.chord_out)
+    }
+    pub fn loop_iteration(&self) -> (u32, bool) {
+        self.pm.loop_iteration()
+    }
+    /// export setup 用: Part の設定を (項目, 値) で返す。設定していない項目は省く
+    pub fn setup_summary(&self, tick_for_onemsr: i32) -> Vec<(&'static str, String)> {
+        let mut rows = Vec::new();
+        if let Some(txt) = self.pm.setup_summary(tick_for_onemsr) {
+            rows.push(("phrase", txt));
+        }
+        if let Some(txt) = self.cm.setup_summary(tick_for_onemsr) {
+            rows.push(("composition", txt));
+        }
+        if let Some((note, lower, upper)) = self.split {
+            rows.push((
+                "split",
+                format!("note {}: ch.{} / ch.{}", note, lower + 1, upper + 1),
+            ));
+        }
+        if self.chord_out != 0 {
+            rows.push(("chord out", format!("ch.{}", self.chord_out)));
+        }
+        if self.pad == PAD_REATTACK {
+            rows.push(("sustain", "reattack".to_string()));
+        } else if self.pad != PAD_OFF {
+            rows.push(("sustain", format!("legato {} tick", self.pad)));
+        }
+        let ks: Vec<String> = ["normal", "staccato", "legato"]
+            .iter()
+            .zip(self.keysw.map.iter())
+            .filter_map(|(name, ks)| match ks {
+                Some((0x90, note, _)) => Some(format!("{} note {}", name, note)),
+                Some((_, cc, val)) => Some(format!("{} cc{}={}", name, cc, val)),
+                None => None,
+            })
+            .collect();
+        if !ks.is_empty() {
+            rows.push(("keyswitch", ks.join(", ")));
+        }
+        if let Some(cents) = self.tuning.cents {
+            let cents: Vec<String> = cents.iter().map(|c| c.to_string()).collect();
+            rows.push((
+                "tuning",
+                format!(
+                    "{} cent, bend range {}",
+                    cents.join(","),
+                    self.tuning.bend_range
+                ),
+            ));
+        }
+        // groove
+        if let Some(q) = self.pm.quantize {
+            let txt = if q == 0 {
+                "off".to_string()
+            } else {
+                format!("{}%, grid {} tick", q & 0xff, (q >> 8) * 60)
+            };
+            rows.push(("quantize", txt));
+        }
+        if self.pm.filler != FILLER_OFF {
+            let txt = if self.pm.filler == FILLER_ARP {
+                "arp"
+            } else {
+                "chord"
+            };
+            rows.push(("filler", txt.to_string()));
+        }
+        if self.pm.speed != (1, 1) {
+            rows.push(("speed", format!("x{}/{}", self.pm.speed.0, self.pm.speed.1)));
+        }
+        if self.roll_tick != 0 {
+            rows.push(("roll", format!("{} tick", self.roll_tick)));
+        }
+        if self.pm.global_phase {
+            rows.push(("phase", "global".to_string()));
+        }
+        if self.evolve != 0 {
+            rows.push(("evolve", format!("up to {} voices", self.evolve)));
+        }
+        if !self.riser.offsets.is_empty() {
+            let ofs: Vec<String> = self.riser.offsets.iter().map(|x| x.to_string()).collect();
+            rows.push((
+                "riser",
+                format!("every {} msr: {}", self.riser.msrs, ofs.join(",")),
+            ));
+        }
+        let av = &self.auto_vari;
+        if av.dominant != 0 || av.subdominant != 0 || av.fill != 0 {
+            rows.push((
+                "auto vari",
+                format!(
+                    "dominant @{}, subdominant @{}, fill @{}",
+                    av.dominant, av.subdominant, av.fill
+                ),
+            ));
+        }
+        // dynamics
+        if self.ramp.start != 0 {
+            rows.push((
+                "velocity ramp",
+                format!(
+                    "{}% -> {}% / {} loop",
+                    self.ramp.start, self.ramp.end, self.ramp.loops
+                ),
+            ));
+        }
+        if self.vel_limit.floor != 0 {
+            rows.push((
+                "velocity limit",
+                format!("{}-{}", self.vel_limit.floor, self.vel_limit.ceiling),
+            ));
+        }
+        if (0..=119).contains(&self.vel_cc.cc_num) {
+            let curve = ["linear", "exp", "log"][self.vel_cc.curve.clamp(0, 2) as usize];
+            rows.push((
+                "velocity cc",
+                format!(
+                    "cc{}, {}, smooth {}%",
+                    self.vel_cc.cc_num, curve, self.vel_cc.smooth
+                ),
+            ));
+        }
+        // pan
+        if self.pan.pan >= 0 || self.pan.width >= 0 {
+            let mut txt = if self.pan.pan >= 0 {
+                format!("{}", self.pan.pan)
+            } else {
+                "64".to_string()
+            };
+            if self.pan.width >= 0 {
+                let curve = ["linear", "exp", "log"][self.pan.curve as usize];
+                txt += &format!(
+                    ", pitch +-{} ({}), center note {}",
+                    self.pan.width, curve, self.pan.center
+                );
+            }
+            rows.push(("pan", txt));
+        }
+        rows
+    }
+    pub fn set_evolve(&mut self, value: i16) {
+        if value == EVOLVE_RESET {
+            self.pm.reset_evolve();
+        } else {
+            if self.evolve == 0 {
+                self.pm.reset_evolve(); // 次の Loop から増やし始める
+            }
+            self.evolve = value;
+        }
+    }
+    /// Cluster に加える声部の数
+    pub fn get_evolve(&self) -> i16 {
+        self.pm.evolve_level(self.evolve)
+    }
+    /// 鳴らした音を freeze 用に記録する
+    pub fn rec_freeze(&mut self, msr: i32, tick: i32, tick_for_onemsr: i32, mut ev: PhrEvt) {
+        if let Some((top, tick_in_loop, whole)) = self.pm.loop_tick(msr, tick, tick_for_onemsr) {
+            ev.tick = tick_in_loop as i16;
+            self.freeze.put(top, whole, ev);
+        }
+    }
+    /// 直前の Loop で鳴らした音と、その Loop の長さ
+    pub fn get_frozen(&self, msr: i32, tick_for_onemsr: i32) -> Option<(Vec<PhrEvt>, i32)> {
+        let top = self
+            .pm
+            .loop_tick(msr, 0, tick_for_onemsr)
+            .map(|(top, _, _)| top);
+        self.freeze.get(top)
+    }
+    pub fn set_quantize(&mut self, value: i16) {
+        self.pm.set_quantize(value);
+    }
+    pub fn set_filler(&mut self, value: i16) {
+        self.pm.set_filler(value);
+    }
+    /// 倍率で伸ばすと Phrase が長くなりすぎる時は false
+    pub fn set_speed(&mut self, value: i16, tick_for_onemsr: i32) -> bool {
+        let num = (value & 0x0f).clamp(1, 8) as i32;
+        let den = ((value >> 4) & 0x0f).clamp(1, 8) as i32;
+        self.pm.set_speed((num, den), tick_for_onemsr)
+    }
+    pub fn set_ramp(&mut self, kind: i16, value: i16) {
+        self.ramp.set(kind, value);
+    }
+    /// Loop 内の位置に応じて、velocity を変化させる
+    pub fn gen_ramp_vel(&self, msr: i32, tick: i32, tick_for_onemsr: i32, vel: u8) -> u8 {
+        match self.pm.loop_position(msr, tick, tick_for_onemsr) {
+            Some((cnt, pos)) => self.ramp.gen_vel(cnt, pos, vel),
+            None => vel,
+        }
+    }
+    /// msrs: 何小節ごとに進めるか(0:最初に戻すだけ), offsets: 空なら off
+    pub fn set_riser(&mut self, msrs: i16, offsets: Vec<i16>) {
+        self.riser.set(msrs, offsets);
+    }
+    pub fn get_riser(&mut self, msr: i32) -> i16 {
+        self.riser.get(msr)
+    }
+    /// value: MSG_SETPT_AUTO_VARI の形式
+    pub fn set_auto_vari(&mut self, value: i16) {
+        self.auto_vari.set(value);
+    }
+    /// Composition がこの小節で Loop の最後の小節か
+    pub fn is_cmps_last_msr(&self, crnt_: &CrntMsrTick) -> bool {
+        self.cm.get_cmps().is_some_and(|cmps| {
+            cmps.borrow()
+                .get_msr_chord(crnt_.msr, crnt_.tick_for_onemsr)
+                .is_some_and(|(_, _, last)| last)
+        })
+    }
+    /// 小節の頭で、auto_vari の規則に合えば Variation を予約する
+    fn reserve_auto_vari(&mut self, crnt_: &CrntMsrTick) {
+        let chord = self.cm.get_cmps().and_then(|cmps| {
+            cmps.borrow()
+                .get_msr_chord(crnt_.msr, crnt_.tick_for_onemsr)
+        });
+        if let Some(vari) = self.auto_vari.select(chord) {
+            self.pm.reserve_vari(vari);
+        }
+    }
+    /// value: MSG_SETPT_VEL_LIMIT の形式, 負なら off
+    pub fn set_vel_limit(&mut self, value: i16) {
+        self.vel_limit.set(value);
+    }
+    pub fn limit_vel(&self, vel: u8) -> u8 {
+        self.vel_limit.gen_vel(vel)
+    }
+    pub fn set_mask(&mut self, mask: Vec<bool>) {
+        self.pm.set_mask(mask);
+    }
+    pub fn is_masked(&self, msr_in_loop: i32) -> bool {
+        self.pm.is_masked(msr_in_loop)
+    }
+    /// pad mode の時、PAD_REATTACK か legato で重ねる tick 数を返す
+    pub fn get_pad(&self) -> Option<i16> {
+        (self.pad != PAD_OFF).then_some(self.pad)
+    }
+    /// Note の articulation から、送るべき keyswitch(status, data1, data2) を返す
+    pub fn gen_keysw(&mut self, artic: i16) -> Option<(u8, u8, u8)> {
+        self.keysw.gen_ks(artic)
+    }
+    /// Note On の velocity から、送るべき CC(num, value) を返す
+    pub fn gen_vel_cc(&mut self, vel: u8) -> Option<(u8, u8)> {
+        self.vel_cc.gen_cc(vel)
+    }
+    pub fn gen_pan(&self, note: u8) -> Option<u8> {
+        self.pan.gen_cc(note)
+    }
     /// sync command 発行時にコールされる
     pub fn set_sync(&mut self) {
         self.pm.state_reserve = true;
         self.cm.state_reserve = true;
         self.sync_next_msr_flag = true;
     }
-    pub fn gen_part_indicator(&self, crnt_: &CrntMsrTick) -> PartUi {
+    pub fn gen_part_indicator(&self, crnt_: &CrntMsrTick, disp: Option<(i16, i16)>) -> PartUi {
         let mut exist = true;
         let mut flow = false;
         let mut chord_name = "".to_string();
+        let mut quality = ChordQuality::None;
         let mut msr_in_loop = 0;
         let mut all_msrs = 0;
         if self.pm.whole_tick != 0 {
@@ -704,9 +1965,11 @@ impl Part {
             } else {
                 exist = false;
             }
-            chord_name = self.cm.gen_chord_name();
+            chord_name = self.cm.gen_chord_name(disp);
+            quality = self.cm.gen_chord_quality();
         } else if self.flow.is_some() && self.during_play {
-            chord_name = self.cm.gen_chord_name().to_string();
+            chord_name = self.cm.gen_chord_name(disp).to_string();
+            quality = self.cm.gen_chord_quality();
             flow = true;
         } else {
             exist = false;
@@ -717,6 +1980,8 @@ impl Part {
             all_msrs,
             flow,
             chord_name,
+            quality,
+            vari: self.pm.active_vari(),
         }
     }
     pub fn rcv_midi_in(
@@ -732,12 +1997,60 @@ impl Part {
         }
     }
     /// Composition Loop から、次の小説の Phrase Variation を指定する
+    /// 連動時は Composition も同じ番号の Variation に切り替える
     pub fn set_phrase_vari(&mut self, vari_num: usize) {
+        if self.cm.link && self.cm.active_vari == vari_num {
+            // 再生中の Composition Variation 自身からの指定
+            return;
+        }
         self.pm.reserve_vari(vari_num);
+        if self.cm.link {
+            self.cm.reserve_vari(vari_num);
+        }
+    }
+    pub fn set_global_phase(&mut self, global: bool) {
+        self.pm.global_phase = global;
+    }
+    /// 次の拍頭で Variation を切り替える。小節の最後の拍なら、次の小節頭で切り替える
+    pub fn reserve_vari_beat(&mut self, vari_num: usize, crnt_: &CrntMsrTick, tick_for_beat: i32) {
+        let next_beat = (crnt_.tick / tick_for_beat + 1) * tick_for_beat;
+        if !self.during_play || tick_for_beat <= 0 || next_beat >= crnt_.tick_for_onemsr {
+            self.select_vari(MSG_SETPT_VARI, vari_num as i16);
+        } else {
+            self.vari_beat = Some((vari_num, crnt_.msr, next_beat));
+        }
+    }
+    /// User による Variation の指定
+    pub fn select_vari(&mut self, kind: i16, value: i16) {
+        if kind == MSG_SETPT_VARI_LINK {
+            self.cm.link = value != 0;
+        } else if kind == MSG_SETPT_CMPS_VARI {
+            self.cm.reserve_vari(value as usize);
+        } else {
+            self.riser.reset();
+            self.pm.reserve_vari(value as usize);
+            if self.cm.link {
+                self.cm.reserve_vari(value as usize);
+            }
+        }
     }
     pub fn set_loop_end(&mut self) {
         // nothing to do
     }
+    /// punch で弾いた音を Normal Phrase に差し替えた音と、その長さ
+    /// rec の note は、key を含む実際の音
+    pub fn merge_punch(
+        &self,
+        region: (i32, i32),
+        rec: &[PunchNote],
+        tick_for_onemsr: i32,
+    ) -> Option<(Vec<PhrEvt>, i32)> {
+        let rec: Vec<(i32, i32, i16, i32, u8)> = rec
+            .iter()
+            .map(|(m, t, nt, d, v)| (*m, *t, *nt as i16 - self.keynote as i16, *d, *v))
+            .collect();
+        self.pm.merge_punch(region, &rec, tick_for_onemsr)
+    }
 }
 impl Elapse for Part {
     /// id を得る
@@ -750,14 +2063,20 @@ impl Elapse for Part {
     }
     /// 次に呼ばれる小節番号、Tick数を返す
     fn next(&self) -> (i32, i32) {
-        (self.next_msr, self.next_tick)
+        match self.vari_beat {
+            Some((_, msr, tick)) if (msr, tick) < (self.next_msr, self.next_tick) => (msr, tick),
+            _ => (self.next_msr, self.next_tick),
+        }
     }
     /// User による start/play 時にコールされる msr:開始小節番号
     fn start(&mut self, msr: i32) {
         self.during_play = true;
+        self.vari_beat = None;
         self.start_flag = true;
         self.next_msr = msr;
         self.next_tick = 0;
+        self.keysw.last = None;
+        self.riser.reset();
         self.cm.start();
         self.pm.start();
     }
@@ -772,15 +2091,25 @@ impl Elapse for Part {
     }
     /// 再生 msr/tick に達したらコールされる
     fn process(&mut self, crnt_: &CrntMsrTick, estk: &mut ElapseStack) {
-        let pbp = PartBasicPrm {
-            part_num: self.id.sid,
-            keynote: self.keynote,
-            sync_flag: self.sync_next_msr_flag,
-        };
+        let pbp = self.basic_prm();
+        if let Some((vari, msr, tick)) = self.vari_beat {
+            if (crnt_.msr, crnt_.tick) >= (msr, tick)
+                && (msr, tick) < (self.next_msr, self.next_tick)
+            {
+                // 拍頭での Variation 切り替え(Composition は次の小節頭で切り替える)
+                self.vari_beat = None;
+                self.pm.switch_vari_at_beat(vari, crnt_, estk, pbp);
+                if self.cm.link {
+                    self.cm.reserve_vari(vari);
+                }
+                return;
+            }
+        }
         if self.start_flag {
             // Start 直後
             self.cm.process(crnt_, estk, pbp);
-            self.pm.process(crnt_, estk, pbp);
+            self.reserve_auto_vari(crnt_);
+            self.pm.process(crnt_, estk, self.basic_prm());
             self.start_flag = false;
             // 小節最後の tick をセット
             self.next_tick = crnt_.tick_for_onemsr - 1;
@@ -797,7 +2126,9 @@ impl Elapse for Part {
             self.next_tick = 0;
         } else {
             // 小節先頭
+            self.reserve_auto_vari(crnt_);
             self.pm.process(crnt_, estk, pbp);
+            self.cm.proc_vari(crnt_, estk, pbp);
             self.sync_next_msr_flag = false;
             // 小節最後の tick をセット
             self.next_tick = crnt_.tick_for_onemsr - 1;
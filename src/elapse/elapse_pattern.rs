@@ -14,7 +14,17 @@ use super::tickgen::CrntMsrTick;
 use crate::cmd::txt2seq_ana;
 use crate::cmd::txt2seq_cmps;
 use crate::lpnlib::*;
+use rand::Rng;
 
+/// Euclidean rhythm : steps に pulses 個の打点を均等に散らし、rot だけ左に回した時、idx が打点か
+/// E(3,8) : x..x..x.
+pub fn euclid_hit(pulses: i16, steps: i16, rot: i16, idx: i16) -> bool {
+    if steps <= 0 || pulses <= 0 {
+        return false;
+    }
+    let pos = (idx + rot).rem_euclid(steps) as i32;
+    (pos * pulses as i32) % (steps as i32) < pulses as i32
+}
 //*******************************************************************
 //          Dynamic Pattern Struct
 //*******************************************************************
@@ -23,6 +33,9 @@ pub struct DynamicPattern {
     priority: u32,
 
     arp_available: bool,
+    strum_available: bool,
+    euclid: Option<(i16, i16, i16, i16)>, // (step数, 打点の数, 回転, 鳴らす音)
+    hit_counter: usize,                   // for euclid
     ptn_tick: i32,
     ptn_min_nt: i16,
     ptn_vel: i32,
@@ -31,6 +44,7 @@ pub struct DynamicPattern {
     ptn_arp_type: i32,
     next_index: usize,  // for arp
     oct_up: i16,        // for arp
+    last_step: i16,     // for arp
     note_close_to: i16, // for arp
     analys: Vec<AnaEvt>,
 
@@ -49,6 +63,14 @@ pub struct DynamicPattern {
     next_tick: i32, //   次に呼ばれるTick数が保持される
 }
 impl DynamicPattern {
+    const ROLL_VEL_TILT: i16 = 3;
+    const GUITAR_STRINGS: [i16; 6] = [40, 45, 50, 55, 59, 64]; // E2,A2,D3,G3,B3,E4
+    const FRET_SPAN: i16 = 3; // ポジションから押さえられる fret の幅
+    const MAX_MUTE_STRINGS: usize = 2; // root を最低音にするために消音できる低音弦の数
+    const STRUM_TICK: i32 = 12; // 弦ごとのずれ(roll の設定がなければ)
+    const UP_STRINGS: usize = 4; // up で鳴らす高音弦の数
+    const UP_VEL_DIFF: i16 = 12;
+
     pub fn new(
         sid: u32,
         pid: u32,
@@ -73,6 +95,20 @@ impl DynamicPattern {
             }
         });
         let arp_available = ptn.mtype == TYPE_ARP;
+        let euclid = (ptn.mtype == TYPE_EUCLID).then_some((
+            (ptn.trns & 0x0f) + 1,
+            (ptn.trns >> EUC_PULSE_SHIFT) & 0x1f,
+            (ptn.trns >> EUC_ROT_SHIFT) & 0x0f,
+            (ptn.trns >> EUC_SRC_SHIFT) & 0x03,
+        ));
+        let (max_vce, arp_type) = if arp_available {
+            (ptn.trns >> ARP_VCE_SHIFT, ptn.trns & ARP_TYPE_MASK)
+        } else if euclid.is_some() {
+            (4, ARP_UP) // Cluster は4音、Arpeggio は上昇
+        } else {
+            (ptn.trns, ptn.trns)
+        };
+        let strum_available = ptn.mtype == TYPE_STRUM;
 
         #[cfg(feature = "verbose")]
         println!("New DynaPtn: para:{}", para);
@@ -85,15 +121,19 @@ impl DynamicPattern {
                 elps_type: ElapseType::TpDynamicPattern,
             },
             arp_available,
+            strum_available,
+            euclid,
+            hit_counter: 0,
             priority: PRI_DYNPTN,
             ptn_tick: ptn.tick as i32,
             ptn_min_nt: ptn.note,
             ptn_vel: ptn.vel as i32,
             ptn_each_dur: ptn.each_dur as i32,
-            ptn_max_vce: ptn.trns as i32,
-            ptn_arp_type: ptn.trns as i32,
+            ptn_max_vce: max_vce as i32,
+            ptn_arp_type: arp_type as i32,
             next_index: 0,
             oct_up: 0,
+            last_step: 0,
             note_close_to: ptn.note,
             analys: ana,
             part,
@@ -146,9 +186,15 @@ impl DynamicPattern {
             estk.tg().get_meter().1,
         );
 
-        if self.arp_available {
+        if let Some(euc) = self.euclid {
+            // Euclidean rhythm
+            self.play_euclid(estk, root, tblptr, vel, euc);
+        } else if self.strum_available {
+            // Strum
+            self.play_strum(estk, root, tblptr, vel);
+        } else if self.arp_available {
             // Arpeggio
-            self.play_arpeggio(estk, root, tblptr, vel);
+            self.play_arpeggio(estk, root, tblptr, vel, self.play_counter);
         } else {
             // Cluster
             self.play_cluster(estk, root, tblptr, vel);
@@ -173,46 +219,192 @@ impl DynamicPattern {
     }
     fn play_cluster(&mut self, estk: &mut ElapseStack, root: i16, tblptr: &[i16], vel: i16) {
         // 最低ノートとpara設定から、各ノートのオクターブを算出
-        let mut ntlist: Vec<i16> = Vec::new();
-        for nt in tblptr {
-            let mut note = *nt + DEFAULT_NOTE_NUMBER as i16;
-            if self.para {
-                while note < self.ptn_min_nt {
-                    //展開
-                    note += 12;
-                }
-                //並行移動
-                note += root;
-            } else {
-                //並行移動
-                note += root;
-                while note < self.ptn_min_nt {
-                    //最低音以下の音をオクターブアップ
-                    note += 12;
-                }
-                while self.ptn_min_nt <= (note - 12) {
-                    //最低音のすぐ上に降ろす
-                    note -= 12;
-                }
-            }
-            ntlist.push(note);
-        }
+        let mut ntlist: Vec<i16> = tblptr.iter().map(|nt| self.place_note(*nt, root)).collect();
 
         // 低い順に並べ、同時発音数を決定する
         ntlist.sort();
         //println!("Cluster::{:?}/{}", ntlist, self.keynote);
-        let maxnt = if self.ptn_max_vce as usize > ntlist.len() {
+        let evolve = estk.get_evolve(self.part as usize) as usize;
+        let max_vce = self.ptn_max_vce as usize + evolve;
+        if evolve > 0 {
+            // evolve : 増やした声部は、構成音を上のオクターブに重ねて音域を広げる
+            let base = ntlist.len();
+            for i in base..max_vce {
+                let nt = ntlist[i - base] + 12;
+                if nt > MAX_NOTE_NUMBER as i16 {
+                    break;
+                }
+                ntlist.push(nt);
+            }
+        }
+        let maxnt = if max_vce > ntlist.len() {
             ntlist.len()
         } else {
-            self.ptn_max_vce as usize
+            max_vce
         };
 
         // Cluster発音
-        for &note in ntlist.iter().take(maxnt) {
-            self.gen_note_ev(estk, note, vel);
+        let roll = self.fit_roll(estk, estk.get_roll(self.part as usize) as i32, maxnt);
+        if roll == 0 {
+            for &note in ntlist.iter().take(maxnt) {
+                self.gen_note_ev(estk, note, vel, 0);
+            }
+        } else {
+            // roll : 表拍は下から(down)、裏拍は上から(up)順に鳴らす
+            let mut rolled: Vec<i16> = ntlist.into_iter().take(maxnt).collect();
+            let tick_for_beat = estk.tg().get_beat_tick().1;
+            if self.crnt_tick() % tick_for_beat != 0 {
+                rolled.reverse();
+            }
+            for (i, &note) in rolled.iter().enumerate() {
+                // 先に鳴る音ほど少し強くする
+                let tilt = Self::ROLL_VEL_TILT - (i as i16) * 2;
+                let rvel = (vel + tilt).clamp(1, 127);
+                self.gen_note_ev(estk, note, rvel, roll * i as i32);
+            }
         }
     }
-    fn play_arpeggio(&mut self, estk: &mut ElapseStack, root: i16, tblptr: &[i16], vel: i16) {
+    /// 最低ノートとpara設定から、和音の構成音のオクターブを決める
+    fn place_note(&self, nt: i16, root: i16) -> i16 {
+        let mut note = nt + DEFAULT_NOTE_NUMBER as i16;
+        if self.para {
+            while note < self.ptn_min_nt {
+                //展開
+                note += 12;
+            }
+            //並行移動
+            note += root;
+        } else {
+            //並行移動
+            note += root;
+            while note < self.ptn_min_nt {
+                //最低音以下の音をオクターブアップ
+                note += 12;
+            }
+            while self.ptn_min_nt <= (note - 12) {
+                //最低音のすぐ上に降ろす
+                note -= 12;
+            }
+        }
+        note
+    }
+    /// Euclidean rhythm : 打点の step だけ、root の単音/Cluster/Arpeggio を鳴らす
+    fn play_euclid(
+        &mut self,
+        estk: &mut ElapseStack,
+        root: i16,
+        tblptr: &[i16],
+        vel: i16,
+        euc: (i16, i16, i16, i16),
+    ) {
+        let (steps, pulses, rot, src) = euc;
+        let idx = (self.play_counter % steps as usize) as i16;
+        if !euclid_hit(pulses, steps, rot, idx) {
+            return;
+        }
+        match src {
+            EUC_SRC_ROOT => {
+                let note = self.place_note(tblptr[0], root);
+                self.gen_note_ev(estk, note, vel, 0);
+            }
+            EUC_SRC_ARP => self.play_arpeggio(estk, root, tblptr, vel, self.hit_counter),
+            _ => self.play_cluster(estk, root, tblptr, vel),
+        }
+        self.hit_counter += 1;
+    }
+    /// Guitar の Strum : 今の和音をギターの押さえ方にして、down は低音弦から、up は高音弦から鳴らす
+    fn play_strum(&mut self, estk: &mut ElapseStack, root: i16, tblptr: &[i16], vel: i16) {
+        let steps = (0..STRUM_MAX_STEP)
+            .take_while(|i| (self.ptn_arp_type >> (i * 2)) & 0x03 != 0)
+            .count()
+            .max(1);
+        let step = (self.ptn_arp_type >> ((self.play_counter % steps) * 2)) as i16 & 0x03;
+        if step != STRUM_DOWN && step != STRUM_UP {
+            return;
+        }
+        let mut notes = self.guitar_voicing(root, tblptr);
+        let mut vel = vel;
+        if step == STRUM_UP {
+            notes = notes.into_iter().rev().take(Self::UP_STRINGS).collect();
+            vel -= Self::UP_VEL_DIFF;
+        }
+        let roll = estk.get_roll(self.part as usize) as i32;
+        let strum_tick = if roll == 0 { Self::STRUM_TICK } else { roll };
+        let strum_tick = self.fit_roll(estk, strum_tick, notes.len());
+        for (i, &note) in notes.iter().enumerate() {
+            let tilt = Self::ROLL_VEL_TILT - (i as i16);
+            let svel = (vel + tilt).clamp(1, 127);
+            self.gen_note_ev(estk, note, svel, strum_tick * i as i32);
+        }
+    }
+    /// 標準チューニングで、ptn_min_nt(fret) 以上のポジションから FRET_SPAN 以内で押さえられる
+    /// 和音を探し、低音弦から順に返す(keynote を含まない音)
+    fn guitar_voicing(&self, root: i16, tblptr: &[i16]) -> Vec<i16> {
+        let key = self.keynote as i16;
+        let tones: Vec<i16> = if tblptr.len() > 5 {
+            // Scale の場合は、三度ずつ重ねた音を構成音とする
+            tblptr.iter().step_by(2).take(3).copied().collect()
+        } else {
+            tblptr.to_vec()
+        };
+        let chord: Vec<i16> = tones
+            .iter()
+            .map(|x| (x + root + key).rem_euclid(12))
+            .collect();
+        let root_pc = (root + key).rem_euclid(12);
+
+        let mut best: (usize, Vec<i16>) = (0, Vec::new());
+        for pos in self.ptn_min_nt.max(0)..=STRUM_MAX_POSITION {
+            let first_fret = if pos == 0 { 0 } else { pos };
+            let strings: Vec<Option<i16>> = Self::GUITAR_STRINGS
+                .iter()
+                .map(|s| {
+                    (first_fret..=pos + Self::FRET_SPAN)
+                        .map(|f| s + f)
+                        .find(|nt| chord.contains(&nt.rem_euclid(12)))
+                })
+                .collect();
+            // root が最低音になるように、低音弦を消音する
+            let lowest = strings
+                .iter()
+                .take(Self::MAX_MUTE_STRINGS + 1)
+                .position(|s| s.is_some_and(|nt| nt.rem_euclid(12) == root_pc))
+                .unwrap_or(0);
+            let notes: Vec<i16> = strings.iter().skip(lowest).flatten().copied().collect();
+            let covered = chord
+                .iter()
+                .filter(|pc| notes.iter().any(|nt| nt.rem_euclid(12) == **pc))
+                .count();
+            if covered == chord.len() && notes.len() >= Self::UP_STRINGS {
+                best = (covered, notes);
+                break;
+            } else if covered > best.0 {
+                best = (covered, notes);
+            }
+        }
+        best.1.iter().map(|nt| nt - key).collect()
+    }
+    fn crnt_tick(&self) -> i32 {
+        self.ptn_tick + self.ptn_each_dur * (self.play_counter as i32)
+    }
+    /// 最後の音が、音の長さと小節の残りを越えないように roll のずれを詰める
+    fn fit_roll(&self, estk: &ElapseStack, roll: i32, num: usize) -> i32 {
+        if num < 2 {
+            return roll;
+        }
+        let msr_left = estk.tg().get_crnt_msr_tick().tick_for_onemsr - self.crnt_tick();
+        let limit = (self.ptn_each_dur.min(msr_left) - 1).max(0);
+        roll.min(limit / (num as i32 - 1))
+    }
+    /// cnt: 何音目か
+    fn play_arpeggio(
+        &mut self,
+        estk: &mut ElapseStack,
+        root: i16,
+        tblptr: &[i16],
+        vel: i16,
+        cnt: usize,
+    ) {
         let max_tbl_num = tblptr.len();
         let incdec_idx = |inc: bool, mut x, mut oct| -> (usize, i16) {
             if inc {
@@ -244,14 +436,13 @@ impl DynamicPattern {
             pre_add_nt += root + 12;
         }
 
-        let mut note: i16;
-        if self.play_counter == 0 {
+        if cnt == 0 {
             // アルペジオの最初の音を決める
             let mut index = 0;
             let mut oct_up: i16 = 0;
             let mut old_inc: Option<bool> = None;
             loop {
-                note = tblptr[index] + pre_add_nt + oct_up * 12;
+                let note = tblptr[index] + pre_add_nt + oct_up * 12;
                 if note == self.note_close_to {
                     self.next_index = index;
                     self.oct_up = oct_up;
@@ -269,15 +460,58 @@ impl DynamicPattern {
                 }
                 (index, oct_up) = incdec_idx(inc, index, oct_up);
             }
-            note += post_add_nt;
+        }
+        // 最初の音から構成音をいくつ進めるか
+        let tbl_num = max_tbl_num as i16;
+        let step = self.arp_step(estk, tbl_num, cnt as i16);
+        self.last_step = step;
+        let pos = self.next_index as i16 + step;
+        let note = tblptr[pos.rem_euclid(tbl_num) as usize]
+            + pre_add_nt
+            + (self.oct_up + pos.div_euclid(tbl_num)) * 12
+            + post_add_nt;
+        self.gen_note_ev(estk, note, vel, 0);
+    }
+    /// 最初の音からの構成音の数(下降は負)
+    /// 一巡する音数の指定がなければ、上昇/下降はそのまま進み、他は一オクターブ分で折り返す
+    fn arp_step(&self, estk: &mut ElapseStack, tbl_num: i16, cnt: i16) -> i16 {
+        let arp_type = self.ptn_arp_type as i16;
+        let vce = if self.ptn_max_vce > 0 {
+            self.ptn_max_vce as i16
+        } else if arp_type == ARP_UP || arp_type == ARP_DOWN {
+            0
         } else {
-            (self.next_index, self.oct_up) = incdec_idx(up, self.next_index, self.oct_up);
-            note = tblptr[self.next_index] + pre_add_nt + self.oct_up * 12;
-            note += post_add_nt;
+            tbl_num + 1
+        };
+        match arp_type {
+            ARP_UP | ARP_DOWN => {
+                let step = if vce > 0 { cnt % vce } else { cnt };
+                if arp_type == ARP_UP {
+                    step
+                } else {
+                    -step
+                }
+            }
+            ARP_UPDOWN | ARP_DOWNUP => {
+                let vce = vce.max(2);
+                let period = 2 * (vce - 1);
+                let m = cnt % period;
+                let step = if m < vce { m } else { period - m };
+                if arp_type == ARP_UPDOWN {
+                    step
+                } else {
+                    -step
+                }
+            }
+            _ if cnt == 0 || vce < 2 => 0,
+            _ => {
+                // 直前と同じ音は避ける
+                let step = estk.rng(self.part).random_range(1..vce);
+                (self.last_step + step) % vce
+            }
         }
-        self.gen_note_ev(estk, note, vel);
     }
-    fn gen_note_ev(&mut self, estk: &mut ElapseStack, note: i16, vel: i16) {
+    fn gen_note_ev(&mut self, estk: &mut ElapseStack, note: i16, vel: i16, ofs_tick: i32) {
         let mut crnt_ev = PhrEvt {
             dur: self.ptn_each_dur as i16,
             note,
@@ -300,7 +534,7 @@ impl DynamicPattern {
                 self.keynote,
                 format!(" / Pt:{} Lp:{}", &self.part, &self.id.sid),
                 self.first_msr_num,
-                self.ptn_tick + self.ptn_each_dur * (self.play_counter as i32),
+                self.crnt_tick() + ofs_tick,
                 self.part,
             ),
         );
@@ -335,6 +569,7 @@ impl Elapse for DynamicPattern {
     fn clear(&mut self, _estk: &mut ElapseStack) {
         self.analys = Vec::new();
         self.play_counter = 0;
+        self.hit_counter = 0;
         self.last_note = NO_NOTE as i16;
         self.next_msr = 0;
         self.next_tick = 0;
@@ -0,0 +1,40 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::time::{Duration, Instant};
+
+//*******************************************************************
+//          Master Fade Struct
+//*******************************************************************
+//  全パートの velocity を、指定した時間[秒]をかけて 0 から 100% へ(fade in)、
+//  あるいは 100% から 0 へ(fade out)変化させる
+//  fade out が終わったら、再生を止める
+pub struct MasterFade {
+    start: Instant,
+    dur: Duration,
+    out: bool,
+}
+impl MasterFade {
+    /// sec: 正なら fade in, 負なら fade out
+    pub fn new(start: Instant, sec: i16) -> Self {
+        Self {
+            start,
+            dur: Duration::from_secs(sec.unsigned_abs().max(1) as u64),
+            out: sec < 0,
+        }
+    }
+    pub fn is_out(&self) -> bool {
+        self.out
+    }
+    pub fn is_end(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start) >= self.dur
+    }
+    pub fn gen_vel(&self, now: Instant, vel: u8) -> u8 {
+        let progress = (now.saturating_duration_since(self.start).as_secs_f32()
+            / self.dur.as_secs_f32())
+        .min(1.0);
+        let rate = if self.out { 1.0 - progress } else { progress };
+        (vel as f32 * rate).round().clamp(1.0, 127.0) as u8
+    }
+}
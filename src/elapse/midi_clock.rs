@@ -0,0 +1,69 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use super::tickgen::CrntMsrTick;
+use crate::lpnlib::*;
+
+//*******************************************************************
+//          Midi Clock Struct
+//*******************************************************************
+//  外部機器を loopian の tempo に合わせるため、MIDI Clock(0xF8) を 24/四分音符 で送る
+//  TickGen の進んだ tick 数から数えるので、rit. の曲線にも追従する
+pub struct MidiClock {
+    enable: bool,
+    last: Option<(i32, i32, i32)>, // 前回の (msr, tick, tick_for_onemsr)
+    rest: i32,                     // 前回の Clock からの tick 数
+}
+impl MidiClock {
+    /// Clock 一つ分の tick 数
+    const TICK_PER_CLOCK: i32 = DEFAULT_TICK_FOR_QUARTER / 24;
+
+    pub fn new() -> Self {
+        Self {
+            enable: false,
+            last: None,
+            rest: 0,
+        }
+    }
+    pub fn set(&mut self, enable: bool) {
+        self.enable = enable;
+        self.last = None;
+    }
+    pub fn is_enable(&self) -> bool {
+        self.enable
+    }
+    /// Start/Continue の時に呼び、次の tick で最初の Clock を送る
+    pub fn start(&mut self) {
+        self.last = None;
+        self.rest = 0;
+    }
+    /// 前回から進んだ tick 数に応じて、送るべき Clock の数を返す
+    pub fn count(&mut self, crnt_: &CrntMsrTick) -> i32 {
+        if !self.enable {
+            return 0;
+        }
+        let now = (crnt_.msr, crnt_.tick, crnt_.tick_for_onemsr);
+        let (msr, tick, tick_for_onemsr) = match self.last.replace(now) {
+            Some(l) => l,
+            None => {
+                // 今の tick から数え始める(演奏中に on にしても、溜まった Clock は送らない)
+                // Clock の境目にいれば、そこで一つ送る
+                self.rest = crnt_.tick % Self::TICK_PER_CLOCK;
+                return (self.rest == 0) as i32;
+            }
+        };
+        let delta = if msr == crnt_.msr {
+            crnt_.tick - tick
+        } else {
+            // 小節が変わった(jump も含む)
+            tick_for_onemsr - tick + crnt_.tick
+        };
+        if delta <= 0 {
+            return 0;
+        }
+        let total = self.rest + delta;
+        self.rest = total % Self::TICK_PER_CLOCK;
+        total / Self::TICK_PER_CLOCK
+    }
+}
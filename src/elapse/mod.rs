@@ -1,4 +1,8 @@
+pub mod chord_subst;
+pub mod doubling;
+pub mod edit_lock;
 pub mod elapse_base;
+pub mod elapse_click;
 pub mod elapse_damper;
 pub mod elapse_flow;
 pub mod elapse_loop_cmp;
@@ -6,6 +10,14 @@ pub mod elapse_loop_phr;
 pub mod elapse_note;
 pub mod elapse_part;
 pub mod elapse_pattern;
+pub mod fade;
+pub mod midi_clock;
 pub mod note_translation;
+pub mod punch_rec;
+pub mod rand_seed;
+pub mod set_list;
 pub mod stack_elapse;
+pub mod tempo_map;
 pub mod tickgen;
+pub mod tone_analysis;
+pub mod trigger;
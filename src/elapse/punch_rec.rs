@@ -0,0 +1,80 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use super::tickgen::CrntMsrTick;
+use crate::lpnlib::*;
+
+pub type PunchNote = (i32, i32, u8, i32, u8); // 弾いた音の (msr, tick, note, dur, vel)
+
+//*******************************************************************
+//          Punch Record Struct
+//*******************************************************************
+//  punch 区間 [in, out) の中で ORBIT から弾いた音を記録する
+//  区間の終わりで、記録した音をパートの Phrase の同じ位置に差し替える
+//  区間で一音も弾かなければ、Phrase はそのまま
+pub struct PunchRec {
+    region: Option<(i32, i32)>, // [in, out), 0origin
+    part: usize,
+    held: Vec<(u8, i32, i32, u8)>, // 鳴っている音の (note, msr, tick, vel)
+    rec: Vec<PunchNote>,
+}
+impl PunchRec {
+    pub fn new() -> Self {
+        Self {
+            region: None,
+            part: RIGHT1,
+            held: Vec::new(),
+            rec: Vec::new(),
+        }
+    }
+    /// punch 区間と、弾いた音を差し替える Part を設定する(None:解除)
+    pub fn set(&mut self, region: Option<(i32, i32)>, part: usize) {
+        self.region = region;
+        self.part = part;
+        self.clear();
+    }
+    pub fn clear(&mut self) {
+        self.held.clear();
+        self.rec.clear();
+    }
+    pub fn note_on(&mut self, crnt_: &CrntMsrTick, note: u8, vel: u8) {
+        if self
+            .region
+            .is_some_and(|(pin, pout)| (pin..pout).contains(&crnt_.msr))
+        {
+            self.held.retain(|h| h.0 != note);
+            self.held.push((note, crnt_.msr, crnt_.tick, vel));
+        }
+    }
+    pub fn note_off(&mut self, crnt_: &CrntMsrTick, note: u8) {
+        if let Some(idx) = self.held.iter().position(|h| h.0 == note) {
+            let (_, msr, tick, vel) = self.held.remove(idx);
+            let dur = (crnt_.msr - msr) * crnt_.tick_for_onemsr + crnt_.tick - tick;
+            self.rec.push((msr, tick, note, dur.max(1), vel));
+        }
+    }
+    /// 区間の終わりに来ていたら、(Part, 区間, 弾いた音) を返す
+    /// まだ離していない音は、区間の終わりまでの長さにする
+    pub fn punch_out(
+        &mut self,
+        crnt_: &CrntMsrTick,
+    ) -> Option<(usize, (i32, i32), Vec<PunchNote>)> {
+        let (pin, pout) = self.region?;
+        if crnt_.msr < pout || (self.rec.is_empty() && self.held.is_empty()) {
+            return None;
+        }
+        let end = CrntMsrTick {
+            msr: pout,
+            tick: 0,
+            ..*crnt_
+        };
+        let held: Vec<u8> = self.held.iter().map(|h| h.0).collect();
+        for note in held.into_iter() {
+            self.note_off(&end, note);
+        }
+        let mut rec = std::mem::take(&mut self.rec);
+        rec.sort_by_key(|r| (r.0, r.1));
+        Some((self.part, (pin, pout), rec))
+    }
+}
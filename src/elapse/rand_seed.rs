@@ -0,0 +1,69 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use crate::lpnlib::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+//*******************************************************************
+//          Random Seed Struct
+//*******************************************************************
+//  Part ごとの乱数生成器と、その種
+//  同じ種からは、同じ偶然性(velocity の揺れ、ランダムなアルペジオ、和音の置き換え)が再現される
+//  reroll した種は、次の小節の頭で入れ替わる
+pub struct RandSeed {
+    seed: [u32; RandSeed::MAX_PART],
+    rng: Vec<StdRng>,
+    pending: Vec<(Option<usize>, u32)>, // part(None:全体), 種
+}
+impl RandSeed {
+    pub const MAX_PART: usize = DAMPER_PEDAL_PART + 1;
+
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed: [seed; Self::MAX_PART],
+            rng: (0..Self::MAX_PART)
+                .map(|pt| StdRng::seed_from_u64(Self::part_seed(seed, pt)))
+                .collect(),
+            pending: Vec::new(),
+        }
+    }
+    /// 同じ種でも、Part ごとに違う乱数列にする
+    fn part_seed(seed: u32, part: usize) -> u64 {
+        ((seed as u64) << 8) | part as u64
+    }
+    pub fn rng(&mut self, part: usize) -> &mut StdRng {
+        &mut self.rng[part.min(Self::MAX_PART - 1)]
+    }
+    /// 次の小節の頭で入れ替える種を預かる
+    pub fn reserve(&mut self, part: Option<usize>, seed: u32) {
+        if part.is_none() {
+            self.pending.clear();
+        }
+        self.pending.retain(|(p, _)| p.is_none() || *p != part);
+        self.pending.push((part, seed));
+    }
+    /// 預かった種に入れ替え、その Part の乱数列だけやり直す
+    pub fn apply(&mut self) {
+        for (part, seed) in std::mem::take(&mut self.pending) {
+            match part {
+                Some(pt) if pt < Self::MAX_PART => {
+                    self.seed[pt] = seed;
+                    self.rng[pt] = StdRng::seed_from_u64(Self::part_seed(seed, pt));
+                }
+                Some(_) => (),
+                None => {
+                    self.seed = [seed; Self::MAX_PART];
+                    self.restart();
+                }
+            }
+        }
+    }
+    /// 今の種から乱数列をやり直す(演奏の開始時)
+    pub fn restart(&mut self) {
+        for (pt, rng) in self.rng.iter_mut().enumerate() {
+            *rng = StdRng::seed_from_u64(Self::part_seed(self.seed[pt], pt));
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use crate::lpnlib::*;
+
+//*******************************************************************
+//          Set List Struct
+//*******************************************************************
+//  scene を決めた小節数ずつ順に演奏し、小節の頭で次の scene に進める
+//  最後の scene に来たら、そのまま続ける
+//  小節番号ではなく、演奏した小節を数えるので、loop や practice で戻っても進む
+pub struct SetList {
+    entries: Vec<SetListEntry>,
+    crnt: usize,
+    played: i32, // crnt の scene で、頭を迎えた小節の数(0: まだ始まっていない)
+}
+impl SetList {
+    pub fn new(entries: Vec<SetListEntry>) -> Option<Self> {
+        (!entries.is_empty()).then_some(Self {
+            entries,
+            crnt: 0,
+            played: 0,
+        })
+    }
+    /// idx の scene に移る。次の小節の頭から数える
+    /// 戻り値: (cmd 側の scene 番号, scene の msg)
+    pub fn jump(&mut self, idx: usize) -> Option<(usize, Vec<ElpsMsg>)> {
+        let ent = self.entries.get(idx)?;
+        self.crnt = idx;
+        self.played = 0;
+        Some((ent.0, ent.3.clone()))
+    }
+    pub fn next_idx(&self) -> Option<usize> {
+        (self.crnt + 1 < self.entries.len()).then_some(self.crnt + 1)
+    }
+    /// 小節の頭で呼ぶ。今の scene の最後の小節なら、次の scene の番号を返す
+    pub fn msr_top(&mut self) -> Option<usize> {
+        self.played += 1;
+        if self.played >= self.entries[self.crnt].2 as i32 {
+            self.next_idx()
+        } else {
+            None
+        }
+    }
+    pub fn stop(&mut self) {
+        self.played = 0;
+    }
+    /// 表示用: verse 3/8 > chorus
+    pub fn indicator(&self) -> String {
+        let (_, name, msrs, _) = &self.entries[self.crnt];
+        let cnt = if self.played > 0 {
+            self.played.to_string()
+        } else {
+            "-".to_string()
+        };
+        let next = match self.next_idx() {
+            Some(n) => self.entries[n].1.as_str(),
+            None => "end",
+        };
+        format!("{} {}/{} > {}", name, cnt, msrs, next)
+    }
+}
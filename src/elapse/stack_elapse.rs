@@ -5,24 +5,48 @@
 //
 use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::VecDeque;
 use std::rc::Rc;
 use std::sync::mpsc;
 use std::sync::mpsc::TryRecvError;
 use std::sync::mpsc::{Receiver, Sender};
 use std::thread;
+use std::thread::JoinHandle;
 use std::time::{Duration, Instant};
 use std::vec::Vec;
 
+use super::chord_subst::ChordSubst;
+use super::doubling::DoublingFilter;
+use super::edit_lock::EditLock;
 use super::elapse_base::*;
+use super::elapse_click::Click;
 use super::elapse_damper::DamperPart;
 use super::elapse_flow::Flow;
 use super::elapse_loop_cmp::CompositionLoop;
 use super::elapse_loop_phr::PhraseLoop;
 use super::elapse_part::Part;
-use super::tickgen::{CrntMsrTick, RitType, TickGen};
+use super::fade::MasterFade;
+use super::midi_clock::MidiClock;
+use super::punch_rec::PunchRec;
+use super::rand_seed::RandSeed;
+use super::set_list::SetList;
+use super::tempo_map::TempoMap;
+use super::tickgen::{CrntMsrTick, RitType, TempoGesture, TickGen};
+use super::tone_analysis::ToneAnalyzer;
+use super::trigger::Trigger;
+use crate::audio::listen::Listener;
+use crate::file::light_bridge::LightBridge;
+use crate::file::settings::{CaptureSetting, LightSetting, Settings};
+use crate::file::setup_sheet::SetupSheet;
+use crate::file::stats::StatsCollector;
 use crate::lpnlib::{ElpsMsg::*, *};
+use crate::midi::capture::MidiCapture;
 use crate::midi::midirx::MidiRx;
 use crate::midi::miditx::MidiTx;
+use crate::midi::monitor::MidiMonitor;
+use crate::midi::route::{MidiRoute, PartRoute};
+use crate::midi::watchdog::MidiWatchdog;
+use rand::rngs::StdRng;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum SameKeyState {
@@ -31,6 +55,20 @@ pub enum SameKeyState {
     Nothing, //  もうない
 }
 
+//*******************************************************************
+//          Practice Loop Struct
+//*******************************************************************
+//  指定小節区間を繰り返し、rpt 回ごとに bpm を inc_bpm だけ上げる
+#[derive(Debug, Copy, Clone)]
+struct PracticeLoop {
+    start_msr: i32, // 0origin
+    end_msr: i32,   // 0origin, この小節の終わりで start_msr に戻る
+    inc_bpm: i16,
+    rpt: i32,
+    pass_cnt: i32,
+    step: i32, // 1origin
+}
+
 //*******************************************************************
 //          Elapse Stack Struct
 //*******************************************************************
@@ -42,12 +80,49 @@ pub struct ElapseStack {
     ui_hndr: mpsc::Sender<UiMsg>,
     rx_hndr: mpsc::Receiver<ElpsMsg>,
     tx_ctrl: mpsc::Sender<ElpsMsg>,
+    rx_thread: Option<JoinHandle<bool>>,
     mdx: MidiTx,
+    route: MidiRoute, // Part ごとの出力 ch と port
+    wdog: MidiWatchdog,
+    mcap: MidiCapture,
+    monitor: MidiMonitor,
+    stats: StatsCollector,
+    light: LightBridge,
+    tone: ToneAnalyzer,
+    doubling: DoublingFilter,
+    subst: ChordSubst,
+    edit_lock: EditLock,
+    seed: RandSeed,
+    quantize: i16,   // MSG_SET_QUANTIZE の値(0:off)
+    note_map_ch: u8, // Note On を UI に送る ch(1-16), 0:off
+    listener: Option<Listener>,
+    humanize: bool,  // velocity を揺らす(テスト時は false)
+    thru: bool,      // Flow への入力を MIDI Rx thread からそのまま出力する
+    conduct_cc: i16, // テンポを揺らす CC 番号(-1:off)
 
     crnt_time: Instant,
     bpm_stock: i16,
+    tempo_map: TempoMap,    // 小節ごとの bpm の変化
+    trigger: Trigger,       // 曲の出来事に合わせて鳴らす one-shot の音
+    song_msr: i32,          // 曲の小節数(0:最長の loop の長さを使う)
+    disp_trns: Option<i16>, // 和音名表示の移調(None:度数表示)
+    spell: i16,             // 音名表示の綴り
+    notename: i16,          // 入力した音の名前の表示(NOTENAME_*)
+    // 決めた小節数ずつ順に演奏する scene
+    set_list: Option<SetList>,
     beat_stock: Meter,
     fine_stock: bool,
+    fade: Option<MasterFade>,
+    clock: MidiClock,
+    ext_start: Option<bool>, // 外部から受けた Start(false)/Continue(true)
+    practice: Option<PracticeLoop>,
+    loop_rgn: Option<(i32, i32)>, // 全体を繰り返す小節区間 (start, end), 0origin
+    song_pos: Option<i32>,        // Song Position で次の小節頭から移る小節, 0origin
+    punch_rec: PunchRec,          // punch 区間で弾いた音
+    mute: [bool; MAX_KBD_PART],
+    reserved_msg: Vec<(i32, ElpsMsg)>, // 指定小節の頭で実行する msg
+    msr_text: Vec<(i32, String)>,      // 小節に付けた歌詞やメモ(小節順)
+    text_shown: Option<i32>,           // 表示中の msr_text の小節
 
     during_play: bool,
     display_time: Instant,
@@ -55,35 +130,68 @@ pub struct ElapseStack {
     flac: u64,
     part_vec: Vec<Rc<RefCell<Part>>>, // Part Instance が繋がれた Vec
     damper_part: Rc<RefCell<DamperPart>>,
+    click: Rc<RefCell<Click>>,
     elapse_vec: Vec<Rc<RefCell<dyn Elapse>>>, // dyn Elapse Instance が繋がれた Vec
-    key_map: [i32; (MAX_NOTE_NUMBER - MIN_NOTE_NUMBER + 1) as usize],
+    key_map: [[i32; (MAX_NOTE_NUMBER - MIN_NOTE_NUMBER + 1) as usize]; 16], // [ch][note]
     limit_for_deb: i32,
 }
 //*******************************************************************
 //          Public Method for Elapse Stack Struct
 //*******************************************************************
-fn gen_midirx_thread() -> (Receiver<ElpsMsg>, Sender<ElpsMsg>) {
+/// thread の戻り値: MIDI Rx が動いていたら true, 初期化できなかったら false
+fn gen_midirx_thread() -> (Receiver<ElpsMsg>, Sender<ElpsMsg>, JoinHandle<bool>) {
     //  create new thread & channel
     let (txmsg, rxmsg) = mpsc::channel();
     let (txctrl, rxctrl) = mpsc::channel();
-    thread::spawn(move || match MidiRx::new(txmsg /* , rxctrl*/) {
+    let hndl = thread::spawn(move || match MidiRx::new(txmsg /* , rxctrl*/) {
         Some(mut rx) => loop {
             if rx.periodic(rxctrl.try_recv()) {
-                break;
+                break true;
             }
         },
         None => {
-            println!("MIDI Rx thread does't work")
+            println!("MIDI Rx thread does't work");
+            false
         }
     });
-    (rxmsg, txctrl)
+    (rxmsg, txctrl, hndl)
 }
 impl ElapseStack {
+    const MAX_PROCESS_IN_TICK: i32 = 100; // 一回の periodic で process を呼べる上限
+    const THREAD_JOIN_MSEC: u64 = 500;
+    const UPCOMING_NUM: usize = 16; // UI に送る、これから鳴る音の数
+
     pub fn new(ui_hndr: mpsc::Sender<UiMsg>) -> Self {
         let (c, e) = MidiTx::connect();
         if let Some(err) = e {
             println!("{}", err);
         }
+        let (rx_hndr, tx_ctrl, rx_thread) = gen_midirx_thread();
+        let mut stk = Self::build(ui_hndr, c, rx_hndr, tx_ctrl, Some(rx_thread));
+        let sts = Settings::load_settings();
+        stk.light = LightBridge::new(&sts.light);
+        stk.mcap = MidiCapture::new(sts.capture.minutes);
+        stk
+    }
+    /// 結合テスト用: MIDI 機器にも MIDI Rx thread にも繋がず、MIDI 出力を記録する
+    /// rx_hndr から MIDIRx を送れば、MIDI 入力の代わりになる
+    #[cfg(test)]
+    pub fn new_mock(
+        ui_hndr: mpsc::Sender<UiMsg>,
+        rx_hndr: Receiver<ElpsMsg>,
+        tx_ctrl: Sender<ElpsMsg>,
+    ) -> Self {
+        let mut stk = Self::build(ui_hndr, MidiTx::mock(), rx_hndr, tx_ctrl, None);
+        stk.humanize = false;
+        stk
+    }
+    fn build(
+        ui_hndr: mpsc::Sender<UiMsg>,
+        mdx: MidiTx,
+        rx_hndr: Receiver<ElpsMsg>,
+        tx_ctrl: Sender<ElpsMsg>,
+        rx_thread: Option<JoinHandle<bool>>,
+    ) -> Self {
         let mut part_vec = Vec::new();
         let mut elapse_vec = Vec::new();
 
@@ -103,25 +211,64 @@ impl ElapseStack {
         // Damper Part
         let damper_part = DamperPart::new(DAMPER_PEDAL_PART as u32);
         elapse_vec.push(Rc::clone(&damper_part) as Rc<RefCell<dyn Elapse>>);
+        // Click
+        let click = Click::new(CLICK_PART as u32);
+        elapse_vec.push(Rc::clone(&click) as Rc<RefCell<dyn Elapse>>);
 
-        let (rx_hndr, tx_ctrl) = gen_midirx_thread();
         Self {
             ui_hndr,
             rx_hndr,
             tx_ctrl,
-            mdx: c,
+            rx_thread,
+            mdx,
+            route: MidiRoute::new(),
+            wdog: MidiWatchdog::new(),
+            mcap: MidiCapture::new(CaptureSetting::default().minutes),
+            monitor: MidiMonitor::new(),
+            stats: StatsCollector::new(),
+            light: LightBridge::new(&LightSetting::default()),
+            tone: ToneAnalyzer::new(),
+            doubling: DoublingFilter::new(),
+            subst: ChordSubst::new(),
+            edit_lock: EditLock::new(),
+            seed: RandSeed::new(rand::random::<u32>()),
+            quantize: 0,
+            note_map_ch: 0,
+            listener: None,
+            humanize: true,
+            thru: false,
+            conduct_cc: -1,
             crnt_time: Instant::now(),
             bpm_stock: DEFAULT_BPM,
+            tempo_map: TempoMap::new(),
+            trigger: Trigger::new(),
+            set_list: None,
+            song_msr: 0,
+            disp_trns: None,
+            spell: SPELL_AUTO,
+            notename: NOTENAME_OFF,
             beat_stock: Meter(4, 4),
             fine_stock: false,
+            fade: None,
+            clock: MidiClock::new(),
+            ext_start: None,
+            practice: None,
+            loop_rgn: None,
+            song_pos: None,
+            punch_rec: PunchRec::new(),
+            mute: [false; MAX_KBD_PART],
+            reserved_msg: Vec::new(),
+            msr_text: Vec::new(),
+            text_shown: None,
             during_play: false,
             display_time: Instant::now(),
             tg: TickGen::new(RitType::Sigmoid),
             flac: 0,
             part_vec: part_vec.clone(),
             damper_part,
+            click,
             elapse_vec,
-            key_map: [0; (MAX_NOTE_NUMBER - MIN_NOTE_NUMBER + 1) as usize],
+            key_map: [[0; (MAX_NOTE_NUMBER - MIN_NOTE_NUMBER + 1) as usize]; 16],
             limit_for_deb: 0,
         }
     }
@@ -138,6 +285,21 @@ impl ElapseStack {
             self.elapse_vec.remove(remove_index);
         }
     }
+    /// 暴走した elapse obj. を elapse_vec から外し、UI にエラーを知らせる
+    fn quarantine(&mut self, elps: Rc<RefCell<dyn Elapse>>, crnt_: &CrntMsrTick) {
+        self.elapse_vec.retain(|x| !Rc::ptr_eq(x, &elps));
+        let id = elps.borrow().id();
+        let err = format!(
+            "Error! Elapse Obj. was removed. pid:{} sid:{} type:{:?} at {}:{}",
+            id.pid,
+            id.sid,
+            id.elps_type,
+            crnt_.msr + 1,
+            crnt_.tick
+        );
+        println!("{}", err);
+        self.send_msg_to_ui(UiMsg::ErrorUi(err));
+    }
     pub fn _get_part(&mut self, id: ElapseId) -> Option<Rc<RefCell<Part>>> {
         if let Some(index) = self.part_vec.iter().position(|x| x.borrow().id() == id) {
             let part = Rc::clone(&self.part_vec[index]);
@@ -152,25 +314,120 @@ impl ElapseStack {
     pub fn get_cmps(&self, part_num: usize) -> Option<Rc<RefCell<CompositionLoop>>> {
         self.part_vec[part_num].borrow().get_cmps()
     }
+    pub fn get_pad(&self, part_num: usize) -> Option<i16> {
+        if part_num < MAX_KBD_PART {
+            self.part_vec[part_num].borrow().get_pad()
+        } else {
+            None
+        }
+    }
+    /// 和音を出力する MIDI ch(1-16)、None なら通常通り Phrase を鳴らす
+    pub fn get_chord_out(&self, part_num: usize) -> Option<i16> {
+        if part_num < MAX_KBD_PART {
+            self.part_vec[part_num].borrow().get_chord_out()
+        } else {
+            None
+        }
+    }
+    /// Phrase Loop が何回目か(0origin)と、最後の回か
+    pub fn loop_iteration(&self, part_num: usize) -> (u32, bool) {
+        if part_num < MAX_KBD_PART {
+            self.part_vec[part_num].borrow().loop_iteration()
+        } else {
+            (0, false)
+        }
+    }
+    /// evolve の設定で、Cluster に加える声部の数
+    pub fn get_evolve(&self, part_num: usize) -> i16 {
+        if part_num < MAX_KBD_PART {
+            self.part_vec[part_num].borrow().get_evolve()
+        } else {
+            0
+        }
+    }
+    /// riser の設定で、msr に鳴らす音の移調量
+    pub fn get_riser(&self, part_num: usize, msr: i32) -> i16 {
+        if part_num < MAX_KBD_PART {
+            self.part_vec[part_num].borrow_mut().get_riser(msr)
+        } else {
+            0
+        }
+    }
+    /// Loop 内の小節(0origin)が mask で鳴らさない小節か
+    pub fn is_masked(&self, part_num: usize, msr_in_loop: i32) -> bool {
+        part_num < MAX_KBD_PART && self.part_vec[part_num].borrow().is_masked(msr_in_loop)
+    }
+    pub fn get_roll(&self, part_num: usize) -> i16 {
+        self.part_vec[part_num].borrow().get_roll()
+    }
     pub fn get_flow(&self) -> Option<Rc<RefCell<Flow>>> {
         self.part_vec[FLOW_PART].borrow().get_flow()
     }
     pub fn tg(&self) -> &TickGen {
         &self.tg
     }
-    pub fn inc_key_map(&mut self, key_num: u8, vel: u8, pt: u8) {
-        self.key_map[(key_num - MIN_NOTE_NUMBER) as usize] += 1;
+    pub fn humanize(&self) -> bool {
+        self.humanize
+    }
+    /// Flow への入力は、MIDI Rx thread が既に出力している
+    pub fn direct_thru(&self) -> bool {
+        self.thru
+    }
+    /// new_mock で生成した時の、記録された MIDI 出力を取り出す
+    #[cfg(test)]
+    pub fn take_midi_log(&mut self) -> Vec<(u8, u8, u8)> {
+        self.mdx.take_log()
+    }
+    /// 演奏する音(keynote を含む)を、今の和音の構成音/Tension/非和声音に分類して数え、
+    /// velocity の増減を返す
+    pub fn put_tone(&mut self, part: u32, root: i16, ctbl: i16, note: i16, keynote: u8) -> i16 {
+        self.tone
+            .put(part as usize, root, ctbl, note - keynote as i16)
+    }
+    /// 全体の quantize の設定(MSG_SET_QUANTIZE の形式)
+    pub fn get_quantize(&self) -> i16 {
+        self.quantize
+    }
+    /// Composition Loop の開始時に、設定された確率で和音を置き換える
+    pub fn substitute_chords(&mut self, part: u32, evts: Vec<ChordEvt>) -> Vec<ChordEvt> {
+        let rng = self.seed.rng(part as usize);
+        self.subst.apply(evts, rng)
+    }
+    /// Part ごとの乱数生成器(reroll で種が変わる)
+    pub fn rng(&mut self, part: u32) -> &mut StdRng {
+        self.seed.rng(part as usize)
+    }
+    /// 他のパートと同じ高さの音にならないように、鳴らす note を返す(None:鳴らさない)
+    pub fn check_doubling(&self, part: u32, note: u8) -> Option<u8> {
+        self.doubling.check(part, note)
+    }
+    pub fn doubling_rank(&self, part: u32) -> u32 {
+        self.doubling.rank(part)
+    }
+    pub fn doubling_on(&mut self, part: u32, note: u8) {
+        self.doubling.note_on(part, note);
+    }
+    pub fn doubling_off(&mut self, part: u32, note: u8) {
+        self.doubling.note_off(part, note);
+    }
+    pub fn is_muted(&self, part: u32) -> bool {
+        (part as usize) < MAX_KBD_PART && self.mute[part as usize]
+    }
+    /// 同じ ch の同じ音を数える。ch が違えば別の音として扱う
+    pub fn inc_key_map(&mut self, key_num: u8, vel: u8, pt: u8, ch: u8) {
+        self.key_map[(ch & 0x0f) as usize][(key_num - MIN_NOTE_NUMBER) as usize] += 1;
+        self.stats.put_note(pt as usize);
         self.send_msg_to_ui(UiMsg::NoteUi(NoteUiEv { key_num, vel, pt }));
     }
-    pub fn dec_key_map(&mut self, key_num: u8) -> SameKeyState {
-        let idx = (key_num - MIN_NOTE_NUMBER) as usize;
-        match self.key_map[idx].cmp(&1) {
+    pub fn dec_key_map(&mut self, key_num: u8, ch: u8) -> SameKeyState {
+        let cnt = &mut self.key_map[(ch & 0x0f) as usize][(key_num - MIN_NOTE_NUMBER) as usize];
+        match (*cnt).cmp(&1) {
             Ordering::Greater => {
-                self.key_map[idx] -= 1;
+                *cnt -= 1;
                 SameKeyState::More
             }
             Ordering::Equal => {
-                self.key_map[idx] = 0;
+                *cnt = 0;
                 SameKeyState::Last
             }
             Ordering::Less => SameKeyState::Nothing,
@@ -185,10 +442,179 @@ impl ElapseStack {
         self.part_vec[part_num].borrow_mut().set_loop_end();
     }
     pub fn midi_out(&mut self, status: u8, data1: u8, data2: u8) {
-        self.mdx.midi_out(status, data1, data2, true);
+        self.midi_out_from(None, status, data1, data2);
+    }
+    /// 和音が変わったら、属和音への変化の one-shot を調べる
+    /// at: 和音の位置(msr, tick)
+    pub fn trigger_chord(&mut self, part: u32, root: i16, tbl: i16, at: (i32, i32)) {
+        if !self.trigger.is_empty() {
+            self.trigger.chord(part as usize, root, tbl, at);
+        }
+    }
+    fn trigger_out(&mut self, notes: Vec<(u8, u8, u8)>) {
+        for (status, note, vel) in notes.into_iter() {
+            self.midi_out(status, note, vel);
+        }
+    }
+    /// 和音が変わったことを照明に送る(degree: key からの度数 0-11)
+    pub fn light_chord(&mut self, part: u32, name: &str, degree: i16) {
+        self.light.chord(part as usize, name, degree);
+    }
+    /// 出力元の Part が分かっている時は、MIDI Monitor に Part 名を付ける
+    pub fn midi_out_from(&mut self, part: Option<usize>, status: u8, data1: u8, data2: u8) {
+        let (status, port) = self.route.apply_note(part, status, data1, data2);
+        self.mdx
+            .midi_out_port(port.as_deref(), status, data1, data2);
+        self.mdx.midi_out_for_led(part, status, data1, data2);
+        self.mcap.put(self.crnt_time, status, data1, data2);
+        self.monitor_msg(false, part, status, data1, data2);
+    }
+    /// click は LED にも録音にも送らない
+    pub fn click_out(&mut self, status: u8, data1: u8, data2: u8) {
+        let (status, port) = self.route.apply(Some(CLICK_PART), status);
+        self.mdx.midi_out_port(port, status, data1, data2);
+        self.monitor_msg(false, Some(CLICK_PART), status, data1, data2);
+    }
+    /// MIDI Monitor が on なら、filter を通った message を UI に送る
+    fn monitor_msg(&self, input: bool, part: Option<usize>, sts: u8, dt1: u8, dt2: u8) {
+        if !self.monitor.is_enable() {
+            return;
+        }
+        let crnt_ = self.tg.get_crnt_msr_tick();
+        if let Some(line) = self
+            .monitor
+            .line(input, (crnt_.msr, crnt_.tick), part, sts, dt1, dt2)
+        {
+            self.send_msg_to_ui(UiMsg::MonitorUi(Some(line)));
+        }
+    }
+    /// velocity から CC を生成する設定がある Part なら、Note On の前に CC を送る
+    pub fn vel_cc_out(&mut self, part: u32, vel: u8) {
+        if part as usize >= MAX_KBD_PART {
+            return;
+        }
+        let cc = self.part_vec[part as usize].borrow_mut().gen_vel_cc(vel);
+        if let Some((num, val)) = cc {
+            self.midi_out_from(Some(part as usize), 0xb0, num, val);
+        }
+    }
+    /// pan の設定がある Part なら、Note On の前に CC10 を送る
+    pub fn pan_out(&mut self, part: u32, note: u8, ch: u8) {
+        if part as usize >= MAX_KBD_PART {
+            return;
+        }
+        let pan = self.part_vec[part as usize].borrow().gen_pan(note);
+        if let Some(val) = pan {
+            self.midi_out_from(Some(part as usize), 0xb0 | ch, 10, val);
+        }
+    }
+    /// 鳴らした音を、freeze 用に Part に記録する
+    pub fn rec_freeze(&self, part: u32, msr: i32, tick: i32, ev: PhrEvt) {
+        if part as usize >= MAX_KBD_PART {
+            return;
+        }
+        let tick_for_onemsr = self.tg.get_crnt_msr_tick().tick_for_onemsr;
+        self.part_vec[part as usize]
+            .borrow_mut()
+            .rec_freeze(msr, tick, tick_for_onemsr, ev);
+    }
+    /// velocity ramp の設定がある Part なら、Loop 内の位置に応じた velocity を返す
+    pub fn ramp_vel(&self, part: u32, msr: i32, tick: i32, vel: u8) -> u8 {
+        if part as usize >= MAX_KBD_PART {
+            return vel;
+        }
+        let tick_for_onemsr = self.tg.get_crnt_msr_tick().tick_for_onemsr;
+        self.part_vec[part as usize]
+            .borrow()
+            .gen_ramp_vel(msr, tick, tick_for_onemsr, vel)
+    }
+    /// velocity の下限/上限の設定がある Part なら、その範囲に収めた velocity を返す
+    pub fn limit_vel(&self, part: u32, vel: u8) -> u8 {
+        if part as usize >= MAX_KBD_PART {
+            return vel;
+        }
+        self.part_vec[part as usize].borrow().limit_vel(vel)
+    }
+    /// fade 中なら、経過時間に応じた velocity を返す
+    pub fn fade_vel(&self, vel: u8) -> u8 {
+        match &self.fade {
+            Some(f) => f.gen_vel(self.crnt_time, vel),
+            None => vel,
+        }
+    }
+    /// keyswitch の設定がある Part なら、Note On の前に keyswitch を送る
+    pub fn keysw_out(&mut self, part: u32, artic: i16, ch: u8) {
+        if part as usize >= MAX_KBD_PART {
+            return;
+        }
+        let ks = self.part_vec[part as usize].borrow_mut().gen_keysw(artic);
+        if let Some((status, dt1, dt2)) = ks {
+            // keyswitch は LED には送らない
+            let (sts, port) = self.route.apply(Some(part as usize), status | ch);
+            self.mdx.midi_out_port(port, sts, dt1, dt2);
+            self.mcap.put(self.crnt_time, sts, dt1, dt2);
+            self.monitor_msg(false, Some(part as usize), sts, dt1, dt2);
+            if status == 0x90 {
+                self.mdx.midi_out_port(port, sts, dt1, 0);
+                self.mcap.put(self.crnt_time, sts, dt1, 0);
+                self.monitor_msg(false, Some(part as usize), sts, dt1, 0);
+            }
+        }
+    }
+    /// 音域で ch を分ける設定がある Part なら、note の ch を返す
+    pub fn split_ch(&self, part: u32, note: u8, ch: u8) -> u8 {
+        if part as usize >= MAX_KBD_PART {
+            return ch;
+        }
+        self.part_vec[part as usize]
+            .borrow()
+            .gen_split_ch(note)
+            .unwrap_or(ch)
+    }
+    /// tuning の設定がある Part なら、Note On の前に pitch bend を送り、発音する ch を返す
+    pub fn tuning_out(&mut self, part: u32, note: u8, keynote: u8, ch: u8) -> u8 {
+        if part as usize >= MAX_KBD_PART {
+            return ch;
+        }
+        let pb = self.part_vec[part as usize]
+            .borrow_mut()
+            .gen_tuning(note, keynote);
+        if let Some((tch, lsb, msb, stolen)) = pb {
+            if let Some(snote) = stolen {
+                // 止めた音の Note Off は、後で重ねて送らない
+                self.dec_key_map(snote, tch);
+                self.midi_out_from(Some(part as usize), 0x90 | tch, snote, 0);
+            }
+            self.midi_out_from(Some(part as usize), 0xe0 | tch, lsb, msb);
+            tch
+        } else {
+            ch
+        }
+    }
+    /// tuning で使った ch を空ける
+    pub fn tuning_off(&mut self, part: u32, ch: u8, note: u8) {
+        if (part as usize) < MAX_KBD_PART {
+            self.part_vec[part as usize]
+                .borrow_mut()
+                .tuning_off(ch, note);
+        }
     }
     pub fn midi_out_flow(&mut self, status: u8, data1: u8, data2: u8) {
-        self.mdx.midi_out(status, data1, data2, false);
+        let status = status & 0xf0; // Flow は ch.1 で出力
+        let (status, port) = self.route.apply_note(Some(FLOW_PART), status, data1, data2);
+        self.mdx
+            .midi_out_port(port.as_deref(), status, data1, data2);
+        self.mcap.put(self.crnt_time, status, data1, data2);
+        self.monitor_msg(false, Some(FLOW_PART), status, data1, data2);
+    }
+    /// thru の時: MIDI Rx thread が出力済みなので、出力以外(route、記録、LED)だけ行う
+    pub fn midi_thru_flow(&mut self, status: u8, data1: u8, data2: u8) {
+        let status = status & 0xf0;
+        let (status, _port) = self.route.apply_note(Some(FLOW_PART), status, data1, data2);
+        self.mcap.put(self.crnt_time, status, data1, data2);
+        self.monitor_msg(false, Some(FLOW_PART), status, data1, data2);
+        let vel = if (status & 0xf0) == 0x90 { data2 } else { 0 };
+        self.punch_note(data1, vel);
     }
     pub fn midi_out_ext(&mut self, status: u8, data1: u8, data2: u8) {
         self.mdx.midi_out_only_for_another(status, data1, data2);
@@ -197,30 +623,56 @@ impl ElapseStack {
     //      Periodic
     //*******************************************************************
     pub fn periodic(&mut self, msg: Result<ElpsMsg, TryRecvError>) -> bool {
-        self.crnt_time = Instant::now();
+        self.periodic_at(msg, Instant::now())
+    }
+    /// 時刻を外から与える periodic (テストでは擬似的な時刻を与える)
+    pub fn periodic_at(&mut self, msg: Result<ElpsMsg, TryRecvError>, time: Instant) -> bool {
+        self.crnt_time = time;
 
         // message 受信処理
         if self.handle_msg(msg) {
-            self.send_msg_to_rx(ElpsMsg::Ctrl(MSG_CTRL_QUIT));
+            self.shutdown();
             return true;
         }
+        self.watchdog();
+        self.ext_start();
 
         //  新tick計算
         let mut crnt_ = CrntMsrTick::default();
+        let mut at_msrtop = false;
         if self.during_play {
             let (msrtop, beattop, beatnum) = self.tg.gen_tick(self.crnt_time);
             crnt_ = self.tg.get_crnt_msr_tick();
+            let fade_end = self.fade.as_ref().filter(|f| f.is_end(self.crnt_time));
+            if let Some(out) = fade_end.map(|f| f.is_out()) {
+                self.fade = None;
+                if out {
+                    // fade out が終わったら止める
+                    self.stop_playing();
+                    return false;
+                }
+            }
             if msrtop {
                 if self.fine_stock {
-                    self.stop();
+                    self.stop_playing();
                     self.fine_stock = false;
                 } else {
+                    self.practice_jump(&mut crnt_);
+                    self.loop_rgn_jump(&mut crnt_);
+                    self.song_pos_jump(&mut crnt_);
                     self.measure_top(&mut crnt_);
+                    // lock 中に預かった変更を、パートが次の小節を始める前に実行する
+                    let deferred = self.edit_lock.flush();
+                    self.release_deferred(deferred);
+                    at_msrtop = true;
                 }
             }
+            self.clock_out(&crnt_);
             if beattop {
+                self.light.beat(beatnum);
                 self.send_msg_to_ui(UiMsg::NewBeat(beatnum));
             }
+            self.follow_onset();
         };
 
         //  for GUI
@@ -244,7 +696,11 @@ impl ElapseStack {
                 }
                 felps.borrow_mut().process(&crnt_, self);
                 debcnt += 1;
-                assert!(debcnt < 100, "Last Tick:{:?}", crnt_.tick);
+                if debcnt >= Self::MAX_PROCESS_IN_TICK {
+                    // 同じ tick で処理が終わらない obj は、強制的に取り除いて演奏を続ける
+                    self.quarantine(felps, &crnt_);
+                    debcnt = 0;
+                }
             }
             if self.limit_for_deb < debcnt {
                 self.limit_for_deb = debcnt;
@@ -252,6 +708,15 @@ impl ElapseStack {
 
             // remove ended obj
             self.destroy_finished_elps();
+
+            // one-shot の音を鳴らし、一小節鳴らした音を止める
+            let notes = self.trigger.due(&crnt_);
+            self.trigger_out(notes);
+
+            // set list の次の scene は、小節の頭の処理の後に送り、次の小節の頭で揃えて切り替える
+            if at_msrtop {
+                self.set_list_msr_top();
+            }
         }
 
         // play 中でなければ return
@@ -278,6 +743,29 @@ impl ElapseStack {
             self.tg.change_beat_event(tick_for_onemsr, self.beat_stock);
             *crnt_ = self.tg.get_crnt_msr_tick(); //再設定
         }
+        self.stats.new_measure(crnt_.msr, self.get_bpm());
+        self.light.msr(crnt_.msr);
+        if self.tone.is_enable() {
+            let tone = self.tone.new_measure();
+            self.send_msg_to_ui(UiMsg::ToneUi(Some(tone)));
+        }
+        // reroll された種に入れ替える
+        self.seed.apply();
+        // 次の小節の頭で tempo map の bpm に変える
+        if let Some(bpm) = self.tempo_map.change_at(crnt_.msr + 1) {
+            self.tg.change_bpm(bpm);
+        }
+        // 予約された msg の実行
+        self.exec_reserved_msg(crnt_.msr);
+        self.punch_note_out(crnt_);
+        // section / loop end の one-shot
+        if !self.trigger.is_empty() {
+            let loop_end = self.part_vec[0..MAX_KBD_PART]
+                .iter()
+                .any(|pt| pt.borrow().is_cmps_last_msr(crnt_));
+            self.trigger.msr_top(loop_end, crnt_);
+        }
+        self.update_msr_text(crnt_.msr);
         // for GUI(8indicator)
         self.update_gui_at_msrtop();
     }
@@ -295,6 +783,16 @@ impl ElapseStack {
                             self.parse_elps_msg(n)
                         }
                     }
+                    _ if self.during_play => {
+                        let crnt_ = self.tg.get_crnt_msr_tick();
+                        let (_, tick_for_beat) = self.tg.get_beat_tick();
+                        match self.edit_lock.check(n, &crnt_, tick_for_beat) {
+                            Some(m) => self.parse_elps_msg(m),
+                            None => self.send_msg_to_ui(UiMsg::ErrorUi(
+                                "Locked: the change will start after this measure.".to_string(),
+                            )),
+                        }
+                    }
                     _ => self.parse_elps_msg(n),
                 }
                 //if n[0] == MSG_QUIT {return true;}
@@ -310,6 +808,7 @@ impl ElapseStack {
             Ctrl(m) => self.ctrl_msg(m),
             Sync(m) => self.sync(m),
             Rit(m) => self.rit(m),
+            RitChain(m) => self.rit_chain(m),
             Set(m) => self.setting_cmnd(m),
             Efct(m) => self.efct(m),
             SetMeter(m) => self.set_meter(m),
@@ -317,24 +816,60 @@ impl ElapseStack {
             Cmp(m0, mv) => self.composition(m0, mv),
             PhrX(m) => self.del_phrase(m),
             CmpX(m) => self.del_composition(m),
+            Practice(m) => self.set_practice(m),
+            LoopRgn(m) => self.set_loop_rgn(m),
+            Punch(m) => self.set_punch(m),
+            SetPart(m0, mv) => self.setting_part(m0, mv),
+            Listen(m) => self.listen(m),
+            Mute(m0, mv) => self.set_mute(m0, mv),
+            Mask(m0, mv) => self.set_mask(m0, mv),
+            Riser(m0, m1, mv) => self.set_riser(m0, m1, mv),
+            Reserve(m0, mv) => self.reserved_msg.push((m0 as i32, *mv)),
+            Graph(m) => self.send_msg_to_ui(UiMsg::GraphUi(m)),
+            MsrText(m0, mv) => self.set_msr_text(m0, mv),
+            Route(m0, m1, mv) => self.set_route(m0, m1, mv),
+            SplitPort(m0, m1, mv) => self.set_split_port(m0, m1, mv),
+            Reroll(m0, m1) => self.reroll(m0, m1),
+            ChordPad(m0, m1) => self.chord_pad(m0, m1),
+            ExportSetup(m) => self.export_setup(m),
+            TempoMap(m0, m1) => self.set_tempo_map(m0, m1),
+            TempoCtrl(m) => self.tg.set_tempo_ctrl(m),
+            Scene(mv) => self.scene(mv),
+            Trigger(m0, mv) => self.trigger.set(m0, mv[0], mv[1], mv[2]),
+            SetList(mv) => self.set_set_list(mv),
+            SetListCtrl(m) => self.set_list_ctrl(m),
+            Freeze(m0, mv) => self.freeze(m0, mv),
             _ => (),
         }
     }
     fn ctrl_msg(&mut self, msg: i16) {
-        if msg == MSG_CTRL_START {
-            self.start(false);
+        if msg == MSG_CTRL_START || msg == MSG_CTRL_COUNT_IN {
+            if !self.during_play {
+                self.tg.set_count_in(msg == MSG_CTRL_COUNT_IN);
+                self.start(false);
+                self.clock_start(0xfa);
+            }
         } else if msg == MSG_CTRL_STOP {
-            self.stop();
+            self.stop_playing();
         } else if msg == MSG_CTRL_FINE {
             self.fine(msg);
         } else if msg == MSG_CTRL_PANIC {
             self.panic();
         } else if msg == MSG_CTRL_RESUME {
+            let cont = !self.during_play;
             self.start(true);
+            if cont {
+                self.clock_start(0xfb);
+            }
         } else if msg == MSG_CTRL_CLEAR {
             self.clear_elapse();
         } else if msg == MSG_CTRL_MIDI_RECONNECT {
             self.reconnect();
+        } else if msg == MSG_CTRL_KEEP_THAT {
+            match self.mcap.keep_that("".to_string()) {
+                Ok(fname) => self.send_msg_to_ui(UiMsg::ErrorUi(format!("Kept that! {}", fname))),
+                Err(e) => self.send_msg_to_ui(UiMsg::ErrorUi(e)),
+            }
         }
     }
     fn send_msg_to_ui(&self, msg: UiMsg) {
@@ -352,6 +887,8 @@ impl ElapseStack {
             Ok(rxmsg) => {
                 if let MIDIRx(sts, nt, vel, extra) = rxmsg {
                     self.rcv_midi_msg(crnt_, sts, nt, vel, extra);
+                } else if let TempoCtrl(ratio) = rxmsg {
+                    self.tg.set_tempo_ctrl(ratio);
                 }
             }
             Err(TryRecvError::Disconnected) => {} // Wrong!
@@ -359,19 +896,56 @@ impl ElapseStack {
         }
     }
     fn rcv_midi_msg(&mut self, crnt_: &CrntMsrTick, sts: u8, nt: u8, vel: u8, ex: u8) {
+        if sts >= 0xf8 {
+            self.rcv_realtime_msg(sts);
+            return;
+        }
+        // ORBIT からの Note は Flow Part への入力
+        let part = if sts & 0x0f != 0x0a && sts & 0xe0 == 0x80 {
+            Some(FLOW_PART)
+        } else {
+            None
+        };
+        self.monitor_msg(true, part, sts, nt, vel);
+        if self.notename != NOTENAME_OFF && sts & 0x0f != 0x0a && sts & 0xf0 == 0x90 && vel > 0 {
+            // 聴音練習用に、入力した音の名前を表示する
+            let key = self.part_vec[FLOW_PART].borrow().get_keynote() as i16;
+            let name = note_readout(nt, key, self.spell, self.notename);
+            self.send_msg_to_ui(UiMsg::NoteNameUi(Some(name)));
+        }
+        if self.note_map_ch != 0 && sts < 0xf0 && sts & 0x0f == self.note_map_ch - 1 {
+            // pad などの Note On は、[midi_map] のコマンドに使う
+            if sts & 0xf0 == 0x90 && vel > 0 {
+                self.send_msg_to_ui(UiMsg::NoteMap(nt));
+            }
+            return;
+        }
+        if sts == 0xf2 {
+            // Song Position Pointer: 16分音符単位の位置の小節に移る
+            self.song_position(((vel as i32) << 7) | nt as i32);
+            return;
+        } else if sts == 0xf3 {
+            self.send_msg_to_ui(UiMsg::SongSel(nt));
+            return;
+        }
         if sts & 0x0f == 0x0a {
             // 0a ch <from another loopian>
             if !self.during_play {
                 // pattern 再生中は、External Loopian とは繋がない
                 if sts & 0xe0 == 0x80 {
                     // LED を光らせる
-                    self.mdx.midi_out_for_led(sts, nt, vel);
+                    self.mdx.midi_out_for_led(None, sts, nt, vel);
                 } else if sts & 0xf0 == 0xa0 {
                     // Flow Part に和音を設定する
                     if let Some(fl) = self.part_vec[FLOW_PART].borrow_mut().get_flow() {
                         fl.borrow_mut().set_chord_for_noplay(nt, vel, ex);
                     }
                 }
+            } else if sts & 0xf0 == 0xa0 {
+                // chordfix の設定がある時のみ、再生中も Flow Part に和音を設定する
+                if let Some(fl) = self.get_flow() {
+                    fl.borrow_mut().set_live_chord(self, crnt_, nt, vel, ex);
+                }
             }
         } else {
             // 0b/0c ch <from ORBIT>
@@ -380,6 +954,12 @@ impl ElapseStack {
                 let pt = self.part_vec[FLOW_PART].clone();
                 pt.borrow_mut()
                     .rcv_midi_in(self, crnt_, sts & 0xf0, nt, vel);
+            } else if (sts & 0xf0) == 0xb0 && nt == 0x40 {
+                // Damper Pedal は、録音中だけ記録して出力する
+                if self.during_play && self.damper_part.borrow().is_recording(crnt_.msr) {
+                    self.damper_part.borrow_mut().rec_pedal(crnt_, vel);
+                    self.midi_out(0xb0, 0x40, vel);
+                }
             } else if (sts & 0xf0) == 0xc0 {
                 // PCN は Pattern 切り替えに使用する
                 self.send_msg_to_ui(UiMsg::ChangePtn(nt));
@@ -394,7 +974,18 @@ impl ElapseStack {
             return;
         }
         self.during_play = true;
-        self.tg.start(self.crnt_time, self.bpm_stock, resume);
+        // tempo map があれば、始める小節で使う bpm から始める
+        let msr = if resume {
+            self.tg.get_crnt_msr_tick().msr
+        } else {
+            0
+        };
+        let bpm = self.tempo_map.bpm_at(msr).unwrap_or(self.bpm_stock);
+        self.tg.start(self.crnt_time, bpm, resume);
+        if !resume {
+            // 同じ種なら、毎回同じ偶然性で始まる
+            self.seed.restart();
+        }
         let start_msr = if resume {
             self.tg.get_crnt_msr_tick().msr
         } else {
@@ -403,29 +994,319 @@ impl ElapseStack {
         for elps in self.elapse_vec.iter() {
             elps.borrow_mut().start(start_msr);
         }
+        if self.tg.in_count_in() {
+            // 一小節の弱起で始まる Part だけ、一小節前から鳴らす
+            for pt in self.part_vec.iter().filter(|p| p.borrow().has_pickup()) {
+                pt.borrow_mut().start(-1);
+            }
+            self.click.borrow_mut().start(-1); // カウントのための click
+        }
         self.send_msg_to_rx(ElpsMsg::Ctrl(MSG_CTRL_START));
         println!("<Start Playing! in stack_elapse> M:{}", start_msr);
     }
+    /// 終了時、全 ch の音と Controller をリセットし、録音バッファを書き出し、
+    /// MIDI Rx thread の終了を待つ
+    fn shutdown(&mut self) {
+        self.stop_playing();
+        let ports = self.route.ports();
+        let ports = std::iter::once(None).chain(ports.iter().map(|p| Some(p.as_str())));
+        for port in ports {
+            for ch in 0..16 {
+                let sts = 0xb0 | ch;
+                self.mdx.midi_out_port(port, sts, 0x40, 0x00); // damper off
+                self.mdx.midi_out_port(port, sts, 0x7b, 0x00); // all notes off
+                self.mdx.midi_out_port(port, sts, 0x79, 0x00); // reset all controllers
+            }
+        }
+        if let Err(e) = self.mcap.keep_that("".to_string()) {
+            println!("{}", e);
+        }
+        self.send_msg_to_rx(ElpsMsg::Ctrl(MSG_CTRL_QUIT));
+        if let Some(hndl) = self.rx_thread.take() {
+            if !join_with_timeout(hndl, Self::THREAD_JOIN_MSEC) {
+                println!("MIDI Rx thread didn't finish!");
+            }
+        }
+    }
     fn panic(&mut self) {
         self.midi_out(0xb0, 0x78, 0x00);
+        for port in self.route.ports().iter() {
+            self.mdx.midi_out_port(Some(port), 0xb0, 0x78, 0x00);
+        }
+    }
+    /// 演奏を止めて統計を書き出し、外部機器にも Stop を送る
+    fn stop_playing(&mut self) {
+        let playing = self.during_play;
+        self.stop();
+        self.stats.flush();
+        if playing && self.clock.is_enable() {
+            self.mdx.midi_out_realtime(0xfc);
+        }
+    }
+    /// status: Start(0xFA) / Continue(0xFB)
+    fn clock_start(&mut self, status: u8) {
+        if self.clock.is_enable() {
+            self.clock.start();
+            self.mdx.midi_out_realtime(status);
+        }
+    }
+    /// 外部の Start/Continue は、次の periodic の tick 計算の前に実行する
+    fn ext_start(&mut self) {
+        if let Some(resume) = self.ext_start.take() {
+            if !resume {
+                self.stop();
+            }
+            self.start(resume);
+            self.tg.ext_clock_start();
+        }
+    }
+    /// slave mode の時、外部の Clock/Start/Continue/Stop に従う
+    fn rcv_realtime_msg(&mut self, sts: u8) {
+        if !self.tg.is_ext_clock() {
+            return;
+        }
+        match sts {
+            0xf8 if self.during_play => self.tg.ext_clock(self.crnt_time),
+            0xfa => self.ext_start = Some(false),
+            0xfb if !self.during_play => self.ext_start = Some(true),
+            0xfc => self.stop_playing(),
+            _ => (),
+        }
+    }
+    /// 進んだ tick の分だけ MIDI Clock を送る
+    fn clock_out(&mut self, crnt_: &CrntMsrTick) {
+        if !self.during_play {
+            return;
+        }
+        for _ in 0..self.clock.count(crnt_) {
+            self.mdx.midi_out_realtime(0xf8);
+        }
     }
     fn stop(&mut self) {
         if !self.during_play {
             return;
         }
         self.during_play = false;
+        self.song_pos = None;
+        self.fade = None;
         let stop_vec = self.elapse_vec.to_vec();
         for elps in stop_vec.iter() {
             elps.borrow_mut().stop(self);
         }
         // destroy flag の立った elapse obj.を回収
         self.destroy_finished_elps();
+        let deferred = self.edit_lock.flush();
+        self.release_deferred(deferred);
+        let offs = self.trigger.stop();
+        self.trigger_out(offs);
+        if let Some(sl) = self.set_list.as_mut() {
+            sl.stop();
+            let txt = sl.indicator();
+            self.send_msg_to_ui(UiMsg::SetListUi(Some(txt)));
+        }
+        self.punch_rec.clear();
+        if self.text_shown.take().is_some() {
+            self.send_msg_to_ui(UiMsg::TextUi(String::new()));
+        }
+    }
+    fn release_deferred(&mut self, deferred: Vec<ElpsMsg>) {
+        if deferred.is_empty() {
+            return;
+        }
+        for msg in deferred.into_iter() {
+            self.parse_elps_msg(msg);
+        }
+        self.send_msg_to_ui(UiMsg::ErrorUi("Locked change has started!".to_string()));
     }
     fn clear_elapse(&mut self) {
         let clear_vec = self.elapse_vec.to_vec();
         for elps in clear_vec.iter() {
             elps.borrow_mut().clear(self);
         }
+        self.mute = [false; MAX_KBD_PART];
+        self.reserved_msg.clear();
+        self.edit_lock.flush();
+    }
+    /// MIDI Rx thread が止まっていたら作り直し、MIDI Out の送信が続けて失敗していたら繋ぎ直す
+    fn watchdog(&mut self) {
+        if !self.wdog.is_time(self.crnt_time) {
+            return;
+        }
+        if self.rx_thread.as_ref().is_some_and(|h| h.is_finished()) {
+            // 初期化できなかった(MIDI In が無い)時は作り直さない
+            let died = self
+                .rx_thread
+                .take()
+                .map(|h| h.join())
+                .is_some_and(|r| !matches!(r, Ok(false)));
+            if died {
+                let (rx_hndr, tx_ctrl, rx_thread) = gen_midirx_thread();
+                self.rx_hndr = rx_hndr;
+                self.tx_ctrl = tx_ctrl;
+                self.rx_thread = Some(rx_thread);
+                if self.note_map_ch != 0 {
+                    self.send_msg_to_rx(Set([MSG_SET_NOTE_MAP_CH, self.note_map_ch as i16]));
+                }
+                if self.tg.is_ext_clock() {
+                    self.send_msg_to_rx(Set([MSG_SET_CLOCK, CLOCK_SLAVE]));
+                }
+                if self.thru {
+                    self.send_msg_to_rx(Set([MSG_SET_THRU, 1]));
+                }
+                if self.conduct_cc >= 0 {
+                    self.send_msg_to_rx(Set([MSG_SET_CONDUCT_CC, self.conduct_cc]));
+                }
+                self.incident("MIDI Rx thread stopped! Restarted.");
+            }
+        }
+        if self.mdx.send_fail() >= MidiWatchdog::MAX_SEND_FAIL {
+            if let Some(msg) = self.wdog.tx_state(true) {
+                self.incident(msg);
+            }
+            if !self.wdog.may_reconnect(self.crnt_time) {
+                return;
+            }
+            let (c, e) = MidiTx::connect();
+            if e.is_some() {
+                self.wdog.reconnect_failed(self.crnt_time);
+            } else {
+                self.mdx = c;
+                for port in self.route.ports().iter() {
+                    self.mdx.connect_port(port);
+                }
+                if let Some(msg) = self.wdog.tx_state(false) {
+                    self.incident(msg);
+                }
+            }
+        }
+    }
+    /// 乱数の種を預け、次の小節の頭で入れ替える(演奏中でなければすぐに)
+    fn reroll(&mut self, part: i16, seed: u32) {
+        let part = if part < 0 { None } else { Some(part as usize) };
+        self.seed.reserve(part, seed);
+        if !self.during_play {
+            self.seed.apply();
+        }
+    }
+    /// Flow の和音と voicing を pad に記憶する/呼び出す
+    fn chord_pad(&mut self, store: bool, num: i16) {
+        let fl = match self.get_flow() {
+            Some(fl) => fl,
+            None => return,
+        };
+        if store {
+            if !fl.borrow_mut().store_chord_memory(self, num as usize) {
+                self.send_msg_to_ui(UiMsg::ErrorUi("No chord to store!".to_string()));
+            }
+        } else if num == 0 {
+            fl.borrow_mut().release_chord_memory(self);
+        } else if !fl.borrow_mut().recall_chord_memory(self, num as usize) {
+            self.send_msg_to_ui(UiMsg::ErrorUi(format!("Chord pad {} is empty!", num)));
+        }
+    }
+    fn set_tempo_map(&mut self, msr: i16, bpm: i16) {
+        if msr < 0 {
+            self.tempo_map.clear();
+        } else {
+            self.tempo_map.set(msr as i32, bpm);
+        }
+    }
+    /// 今の設定を、Part ごとの一覧にして書き出す
+    fn export_setup(&mut self, fname: String) {
+        let route = |part: usize| match self.route.get(part) {
+            Some(rt) if rt.ch != 0 || !rt.port.is_empty() => format!(
+                "ch.{}, port: {}",
+                rt.ch.max(1),
+                if rt.port.is_empty() {
+                    "default"
+                } else {
+                    &rt.port
+                }
+            ),
+            _ => "ch.1, port: default".to_string(),
+        };
+        let mut sheet = SetupSheet::new();
+        let meter = self.tg.get_meter();
+        let key = self.part_vec[LEFT1].borrow().get_keynote() as i16;
+        let mut rows = vec![
+            ("bpm", self.get_bpm().to_string()),
+            ("meter", format!("{}/{}", meter.0, meter.1)),
+            ("key", note_name(key, key, self.spell).to_string()),
+        ];
+        if self.quantize != 0 {
+            let q = self.quantize;
+            rows.push((
+                "quantize",
+                format!("{}%, grid {} tick", q & 0xff, (q >> 8) * 60),
+            ));
+        }
+        if let Some(txt) = self.tempo_map.summary() {
+            rows.push(("tempo map", txt));
+        }
+        if self.thru {
+            rows.push(("thru", "on".to_string()));
+        }
+        sheet.add_section("Global", rows);
+        if !self.trigger.is_empty() {
+            sheet.add_section("Trigger", self.trigger.summary(key, self.spell));
+        }
+        let (tick_for_onemsr, _) = self.tg.get_beat_tick();
+        for (part, name) in ["L1", "L2", "R1", "R2"].iter().enumerate() {
+            let mut rows = vec![("route", route(part))];
+            if let Some((_, [low, high])) = self.route.get_split(part) {
+                rows.push(("split port", format!("{} / {}", low, high)));
+            }
+            if self.mute[part] {
+                rows.push(("mute", "on".to_string()));
+            }
+            rows.extend(self.part_vec[part].borrow().setup_summary(tick_for_onemsr));
+            sheet.add_section(name, rows);
+        }
+        for (part, name) in [
+            (FLOW_PART, "Flow"),
+            (DAMPER_PEDAL_PART, "Damper"),
+            (CLICK_PART, "Click"),
+        ] {
+            sheet.add_section(name, vec![("route", route(part))]);
+        }
+        let msg = match sheet.write(&fname) {
+            Ok(fname) => format!("Setup has been exported to {}!", fname),
+            Err(e) => e,
+        };
+        self.send_msg_to_ui(UiMsg::ErrorUi(msg));
+    }
+    /// part の出力先を変える。port に繋げなければ、変えずに UI に知らせる
+    fn set_route(&mut self, part: i16, ch: u8, port: String) {
+        if part < 0 || part as usize >= MidiRoute::MAX_PART {
+            return;
+        }
+        if !port.is_empty() && !self.mdx.connect_port(&port) {
+            self.send_msg_to_ui(UiMsg::ErrorUi(format!("MIDI port not found: {}", port)));
+            return;
+        }
+        self.route.set(part as usize, PartRoute { ch, port });
+    }
+    /// 音域で分けた音の出力先の port を変える。port に繋げなければ、変えずに UI に知らせる
+    fn set_split_port(&mut self, part: i16, border: i16, ports: [String; 2]) {
+        if part < 0 || part as usize >= MidiRoute::MAX_PART {
+            return;
+        }
+        if let Some(port) = ports
+            .iter()
+            .find(|p| !p.is_empty() && !self.mdx.connect_port(p))
+        {
+            let msg = format!("MIDI port not found: {}", port);
+            self.send_msg_to_ui(UiMsg::ErrorUi(msg));
+            return;
+        }
+        let split =
+            (border >= 0 && ports.iter().any(|p| !p.is_empty())).then_some((border as u8, ports));
+        self.route.set_split(part as usize, split);
+    }
+    /// 障害を log と UI に知らせる
+    fn incident(&self, msg: &str) {
+        println!("<Watchdog in stack_elapse> {}", msg);
+        self.send_msg_to_ui(UiMsg::ErrorUi(msg.to_string()));
     }
     fn reconnect(&mut self) {
         let (_c, e) = MidiTx::connect();
@@ -437,7 +1318,7 @@ impl ElapseStack {
     }
     fn fine(&mut self, _msg: i16) {
         if self.tg().get_bpm() == 0 {
-            self.stop();
+            self.stop_playing();
         } else {
             self.fine_stock = true;
         }
@@ -464,6 +1345,15 @@ impl ElapseStack {
         }
     }
     fn rit(&mut self, msg: [i16; 2]) {
+        let gesture = self.tempo_gesture(msg);
+        self.tg.prepare_gesture(VecDeque::from([gesture]));
+    }
+    /// then で連結された rit./accel. を、前から順に実行する
+    fn rit_chain(&mut self, msgs: Vec<[i16; 2]>) {
+        let chain = msgs.into_iter().map(|m| self.tempo_gesture(m)).collect();
+        self.tg.prepare_gesture(chain);
+    }
+    fn tempo_gesture(&self, msg: [i16; 2]) -> TempoGesture {
         let strength_set: [(i16, i32); 3] =
             [(MSG_RIT_POCO, 80), (MSG_RIT_NRM, 60), (MSG_RIT_MLT, 40)];
         let strength_msg = msg[0] % 10;
@@ -477,10 +1367,17 @@ impl ElapseStack {
             target_bpm = self.tg.get_bpm();
         } else if msg[1] == MSG2_RIT_FERMATA {
             target_bpm = 0;
+        } else if msg[1] == MSG2_RIT_HOLD {
+            target_bpm = TempoGesture::TEMPO_HOLD;
         } else {
             target_bpm = msg[1];
         }
-        self.tg.prepare_rit(strength.1, bar, target_bpm);
+        TempoGesture {
+            accel: strength_msg == MSG_RIT_ACCEL,
+            ratio: strength.1,
+            bar,
+            target_bpm,
+        }
     }
     fn setting_cmnd(&mut self, msg: [i16; 2]) {
         if msg[0] == MSG_SET_BPM {
@@ -490,6 +1387,13 @@ impl ElapseStack {
             self.part_vec
                 .iter()
                 .for_each(|x| x.borrow_mut().change_key(msg[1] as u8));
+        } else if msg[0] == MSG_SET_MODULATE {
+            let key = (msg[1] % MSG2_KEY_PIVOT) as u8;
+            let pivot = msg[1] >= MSG2_KEY_PIVOT;
+            self.part_vec
+                .iter()
+                .for_each(|x| x.borrow_mut().modulate(key, pivot));
+            self.send_msg_to_ui(UiMsg::KeyUi(key));
         } else if msg[0] == MSG_SET_TURN {
             self.part_vec
                 .iter_mut()
@@ -499,6 +1403,189 @@ impl ElapseStack {
                 self.stop();
             }
             self.tg.set_crnt_msr(msg[1] as i32);
+        } else if msg[0] == MSG_SET_SONG_MSR {
+            self.song_msr = msg[1].max(0) as i32;
+        } else if msg[0] == MSG_SET_DISP_TRNS {
+            self.disp_trns = if msg[1] == DISP_TRNS_OFF {
+                None
+            } else {
+                Some(msg[1])
+            };
+        } else if msg[0] == MSG_SET_SPELL {
+            self.spell = msg[1];
+        } else if msg[0] == MSG_SET_PEDREC {
+            let crnt_ = self.tg.get_crnt_msr_tick();
+            let mut dp = self.damper_part.borrow_mut();
+            if msg[1] == PEDREC_ON {
+                dp.start_rec(&crnt_);
+            } else if msg[1] == PEDREC_OFF {
+                dp.stop_rec(&crnt_);
+            } else {
+                dp.clear_rec();
+            }
+        } else if msg[0] == MSG_SET_TONE {
+            self.tone.set(msg[1]);
+            if msg[1] == TONE_OFF {
+                self.send_msg_to_ui(UiMsg::ToneUi(None));
+            }
+        } else if msg[0] == MSG_SET_MONITOR {
+            self.monitor.set(msg[1]);
+            if msg[1] == MONITOR_OFF {
+                self.send_msg_to_ui(UiMsg::MonitorUi(None));
+            }
+        } else if msg[0] == MSG_SET_NOTENAME {
+            self.notename = msg[1];
+            if msg[1] == NOTENAME_OFF {
+                self.send_msg_to_ui(UiMsg::NoteNameUi(None));
+            }
+        } else if msg[0] == MSG_SET_PED_RHYTHM {
+            self.damper_part.borrow_mut().set_rhythm(msg[1] != 0);
+        } else if msg[0] == MSG_SET_SUBST {
+            self.subst.set(msg[1]);
+        } else if msg[0] == MSG_SET_QUANTIZE {
+            self.quantize = msg[1];
+        } else if msg[0] == MSG_SET_FADE {
+            self.fade = (msg[1] != 0).then(|| MasterFade::new(self.crnt_time, msg[1]));
+        } else if msg[0] == MSG_SET_CLOCK {
+            self.clock.set(msg[1] == CLOCK_MASTER);
+            self.tg.set_ext_clock(msg[1] == CLOCK_SLAVE);
+            self.send_msg_to_rx(ElpsMsg::Set(msg)); // Realtime Message を受信する
+        } else if msg[0] == MSG_SET_EDIT_LOCK {
+            self.edit_lock.set(msg[1]);
+        } else if msg[0] == MSG_SET_CLICK {
+            let click = Rc::clone(&self.click);
+            click.borrow_mut().set_enable(msg[1] != 0, self);
+        } else if msg[0] == MSG_SET_CLICK_CH {
+            let click = Rc::clone(&self.click);
+            click.borrow_mut().set_ch(msg[1] as u8, self);
+        } else if msg[0] == MSG_SET_CLICK_NOTE {
+            self.click
+                .borrow_mut()
+                .set_note((msg[1] & 0xff) as u8, (msg[1] >> 8) as u8);
+        } else if msg[0] == MSG_SET_THRU {
+            self.thru = msg[1] != 0;
+            self.send_msg_to_rx(ElpsMsg::Set(msg)); // MIDI Rx thread が直接出力する
+        } else if msg[0] == MSG_SET_CONDUCT_CC {
+            self.conduct_cc = msg[1];
+            self.send_msg_to_rx(ElpsMsg::Set(msg)); // MIDI Rx thread が CC を TempoCtrl にする
+        } else if msg[0] == MSG_SET_NOTE_MAP_CH {
+            self.note_map_ch = msg[1].clamp(0, 16) as u8;
+            self.send_msg_to_rx(ElpsMsg::Set(msg)); // 受信する ch を増やす
+        } else if msg[0] == MSG_SET_DOUBLING {
+            self.doubling.set(msg[1]);
+        } else if msg[0] == MSG_SET_RIT_TYPE {
+            self.tg.set_rit_type(match msg[1] {
+                RIT_TYPE_LINEAR => RitType::Linear,
+                RIT_TYPE_PRECISE => RitType::LinearPrecise,
+                _ => RitType::Sigmoid,
+            });
+        } else if msg[0] == MSG_SET_RIT_MIN {
+            self.tg.set_rit_min_bpm(msg[1]);
+        } else if msg[0] == MSG_SET_RIT_STEEP {
+            self.tg.set_rit_steep(msg[1]);
+        } else if msg[0] == MSG_SET_RATE {
+            self.tg.set_rate(msg[1]);
+        } else if msg[0] == MSG_SET_NUDGE {
+            self.tg.nudge(msg[1]);
+        } else if msg[0] == MSG_SET_CHORD_FIX {
+            if let Some(fl) = self.get_flow() {
+                fl.borrow_mut().set_fix_window(msg[1] as i32);
+            }
+        } else if msg[0] == MSG_SET_STATS {
+            self.stats.set_enable(msg[1] != 0);
+        } else if msg[0] == MSG_SET_FLOW_PAD {
+            if let Some(fl) = self.get_flow() {
+                fl.borrow_mut().set_pad_mode(msg[1] != 0);
+            }
+        }
+    }
+    fn setting_part(&mut self, part_num: i16, msg: [i16; 2]) {
+        if part_num as usize >= MAX_KBD_PART {
+            return;
+        }
+        if msg[0] == MSG_SETPT_ROLL {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_roll(msg[1]);
+        } else if msg[0] == MSG_SETPT_TUNE || msg[0] == MSG_SETPT_BEND_RANGE {
+            let setup = self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_tuning(msg[0], msg[1]);
+            for (status, dt1, dt2) in setup.into_iter() {
+                self.midi_out_from(Some(part_num as usize), status, dt1, dt2);
+            }
+        } else if msg[0] == MSG_SETPT_SPLIT {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_split(msg[1]);
+        } else if msg[0] == MSG_SETPT_PAD {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_pad(msg[1]);
+        } else if msg[0] == MSG_SETPT_QUANTIZE {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_quantize(msg[1]);
+        } else if msg[0] == MSG_SETPT_SPEED {
+            let tfm = self.tg.get_crnt_msr_tick().tick_for_onemsr;
+            if !self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_speed(msg[1], tfm)
+            {
+                self.send_msg_to_ui(UiMsg::ErrorUi(
+                    "Phrase is too long for the speed!".to_string(),
+                ));
+            }
+        } else if msg[0] == MSG_SETPT_FILLER {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_filler(msg[1]);
+        } else if (MSG_SETPT_PAN..=MSG_SETPT_PAN_CENTER).contains(&msg[0]) {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_pan(msg[0], msg[1]);
+        } else if msg[0] == MSG_SETPT_AUTO_VARI {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_auto_vari(msg[1]);
+        } else if msg[0] == MSG_SETPT_VEL_LIMIT {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_vel_limit(msg[1]);
+        } else if msg[0] == MSG_SETPT_EVOLVE {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_evolve(msg[1]);
+        } else if msg[0] == MSG_SETPT_RAMP || msg[0] == MSG_SETPT_RAMP_END {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_ramp(msg[0], msg[1]);
+        } else if msg[0] == MSG_SETPT_CHORD_OUT {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_chord_out(msg[1]);
+        } else if msg[0] == MSG_SETPT_GLOBAL_PHASE {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_global_phase(msg[1] != 0);
+        } else if msg[0] == MSG_SETPT_VARI_BEAT {
+            let crnt_ = self.tg.get_crnt_msr_tick();
+            let (_, tick_for_beat) = self.tg.get_beat_tick();
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .reserve_vari_beat(msg[1] as usize, &crnt_, tick_for_beat);
+        } else if (MSG_SETPT_VARI..=MSG_SETPT_VARI_LINK).contains(&msg[0]) {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .select_vari(msg[0], msg[1]);
+        } else if (MSG_SETPT_KEYSW_NORM..=MSG_SETPT_KEYSW_LEGATO).contains(&msg[0]) {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_keysw(msg[0], msg[1]);
+        } else if (MSG_SETPT_VELCC..=MSG_SETPT_VELCC_SMOOTH).contains(&msg[0]) {
+            self.part_vec[part_num as usize]
+                .borrow_mut()
+                .set_vel_cc(msg[0], msg[1]);
         }
     }
     fn efct(&mut self, msg: [i16; 2]) {
@@ -517,6 +1604,309 @@ impl ElapseStack {
             self.tg.change_beat_event(tick_for_onemsr, self.beat_stock);
         }
     }
+    fn listen(&mut self, msg: [i16; 2]) {
+        if msg[1] == LISTEN_HOLD {
+            if let Some(lsn) = self.listener.as_mut() {
+                let hold = lsn.toggle_hold();
+                println!("Audio Chord Hold: {}", hold);
+            }
+            return;
+        }
+        // 一度止めてから、新しい sensitivity で開始する
+        if self.listener.take().is_some_and(|lsn| lsn.is_chord_mode()) {
+            if let Some(fl) = self.get_flow() {
+                fl.borrow_mut().set_audio_chord(None);
+            }
+        }
+        if msg[0] >= 0 {
+            self.listener = Listener::start(msg[0], msg[1]);
+        }
+    }
+    /// オーディオ入力の onset に合わせて、位相とテンポを寄せる
+    fn follow_onset(&mut self) {
+        if let Some(lsn) = self.listener.as_mut() {
+            while let Some(onset) = lsn.try_onset() {
+                let tick_for_beat = self.tg.get_beat_tick().1;
+                let err = self.tg.beat_error(onset);
+                let (shift, bpm_diff) = lsn.follow(err, tick_for_beat);
+                self.tg.shift_phase(shift);
+                if bpm_diff != 0 {
+                    self.bpm_stock =
+                        (self.bpm_stock + bpm_diff).clamp(MIN_BPM_VALUE, MAX_BPM_VALUE);
+                    self.tg.change_bpm(self.bpm_stock);
+                }
+            }
+            // 推定した和音を Flow に設定する
+            if let Some(chord) = lsn.try_chord() {
+                if let Some(fl) = self.part_vec[FLOW_PART].borrow().get_flow() {
+                    fl.borrow_mut().set_audio_chord(Some(chord));
+                }
+            }
+        }
+    }
+    fn set_mute(&mut self, part: i16, mute: bool) {
+        if (part as usize) < MAX_KBD_PART {
+            self.mute[part as usize] = mute;
+        }
+    }
+    fn set_riser(&mut self, part: i16, msrs: i16, offsets: Vec<i16>) {
+        if (part as usize) < MAX_KBD_PART {
+            self.part_vec[part as usize]
+                .borrow_mut()
+                .set_riser(msrs, offsets);
+        }
+    }
+    fn set_mask(&mut self, part: i16, mask: Vec<bool>) {
+        if (part as usize) < MAX_KBD_PART {
+            self.part_vec[part as usize].borrow_mut().set_mask(mask);
+        }
+    }
+    /// scene の msg を一度に実行し、全パートを次の小節の頭で揃えて切り替える
+    fn scene(&mut self, msgs: Vec<ElpsMsg>) {
+        for msg in msgs.into_iter() {
+            self.parse_elps_msg(msg);
+        }
+        if self.during_play {
+            self.sync(MSG_SYNC_ALL);
+            self.trigger.reserve_section();
+        }
+    }
+    /// set list を入れ替え、最初の scene に移る(空なら off)
+    fn set_set_list(&mut self, entries: Vec<SetListEntry>) {
+        self.set_list = SetList::new(entries);
+        if self.set_list.is_some() {
+            self.set_list_jump(0);
+        } else {
+            self.send_msg_to_ui(UiMsg::SetListUi(None));
+        }
+    }
+    /// 手動で set list の scene を移る
+    fn set_list_ctrl(&mut self, idx: i16) {
+        let next = match self.set_list.as_ref() {
+            Some(sl) if idx == SETLIST_NEXT => sl.next_idx(),
+            Some(_) if idx >= 0 => Some(idx as usize),
+            _ => None,
+        };
+        match next {
+            Some(n) => self.set_list_jump(n),
+            None => self.send_msg_to_ui(UiMsg::ErrorUi("No next scene.".to_string())),
+        }
+    }
+    fn set_list_jump(&mut self, idx: usize) {
+        let sl = match self.set_list.as_mut() {
+            Some(sl) => sl,
+            None => return,
+        };
+        match sl.jump(idx) {
+            Some((scene, msgs)) => {
+                let txt = sl.indicator();
+                self.scene(msgs);
+                self.send_msg_to_ui(UiMsg::SceneUi(scene));
+                self.send_msg_to_ui(UiMsg::SetListUi(Some(txt)));
+            }
+            None => self.send_msg_to_ui(UiMsg::ErrorUi("Scene number is wrong.".to_string())),
+        }
+    }
+    /// 今の scene の最後の小節なら、次の scene に移る
+    fn set_list_msr_top(&mut self) {
+        let sl = match self.set_list.as_mut() {
+            Some(sl) => sl,
+            None => return,
+        };
+        match sl.msr_top() {
+            Some(next) => self.set_list_jump(next),
+            None => {
+                let txt = sl.indicator();
+                self.send_msg_to_ui(UiMsg::SetListUi(Some(txt)));
+            }
+        }
+    }
+    /// 小節の頭で、その小節に予約された msg を実行する
+    fn exec_reserved_msg(&mut self, msr: i32) {
+        let (now, later): (Vec<_>, Vec<_>) = self
+            .reserved_msg
+            .drain(..)
+            .partition(|(rsv_msr, _)| *rsv_msr <= msr);
+        self.reserved_msg = later;
+        for (_, msg) in now.into_iter() {
+            self.parse_elps_msg(msg);
+        }
+    }
+    fn set_msr_text(&mut self, msr: i16, txt: String) {
+        let msr = msr as i32;
+        self.msr_text.retain(|(m, _)| *m != msr);
+        if !txt.is_empty() {
+            let idx = self.msr_text.partition_point(|(m, _)| *m < msr);
+            self.msr_text.insert(idx, (msr, txt));
+        }
+    }
+    /// 直前の Loop で鳴らした音を、Phrase にするため UI に送る
+    fn freeze(&mut self, part: i16, vari: i16) {
+        let part = part as usize;
+        let crnt_ = self.tg.get_crnt_msr_tick();
+        let frozen = if part < MAX_KBD_PART {
+            self.part_vec[part]
+                .borrow()
+                .get_frozen(crnt_.msr, crnt_.tick_for_onemsr)
+        } else {
+            None
+        };
+        match frozen {
+            Some((evts, whole_tick)) => {
+                self.send_msg_to_ui(UiMsg::FreezeUi(part, vari, evts, whole_tick))
+            }
+            None => self.send_msg_to_ui(UiMsg::ErrorUi("Nothing to freeze.".to_string())),
+        }
+    }
+    /// その小節で有効な歌詞やメモが変わったら、UI に送る
+    fn update_msr_text(&mut self, msr: i32) {
+        let crnt = self.msr_text.iter().rev().find(|(m, _)| *m <= msr);
+        let shown = crnt.map(|(m, _)| *m);
+        if shown != self.text_shown {
+            let txt = match crnt {
+                Some((_, t)) if t != "-" => t.clone(),
+                _ => String::new(),
+            };
+            self.text_shown = shown;
+            self.send_msg_to_ui(UiMsg::TextUi(txt));
+        }
+    }
+    fn set_practice(&mut self, msg: [i16; 4]) {
+        if msg[0] == MSG_PRACTICE_OFF {
+            self.practice = None;
+            self.send_msg_to_ui(UiMsg::PracticeUi(0));
+        } else {
+            self.practice = Some(PracticeLoop {
+                start_msr: msg[0] as i32 - 1,
+                end_msr: msg[1] as i32 - 1,
+                inc_bpm: msg[2],
+                rpt: msg[3] as i32,
+                pass_cnt: 0,
+                step: 1,
+            });
+            if !self.during_play {
+                // resume で区間の先頭から始められるように
+                self.tg.set_crnt_msr(msg[0] as i32 - 1);
+            }
+            self.send_msg_to_ui(UiMsg::PracticeUi(1));
+        }
+    }
+    /// practice 中、区間の最後の小節を終えたら区間の先頭に戻り、必要なら bpm を上げる
+    fn practice_jump(&mut self, crnt_: &mut CrntMsrTick) {
+        let mut prc = match self.practice {
+            Some(p) if crnt_.msr == p.end_msr + 1 => p,
+            _ => return,
+        };
+        prc.pass_cnt += 1;
+        if prc.pass_cnt >= prc.rpt {
+            prc.pass_cnt = 0;
+            prc.step += 1;
+            self.bpm_stock = (self.bpm_stock + prc.inc_bpm).clamp(MIN_BPM_VALUE, MAX_BPM_VALUE);
+        }
+        self.practice = Some(prc);
+        println!(
+            "<Practice in stack_elapse> Step: {} Bpm: {}",
+            prc.step, self.bpm_stock
+        );
+        // set.msr + resume と同じ手順で先頭に戻る
+        self.jump_to(prc.start_msr, crnt_);
+        self.send_msg_to_ui(UiMsg::PracticeUi(prc.step));
+    }
+    fn set_loop_rgn(&mut self, msg: [i16; 2]) {
+        if msg[0] == MSG_LOOP_RGN_OFF {
+            self.loop_rgn = None;
+        } else {
+            self.loop_rgn = Some((msg[0] as i32 - 1, msg[1] as i32 - 1));
+            if !self.during_play {
+                // resume で区間の先頭から始められるように
+                self.tg.set_crnt_msr(msg[0] as i32 - 1);
+            }
+        }
+    }
+    /// 再生中なら次の小節頭でその小節に移る。停止中なら resume で始まる小節にする
+    fn song_position(&mut self, sixteenth: i32) {
+        let tick = sixteenth * DEFAULT_TICK_FOR_QUARTER / 4;
+        let (tick_for_onemsr, _) = self.tg.get_beat_tick();
+        let msr = tick / tick_for_onemsr;
+        if self.during_play {
+            self.song_pos = Some(msr);
+        } else {
+            self.tg.set_crnt_msr(msr);
+        }
+    }
+    fn song_pos_jump(&mut self, crnt_: &mut CrntMsrTick) {
+        let msr = match self.song_pos.take() {
+            Some(m) => m,
+            None => return,
+        };
+        println!("<Song Position in stack_elapse> M{}", msr + 1);
+        self.jump_to(msr, crnt_);
+    }
+    fn set_punch(&mut self, msg: [i16; 3]) {
+        let punch = if msg[0] == MSG_PUNCH_OFF {
+            None
+        } else {
+            Some((msg[0] as i32 - 1, msg[1] as i32 - 1))
+        };
+        self.damper_part.borrow_mut().set_punch(punch);
+        self.punch_rec.set(punch, msg[2] as usize);
+    }
+    /// ORBIT から弾いた音を、punch 区間なら記録する
+    pub fn punch_note(&mut self, note: u8, vel: u8) {
+        let crnt_ = self.tg.get_crnt_msr_tick();
+        if vel > 0 {
+            self.punch_rec.note_on(&crnt_, note, vel);
+        } else {
+            self.punch_rec.note_off(&crnt_, note);
+        }
+    }
+    /// punch 区間の終わりで、弾いた音を Phrase に差し替えるため UI に送る
+    fn punch_note_out(&mut self, crnt_: &CrntMsrTick) {
+        let (part, region, rec) = match self.punch_rec.punch_out(crnt_) {
+            Some(pr) => pr,
+            None => return,
+        };
+        let merged = if part < MAX_KBD_PART {
+            self.part_vec[part]
+                .borrow()
+                .merge_punch(region, &rec, crnt_.tick_for_onemsr)
+        } else {
+            None
+        };
+        match merged {
+            Some((evts, whole_tick)) => self.send_msg_to_ui(UiMsg::PunchUi(part, evts, whole_tick)),
+            None => self.send_msg_to_ui(UiMsg::ErrorUi("Punch failed.".to_string())),
+        }
+    }
+    /// loop 区間の終点の小節頭に来たら、区間の先頭に戻る
+    fn loop_rgn_jump(&mut self, crnt_: &mut CrntMsrTick) {
+        let (start_msr, end_msr) = match self.loop_rgn {
+            Some(rgn) if crnt_.msr >= rgn.1 => rgn,
+            _ => return,
+        };
+        // 戻る前に、punch out を済ませておく
+        self.damper_part.borrow_mut().punch_out(crnt_);
+        self.punch_note_out(crnt_);
+        println!(
+            "<Loop in stack_elapse> M{} -> M{}",
+            end_msr + 1,
+            start_msr + 1
+        );
+        // set.msr + resume と同じ手順で、各 Loop Obj. を先頭から作り直す
+        self.jump_to(start_msr, crnt_);
+    }
+    /// 演奏中に msr に移る(stop + set.msr + resume)
+    /// one-shot の音は止めずに、移った先の時刻に合わせて残す
+    fn jump_to(&mut self, msr: i32, crnt_: &mut CrntMsrTick) {
+        let shift = msr - crnt_.msr;
+        let trigger = std::mem::replace(&mut self.trigger, Trigger::new());
+        self.stop();
+        self.tg.set_crnt_msr(msr);
+        self.start(true);
+        self.trigger = trigger;
+        self.trigger.shift(shift);
+        *crnt_ = self.tg.get_crnt_msr_tick();
+    }
     fn phrase(&mut self, part_num: i16, evts: PhrData) {
         println!("Received Phrase Message! Part: {}", part_num);
         self.part_vec[part_num as usize]
@@ -635,15 +2025,52 @@ impl ElapseStack {
             self.send_msg_to_ui(UiMsg::Meter(beat.0, beat.1));
             // bpm
             self.send_msg_to_ui(UiMsg::BpmUi(self.get_bpm()));
+            self.send_msg_to_ui(UiMsg::GestureUi(self.tg.get_gesture()));
             // tick
             let (m, b, t, _c) = self.tg.get_tick();
             self.send_msg_to_ui(UiMsg::TickUi(self.during_play, m, b, t));
             // part
             let crnt_ = self.tg.get_crnt_msr_tick();
+            let mut longest = 0;
             for i in 0..MAX_KBD_PART {
-                let part_ui = self.part_vec[i].borrow().gen_part_indicator(&crnt_);
+                let part_ui = self.part_vec[i]
+                    .borrow()
+                    .gen_part_indicator(&crnt_, self.disp_trns.map(|t| (t, self.spell)));
+                longest = longest.max(part_ui.all_msrs);
                 self.send_msg_to_ui(UiMsg::PartUi(i, part_ui));
             }
+            // これから鳴る音
+            if self.during_play {
+                for i in 0..MAX_KBD_PART {
+                    let cmps = self.get_cmps(i);
+                    let chord_after = |after: i32| {
+                        cmps.as_ref().map_or((NO_ROOT, NO_TABLE), |c| {
+                            c.borrow().get_chord_after(&crnt_, after)
+                        })
+                    };
+                    let upcoming = self
+                        .get_phr(i)
+                        .map(|p| {
+                            p.borrow()
+                                .get_upcoming(&crnt_, chord_after, Self::UPCOMING_NUM)
+                        })
+                        .unwrap_or_default();
+                    self.send_msg_to_ui(UiMsg::UpcomingUi(i, upcoming));
+                }
+            }
+            // time
+            let song_msr = if self.song_msr > 0 {
+                self.song_msr
+            } else {
+                longest
+            };
+            let elapsed = if self.during_play {
+                (self.tg.get_elapsed_time() * 1000.0) as i32
+            } else {
+                0
+            };
+            let total = (self.tg.estimate_time(song_msr) * 1000.0) as i32;
+            self.send_msg_to_ui(UiMsg::TimeUi(elapsed, total));
             self.flac = (t % 10) as u64;
         }
     }
@@ -0,0 +1,56 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+
+//*******************************************************************
+//          Tempo Map Struct
+//*******************************************************************
+//  指定した小節の頭で bpm を変える(曲の構成ごとのテンポ)
+//  最初の変化までは set.bpm の値、それ以降は直前の変化の bpm が続く
+//  一度きりの予約(Reserve)と違い、play のたびに同じ小節で変わる
+pub struct TempoMap {
+    changes: Vec<(i32, i16)>, // (msr:0origin, bpm) 小節順
+}
+impl TempoMap {
+    pub fn new() -> Self {
+        Self {
+            changes: Vec::new(),
+        }
+    }
+    /// bpm が 0 なら、その小節の変化を消す
+    pub fn set(&mut self, msr: i32, bpm: i16) {
+        self.changes.retain(|(m, _)| *m != msr);
+        if bpm > 0 {
+            let idx = self.changes.partition_point(|(m, _)| *m < msr);
+            self.changes.insert(idx, (msr, bpm));
+        }
+    }
+    pub fn clear(&mut self) {
+        self.changes.clear();
+    }
+    /// msr の頭で変える bpm
+    pub fn change_at(&self, msr: i32) -> Option<i16> {
+        self.changes
+            .iter()
+            .find(|(m, _)| *m == msr)
+            .map(|(_, bpm)| *bpm)
+    }
+    /// msr で使う bpm(None: まだ変化がなく、set.bpm の値)
+    pub fn bpm_at(&self, msr: i32) -> Option<i16> {
+        self.changes
+            .iter()
+            .take_while(|(m, _)| *m <= msr)
+            .last()
+            .map(|(_, bpm)| *bpm)
+    }
+    /// export setup 用: M9:140, M17:90
+    pub fn summary(&self) -> Option<String> {
+        let txt: Vec<String> = self
+            .changes
+            .iter()
+            .map(|(m, bpm)| format!("M{}:{}", m + 1, bpm))
+            .collect();
+        (!txt.is_empty()).then(|| txt.join(", "))
+    }
+}
@@ -3,7 +3,8 @@
 //  Released under the MIT license
 //  https://opensource.org/licenses/mit-license.php
 //
-use crate::lpnlib::{Meter, DEFAULT_BPM, DEFAULT_TICK_FOR_ONE_MEASURE};
+use crate::lpnlib::{Meter, DEFAULT_BPM, DEFAULT_TICK_FOR_ONE_MEASURE, DEFAULT_TICK_FOR_QUARTER};
+use std::collections::VecDeque;
 use std::time::{Duration, Instant};
 
 //*******************************************************************
@@ -21,7 +22,13 @@ pub struct TickGen {
     meter_start_msr: i32,    // meter が変わった時点の経過小節数
     crnt_msr: i32,           // start からの小節数（最初の小節からイベントを出すため、-1初期化)
     crnt_tick_inmsr: i32,    // 現在の小節内の tick 数
-    crnt_time: Instant,      // 現在の時刻
+    start_msr: i32,          // 演奏を始めた小節(0, play from -1 の時は -1)
+    count_in: bool,          // 次の start を一小節前(-1)から始める
+    crnt_time: Instant,      // 現在の時刻(rate で伸縮した、演奏上の時刻)
+    real_time: Instant,      // crnt_time を求めたときの実際の時刻
+    rate: i32,               // 再生速度[%]、bpm の表示は変えずに実際の速さだけ変える
+    // nudge 中の速さ[%]と、元に戻す時刻
+    nudge: Option<(i32, Instant)>,
 
     prepare_rit: bool, // rit. 開始準備中
     rit_state: bool,
@@ -29,6 +36,55 @@ pub struct TickGen {
     prm: RitPrm,
     start_mt: CrntMsrTick,
     ritgen: Box<dyn Rit>,
+    rit_type: RitType,
+    ctrl: bool,                            // RitCtrl で外部から速さを変えている
+    rit_min_bpm: i16,                      // rit. で下回らないテンポ(0:各 Rit の既定値)
+    rit_steep: i32,                        // Sigmoid の曲線の急峻さ[%](100:標準)
+    gesture: Option<TempoGesture>,         // 準備中/実行中の rit./accel.
+    gesture_queue: VecDeque<TempoGesture>, // then で連結された、次以降の rit./accel.
+    tempo_map: Vec<TempoSeg>,              // 演奏中に変化した tempo/meter の履歴
+    resume_offset: f32,                    // resume 時、開始小節までの推定時間[sec]
+    ext_clock: Option<ExtClock>,           // 外部の MIDI Clock に合わせる(slave mode)
+}
+/// msr 小節目から適用される tempo/meter
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct TempoSeg {
+    msr: i32,
+    bpm: i16,
+    tick_for_onemsr: i32,
+    tick_for_beat: i32,
+}
+impl TempoSeg {
+    fn msr_to_sec(&self, msrs: i32) -> f32 {
+        if self.bpm <= 0 {
+            return 0.0; // fermata
+        }
+        ((msrs * self.tick_for_onemsr) as f32 * 60.0)
+            / ((self.tick_for_beat as f32) * (self.bpm as f32))
+    }
+}
+/// rit./accel. 一つ分の指定
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TempoGesture {
+    pub accel: bool,     // true: target_bpm に向けて速くする
+    pub ratio: i32,      // rit. の最終到達テンポ[%]
+    pub bar: i32,        // 次の小節に加えて、何小節かけるか
+    pub target_bpm: i16, // 終了後の bpm, TEMPO_HOLD:到達したテンポのまま
+}
+impl TempoGesture {
+    pub const TEMPO_HOLD: i16 = -1;
+    /// 開始時の bpm から、最終到達テンポ[%]を求める
+    fn ratio_from(&self, bpm: i16) -> i32 {
+        if !self.accel || bpm <= 0 {
+            return self.ratio;
+        }
+        let ratio = (self.target_bpm as i32) * 100 / (bpm as i32);
+        if ratio == 100 {
+            101 // 100 だとテンポが変化しないので、わずかにずらす
+        } else {
+            ratio
+        }
+    }
 }
 #[derive(Clone, Copy, PartialEq, Default, Debug)]
 pub struct CrntMsrTick {
@@ -36,7 +92,73 @@ pub struct CrntMsrTick {
     pub tick: i32,
     pub tick_for_onemsr: i32,
 }
+//*******************************************************************
+//          External Clock Struct
+//*******************************************************************
+//  外部の MIDI Clock(24/四分音符) から tick を作る(slave mode)
+//  Clock と Clock の間は、平均した間隔で補間する
+struct ExtClock {
+    ticks: i32,            // 受信した Clock の tick 数(Start 後の最初の Clock が 0)
+    last: Option<Instant>, // 最後に Clock を受信した時刻
+    interval: f32,         // 平均した Clock の間隔[sec]
+    mark: i32,             // bpm_start_time の時点の tick
+}
+impl ExtClock {
+    const TICK_PER_CLOCK: i32 = DEFAULT_TICK_FOR_QUARTER / 24;
+    const AVERAGE: f32 = 0.1; // 新しい間隔を平均に混ぜる割合
+    const MAX_INTERVAL: f32 = 1.0; // これより空いたら止まっていたとみなす[sec]
+
+    fn new() -> Self {
+        Self {
+            ticks: -Self::TICK_PER_CLOCK,
+            last: None,
+            interval: 0.0,
+            mark: 0,
+        }
+    }
+    /// Start/Continue の後は、次の Clock を 0 とする(間隔の平均は残す)
+    fn start(&mut self) {
+        self.ticks = -Self::TICK_PER_CLOCK;
+        self.last = None;
+        self.mark = 0;
+    }
+    fn receive(&mut self, time: Instant) {
+        if let Some(last) = self.last {
+            let dt = time.saturating_duration_since(last).as_secs_f32();
+            if dt < Self::MAX_INTERVAL {
+                if self.interval <= 0.0 {
+                    self.interval = dt;
+                } else {
+                    self.interval += (dt - self.interval) * Self::AVERAGE;
+                }
+            }
+        }
+        self.last = Some(time);
+        self.ticks += Self::TICK_PER_CLOCK;
+    }
+    /// time の tick、次の Clock の tick は越えない
+    fn tick_at(&self, time: Instant) -> i32 {
+        if self.ticks < 0 {
+            return 0;
+        }
+        let interp = match self.last {
+            Some(last) if self.interval > 0.0 => {
+                let dt = time.saturating_duration_since(last).as_secs_f32();
+                (dt / self.interval * Self::TICK_PER_CLOCK as f32) as i32
+            }
+            _ => 0,
+        };
+        self.ticks + interp.min(Self::TICK_PER_CLOCK - 1)
+    }
+    fn bpm(&self) -> i16 {
+        if self.interval <= 0.0 {
+            return 0;
+        }
+        (60.0 / (self.interval * 24.0)).round() as i16
+    }
+}
 #[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum RitType {
     Linear,
     LinearPrecise,
@@ -45,12 +167,6 @@ pub enum RitType {
 }
 impl TickGen {
     pub fn new(tp: RitType) -> Self {
-        let rit: Box<dyn Rit> = match tp {
-            RitType::Linear => Box::new(RitLinear::new()),
-            RitType::LinearPrecise => Box::new(RitLinearPrecise::new()),
-            RitType::Sigmoid => Box::new(RitSigmoid::new()),
-            RitType::Control => Box::new(RitCtrl::new()),
-        };
         Self {
             bpm: DEFAULT_BPM,
             meter: Meter(4, 4),
@@ -63,25 +179,74 @@ impl TickGen {
             meter_start_msr: 0,
             crnt_msr: -1,
             crnt_tick_inmsr: 0,
+            start_msr: 0,
+            count_in: false,
             crnt_time: Instant::now(),
+            real_time: Instant::now(),
+            rate: 100,
+            nudge: None,
             prepare_rit: false,
             rit_state: false,
             fermata_state: false,
             prm: RitPrm::default(),
             start_mt: CrntMsrTick::default(),
-            ritgen: rit,
+            ritgen: Self::gen_ritgen(tp, false),
+            rit_type: tp,
+            ctrl: false,
+            rit_min_bpm: 0,
+            rit_steep: 100,
+            gesture: None,
+            gesture_queue: VecDeque::new(),
+            tempo_map: Vec::new(),
+            resume_offset: 0.0,
+            ext_clock: None,
+        }
+    }
+    /// RitLinear は accel. に対応しないため、accel. の時は RitLinearPrecise を使う
+    fn gen_ritgen(tp: RitType, accel: bool) -> Box<dyn Rit> {
+        match tp {
+            RitType::Linear if accel => Box::new(RitLinearPrecise::new()),
+            RitType::Linear => Box::new(RitLinear::new()),
+            RitType::LinearPrecise => Box::new(RitLinearPrecise::new()),
+            RitType::Sigmoid => Box::new(RitSigmoid::new()),
+            RitType::Control => Box::new(RitCtrl::new()),
+        }
+    }
+    /// rit. の種類と、最低テンポ(0:既定値)、Sigmoid の急峻さ[%]を変える
+    /// 実行中の rit. には影響せず、次の rit. から有効
+    pub fn set_rit_type(&mut self, tp: RitType) {
+        self.rit_type = tp;
+    }
+    pub fn set_rit_min_bpm(&mut self, bpm: i16) {
+        self.rit_min_bpm = bpm;
+    }
+    pub fn set_rit_steep(&mut self, steep: i16) {
+        self.rit_steep = (steep as i32).clamp(100, 400);
+    }
+    fn gen_rit_prm(&self, ratio: i32, bar: i32) -> RitPrm {
+        RitPrm {
+            ratio,
+            bar,
+            tick_for_onemsr: self.tick_for_onemsr,
+            tick_for_beat: self.tick_for_beat,
+            min_bpm: self.rit_min_bpm,
+            steep: self.rit_steep,
         }
     }
     pub fn change_beat_event(&mut self, tick_for_onemsr: i32, meter: Meter) {
         self.rit_state = false;
+        self.clear_gesture();
         self.fermata_state = false;
         self.tick_for_onemsr = tick_for_onemsr;
         self.meter = meter;
         self.meter_start_msr = self.crnt_msr;
         self.bpm_start_time = self.crnt_time;
         self.bpm_start_tick = 0;
+        self.nudge = None;
         // DEFAULT_TICK_FOR_ONE_MEASURE を分母で割った値が 1拍の tick 数で正しい！
         self.tick_for_beat = DEFAULT_TICK_FOR_ONE_MEASURE / self.meter.1;
+        self.mark_ext_clock();
+        self.record_tempo();
     }
     pub fn change_bpm(&mut self, bpm: i16) {
         self.bpm_stock = bpm;
@@ -91,7 +256,10 @@ impl TickGen {
         self.fermata_state = false;
         self.bpm_start_tick = self.calc_crnt_tick();
         self.bpm_start_time = self.crnt_time; // Get current time
+        self.nudge = None;
+        self.mark_ext_clock();
         self.bpm = bpm;
+        self.record_tempo();
     }
     fn _change_fermata_event(&mut self) {
         self.rit_state = false;
@@ -102,36 +270,56 @@ impl TickGen {
     //pub fn calc_tick(&mut self)
     pub fn start(&mut self, time: Instant, bpm: i16, resume: bool) {
         self.rit_state = false;
+        self.ctrl = false;
+        self.nudge = None;
+        self.prepare_rit = false;
+        self.clear_gesture();
         self.fermata_state = false;
         self.origin_time = time;
         self.crnt_time = time;
+        self.real_time = time;
         self.bpm_start_tick = 0;
         self.bpm_start_time = time;
         self.bpm = bpm;
         self.bpm_stock = bpm;
+        self.mark_ext_clock();
+        let count_in = std::mem::take(&mut self.count_in);
         if resume {
+            self.start_msr = 0;
             self.meter_start_msr = self.crnt_msr;
+            self.resume_offset = self.estimate_time(self.crnt_msr.max(0));
         } else {
-            self.meter_start_msr = 0;
+            self.start_msr = if count_in { -1 } else { 0 };
+            self.meter_start_msr = self.start_msr;
+            self.resume_offset = 0.0;
+            self.tempo_map.clear();
+            if count_in {
+                self.crnt_msr = self.start_msr - 1; // 最初の gen_tick で小節の頭になる
+            }
         }
+        self.record_tempo();
     }
     pub fn gen_tick(&mut self, crnt_time: Instant) -> (bool, bool, i32) {
         let former_msr = self.crnt_msr;
         let former_tick = self.crnt_tick_inmsr;
-        self.crnt_time = crnt_time;
+        self.crnt_time = self.stretch_time(crnt_time);
+        self.real_time = crnt_time;
         if self.rit_state {
             self.gen_rit();
         } else {
             // same bpm
+            if self.nudge.is_some_and(|(_, end)| self.crnt_time >= end) {
+                self.end_nudge();
+            }
             let tick_from_meter_starts = self.calc_crnt_tick();
             self.crnt_msr = tick_from_meter_starts / self.tick_for_onemsr + self.meter_start_msr;
             self.crnt_tick_inmsr = tick_from_meter_starts % self.tick_for_onemsr;
-            if self.prepare_rit && self.is_over(self.start_mt) {
+            if self.prepare_rit && self.ext_clock.is_none() && self.is_over(self.start_mt) {
                 self.start_rit(self.crnt_time);
             }
         }
         let new_msr = self.crnt_msr != former_msr;
-        if new_msr && !self.rit_state && (self.bpm != self.bpm_stock) {
+        if new_msr && !self.rit_state && self.ext_clock.is_none() && (self.bpm != self.bpm_stock) {
             // Tempo Change
             self.change_bpm_event(self.bpm_stock);
             if self.bpm == 0 {
@@ -147,8 +335,16 @@ impl TickGen {
         };
         (new_msr, new_beat, beat_num)
     }
+    /// 次の start を一小節前(-1)から始める(弱起やカウントのための小節)
+    pub fn set_count_in(&mut self, count_in: bool) {
+        self.count_in = count_in;
+    }
+    /// 一小節前から始めた、最初の小節の中
+    pub fn in_count_in(&self) -> bool {
+        self.crnt_msr < 0 && self.start_msr < 0
+    }
     pub fn get_crnt_msr_tick(&self) -> CrntMsrTick {
-        let msr = if self.crnt_msr < 0 { 0 } else { self.crnt_msr }; // 0以上の値にする
+        let msr = self.crnt_msr.max(self.start_msr); // 開始した小節以上の値にする
         CrntMsrTick {
             msr,
             tick: self.crnt_tick_inmsr,
@@ -157,11 +353,15 @@ impl TickGen {
     }
     pub fn set_crnt_msr(&mut self, msr: i32) {
         self.rit_state = false;
+        self.prepare_rit = false;
+        self.clear_gesture();
         self.fermata_state = false;
         self.origin_time = Instant::now();
         self.crnt_time = Instant::now();
+        self.real_time = self.crnt_time;
         self.bpm_start_time = Instant::now();
         self.bpm_start_tick = 0;
+        self.nudge = None;
         self.crnt_msr = msr;
         self.meter_start_msr = msr;
         self.crnt_tick_inmsr = 0;
@@ -178,49 +378,259 @@ impl TickGen {
         (self.tick_for_onemsr, self.tick_for_beat)
     }
     pub fn get_bpm(&self) -> i16 {
-        self.bpm
+        match self.ext_clock.as_ref().map(|ec| ec.bpm()) {
+            Some(bpm) if bpm > 0 => bpm,
+            _ => self.bpm,
+        }
     }
     pub fn get_real_bpm(&self) -> i16 {
-        if self.rit_state {
+        if self.ext_clock.is_some() {
+            self.get_bpm()
+        } else if self.rit_state {
             self.ritgen.get_real_bpm()
         } else {
             self.bpm
         }
     }
+    /// 再生速度[%]を変える(10-200)、pitch は MIDI なので変わらない
+    pub fn set_rate(&mut self, rate: i16) {
+        self.rate = (rate as i32).clamp(10, 200);
+    }
+    /// 今のテンポに対する速さ[%]を、外部の CC や UI から変える(conductor)
+    /// 最初の指定で RitCtrl を始め、100% に戻りきったら終わる。rit./accel. の間は効かない
+    pub fn set_tempo_ctrl(&mut self, ratio: i16) {
+        if self.ext_clock.is_some() || self.fermata_state || self.prepare_rit || self.bpm <= 0 {
+            return;
+        }
+        if !self.in_ctrl() {
+            if self.rit_state || ratio == 100 {
+                return;
+            }
+            self.ritgen = Self::gen_ritgen(RitType::Control, false);
+            self.ritgen.set_rit(
+                self.bpm as f32,
+                self.crnt_time,
+                self.crnt_tick_inmsr,
+                self.gen_rit_prm(100, 0),
+            );
+            self.rit_state = true;
+            self.ctrl = true;
+            self.nudge = None;
+            self.meter_start_msr = self.crnt_msr;
+            self.bpm_start_time = self.crnt_time;
+        }
+        self.ritgen.control(ratio as i32);
+    }
+    fn in_ctrl(&self) -> bool {
+        self.rit_state && self.ctrl
+    }
+    /// 実際の時刻を、rate で伸縮した演奏上の時刻にする
+    fn stretch_time(&self, time: Instant) -> Instant {
+        if time >= self.real_time {
+            self.crnt_time + (time - self.real_time) * self.rate as u32 / 100
+        } else {
+            self.crnt_time - (self.real_time - time) * self.rate as u32 / 100
+        }
+    }
     pub fn get_meter(&self) -> Meter {
         self.meter
     }
     pub fn get_origin_time(&self) -> Instant {
         self.origin_time
     }
-    fn calc_crnt_tick(&self) -> i32 {
-        let diff = self.crnt_time - self.bpm_start_time;
+    /// start からの経過時間[sec](rate で伸縮した時間)、resume 時は開始小節までの推定時間を足す
+    pub fn get_elapsed_time(&self) -> f32 {
+        (self.crnt_time - self.origin_time).as_secs_f32() + self.resume_offset
+    }
+    /// 先頭から msr 小節目の頭までの推定時間[sec]
+    /// tempo_map を積分し、それ以降は最後の tempo/meter が続くとみなす
+    pub fn estimate_time(&self, msr: i32) -> f32 {
+        let mut seg = TempoSeg {
+            msr: 0,
+            bpm: self.bpm_stock,
+            tick_for_onemsr: self.tick_for_onemsr,
+            tick_for_beat: self.tick_for_beat,
+        };
+        let mut sec = 0.0;
+        for next in self.tempo_map.iter() {
+            if next.msr >= msr {
+                break;
+            }
+            sec += seg.msr_to_sec(next.msr - seg.msr);
+            seg = *next;
+        }
+        sec + seg.msr_to_sec(msr - seg.msr)
+    }
+    /// 現在の小節から適用される tempo/meter を記録する
+    fn record_tempo(&mut self) {
+        let msr = self.crnt_msr.max(0);
+        self.tempo_map.retain(|x| x.msr < msr);
+        self.tempo_map.push(TempoSeg {
+            msr,
+            bpm: self.bpm,
+            tick_for_onemsr: self.tick_for_onemsr,
+            tick_for_beat: self.tick_for_beat,
+        });
+    }
+    /// time が一番近い拍から何 tick ずれているかを返す(+:拍より後)
+    pub fn beat_error(&self, time: Instant) -> i32 {
+        let diff = self
+            .stretch_time(time)
+            .saturating_duration_since(self.bpm_start_time);
         let elapsed_tick =
             ((self.tick_for_beat as f32) * (self.bpm as f32) * diff.as_secs_f32()) / 60.0;
-        elapsed_tick as i32 + self.bpm_start_tick
+        let tick_in_beat = (elapsed_tick as i32 + self.bpm_start_tick) % self.tick_for_beat;
+        if tick_in_beat < self.tick_for_beat / 2 {
+            tick_in_beat
+        } else {
+            tick_in_beat - self.tick_for_beat
+        }
+    }
+    /// 位相を tick 数だけずらす(+:進める)
+    /// 小節線を逆戻りしないように、戻す量は小節内の tick 数までとする
+    pub fn shift_phase(&mut self, tick: i32) {
+        if self.rit_state || self.fermata_state {
+            return;
+        }
+        self.bpm_start_tick += tick.max(-self.crnt_tick_inmsr);
+    }
+    /// 外部の MIDI Clock に合わせるか(slave mode)、切り替えた所から tick を続ける
+    pub fn set_ext_clock(&mut self, on: bool) {
+        if on == self.ext_clock.is_some() {
+            return;
+        }
+        self.bpm_start_tick = self.calc_crnt_tick();
+        self.bpm_start_time = self.crnt_time;
+        self.nudge = None;
+        self.ext_clock = if on { Some(ExtClock::new()) } else { None };
+    }
+    pub fn is_ext_clock(&self) -> bool {
+        self.ext_clock.is_some()
+    }
+    /// Start/Continue を受信した、次の Clock から数え直す
+    pub fn ext_clock_start(&mut self) {
+        if let Some(ec) = self.ext_clock.as_mut() {
+            ec.start();
+        }
+    }
+    /// MIDI Clock(0xF8) を受信した
+    pub fn ext_clock(&mut self, time: Instant) {
+        if let Some(ec) = self.ext_clock.as_mut() {
+            ec.receive(time);
+        }
+    }
+    /// bpm_start_time の時点の、外部 Clock による tick を記録する
+    fn mark_ext_clock(&mut self) {
+        let time = self.real_time;
+        if let Some(ec) = self.ext_clock.as_mut() {
+            ec.mark = ec.tick_at(time);
+        }
+    }
+    fn calc_crnt_tick(&self) -> i32 {
+        if let Some(ec) = self.ext_clock.as_ref() {
+            return ec.tick_at(self.real_time) - ec.mark + self.bpm_start_tick;
+        }
+        self.elapsed_tick(self.crnt_time) + self.bpm_start_tick
+    }
+    /// bpm_start_time から time までの tick 数、nudge 中はその速さで数える
+    fn elapsed_tick(&self, time: Instant) -> i32 {
+        let diff = time.saturating_duration_since(self.bpm_start_time);
+        let rate = self.nudge.map_or(100, |(rate, _)| rate);
+        let elapsed_tick = ((self.tick_for_beat as f32)
+            * (self.bpm as f32)
+            * (rate as f32 / 100.0)
+            * diff.as_secs_f32())
+            / 60.0;
+        elapsed_tick as i32
+    }
+    /// 一拍の間だけ速さを pct[%] ずらし、拍の位相を少し動かしてから元の bpm に戻る
+    /// bpm を変えるのとは違い、表示や tempo の記録は変えない。続けて指定すると、そこから一拍延びる
+    pub fn nudge(&mut self, pct: i16) {
+        if self.rit_state || self.fermata_state || self.ext_clock.is_some() || self.bpm <= 0 {
+            return;
+        }
+        self.end_nudge();
+        let beat = Duration::from_secs_f32(60.0 / self.bpm as f32);
+        self.nudge = Some((100 + pct.clamp(-10, 10) as i32, self.crnt_time + beat));
+    }
+    /// nudge を終わり(nudge 中でなければ今の時刻で)、そこから元の速さで数え直す
+    fn end_nudge(&mut self) {
+        let time = self
+            .nudge
+            .map_or(self.crnt_time, |(_, end)| end.min(self.crnt_time));
+        self.bpm_start_tick += self.elapsed_tick(time);
+        self.bpm_start_time = time;
+        self.nudge = None;
     }
     /// rit. を開始準備する
     /// 現在の時間と tick を得るが、rit. 開始は拍にクオンタイズされるため、すぐに開始しない
     pub fn prepare_rit(&mut self, ratio: i32, bar: i32, target_bpm: i16) {
-        self.prm = RitPrm {
-            ratio,
-            bar,
-            tick_for_onemsr: self.tick_for_onemsr,
-            tick_for_beat: self.tick_for_beat,
-        };
+        if self.in_ctrl() {
+            // RitCtrl で変えていた速さは、ここで元に戻す
+            self.meter_start_msr = self.crnt_msr;
+            self.bpm_start_time = self.crnt_time;
+            self.bpm_start_tick = self.crnt_tick_inmsr;
+        }
+        self.ctrl = false;
+        self.prm = self.gen_rit_prm(ratio, bar);
         self.prepare_rit = true;
         self.rit_state = false;
         let crnt = self.get_crnt_msr_tick();
         self.start_mt = self.quantize_tick(crnt, self.meter.1);
         self.bpm_stock = target_bpm;
     }
+    /// then で連結された rit./accel. を準備する
+    /// 最初のものは prepare_rit と同じく拍から、以降は前のものが終わった小節の頭から始める
+    pub fn prepare_gesture(&mut self, mut chain: VecDeque<TempoGesture>) {
+        if let Some(first) = chain.pop_front() {
+            let ratio = first.ratio_from(self.bpm);
+            let target_bpm = if first.target_bpm == TempoGesture::TEMPO_HOLD {
+                self.bpm
+            } else {
+                first.target_bpm
+            };
+            self.prepare_rit(ratio, first.bar, target_bpm);
+            self.gesture = Some(first);
+            self.gesture_queue = chain;
+        }
+    }
+    /// 実行中の rit./accel. (accel か, 到達 bpm, 後に続く数)
+    pub fn get_gesture(&self) -> Option<(bool, i16, usize)> {
+        self.gesture
+            .map(|g| (g.accel, g.target_bpm, self.gesture_queue.len()))
+    }
+    fn clear_gesture(&mut self) {
+        self.gesture = None;
+        self.gesture_queue.clear();
+    }
+    /// rit. 終了時、連結された次の rit./accel. があればすぐに開始する
+    fn next_gesture(&mut self) {
+        self.gesture = None;
+        if self.bpm <= 0 {
+            // fermata の後には続けない
+            self.gesture_queue.clear();
+            return;
+        }
+        if let Some(next) = self.gesture_queue.pop_front() {
+            self.prm = self.gen_rit_prm(next.ratio_from(self.bpm), next.bar);
+            self.bpm_stock = if next.target_bpm == TempoGesture::TEMPO_HOLD {
+                self.bpm
+            } else {
+                next.target_bpm
+            };
+            self.gesture = Some(next);
+            self.start_rit(self.crnt_time);
+        }
+    }
     // rit. 開始
     fn start_rit(&mut self, start_time: Instant) {
-        if self.prm.ratio < 100 && !self.rit_state && !self.fermata_state {
+        if self.prm.ratio != 100 && !self.rit_state && !self.fermata_state {
+            self.ritgen = Self::gen_ritgen(self.rit_type, self.prm.ratio > 100);
             self.ritgen
                 .set_rit(self.bpm as f32, start_time, self.crnt_tick_inmsr, self.prm);
         }
         self.rit_state = true;
+        self.nudge = None;
         self.meter_start_msr = self.crnt_msr;
         self.bpm_start_time = start_time;
     }
@@ -232,12 +642,21 @@ impl TickGen {
             // rit 終了
             self.prepare_rit = false;
             self.rit_state = false;
+            self.ctrl = false;
             self.meter_start_msr = self.crnt_msr;
             self.bpm_start_time = self.crnt_time;
             self.bpm_start_tick = self.crnt_tick_inmsr;
             self.prm = RitPrm::default();
             self.start_mt = CrntMsrTick::default();
+            if self
+                .gesture
+                .is_some_and(|g| g.target_bpm == TempoGesture::TEMPO_HOLD)
+            {
+                self.bpm_stock = self.ritgen.get_real_bpm();
+            }
             self.bpm = self.bpm_stock;
+            self.record_tempo();
+            self.next_gesture();
         }
     }
     fn is_over(&self, tgt: CrntMsrTick) -> bool {
@@ -268,6 +687,20 @@ pub struct RitPrm {
     bar: i32, // これから rit.する小節数, 0: 次の小節まで、1: 次の次の小節まで (何回小節跨ぎをスルーするか)
     tick_for_onemsr: i32, // 1小節の tick 数
     tick_for_beat: i32, // 1拍の tick 数
+    min_bpm: i16, // これより遅くしない(0:各 Rit の既定値)
+    steep: i32, // Sigmoid の曲線の急峻さ[%](100:標準)
+}
+impl RitPrm {
+    /// rit. の最終到達 tps を、最低テンポで制限する
+    /// 最低テンポが開始時より速くても、rit. で速くはしない
+    fn limit_tps(&self, original_tps: i32, target_tps: i32, bpm2tps: f32) -> i32 {
+        let limited = target_tps.max((self.min_bpm as f32 * bpm2tps) as i32);
+        if target_tps < original_tps {
+            limited.min(original_tps)
+        } else {
+            limited
+        }
+    }
 }
 pub trait Rit {
     // rit 開始時に呼ばれる
@@ -288,6 +721,9 @@ pub trait Rit {
 
     //  現在の bpm を得る
     fn get_real_bpm(&self) -> i16; // 現在のテンポ
+
+    //  rit 中に外部から速さ[%]を変える(RitCtrl のみ)
+    fn control(&mut self, _ratio: i32) {}
 }
 
 //*******************************************************************
@@ -307,6 +743,7 @@ pub struct RitLinear {
     last_addup_time: Instant,
     t0_time: f32,       // tempo=0 到達時間
     t0_addup_tick: i32, // tempo=0 到達時の積算tick
+    min_tempo: i16,     // これより遅くしない
 }
 
 impl Rit for RitLinear {
@@ -332,6 +769,11 @@ impl Rit for RitLinear {
         self.t0_addup_tick = ((self.delta_tps / 2.0) * self.t0_time * self.t0_time) as i32;
         self.rit_bar = prm.bar;
         self.rit_bar_count = 0;
+        self.min_tempo = if prm.min_bpm > 0 {
+            prm.min_bpm
+        } else {
+            Self::MINIMUM_TEMPO
+        };
     }
     fn calc_tick_rit(&mut self, crnt_time: Instant) -> (i32, bool, bool) {
         // output: self.crnt_msr の更新
@@ -372,24 +814,25 @@ impl RitLinear {
             last_addup_time: Instant::now(),
             t0_time: 0.0,
             t0_addup_tick: 0,
+            min_tempo: Self::MINIMUM_TEMPO,
         }
     }
+    const MINIMUM_TEMPO: i16 = 20;
     fn calc_addup_tick_rit(&mut self, crnt_time: Instant) -> i32 {
-        const MINIMUM_TEMPO: i16 = 20;
         let start_time = (crnt_time - self.start_time).as_secs_f32();
         let time_to0 = self.t0_time - start_time;
         self.delta_bpm = (self.delta_tps * start_time / self.bpm2tps) as i16;
         let addup_tick: i32;
-        if self.original_bpm as i16 - self.delta_bpm > MINIMUM_TEMPO {
-            // target bpm が MINIMUM_TEMPO 以上
+        if self.original_bpm as i16 - self.delta_bpm > self.min_tempo {
+            // target bpm が min_tempo 以上
             addup_tick = self.t0_addup_tick - (time_to0 * time_to0 * self.delta_tps / 2.0) as i32; // 積算Tickの算出
             self.last_addup_tick = addup_tick;
             self.last_addup_time = crnt_time;
         } else {
-            self.delta_bpm = self.original_bpm as i16 - MINIMUM_TEMPO;
+            self.delta_bpm = self.original_bpm as i16 - self.min_tempo;
             addup_tick = self.last_addup_tick
                 + (self.bpm2tps
-                    * (MINIMUM_TEMPO as f32)
+                    * (self.min_tempo as f32)
                     * (crnt_time - self.last_addup_time).as_secs_f32()) as i32;
         }
         addup_tick
@@ -430,7 +873,11 @@ impl Rit for RitLinearPrecise {
         self.tick_for_onemsr = prm.tick_for_onemsr;
         self.original_tps = (bpm * self.bpm2tps) as i32;
         self.crnt_tps = self.original_tps;
-        self.target_tps = (self.original_tps * prm.ratio) / 100;
+        self.target_tps = prm.limit_tps(
+            self.original_tps,
+            (self.original_tps * prm.ratio) / 100,
+            self.bpm2tps,
+        );
         self.total_tick = (prm.tick_for_onemsr - start_tick) + (prm.bar * prm.tick_for_onemsr);
         let milli_sec = ((self.total_tick as f32) * 2.0)
             / (self.original_tps as f32 + self.target_tps as f32)
@@ -560,7 +1007,10 @@ pub struct RitSigmoid {
     crnt_tps: i32,
     tick_for_onemsr: i32,
     tps_ratio: f32,
+    steep: f32,     // 曲線の急峻さ(1.0:標準)
     bar_count: i32, // rit_bar を小節頭で inc.
+    // 標準でない steep の sigmoid の値と積分値(set_rit で作る)
+    steep_table: Vec<(f32, f32)>,
 }
 
 impl Rit for RitSigmoid {
@@ -581,13 +1031,27 @@ impl Rit for RitSigmoid {
         self.tick_for_onemsr = prm.tick_for_onemsr;
         self.original_tps = (bpm * self.bpm2tps) as i32;
         self.crnt_tps = self.original_tps;
-        self.target_tps = (self.original_tps * prm.ratio) / 100;
+        self.target_tps = prm.limit_tps(
+            self.original_tps,
+            (self.original_tps * prm.ratio) / 100,
+            self.bpm2tps,
+        );
         self.total_tick = (prm.tick_for_onemsr - start_tick) + (prm.bar * prm.tick_for_onemsr);
         let milli_sec = ((self.total_tick as f32) * 2.0)
             / (self.original_tps as f32 + self.target_tps as f32)
             * 1000.0;
         self.total_time = Duration::from_millis(milli_sec as u64);
         self.tps_ratio = self.original_tps as f32 / self.target_tps as f32;
+        self.steep = if prm.steep > 0 {
+            prm.steep as f32 / 100.0
+        } else {
+            1.0
+        };
+        self.steep_table = if self.steep == 1.0 {
+            Vec::new()
+        } else {
+            Self::gen_steep_table(self.steep)
+        };
         self.bar_count = 0;
         println!(
             ">>>Rit Status: total_tick:{:?}, total_time:{:?}",
@@ -596,24 +1060,28 @@ impl Rit for RitSigmoid {
     }
     fn calc_tick_rit(&mut self, crnt_time: Instant) -> (i32, bool, bool) {
         let elapsed_time = crnt_time - self.start_time;
-        let time_index =
-            (IDX_MAX as f32 * elapsed_time.as_secs_f32() / self.total_time.as_secs_f32()) as usize;
+        let time_pos = IDX_MAX as f32 * elapsed_time.as_secs_f32() / self.total_time.as_secs_f32();
         let index_rate;
         let integral_sig;
-        if time_index >= IDX_MAX {
+        if time_pos as usize >= IDX_MAX {
             // reached last bar, and stop rit.
             self.crnt_tps = self.target_tps;
             index_rate = 1.0;
             integral_sig = 1.0;
         } else {
-            self.crnt_tps = self.target_tps
-                + ((self.original_tps - self.target_tps) as f32 * SIGMOID[time_index]) as i32;
-            index_rate = time_index as f32 / IDX_MAX as f32;
-            integral_sig = INTEGRAL_SIGMOID[time_index];
-        }
-        let tps_rate =
-            2.0 * self.target_tps as f32 / (self.original_tps as f32 - self.target_tps as f32);
-        let addup_base = (integral_sig + (tps_rate * index_rate)) / (1.0 + tps_rate);
+            let (sig, integral) = self.sigmoid_at(time_pos);
+            self.crnt_tps =
+                self.target_tps + ((self.original_tps - self.target_tps) as f32 * sig) as i32;
+            index_rate = time_pos / IDX_MAX as f32;
+            integral_sig = integral;
+        }
+        let addup_base = if self.original_tps == self.target_tps {
+            index_rate // テンポが変わらない
+        } else {
+            let tps_rate =
+                2.0 * self.target_tps as f32 / (self.original_tps as f32 - self.target_tps as f32);
+            (integral_sig + (tps_rate * index_rate)) / (1.0 + tps_rate)
+        };
         let addup_tick = (addup_base * (self.total_tick as f32)) as i32;
         if addup_tick >= self.total_tick {
             // reached last bar, and stop rit.
@@ -652,39 +1120,143 @@ impl RitSigmoid {
             target_tps: 0,
             crnt_tps: 0,
             tps_ratio: 0.0,
+            steep: 1.0,
             bar_count: 0,
+            steep_table: Vec::new(),
         }
     }
+    /// 表の間の時刻(time_pos)での sigmoid の値と積分値
+    /// 表の一区間の中で tick が止まり、区間の境目で音がまとまって出ないよう、前後の値を補間する
+    /// 積分値は区間の終わりまでの値なので、一つ前の値(最初は0)から補間する
+    fn sigmoid_at(&self, time_pos: f32) -> (f32, f32) {
+        let idx = time_pos as usize;
+        let frac = time_pos - idx as f32;
+        let (sig, itg) = self.sigmoid(idx);
+        let itg_before = if idx == 0 {
+            0.0
+        } else {
+            self.sigmoid(idx - 1).1
+        };
+        let sig_next = if idx + 1 < IDX_MAX {
+            self.sigmoid(idx + 1).0
+        } else {
+            sig
+        };
+        (
+            sig + (sig_next - sig) * frac,
+            itg_before + (itg - itg_before) * frac,
+        )
+    }
+    /// time_index での sigmoid の値と、その積分値(全体で1.0)
+    fn sigmoid(&self, time_index: usize) -> (f32, f32) {
+        match self.steep_table.get(time_index) {
+            Some(&v) => v,
+            None => (SIGMOID[time_index], INTEGRAL_SIGMOID[time_index]),
+        }
+    }
+    /// 中央を軸に時間を縮めた sigmoid の表と、その積分値の表
+    fn gen_steep_table(steep: f32) -> Vec<(f32, f32)> {
+        let sig: Vec<f32> = (0..IDX_MAX)
+            .map(|idx| {
+                let t = idx as f32 / (IDX_MAX - 1) as f32;
+                let st = (0.5 + (t - 0.5) * steep).clamp(0.0, 1.0);
+                SIGMOID[(st * (IDX_MAX - 1) as f32).round() as usize]
+            })
+            .collect();
+        let total: f32 = sig.iter().sum();
+        let mut integral = 0.0;
+        sig.iter()
+            .map(|s| {
+                integral += s;
+                (*s, integral / total)
+            })
+            .collect()
+    }
 }
 
 //*******************************************************************
 //          Rit. Control Struct
 //*******************************************************************
-pub struct RitCtrl {}
+//  外部の CC や UI から、今のテンポに対する速さ[%]を連続的に変える(conductor)
+//  急に変わらないよう、目標の速さに SMOOTH_SEC の時定数で近づける
+pub struct RitCtrl {
+    bpm2tps: f32,
+    original_tps: f32,
+    last_time: Instant,
+    start_tick: i32,
+    addup_tick: f32, // rit.開始時からの積算tick
+    tick_for_onemsr: i32,
+    crnt_ratio: f32,   // 今の速さ[%]
+    target_ratio: f32, // 目標の速さ[%]
+    bar_count: i32,
+}
 
 impl Rit for RitCtrl {
     //==== rit. ======================
-    // ratio  0:   tempo 停止
-    //        50:  1secで tempo を 50%(1/2)
-    //        100: そのまま
+    // ratio  50:  tempo を 50%(1/2) に
+    //        100: そのまま(戻りきったら終了)
+    //        200: tempo を 200%(2倍) に
     fn set_rit(
         &mut self,
-        _bpm: f32,
-        _start_time: Instant,
-        _start_tick: i32,
-        _prm: RitPrm, // rit.のパラメータ
+        bpm: f32,
+        start_time: Instant,
+        start_tick: i32,
+        prm: RitPrm, // rit.のパラメータ
     ) {
+        self.bpm2tps = prm.tick_for_beat as f32 / 60.0;
+        self.original_tps = bpm * self.bpm2tps;
+        self.last_time = start_time;
+        self.start_tick = start_tick;
+        self.addup_tick = 0.0;
+        self.tick_for_onemsr = prm.tick_for_onemsr;
+        self.crnt_ratio = 100.0;
+        self.target_ratio = prm.ratio as f32;
+        self.bar_count = 0;
     }
-    fn calc_tick_rit(&mut self, _crnt_time: Instant) -> (i32, bool, bool) {
-        (0, true, true)
+    fn calc_tick_rit(&mut self, crnt_time: Instant) -> (i32, bool, bool) {
+        let dt = crnt_time
+            .saturating_duration_since(self.last_time)
+            .as_secs_f32();
+        self.last_time = crnt_time;
+        let follow = (dt / Self::SMOOTH_SEC).min(1.0);
+        self.crnt_ratio += (self.target_ratio - self.crnt_ratio) * follow;
+        self.addup_tick += self.original_tps * self.crnt_ratio / 100.0 * dt;
+        let return_tick = self.start_tick + self.addup_tick as i32;
+        let bar_cnt = return_tick / self.tick_for_onemsr;
+        let mut cross_barline = false;
+        if bar_cnt > self.bar_count {
+            // 小節線を超えたとき
+            self.bar_count = bar_cnt;
+            cross_barline = true;
+        }
+        let rit_end =
+            self.target_ratio == 100.0 && (self.crnt_ratio - 100.0).abs() < Self::END_RANGE;
+        (return_tick, cross_barline, rit_end)
     }
     fn get_real_bpm(&self) -> i16 {
-        0
+        (self.original_tps * self.crnt_ratio / 100.0 / self.bpm2tps) as i16
+    }
+    fn control(&mut self, ratio: i32) {
+        self.target_ratio = ratio.clamp(Self::MIN_RATIO, Self::MAX_RATIO) as f32;
     }
 }
 
 impl RitCtrl {
+    const SMOOTH_SEC: f32 = 0.3; // 目標の速さに近づく時定数
+    const END_RANGE: f32 = 0.5; // 100% とみなす範囲[%]
+    const MIN_RATIO: i32 = 50;
+    const MAX_RATIO: i32 = 200;
     pub fn new() -> Self {
-        Self {}
+        Self {
+            bpm2tps: 0.0,
+            original_tps: 0.0,
+            last_time: Instant::now(),
+            start_tick: 0,
+            addup_tick: 0.0,
+            tick_for_onemsr: DEFAULT_TICK_FOR_ONE_MEASURE,
+            crnt_ratio: 100.0,
+            target_ratio: 100.0,
+            bar_count: 0,
+        }
     }
 }
@@ -0,0 +1,81 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use crate::cmd::txt2seq_cmps;
+use crate::lpnlib::*;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum ToneClass {
+    Chord,       // 和音の構成音
+    Tension,     // 9th, 11th, 13th (Scale の時は、構成音以外の Scale 音)
+    NonHarmonic, // それ以外
+}
+
+/// 今の和音(root:0-11, ctbl)に対して、note(keynote を含まない)がどの音かを調べる
+pub fn classify_tone(root: i16, ctbl: i16, note: i16) -> ToneClass {
+    const TENSION: [i16; 3] = [2, 5, 9]; // root からの音程
+    let (tbl, _) = txt2seq_cmps::get_table(ctbl as usize);
+    let chord_tone: Vec<i16> = if tbl.len() > 5 {
+        // Scale の場合は、三度ずつ重ねた音を構成音とする
+        tbl.iter().step_by(2).take(3).copied().collect()
+    } else {
+        tbl.to_vec()
+    };
+    let pc = (note - root).rem_euclid(12);
+    if chord_tone.iter().any(|x| x.rem_euclid(12) == pc) {
+        ToneClass::Chord
+    } else if (tbl.len() > 5 && tbl.iter().any(|x| x.rem_euclid(12) == pc))
+        || (tbl.len() <= 5 && TENSION.contains(&pc))
+    {
+        ToneClass::Tension
+    } else {
+        ToneClass::NonHarmonic
+    }
+}
+
+//*******************************************************************
+//          Tone Analyzer Struct
+//*******************************************************************
+//  演奏された音を、和音に対して 構成音/Tension/非和声音 に分類して数える
+//  emphasis が 0 でなければ、構成音を強く、非和声音を弱く演奏する
+pub struct ToneAnalyzer {
+    enable: bool,
+    emphasis: i16,
+    crnt: ToneStats, // 今の小節
+}
+impl ToneAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            enable: false,
+            emphasis: 0,
+            crnt: [[0; TONE_CLASS_NUM]; MAX_COMPOSITION_PART],
+        }
+    }
+    pub fn is_enable(&self) -> bool {
+        self.enable
+    }
+    /// emphasis: 負なら off
+    pub fn set(&mut self, emphasis: i16) {
+        self.enable = emphasis >= 0;
+        self.emphasis = emphasis.max(0);
+        self.crnt = [[0; TONE_CLASS_NUM]; MAX_COMPOSITION_PART];
+    }
+    /// 音を分類して数え、velocity の増減を返す
+    pub fn put(&mut self, part: usize, root: i16, ctbl: i16, note: i16) -> i16 {
+        if !self.enable || ctbl == NO_TABLE || part >= MAX_COMPOSITION_PART {
+            return 0;
+        }
+        let tc = classify_tone(root, ctbl, note);
+        self.crnt[part][tc as usize] += 1;
+        match tc {
+            ToneClass::Chord => self.emphasis,
+            ToneClass::Tension => 0,
+            ToneClass::NonHarmonic => -self.emphasis,
+        }
+    }
+    /// 小節頭で呼び、前の小節の集計を返す
+    pub fn new_measure(&mut self) -> ToneStats {
+        std::mem::replace(&mut self.crnt, [[0; TONE_CLASS_NUM]; MAX_COMPOSITION_PART])
+    }
+}
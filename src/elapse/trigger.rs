@@ -0,0 +1,165 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use super::note_translation::ROOT2NTNUM;
+use super::tickgen::CrntMsrTick;
+use crate::cmd::txt2seq_cmps;
+use crate::lpnlib::*;
+
+/// V, VII, 属七の和音か
+pub fn is_dominant(root: i16, tbl: i16) -> bool {
+    if root == NO_ROOT || root as usize >= ROOT2NTNUM.len() || tbl == NO_TABLE {
+        return false;
+    }
+    let degree = ROOT2NTNUM[root as usize].rem_euclid(12);
+    degree == 7 || degree == 11 || txt2seq_cmps::get_table_name(tbl as usize) == "_7"
+}
+
+//*******************************************************************
+//          Trigger Struct
+//*******************************************************************
+//  曲の出来事(scene の切り替え、属和音への変化、Loop の最後の小節)に合わせて、
+//  one-shot の音(サンプラーのシンバルのスウェルや riser など)を鳴らす
+//  鳴らした音は、一小節後に止める
+pub struct Trigger {
+    rules: Vec<(i16, u8, u8, u8)>, // (event, ch:0-15, note, velocity)
+    dominant: [bool; MAX_COMPOSITION_PART], // 直前の和音が属和音か
+    fired: [Option<(i32, i32)>; TRIGGER_MAX], // 最後に鳴らした時刻
+    section: bool,                 // 次の小節の頭で section の音を鳴らす
+    queue: Vec<((i32, i32), [u8; 3])>, // (時刻, Note On/Off) 時刻順
+}
+impl Trigger {
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            dominant: [false; MAX_COMPOSITION_PART],
+            fired: [None; TRIGGER_MAX],
+            section: false,
+            queue: Vec::new(),
+        }
+    }
+    /// note が負なら、その event の設定を全て消す。event が負なら全て消す
+    pub fn set(&mut self, event: i16, note: i16, ch: i16, vel: i16) {
+        if event < 0 {
+            self.rules.clear();
+        } else if note < 0 {
+            self.rules.retain(|r| r.0 != event);
+        } else {
+            self.rules.retain(|r| (r.0, r.2) != (event, note as u8));
+            self.rules.push((
+                event,
+                (ch - 1).clamp(0, 15) as u8,
+                note.clamp(0, 127) as u8,
+                vel.clamp(1, 127) as u8,
+            ));
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+    /// scene の切り替えを知らせる
+    pub fn reserve_section(&mut self) {
+        self.section = true;
+    }
+    /// 和音が変わった時。属和音でない和音から、属和音に変わったら、和音の位置(at)で鳴らす
+    /// 複数のパートが同じ和音に変わっても、一度だけ鳴らす
+    pub fn chord(&mut self, part: usize, root: i16, tbl: i16, at: (i32, i32)) {
+        if part >= MAX_COMPOSITION_PART {
+            return;
+        }
+        let dom = is_dominant(root, tbl);
+        if dom && !self.dominant[part] {
+            self.fire(TRIGGER_DOMINANT, at);
+        }
+        self.dominant[part] = dom;
+    }
+    /// 小節の頭。loop_end: いずれかのパートの Composition が Loop の最後の小節か
+    pub fn msr_top(&mut self, loop_end: bool, crnt_: &CrntMsrTick) {
+        let now = (crnt_.msr, crnt_.tick);
+        if self.section {
+            self.section = false;
+            self.fire(TRIGGER_SECTION, now);
+        }
+        if loop_end {
+            self.fire(TRIGGER_LOOP_END, now);
+        }
+    }
+    /// 今の時刻までに鳴らす/止める音
+    pub fn due(&mut self, crnt_: &CrntMsrTick) -> Vec<(u8, u8, u8)> {
+        let now = (crnt_.msr, crnt_.tick);
+        let idx = self.queue.partition_point(|(at, _)| *at <= now);
+        self.queue
+            .drain(..idx)
+            .map(|(_, [sts, note, vel])| (sts, note, vel))
+            .collect()
+    }
+    /// stop 時: 鳴っている音を全て止め、まだ鳴らしていない音と和音の記憶を消す
+    pub fn stop(&mut self) -> Vec<(u8, u8, u8)> {
+        self.dominant = [false; MAX_COMPOSITION_PART];
+        self.fired = [None; TRIGGER_MAX];
+        self.section = false;
+        let mut offs: Vec<(u8, u8, u8)> = self
+            .queue
+            .drain(..)
+            .filter(|(_, [_, _, vel])| *vel == 0)
+            .map(|(_, [sts, note, _])| (sts, note, 0))
+            .collect();
+        offs.dedup();
+        offs
+    }
+    /// loop や practice で小節が飛んだ時: 鳴っている音を、飛んだ先の時刻で一小節後に止める
+    /// 和音の記憶は消して、戻った先の属和音でもう一度鳴らす
+    pub fn shift(&mut self, msrs: i32) {
+        self.dominant = [false; MAX_COMPOSITION_PART];
+        for (at, _) in self.queue.iter_mut() {
+            at.0 += msrs;
+        }
+        for at in self.fired.iter_mut().flatten() {
+            at.0 += msrs;
+        }
+    }
+    /// export setup 用: dominant: ch.10 note49
+    /// 音は key(0-11) と spell に合わせた音名で書く
+    pub fn summary(&self, key: i16, spell: i16) -> Vec<(&str, String)> {
+        let names = ["section", "dominant", "loop end"];
+        self.rules
+            .iter()
+            .map(|(ev, ch, note, vel)| {
+                (
+                    names[*ev as usize],
+                    format!(
+                        "ch.{} {}({}) vel{}",
+                        ch + 1,
+                        note_readout(*note, key, spell, NOTENAME_NAME),
+                        note,
+                        vel
+                    ),
+                )
+            })
+            .collect()
+    }
+    fn fire(&mut self, event: i16, at: (i32, i32)) {
+        if self.fired[event as usize] == Some(at) {
+            return;
+        }
+        self.fired[event as usize] = Some(at);
+        let notes: Vec<(u8, u8, u8)> = self
+            .rules
+            .iter()
+            .filter(|r| r.0 == event)
+            .map(|(_, ch, note, vel)| (0x90 | ch, *note, *vel))
+            .collect();
+        for (sts, note, vel) in notes.into_iter() {
+            self.push(at, [sts, note, vel]);
+            self.push((at.0 + 1, at.1), [sts, note, 0]);
+        }
+    }
+    /// 同じ時刻なら Note Off を先にする
+    fn push(&mut self, at: (i32, i32), msg: [u8; 3]) {
+        let idx = self
+            .queue
+            .partition_point(|(t, m)| (*t, m[2] != 0) <= (at, msg[2] != 0));
+        self.queue.insert(idx, (at, msg));
+    }
+}
@@ -0,0 +1,87 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use chrono::Local;
+
+use super::lpn_file::*;
+
+pub const CAPTURE_FOLDER: &str = "capture";
+
+//*******************************************************************
+//          Frame Capture Struct
+//*******************************************************************
+//  画面を PNG で保存するファイル名を決める
+//  実際の書き出しは nannou の capture_frame に任せる(別 thread で行われる)
+pub struct FrameCapture {
+    shot: bool,
+    rec: Option<RecState>,
+}
+struct RecState {
+    folder: String,
+    every: u32, // 何 frame ごとに保存するか(1:全 frame)
+    frame: u32,
+    count: u32,
+}
+impl LpnFile for FrameCapture {}
+impl FrameCapture {
+    pub fn new() -> Self {
+        Self {
+            shot: false,
+            rec: None,
+        }
+    }
+    /// 次の frame を一枚保存する
+    pub fn req_shot(&mut self) {
+        self.shot = true;
+    }
+    /// every: 何 frame ごとに保存するか, 0:録画終了
+    pub fn set_rec(&mut self, every: u32) {
+        if every == 0 {
+            if let Some(rec) = self.rec.take() {
+                println!(
+                    "Recorded {} frames to {}. (ex. ffmpeg -framerate 30 -i {}/%06d.png out.mp4)",
+                    rec.count, rec.folder, rec.folder
+                );
+            }
+            return;
+        }
+        let folder = format!(
+            "{}/{}",
+            CAPTURE_FOLDER,
+            Local::now().format("%Y-%m-%d_%H-%M-%S")
+        );
+        self.make_folder(&folder);
+        self.rec = Some(RecState {
+            folder,
+            every,
+            frame: 0,
+            count: 0,
+        });
+    }
+    /// frame ごとに呼ばれ、保存するならファイル名を返す
+    /// 録画は再生中のみ行う
+    pub fn next_frame(&mut self, during_play: bool) -> Option<String> {
+        if self.shot {
+            self.shot = false;
+            self.make_folder(CAPTURE_FOLDER);
+            let dflt = self.default_file_name();
+            return Some(format!(
+                "{}/{}.png",
+                CAPTURE_FOLDER,
+                &dflt[0..dflt.len() - 4]
+            ));
+        }
+        match self.rec.as_mut() {
+            Some(rec) if during_play => {
+                rec.frame += 1;
+                if rec.frame % rec.every != 0 {
+                    return None;
+                }
+                rec.count += 1;
+                Some(format!("{}/{:06}.png", rec.folder, rec.count))
+            }
+            _ => None,
+        }
+    }
+}
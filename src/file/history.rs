@@ -15,7 +15,7 @@ use crate::lpnlib::*;
 pub struct History {
     input_lines: Vec<(String, String)>,
     history_ptr: usize,
-    loaded_text: Vec<String>,
+    loaded_text: Vec<(usize, String)>, // (ファイルの行番号(1origin), コマンド)
 }
 
 impl LpnFile for History {}
@@ -88,7 +88,7 @@ impl History {
         self.loaded_text = Vec::new();
         match fs::read_to_string(fp) {
             Ok(content) => {
-                for line in content.lines() {
+                for (num, line) in content.lines().enumerate() {
                     let mut lodable = true;
                     if line.len() > 1 {
                         let notxt = line[0..2].to_string();
@@ -116,7 +116,7 @@ impl History {
                         inside_blk = false;
                     }
                     if !line.is_empty() && lodable && inside_blk {
-                        self.loaded_text.push(line.to_string());
+                        self.loaded_text.push((num + 1, line.to_string()));
                     }
                 }
             }
@@ -156,13 +156,14 @@ impl History {
     /// ファイル内で !msr() を使ったデータにおいて、
     /// 指定された小節数から、ロードされたデータの再生開始場所を調べ、
     /// そこから次の !msr() までのデータを返す
-    pub fn get_loaded_text(&self, mt: CrntMsrTick) -> (Vec<String>, Option<CrntMsrTick>) {
-        let mut txt_this_time: Vec<String> = Vec::new();
+    /// 返す各行には、ファイルの行番号が付く
+    pub fn get_loaded_text(&self, mt: CrntMsrTick) -> (Vec<(usize, String)>, Option<CrntMsrTick>) {
+        let mut txt_this_time: Vec<(usize, String)> = Vec::new();
         let mut idx: usize = 0;
         // 先頭を探す
         if mt.msr != 0 {
             for crnt in self.loaded_text.iter().enumerate() {
-                let ctxt = crnt.1;
+                let ctxt = &crnt.1 .1;
                 if ctxt.len() > 5 && ctxt[0..5] == *"!msr(" {
                     if let Some(msr) = extract_number_from_parentheses(ctxt) {
                         if msr == mt.msr.try_into().unwrap_or(0) {
@@ -175,7 +176,7 @@ impl History {
         }
         // ここから記録
         for n in idx..self.loaded_text.len() {
-            let ctxt = &self.loaded_text[n];
+            let ctxt = &self.loaded_text[n].1;
             if ctxt.len() > 5 && ctxt[0..5] == *"!msr(" {
                 let msr;
                 if let Some(m) = extract_number_from_parentheses(ctxt) {
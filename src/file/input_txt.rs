@@ -3,17 +3,25 @@
 //  Released under the MIT license
 //  https://opensource.org/licenses/mit-license.php
 //
+use chrono::{Local, Timelike};
 use cli_clipboard::{ClipboardContext, ClipboardProvider};
 use nannou::prelude::*;
+use std::collections::HashMap;
 use std::sync::mpsc;
+use std::thread::JoinHandle;
 
 use super::cnv_file;
 use super::history::History;
+use super::lpn_bank::LpnBank;
+use super::schedule::Scheduler;
+use super::scroll_text::ScrollText;
+use super::settings::{Settings, VariPad};
 use crate::cmd::cmdparse::*;
 use crate::cmd::txt_common::*;
 use crate::elapse::tickgen::CrntMsrTick;
 use crate::graphic::guiev::GuiEv;
 use crate::lpnlib::*;
+use crate::server::collab::{self, CollabClient, CollabServer};
 
 //*******************************************************************
 //      Input Text
@@ -25,18 +33,38 @@ pub struct InputText {
     history_cnt: usize,
     file_name_stock: String,
     next_msr_tick: Option<CrntMsrTick>,
-    scroll_lines: Vec<(TextAttribute, String, String)>,
+    scroll_lines: ScrollText,
     history: History,
     cmd: LoopianCmd,
     shift_pressed: bool,
     ctrl_pressed: bool,
     just_after_hokan: bool,
+    elps_thread: Option<JoinHandle<()>>,
+    alias: HashMap<String, String>,    // 別名 -> コマンド
+    keybind: HashMap<String, String>,  // Key 名 -> コマンド
+    midi_map: HashMap<String, String>, // 受信した MIDI message 名 -> コマンド
+    scheduler: Scheduler,
+    collab: Option<CollabServer>,        // 他の奏者からコマンドを受ける
+    collab_client: Option<CollabClient>, // 他の loopian にコマンドを送る
 }
 impl InputText {
     const CURSOR_MAX_VISIBLE_LOCATE: usize = 65;
     const COMMAND_INPUT_REST_TICK: i32 = 240;
+    const THREAD_JOIN_MSEC: u64 = 1000;
 
-    pub fn new(msg_hndr: mpsc::Sender<ElpsMsg>) -> Self {
+    pub fn new(msg_hndr: mpsc::Sender<ElpsMsg>, elps_thread: JoinHandle<()>) -> Self {
+        let sts = Settings::load_settings();
+        let mut midi_map = sts.midi_map;
+        let cmd = LoopianCmd::new(msg_hndr);
+        if sts.vari_pad.is_available() {
+            for (name, vari_cmd) in Self::gen_vari_pad(&sts.vari_pad) {
+                midi_map.entry(name).or_insert(vari_cmd); // [midi_map] の記述を優先
+            }
+            cmd.sndr
+                .send_msg_to_elapse(ElpsMsg::Set([MSG_SET_NOTE_MAP_CH, sts.vari_pad.ch as i16]));
+        } else if sts.vari_pad.ch != 0 {
+            println!("[vari_pad] ch.{} can't be used!", sts.vari_pad.ch);
+        }
         Self {
             input_text: "".to_string(),
             input_locate: 0,
@@ -44,13 +72,44 @@ impl InputText {
             history_cnt: 0,
             file_name_stock: String::new(),
             next_msr_tick: None,
-            scroll_lines: vec![],
+            scroll_lines: ScrollText::new(ScrollText::MAX_LINES),
             history: History::new(),
-            cmd: LoopianCmd::new(msg_hndr),
+            cmd,
             shift_pressed: false,
             ctrl_pressed: false,
             just_after_hokan: false,
+            elps_thread: Some(elps_thread),
+            alias: sts.alias,
+            keybind: sts.keybind,
+            midi_map,
+            scheduler: Scheduler::new(&sts.schedule),
+            collab: (sts.collab.port != 0)
+                .then(|| CollabServer::new(&sts.collab.bind, sts.collab.port, &sts.collab.token))
+                .flatten(),
+            collab_client: (!sts.collab.connect.is_empty())
+                .then(|| {
+                    CollabClient::connect(&sts.collab.connect, &sts.collab.name, &sts.collab.token)
+                })
+                .flatten(),
+        }
+    }
+    /// [vari_pad] の設定から、noteN で Variation を選ぶコマンドを作る
+    pub fn gen_vari_pad(pad: &VariPad) -> Vec<(String, String)> {
+        let parts: Vec<&str> = if pad.part == "grid" {
+            vec!["L1", "L2", "R1", "R2"]
+        } else {
+            vec![pad.part.as_str()]
+        };
+        let mut map = Vec::new();
+        for (row, part) in parts.iter().enumerate() {
+            for vari in 1..=8 {
+                let note = pad.note as usize + row * 8 + vari - 1;
+                if note <= 127 {
+                    map.push((format!("note{}", note), format!("{}.vari({})", part, vari)));
+                }
+            }
         }
+        map
     }
     pub fn get_history_locate(&self) -> usize {
         self.history_cnt
@@ -61,18 +120,164 @@ impl InputText {
     pub fn get_input_part(&self) -> usize {
         self.cmd.get_input_part()
     }
+    /// 譜面表示用に、パートの vari の Phrase と whole_tick, 一小節の tick を返す
+    pub fn get_phrase(&self, part: usize, vari: PhraseAs) -> (&Vec<PhrEvt>, i32, i32) {
+        let pdt = self.cmd.dtstk.get_pdstk(part, vari);
+        (
+            pdt.get_phr(),
+            pdt.get_whole_tick(),
+            self.cmd.dtstk.get_tick_for_onemsr(),
+        )
+    }
     pub fn get_indicator_key_stock(&self) -> String {
         self.cmd.get_indicator_key_stock()
     }
     pub fn put_and_get_responce(&mut self, input_text: &str) -> Option<CmndRtn> {
-        self.cmd.put_and_get_responce(input_text)
+        let itxt = self.expand_alias(input_text.to_string());
+        self.cmd.put_and_get_responce(&itxt)
+    }
+    /// 別名として登録されていれば、コマンドに置き換える
+    fn expand_alias(&self, itxt: String) -> String {
+        expand_alias(&self.alias, &itxt)
+    }
+    /// !alias p = play : 別名を登録する(右辺が空なら削除)
+    fn set_alias(&mut self, itxt: &str) -> String {
+        let prm = itxt.trim_start_matches("!alias");
+        match prm.split_once('=') {
+            Some((name, cmd)) => {
+                let name = name.trim_matches(['.', ' ']).to_string();
+                let cmd = cmd.trim_matches(['.', ' ']).to_string();
+                if name.is_empty() {
+                    "what?".to_string()
+                } else if cmd.is_empty() {
+                    self.alias.remove(&name);
+                    format!("Alias {} has been deleted!", name)
+                } else {
+                    let answer = format!("Alias {} = {}", name, cmd);
+                    self.alias.insert(name, cmd);
+                    answer
+                }
+            }
+            None => "what?".to_string(),
+        }
     }
     pub fn get_input_text(&self) -> String {
         self.input_text[self.visible_locate..].to_string()
     }
-    pub fn get_scroll_lines(&self) -> &Vec<(TextAttribute, String, String)> {
+    pub fn get_scroll_lines(&self) -> &ScrollText {
         &self.scroll_lines
     }
+    /// App の終了
+    /// force でなければ log を残し、Elapse thread が MIDI を後始末して終わるのを待つ
+    pub fn quit_app(&mut self, force: bool) -> ! {
+        if !force {
+            self.cmd.send_quit();
+            self.gen_log(0, "".to_string());
+            if let Some(hndl) = self.elps_thread.take() {
+                if !join_with_timeout(hndl, Self::THREAD_JOIN_MSEC) {
+                    println!("Elapse thread didn't finish!");
+                }
+            }
+        }
+        println!("That's all. Thank you!");
+        std::process::exit(0);
+    }
+    /// 予約された転調が実行された時、key 表示を変える
+    pub fn set_indicator_key(&mut self, key: u8) {
+        let kname = note_name(key as i16, key as i16, SPELL_AUTO);
+        self.cmd.indicator_key_stock(kname.to_string());
+    }
+    /// set list が scene を切り替えた時、入力側の Data も合わせる
+    pub fn set_list_scene(&mut self, idx: usize) {
+        self.cmd.set_list_scene(idx);
+    }
+    /// Elapse 側のエラーを、返答として表示する
+    pub fn put_error(&mut self, err: String) {
+        self.scroll_lines
+            .push(TextAttribute::Answer, "".to_string(), err);
+    }
+    /// 受信した Program Change(pc0-127) / Song Select(song0-127) に、
+    /// settings.toml の [midi_map] でコマンドが割り当てられていれば実行する
+    pub fn midi_action(&mut self, name: &str, graphmsg: &mut Vec<GraphicMsg>) {
+        if let Some(cmd) = self.midi_map.get(name).cloned() {
+            let msg = self.one_command(get_crnt_date_txt(), cmd, true);
+            self.set_graphic_msg(msg, graphmsg);
+        }
+    }
+    /// [[schedule]] の時刻になったコマンドを実行する  called from main::update()
+    pub fn scheduled_command(&mut self, graphmsg: &mut Vec<GraphicMsg>) {
+        let now = Local::now();
+        for cmd in self.scheduler.check(now.hour() * 60 + now.minute()) {
+            if cmd.starts_with('!') {
+                self.non_logged_command(cmd, graphmsg);
+            } else {
+                let msg = self.one_command(get_crnt_date_txt(), cmd, true);
+                self.set_graphic_msg(msg, graphmsg);
+            }
+        }
+    }
+    /// 他の奏者から届いたコマンドを、その人のパートを入力パートにして実行し、返答を送る
+    /// 他の loopian に繋いでいる時は、届いた返答を表示する  called from main::update()
+    pub fn collab_command(&mut self, graphmsg: &mut Vec<GraphicMsg>) {
+        if let Some(cl) = self.collab_client.as_mut() {
+            for line in cl.poll() {
+                self.scroll_lines
+                    .push(TextAttribute::Answer, "".to_string(), line);
+            }
+        }
+        let reqs = match self.collab.as_mut() {
+            Some(srv) => srv.poll(),
+            None => return,
+        };
+        for req in reqs.into_iter() {
+            let org_part = self.cmd.get_input_part();
+            self.cmd.set_input_part(req.part);
+            let answer = self.cmd.put_and_get_responce(&req.cmd);
+            self.cmd.set_input_part(org_part);
+            let answer = match answer {
+                Some(a) => a,
+                None => continue,
+            };
+            self.history
+                .set_scroll_text(get_crnt_date_txt(), req.cmd.clone());
+            self.scroll_lines.push(
+                TextAttribute::Common,
+                get_crnt_date_txt(),
+                format!("{}> {}", req.name, req.cmd),
+            );
+            self.scroll_lines
+                .push(TextAttribute::Answer, "".to_string(), answer.0.clone());
+            if let Some(srv) = self.collab.as_mut() {
+                srv.reply(req.client, &answer.0);
+            }
+            self.set_graphic_msg(answer.1, graphmsg);
+        }
+    }
+    /// punch で弾いた音を差し替えた Phrase を、入力する(履歴から編集できる)
+    pub fn punch(
+        &mut self,
+        part: usize,
+        evts: &[PhrEvt],
+        whole_tick: i32,
+        graphmsg: &mut Vec<GraphicMsg>,
+    ) {
+        let cmd = self.cmd.gen_punch_cmd(part, evts, whole_tick);
+        let msg = self.one_command(get_crnt_date_txt(), cmd, true);
+        self.set_graphic_msg(msg, graphmsg);
+    }
+    /// freeze で Elapse から届いた音を、Variation として入力する(履歴から編集できる)
+    pub fn freeze(
+        &mut self,
+        part: usize,
+        vari: i16,
+        evts: &[PhrEvt],
+        whole_tick: i32,
+        graphmsg: &mut Vec<GraphicMsg>,
+    ) {
+        let cmd = self.cmd.gen_freeze_cmd(part, vari, evts, whole_tick);
+        let msg = self.one_command(get_crnt_date_txt(), cmd, true);
+        self.set_graphic_msg(msg, graphmsg);
+    }
     #[cfg(feature = "raspi")]
     pub fn send_reconnect(&self) {
         self.cmd.send_reconnect();
@@ -175,6 +380,12 @@ impl InputText {
                     self.visible_locate = 0;
                 }
             }
+            &Key::PageUp => {
+                self.scroll_lines.scroll_up();
+            }
+            &Key::PageDown => {
+                self.scroll_lines.scroll_down();
+            }
             &Key::RControl => {}
             &Key::LAlt => {}
             &Key::RAlt => {}
@@ -185,7 +396,13 @@ impl InputText {
                     self.set_graphic_msg(GraphicMsg::TextVisibleCtrl, graphmsg);
                 }
             }
-            _ => {}
+            _ => {
+                // keybind に登録された Key なら、そのコマンドを実行する
+                if let Some(cmd) = self.keybind.get(&format!("{:?}", key)).cloned() {
+                    let msg = self.one_command(get_crnt_date_txt(), cmd, true);
+                    self.set_graphic_msg(msg, graphmsg);
+                }
+            }
         }
         self.just_after_hokan = false;
     }
@@ -245,7 +462,20 @@ impl InputText {
         self.input_locate = 0;
         self.visible_locate = 0;
         let chr = itxt.chars().nth(0).unwrap_or(' ');
-        if chr != '!' {
+        if chr != '!' && self.collab_client.is_some() {
+            // 他の loopian に送る
+            let sent = self.collab_client.as_mut().is_some_and(|cl| cl.send(&itxt));
+            self.scroll_lines
+                .push(TextAttribute::Common, get_crnt_date_txt(), itxt);
+            if !sent {
+                self.collab_client = None;
+                self.scroll_lines.push(
+                    TextAttribute::Answer,
+                    "".to_string(),
+                    "Disconnected!".to_string(),
+                );
+            }
+        } else if chr != '!' {
             // Normal Input
             let msg = self.one_command(get_crnt_date_txt(), itxt, true);
             self.set_graphic_msg(msg, graphmsg);
@@ -255,12 +485,12 @@ impl InputText {
     }
     fn non_logged_command(&mut self, itxt: String, graphmsg: &mut Vec<GraphicMsg>) {
         let len = itxt.chars().count();
-        if (len == 2 && &itxt[0..2] == "!q") || (len >= 5 && &itxt[0..5] == "!quit") {
+        if len == 6 && &itxt[0..6] == "!quit!" {
+            // 後始末をせずに終了
+            self.quit_app(true);
+        } else if (len == 2 && &itxt[0..2] == "!q") || (len >= 5 && &itxt[0..5] == "!quit") {
             // The end of the App
-            self.cmd.send_quit();
-            self.gen_log(0, "".to_string());
-            println!("That's all. Thank you!");
-            std::process::exit(0);
+            self.quit_app(false);
         } else if (len >= 2 && &itxt[0..2] == "!l") || (len >= 5 && &itxt[0..5] == "!load") {
             // Load File
             self.load_file(&itxt[0..], graphmsg);
@@ -271,11 +501,11 @@ impl InputText {
             // clear loaded file data
             self.clear_loaded_data();
             self.cmd.send_clear();
-            self.scroll_lines.push((
+            self.scroll_lines.push(
                 TextAttribute::Answer,
                 "".to_string(),
                 "All data cleared!".to_string(),
-            ));
+            );
         } else if (len >= 2 && &itxt[0..2] == "!s") || (len >= 5 && &itxt[0..5] == "!save") {
             let itxts = split_by('.', itxt);
             let fname = if itxts.len() >= 2 {
@@ -290,11 +520,11 @@ impl InputText {
                 num = 0;
             }
             self.gen_log(num, fname);
-            self.scroll_lines.push((
+            self.scroll_lines.push(
                 TextAttribute::Answer,
                 "".to_string(),
                 "log saved!".to_string(),
-            ));
+            );
         } else if (len >= 2 && &itxt[0..2] == "!r")
             || (len >= 3 && &itxt[0..3] == "!rd")
             || (len >= 5 && &itxt[0..5] == "!read")
@@ -312,19 +542,118 @@ impl InputText {
             ) {
                 self.input_text = cmd;
             }
+        } else if len >= 5 && &itxt[0..5] == "!diff" {
+            // 二つのファイルの Phrase の違いを表示
+            let itxts = split_by('.', itxt);
+            let answer = if itxts.len() >= 3 {
+                let path = self.cmd.get_path();
+                match (
+                    LpnBank::load(&itxts[1], path.as_deref()),
+                    LpnBank::load(&itxts[2], path.as_deref()),
+                ) {
+                    (Some(a), Some(b)) => {
+                        let dif = a.diff(&b);
+                        for d in dif.iter() {
+                            self.scroll_lines.push(
+                                TextAttribute::Answer,
+                                "".to_string(),
+                                d.clone(),
+                            );
+                        }
+                        if dif.is_empty() {
+                            "No difference!".to_string()
+                        } else {
+                            format!("{} differences.", dif.len())
+                        }
+                    }
+                    _ => "Can't open a file".to_string(),
+                }
+            } else {
+                "what?".to_string()
+            };
+            self.scroll_lines
+                .push(TextAttribute::Answer, "".to_string(), answer);
+        } else if len >= 6 && &itxt[0..6] == "!merge" {
+            // !merge.src.dst.L1.R1 : src の L1,R1 を dst に追記
+            let itxts = split_by('.', itxt);
+            let answer = if itxts.len() >= 4 {
+                match LpnBank::merge(
+                    &itxts[1],
+                    &itxts[2],
+                    &itxts[3..],
+                    self.cmd.get_path().as_deref(),
+                ) {
+                    Ok(n) => format!("Merged {} lines!", n),
+                    Err(e) => e,
+                }
+            } else {
+                "what?".to_string()
+            };
+            self.scroll_lines
+                .push(TextAttribute::Answer, "".to_string(), answer);
+        } else if len >= 5 && &itxt[0..5] == "!find" {
+            // !find.word : 表示している所から遡って word を含む行を探す
+            let word = itxt[5..].trim_start_matches('.');
+            if word.is_empty() || !self.scroll_lines.search(word) {
+                self.scroll_lines.reset_view();
+                self.scroll_lines.push(
+                    TextAttribute::Answer,
+                    "".to_string(),
+                    "Not found.".to_string(),
+                );
+            }
+        } else if len >= 5 && &itxt[0..5] == "!copy" {
+            // !copy(5) : 見つけた行(なければ最新の行)までの 5行を clipboard に入れる
+            let num = extract_number_from_parentheses(&itxt).unwrap_or(1);
+            let text = self.scroll_lines.copy_text(num);
+            let answer = match ClipboardContext::new().and_then(|mut ctx| ctx.set_contents(text)) {
+                Ok(_) => "Copied!".to_string(),
+                Err(_) => "Can't copy.".to_string(),
+            };
+            self.scroll_lines
+                .push(TextAttribute::Answer, "".to_string(), answer);
+        } else if len >= 4 && &itxt[0..4] == "!who" {
+            // 他の奏者と分担しているパートの持ち主
+            let answer = match self.collab.as_ref() {
+                Some(srv) => srv.owner().list(),
+                None => "Collab server is off.".to_string(),
+            };
+            self.scroll_lines
+                .push(TextAttribute::Answer, "".to_string(), answer);
+        } else if len >= 5 && &itxt[0..5] == "!free" {
+            // !free.R1 : 他の奏者の持っているパートを手放させる
+            let part = LoopianCmd::detect_part(itxt[5..].trim_start_matches('.'));
+            let answer = match (self.collab.as_mut(), part) {
+                (Some(srv), Some(pt)) => srv
+                    .owner_mut()
+                    .release(pt, collab::LOCAL_NAME)
+                    .unwrap_or_else(|e| e),
+                (None, _) => "Collab server is off.".to_string(),
+                _ => "what?".to_string(),
+            };
+            self.scroll_lines
+                .push(TextAttribute::Answer, "".to_string(), answer);
+        } else if len >= 6 && &itxt[0..6] == "!alias" {
+            let answer = self.set_alias(&itxt);
+            self.scroll_lines
+                .push(TextAttribute::Answer, "".to_string(), answer);
         } else if len >= 7 && &itxt[0..7] == "!cnv2tl" {
             println!("Convert to Timeline File");
             let itxts = split_by('.', itxt);
             if itxts.len() >= 2 {
                 cnv_file::convert_to_timeline(itxts[1].clone(), self.cmd.get_path().as_deref());
-                self.scroll_lines.push((
+                self.scroll_lines.push(
                     TextAttribute::Answer,
                     "".to_string(),
                     "Converted to Timeline File!".to_string(),
-                ));
+                );
             }
         }
     }
+    /// 起動時に指定されたファイルを読み込む(--load=fname)
+    pub fn load_at_startup(&mut self, fname: &str, graphmsg: &mut Vec<GraphicMsg>) {
+        self.load_file(&format!("!l.{}", fname), graphmsg);
+    }
     fn load_file(&mut self, itxt: &str, graphmsg: &mut Vec<GraphicMsg>) {
         let blk_exists = |fnm: String| -> (Option<String>, Option<usize>) {
             let mut ltr = None;
@@ -381,11 +710,11 @@ impl InputText {
             self.next_msr_tick = self.get_loaded_text(mt, graphmsg);
         } else {
             // 適切なファイルや中身がなかった場合
-            self.scroll_lines.push((
+            self.scroll_lines.push(
                 TextAttribute::Answer,
                 "".to_string(),
                 "No history".to_string(),
-            ));
+            );
         }
     }
     /// Auto Load  called from main::update()
@@ -407,16 +736,50 @@ impl InputText {
         graphmsg: &mut Vec<GraphicMsg>,
     ) -> Option<CrntMsrTick> {
         let loaded = self.history.get_loaded_text(mt);
-        for onecmd in loaded.0.iter() {
-            let msg = self.one_command(get_crnt_date_txt(), onecmd.clone(), false);
-            self.set_graphic_msg(msg, graphmsg);
+        match self.put_batch(&loaded.0) {
+            Ok(answers) => {
+                for (itxt, answer) in answers {
+                    if let Some(ans) = answer {
+                        self.history_cnt = self
+                            .history
+                            .set_scroll_text(get_crnt_date_txt(), itxt.clone()); // input history
+                        self.scroll_lines
+                            .push(TextAttribute::Common, get_crnt_date_txt(), itxt);
+                        self.set_graphic_msg(ans.1, graphmsg);
+                    }
+                }
+                self.scroll_lines.push(
+                    TextAttribute::Answer,
+                    "".to_string(),
+                    "Loaded from designated file".to_string(),
+                );
+                loaded.1
+            }
+            Err(err) => {
+                self.scroll_lines
+                    .push(TextAttribute::Answer, "".to_string(), err);
+                self.clear_loaded_data();
+                None
+            }
+        }
+    }
+    /// ファイルから読んだ複数行を、まとめて実行する(lines: (行番号, コマンド))
+    /// 途中で失敗したら、実行前の状態に戻し、失敗した行を知らせる文字列を返す
+    pub fn put_batch(
+        &mut self,
+        lines: &[(usize, String)],
+    ) -> Result<Vec<(String, Option<CmndRtn>)>, String> {
+        let cmds: Vec<String> = lines
+            .iter()
+            .map(|(_, cmd)| self.expand_alias(cmd.clone()))
+            .collect();
+        match self.cmd.put_batch(&cmds) {
+            Ok(answers) => Ok(cmds.into_iter().zip(answers).collect()),
+            Err((idx, reply)) => Err(format!(
+                "Load failed at line {}: {} ({}) / Rolled back.",
+                lines[idx].0, lines[idx].1, reply
+            )),
         }
-        self.scroll_lines.push((
-            TextAttribute::Answer,
-            "".to_string(),
-            "Loaded from designated file".to_string(),
-        ));
-        loaded.1
     }
     fn clear_loaded_data(&mut self) {
         self.file_name_stock = String::new();
@@ -424,22 +787,62 @@ impl InputText {
     }
     fn one_command(&mut self, time: String, itxt: String, verbose: bool) -> GraphicMsg {
         // 通常のコマンド入力
+        let itxt = self.expand_alias(itxt);
+        if let Some(owner) = self.locked_by_other(&itxt) {
+            self.scroll_lines
+                .push(TextAttribute::Common, time, itxt.clone());
+            self.scroll_lines
+                .push(TextAttribute::Answer, "".to_string(), owner);
+            return GraphicMsg::NoMsg;
+        }
         if let Some(answer) = self.cmd.put_and_get_responce(&itxt) {
             // normal command
             self.history_cnt = self
                 .history
                 .set_scroll_text(get_crnt_date_txt(), itxt.clone()); // input history
+            self.scroll_lines.reset_view();
             self.scroll_lines
-                .push((TextAttribute::Common, time.clone(), itxt.clone())); // for display text
+                .push(TextAttribute::Common, time.clone(), itxt.clone()); // for display text
             if verbose {
                 self.scroll_lines
-                    .push((TextAttribute::Answer, "".to_string(), answer.0));
+                    .push(TextAttribute::Answer, "".to_string(), answer.0);
             }
             return answer.1;
         }
         GraphicMsg::NoMsg
     }
+    /// 他の奏者が持っているパートを変えるコマンドなら、その返答を返す
+    fn locked_by_other(&self, itxt: &str) -> Option<String> {
+        let owner = self.collab.as_ref()?.owner();
+        // ; で区切った batch は、一つずつ調べる
+        itxt.split(';').find_map(|cmd| {
+            collab::target_parts(cmd.trim(), self.cmd.get_input_part())
+                .into_iter()
+                .find(|&part| !owner.can_edit(part, collab::LOCAL_NAME))
+                .and_then(|part| owner.get(part))
+                .map(|o| format!("This part is owned by {}.", o))
+        })
+    }
     fn set_graphic_msg(&mut self, msg: GraphicMsg, graphmsg: &mut Vec<GraphicMsg>) {
         graphmsg.push(msg);
     }
 }
+/// ; で区切った各コマンドの先頭の語が別名なら、置き換える
+/// `p; stop` や `b(120)` のように、後ろに続くコマンドや引数はそのまま残す
+pub fn expand_alias(alias: &HashMap<String, String>, itxt: &str) -> String {
+    itxt.split(';')
+        .map(|one| {
+            let body = one.trim_start();
+            let head = &one[..one.len() - body.len()];
+            let end = body
+                .find(|c: char| c.is_whitespace() || c == '(')
+                .unwrap_or(body.len());
+            let (word, rest) = body.split_at(end);
+            match alias.get(word) {
+                Some(cmd) => format!("{}{}{}", head, cmd, rest),
+                None => one.to_string(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(";")
+}
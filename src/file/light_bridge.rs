@@ -0,0 +1,184 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+
+use super::settings::LightSetting;
+
+const PART_NAME: [&str; 5] = ["L1", "L2", "R1", "R2", "Flow"];
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum LightProtocol {
+    Off,
+    Text, // "MSR 3\n" のような一行の文字列
+    Osc,  // OSC message (QLC+ などで受ける)
+}
+enum LightPort {
+    Udp(UdpSocket, SocketAddr),
+    Serial(mpsc::Sender<Vec<u8>>), // 書き込みは別 thread で行う
+}
+//*******************************************************************
+//          Light Bridge Struct
+//*******************************************************************
+//  照明の制御機器が和音や曲の区切りに合わせて光れるように、小節/拍/和音の変化を送る
+//  target に ':' があれば UDP の宛先(127.0.0.1:7700 など)、なければ serial の device
+//  Text : MSR <小節> / BEAT <拍> / CHORD <パート> <和音名> <値>
+//  OSC  : <prefix>/msr ,i <小節> / <prefix>/beat ,i <拍> /
+//         <prefix>/chord/<パート> ,sf <和音名> <値/255>
+//  値は、key からの度数(0-11)を degree_value で変換したもの(0-255)
+pub struct LightBridge {
+    protocol: LightProtocol,
+    port: Option<LightPort>,
+    prefix: String,
+    beat: bool,
+    degree_value: [u8; 12],
+}
+impl LightBridge {
+    pub fn new(setting: &LightSetting) -> Self {
+        let protocol = match setting.protocol.as_str() {
+            "text" => LightProtocol::Text,
+            "osc" => LightProtocol::Osc,
+            _ => LightProtocol::Off,
+        };
+        let mut degree_value = [0; 12];
+        for (i, v) in degree_value.iter_mut().enumerate() {
+            *v = setting
+                .degree_value
+                .get(i)
+                .copied()
+                .unwrap_or((i * 255 / 11) as u8);
+        }
+        let mut this = Self {
+            protocol,
+            port: None,
+            prefix: setting.prefix.clone(),
+            beat: setting.beat,
+            degree_value,
+        };
+        if protocol != LightProtocol::Off {
+            this.port = Self::open(&setting.target);
+            if this.port.is_none() {
+                println!("Light bridge can't open: {}", setting.target);
+            }
+        }
+        this
+    }
+    /// UDP の宛先は、ここで一度だけ名前解決する
+    /// serial は書き込みで待たされることがあるので、Elapse thread とは別の thread で書く
+    fn open(target: &str) -> Option<LightPort> {
+        if target.contains(':') {
+            let addr = target.to_socket_addrs().ok()?.next()?;
+            let sock = UdpSocket::bind("0.0.0.0:0").ok()?;
+            let _ = sock.set_nonblocking(true);
+            Some(LightPort::Udp(sock, addr))
+        } else {
+            let mut file = OpenOptions::new().write(true).open(target).ok()?;
+            let (tx, rx) = mpsc::channel::<Vec<u8>>();
+            thread::spawn(move || {
+                for pkt in rx.iter() {
+                    let _ = file.write_all(&pkt);
+                }
+            });
+            Some(LightPort::Serial(tx))
+        }
+    }
+    /// 小節頭で呼ぶ(msr: 0origin)
+    pub fn msr(&mut self, msr: i32) {
+        let pkt = self.encode_msr(msr);
+        self.send(pkt);
+    }
+    /// 拍頭で呼ぶ(beat: 0origin)
+    pub fn beat(&mut self, beat: i32) {
+        if self.beat {
+            let pkt = self.encode_beat(beat);
+            self.send(pkt);
+        }
+    }
+    /// 和音が変わった時に呼ぶ(degree: key からの度数 0-11)
+    pub fn chord(&mut self, part: usize, name: &str, degree: i16) {
+        let pkt = self.encode_chord(part, name, degree);
+        self.send(pkt);
+    }
+    pub fn encode_msr(&self, msr: i32) -> Vec<u8> {
+        match self.protocol {
+            LightProtocol::Text => format!("MSR {}\n", msr + 1).into_bytes(),
+            LightProtocol::Osc => osc_msg(&format!("{}/msr", self.prefix), &[OscArg::Int(msr + 1)]),
+            LightProtocol::Off => Vec::new(),
+        }
+    }
+    pub fn encode_beat(&self, beat: i32) -> Vec<u8> {
+        match self.protocol {
+            LightProtocol::Text => format!("BEAT {}\n", beat + 1).into_bytes(),
+            LightProtocol::Osc => {
+                osc_msg(&format!("{}/beat", self.prefix), &[OscArg::Int(beat + 1)])
+            }
+            LightProtocol::Off => Vec::new(),
+        }
+    }
+    pub fn encode_chord(&self, part: usize, name: &str, degree: i16) -> Vec<u8> {
+        let pname = PART_NAME.get(part).copied().unwrap_or("Flow");
+        let value = self.degree_value[degree.rem_euclid(12) as usize];
+        match self.protocol {
+            LightProtocol::Text => format!("CHORD {} {} {}\n", pname, name, value).into_bytes(),
+            LightProtocol::Osc => osc_msg(
+                &format!("{}/chord/{}", self.prefix, pname),
+                &[
+                    OscArg::Str(name.to_string()),
+                    OscArg::Float(value as f32 / 255.0),
+                ],
+            ),
+            LightProtocol::Off => Vec::new(),
+        }
+    }
+    fn send(&mut self, pkt: Vec<u8>) {
+        if pkt.is_empty() {
+            return;
+        }
+        match self.port.as_mut() {
+            Some(LightPort::Udp(sock, addr)) => {
+                let _ = sock.send_to(&pkt, *addr);
+            }
+            Some(LightPort::Serial(tx)) => {
+                let _ = tx.send(pkt);
+            }
+            None => (),
+        }
+    }
+}
+enum OscArg {
+    Int(i32),
+    Float(f32),
+    Str(String),
+}
+/// OSC の文字列: 終端の 0 を含めて 4byte 単位に揃える
+fn osc_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(s.as_bytes());
+    let pad = 4 - s.len() % 4;
+    buf.extend(std::iter::repeat_n(0, pad));
+}
+fn osc_msg(addr: &str, args: &[OscArg]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    osc_str(&mut buf, addr);
+    let mut tags = ",".to_string();
+    for a in args {
+        tags.push(match a {
+            OscArg::Int(_) => 'i',
+            OscArg::Float(_) => 'f',
+            OscArg::Str(_) => 's',
+        });
+    }
+    osc_str(&mut buf, &tags);
+    for a in args {
+        match a {
+            OscArg::Int(n) => buf.extend_from_slice(&n.to_be_bytes()),
+            OscArg::Float(f) => buf.extend_from_slice(&f.to_be_bytes()),
+            OscArg::Str(s) => osc_str(&mut buf, s),
+        }
+    }
+    buf
+}
@@ -0,0 +1,135 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+
+use super::lpn_file::*;
+
+//*******************************************************************
+//          Lpn Bank Struct
+//*******************************************************************
+//  .lpn ファイルを読んで、パートごとの最終的な Phrase/Composition/Variation を集める
+//  二つのファイルの差分を調べたり、選んだパートを別のファイルに取り込んだりする
+//  key: (パート名, 種類)  種類は "[]", "{}", "@1", "@msr(3)" など
+pub struct LpnBank {
+    bank: BTreeMap<(String, String), String>,
+}
+impl LpnFile for LpnBank {}
+impl LpnBank {
+    pub const PART: [(&'static str, &'static str); 4] = [
+        ("L1", "left1"),
+        ("L2", "left2"),
+        ("R1", "right1"),
+        ("R2", "right2"),
+    ];
+
+    pub fn new() -> Self {
+        Self {
+            bank: BTreeMap::new(),
+        }
+    }
+    /// ファイルを読み込む。読めなければ None
+    pub fn load(fname: &str, path: Option<&str>) -> Option<Self> {
+        let mut lb = Self::new();
+        let fp = lb.gen_lpn_file_name(fname.to_string(), path);
+        let content = fs::read_to_string(fp).ok()?;
+        lb.parse(&content);
+        Some(lb)
+    }
+    /// ファイルの中身を一行ずつ調べる。後から出てきたものが有効
+    pub fn parse(&mut self, content: &str) {
+        let mut crnt_part = "R1";
+        for line in content.lines() {
+            let line = line.trim();
+            if line.starts_with("//") || line.starts_with('!') {
+                continue;
+            }
+            if let Some((pt, _)) = Self::PART.iter().find(|(_, long)| line == *long) {
+                crnt_part = pt;
+                continue;
+            }
+            let (part, body) = Self::PART
+                .iter()
+                .find_map(|(pt, _)| {
+                    line.strip_prefix(pt)
+                        .and_then(|b| b.strip_prefix('.'))
+                        .map(|b| (*pt, b))
+                })
+                .unwrap_or((crnt_part, line));
+            if let Some(kind) = Self::kind(body) {
+                self.bank.insert((part.to_string(), kind), body.to_string());
+            }
+        }
+    }
+    fn kind(body: &str) -> Option<String> {
+        if body.starts_with('[') {
+            Some("[]".to_string())
+        } else if body.starts_with('{') {
+            Some("{}".to_string())
+        } else if body.starts_with('@') {
+            body.split_once('=').map(|(k, _)| k.trim().to_string())
+        } else {
+            None
+        }
+    }
+    /// other との違いを一行ずつの文字列で返す
+    pub fn diff(&self, other: &LpnBank) -> Vec<String> {
+        let mut rtn = Vec::new();
+        for (key, body) in self.bank.iter() {
+            match other.bank.get(key) {
+                Some(ob) if ob == body => (),
+                Some(_) => rtn.push(format!("{}.{} changed", key.0, key.1)),
+                None => rtn.push(format!("{}.{} only in 1st", key.0, key.1)),
+            }
+        }
+        for key in other.bank.keys() {
+            if !self.bank.contains_key(key) {
+                rtn.push(format!("{}.{} only in 2nd", key.0, key.1));
+            }
+        }
+        rtn
+    }
+    /// 指定パートのデータを、パート名付きの行にして返す
+    pub fn part_lines(&self, part: &str) -> Vec<String> {
+        self.bank
+            .iter()
+            .filter(|(key, _)| key.0 == part)
+            .map(|(key, body)| format!("{}.{}", key.0, body))
+            .collect()
+    }
+    /// src の指定パートを dst ファイルの最後に追記する。追記した行数を返す
+    pub fn merge(
+        src: &str,
+        dst: &str,
+        parts: &[String],
+        path: Option<&str>,
+    ) -> Result<usize, String> {
+        let sbank = Self::load(src, path).ok_or("Can't open a file")?;
+        let mut lines: Vec<String> = Vec::new();
+        for pt in parts.iter() {
+            if !Self::PART.iter().any(|(p, _)| p == pt) {
+                return Err("what?".to_string());
+            }
+            lines.extend(sbank.part_lines(pt));
+        }
+        if lines.is_empty() {
+            return Err("No data".to_string());
+        }
+        let fp = sbank.gen_lpn_file_name(dst.to_string(), path);
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&fp)
+            .map_err(|_| "Can't open a file".to_string())?;
+        let mut txt = format!("// merged {} from {}\n", parts.join(","), src);
+        for ln in lines.iter() {
+            txt += ln;
+            txt += "\n";
+        }
+        file.write_all(txt.as_bytes())
+            .map_err(|_| "Can't write a file".to_string())?;
+        Ok(lines.len())
+    }
+}
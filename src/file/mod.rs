@@ -1,5 +1,15 @@
+pub mod capture;
 pub mod cnv_file;
 pub mod history;
 pub mod input_txt;
+pub mod light_bridge;
+pub mod lpn_bank;
 pub mod lpn_file;
+pub mod schedule;
+pub mod scroll_text;
+pub mod session;
+pub mod set_list_file;
 pub mod settings;
+pub mod setup_sheet;
+pub mod smf;
+pub mod stats;
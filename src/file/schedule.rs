@@ -0,0 +1,60 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use super::settings::ScheduleSetting;
+
+//*******************************************************************
+//          Scheduler Struct
+//*******************************************************************
+//  settings.toml の [[schedule]] に書いた時刻になったら、コマンドを実行する
+//  (展示などで、開場時間に合わせて無人で演奏させる)
+//  fade が指定された play/stop は、fade in してから始め、fade out して止める
+pub struct Scheduler {
+    entries: Vec<(u32, Vec<String>)>, // (0時からの分, コマンド)
+    last_min: Option<u32>,
+}
+impl Scheduler {
+    pub fn new(setting: &[ScheduleSetting]) -> Self {
+        let mut entries = Vec::new();
+        for sch in setting.iter() {
+            let min = match Self::parse_time(&sch.time) {
+                Some(m) => m,
+                None => {
+                    println!("Schedule time is wrong: {}", sch.time);
+                    continue;
+                }
+            };
+            let cmds = match sch.cmd.as_str() {
+                "play" if sch.fade > 0 => {
+                    vec![format!("set.fade(in,{})", sch.fade), "play".to_string()]
+                }
+                "stop" if sch.fade > 0 => vec![format!("set.fade(out,{})", sch.fade)],
+                _ => vec![sch.cmd.clone()],
+            };
+            entries.push((min, cmds));
+        }
+        Self {
+            entries,
+            last_min: None,
+        }
+    }
+    /// "10:30" -> 630
+    fn parse_time(time: &str) -> Option<u32> {
+        let (h, m) = time.trim().split_once(':')?;
+        let (h, m) = (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?);
+        (h < 24 && m < 60).then_some(h * 60 + m)
+    }
+    /// now: 0時からの分。分が変わった時、その時刻のコマンドを返す
+    pub fn check(&mut self, now: u32) -> Vec<String> {
+        if self.last_min == Some(now) {
+            return Vec::new();
+        }
+        self.last_min = Some(now);
+        self.entries
+            .iter()
+            .filter(|(min, _)| *min == now)
+            .flat_map(|(_, cmds)| cmds.clone())
+            .collect()
+    }
+}
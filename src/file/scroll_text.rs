@@ -0,0 +1,113 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use crate::lpnlib::TextAttribute;
+use std::collections::VecDeque;
+use std::ops::Index;
+
+//*******************************************************************
+//          Scroll Text Struct
+//*******************************************************************
+//  画面に表示する入力と返答の履歴
+//  長時間使ってもメモリが増え続けないよう、max_lines を超えたら古い行から捨てる
+//  scroll back して前の行を見たり、検索したり、コピーしたりできる
+pub struct ScrollText {
+    lines: VecDeque<(TextAttribute, String, String)>,
+    max_lines: usize,
+    dropped_cmds: usize,  // 捨てた入力(Common)の行数
+    back: usize,          // 最新の行から scroll back した行数(0:最新の行を表示)
+    found: Option<usize>, // search で見つけた行
+}
+impl ScrollText {
+    pub const MAX_LINES: usize = 2000;
+    const SCROLL_STEP: usize = 10;
+
+    pub fn new(max_lines: usize) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            max_lines: max_lines.max(1),
+            dropped_cmds: 0,
+            back: 0,
+            found: None,
+        }
+    }
+    pub fn push(&mut self, attr: TextAttribute, time: String, txt: String) {
+        self.lines.push_back((attr, time, txt));
+        if self.back > 0 {
+            self.back += 1; // scroll back 中は、見ている所を動かさない
+        }
+        while self.lines.len() > self.max_lines {
+            if let Some((TextAttribute::Common, _, _)) = self.lines.pop_front() {
+                self.dropped_cmds += 1;
+            }
+            self.found = self.found.and_then(|f| f.checked_sub(1));
+        }
+        self.back = self.back.min(self.lines.len() - 1);
+    }
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &(TextAttribute, String, String)> {
+        self.lines.iter()
+    }
+    /// 捨てた入力の行数(入力履歴の番号と、表示する行を合わせるのに使う)
+    pub fn dropped_cmds(&self) -> usize {
+        self.dropped_cmds
+    }
+    pub fn get_back(&self) -> usize {
+        self.back
+    }
+    pub fn get_found(&self) -> Option<usize> {
+        self.found
+    }
+    pub fn scroll_up(&mut self) {
+        self.back = (self.back + Self::SCROLL_STEP).min(self.lines.len().saturating_sub(1));
+    }
+    pub fn scroll_down(&mut self) {
+        self.back = self.back.saturating_sub(Self::SCROLL_STEP);
+        if self.back == 0 {
+            self.found = None;
+        }
+    }
+    /// 最新の行の表示に戻る
+    pub fn reset_view(&mut self) {
+        self.back = 0;
+        self.found = None;
+    }
+    /// 表示している一番下の行(前に見つけた行があればその前)から遡って word を探し、
+    /// 見つけた行が一番下に来るように scroll back する
+    pub fn search(&mut self, word: &str) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let start = self.found.unwrap_or(self.lines.len() - self.back);
+        let found = (0..start)
+            .rev()
+            .find(|&i| self.lines[i].2.contains(word) || self.lines[i].1.contains(word));
+        if let Some(i) = found {
+            self.found = Some(i);
+            self.back = self.lines.len() - 1 - i;
+        }
+        found.is_some()
+    }
+    /// 見つけた行(なければ最新の行)までの num 行を、一つの文字列にする
+    pub fn copy_text(&self, num: usize) -> String {
+        let end = self.found.map(|f| f + 1).unwrap_or(self.lines.len());
+        let start = end.saturating_sub(num.max(1));
+        self.lines
+            .range(start..end)
+            .map(|(_, time, txt)| format!("{}{}", time, txt))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+impl Index<usize> for ScrollText {
+    type Output = (TextAttribute, String, String);
+    fn index(&self, idx: usize) -> &Self::Output {
+        &self.lines[idx]
+    }
+}
@@ -0,0 +1,77 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use super::lpn_file::*;
+
+pub const SESSION_FOLDER: &str = "session";
+
+//*******************************************************************
+//          Session Struct
+//*******************************************************************
+//  演奏の状態全体を、リハーサルの間で保存/復元する(session/*.toml)
+//  Phrase/Composition は入力した文字列で持ち、load 時に変換し直す
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub bpm: i16,
+    pub meter: [i16; 2],
+    pub key: String,
+    pub turnnote: i16,
+    pub input_mode: String, // "closer" / "fixed"
+    pub part: Vec<SessionPart>,
+}
+/// パートごとの入力。順番は L1, L2, R1, R2, Flow
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionPart {
+    pub name: String,
+    pub oct: i32,                 // 0:既定の octave
+    pub phrase: Vec<String>,      // 0:Normal, 1-9:Variation
+    pub composition: Vec<String>, // 0:Normal, 1-9:Variation
+    pub vari_link: bool,
+    pub vari: usize,                // 再生中の Variation(0:Normal)
+    pub measure: Vec<SessionMsr>,   // @msr(n) の Phrase
+    pub vellimit: Option<[i16; 2]>, // set.vellimit の (下限, 上限)
+}
+/// @msr(n)=[..] で入力した小節と Phrase
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct SessionMsr {
+    pub msr: usize,
+    pub phrase: String,
+}
+impl LpnFile for Session {}
+impl Session {
+    pub fn gen_text(&self) -> Result<String, String> {
+        toml::to_string(self).map_err(|e| e.to_string())
+    }
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string())
+    }
+    /// session フォルダに fname.toml で書き出し、書き出したファイル名を返す
+    pub fn write(&self, fname: &str) -> Result<String, String> {
+        self.make_folder(SESSION_FOLDER);
+        let fn_with_path = Self::file_path(fname);
+        match fs::write(self.path_str(&fn_with_path), self.gen_text()?) {
+            Err(why) => Err(format!("couldn't write to {}: {}", fn_with_path, why)),
+            Ok(_) => Ok(fn_with_path),
+        }
+    }
+    pub fn read(fname: &str) -> Result<Self, String> {
+        let fn_with_path = Self::file_path(fname);
+        match fs::read_to_string(&fn_with_path) {
+            Err(why) => Err(format!("couldn't read {}: {}", fn_with_path, why)),
+            Ok(text) => Self::from_text(&text),
+        }
+    }
+    fn file_path(fname: &str) -> String {
+        format!(
+            "{}/{}.toml",
+            SESSION_FOLDER,
+            fname.trim_end_matches(".toml")
+        )
+    }
+}
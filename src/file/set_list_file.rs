@@ -0,0 +1,48 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::fs;
+
+pub const SETLIST_FOLDER: &str = "setlist";
+
+//*******************************************************************
+//          Set List File Struct
+//*******************************************************************
+//  scene を演奏する順と長さ(小節数)を並べたファイル(setlist/*.txt)
+//  一行に scene 名と小節数を書く。// から後はコメント
+//      verse 8
+//      chorus 16
+pub struct SetListFile {
+    pub entries: Vec<(String, i16)>, // (scene 名, 小節数)
+}
+impl SetListFile {
+    pub fn from_text(text: &str) -> Result<Self, String> {
+        let mut entries = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split("//").next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match (
+                words.len(),
+                words.get(1).and_then(|w| w.parse::<i16>().ok()),
+            ) {
+                (2, Some(msrs)) if msrs > 0 => entries.push((words[0].to_string(), msrs)),
+                _ => return Err(format!("Set list is wrong at line {}: {}", i + 1, line)),
+            }
+        }
+        if entries.is_empty() {
+            return Err("Set list is empty.".to_string());
+        }
+        Ok(Self { entries })
+    }
+    pub fn read(fname: &str) -> Result<Self, String> {
+        let fn_with_path = format!("{}/{}.txt", SETLIST_FOLDER, fname.trim_end_matches(".txt"));
+        match fs::read_to_string(&fn_with_path) {
+            Err(why) => Err(format!("couldn't read {}: {}", fn_with_path, why)),
+            Ok(text) => Self::from_text(&text),
+        }
+    }
+}
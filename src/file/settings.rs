@@ -4,6 +4,7 @@
 //  https://opensource.org/licenses/mit-license.php
 //
 
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 //use std::fs::File;
@@ -20,10 +21,164 @@ pub struct Midi {
     pub midi_ext_out: String,
     pub midi_device: String,
 }
+/// パートごとの色("#rrggbb")と icon 文字。順番は L1, L2, R1, R2, Flow
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PartLook {
+    #[serde(default)]
+    pub color: Vec<String>,
+    #[serde(default)]
+    pub icon: Vec<String>,
+}
+/// 入力 device ごとのチャタリング対策。device は device名の一部
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct InputFilter {
+    pub device: String,
+    #[serde(default)]
+    pub note_window: u64, // 同じ音の Note On を省く時間[ms]
+    #[serde(default)]
+    pub cc_threshold: u8, // これ未満の CC の変化は省く
+    #[serde(default)]
+    pub cc_interval: u64, // CC を送る最小間隔[ms]
+}
+/// LED 付き鍵盤/ORBIT へ送る protocol。mode は "note", "color", "sysex"
+/// color は L1, L2, R1, R2, Flow(外部の Loopian も含む) の順の色番号(0-127)
+/// range は LED1, LED2 が受け持つ音域 [最低音, 最高音](省略時は全域)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LedSetting {
+    pub mode: String,
+    pub ch: u8,       // 1-16
+    pub color_cc: u8, // mode = "color" の時、色番号を送る CC
+    pub color: Vec<u8>,
+    pub range: Vec<[u8; 2]>,
+}
+impl Default for LedSetting {
+    fn default() -> Self {
+        Self {
+            mode: "note".to_string(),
+            ch: 16,
+            color_cc: 20,
+            color: Vec::new(),
+            range: Vec::new(),
+        }
+    }
+}
+/// time("10:00") になったら cmd を実行する。fade[秒] は play/stop の時のみ使う
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ScheduleSetting {
+    pub time: String,
+    pub cmd: String,
+    #[serde(default)]
+    pub fade: i16,
+}
+/// 受信した Note で Variation を選ぶ pad。ch が 0 なら使わない
+/// part が "R1" などなら、note から 8つの pad で vari(1)-vari(8) を選ぶ
+/// part が "grid" なら、L1, L2, R1, R2 の順に 8つずつ並べる
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VariPad {
+    pub ch: u8,   // 1-16, 0:off
+    pub note: u8, // 一番下の pad の note
+    pub part: String,
+}
+impl Default for VariPad {
+    fn default() -> Self {
+        Self {
+            ch: 0,
+            note: 36,
+            part: "R1".to_string(),
+        }
+    }
+}
+impl VariPad {
+    /// ch.12/13 は ORBIT の入力に使うので、pad には使えない
+    pub fn is_available(&self) -> bool {
+        (1..=16).contains(&self.ch) && self.ch != 12 && self.ch != 13
+    }
+}
+/// 照明の制御機器へ、小節/拍/和音の変化を送る。protocol は "text", "osc"(空なら使わない)
+/// target は UDP の宛先("127.0.0.1:7700")か serial の device("/dev/ttyUSB0")
+/// degree_value は key からの度数(0-11)ごとに、和音を送る時の値(0-255)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LightSetting {
+    pub protocol: String,
+    pub target: String,
+    pub prefix: String, // OSC の address の頭
+    pub beat: bool,     // 拍頭も送る
+    pub degree_value: Vec<u8>,
+}
+impl Default for LightSetting {
+    fn default() -> Self {
+        Self {
+            protocol: String::new(),
+            target: "127.0.0.1:7700".to_string(),
+            prefix: "/loopian".to_string(),
+            beat: true,
+            degree_value: Vec::new(),
+        }
+    }
+}
+/// 他の奏者とパートを分担して演奏する。port を 0 以外にすると、bind の address のその TCP port で待ち受ける
+/// connect に "192.168.0.2:7800" のように書くと、その loopian に name の名前で繋ぎ、コマンドを送る
+/// token を書くと、待ち受ける側は hello に同じ token を求め、繋ぐ側は hello に付けて送る
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CollabSetting {
+    pub port: u16,
+    pub bind: String,
+    pub token: String,
+    pub connect: String,
+    pub name: String,
+}
+impl Default for CollabSetting {
+    fn default() -> Self {
+        Self {
+            port: 0,
+            bind: "127.0.0.1".to_string(),
+            token: String::new(),
+            connect: String::new(),
+            name: "guest".to_string(),
+        }
+    }
+}
+/// keep that のために、直近の MIDI 出力を保持する時間[分]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureSetting {
+    pub minutes: u64,
+}
+impl Default for CaptureSetting {
+    fn default() -> Self {
+        Self { minutes: 10 }
+    }
+}
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Settings {
     pub window_size: WindowSize,
     pub midi: Midi,
+    #[serde(default)]
+    pub part: PartLook,
+    #[serde(default)]
+    pub alias: HashMap<String, String>, // 別名 = コマンド
+    #[serde(default)]
+    pub keybind: HashMap<String, String>, // F1 など = コマンド
+    #[serde(default)]
+    pub midi_map: HashMap<String, String>, // 受信した pc3, song1 など = コマンド
+    #[serde(default)]
+    pub input_filter: Vec<InputFilter>,
+    #[serde(default)]
+    pub led: LedSetting,
+    #[serde(default)]
+    pub vari_pad: VariPad,
+    #[serde(default)]
+    pub schedule: Vec<ScheduleSetting>,
+    #[serde(default)]
+    pub light: LightSetting,
+    #[serde(default)]
+    pub collab: CollabSetting,
+    #[serde(default)]
+    pub capture: CaptureSetting,
 }
 
 impl Settings {
@@ -0,0 +1,58 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::fs;
+
+use super::lpn_file::*;
+
+pub const SETUP_FOLDER: &str = "setup";
+
+//*******************************************************************
+//          Setup Sheet Struct
+//*******************************************************************
+//  演奏中の設定を、ステージ用の一覧(Markdown)として書き出す
+//  section ごとに (項目, 値) の表にする
+pub struct SetupSheet {
+    sections: Vec<(String, Vec<(String, String)>)>,
+}
+impl LpnFile for SetupSheet {}
+impl SetupSheet {
+    pub fn new() -> Self {
+        Self {
+            sections: Vec::new(),
+        }
+    }
+    pub fn add_section(&mut self, name: &str, rows: Vec<(&str, String)>) {
+        let rows = rows
+            .into_iter()
+            .map(|(item, value)| (item.to_string(), value))
+            .collect();
+        self.sections.push((name.to_string(), rows));
+    }
+    pub fn gen_markdown(&self) -> String {
+        let mut txt = "# Loopian Setup\n".to_string();
+        for (name, rows) in self.sections.iter() {
+            txt += &format!("\n## {}\n\n", name);
+            if rows.is_empty() {
+                txt += "(default)\n";
+                continue;
+            }
+            txt += "| item | value |\n|---|---|\n";
+            for (item, value) in rows.iter() {
+                txt += &format!("| {} | {} |\n", item, value);
+            }
+        }
+        txt
+    }
+    /// setup フォルダに fname.md で書き出し、書き出したファイル名を返す
+    pub fn write(&self, fname: &str) -> Result<String, String> {
+        self.make_folder(SETUP_FOLDER);
+        let fname = fname.trim_end_matches(".md");
+        let fn_with_path = format!("{}/{}.md", SETUP_FOLDER, fname);
+        match fs::write(self.path_str(&fn_with_path), self.gen_markdown()) {
+            Err(why) => Err(format!("couldn't write to {}: {}", fn_with_path, why)),
+            Ok(_) => Ok(fn_with_path),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::fs;
+use std::io::Write;
+
+use super::lpn_file::*;
+
+pub const SMF_FOLDER: &str = "smf";
+
+//*******************************************************************
+//          Standard MIDI File (Format 0)
+//*******************************************************************
+/// tempo=60(1,000,000us/quarter), division=1000 とし、1tick=1msec で記録する
+const SMF_DIVISION: u16 = 1000;
+const SMF_TEMPO: u32 = 1_000_000;
+
+/// (msec, status, data1, data2) の列を SMF として書き出す
+pub struct SmfWriter {}
+impl LpnFile for SmfWriter {}
+impl SmfWriter {
+    pub fn new() -> Self {
+        Self {}
+    }
+    /// fname が空ならば、日時をファイル名とする
+    pub fn write(&self, fname: String, evts: &[(u64, u8, u8, u8)]) -> Option<String> {
+        self.make_folder(SMF_FOLDER);
+        let fname = if fname.is_empty() {
+            let dflt = self.default_file_name();
+            dflt[0..dflt.len() - 4].to_string() + ".mid"
+        } else {
+            fname + ".mid"
+        };
+        let fn_with_path = String::from(SMF_FOLDER) + "/" + &fname;
+        let mut file = match fs::File::create(self.path_str(&fn_with_path)) {
+            Err(why) => {
+                println!("couldn't create {}: {}", fn_with_path, why);
+                return None;
+            }
+            Ok(file) => file,
+        };
+        match file.write_all(&Self::gen_smf(evts)) {
+            Err(why) => {
+                println!("couldn't write to {}: {}", fn_with_path, why);
+                None
+            }
+            Ok(_) => {
+                println!("successfully wrote to {}", fn_with_path);
+                Some(fn_with_path)
+            }
+        }
+    }
+    pub fn gen_smf(evts: &[(u64, u8, u8, u8)]) -> Vec<u8> {
+        // Track
+        let mut trk: Vec<u8> = Vec::new();
+        trk.extend_from_slice(&[0x00, 0xff, 0x51, 0x03]);
+        trk.extend_from_slice(&SMF_TEMPO.to_be_bytes()[1..4]);
+        let first = evts.first().map(|x| x.0).unwrap_or(0);
+        let mut last_time = first;
+        for ev in evts.iter() {
+            Self::put_delta(&mut trk, (ev.0 - last_time) as u32);
+            last_time = ev.0;
+            trk.push(ev.1);
+            trk.push(ev.2);
+            if ev.1 & 0xe0 != 0xc0 {
+                // PC/Ch.Pressure 以外は 2byte
+                trk.push(ev.3);
+            }
+        }
+        trk.extend_from_slice(&[0x00, 0xff, 0x2f, 0x00]);
+
+        // Header
+        let mut smf: Vec<u8> = Vec::new();
+        smf.extend_from_slice(b"MThd");
+        smf.extend_from_slice(&6u32.to_be_bytes());
+        smf.extend_from_slice(&0u16.to_be_bytes()); // format 0
+        smf.extend_from_slice(&1u16.to_be_bytes()); // 1 track
+        smf.extend_from_slice(&SMF_DIVISION.to_be_bytes());
+        smf.extend_from_slice(b"MTrk");
+        smf.extend_from_slice(&(trk.len() as u32).to_be_bytes());
+        smf.append(&mut trk);
+        smf
+    }
+    /// 可変長数値表現
+    fn put_delta(trk: &mut Vec<u8>, mut delta: u32) {
+        let mut buf: Vec<u8> = vec![(delta & 0x7f) as u8];
+        delta >>= 7;
+        while delta > 0 {
+            buf.push(((delta & 0x7f) as u8) | 0x80);
+            delta >>= 7;
+        }
+        buf.reverse();
+        trk.append(&mut buf);
+    }
+}
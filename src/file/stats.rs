@@ -0,0 +1,116 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::fs;
+
+use super::lpn_file::*;
+use crate::lpnlib::*;
+
+pub const STATS_FOLDER: &str = "stats";
+const PART_NAME: [&str; MAX_COMPOSITION_PART] = ["L1", "L2", "R1", "R2", "Flow"];
+
+//*******************************************************************
+//          Stats Collector Struct
+//*******************************************************************
+//  演奏中の Note On と小節頭のイベントを集計し、
+//  stop/quit 時に JSON と CSV で書き出す
+pub struct StatsCollector {
+    enable: bool,
+    msrs: Vec<MsrStats>, // 小節ごとの集計
+}
+#[derive(Clone, Copy)]
+struct MsrStats {
+    msr: i32, // 1origin
+    bpm: i16,
+    notes: [u32; MAX_COMPOSITION_PART],
+}
+impl LpnFile for StatsCollector {}
+impl StatsCollector {
+    pub fn new() -> Self {
+        Self {
+            enable: false,
+            msrs: Vec::new(),
+        }
+    }
+    pub fn set_enable(&mut self, enable: bool) {
+        self.enable = enable;
+        self.msrs.clear();
+    }
+    /// 小節頭で呼ぶ
+    pub fn new_measure(&mut self, msr: i32, bpm: i16) {
+        if self.enable {
+            self.msrs.push(MsrStats {
+                msr: msr + 1,
+                bpm,
+                notes: [0; MAX_COMPOSITION_PART],
+            });
+        }
+    }
+    /// Note On ごとに呼ぶ
+    pub fn put_note(&mut self, part: usize) {
+        if let Some(last) = self.msrs.last_mut() {
+            if part < MAX_COMPOSITION_PART {
+                last.notes[part] += 1;
+            }
+        }
+    }
+    /// 集計結果を書き出し、集計をやり直す
+    pub fn flush(&mut self) {
+        if !self.enable || self.msrs.is_empty() {
+            return;
+        }
+        self.make_folder(STATS_FOLDER);
+        let dflt = self.default_file_name();
+        let fname = String::from(STATS_FOLDER) + "/" + &dflt[0..dflt.len() - 4];
+        for (ext, txt) in [(".json", self.gen_json()), (".csv", self.gen_csv())] {
+            let fn_with_path = fname.clone() + ext;
+            match fs::write(self.path_str(&fn_with_path), txt) {
+                Err(why) => println!("couldn't write to {}: {}", fn_with_path, why),
+                Ok(_) => println!("successfully wrote to {}", fn_with_path),
+            }
+        }
+        self.msrs.clear();
+    }
+    fn gen_json(&self) -> String {
+        let mut total = [0; MAX_COMPOSITION_PART];
+        for m in self.msrs.iter() {
+            for (t, n) in total.iter_mut().zip(m.notes.iter()) {
+                *t += n;
+            }
+        }
+        let notes_per_part: Vec<String> = PART_NAME
+            .iter()
+            .zip(total.iter())
+            .map(|(name, n)| format!("\"{}\": {}", name, n))
+            .collect();
+        let tempo: Vec<String> = self
+            .msrs
+            .iter()
+            .map(|m| format!("[{}, {}]", m.msr, m.bpm))
+            .collect();
+        let density: Vec<String> = self
+            .msrs
+            .iter()
+            .map(|m| format!("[{}, {}]", m.msr, m.notes.iter().sum::<u32>()))
+            .collect();
+        format!(
+            "{{\n  \"measures\": {},\n  \"notes_per_part\": {{{}}},\n  \"tempo\": [{}],\n  \"density\": [{}]\n}}\n",
+            self.msrs.len(),
+            notes_per_part.join(", "),
+            tempo.join(", "),
+            density.join(", ")
+        )
+    }
+    fn gen_csv(&self) -> String {
+        let mut csv = String::from("msr,bpm,notes");
+        PART_NAME.iter().for_each(|x| csv += &format!(",{}", x));
+        csv += "\n";
+        for m in self.msrs.iter() {
+            csv += &format!("{},{},{}", m.msr, m.bpm, m.notes.iter().sum::<u32>());
+            m.notes.iter().for_each(|x| csv += &format!(",{}", x));
+            csv += "\n";
+        }
+        csv
+    }
+}
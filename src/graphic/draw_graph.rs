@@ -11,10 +11,13 @@ use super::beatlissa::*;
 use super::generative_view::*;
 use super::guiev::*;
 use super::lissajous::*;
+use super::notation::NotationLayout;
+use super::particle::ParticleView;
 use super::voice4::*;
 use super::waterripple::WaterRipple;
 use crate::cmd::txt_common::*;
 use crate::file::input_txt::InputText;
+use crate::file::settings::Settings;
 use crate::lpnlib::*;
 
 //*******************************************************************
@@ -32,15 +35,13 @@ pub struct Resize {
     input_txt_left: f32,
 }
 impl Resize {
-    pub fn new(app: &App) -> Resize {
+    pub fn new(win_rect: Rect) -> Resize {
         const EIGHT_INDIC_TOP: f32 = 40.0; // eight indicator
         const SCROLL_TXT_TOP: f32 = 80.0; // scroll text
         const INPUT_TXT_LOWER_MERGIN: f32 = 80.0; // input text
         const MIN_LEFT_MERGIN: f32 = 140.0;
         const MIN_RIGHT_MERGIN: f32 = 140.0;
 
-        let win = app.main_window();
-        let win_rect = win.rect();
         let win_width = win_rect.w();
         let win_height = win_rect.h();
         let st_left_mergin = -win_width / 2.0 + MIN_LEFT_MERGIN;
@@ -64,6 +65,56 @@ impl Resize {
     }
 }
 //*******************************************************************
+//      struct PartStyle
+//*******************************************************************
+//  パートごとの色と icon。settings.toml の [part] で変更できる
+#[derive(Debug, Clone)]
+pub struct PartStyle {
+    color: [(f32, f32, f32); MAX_COMPOSITION_PART],
+    icon: Vec<String>,
+}
+impl PartStyle {
+    const DEFAULT_COLOR: [(f32, f32, f32); MAX_COMPOSITION_PART] = [
+        (0.3, 0.5, 1.0), // L1
+        (0.3, 0.9, 0.8), // L2
+        (1.0, 0.6, 0.2), // R1
+        (1.0, 0.3, 0.5), // R2
+        (0.8, 0.4, 1.0), // Flow
+    ];
+    pub fn load() -> Self {
+        let look = Settings::load_settings().part;
+        let mut color = Self::DEFAULT_COLOR;
+        for (c, txt) in color.iter_mut().zip(look.color.iter()) {
+            match Self::parse_color(txt) {
+                Some(rgb) => *c = rgb,
+                None => println!("Part color is wrong: {}", txt),
+            }
+        }
+        let mut icon = look.icon;
+        icon.resize(MAX_COMPOSITION_PART, String::new());
+        Self { color, icon }
+    }
+    /// "#rrggbb" -> (r, g, b)
+    fn parse_color(txt: &str) -> Option<(f32, f32, f32)> {
+        let hex = txt.strip_prefix('#')?;
+        if hex.len() != 6 {
+            return None;
+        }
+        let v = u32::from_str_radix(hex, 16).ok()?;
+        let ch = |sft: u32| ((v >> sft) & 0xff) as f32 / 255.0;
+        Some((ch(16), ch(8), ch(0)))
+    }
+    pub fn color(&self, pt: usize) -> (f32, f32, f32) {
+        self.color[pt.min(MAX_COMPOSITION_PART - 1)]
+    }
+    pub fn colors(&self) -> [(f32, f32, f32); MAX_COMPOSITION_PART] {
+        self.color
+    }
+    pub fn icon(&self, pt: usize) -> &str {
+        self.icon.get(pt).map_or("", |x| x.as_str())
+    }
+}
+//*******************************************************************
 //      struct Graphic
 //*******************************************************************
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -97,6 +148,13 @@ pub struct Graphic {
     top_visible_line: usize,
     max_lines: usize,
     crnt_line: usize,
+    win_req: Vec<GraphicMsg>, // main.rs で処理する Window 操作
+    pstyle: PartStyle,        // パートごとの色と icon
+    metronome: bool,          // Visual Metronome の表示
+    beat_time: f32,           // 直近の拍の時刻 [sec]
+    beat_num: i32,            // 直近の拍(0origin)
+    notation: Option<(usize, NotationLayout)>, // 譜面表示するパートとその Layout
+    piano_roll: bool,         // これから鳴る音の表示
 }
 
 //*******************************************************************
@@ -127,6 +185,13 @@ impl Graphic {
             top_visible_line: 0,
             max_lines: 0,
             crnt_line: 0,
+            win_req: Vec::new(),
+            pstyle: PartStyle::load(),
+            metronome: false,
+            beat_time: -1.0,
+            beat_num: 0,
+            notation: None,
+            piano_roll: false,
         }
     }
     fn load_font(app: &App, font_path: &str) -> nannou::text::Font {
@@ -148,6 +213,10 @@ impl Graphic {
     pub fn set_rs(&mut self, rs: Resize) {
         self.rs = rs;
     }
+    /// Window 操作の要求を取り出す(App が必要なため main.rs で処理する)
+    pub fn take_win_req(&mut self) -> Vec<GraphicMsg> {
+        std::mem::take(&mut self.win_req)
+    }
 
     //*******************************************************************
     //      Operate Events & Update Model
@@ -181,6 +250,8 @@ impl Graphic {
                             .parse::<f32>()
                             .unwrap_or(100.0);
                         let draw_time = (60.0 / bpm) + 0.1;
+                        self.beat_time = crnt_time;
+                        self.beat_num = beat;
                         if let Some(sv) = self.svce.as_mut() {
                             sv.on_beat(beat, crnt_time, draw_time);
                         }
@@ -195,6 +266,13 @@ impl Graphic {
             sv.update_model(crnt_time, self.rs.clone());
         }
 
+        // 譜面の Layout 更新(再生中の Phrase が変わった時だけ作り直す)
+        if let Some((pt, layout)) = self.notation.as_mut() {
+            let vari = guiev.get_part_vari(*pt);
+            let (phr, whole_tick, tick_for_onemsr) = itxt.get_phrase(*pt, vari.clone());
+            layout.update(vari, phr, whole_tick, tick_for_onemsr);
+        }
+
         // Scroll Text の更新
         self.update_scroll_text(itxt);
     }
@@ -226,6 +304,13 @@ impl Graphic {
                 self.gptn = GraphPattern::Lissajous;
                 self.svce = Some(Box::new(Lissajous::new(self.gmode)));
             }
+            GraphicMsg::ParticlePattern => {
+                self.gptn = GraphPattern::Particle;
+                self.svce = Some(Box::new(ParticleView::new(
+                    self.gmode,
+                    self.pstyle.colors(),
+                )));
+            }
             GraphicMsg::BeatLissaPattern(md) => {
                 let mt = guiev.get_indicator(INDC_METER).to_string();
                 let num_str = split_by('/', mt);
@@ -233,6 +318,25 @@ impl Graphic {
                 self.gptn = GraphPattern::BeatLissa;
                 self.svce = Some(Box::new(BeatLissa::new(num, crnt_time, md, self.gmode)));
             }
+            GraphicMsg::FullScreen(_)
+            | GraphicMsg::AudienceWin(_)
+            | GraphicMsg::Screenshot
+            | GraphicMsg::FrameRec(_) => {
+                self.win_req.push(msg);
+            }
+            GraphicMsg::Metronome(on) => {
+                self.metronome = on;
+            }
+            GraphicMsg::Notation(pt) => {
+                self.notation = if pt >= 0 && (pt as usize) < MAX_KBD_PART {
+                    Some((pt as usize, NotationLayout::default()))
+                } else {
+                    None
+                };
+            }
+            GraphicMsg::PianoRoll(on) => {
+                self.piano_roll = on;
+            }
             GraphicMsg::TextVisibleCtrl => {
                 self.text_visible = self.text_visible.next();
             }
@@ -264,7 +368,10 @@ impl Graphic {
         }
 
         // Adjust top_visible_line
-        let crnt_history = itxt.get_history_locate();
+        // 古い行は捨てられているので、入力履歴の番号をずらす
+        let crnt_history = itxt
+            .get_history_locate()
+            .saturating_sub(scroll_texts.dropped_cmds());
         let mut crnt_line: usize = lines;
         if crnt_history < max_histories {
             // 対応する履歴が全体のどの位置にあるかを調べる
@@ -284,7 +391,12 @@ impl Graphic {
                 top_visible_line = crnt_line - max_lines_in_window + 1;
             }
         } else if lines >= max_lines_in_window {
-            top_visible_line = lines - max_lines_in_window;
+            // scroll back していれば、その分前の行を表示する
+            top_visible_line =
+                (lines - max_lines_in_window).saturating_sub(scroll_texts.get_back());
+        }
+        if let Some(found) = scroll_texts.get_found() {
+            crnt_line = found; // 検索で見つけた行に下線を引く
         }
 
         self.top_visible_line = top_visible_line;
@@ -314,6 +426,25 @@ impl Graphic {
         }
         self.title(draw.clone());
         self.eight_indicator(draw.clone(), guiev);
+        self.msr_text(draw.clone(), guiev);
+        self.set_list_line(draw.clone(), guiev);
+        if self.metronome {
+            self.visual_metronome(draw.clone(), guiev, tm);
+        }
+        if self.text_visible != TextVisible::Invisible {
+            self.notation(draw.clone(), guiev);
+            if self.piano_roll {
+                self.piano_roll(draw.clone(), guiev);
+            }
+            self.midi_monitor(draw.clone(), guiev);
+            self.note_name(draw.clone(), guiev);
+        }
+    }
+    /// 観客用 Window の描画(Generative Pattern のみ)
+    pub fn view_audience(&self, draw: Draw, tm: f32, rs: Resize) {
+        if let Some(sv) = self.svce.as_ref() {
+            sv.disp(draw, tm, rs);
+        }
     }
     fn view_loopian_generative_view(&self, draw: Draw, tm: f32) {
         if let Some(sv) = self.svce.as_ref() {
@@ -350,6 +481,18 @@ impl Graphic {
             .x_y(self.rs.eight_indic_left, self.rs.eight_indic_top)
             .w_h(400.0, 40.0);
 
+        let time = guiev.get_indicator(INDC_TIME);
+        draw.text(time)
+            .font(self.font_nrm.clone())
+            .font_size(18)
+            .color(txt_color)
+            .left_justify()
+            .x_y(
+                self.rs.eight_indic_left + 40.0,
+                self.rs.eight_indic_top - 36.0,
+            )
+            .w_h(400.0, 24.0);
+
         let bpm = guiev.get_indicator(INDC_BPM);
         draw.text("bpm:")
             .font(self.font_nrm.clone())
@@ -371,6 +514,19 @@ impl Graphic {
                 self.rs.eight_indic_top - 70.0,
             )
             .w_h(400.0, 40.0);
+        if let Some(gesture) = guiev.get_gesture_txt() {
+            // 実行中の rit./accel. と到達テンポ
+            draw.text(&gesture)
+                .font(self.font_nrm.clone())
+                .font_size(20)
+                .color(txt_color)
+                .left_justify()
+                .x_y(
+                    self.rs.eight_indic_left + 240.0,
+                    self.rs.eight_indic_top - 70.0,
+                )
+                .w_h(400.0, 40.0);
+        }
 
         let meter = guiev.get_indicator(INDC_METER);
         draw.text("meter:")
@@ -418,18 +574,261 @@ impl Graphic {
 
         for i in 0..4 {
             let pt = guiev.get_indicator(7 - i);
-            draw.text(&(guiev.get_part_txt(3 - i).to_string() + pt))
+            let (r, g, b) = self.pstyle.color(3 - i);
+            let mut txt = self.pstyle.icon(3 - i).to_string() + guiev.get_part_txt(3 - i) + pt;
+            if let Some(tone) = guiev.get_tone_txt(3 - i) {
+                txt += &tone;
+            }
+            draw.text(&txt)
                 .font(self.font_nrm.clone())
                 .font_size(20)
-                .color(txt_color)
+                .color(rgb(r, g, b))
                 .left_justify()
                 .x_y(
                     self.rs.eight_indic_left + 40.0,
                     self.rs.eight_indic_top - 190.0 - (i as f32) * 30.0,
                 )
                 .w_h(400.0, 30.0);
+            // 和音の種類を色で示す
+            if let Some((r, g, b)) = Self::quality_color(guiev.get_chord_quality(3 - i)) {
+                draw.ellipse()
+                    .color(rgb(r, g, b))
+                    .x_y(
+                        self.rs.eight_indic_left - 180.0,
+                        self.rs.eight_indic_top - 190.0 - (i as f32) * 30.0,
+                    )
+                    .w_h(12.0, 12.0);
+            }
+        }
+        // 入力された旋律(Flow)の構成音の分析
+        if let Some(tone) = guiev.get_tone_txt(FLOW_PART) {
+            let (r, g, b) = self.pstyle.color(FLOW_PART);
+            let txt = self.pstyle.icon(FLOW_PART).to_string() + "Flow" + &tone;
+            draw.text(&txt)
+                .font(self.font_nrm.clone())
+                .font_size(20)
+                .color(rgb(r, g, b))
+                .left_justify()
+                .x_y(
+                    self.rs.eight_indic_left + 40.0,
+                    self.rs.eight_indic_top - 310.0,
+                )
+                .w_h(400.0, 30.0);
+        }
+    }
+    /// 和音の種類ごとの色(None は表示しない)
+    fn quality_color(quality: ChordQuality) -> Option<(f32, f32, f32)> {
+        match quality {
+            ChordQuality::Major => Some((1.0, 0.75, 0.2)),
+            ChordQuality::Minor => Some((0.3, 0.6, 1.0)),
+            ChordQuality::Dominant => Some((1.0, 0.3, 0.3)),
+            ChordQuality::Diminished => Some((0.7, 0.4, 1.0)),
+            ChordQuality::None => None,
+        }
+    }
+    /// 小節に付けられた歌詞やメモの描画
+    fn msr_text(&self, draw: Draw, guiev: &GuiEv) {
+        let txt = guiev.get_msr_text();
+        if txt.is_empty() {
+            return;
+        }
+        let txt_color = if self.gmode == GraphMode::Light {
+            GRAY
+        } else {
+            WHITE
+        };
+        draw.text(txt)
+            .font(self.font_newyork.clone())
+            .font_size(36)
+            .color(txt_color)
+            .center_justify()
+            .x_y(0.0, self.rs.input_txt_top + 80.0)
+            .w_h(self.rs.full_size_x, 50.0);
+    }
+    /// set list の今/次の scene の描画
+    fn set_list_line(&self, draw: Draw, guiev: &GuiEv) {
+        let txt = match guiev.get_set_list_txt() {
+            Some(txt) => format!("Scene: {}", txt),
+            None => return,
+        };
+        draw.text(&txt)
+            .font(self.font_nrm.clone())
+            .font_size(20)
+            .color(MAGENTA)
+            .center_justify()
+            .x_y(0.0, self.rs.input_txt_top + 40.0)
+            .w_h(self.rs.full_size_x, 30.0);
+    }
+    /// Visual Metronome の描画
+    /// 拍頭で光り、小節の1拍目は色を変える。その横に大きく小節番号を表示する
+    fn visual_metronome(&self, draw: Draw, guiev: &GuiEv, tm: f32) {
+        const FLASH_TIME: f32 = 0.2; // [sec]
+        const FLASH_SIZE: f32 = 80.0;
+        const METRO_TOP: f32 = 360.0; // Eight Indicator の下
+
+        let x = self.rs.eight_indic_left + 80.0;
+        let y = self.rs.eight_indic_top - METRO_TOP;
+        let crnt = guiev.get_msr_tick();
+        let (r, g, b) = if self.beat_num == 0 {
+            (1.0, 0.0, 1.0) // MAGENTA
+        } else if self.gmode == GraphMode::Light {
+            (0.5, 0.5, 0.5)
+        } else {
+            (1.0, 1.0, 1.0)
+        };
+        let elapsed = tm - self.beat_time;
+        if crnt.msr != 0 && self.beat_time >= 0.0 && elapsed < FLASH_TIME {
+            let alpha = 1.0 - elapsed / FLASH_TIME;
+            draw.ellipse()
+                .color(rgba(r, g, b, alpha))
+                .x_y(x, y)
+                .w_h(FLASH_SIZE, FLASH_SIZE);
+        }
+        draw.ellipse()
+            .no_fill()
+            .stroke(rgba(r, g, b, 1.0))
+            .stroke_weight(2.0)
+            .x_y(x, y)
+            .w_h(FLASH_SIZE, FLASH_SIZE);
+
+        let msr = if crnt.msr != 0 { crnt.msr } else { 1 };
+        draw.text(&msr.to_string())
+            .font(self.font_nrm.clone())
+            .font_size(96)
+            .color(rgba(r, g, b, 1.0))
+            .left_justify()
+            .x_y(x + FLASH_SIZE + 160.0, y)
+            .w_h(400.0, 100.0);
+    }
+    /// 選んだパートの Phrase を五線譜で描画
+    fn notation(&self, draw: Draw, guiev: &GuiEv) {
+        const NOTATION_TOP: f32 = 170.0; // Input Text の上
+        const NOTATION_WIDTH: f32 = 1000.0;
+
+        let (pt, layout) = match self.notation.as_ref() {
+            Some(n) => n,
+            None => return,
+        };
+        let txt_color = if self.gmode == GraphMode::Light {
+            (0.5, 0.5, 0.5)
+        } else {
+            (1.0, 1.0, 1.0)
+        };
+        let left = -NOTATION_WIDTH / 2.0;
+        let y = self.rs.input_txt_top + NOTATION_TOP;
+        let (r, g, b) = self.pstyle.color(*pt);
+        draw.text(self.pstyle.icon(*pt))
+            .font(self.font_nrm.clone())
+            .font_size(20)
+            .color(rgba(r, g, b, 1.0))
+            .right_justify()
+            .x_y(left - 60.0, y)
+            .w_h(100.0, 30.0);
+        if layout.is_empty() {
+            return;
+        }
+        let crnt = guiev.get_msr_tick();
+        let crnt_tick = if crnt.msr > 0 {
+            (crnt.msr - 1) * crnt.tick_for_onemsr + crnt.tick
+        } else {
+            -1
+        };
+        layout.disp(
+            draw,
+            self.font_italic.clone(),
+            (left, y, NOTATION_WIDTH),
+            txt_color,
+            crnt_tick,
+        );
+    }
+    /// これから鳴る音を、パートの色で右から左へ流れる Piano Roll として描画
+    fn piano_roll(&self, draw: Draw, guiev: &GuiEv) {
+        const ROLL_TOP: f32 = 300.0; // Input Text の上
+        const ROLL_WIDTH: f32 = 1000.0;
+        const ROLL_HEIGHT: f32 = 120.0;
+        const TICK_WIDTH: f32 = 0.1; // 1tick あたりの幅
+        const NOTE_HEIGHT: f32 = 2.0;
+
+        let left = -ROLL_WIDTH / 2.0;
+        let bottom = self.rs.input_txt_top + ROLL_TOP;
+        let line_color = if self.gmode == GraphMode::Light {
+            GRAY
+        } else {
+            WHITE
+        };
+        draw.line()
+            .start(pt2(left, bottom))
+            .end(pt2(left, bottom + ROLL_HEIGHT))
+            .weight(1.0)
+            .color(line_color);
+        for pt in 0..MAX_KBD_PART {
+            let (r, g, b) = self.pstyle.color(pt);
+            for ev in guiev.get_upcoming(pt, usize::MAX) {
+                let x = left + ev.tick as f32 * TICK_WIDTH;
+                if x > left + ROLL_WIDTH {
+                    break;
+                }
+                let y = bottom + (ev.note as f32 - 24.0) * ROLL_HEIGHT / 84.0;
+                let alpha = 0.3 + 0.7 * (ev.vel as f32 / 127.0);
+                draw.rect()
+                    .color(rgba(r, g, b, alpha))
+                    .x_y(x + 4.0, y)
+                    .w_h(8.0, NOTE_HEIGHT);
+            }
         }
     }
+    /// MIDI Monitor の描画(右上から下に向かって古い順)
+    fn midi_monitor(&self, draw: Draw, guiev: &GuiEv) {
+        const MONITOR_WIDTH: f32 = 360.0;
+        const LINE_HEIGHT: f32 = 20.0;
+
+        let mon = match guiev.get_monitor() {
+            Some(m) => m,
+            None => return,
+        };
+        let txt_color = if self.gmode == GraphMode::Light {
+            GRAY
+        } else {
+            WHITE
+        };
+        let x = self.rs.full_size_x / 2.0 - MONITOR_WIDTH / 2.0 - 20.0;
+        draw.text("MIDI Monitor")
+            .font(self.font_nrm.clone())
+            .font_size(18)
+            .color(MAGENTA)
+            .left_justify()
+            .x_y(x, self.rs.eight_indic_top)
+            .w_h(MONITOR_WIDTH, LINE_HEIGHT);
+        for (i, line) in mon.iter().enumerate() {
+            draw.text(line)
+                .font(self.font_nrm.clone())
+                .font_size(14)
+                .color(txt_color)
+                .left_justify()
+                .x_y(x, self.rs.eight_indic_top - LINE_HEIGHT * (i as f32 + 1.0))
+                .w_h(MONITOR_WIDTH, LINE_HEIGHT);
+        }
+    }
+    /// 入力した音の名前の描画(左から古い順)
+    fn note_name(&self, draw: Draw, guiev: &GuiEv) {
+        const NAME_WIDTH: f32 = 600.0;
+        let names = match guiev.get_notename_txt() {
+            Some(n) => n,
+            None => return,
+        };
+        let txt_color = if self.gmode == GraphMode::Light {
+            GRAY
+        } else {
+            WHITE
+        };
+        draw.text(&names)
+            .font(self.font_nrm.clone())
+            .font_size(28)
+            .color(txt_color)
+            .center_justify()
+            .x_y(0.0, self.rs.input_txt_top + 130.0)
+            .w_h(NAME_WIDTH, 40.0);
+    }
     /// Input Text の描画
     fn input_text(&self, draw: Draw, guiev: &GuiEv, itxt: &InputText, tm: f32) {
         const INPUT_TXT_X_SZ: f32 = 1240.0;
@@ -18,6 +18,7 @@ pub enum GraphPattern {
     Voice4,
     Lissajous,
     BeatLissa,
+    Particle,
 }
 
 pub trait GenerativeView {
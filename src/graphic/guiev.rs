@@ -3,6 +3,8 @@
 //  Released under the MIT license
 //  https://opensource.org/licenses/mit-license.php
 //
+use std::collections::VecDeque;
+
 use crate::elapse::tickgen::CrntMsrTick;
 use crate::lpnlib::*;
 
@@ -11,11 +13,12 @@ pub const INDC_BPM: usize = 1;
 pub const INDC_METER: usize = 2;
 pub const INDC_TICK: usize = 3;
 pub const INDC_PART: usize = 4;
-pub const MAX_INDICATOR: usize = 8;
+pub const INDC_TIME: usize = 8;
+pub const MAX_INDICATOR: usize = 9;
 
 //*******************************************************************
 //  Stock GUI Event from Text Input by User,
-//      and Indicator Text: 0:Key, 1:BPM, 2:Meter, 3:Tick, 4-7:Part
+//      and Indicator Text: 0:Key, 1:BPM, 2:Meter, 3:Tick, 4-7:Part, 8:Time
 //*******************************************************************
 pub struct GuiEv {
     has_gui: bool,
@@ -25,8 +28,21 @@ pub struct GuiEv {
     numerator: i32,
     denomirator: i32,
     during_play: bool,
+    practice_step: i32,
+    msr_text: String,                          // 小節に付けられた歌詞やメモ
+    tone: Option<ToneStats>,                   // 構成音/Tension/非和声音の数
+    monitor: Option<VecDeque<String>>,         // MIDI Monitor の直近の行
+    notename: Option<VecDeque<String>>,        // 入力した音の名前(古い順)
+    gesture: Option<(bool, i16, usize)>,       // 実行中の rit./accel.
+    set_list: Option<String>,                  // set list の今/次の scene
+    upcoming: [Vec<UpcomingEv>; MAX_KBD_PART], // パートごとの、これから鳴る音
+    quality: [ChordQuality; MAX_KBD_PART],     // パートごとの、今の和音の種類
+    vari: [PhraseAs; MAX_KBD_PART],            // パートごとの、再生中の Phrase
 }
 impl GuiEv {
+    pub const MONITOR_LINES: usize = 20;
+    pub const NOTENAME_NUM: usize = 12;
+
     pub fn new(has_gui: bool) -> Self {
         let mut indicator = vec![String::from("---"); MAX_INDICATOR];
         indicator[INDC_KEY] = "C".to_string();
@@ -39,6 +55,16 @@ impl GuiEv {
             numerator: 4,
             denomirator: 4,
             during_play: false,
+            practice_step: 0,
+            msr_text: String::new(),
+            tone: None,
+            monitor: None,
+            notename: None,
+            gesture: None,
+            set_list: None,
+            upcoming: Default::default(),
+            quality: [ChordQuality::None; MAX_KBD_PART],
+            vari: Default::default(),
         }
     }
     pub fn get_part_txt(&self, input_part: usize) -> &str {
@@ -53,6 +79,67 @@ impl GuiEv {
     pub fn get_indicator(&self, num: usize) -> &str {
         &self.indicator[num]
     }
+    pub fn during_play(&self) -> bool {
+        self.during_play
+    }
+    pub fn get_msr_text(&self) -> &str {
+        &self.msr_text
+    }
+    /// 前の小節の 構成音/Tension/非和声音 の割合を表示用の文字列にする
+    pub fn get_tone_txt(&self, part: usize) -> Option<String> {
+        let tone = self.tone?[part];
+        let total: u32 = tone.iter().sum();
+        if total == 0 {
+            return Some(" C-- T-- N--".to_string());
+        }
+        let pct = |n: u32| n * 100 / total;
+        Some(format!(
+            " C{:02} T{:02} N{:02}",
+            pct(tone[0]),
+            pct(tone[1]),
+            pct(tone[2])
+        ))
+    }
+    /// 実行中の rit./accel. と到達テンポを表示用の文字列にする
+    pub fn get_gesture_txt(&self) -> Option<String> {
+        let (accel, target, rest) = self.gesture?;
+        let name = if accel { "accel." } else { "rit." };
+        let tgt = match target {
+            0 => "fermata".to_string(),
+            x if x < 0 => "-".to_string(),
+            x => x.to_string(),
+        };
+        if rest > 0 {
+            Some(format!("{}>{} +{}", name, tgt, rest))
+        } else {
+            Some(format!("{}>{}", name, tgt))
+        }
+    }
+    pub fn get_set_list_txt(&self) -> Option<&str> {
+        self.set_list.as_deref()
+    }
+    /// part のこれから鳴る音を、近い順に num 個まで返す(tick は最後に受け取った時点から)
+    /// piano roll などの表示は、Loop の計算をせずにこれを使う
+    pub fn get_upcoming(&self, part: usize, num: usize) -> &[UpcomingEv] {
+        match self.upcoming.get(part) {
+            Some(up) if self.during_play => &up[..num.min(up.len())],
+            _ => &[],
+        }
+    }
+    pub fn get_part_vari(&self, part: usize) -> PhraseAs {
+        self.vari.get(part).cloned().unwrap_or_default()
+    }
+    pub fn get_chord_quality(&self, part: usize) -> ChordQuality {
+        self.quality.get(part).copied().unwrap_or_default()
+    }
+    pub fn get_monitor(&self) -> Option<&VecDeque<String>> {
+        self.monitor.as_ref()
+    }
+    pub fn get_notename_txt(&self) -> Option<String> {
+        self.notename
+            .as_ref()
+            .map(|nm| nm.iter().cloned().collect::<Vec<_>>().join(" "))
+    }
     pub fn get_graphic_ev(&self) -> Option<Vec<GraphicEv>> {
         if self.has_gui {
             Some(self.graphic_ev.clone())
@@ -90,7 +177,11 @@ impl GuiEv {
             UiMsg::TickUi(during_play, m, b, t) => {
                 let p = if during_play { ">" } else { " " };
                 let msr = if m != 0 { m } else { 1 };
-                self.indicator[INDC_TICK] = format!("{}{}:{}:{:>03}", p, msr, b, t);
+                self.indicator[INDC_TICK] = if self.practice_step != 0 {
+                    format!("{}{}:{}:{:>03} P{}", p, msr, b, t, self.practice_step)
+                } else {
+                    format!("{}{}:{}:{:>03}", p, msr, b, t)
+                };
                 self.during_play = during_play;
                 self.crnt_msr.msr = m;
                 let base_tick = DEFAULT_TICK_FOR_ONE_MEASURE / self.denomirator;
@@ -98,6 +189,16 @@ impl GuiEv {
                 self.crnt_msr.tick_for_onemsr = base_tick * self.numerator;
             }
             UiMsg::PartUi(pnum, pui) => {
+                if let Some(v) = self.vari.get_mut(pnum) {
+                    *v = pui.vari.clone();
+                }
+                if let Some(q) = self.quality.get_mut(pnum) {
+                    *q = if pui.exist || pui.flow {
+                        pui.quality
+                    } else {
+                        ChordQuality::None
+                    };
+                }
                 if pui.exist {
                     let loop_msr = format!(" {}/{}", pui.msr_in_loop, pui.all_msrs);
                     self.indicator[INDC_PART + pnum] = format!(" {} {}", loop_msr, pui.chord_name);
@@ -111,6 +212,61 @@ impl GuiEv {
             UiMsg::NoteUi(note_ev) => {
                 self.graphic_ev.push(GraphicEv::NoteEv(note_ev));
             }
+            UiMsg::TextUi(txt) => {
+                self.msr_text = txt;
+            }
+            UiMsg::ToneUi(tone) => {
+                self.tone = tone;
+            }
+            UiMsg::GestureUi(gesture) => {
+                self.gesture = gesture;
+            }
+            UiMsg::SetListUi(txt) => {
+                self.set_list = txt;
+            }
+            UiMsg::UpcomingUi(part, upcoming) if part < MAX_KBD_PART => {
+                self.upcoming[part] = upcoming;
+            }
+            UiMsg::MonitorUi(line) => match line {
+                Some(ln) => {
+                    let mon = self.monitor.get_or_insert_with(VecDeque::new);
+                    mon.push_back(ln);
+                    if mon.len() > Self::MONITOR_LINES {
+                        mon.pop_front();
+                    }
+                }
+                None => self.monitor = None,
+            },
+            UiMsg::NoteNameUi(name) => match name {
+                Some(nm) => {
+                    let names = self.notename.get_or_insert_with(VecDeque::new);
+                    names.push_back(nm);
+                    if names.len() > Self::NOTENAME_NUM {
+                        names.pop_front();
+                    }
+                }
+                None => self.notename = None,
+            },
+            UiMsg::PracticeUi(step) => {
+                self.practice_step = step;
+            }
+            UiMsg::TimeUi(elapsed, total) => {
+                // 経過時間は SMPTE(30fps) 形式、推定時間は分:秒
+                let ms = elapsed.max(0);
+                let (h, m, s, f) = (
+                    ms / 3_600_000,
+                    (ms / 60_000) % 60,
+                    (ms / 1000) % 60,
+                    (ms % 1000) * 30 / 1000,
+                );
+                let smpte = format!("{:02}:{:02}:{:02}:{:02}", h, m, s, f);
+                self.indicator[INDC_TIME] = if total > 0 {
+                    let ts = total / 1000;
+                    format!("{} / {}:{:02}", smpte, ts / 60, ts % 60)
+                } else {
+                    smpte
+                };
+            }
             _ => {}
         }
 
@@ -3,5 +3,7 @@ pub mod draw_graph;
 pub mod generative_view;
 pub mod guiev;
 pub mod lissajous;
+pub mod notation;
+pub mod particle;
 pub mod voice4;
 pub mod waterripple;
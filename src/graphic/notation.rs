@@ -0,0 +1,252 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use nannou::prelude::*;
+
+use crate::lpnlib::*;
+
+//*******************************************************************
+//          Notation Layout Struct
+//*******************************************************************
+//  Phrase を簡単な五線譜として並べる(符頭・符幹・旗・小節線のみ)
+//  x は whole_tick に対する割合(0.0-1.0)、step は五線の一番下の線からの音度
+#[derive(Debug, Clone)]
+struct NoteGroup {
+    x: f32,
+    heads: Vec<(i32, bool)>, // (step, sharp)
+    value: usize,            // 0:全音符 1:2分 2:4分 3:8分 4:16分 5:32分
+    dot: bool,
+}
+#[derive(Debug, Clone, Default)]
+pub struct NotationLayout {
+    bass_clef: bool,
+    groups: Vec<NoteGroup>,
+    bars: Vec<f32>, // 小節線の x
+    whole_tick: i32,
+    src: Option<(PhraseAs, i32, i32, Vec<PhrEvt>)>, // Layout を作った Phrase
+}
+impl NotationLayout {
+    const TREBLE_BOTTOM: i32 = 37; // E4 の音度
+    const BASS_BOTTOM: i32 = 25; // G2 の音度
+    const VALUE_TICK: [i32; 6] = [1920, 960, 480, 240, 120, 60];
+
+    pub fn new(evts: &[PhrEvt], whole_tick: i32, tick_for_onemsr: i32) -> Self {
+        let notes: Vec<&PhrEvt> = evts.iter().filter(|e| e.mtype == TYPE_NOTE).collect();
+        if notes.is_empty() || whole_tick <= 0 {
+            return Self::default();
+        }
+        // 平均の音高でト音記号/ヘ音記号を選ぶ
+        let avg = notes.iter().map(|e| e.note as i32).sum::<i32>() / notes.len() as i32;
+        let bass_clef = avg < 60;
+        let bottom = if bass_clef {
+            Self::BASS_BOTTOM
+        } else {
+            Self::TREBLE_BOTTOM
+        };
+
+        let mut groups: Vec<NoteGroup> = Vec::new();
+        for ev in notes.iter() {
+            let (dn, sharp) = Self::diatonic(ev.note as i32);
+            let head = (dn - bottom, sharp);
+            match groups.last_mut() {
+                Some(g) if g.x == Self::ratio(ev.tick as i32, whole_tick) => g.heads.push(head),
+                _ => {
+                    let (value, dot) = Self::note_value(ev.dur as i32);
+                    groups.push(NoteGroup {
+                        x: Self::ratio(ev.tick as i32, whole_tick),
+                        heads: vec![head],
+                        value,
+                        dot,
+                    });
+                }
+            }
+        }
+        let mut bars = Vec::new();
+        if tick_for_onemsr > 0 {
+            let mut tk = tick_for_onemsr;
+            while tk <= whole_tick {
+                bars.push(Self::ratio(tk, whole_tick));
+                tk += tick_for_onemsr;
+            }
+        }
+        Self {
+            bass_clef,
+            groups,
+            bars,
+            whole_tick,
+            src: None,
+        }
+    }
+    /// 再生中の Phrase が Layout を作った時から変わっていたら作り直す(作り直したら true)
+    pub fn update(
+        &mut self,
+        vari: PhraseAs,
+        evts: &[PhrEvt],
+        whole_tick: i32,
+        tick_for_onemsr: i32,
+    ) -> bool {
+        let same = self.src.as_ref().is_some_and(|(v, w, t, p)| {
+            *v == vari && *w == whole_tick && *t == tick_for_onemsr && p == evts
+        });
+        if same {
+            return false;
+        }
+        *self = Self::new(evts, whole_tick, tick_for_onemsr);
+        self.src = Some((vari, whole_tick, tick_for_onemsr, evts.to_vec()));
+        true
+    }
+    fn ratio(tick: i32, whole_tick: i32) -> f32 {
+        tick as f32 / whole_tick as f32
+    }
+    /// MIDI note を (C-1 からの音度, #の有無) にする
+    fn diatonic(note: i32) -> (i32, bool) {
+        const STEP: [i32; 12] = [0, 0, 1, 1, 2, 3, 3, 4, 4, 5, 5, 6];
+        const SHARP: [bool; 12] = [
+            false, true, false, true, false, false, true, false, true, false, true, false,
+        ];
+        let pc = note.rem_euclid(12) as usize;
+        (note.div_euclid(12) * 7 + STEP[pc], SHARP[pc])
+    }
+    /// 音価を (音符の種類, 付点) にする。割り切れない長さは短い方に丸める
+    fn note_value(dur: i32) -> (usize, bool) {
+        for (i, vt) in Self::VALUE_TICK.iter().enumerate() {
+            if dur == vt * 3 / 2 {
+                return (i, true);
+            } else if dur >= *vt {
+                return (i, false);
+            }
+        }
+        (Self::VALUE_TICK.len() - 1, false)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    //*******************************************************************
+    //      Draw
+    //      (left, center_y): 五線の左端と中央線, width: 五線の長さ
+    //      crnt_tick: 今の Phrase 内の位置(負なら表示しない)
+    //*******************************************************************
+    pub fn disp(
+        &self,
+        draw: Draw,
+        font: nannou::text::Font,
+        (left, center_y, width): (f32, f32, f32),
+        color: (f32, f32, f32),
+        crnt_tick: i32,
+    ) {
+        const SPACE: f32 = 10.0; // 線の間隔
+        const HEAD_X: f32 = 60.0; // 音部記号の幅
+        const STEM_LEN: f32 = SPACE * 3.5;
+
+        let (r, g, b) = color;
+        let col = rgba(r, g, b, 1.0);
+        let bottom = center_y - SPACE * 2.0;
+        let step_y = |step: i32| bottom + step as f32 * SPACE / 2.0;
+        let area = width - HEAD_X - SPACE * 2.0;
+        let note_x = |x: f32| left + HEAD_X + x * area;
+
+        // 五線と音部記号
+        for i in 0..5 {
+            let y = bottom + i as f32 * SPACE;
+            draw.line()
+                .start(pt2(left, y))
+                .end(pt2(left + width, y))
+                .weight(1.0)
+                .color(col);
+        }
+        draw.text(if self.bass_clef { "F" } else { "G" })
+            .font(font.clone())
+            .font_size(36)
+            .color(col)
+            .center_justify()
+            .x_y(left + HEAD_X / 2.0, center_y)
+            .w_h(HEAD_X, SPACE * 5.0);
+
+        // 小節線
+        for bx in self.bars.iter() {
+            let x = note_x(*bx) - SPACE;
+            draw.line()
+                .start(pt2(x, bottom))
+                .end(pt2(x, bottom + SPACE * 4.0))
+                .weight(if *bx >= 1.0 { 3.0 } else { 1.0 })
+                .color(col);
+        }
+
+        // 音符
+        for grp in self.groups.iter() {
+            let x = note_x(grp.x);
+            let low = grp.heads.iter().map(|h| h.0).min().unwrap_or(0);
+            let high = grp.heads.iter().map(|h| h.0).max().unwrap_or(0);
+            for (step, sharp) in grp.heads.iter() {
+                let y = step_y(*step);
+                // 加線
+                let mut ledger = if *step < 0 { -2 } else { 10 };
+                while (*step < 0 && ledger >= *step) || (*step > 8 && ledger <= *step) {
+                    let ly = step_y(ledger);
+                    draw.line()
+                        .start(pt2(x - SPACE, ly))
+                        .end(pt2(x + SPACE, ly))
+                        .weight(1.0)
+                        .color(col);
+                    ledger += if *step < 0 { -2 } else { 2 };
+                }
+                let head = draw.ellipse().x_y(x, y).w_h(SPACE * 1.3, SPACE);
+                if grp.value >= 2 {
+                    head.color(col);
+                } else {
+                    head.no_fill().stroke(col).stroke_weight(1.5);
+                }
+                if *sharp {
+                    draw.text("#")
+                        .font(font.clone())
+                        .font_size(16)
+                        .color(col)
+                        .x_y(x - SPACE * 1.4, y)
+                        .w_h(SPACE * 2.0, SPACE * 2.0);
+                }
+                if grp.dot {
+                    draw.ellipse()
+                        .color(col)
+                        .x_y(x + SPACE * 1.2, y + SPACE / 4.0)
+                        .w_h(3.0, 3.0);
+                }
+            }
+            if grp.value == 0 {
+                continue;
+            }
+            // 符幹と旗: 真ん中の線より下なら上向き
+            let up = (low + high) < 8;
+            let (sx, sy, ey) = if up {
+                (x + SPACE * 0.6, step_y(low), step_y(high) + STEM_LEN)
+            } else {
+                (x - SPACE * 0.6, step_y(high), step_y(low) - STEM_LEN)
+            };
+            draw.line()
+                .start(pt2(sx, sy))
+                .end(pt2(sx, ey))
+                .weight(1.5)
+                .color(col);
+            let dir = if up { -1.0 } else { 1.0 };
+            for i in 0..grp.value.saturating_sub(2) {
+                let fy = ey + dir * i as f32 * SPACE * 0.8;
+                draw.line()
+                    .start(pt2(sx, fy))
+                    .end(pt2(sx + SPACE * 0.8, fy + dir * SPACE * 1.2))
+                    .weight(1.5)
+                    .color(col);
+            }
+        }
+
+        // 今の位置
+        if crnt_tick >= 0 && self.whole_tick > 0 {
+            let x = note_x(Self::ratio(crnt_tick % self.whole_tick, self.whole_tick));
+            draw.line()
+                .start(pt2(x, bottom - SPACE * 2.0))
+                .end(pt2(x, bottom + SPACE * 6.0))
+                .weight(2.0)
+                .color(rgba(1.0, 0.0, 1.0, 0.8));
+        }
+    }
+}
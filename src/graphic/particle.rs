@@ -0,0 +1,179 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use nannou::prelude::*;
+
+use super::draw_graph::Resize;
+use super::generative_view::*;
+use crate::lpnlib::*;
+
+//*******************************************************************
+//          Particle View Struct
+//*******************************************************************
+//  Note ごとに粒子を放ち、音高で位置、velocity で数と大きさ、
+//  パートで色と動き(重力・広がり)を変える
+pub struct ParticleView {
+    mode: GraphMode,
+    color: [(f32, f32, f32); MAX_COMPOSITION_PART], // パートごとの色
+    nobj: Vec<Box<dyn NoteObj>>,                    // Note Object
+}
+impl ParticleView {
+    const MAX_NOTE_OBJ: usize = 200; // これを超えたら古いものから消す
+
+    pub fn new(mode: GraphMode, color: [(f32, f32, f32); MAX_COMPOSITION_PART]) -> Self {
+        Self {
+            mode,
+            color,
+            nobj: Vec::new(),
+        }
+    }
+}
+impl GenerativeView for ParticleView {
+    fn update_model(&mut self, tm: f32, rs: Resize) {
+        // Note Object の更新と削除
+        self.nobj.retain_mut(|obj| obj.update_model(tm, rs.clone()));
+    }
+    /// Note 演奏情報を受け取る
+    fn note_on(&mut self, nt: i32, vel: i32, pt: i32, tm: f32) {
+        if self.nobj.len() >= Self::MAX_NOTE_OBJ {
+            self.nobj.remove(0);
+        }
+        let color = self.color[(pt as usize).min(MAX_COMPOSITION_PART - 1)];
+        self.nobj.push(Box::new(ParticleNote::new(
+            nt, vel, pt, color, tm, self.mode,
+        )));
+    }
+    /// Mode 情報を受け取る
+    fn set_mode(&mut self, mode: GraphMode) {
+        self.mode = mode;
+    }
+    fn disp(&self, draw: Draw, tm: f32, rs: Resize) {
+        for obj in self.nobj.iter() {
+            obj.disp(draw.clone(), tm, rs.clone());
+        }
+    }
+}
+
+//*******************************************************************
+//          Particle Note Struct
+//*******************************************************************
+//  一つの Note から放たれた粒子の集まり
+struct Particle {
+    pos: Vec2,
+    spd: Vec2,
+}
+pub struct ParticleNote {
+    ptcl: Vec<Particle>,
+    size: f32,
+    color: (f32, f32, f32),
+    physics: PartPhysics,
+    start_time: f32,
+    last_time: f32,
+    elapsed_time: f32,
+}
+#[derive(Clone, Copy)]
+struct PartPhysics {
+    gravity: f32, // 下向きの加速度(負なら上に昇る)
+    spread: f32,  // 最初の速さ
+    drag: f32,    // 1秒あたりの減速の割合
+}
+impl ParticleNote {
+    const DISAPPEAR_TIME: f32 = 3.0; // [sec]
+    const MAX_PARTICLE: f32 = 24.0;
+    //  パートごとの動き L1,L2:重く沈む R1,R2:軽く昇る Flow:大きく広がる
+    const PHYSICS: [PartPhysics; MAX_COMPOSITION_PART] = [
+        PartPhysics {
+            gravity: 120.0,
+            spread: 80.0,
+            drag: 0.6,
+        },
+        PartPhysics {
+            gravity: 80.0,
+            spread: 100.0,
+            drag: 0.6,
+        },
+        PartPhysics {
+            gravity: -40.0,
+            spread: 140.0,
+            drag: 0.8,
+        },
+        PartPhysics {
+            gravity: -60.0,
+            spread: 160.0,
+            drag: 0.8,
+        },
+        PartPhysics {
+            gravity: 0.0,
+            spread: 240.0,
+            drag: 0.9,
+        },
+    ];
+
+    pub fn new(
+        nt: i32,
+        vel: i32,
+        pt: i32,
+        color: (f32, f32, f32),
+        tm: f32,
+        mode: GraphMode,
+    ) -> Self {
+        let pidx = (pt as usize).min(Self::PHYSICS.len() - 1);
+        let physics = Self::PHYSICS[pidx];
+        let num = ((vel as f32 / 127.0) * Self::MAX_PARTICLE) as usize + 1;
+        // 音高で横位置を決める(88鍵を画面幅に)
+        let x = (nt as f32 - 21.0) / 87.0 - 0.5;
+        let ptcl = (0..num)
+            .map(|_| {
+                let angle = random_f32() * 2.0 * PI;
+                let spd = physics.spread * (0.3 + random_f32() * 0.7);
+                Particle {
+                    pos: vec2(x, 0.0),
+                    spd: vec2(angle.cos() * spd, angle.sin() * spd),
+                }
+            })
+            .collect();
+        let (r, g, b) = color;
+        let color = if mode == GraphMode::Dark {
+            (r, g, b)
+        } else {
+            (r * 0.7, g * 0.7, b * 0.7)
+        };
+        Self {
+            ptcl,
+            size: 3.0 + (vel as f32 / 127.0) * 9.0,
+            color,
+            physics,
+            start_time: tm,
+            last_time: tm,
+            elapsed_time: 0.0,
+        }
+    }
+}
+impl NoteObj for ParticleNote {
+    fn update_model(&mut self, crnt_time: f32, _rs: Resize) -> bool {
+        let dt = (crnt_time - self.last_time).max(0.0);
+        self.last_time = crnt_time;
+        self.elapsed_time = crnt_time - self.start_time;
+        let drag = (1.0 - self.physics.drag * dt).max(0.0);
+        for p in self.ptcl.iter_mut() {
+            p.spd.y -= self.physics.gravity * dt;
+            p.spd *= drag;
+            // x は画面幅に対する割合、y は pixel
+            p.pos.x += p.spd.x * dt / 1000.0;
+            p.pos.y += p.spd.y * dt;
+        }
+        self.elapsed_time <= Self::DISAPPEAR_TIME
+    }
+    fn disp(&self, draw: Draw, _crnt_time: f32, rs: Resize) {
+        let alpha = ((Self::DISAPPEAR_TIME - self.elapsed_time) / Self::DISAPPEAR_TIME).max(0.0);
+        let size = self.size * (0.5 + alpha * 0.5);
+        let (r, g, b) = self.color;
+        for p in self.ptcl.iter() {
+            draw.ellipse()
+                .color(rgba(r, g, b, alpha))
+                .x_y(p.pos.x * rs.get_full_size_x(), p.pos.y)
+                .w_h(size, size);
+        }
+    }
+}
@@ -20,6 +20,18 @@ pub const _KEEP: i32 = 0;
 pub const LAST: i32 = 10000;
 
 pub const NO_ROOT: i16 = 0; // root = 1:Ib,2:I,3:I# ...
+pub const NOTE_NAME: [&str; 12] = [
+    "C", "C#", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+pub const NOTE_NAME_SHARP: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+pub const NOTE_NAME_FLAT: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+];
+pub const SPELL_AUTO: i16 = 0; // key によって #/b を選ぶ
+pub const SPELL_SHARP: i16 = 1;
+pub const SPELL_FLAT: i16 = 2;
 pub const NO_TABLE: i16 = 10000;
 pub const _CANCEL: i16 = -1;
 pub const NOTHING: i16 = -1;
@@ -41,12 +53,16 @@ pub const MAX_COMPOSITION_PART: usize = MAX_KBD_PART + 1;
 pub const MAX_VARIATION: usize = 10; // normal + vari(1-9) + 1(for measure)
 pub const FLOW_PART: usize = MAX_KBD_PART;
 pub const DAMPER_PEDAL_PART: usize = MAX_KBD_PART + 1;
+pub const CLICK_PART: usize = MAX_KBD_PART + 2; // set.route の出力先を決めるための番号
 pub const NONE_NUM: usize = 255;
+pub const MAX_CHORD_PAD: usize = 16; // Flow の和音を記憶する pad の数
 
 //*******************************************************************
 //          default value
 //*******************************************************************
 pub const DEFAULT_BPM: i16 = 100;
+pub const MIN_BPM_VALUE: i16 = 1; // set.bpm で指定できる範囲
+pub const MAX_BPM_VALUE: i16 = 400;
 pub const DEFAULT_NOTE_NUMBER: u8 = 60; // C4
 pub const MAX_NOTE_NUMBER: u8 = 108; // C8
 pub const MIN_NOTE_NUMBER: u8 = 21; // A0
@@ -71,6 +87,9 @@ pub const _TYPE_ID: i16 = 1000; // for TYPE
 pub const TYPE_NOTE: i16 = 1001; // for index TYPE
 pub const TYPE_CLS: i16 = 1010;
 pub const TYPE_ARP: i16 = 1020;
+pub const TYPE_STRUM: i16 = 1030;
+pub const TYPE_PAD: i16 = 1040; // Composition から生成する持続音(音価を自動で縮めない)
+pub const TYPE_EUCLID: i16 = 1050; // n step に k 個の打点を均等に散らしたリズム
 pub const TYPE_INFO: i16 = 1090; // タイミングを持つ演奏以外の情報
 #[derive(Default, Clone, Debug, PartialEq, Eq)]
 pub struct PhrEvt {
@@ -79,15 +98,21 @@ pub struct PhrEvt {
     pub dur: i16, // duration
     pub note: i16,
     // TYPE_NOTE: note number
-    // TYPE_CLS/ARP:  -7..0..7: lowest note
+    // TYPE_CLS/ARP/EUCLID:  -7..0..7: lowest note
+    // TYPE_STRUM: 0-12: position(fret)
     // TYPE_INFO: RPT_HEAD
     pub vel: i16,  // velocity
     pub trns: i16, // translation
     // TYPE_CLS: number of notes: 2-5
-    // TYPE_ARP: u/d/xu/xd(0-3) figure of arpeggio
+    // TYPE_ARP: 下位4bit:音形(ARP_*), 上位:一巡する音数(0:制限なし)
+    // TYPE_STRUM: 2bit ごとの down/up/rest の並び(STRUM_*)
+    // TYPE_EUCLID: step 数, 打点の数, 回転, 鳴らす音(EUC_*)
     pub each_dur: i16, // each duration for special purpose
-    // TYPE_CLS/ARP: each note's duration
+    // TYPE_CLS/ARP/STRUM/EUCLID: each note's duration
     pub artic: i16, // 0..100..200[%] staccato/legato
+    pub orna: i16,  // TYPE_NOTE: 装飾(trill/mordent/turn)
+    pub ch: i16,    // TYPE_NOTE: 0: Part の MIDI ch, 1-16: 指定した MIDI ch
+    pub cond: i16,  // Loop の何回目で鳴らすか(COND_*, 1-:n回ごと)
 }
 impl PhrEvt {
     pub fn gen_repeat(tick: i16) -> Self {
@@ -100,9 +125,45 @@ impl PhrEvt {
             trns: TRNS_NONE,
             each_dur: 0,
             artic: 100,
+            orna: ORNA_NONE,
+            ch: 0,
+            cond: COND_ALWAYS,
         }
     }
 }
+/// for cond
+pub const COND_ALWAYS: i16 = 0;
+pub const COND_FIRST: i16 = -1; // 最初の一回目のみ
+pub const COND_LAST: i16 = -2; // 次の Phrase/Variation に移る前の最後のみ
+/// for TYPE_ARP (偶数は下から、奇数は上から始める)
+pub const ARP_UP: i16 = 0;
+pub const ARP_DOWN: i16 = 1;
+pub const ARP_UPDOWN: i16 = 2;
+pub const ARP_DOWNUP: i16 = 3;
+pub const ARP_RANDOM: i16 = 4;
+pub const ARP_TYPE_MASK: i16 = 0x0f;
+pub const ARP_VCE_SHIFT: i16 = 4;
+pub const ARP_MAX_VCE: i16 = 16;
+/// for TYPE_STRUM
+pub const STRUM_DOWN: i16 = 1;
+pub const STRUM_UP: i16 = 2;
+pub const STRUM_REST: i16 = 3;
+pub const STRUM_MAX_STEP: usize = 7;
+pub const STRUM_MAX_POSITION: i16 = 12;
+pub const ROLL_MAX_TICK: i16 = 120; // set.roll の上限(16分音符)
+/// for TYPE_EUCLID : 下位4bit:step数-1, 次の5bit:打点の数, 次の4bit:回転, 上位2bit:鳴らす音
+pub const EUC_MAX_STEP: i16 = 16;
+pub const EUC_PULSE_SHIFT: i16 = 4;
+pub const EUC_ROT_SHIFT: i16 = 9;
+pub const EUC_SRC_SHIFT: i16 = 13;
+pub const EUC_SRC_ROOT: i16 = 0; // 和音の root の単音
+pub const EUC_SRC_CLS: i16 = 1; // Cluster
+pub const EUC_SRC_ARP: i16 = 2; // 上昇 Arpeggio(打点ごとに進む)
+/// for orna
+pub const ORNA_NONE: i16 = 0;
+pub const ORNA_TRILL: i16 = 1;
+pub const ORNA_MORDENT: i16 = 2;
+pub const ORNA_TURN: i16 = 3;
 //-------------------------------------------------------------------
 // MSG_ANA
 /// for mtype
@@ -159,7 +220,8 @@ pub struct PhrData {
     pub evts: Vec<PhrEvt>,
     pub ana: Vec<AnaEvt>,
     pub vari: PhraseAs,
-    pub auftakt: i16, // 0:no auftakt, 1..:auftakt(beat number)
+    pub auftakt: i16,   // 0:no auftakt, 1..:auftakt(弱起の小節数)
+    pub tie_loop: bool, // 最後の音を次の Loop の最初の音にタイで繋ぐ
 }
 impl PhrData {
     pub fn empty() -> Self {
@@ -170,6 +232,7 @@ impl PhrData {
             ana: Vec::new(),
             vari: PhraseAs::Normal,
             auftakt: 0,
+            tie_loop: false,
         }
     }
 }
@@ -195,6 +258,7 @@ pub struct ChordData {
     pub evts: Vec<ChordEvt>,
     // how to start
     pub measure: i16, // NOTHING: no effect, 1..:measure number
+    pub vari: usize,  // 0:Normal, 1-9:Variation
 }
 impl ChordData {
     pub fn empty() -> Self {
@@ -203,6 +267,7 @@ impl ChordData {
             do_loop: true,
             evts: Vec::new(),
             measure: NOTHING,
+            vari: 0,
         }
     }
 }
@@ -213,6 +278,7 @@ pub struct DmprEvt {
     pub tick: i16,
     pub dur: i16,      // duration
     pub position: i16, // damper position
+    pub release: i16,  // 終わりに送る値(0:離す, それ以外:partial change)
 }
 impl DmprEvt {
     pub fn _new() -> Self {
@@ -221,6 +287,7 @@ impl DmprEvt {
             tick: 0,
             dur: 0,
             position: 0,
+            release: 0,
         }
     }
 }
@@ -231,15 +298,39 @@ pub enum ElpsMsg {
     Ctrl(i16),
     Sync(i16),
     Rit([i16; 2]),
+    RitChain(Vec<[i16; 2]>), // then で連結された rit./accel. を順に実行
     Set([i16; 2]),
     Efct([i16; 2]),
     //    SetBpm([i16; 3]),
     SetMeter([i16; 2]),
     //    SetKey([i16; 3]),
-    Phr(i16, PhrData),      //  Phr : part, (whole_tick,evts)
-    PhrX(i16),              //  PhrX : part
-    Cmp(i16, ChordData),    //  Cmp : part, (whole_tick,evts)
-    CmpX(i16),              //  CmpX : part
+    Phr(i16, PhrData),                //  Phr : part, (whole_tick,evts)
+    PhrX(i16),                        //  PhrX : part
+    Cmp(i16, ChordData),              //  Cmp : part, (whole_tick,evts)
+    CmpX(i16),                        //  CmpX : part
+    Practice([i16; 4]),               //  Practice : start_msr, end_msr, inc_bpm, repeat
+    LoopRgn([i16; 2]),                //  LoopRgn : start_msr, end_msr(ここで start_msr に戻る)
+    Punch([i16; 3]), //  Punch : start_msr, end_msr(ここで録音を止める), 音を入れる part
+    SetPart(i16, [i16; 2]), //  SetPart : part, [kind, value]
+    Listen([i16; 2]), //  Listen : sensitivity(0-100, -1:off), mode
+    Mute(i16, bool), //  Mute : part, on/off
+    Mask(i16, Vec<bool>), //  Mask : part, Loop の小節ごとに鳴らすか(空なら off)
+    Riser(i16, i16, Vec<i16>), //  Riser : part, 何小節ごとか(0:最初に戻す), 移調量の列(空なら off)
+    Reserve(i16, Box<ElpsMsg>), //  Reserve : msr(0origin), 指定小節の頭で実行する msg
+    Graph(GraphicMsg), //  Graph : Graphic へそのまま送る(Reserve と一緒に使う)
+    MsrText(i16, String), //  MsrText : msr(0origin), 小節に付ける歌詞やメモ(空なら削除)
+    Freeze(i16, i16), //  Freeze : part, 書き出す先の variation
+    Route(i16, u8, String), //  Route : part(Flow,Damper も含む), ch(1-16, 0:変えない), port(空なら通常)
+    SplitPort(i16, i16, [String; 2]), //  SplitPort : part, 境目の note(-1:off), [境目より下, 以上] の port(空なら part の port)
+    Reroll(i16, u32),                 //  Reroll : part(-1:全体), 次の小節の頭から使う乱数の種
+    ChordPad(bool, i16), //  ChordPad : true:記憶/false:呼び出し, pad 番号(1-16, 0:鳴らしている音を消す)
+    ExportSetup(String), //  ExportSetup : 設定の一覧を書き出すファイル名
+    TempoMap(i16, i16), //  TempoMap : msr(0origin, -1:全て消す), その小節の頭からの bpm(0:変化を消す)
+    TempoCtrl(i16),     //  TempoCtrl : 今のテンポに対する速さ[%](50-200, 100:元に戻す)
+    Scene(Vec<ElpsMsg>), //  Scene : 全パートの Data と bpm/key、次の小節の頭で揃えて切り替える
+    Trigger(i16, [i16; 3]), //  Trigger : event(TRIGGER_*, -1:全て消す), [note(-1:event の設定を消す), ch(1-16), velocity]
+    SetList(Vec<SetListEntry>), //  SetList : 順に演奏する scene(空なら off)
+    SetListCtrl(i16),       //  SetListCtrl : 移る scene の順番(0origin), SETLIST_NEXT
     MIDIRx(u8, u8, u8, u8), //  status, dt1, dt2, extra
 }
 //  Ctrl
@@ -251,6 +342,8 @@ pub const MSG_CTRL_PANIC: i16 = -13;
 pub const MSG_CTRL_RESUME: i16 = -12;
 pub const MSG_CTRL_CLEAR: i16 = -11; // Elapse Objectの内容をクリア
 pub const MSG_CTRL_MIDI_RECONNECT: i16 = -10;
+pub const MSG_CTRL_KEEP_THAT: i16 = -9; // 直近の MIDI 出力を SMF に書き出す
+pub const MSG_CTRL_COUNT_IN: i16 = -8; // 一小節前(-1)から start する
 pub const _MSG_CTRL_FLOW: i16 = 100; // 100-104
 pub const _MSG_CTRL_ENDFLOW: i16 = 110;
 //  Sync
@@ -258,10 +351,22 @@ pub const _MSG_CTRL_ENDFLOW: i16 = 110;
 pub const MSG_SYNC_LFT: i16 = 5;
 pub const MSG_SYNC_RGT: i16 = 6;
 pub const MSG_SYNC_ALL: i16 = 7;
+//  Trigger : one-shot の音を鳴らす event
+pub const TRIGGER_SECTION: i16 = 0; // scene が切り替わった小節の頭
+pub const TRIGGER_DOMINANT: i16 = 1; // 属和音に変わった時
+pub const TRIGGER_LOOP_END: i16 = 2; // Composition の Loop の最後の小節の頭
+pub const TRIGGER_MAX: usize = 3;
+//  Set List : (cmd 側の scene 番号, scene 名, 小節数, scene の msg)
+pub type SetListEntry = (usize, String, i16, Vec<ElpsMsg>);
+pub const SETLIST_NEXT: i16 = -1;
+//  Mask
+pub const MAX_MASK_MSR: usize = 16;
 //  Rit : rit.を１小節以上かける場合、1byte目に [小節数*10] を足す
 pub const MSG_RIT_NRM: i16 = 1;
 pub const MSG_RIT_POCO: i16 = 2;
 pub const MSG_RIT_MLT: i16 = 3;
+pub const MSG_RIT_ACCEL: i16 = 4; // accel.(到達テンポは msg[1])
+pub const MSG2_RIT_HOLD: i16 = 9998; // 到達したテンポのまま次へ(連結時)
 pub const MSG2_RIT_ATMP: i16 = 9999;
 pub const MSG2_RIT_FERMATA: i16 = 10000;
 //  Set
@@ -269,10 +374,116 @@ pub const MSG_SET_BPM: i16 = 1;
 pub const MSG_SET_KEY: i16 = 2;
 pub const MSG_SET_TURN: i16 = 3;
 pub const MSG_SET_CRNT_MSR: i16 = 4; // RESUME と一緒に使う
-                                     //  Set BEAT  : numerator, denomirator
-                                     //  Effect
+pub const MSG_SET_FLOW_PAD: i16 = 5; // 1:Flow の chord pad mode, 0:通常
+pub const MSG_SET_SONG_MSR: i16 = 6; // 曲の小節数(0:最長の loop の長さ)
+pub const MSG_SET_STATS: i16 = 7; // 1:stop/quit 時に演奏の統計を書き出す, 0:しない
+pub const MSG_SET_DISP_TRNS: i16 = 8; // 和音名を表示する時の移調(半音単位), DISP_TRNS_OFF:度数表示
+pub const DISP_TRNS_OFF: i16 = 100;
+pub const MSG_SET_SPELL: i16 = 9; // 音名表示の綴り SPELL_AUTO/SHARP/FLAT
+pub const MSG_SET_MODULATE: i16 = 10; // 転調(Reserve と一緒に使う) key(0-11) + MSG2_KEY_PIVOT
+pub const MSG2_KEY_PIVOT: i16 = 16; // 和音の変わり目まで、Composition の key を保つ
+pub const MSG_SET_PEDREC: i16 = 11; // 入力された cc64 の録音 PEDREC_ON/OFF/CLEAR
+pub const PEDREC_OFF: i16 = 0; // 録音終了、録音したペダルを loop する
+pub const PEDREC_ON: i16 = 1;
+pub const PEDREC_CLEAR: i16 = 2; // 録音したペダルを消し、自動 Damper に戻す
+pub const MSG_SET_TONE: i16 = 12; // 構成音/Tension/非和声音の分析 0-:構成音を強調する velocity, TONE_OFF
+pub const TONE_OFF: i16 = -1;
+pub const MSG_SET_CHORD_FIX: i16 = 13; // 再生中に来た和音で、拍頭から指定 tick 以内に鳴らした Flow の音を直す(0:off)
+pub const MSG_SET_MONITOR: i16 = 14; // MIDI Monitor の filter(MON_* の組み合わせ), MONITOR_OFF
+pub const MSG_SET_RATE: i16 = 15; // 再生速度[%](10-200)、bpm の表示は変えない
+pub const MSG_SET_DOUBLING: i16 = 16; // 同じ高さの音を省くパート(優先順に part+1 を3bitずつ) + DOUBLING_OCTAVE, -1:off
+pub const DOUBLING_OCTAVE: i16 = 0x1000; // 省かずにオクターブをずらす
+pub const MSG_SET_NOTENAME: i16 = 17; // 入力した音の名前を表示する NOTENAME_*
+pub const NOTENAME_OFF: i16 = 0;
+pub const NOTENAME_NAME: i16 = 1; // 音名とオクターブ(C4=60)
+pub const NOTENAME_DOREMI: i16 = 2; // key を Do とする移動ド
+pub const NOTENAME_FIXED: i16 = 3; // C を Do とする固定ド
+pub const NOTENAME_DEGREE: i16 = 4; // key からの度数
+pub const MSG_SET_RIT_TYPE: i16 = 18; // rit. の曲線 RIT_TYPE_*
+pub const RIT_TYPE_LINEAR: i16 = 0;
+pub const RIT_TYPE_PRECISE: i16 = 1;
+pub const RIT_TYPE_SIGMOID: i16 = 2;
+pub const MSG_SET_RIT_MIN: i16 = 19; // rit. で下回らない bpm(0:既定値)
+pub const MSG_SET_RIT_STEEP: i16 = 20; // Sigmoid の曲線の急峻さ[%](100-400)
+pub const MSG_SET_SUBST: i16 = 21; // 和音を置き換える確率[%] + 規則(SUBST_*)*256, -1:off
+pub const SUBST_TRITONE: i16 = 1;
+pub const SUBST_RELATIVE: i16 = 2;
+pub const MSG_SET_PED_RHYTHM: i16 = 22; // 1:和音の変わる速さでペダルの長さを変える, 0:off
+pub const MSG_SET_QUANTIZE: i16 = 23; // 強さ(0-100[%]) + grid(tick/60)<<8, 0:off
+pub const MSG_SET_NOTE_MAP_CH: i16 = 24; // [midi_map] の noteN として受ける MIDI ch(1-16), 0:off
+pub const MSG_SET_FADE: i16 = 25; // fade in する秒数(1-600), 負なら fade out して止める, 0:取り消す
+pub const MSG_SET_CLOCK: i16 = 26; // CLOCK_*
+pub const MSG_SET_EDIT_LOCK: i16 = 27; // 小節の最後の何拍の間、Phrase/Composition の変更を次の小節まで待たせるか(0:off)
+pub const MSG_SET_THRU: i16 = 28; // 1: Flow への入力を MIDI Rx thread からそのまま出力する, 0:off
+pub const MSG_SET_CLICK: i16 = 29; // 1: 拍ごとに click を鳴らす, 0:off
+pub const MSG_SET_CLICK_CH: i16 = 30; // click の MIDI ch(1-16)
+pub const MSG_SET_CLICK_NOTE: i16 = 31; // click の音: 二拍目以降 + 一拍目*256
+pub const MSG_SET_CONDUCT_CC: i16 = 32; // テンポを揺らす CC 番号(0-119), -1:off
+pub const MSG_SET_NUDGE: i16 = 33; // 一拍の間だけ速さをずらす[%](-10..10)
+pub const CLOCK_OFF: i16 = 0;
+pub const CLOCK_MASTER: i16 = 1; // MIDI Clock/Start/Stop を送る
+pub const CLOCK_SLAVE: i16 = 2; // 受信した MIDI Clock に合わせて演奏する
+pub const MONITOR_OFF: i16 = -1;
+pub const MON_IN: i16 = 0x0001;
+pub const MON_OUT: i16 = 0x0002;
+pub const MON_NOTE: i16 = 0x0004;
+pub const MON_CC: i16 = 0x0008;
+pub const MON_OTHER: i16 = 0x0010;
+pub const MON_PART: i16 = 0x0020; // MON_PART << part(0-5)
+pub const MON_ALL_PART: i16 = 0x07e0;
+//  Set BEAT  : numerator, denomirator
+//  Effect
 pub const MSG_EFCT_DMP: i16 = 1;
 pub const MSG_EFCT_CC70: i16 = 2;
+//  SetPart : part ごとの設定
+pub const MSG_SETPT_ROLL: i16 = 1; // Cluster の各音をずらす tick 数(0:同時)
+pub const MSG_SETPT_VELCC: i16 = 2; // velocity から生成する CC 番号(-1:off)
+pub const MSG_SETPT_VELCC_CURVE: i16 = 3; // 0:linear, 1:exp, 2:log
+pub const MSG_SETPT_VELCC_SMOOTH: i16 = 4; // 0-99[%] 前回値との平滑化
+pub const MSG_SETPT_KEYSW_NORM: i16 = 5; // keyswitch(-1:off, 0-127:note, 128-:CC)
+pub const MSG_SETPT_KEYSW_STACC: i16 = 6; // CC の時は 128 + cc番号*128 + 値
+pub const MSG_SETPT_KEYSW_LEGATO: i16 = 7;
+pub const MSG_SETPT_TUNE: i16 = 8; // 度数*256 + cent+128 (cent:-100..100), -1:平均律に戻す
+pub const MSG_SETPT_BEND_RANGE: i16 = 9; // pitch bend range(半音), 0:変えずに設定を送り直す
+pub const MSG_SETPT_VARI: i16 = 10; // 次の小節から再生する Variation(1-9)
+pub const MSG_SETPT_CMPS_VARI: i16 = 11; // 次の小節から再生する Composition の Variation(1-9)
+pub const MSG_SETPT_VARI_LINK: i16 = 12; // 1:Phrase と Composition の Variation を連動, 0:独立
+pub const MSG_SETPT_VARI_BEAT: i16 = 13; // 次の拍頭から再生する Variation(1-9)
+pub const MSG_SETPT_GLOBAL_PHASE: i16 = 14; // 1:新しい Phrase を小節0から続いていたように途中から, 0:頭から
+pub const MSG_SETPT_SPLIT: i16 = 15; // 音域で ch を分ける 境目の note + 下の ch*128 + 上の ch*2048 (ch:0-15), -1:off
+pub const MSG_SETPT_PAD: i16 = 16; // 和音を持続音で鳴らす PAD_OFF/PAD_REATTACK, 0-:legato で重ねる tick 数
+pub const PAD_OFF: i16 = -1;
+pub const PAD_REATTACK: i16 = -2; // 和音が変わるたびに全音を弾き直す
+pub const MSG_SETPT_CHORD_OUT: i16 = 17; // Phrase の代わりに和音を出力する MIDI ch(1-16), 0:off
+pub const MSG_SETPT_RAMP: i16 = 18; // Loop 頭の velocity[%](1-200) + 何 Loop かけるか(1-64)*256, -1:off
+pub const MSG_SETPT_RAMP_END: i16 = 19; // Loop 終わりの velocity[%](1-200)
+pub const MSG_SETPT_EVOLVE: i16 = 20; // Loop ごとに Cluster の声部を増やす上限(1-16), 0:off, EVOLVE_RESET
+pub const EVOLVE_RESET: i16 = -1; // 声部の数を元に戻し、また増やしていく
+pub const MSG_SETPT_QUANTIZE: i16 = 21; // MSG_SET_QUANTIZE と同じ形式, QUANTIZE_GLOBAL
+pub const QUANTIZE_GLOBAL: i16 = -1; // Part ごとの設定をやめ、全体の設定に従う
+pub const QUANTIZE_GRID: i32 = 60; // grid の単位 tick
+pub const MSG_SETPT_VEL_LIMIT: i16 = 22; // velocity の下限(1-127) + 上限(1-127)*128, -1:off
+pub const MSG_SETPT_AUTO_VARI: i16 = 23; // 属和音の小節 + 下属和音の小節*16 + Loop 最後の小節*256 で鳴らす Variation(1-9), 0:off
+pub const MSG_SETPT_SPEED: i16 = 24; // 全体の clock に対する速さの倍率 分子 + 分母*16 (1-8), 17:等速
+pub const MSG_SETPT_FILLER: i16 = 25; // Phrase が無い時に Composition から鳴らす伴奏 FILLER_*
+pub const FILLER_OFF: i16 = 0;
+pub const FILLER_CHORD: i16 = 1; // 全音符の和音
+pub const FILLER_ARP: i16 = 2; // 四分音符の上行 Arpeggio
+pub const MSG_SETPT_PAN: i16 = 26; // Note On の前に送る CC10 の値(0-127), -1:off
+pub const MSG_SETPT_PAN_PITCH: i16 = 27; // 音高で pan を広げる幅(1-64) + curve*128 (0:linear, 1:exp, 2:log), -1:off
+pub const MSG_SETPT_PAN_CENTER: i16 = 28; // pan が真ん中になる note
+
+//  Listen : mode
+pub const LISTEN_BEAT: i16 = 1; // 発音タイミングに追従
+pub const LISTEN_CHORD: i16 = 2; // 和音を推定
+pub const LISTEN_HOLD: i16 = 4; // 和音推定の固定/解除
+
+//  Practice : start_msr が 0 のとき、practice 終了
+pub const MSG_PRACTICE_OFF: i16 = 0;
+//  LoopRgn : start_msr が 0 のとき、loop 解除
+pub const MSG_LOOP_RGN_OFF: i16 = 0;
+//  Punch : start_msr が 0 のとき、punch 解除
+pub const MSG_PUNCH_OFF: i16 = 0;
 
 //*******************************************************************
 //          UI Message from Elapse thread
@@ -288,6 +499,23 @@ pub struct NoteUiEv {
     pub vel: u8,
     pub pt: u8,
 }
+/// これから鳴る予定の音(piano roll などの表示用)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UpcomingEv {
+    pub note: u8,  // 今の和音で変換した音(arpeggio などは実際と違うことがある)
+    pub tick: i32, // 送った時点からの tick 数
+    pub vel: u8,
+}
+/// 和音の種類(UI で色分けする)
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChordQuality {
+    #[default]
+    None, // 和音でない(scale, para など)
+    Major,
+    Minor,
+    Dominant,
+    Diminished,
+}
 #[derive(Default, Clone, Debug, PartialEq, Eq)]
 pub struct PartUi {
     pub exist: bool,
@@ -295,12 +523,17 @@ pub struct PartUi {
     pub all_msrs: i32,
     pub flow: bool,
     pub chord_name: String,
+    pub quality: ChordQuality,
+    pub vari: PhraseAs, // 再生中の Phrase
 }
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum GraphicEv {
     NoteEv(NoteUiEv),
     BeatEv(i32),
 }
+/// パートごとの 構成音/Tension/非和声音 の数
+pub const TONE_CLASS_NUM: usize = 3;
+pub type ToneStats = [[u32; TONE_CLASS_NUM]; MAX_COMPOSITION_PART];
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum UiMsg {
     NewMeasure,
@@ -311,6 +544,23 @@ pub enum UiMsg {
     PartUi(usize, PartUi),       // part_num
     NoteUi(NoteUiEv),
     ChangePtn(u8),
+    SongSel(u8),                            // 受信した Song Select
+    NoteMap(u8),                            // note_map ch で受信した Note On
+    PracticeUi(i32),                        // practice の現在の step(0:off)
+    TimeUi(i32, i32),                       // 経過時間[msec], 曲全体の推定時間[msec](0:不明)
+    ErrorUi(String),                        // Elapse 側で起きたエラー
+    KeyUi(u8),                              // 予約された転調が実行された(0-11)
+    GraphUi(GraphicMsg),                    // 予約された Graphic の変更が実行された
+    TextUi(String),                         // 小節に付けられた歌詞やメモ(空なら消す)
+    ToneUi(Option<ToneStats>),              // 前の小節の 構成音/Tension/非和声音 の数(None:非表示)
+    MonitorUi(Option<String>),              // MIDI Monitor の一行(None:非表示)
+    NoteNameUi(Option<String>),             // 入力した音の名前(None:非表示)
+    GestureUi(Option<(bool, i16, usize)>),  // 実行中の rit./accel.(accel か, 到達 bpm, 残りの数)
+    PunchUi(usize, Vec<PhrEvt>, i32), // part, punch で差し替えた Normal Phrase の音, whole_tick
+    FreezeUi(usize, i16, Vec<PhrEvt>, i32), // part, variation, 前の Loop で鳴った音, whole_tick
+    UpcomingUi(usize, Vec<UpcomingEv>), // part, これから鳴る音(近い順)
+    SceneUi(usize),                   // set list が切り替えた scene の番号
+    SetListUi(Option<String>),        // set list の今/次の scene(None:非表示)
 }
 //*******************************************************************
 //          Command Definition
@@ -329,7 +579,15 @@ pub enum GraphicMsg {
     RipplePattern,
     VoicePattern,
     LissajousPattern,
+    ParticlePattern,
     BeatLissaPattern(i32),
+    Metronome(bool),
+    FullScreen(i32),  // monitor 番号(1origin), 0:window に戻す
+    AudienceWin(i32), // 0:window で表示, 1-:monitor 番号で full screen, -1:非表示
+    Screenshot,
+    FrameRec(u32), // 何 frame ごとに PNG で保存するか, 0:終了
+    Notation(i32), // 譜面表示するパート, -1:非表示
+    PianoRoll(bool),
 }
 //-------------------------------------------------------------------
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -337,3 +595,51 @@ pub enum InputMode {
     Fixed,  // 階名のオクターブ位置は固定。絶対位置を指定
     Closer, // 次の階名は近い方のオクターブを選択。遠い方を指示する場合、+/-を使う。
 }
+//-------------------------------------------------------------------
+/// thread の終了を msec だけ待つ。時間内に終われば join して true を返す
+pub fn join_with_timeout<T>(hndl: std::thread::JoinHandle<T>, msec: u64) -> bool {
+    let limit = std::time::Instant::now() + std::time::Duration::from_millis(msec);
+    while !hndl.is_finished() {
+        if std::time::Instant::now() > limit {
+            return false;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    hndl.join().is_ok()
+}
+//-------------------------------------------------------------------
+/// 入力した音の名前を、NOTENAME_* の形式で返す(key:0-11)
+pub fn note_readout(note: u8, key: i16, spell: i16, mode: i16) -> String {
+    const SOLFEGE: [&str; 12] = [
+        "Do", "Di", "Re", "Ri", "Mi", "Fa", "Fi", "Sol", "Si", "La", "Li", "Ti",
+    ];
+    const SOLFEGE_FLAT: [&str; 12] = [
+        "Do", "Ra", "Re", "Me", "Mi", "Fa", "Se", "Sol", "Le", "La", "Te", "Ti",
+    ];
+    const DEGREE: [&str; 12] = [
+        "1", "b2", "2", "b3", "3", "4", "#4", "5", "b6", "6", "b7", "7",
+    ];
+    let pc = note as i16;
+    let degree = (pc - key).rem_euclid(12) as usize;
+    match mode {
+        NOTENAME_DOREMI if spell == SPELL_FLAT => SOLFEGE_FLAT[degree].to_string(),
+        NOTENAME_DOREMI => SOLFEGE[degree].to_string(),
+        NOTENAME_FIXED if spell == SPELL_FLAT => SOLFEGE_FLAT[pc as usize % 12].to_string(),
+        NOTENAME_FIXED => SOLFEGE[pc as usize % 12].to_string(),
+        NOTENAME_DEGREE => DEGREE[degree].to_string(),
+        _ => format!("{}{}", note_name(pc, key, spell), pc / 12 - 1),
+    }
+}
+/// 音名(0-11)を、key(0-11) と spell 指定に合わせた綴りで返す
+pub fn note_name(pc: i16, key: i16, spell: i16) -> &'static str {
+    let pc = pc.rem_euclid(12) as usize;
+    match spell {
+        SPELL_SHARP => NOTE_NAME_SHARP[pc],
+        SPELL_FLAT => NOTE_NAME_FLAT[pc],
+        _ => match key.rem_euclid(12) {
+            2 | 4 | 6 | 7 | 9 | 11 => NOTE_NAME_SHARP[pc], // G,D,A,E,B,F#
+            1 | 3 | 5 | 8 | 10 => NOTE_NAME_FLAT[pc],      // F,Bb,Eb,Ab,Db
+            _ => NOTE_NAME[pc],
+        },
+    }
+}
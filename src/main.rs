@@ -3,6 +3,7 @@
 //  Released under the MIT license
 //  https://opensource.org/licenses/mit-license.php
 //
+mod audio;
 mod cmd;
 mod elapse;
 mod file;
@@ -18,8 +19,10 @@ use std::sync::mpsc;
 use std::sync::mpsc::TryRecvError;
 use std::sync::mpsc::{Receiver, Sender};
 use std::thread;
+use std::thread::JoinHandle;
 
 use elapse::stack_elapse::ElapseStack;
+use file::capture::FrameCapture;
 use file::input_txt::InputText;
 use file::settings::Settings;
 use graphic::draw_graph::{Graphic, Resize};
@@ -59,36 +62,59 @@ fn main() {
 //*******************************************************************
 pub struct Model {
     ui_hndr: mpsc::Receiver<UiMsg>,
+    main_win: WindowId,
+    audience_win: Option<WindowId>,
+    capture: FrameCapture,
     itxt: InputText,
     graph: Graphic,
     guiev: GuiEv,
     // as you like
 }
 fn model(app: &App) -> Model {
-    let (txmsg, rxui) = gen_elapse_thread();
-    app.new_window().view(view).build().unwrap();
+    let (txmsg, rxui, elps_thread) = gen_elapse_thread();
+    let main_win = app.new_window().view(view).build().unwrap();
 
     // app に対する初期設定
     app.set_exit_on_escape(false);
-    let win = app.main_window();
-    let first_width = Settings::load_settings().window_size.window_x_default;
-    let first_height = Settings::load_settings().window_size.window_y_default;
-    win.set_title("Loopian");
-    win.set_inner_size_pixels(first_width, first_height);
+    if let Some(win) = app.window(main_win) {
+        let first_width = Settings::load_settings().window_size.window_x_default;
+        let first_height = Settings::load_settings().window_size.window_y_default;
+        win.set_title("Loopian");
+        win.set_inner_size_pixels(first_width, first_height);
+    }
+
+    // 起動時の引数による Window 設定
+    let (full, audience) = get_window_args();
+    let mut graph = Graphic::new(app);
+    if let Some(m) = full {
+        graph.graph_msg().push(GraphicMsg::FullScreen(m));
+    }
+    if let Some(m) = audience {
+        graph.graph_msg().push(GraphicMsg::AudienceWin(m));
+    }
+
+    // 起動時の引数で指定されたファイルを読み込む
+    let mut itxt = InputText::new(txmsg, elps_thread);
+    if let Some(fname) = get_load_arg() {
+        itxt.load_at_startup(&fname, graph.graph_msg());
+    }
 
     Model {
         ui_hndr: rxui,
-        itxt: InputText::new(txmsg),
-        graph: Graphic::new(app),
+        main_win,
+        audience_win: None,
+        capture: FrameCapture::new(),
+        itxt,
+        graph,
         guiev: GuiEv::new(true),
     }
 }
 /// GUI/CUI 両方から呼ばれる
-fn gen_elapse_thread() -> (Sender<ElpsMsg>, Receiver<UiMsg>) {
+fn gen_elapse_thread() -> (Sender<ElpsMsg>, Receiver<UiMsg>, JoinHandle<()>) {
     //  create new thread & channel
     let (txmsg, rxmsg) = mpsc::channel();
     let (txui, rxui) = mpsc::channel();
-    thread::spawn(move || {
+    let hndl = thread::spawn(move || {
         let mut est = ElapseStack::new(txui);
         loop {
             if est.periodic(rxmsg.try_recv()) {
@@ -96,14 +122,102 @@ fn gen_elapse_thread() -> (Sender<ElpsMsg>, Receiver<UiMsg>) {
             }
         }
     });
-    (txmsg, rxui)
+    (txmsg, rxui, hndl)
 }
 
 //*******************************************************************
 //      Update & Event
 //*******************************************************************
+/// --full=2 : 2番目の monitor で full screen
+/// --audience=1 : 観客用 Window を 1番目の monitor で full screen (=なしなら window)
+fn get_window_args() -> (Option<i32>, Option<i32>) {
+    let mut full = None;
+    let mut audience = None;
+    for arg in env::args() {
+        if let Some(num) = window_arg(&arg, "--full", 1) {
+            full = Some(num);
+        } else if let Some(num) = window_arg(&arg, "--audience", 0) {
+            audience = Some(num);
+        }
+    }
+    (full, audience)
+}
+/// arg が name そのものか name=N なら、N(読めなければ dflt) を返す
+fn window_arg(arg: &str, name: &str, dflt: i32) -> Option<i32> {
+    match arg.strip_prefix(name)? {
+        "" => Some(dflt),
+        prm => prm
+            .strip_prefix('=')
+            .map(|num| num.parse::<i32>().unwrap_or(dflt)),
+    }
+}
+/// --load=fname : 起動時に fname.lpn を読み込む
+fn get_load_arg() -> Option<String> {
+    env::args().find_map(|arg| {
+        arg.strip_prefix("--load=")
+            .filter(|f| !f.is_empty())
+            .map(|f| f.to_string())
+    })
+}
+fn set_fullscreen(app: &App, id: WindowId, monitor: i32) {
+    if let Some(win) = app.window(id) {
+        if monitor >= 1 {
+            let mon = app
+                .available_monitors()
+                .into_iter()
+                .nth(monitor as usize - 1);
+            if mon.is_none() {
+                println!("Monitor {} is not found!", monitor);
+            }
+            win.set_fullscreen_with(Some(Fullscreen::Borderless(mon)));
+        } else {
+            win.set_fullscreen_with(None);
+        }
+    }
+}
+/// Graphic からの Window 操作の要求を処理する
+fn apply_window_req(app: &App, model: &mut Model) {
+    for req in model.graph.take_win_req() {
+        match req {
+            GraphicMsg::FullScreen(m) => set_fullscreen(app, model.main_win, m),
+            GraphicMsg::AudienceWin(m) => {
+                if m < 0 {
+                    if let Some(win) = model.audience_win.and_then(|id| app.window(id)) {
+                        win.set_visible(false);
+                    }
+                    continue;
+                }
+                let id = match model.audience_win {
+                    Some(id) => id,
+                    None => match app
+                        .new_window()
+                        .title("Loopian Audience")
+                        .view(view_audience)
+                        .build()
+                    {
+                        Ok(id) => id,
+                        Err(e) => {
+                            println!("Audience Window can't open! {}", e);
+                            continue;
+                        }
+                    },
+                };
+                model.audience_win = Some(id);
+                if let Some(win) = app.window(id) {
+                    win.set_visible(true);
+                }
+                set_fullscreen(app, id, m);
+            }
+            GraphicMsg::Screenshot => model.capture.req_shot(),
+            GraphicMsg::FrameRec(n) => model.capture.set_rec(n),
+            _ => (),
+        }
+    }
+}
 fn update(app: &App, model: &mut Model, _update: Update) {
-    model.graph.set_rs(Resize::new(app));
+    if let Some(win) = app.window(model.main_win) {
+        model.graph.set_rs(Resize::new(win.rect()));
+    }
     let crnt_time = app.time;
 
     //  Read imformation from StackElapse
@@ -114,16 +228,53 @@ fn update(app: &App, model: &mut Model, _update: Update) {
         .itxt
         .auto_load_command(&model.guiev, model.graph.graph_msg());
 
+    // 時刻で予約されたコマンド
+    model.itxt.scheduled_command(model.graph.graph_msg());
+
+    // 他の奏者から届いたコマンド
+    model.itxt.collab_command(model.graph.graph_msg());
+
     //  Update Model
     model
         .graph
         .update_lpn_model(&mut model.guiev, &model.itxt, crnt_time);
 
+    // Full Screen, 観客用 Window, 画面保存
+    apply_window_req(app, model);
+    if let Some(fname) = model.capture.next_frame(model.guiev.during_play()) {
+        if let Some(win) = app.window(model.main_win) {
+            win.capture_frame(fname);
+        }
+    }
+
     // as you like
 }
 fn read_from_ui_hndr(model: &mut Model) {
     loop {
         match model.ui_hndr.try_recv() {
+            Ok(UiMsg::ErrorUi(err)) => model.itxt.put_error(err),
+            Ok(UiMsg::KeyUi(key)) => model.itxt.set_indicator_key(key),
+            Ok(UiMsg::SceneUi(idx)) => model.itxt.set_list_scene(idx),
+            Ok(UiMsg::GraphUi(gmsg)) => model.graph.graph_msg().push(gmsg),
+            Ok(UiMsg::ChangePtn(pc)) => model
+                .itxt
+                .midi_action(&format!("pc{}", pc), model.graph.graph_msg()),
+            Ok(UiMsg::SongSel(song)) => model
+                .itxt
+                .midi_action(&format!("song{}", song), model.graph.graph_msg()),
+            Ok(UiMsg::NoteMap(note)) => model
+                .itxt
+                .midi_action(&format!("note{}", note), model.graph.graph_msg()),
+            Ok(UiMsg::PunchUi(part, evts, whole)) => {
+                model
+                    .itxt
+                    .punch(part, &evts, whole, model.graph.graph_msg())
+            }
+            Ok(UiMsg::FreezeUi(part, vari, evts, whole)) => {
+                model
+                    .itxt
+                    .freeze(part, vari, &evts, whole, model.graph.graph_msg())
+            }
             Ok(msg) => {
                 let key = model.itxt.get_indicator_key_stock();
                 model.guiev.set_indicator(msg, key);
@@ -156,3 +307,15 @@ fn view(app: &App, model: &Model, frame: Frame) {
 
     draw.to_frame(app, &frame).unwrap();
 }
+/// 観客用 Window : 文字は出さず Graphic Pattern のみ
+fn view_audience(app: &App, model: &Model, frame: Frame) {
+    let draw = app.draw();
+    let tm = app.time;
+    draw.background().color(model.graph.get_bgcolor());
+    if let Some(win) = app.window(frame.window_id()) {
+        model
+            .graph
+            .view_audience(draw.clone(), tm, Resize::new(win.rect()));
+    }
+    draw.to_frame(app, &frame).unwrap();
+}
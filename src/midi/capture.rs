@@ -0,0 +1,54 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use crate::file::smf::SmfWriter;
+
+//*******************************************************************
+//          Midi Capture Struct
+//*******************************************************************
+//  MidiCapture の責務
+//  録音していなくても、直近 minutes 分(settings の [capture])の MIDI 出力を常に保持し、
+//  keep that コマンドで後から SMF に書き出す
+pub struct MidiCapture {
+    origin: Instant,
+    capture_msec: u64,
+    buf: VecDeque<(u64, u8, u8, u8)>, // msec, status, data1, data2
+}
+impl MidiCapture {
+    pub fn new(minutes: u64) -> Self {
+        Self {
+            origin: Instant::now(),
+            capture_msec: minutes * 60 * 1000,
+            buf: VecDeque::new(),
+        }
+    }
+    pub fn put(&mut self, crnt_time: Instant, status: u8, dt1: u8, dt2: u8) {
+        let msec = crnt_time.duration_since(self.origin).as_millis() as u64;
+        self.buf.push_back((msec, status, dt1, dt2));
+        // 古いイベントを捨てる
+        while let Some(ev) = self.buf.front() {
+            if msec - ev.0 > self.capture_msec {
+                self.buf.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+    /// 保持しているイベントを SMF に書き出し、書いたファイル名か、失敗の理由を返す
+    pub fn keep_that(&self, fname: String) -> Result<String, String> {
+        if self.buf.is_empty() {
+            return Err("Nothing to keep!".to_string());
+        }
+        SmfWriter::new()
+            .write(fname, &self.events())
+            .ok_or("Couldn't write the SMF!".to_string())
+    }
+    /// 保持しているイベント(msec, status, data1, data2)
+    pub fn events(&self) -> Vec<(u64, u8, u8, u8)> {
+        self.buf.iter().copied().collect()
+    }
+}
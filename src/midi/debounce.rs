@@ -0,0 +1,107 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use crate::file::settings::InputFilter;
+use std::collections::HashMap;
+
+//*******************************************************************
+//          Input Conditioner Struct
+//*******************************************************************
+//  安価な MIDI Controller のチャタリング対策
+//  Note: 同じ音の Note On が note_window[ms] 以内に続いたら省き、対応する Note Off も省く
+//  CC: 前回送った値との差が cc_threshold 未満、または cc_interval[ms] 以内の値は省く
+//      ただし 0 と 127 は必ず通す
+//      cc_interval で省いた最後の値は、cc_interval 後に flush() で送る
+pub struct InputConditioner {
+    note_window: u64, // [usec], 0:off
+    cc_threshold: u8, // 0:off
+    cc_interval: u64, // [usec], 0:off
+    last_on: HashMap<(u8, u8), u64>,
+    dropped: Vec<(u8, u8)>,
+    last_cc: HashMap<(u8, u8), (u64, u8)>,
+    pending_cc: HashMap<(u8, u8), u8>, // cc_interval で省いた最後の値
+}
+impl InputConditioner {
+    pub fn new(filter: &InputFilter) -> Self {
+        Self {
+            note_window: filter.note_window * 1000,
+            cc_threshold: filter.cc_threshold,
+            cc_interval: filter.cc_interval * 1000,
+            last_on: HashMap::new(),
+            dropped: Vec::new(),
+            last_cc: HashMap::new(),
+            pending_cc: HashMap::new(),
+        }
+    }
+    /// stamp: 受信時刻[usec]、通してよい message なら true
+    pub fn pass(&mut self, stamp: u64, msg: &[u8]) -> bool {
+        if msg.len() < 3 {
+            return true;
+        }
+        let key = (msg[0] & 0x0f, msg[1]);
+        match msg[0] & 0xf0 {
+            0x90 if msg[2] > 0 => {
+                if self.note_window == 0 {
+                    return true;
+                }
+                let chatter = self
+                    .last_on
+                    .get(&key)
+                    .is_some_and(|&tm| stamp.saturating_sub(tm) < self.note_window);
+                self.last_on.insert(key, stamp);
+                if chatter {
+                    self.dropped.push(key);
+                }
+                !chatter
+            }
+            0x80 | 0x90 => {
+                // 省いた Note On に対応する Note Off も省く
+                match self.dropped.iter().position(|&k| k == key) {
+                    Some(i) => {
+                        self.dropped.remove(i);
+                        false
+                    }
+                    None => true,
+                }
+            }
+            0xb0 => {
+                let value = msg[2];
+                if value != 0 && value != 127 {
+                    if let Some(&(tm, last)) = self.last_cc.get(&key) {
+                        if value.abs_diff(last) < self.cc_threshold {
+                            self.pending_cc.remove(&key);
+                            return false;
+                        }
+                        if stamp.saturating_sub(tm) < self.cc_interval {
+                            self.pending_cc.insert(key, value);
+                            return false;
+                        }
+                    }
+                }
+                self.pending_cc.remove(&key);
+                self.last_cc.insert(key, (stamp, value));
+                true
+            }
+            _ => true,
+        }
+    }
+    /// stamp: 今の時刻[usec]、cc_interval が過ぎた省いた CC を返す
+    pub fn flush(&mut self, stamp: u64) -> Vec<[u8; 3]> {
+        let mut msgs = Vec::new();
+        let interval = self.cc_interval;
+        let last_cc = &mut self.last_cc;
+        self.pending_cc.retain(|&(ch, cc), value| {
+            let due = last_cc
+                .get(&(ch, cc))
+                .is_none_or(|&(tm, _)| stamp.saturating_sub(tm) >= interval);
+            if due {
+                last_cc.insert((ch, cc), (stamp, *value));
+                msgs.push([0xb0 | ch, cc, *value]);
+            }
+            !due
+        });
+        msgs.sort();
+        msgs
+    }
+}
@@ -0,0 +1,87 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use crate::file::settings::LedSetting;
+use crate::lpnlib::*;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum LedMode {
+    Note,
+    Color,
+    SysEx,
+}
+//*******************************************************************
+//          Led Map Struct
+//*******************************************************************
+//  鳴らした音を、LED 付き鍵盤/ORBIT を光らせる message にする(doc/orbit.md 参照)
+//  Note  : ch の Note On/Off のみ(従来通り、default は ch.16)
+//  Color : Note On の前に、color_cc で Part の色番号を送る
+//  SysEx : F0 7D 4C 01 <LED番号(音域の最低音が0)> <色番号> <velocity(0:消灯)> F7
+pub struct LedMap {
+    mode: LedMode,
+    ch: u8, // 0-15
+    color_cc: u8,
+    color: [u8; Self::COLOR_NUM],
+    range: [(u8, u8); 2], // LED1, LED2 が受け持つ音域
+}
+impl LedMap {
+    const COLOR_NUM: usize = MAX_KBD_PART + 1;
+    const SYSEX_HEAD: [u8; 4] = [0xf0, 0x7d, 0x4c, 0x01]; // 7D: 非商用, 4C: 'L'
+
+    pub fn new(setting: &LedSetting) -> Self {
+        let mode = match setting.mode.as_str() {
+            "color" => LedMode::Color,
+            "sysex" => LedMode::SysEx,
+            _ => LedMode::Note,
+        };
+        let mut color = [0; Self::COLOR_NUM];
+        for (i, c) in color.iter_mut().enumerate() {
+            *c = setting.color.get(i).copied().unwrap_or(i as u8 + 1) & 0x7f;
+        }
+        let mut range = [(0, 127); 2];
+        for (i, r) in range.iter_mut().enumerate() {
+            if let Some([lo, hi]) = setting.range.get(i) {
+                *r = (*lo.min(hi), *hi.max(lo));
+            }
+        }
+        Self {
+            mode,
+            ch: setting.ch.clamp(1, 16) - 1,
+            color_cc: setting.color_cc & 0x7f,
+            color,
+            range,
+        }
+    }
+    /// device(0:LED1, 1:LED2) に送る message の列
+    /// part: 出力元の Part(None: 外部の Loopian など)
+    pub fn messages(
+        &self,
+        device: usize,
+        part: Option<usize>,
+        status: u8,
+        note: u8,
+        vel: u8,
+    ) -> Vec<Vec<u8>> {
+        let cmnd = status & 0xf0;
+        let (lo, hi) = self.range[device.min(1)];
+        if (cmnd != 0x90 && cmnd != 0x80) || note < lo || note > hi {
+            return Vec::new();
+        }
+        let vel = if cmnd == 0x80 { 0 } else { vel };
+        let color = self.color[part.unwrap_or(Self::COLOR_NUM - 1).min(Self::COLOR_NUM - 1)];
+        match self.mode {
+            LedMode::Note => vec![vec![cmnd | self.ch, note, vel]],
+            LedMode::Color if vel > 0 => vec![
+                vec![0xb0 | self.ch, self.color_cc, color],
+                vec![0x90 | self.ch, note, vel],
+            ],
+            LedMode::Color => vec![vec![cmnd | self.ch, note, 0]],
+            LedMode::SysEx => {
+                let mut msg = Self::SYSEX_HEAD.to_vec();
+                msg.extend_from_slice(&[note - lo, color, vel, 0xf7]);
+                vec![msg]
+            }
+        }
+    }
+}
@@ -5,12 +5,16 @@
 //
 extern crate midir;
 
-use crate::file::settings::Settings;
+use super::debounce::InputConditioner;
+use crate::file::settings::{InputFilter, Settings};
 use crate::lpnlib::*;
-use midir::{Ignore, MidiInput, MidiInputConnection, MidiInputPort};
+use midir::{
+    Ignore, MidiInput, MidiInputConnection, MidiInputPort, MidiOutput, MidiOutputConnection,
+};
 use std::sync::mpsc;
 use std::sync::mpsc::TryRecvError;
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 #[cfg(feature = "raspi")]
 use rppal::uart::{Parity, Uart};
@@ -50,10 +54,16 @@ pub struct MidiRx {
     _conn_in: [Option<MidiInputConnection<()>>; 2],
     mdr_buf: [Option<Arc<Mutex<MidiRxBuf>>>; 2],
     rx_cnct_num: [usize; 2],
+    conditioner: [InputConditioner; 2],
+    last_stamp: [Option<(u64, Instant)>; 2], // 最後に受信した時刻と、その時の Instant
     tx_hndr: mpsc::Sender<ElpsMsg>,
     midi_stream_status: u8,
     midi_stream_data1: u8,
     keynote: u8,
+    note_map_ch: u8,                    // ORBIT 以外に受信する ch(1-16), 0:off
+    clock_in: bool,                     // MIDI Clock などの Realtime Message を受信する
+    thru: Option<MidiOutputConnection>, // Flow への入力を、そのまま出力する
+    conduct_cc: Option<u8>,             // テンポを揺らす CC 番号(ch は問わない)
     #[cfg(feature = "raspi")]
     pub uart: Option<Uart>,
 }
@@ -63,10 +73,19 @@ impl MidiRx {
             _conn_in: [None, None],
             mdr_buf: [None, None],
             rx_cnct_num: [NONE_NUM, NONE_NUM],
+            conditioner: [
+                InputConditioner::new(&InputFilter::default()),
+                InputConditioner::new(&InputFilter::default()),
+            ],
+            last_stamp: [None, None],
             tx_hndr,
             midi_stream_status: INVALID,
             midi_stream_data1: INVALID,
             keynote: 0,
+            note_map_ch: 0,
+            clock_in: false,
+            thru: None,
+            conduct_cc: None,
             #[cfg(feature = "raspi")]
             uart: None,
         };
@@ -147,8 +166,17 @@ impl MidiRx {
         let mut ret_num = NONE_NUM;
         for (i, p) in in_ports.iter().enumerate() {
             let drv_name = midi_in.port_name(p).unwrap();
-            let dev_name = &Settings::load_settings().midi.midi_device;
+            let settings = Settings::load_settings();
+            let dev_name = &settings.midi.midi_device;
             if drv_name.contains(dev_name) && i != num_to_avoid {
+                // device ごとのチャタリング対策
+                let filter = settings
+                    .input_filter
+                    .iter()
+                    .find(|f| drv_name.contains(&f.device))
+                    .cloned()
+                    .unwrap_or_default();
+                self.conditioner[idx_num] = InputConditioner::new(&filter);
                 println!(
                     "<<Input Connected!>> No.{}: {} <as Flow{}>",
                     i,
@@ -181,6 +209,41 @@ impl MidiRx {
         }
         Ok(ret_num)
     }
+    /// Flow と同じ出力先に、thru 用の port を開く
+    fn connect_thru(&mut self, on: bool) {
+        self.thru = None;
+        if !on {
+            return;
+        }
+        let midi_out = Settings::load_settings().midi.midi_out;
+        if let Ok(driver) = MidiOutput::new("Loopian_thru") {
+            let port = driver
+                .ports()
+                .into_iter()
+                .find(|p| driver.port_name(p).is_ok_and(|n| n.contains(&midi_out)));
+            match port.map(|p| driver.connect(&p, "loopian_thru")) {
+                Some(Ok(c)) => {
+                    println!("<<Thru Connected!>> {}", midi_out);
+                    self.thru = Some(c);
+                }
+                _ => println!("Thru connection failed!"),
+            }
+        }
+    }
+    /// ORBIT(ch.12,13) からの Note を、和音で変換しない Flow の音(ch.1)にする
+    /// 4->21 A0, 91->108 C8
+    pub fn thru_msg(msg: &[u8]) -> Option<[u8; 3]> {
+        if msg.len() < 3 || msg[0] & 0xe0 != 0x80 || !(0x0b..=0x0c).contains(&(msg[0] & 0x0f)) {
+            return None;
+        }
+        (4..92)
+            .contains(&msg[1])
+            .then(|| [msg[0] & 0xf0, msg[1] + 17, msg[2]])
+    }
+    /// CC の値(0-127)を速さ[%]にする 0:50%, 64:100%, 127:約200%
+    pub fn conduct_ratio(value: u8) -> i16 {
+        (100.0 * 2.0_f32.powf((value as f32 - 64.0) / 64.0)).round() as i16
+    }
     fn send_msg_to_elapse(&self, msg: ElpsMsg) {
         if let Err(e) = self.tx_hndr.send(msg) {
             println!("Something happened on MPSC from MIDIRx! {}", e);
@@ -188,6 +251,7 @@ impl MidiRx {
     }
     pub fn periodic(&mut self, rx_ctrlmsg: Result<ElpsMsg, TryRecvError>) -> bool {
         self.receive_midi_event();
+        self.flush_conditioner();
         match rx_ctrlmsg {
             // 制御用メッセージ
             Ok(n) => {
@@ -203,6 +267,14 @@ impl MidiRx {
                     } else if m == MSG_CTRL_MIDI_RECONNECT {
                         let _b = self.set_connect();
                     }
+                } else if let ElpsMsg::Set([MSG_SET_NOTE_MAP_CH, ch]) = n {
+                    self.note_map_ch = ch as u8;
+                } else if let ElpsMsg::Set([MSG_SET_CLOCK, clk]) = n {
+                    self.clock_in = clk == CLOCK_SLAVE;
+                } else if let ElpsMsg::Set([MSG_SET_THRU, thru]) = n {
+                    self.connect_thru(thru != 0);
+                } else if let ElpsMsg::Set([MSG_SET_CONDUCT_CC, cc]) = n {
+                    self.conduct_cc = (0..120).contains(&cc).then_some(cc as u8);
                 }
             }
             Err(TryRecvError::Disconnected) => return true, // Wrong!
@@ -213,7 +285,8 @@ impl MidiRx {
     fn receive_midi_event(&mut self) {
         for i in 0..2 {
             if self.mdr_buf[i].is_some() {
-                if let Some(msg_ext) = self.mdr_buf[i].as_ref().unwrap().lock().unwrap().take() {
+                let taken = self.mdr_buf[i].as_ref().unwrap().lock().unwrap().take();
+                if let Some(msg_ext) = taken {
                     let msg = msg_ext.1;
                     #[cfg(feature = "verbose")]
                     {
@@ -229,15 +302,30 @@ impl MidiRx {
                         );
                     }
                     // midi ch=12,13 のみ受信 (Loopian::ORBIT)
+                    // Song Position/Select は ch に関係なく受信する
+                    // note_map の ch も受信する
+                    // slave mode の時は Clock/Start/Continue/Stop も受信する
+                    if msg.len() > 2 && msg[0] & 0xf0 == 0xb0 && Some(msg[1]) == self.conduct_cc {
+                        // テンポを揺らす CC は、どの ch からでも受信する
+                        self.send_msg_to_elapse(ElpsMsg::TempoCtrl(Self::conduct_ratio(msg[2])));
+                        return;
+                    }
                     let input_ch = msg[0] & 0x0f;
-                    if input_ch != 0x0b && input_ch != 0x0c {
+                    let realtime = self.clock_in && [0xf8, 0xfa, 0xfb, 0xfc].contains(&msg[0]);
+                    if !realtime
+                        && input_ch != 0x0b
+                        && input_ch != 0x0c
+                        && msg[0] != 0xf2
+                        && msg[0] != 0xf3
+                        && (msg[0] >= 0xf0 || input_ch + 1 != self.note_map_ch)
+                    {
                         return;
                     }
-                    if msg.len() == 2 {
-                        self.send_msg_to_elapse(ElpsMsg::MIDIRx(msg[0], msg[1], 0, 0));
-                    } else {
-                        self.send_msg_to_elapse(ElpsMsg::MIDIRx(msg[0], msg[1], msg[2], 0));
+                    self.last_stamp[i] = Some((msg_ext.0, Instant::now()));
+                    if !self.conditioner[i].pass(msg_ext.0, &msg) {
+                        return;
                     }
+                    self.forward_midi_event(&msg);
                 }
             }
         }
@@ -256,6 +344,30 @@ impl MidiRx {
             }
         }
     }
+    /// チャタリング対策で省いた CC のうち、送る時刻が来たものを送る
+    fn flush_conditioner(&mut self) {
+        for i in 0..2 {
+            if let Some((stamp, at)) = self.last_stamp[i] {
+                let now = stamp + at.elapsed().as_micros() as u64;
+                for msg in self.conditioner[i].flush(now) {
+                    self.forward_midi_event(&msg);
+                }
+            }
+        }
+    }
+    fn forward_midi_event(&mut self, msg: &[u8]) {
+        if let (Some(cnct), Some(out)) = (self.thru.as_mut(), Self::thru_msg(msg)) {
+            // Elapse thread を待たずに出力する
+            let _ = cnct.send(&out);
+        }
+        if msg.len() == 1 {
+            self.send_msg_to_elapse(ElpsMsg::MIDIRx(msg[0], 0, 0, 0));
+        } else if msg.len() == 2 {
+            self.send_msg_to_elapse(ElpsMsg::MIDIRx(msg[0], msg[1], 0, 0));
+        } else {
+            self.send_msg_to_elapse(ElpsMsg::MIDIRx(msg[0], msg[1], msg[2], 0));
+        }
+    }
     #[allow(dead_code)]
     fn parse_1byte_midi(&mut self, input_data: u8) {
         if input_data & 0x80 == 0x80 {
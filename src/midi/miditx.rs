@@ -5,6 +5,7 @@
 //
 extern crate midir;
 
+use super::led::LedMap;
 use crate::file::settings::Settings;
 use midir::{MidiOutput, /*MidiOutputPort,*/ MidiOutputConnection};
 
@@ -14,8 +15,17 @@ pub struct MidiTx {
     connection_tx_led1: Option<Box<MidiOutputConnection>>,
     connection_tx_led2: Option<Box<MidiOutputConnection>>,
     connection_ext_loopian: Option<Box<MidiOutputConnection>>,
+    ports: Vec<(String, Box<MidiOutputConnection>, u32)>, // set.route で Part ごとに繋いだ port
+    led: LedMap,
+    log: Option<Vec<(u8, u8, u8)>>, // mock の時、出力を記録する
+    send_fail: [u32; 4],            // 接続ごとに続けて送信に失敗した回数(FAIL_XX)
 }
 
+const FAIL_TX: usize = 0;
+const FAIL_LED1: usize = 1;
+const FAIL_LED2: usize = 2;
+const FAIL_EXT: usize = 3;
+
 impl MidiTx {
     // Port が二つとも見つからなければ、コネクトできなければエラーメッセージを返す
     pub fn connect() -> (Self, Option<String>) {
@@ -25,6 +35,10 @@ impl MidiTx {
             connection_tx_led1: None,
             connection_tx_led2: None,
             connection_ext_loopian: None,
+            ports: Vec::new(),
+            led: LedMap::new(&Settings::load_settings().led),
+            log: None,
+            send_fail: [0; 4],
         };
 
         // Get an output port (read from console if multiple are available)
@@ -124,34 +138,126 @@ impl MidiTx {
             (this, Some("port not connected!".into()))
         }
     }
-    pub fn midi_out(&mut self, status: u8, dt1: u8, dt2: u8, to_led: bool) {
+    /// MIDI 機器に繋がず、出力を記録するだけの MidiTx
+    #[cfg(test)]
+    pub fn mock() -> Self {
+        MidiTx {
+            tx_available: false,
+            connection_tx: None,
+            connection_tx_led1: None,
+            connection_tx_led2: None,
+            connection_ext_loopian: None,
+            ports: Vec::new(),
+            led: LedMap::new(&Default::default()),
+            log: Some(Vec::new()),
+            send_fail: [0; 4],
+        }
+    }
+    #[cfg(test)]
+    pub fn take_log(&mut self) -> Vec<(u8, u8, u8)> {
+        self.log.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+    /// 接続ごとに続けて送信に失敗した回数の最大(watchdog が使う)
+    pub fn send_fail(&self) -> u32 {
+        let ports = self.ports.iter().map(|(_, _, f)| *f);
+        self.send_fail
+            .iter()
+            .copied()
+            .chain(ports)
+            .max()
+            .unwrap_or(0)
+    }
+    fn send(cnct: &mut MidiOutputConnection, msg: &[u8], send_fail: &mut u32) {
+        if cnct.send(msg).is_ok() {
+            *send_fail = 0;
+        } else {
+            *send_fail = send_fail.saturating_add(1);
+        }
+    }
+    pub fn midi_out(&mut self, status: u8, dt1: u8, dt2: u8) {
+        if let Some(log) = self.log.as_mut() {
+            log.push((status, dt1, dt2));
+        }
         if !self.tx_available {
             return;
         }
         if let Some(cnct) = self.connection_tx.as_mut() {
-            let status_with_ch = status & 0xf0; // ch.1
-            let _ = cnct.send(&[status_with_ch, dt1, dt2]);
+            Self::send(cnct, &[status, dt1, dt2], &mut self.send_fail[FAIL_TX]);
+            // 下位4bit で ch を指定(通常は ch.1)
         }
         if let Some(cnct) = self.connection_ext_loopian.as_mut() {
             let status_with_ch = (status & 0xf0) + 10; // ch.11
-            let _ = cnct.send(&[status_with_ch, dt1, dt2]);
+            Self::send(
+                cnct,
+                &[status_with_ch, dt1, dt2],
+                &mut self.send_fail[FAIL_EXT],
+            );
         }
-        if to_led {
-            self.midi_out_for_led(status, dt1, dt2);
+    }
+    /// 名前に name を含む port に繋ぐ。既に繋いでいるか、繋げたら true
+    pub fn connect_port(&mut self, name: &str) -> bool {
+        if self.log.is_some() || self.ports.iter().any(|(n, _, _)| n == name) {
+            return true;
+        }
+        let driver = match MidiOutput::new("Loopian_tx") {
+            Ok(d) => d,
+            Err(_e) => return false,
+        };
+        let port = driver
+            .ports()
+            .into_iter()
+            .find(|p| driver.port_name(p).is_ok_and(|n| n.contains(name)));
+        match port.map(|p| driver.connect(&p, "loopian_tx4")) {
+            Some(Ok(c)) => {
+                println!("<<Output Connected!>> {} <as Route>", name);
+                self.ports.push((name.to_string(), Box::new(c), 0));
+                true
+            }
+            _ => false,
+        }
+    }
+    /// port: None なら通常の MIDI Out、Some なら connect_port() で繋いだ port に送る
+    pub fn midi_out_port(&mut self, port: Option<&str>, status: u8, dt1: u8, dt2: u8) {
+        let name = match port {
+            Some(n) => n,
+            None => return self.midi_out(status, dt1, dt2),
+        };
+        if let Some(log) = self.log.as_mut() {
+            log.push((status, dt1, dt2));
+        }
+        if let Some((_, cnct, fail)) = self.ports.iter_mut().find(|(n, _, _)| n == name) {
+            Self::send(cnct, &[status, dt1, dt2], fail);
         }
     }
-    pub fn midi_out_for_led(&mut self, status: u8, dt1: u8, dt2: u8) {
+    /// MIDI Clock などの System Realtime Message(1byte) を送る
+    pub fn midi_out_realtime(&mut self, status: u8) {
+        if let Some(log) = self.log.as_mut() {
+            log.push((status, 0, 0));
+        }
         if !self.tx_available {
             return;
         }
-        let midi_cmnd = status & 0xf0;
-        if midi_cmnd == 0x90 || midi_cmnd == 0x80 {
-            let status_with_ch = midi_cmnd | 0x0f; // ch.16
-            if let Some(cnctl) = self.connection_tx_led1.as_mut() {
-                let _ = cnctl.send(&[status_with_ch, dt1, dt2]);
-            }
-            if let Some(cnctl) = self.connection_tx_led2.as_mut() {
-                let _ = cnctl.send(&[status_with_ch, dt1, dt2]);
+        if let Some(cnct) = self.connection_tx.as_mut() {
+            Self::send(cnct, &[status], &mut self.send_fail[FAIL_TX]);
+        }
+        if let Some(cnct) = self.connection_ext_loopian.as_mut() {
+            Self::send(cnct, &[status], &mut self.send_fail[FAIL_EXT]);
+        }
+    }
+    /// part: 出力元の Part(None: 外部の Loopian など)、Part ごとの色で光らせる
+    pub fn midi_out_for_led(&mut self, part: Option<usize>, status: u8, dt1: u8, dt2: u8) {
+        if !self.tx_available {
+            return;
+        }
+        let leds = [
+            (&mut self.connection_tx_led1, FAIL_LED1),
+            (&mut self.connection_tx_led2, FAIL_LED2),
+        ];
+        for (i, (cnct, fail)) in leds.into_iter().enumerate() {
+            if let Some(cnctl) = cnct.as_mut() {
+                for msg in self.led.messages(i, part, status, dt1, dt2) {
+                    Self::send(cnctl, &msg, &mut self.send_fail[fail]);
+                }
             }
         }
     }
@@ -161,7 +267,11 @@ impl MidiTx {
         }
         if let Some(cnct) = self.connection_ext_loopian.as_mut() {
             let status_with_ch = (status & 0xf0) + 10; // ch.11
-            let _ = cnct.send(&[status_with_ch, dt1, dt2]);
+            Self::send(
+                cnct,
+                &[status_with_ch, dt1, dt2],
+                &mut self.send_fail[FAIL_EXT],
+            );
         }
     }
 }
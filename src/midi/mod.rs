@@ -1,2 +1,8 @@
+pub mod capture;
+pub mod debounce;
+pub mod led;
 pub mod midirx;
 pub mod miditx;
+pub mod monitor;
+pub mod route;
+pub mod watchdog;
@@ -0,0 +1,81 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use crate::lpnlib::*;
+
+//*******************************************************************
+//          Midi Monitor Struct
+//*******************************************************************
+//  送受信した MIDI message を、小節:tick と Part 名を付けた一行の文字列にする
+//  filter は MON_* の bit の組み合わせ(MSG_SET_MONITOR と同じ)
+//  方向/種類/Part の bit が一つも立っていなければ、その項目では絞らない
+pub struct MidiMonitor {
+    filter: i16, // MONITOR_OFF: off
+}
+impl MidiMonitor {
+    pub fn new() -> Self {
+        Self {
+            filter: MONITOR_OFF,
+        }
+    }
+    pub fn is_enable(&self) -> bool {
+        self.filter != MONITOR_OFF
+    }
+    pub fn set(&mut self, filter: i16) {
+        self.filter = filter;
+    }
+    /// filter を通れば、表示用の文字列を返す
+    /// part: 出力元の Part(None: 不明、または入力)
+    pub fn line(
+        &self,
+        input: bool,
+        (msr, tick): (i32, i32),
+        part: Option<usize>,
+        sts: u8,
+        dt1: u8,
+        dt2: u8,
+    ) -> Option<String> {
+        if !self.is_enable() {
+            return None;
+        }
+        let dir = if input { MON_IN } else { MON_OUT };
+        let kind = match sts & 0xf0 {
+            0x80 | 0x90 => MON_NOTE,
+            0xb0 => MON_CC,
+            _ => MON_OTHER,
+        };
+        let pbit = part.map_or(0, |p| MON_PART << p);
+        if !Self::pass(self.filter, MON_IN | MON_OUT, dir)
+            || !Self::pass(self.filter, MON_NOTE | MON_CC | MON_OTHER, kind)
+            || !Self::pass(self.filter, MON_ALL_PART, pbit)
+        {
+            return None;
+        }
+        Some(format!(
+            "{:03}:{:04} {} {} {:02x} {:3} {:3}",
+            msr + 1,
+            tick,
+            if input { "IN " } else { "OUT" },
+            Self::part_name(part),
+            sts,
+            dt1,
+            dt2
+        ))
+    }
+    fn pass(filter: i16, mask: i16, bit: i16) -> bool {
+        filter & mask == 0 || filter & bit != 0
+    }
+    fn part_name(part: Option<usize>) -> &'static str {
+        match part {
+            Some(LEFT1) => "L1",
+            Some(LEFT2) => "L2",
+            Some(RIGHT1) => "R1",
+            Some(RIGHT2) => "R2",
+            Some(FLOW_PART) => "FL",
+            Some(DAMPER_PEDAL_PART) => "DP",
+            Some(CLICK_PART) => "CK",
+            _ => "--",
+        }
+    }
+}
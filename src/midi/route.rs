@@ -0,0 +1,118 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use crate::lpnlib::*;
+
+//*******************************************************************
+//          MIDI Route Struct
+//*******************************************************************
+//  Part(L1,L2,R1,R2,Flow,Damper) ごとの出力先
+//  ch: 1-16 なら、Part の ch.1 の音をその ch で出す(split などで ch.2 の音は次の ch)、0:変えない
+//  port: 空なら通常の MIDI Out、それ以外は名前にその文字列を含む port に出す
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct PartRoute {
+    pub ch: u8,
+    pub port: String,
+}
+pub struct MidiRoute {
+    routes: Vec<PartRoute>,
+    held: Vec<(usize, u8, u8, PartRoute)>, // 鳴っている音の (part, ch, note, Note On の時の出力先)
+    split: Vec<Option<(u8, [String; 2])>>, // 音域で分けた時の (境目の note, [下, 以上] の port)
+}
+impl MidiRoute {
+    pub const MAX_PART: usize = CLICK_PART + 1;
+
+    pub fn new() -> Self {
+        Self {
+            routes: vec![PartRoute::default(); Self::MAX_PART],
+            held: Vec::new(),
+            split: vec![None; Self::MAX_PART],
+        }
+    }
+    pub fn set(&mut self, part: usize, route: PartRoute) {
+        if let Some(rt) = self.routes.get_mut(part) {
+            *rt = route;
+        }
+    }
+    /// 音域で分けた音を、それぞれの port に出す(None:解除)
+    pub fn set_split(&mut self, part: usize, split: Option<(u8, [String; 2])>) {
+        if let Some(sp) = self.split.get_mut(part) {
+            *sp = split;
+        }
+    }
+    pub fn get_split(&self, part: usize) -> Option<&(u8, [String; 2])> {
+        self.split.get(part).and_then(|sp| sp.as_ref())
+    }
+    pub fn get(&self, part: usize) -> Option<&PartRoute> {
+        self.routes.get(part)
+    }
+    /// part から出す status の ch を変え、出力先の port(None:通常)を返す
+    pub fn apply(&self, part: Option<usize>, status: u8) -> (u8, Option<&str>) {
+        match part.and_then(|p| self.routes.get(p)) {
+            Some(rt) => Self::route_status(rt, status),
+            None => (status, None),
+        }
+    }
+    /// apply と同じだが、Note Off は Note On の時の出力先に送る
+    /// 音が鳴っている間に出力先を変えても、前の出力先の音を止められる
+    pub fn apply_note(
+        &mut self,
+        part: Option<usize>,
+        status: u8,
+        note: u8,
+        vel: u8,
+    ) -> (u8, Option<String>) {
+        let pt = match part {
+            Some(p) if p < self.routes.len() && (status & 0xe0) == 0x80 => p,
+            _ => {
+                let (sts, port) = self.apply(part, status);
+                return (sts, port.map(|p| p.to_string()));
+            }
+        };
+        let key = (pt, status & 0x0f, note);
+        let idx = self.held.iter().position(|h| (h.0, h.1, h.2) == key);
+        let rt = if (status & 0xf0) == 0x90 && vel > 0 {
+            let mut rt = self.routes[pt].clone();
+            if let Some((border, ports)) = &self.split[pt] {
+                let port = &ports[(note >= *border) as usize];
+                if !port.is_empty() {
+                    rt.port = port.clone();
+                }
+            }
+            match idx {
+                Some(i) => self.held[i].3 = rt.clone(),
+                None => self.held.push((key.0, key.1, key.2, rt.clone())),
+            }
+            rt
+        } else {
+            match idx {
+                Some(i) => self.held.remove(i).3,
+                None => self.routes[pt].clone(),
+            }
+        };
+        let (sts, port) = Self::route_status(&rt, status);
+        (sts, port.map(|p| p.to_string()))
+    }
+    fn route_status(rt: &PartRoute, status: u8) -> (u8, Option<&str>) {
+        let status = if rt.ch > 0 && (0x80..0xf0).contains(&status) {
+            let ch = ((status & 0x0f) + rt.ch - 1) % 16;
+            (status & 0xf0) | ch
+        } else {
+            status
+        };
+        let port = (!rt.port.is_empty()).then_some(rt.port.as_str());
+        (status, port)
+    }
+    /// 通常の MIDI Out 以外に使っている port(重複なし)
+    pub fn ports(&self) -> Vec<String> {
+        let mut ports: Vec<String> = Vec::new();
+        let split = self.split.iter().flatten().flat_map(|(_, p)| p.iter());
+        for port in self.routes.iter().map(|rt| &rt.port).chain(split) {
+            if !port.is_empty() && !ports.contains(port) {
+                ports.push(port.clone());
+            }
+        }
+        ports
+    }
+}
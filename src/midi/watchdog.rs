@@ -0,0 +1,69 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use std::time::{Duration, Instant};
+
+//*******************************************************************
+//          Midi Watchdog Struct
+//*******************************************************************
+//  長時間の無人運転のため、MIDI Rx thread と MIDI Out の状態を定期的に調べる
+//  Rx thread が止まっていたら作り直し、送信が続けて失敗したら繋ぎ直す
+//  障害と回復は、状態が変わった時だけ報告する
+//  繋ぎ直しは時間がかかるので、失敗するたびに次に試すまでの間隔を倍にする
+pub struct MidiWatchdog {
+    next_check: Option<Instant>,
+    tx_down: bool,
+    retry_at: Option<Instant>, // 次に MIDI Out を繋ぎ直してよい時刻
+    backoff: u64,              // [msec]
+}
+impl MidiWatchdog {
+    const CHECK_MSEC: u64 = 2000;
+    const MAX_BACKOFF_MSEC: u64 = 60000;
+    /// これ以上続けて送信に失敗したら、MIDI Out を繋ぎ直す
+    pub const MAX_SEND_FAIL: u32 = 10;
+
+    pub fn new() -> Self {
+        Self {
+            next_check: None,
+            tx_down: false,
+            retry_at: None,
+            backoff: 0,
+        }
+    }
+    /// 調べる時刻になったら true
+    pub fn is_time(&mut self, now: Instant) -> bool {
+        match self.next_check {
+            Some(tm) if now < tm => false,
+            _ => {
+                self.next_check = Some(now + Duration::from_millis(Self::CHECK_MSEC));
+                true
+            }
+        }
+    }
+    /// MIDI Out を繋ぎ直してよい時刻なら true
+    pub fn may_reconnect(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|tm| now >= tm)
+    }
+    /// 繋ぎ直せなかったので、次に試すまでの間隔を倍にする
+    pub fn reconnect_failed(&mut self, now: Instant) {
+        self.backoff = (self.backoff * 2).clamp(Self::CHECK_MSEC, Self::MAX_BACKOFF_MSEC);
+        self.retry_at = Some(now + Duration::from_millis(self.backoff));
+    }
+    /// MIDI Out の状態を記録し、変わった時だけ報告する文を返す
+    pub fn tx_state(&mut self, down: bool) -> Option<&'static str> {
+        if !down {
+            self.retry_at = None;
+            self.backoff = 0;
+        }
+        if self.tx_down == down {
+            return None;
+        }
+        self.tx_down = down;
+        if down {
+            Some("MIDI Out failed! Reconnecting...")
+        } else {
+            Some("MIDI Out reconnected.")
+        }
+    }
+}
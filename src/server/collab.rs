@@ -0,0 +1,361 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+use crate::cmd::cmdparse::LoopianCmd;
+use crate::lpnlib::*;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/// この loopian を操作している人の名前
+pub const LOCAL_NAME: &str = "local";
+const PART_NAME: [&str; MAX_KBD_PART] = ["L1", "L2", "R1", "R2"];
+/// パートを書かなければ、全パートを書き換えるコマンド
+const ALL_PART_CMD: [&str; 5] = ["clear", "load", "panic", "scene", "setlist"];
+
+//*******************************************************************
+//          Part Owner Struct
+//*******************************************************************
+//  パートごとの持ち主。持ち主のいるパートは、その人のコマンドでしか変えられない
+//  持ち主のいないパートは誰でも変えられる
+pub struct PartOwner {
+    owner: [Option<String>; MAX_KBD_PART],
+}
+impl PartOwner {
+    pub fn new() -> Self {
+        Self {
+            owner: Default::default(),
+        }
+    }
+    pub fn get(&self, part: usize) -> Option<&str> {
+        self.owner.get(part).and_then(|o| o.as_deref())
+    }
+    pub fn can_edit(&self, part: usize, name: &str) -> bool {
+        self.get(part).is_none_or(|o| o == name)
+    }
+    pub fn own(&mut self, part: usize, name: &str) -> Result<String, String> {
+        match self.get(part) {
+            Some(o) if o != name => Err(format!("{} is owned by {}.", PART_NAME[part], o)),
+            _ => {
+                self.owner[part] = Some(name.to_string());
+                Ok(format!("{} is yours.", PART_NAME[part]))
+            }
+        }
+    }
+    /// 持ち主か、この loopian の奏者なら手放せる
+    pub fn release(&mut self, part: usize, name: &str) -> Result<String, String> {
+        if self.get(part).is_some() && !self.can_edit(part, name) && name != LOCAL_NAME {
+            return Err(format!("{} is not yours.", PART_NAME[part]));
+        }
+        self.owner[part] = None;
+        Ok(format!("{} is released.", PART_NAME[part]))
+    }
+    pub fn give(&mut self, part: usize, from: &str, to: &str) -> Result<String, String> {
+        if self.get(part) != Some(from) {
+            return Err(format!("{} is not yours.", PART_NAME[part]));
+        }
+        self.owner[part] = Some(to.to_string());
+        Ok(format!("{} is given to {}.", PART_NAME[part], to))
+    }
+    /// 切断した人のパートを手放す
+    pub fn release_all(&mut self, name: &str) {
+        for ow in self.owner.iter_mut() {
+            if ow.as_deref() == Some(name) {
+                *ow = None;
+            }
+        }
+    }
+    pub fn list(&self) -> String {
+        (0..MAX_KBD_PART)
+            .map(|i| format!("{}:{}", PART_NAME[i], self.get(i).unwrap_or("-")))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+/// L1.[..] / [..] / {..} / @1=[..] の対象のパート(crnt:今の入力パート)
+/// set.route(R1,ch=3) のように、引数に書いたパートも対象
+/// clear, scene A などパートを書かずに全パートを書き換えるコマンドは、全パート
+/// パートに依らないコマンド(play, set.bpm, @m17 など)は空
+pub fn target_parts(cmd: &str, crnt: usize) -> Vec<usize> {
+    if let Some(pt) = cmd
+        .split_once('.')
+        .and_then(|(p, _)| LoopianCmd::detect_part(p))
+    {
+        return vec![pt];
+    }
+    let reserve = cmd
+        .strip_prefix("@m")
+        .is_some_and(|r| r.starts_with(|c: char| c.is_ascii_digit()));
+    if reserve {
+        return Vec::new();
+    } else if cmd.starts_with('[') || cmd.starts_with('{') || cmd.starts_with('@') {
+        return vec![crnt];
+    }
+    let mut parts: Vec<usize> = cmd
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter_map(LoopianCmd::detect_part)
+        .collect();
+    parts.sort();
+    parts.dedup();
+    let words: Vec<&str> = cmd.split_whitespace().collect();
+    let all_part =
+        words.first().is_some_and(|w| ALL_PART_CMD.contains(w)) && words.get(1) != Some(&"save");
+    if parts.is_empty() && all_part {
+        return (0..MAX_KBD_PART).collect();
+    }
+    parts
+}
+
+//*******************************************************************
+//          Collab User Struct
+//*******************************************************************
+//  繋いできた奏者の状態と、一行ごとの protocol の処理
+//  hello 名前 [token] / own R1 / release R1 / give R1 名前 / who
+//  それ以外の行は、自分のパートを今の入力パートにして実行するコマンド
+#[derive(Default)]
+pub struct CollabUser {
+    pub name: Option<String>,
+    pub part: Option<usize>,
+    token: String, // hello に必要な token(空なら不要)
+}
+#[derive(Debug, PartialEq, Eq)]
+pub enum CollabLine {
+    Reply(String),
+    Command(usize, String), // 入力パート, コマンド
+}
+impl CollabUser {
+    pub fn with_token(token: &str) -> Self {
+        Self {
+            token: token.to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn parse_line(&mut self, owner: &mut PartOwner, line: &str) -> CollabLine {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            return CollabLine::Reply(String::new());
+        }
+        if words[0] == "hello" {
+            let token = words.get(2).copied().unwrap_or("");
+            if token != self.token {
+                return CollabLine::Reply("Token is wrong.".to_string());
+            }
+            return match words.get(1) {
+                Some(&n) if n != LOCAL_NAME => {
+                    self.name = Some(n.to_string());
+                    CollabLine::Reply(format!("welcome {}", n))
+                }
+                _ => CollabLine::Reply("what?".to_string()),
+            };
+        }
+        let name = match self.name.clone() {
+            Some(n) => n,
+            None => return CollabLine::Reply("Say hello first.".to_string()),
+        };
+        let part = words.get(1).and_then(|p| LoopianCmd::detect_part(p));
+        let rtn = match (words[0], part) {
+            ("who", _) => Ok(owner.list()),
+            ("own", Some(pt)) => owner.own(pt, &name).inspect(|_| self.part = Some(pt)),
+            ("release", Some(pt)) => owner.release(pt, &name),
+            ("give", Some(pt)) => match words.get(2) {
+                Some(to) => owner.give(pt, &name, to),
+                None => Err("what?".to_string()),
+            },
+            ("own", None) | ("release", None) | ("give", None) => Err("what?".to_string()),
+            _ => return self.command(owner, &name, line.trim()),
+        };
+        if self.part.is_some_and(|pt| !owner.can_edit(pt, &name)) {
+            self.part = None;
+        }
+        CollabLine::Reply(rtn.unwrap_or_else(|e| e))
+    }
+    fn command(&mut self, owner: &PartOwner, name: &str, cmd: &str) -> CollabLine {
+        if cmd.starts_with('!') {
+            return CollabLine::Reply("what?".to_string());
+        }
+        let crnt = match self.part {
+            Some(pt) if owner.get(pt) == Some(name) => pt,
+            _ => return CollabLine::Reply("Own a part first.".to_string()),
+        };
+        if let Some(pt) = LoopianCmd::detect_part(cmd) {
+            // パートの切り替えは、この人の入力パートだけ変える
+            return if owner.get(pt) == Some(name) {
+                self.part = Some(pt);
+                CollabLine::Reply(format!("Changed current part to {}.", PART_NAME[pt]))
+            } else {
+                CollabLine::Reply(format!("{} is not yours.", PART_NAME[pt]))
+            };
+        }
+        match target_parts(cmd, crnt)
+            .into_iter()
+            .find(|&pt| owner.get(pt) != Some(name))
+        {
+            Some(pt) => CollabLine::Reply(format!("{} is not yours.", PART_NAME[pt])),
+            None => CollabLine::Command(crnt, cmd.to_string()),
+        }
+    }
+}
+
+//*******************************************************************
+//          Collab Server Struct
+//*******************************************************************
+//  TCP で待ち受け、繋いできた奏者から一行ずつコマンドを受け取る
+//  bind は待ち受ける address(既定値は 127.0.0.1)。token を決めると、hello で同じ token が要る
+pub struct CollabReq {
+    pub client: usize,
+    pub name: String,
+    pub part: usize,
+    pub cmd: String,
+}
+struct Client {
+    stream: TcpStream,
+    user: CollabUser,
+    buf: Vec<u8>,
+    alive: bool,
+}
+pub struct CollabServer {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    owner: PartOwner,
+    token: String,
+}
+impl CollabServer {
+    pub fn new(bind: &str, port: u16, token: &str) -> Option<Self> {
+        let listener = TcpListener::bind((bind, port)).ok()?;
+        listener.set_nonblocking(true).ok()?;
+        println!("<<Collab Server>> listening on {}:{}", bind, port);
+        Some(Self {
+            listener,
+            clients: Vec::new(),
+            owner: PartOwner::new(),
+            token: token.to_string(),
+        })
+    }
+    pub fn owner(&self) -> &PartOwner {
+        &self.owner
+    }
+    pub fn owner_mut(&mut self) -> &mut PartOwner {
+        &mut self.owner
+    }
+    /// 新しい接続を受け、届いた行を処理して、実行するコマンドを返す
+    pub fn poll(&mut self) -> Vec<CollabReq> {
+        while let Ok((stream, addr)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                println!("<<Collab Server>> connected from {}", addr);
+                self.clients.push(Client {
+                    stream,
+                    user: CollabUser::with_token(&self.token),
+                    buf: Vec::new(),
+                    alive: true,
+                });
+            }
+        }
+        for cl in self.clients.iter_mut().filter(|c| !c.alive) {
+            if let Some(name) = cl.user.name.as_ref() {
+                self.owner.release_all(name);
+            }
+        }
+        self.clients.retain(|c| c.alive);
+
+        let mut reqs = Vec::new();
+        for (i, cl) in self.clients.iter_mut().enumerate() {
+            for line in Self::read_lines(cl) {
+                match cl.user.parse_line(&mut self.owner, &line) {
+                    CollabLine::Reply(txt) => Self::send(cl, &txt),
+                    CollabLine::Command(part, cmd) => reqs.push(CollabReq {
+                        client: i,
+                        name: cl.user.name.clone().unwrap_or_default(),
+                        part,
+                        cmd,
+                    }),
+                }
+            }
+        }
+        reqs
+    }
+    pub fn reply(&mut self, client: usize, txt: &str) {
+        if let Some(cl) = self.clients.get_mut(client) {
+            Self::send(cl, txt);
+        }
+    }
+    fn read_lines(cl: &mut Client) -> Vec<String> {
+        let mut tmp = [0u8; 1024];
+        loop {
+            match cl.stream.read(&mut tmp) {
+                Ok(0) => {
+                    cl.alive = false;
+                    break;
+                }
+                Ok(n) => cl.buf.extend_from_slice(&tmp[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    cl.alive = false;
+                    break;
+                }
+            }
+        }
+        let mut lines = Vec::new();
+        while let Some(pos) = cl.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = cl.buf.drain(..=pos).collect();
+            lines.push(String::from_utf8_lossy(&line).trim().to_string());
+        }
+        lines
+    }
+    fn send(cl: &mut Client, txt: &str) {
+        if !txt.is_empty()
+            && cl
+                .stream
+                .write_all(format!("{}\n", txt).as_bytes())
+                .is_err()
+        {
+            cl.alive = false;
+        }
+    }
+}
+
+//*******************************************************************
+//          Collab Client Struct
+//*******************************************************************
+//  他の loopian に繋ぎ、入力したコマンドを送って、返答を受け取る
+pub struct CollabClient {
+    stream: TcpStream,
+    buf: Vec<u8>,
+}
+impl CollabClient {
+    pub fn connect(addr: &str, name: &str, token: &str) -> Option<Self> {
+        let mut stream = TcpStream::connect(addr).ok()?;
+        stream.set_nonblocking(true).ok()?;
+        let hello = if token.is_empty() {
+            format!("hello {}\n", name)
+        } else {
+            format!("hello {} {}\n", name, token)
+        };
+        stream.write_all(hello.as_bytes()).ok()?;
+        println!("<<Collab Client>> connected to {} as {}", addr, name);
+        Some(Self {
+            stream,
+            buf: Vec::new(),
+        })
+    }
+    pub fn send(&mut self, line: &str) -> bool {
+        self.stream
+            .write_all(format!("{}\n", line).as_bytes())
+            .is_ok()
+    }
+    /// 届いた返答の行
+    pub fn poll(&mut self) -> Vec<String> {
+        let mut tmp = [0u8; 1024];
+        while let Ok(n) = self.stream.read(&mut tmp) {
+            if n == 0 {
+                break;
+            }
+            self.buf.extend_from_slice(&tmp[..n]);
+        }
+        let mut lines = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buf.drain(..=pos).collect();
+            lines.push(String::from_utf8_lossy(&line).trim().to_string());
+        }
+        lines
+    }
+}
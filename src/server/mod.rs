@@ -1 +1,2 @@
+pub mod collab;
 pub mod server_loop;
@@ -30,10 +30,10 @@ pub struct LoopianServer {
 }
 impl LoopianServer {
     pub fn new() -> Self {
-        let (txmsg, rxui) = gen_elapse_thread();
+        let (txmsg, rxui, elps_thread) = gen_elapse_thread();
         Self {
             ui_hndr: rxui,
-            itxt: InputText::new(txmsg),
+            itxt: InputText::new(txmsg, elps_thread),
             cui_mode: false,
         }
     }
@@ -58,18 +58,18 @@ impl LoopianServer {
         if pc_num < MAX_PATTERN_NUM {
             let fname = format!("{}.lpn", pc_num);
             let command_stk = self.load_lpn_when_pc(fname);
-            for one_cmd in command_stk.iter() {
-                let _answer = self.itxt.put_and_get_responce(one_cmd);
+            if let Err(err) = self.itxt.put_batch(&command_stk) {
+                println!("{}", err);
             }
         }
     }
-    fn load_lpn_when_pc(&mut self, fname: String) -> Vec<String> {
-        let mut command: Vec<String> = Vec::new();
+    fn load_lpn_when_pc(&mut self, fname: String) -> Vec<(usize, String)> {
+        let mut command: Vec<(usize, String)> = Vec::new();
         let path = "pattern/".to_owned() + &fname;
         println!("Pattern File: {}", path);
         match fs::read_to_string(path) {
             Ok(content) => {
-                for line in content.lines() {
+                for (num, line) in content.lines().enumerate() {
                     let mut comment = false;
                     if line.len() > 1 {
                         // コメントでないか、過去の 2023.. が書かれてないか
@@ -79,7 +79,7 @@ impl LoopianServer {
                         }
                     }
                     if !line.is_empty() && !comment {
-                        command.push(line.to_string());
+                        command.push((num + 1, line.to_string()));
                     }
                 }
             }
@@ -105,7 +105,9 @@ pub fn cui_loop() {
                 .read_line(&mut buf)
                 .expect("Failed to read line.");
             let input = buf.trim().to_string();
-            if input == "q" || input == "quit" {
+            if input == "quit!" {
+                srv.itxt.quit_app(true); // 後始末をせず終了
+            } else if input == "q" || input == "quit" {
                 break; // 終了
             }
             if let Some(answer) = srv.itxt.put_and_get_responce(&input) {
@@ -114,6 +116,7 @@ pub fn cui_loop() {
         } else {
             //  Read imformation from StackElapse/Gpio
             let rtn = srv.read_from_midi();
+            srv.itxt.collab_command(&mut Vec::new());
             if rtn == MAX_PATTERN_NUM {
                 break; // 終了
             } else if rtn == MAX_PATTERN_NUM + 1 {
@@ -140,6 +143,7 @@ pub fn cui_loop() {
             }
         }
     }
+    srv.itxt.quit_app(false);
 }
 #[cfg(feature = "raspi")]
 pub fn get_rasp_pin(pin: u8) -> Result<InputPin, Box<dyn Error>> {
@@ -1 +1,3 @@
+pub mod test_fuzz;
 pub mod test_general;
+pub mod test_perform;
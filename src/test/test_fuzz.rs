@@ -0,0 +1,164 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+//  Command/Phrase Parser の fuzz target
+//  正しいコマンドを壊した入力や、でたらめな入力を大量に与え、panic しないことを確かめる
+//  LOOPIAN_FUZZ_NUM で試行回数を変えられる (ex. LOOPIAN_FUZZ_NUM=1000000 cargo test fuzz)
+#![cfg(test)]
+
+use std::panic;
+
+const SEED_CMDS: [&str; 46] = [
+    "[d,r,m,f,s,l,t]",
+    "[d,r,m].dmp(off)",
+    "[3[d,r,m],7[d,r,m,f,s,l,t],3h[d,2[r,m],f]]",
+    "[d!tr,r!g,m!2]",
+    "[d'',-r,+m,|f,s..l]",
+    "{I,IV,V,I}",
+    "{C,F_M7,G7,C!}",
+    "{I/_ped,IV,V}.rpt(2)",
+    "{@d,@r}",
+    "@1=[d,r,m]",
+    "@msr(3)=[d,r,m]",
+    "@c=[d,m,s]",
+    "@m17 key E pivot",
+    "@m9 graph voice dark",
+    "@m9 text Hello",
+    "L1.[d,r,m]",
+    "R2.clear",
+    "left1",
+    "right2",
+    "play",
+    "stop",
+    "fermata",
+    "rit.poco.bar(2).fermata",
+    "set.bpm(120)",
+    "set.key(Eb)",
+    "set.beat(3/4)",
+    "set.beat(3/0)",
+    "set.bpm(1920)",
+    "[dあ,r*2,+あ]",
+    "[d,-é,+♯]",
+    "set.oct(-1)",
+    "set.pedrec(on)",
+    "set.songlen(32)",
+    "graph.beatlissa(2)",
+    "graph.full(2)",
+    "graph.rec(30)",
+    "practice.msr(5,8).bpm(4).rpt(2)",
+    "loop m9 m17",
+    "listen.beat(50)",
+    "mute.L1",
+    "mask.L1.1100",
+    "efct.dmp(80)",
+    "sync.all",
+    "keep.that",
+    "!alias p = play",
+    "own あいうえお",
+];
+const CHARS: [char; 40] = [
+    '[', ']', '{', '}', '(', ')', '.', ',', '@', '!', '=', '/', '_', '+', '-', '\'', '|', ' ', 'd',
+    'r', 'm', 'f', 's', 'x', 'I', 'V', 'L', 'R', 'A', 'F', '0', '1', '9', 'あ', '♯', 'é', '\t',
+    '*', '%', '^',
+];
+
+struct XorShift(u64);
+impl XorShift {
+    fn next(&mut self) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 as usize
+    }
+}
+fn mutate(rnd: &mut XorShift, src: &str) -> String {
+    let mut txt: Vec<char> = src.chars().collect();
+    for _ in 0..(rnd.next() % 4 + 1) {
+        let pos = if txt.is_empty() {
+            0
+        } else {
+            rnd.next() % txt.len()
+        };
+        let c = CHARS[rnd.next() % CHARS.len()];
+        match rnd.next() % 4 {
+            0 => txt.insert(pos, c),
+            1 if !txt.is_empty() => {
+                txt.remove(pos);
+            }
+            2 if !txt.is_empty() => txt[pos] = c,
+            _ => txt.truncate(pos),
+        }
+    }
+    txt.into_iter().collect()
+}
+
+#[test]
+fn fuzz_cmd() {
+    let num: usize = std::env::var("LOOPIAN_FUZZ_NUM")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(3000);
+    let mut rnd = XorShift(0x1234_5678_9abc_def0);
+    let mut failed: Vec<String> = Vec::new();
+    let (txmsg, _rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg.clone());
+
+    let dflt_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    for i in 0..num {
+        let input = if i % 4 == 3 {
+            // でたらめな文字列
+            (0..(rnd.next() % 16))
+                .map(|_| CHARS[rnd.next() % CHARS.len()])
+                .collect()
+        } else {
+            let seed = SEED_CMDS[rnd.next() % SEED_CMDS.len()];
+            mutate(&mut rnd, seed)
+        };
+        let rslt = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            cmd.put_and_get_responce(&input);
+        }));
+        if rslt.is_err() {
+            failed.push(input);
+            cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg.clone());
+        }
+    }
+    panic::set_hook(dflt_hook);
+    assert!(failed.is_empty(), "panicked with: {:?}", failed);
+}
+#[test]
+fn fuzz_phrase() {
+    // 入力時に弾かれる non-ASCII の文字も、Phrase Parser に直接与えて確かめる
+    use crate::cmd::txt2seq_phr::*;
+    use crate::lpnlib::*;
+    let num: usize = std::env::var("LOOPIAN_FUZZ_NUM")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(3000);
+    let mut rnd = XorShift(0x0fed_cba9_8765_4321);
+    let mut failed: Vec<String> = Vec::new();
+    let seeds: Vec<&str> = SEED_CMDS
+        .iter()
+        .filter(|x| x.starts_with('['))
+        .copied()
+        .collect();
+
+    let dflt_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    for _ in 0..num {
+        let seed = seeds[rnd.next() % seeds.len()];
+        let input = mutate(&mut rnd, seed);
+        let rslt = panic::catch_unwind(|| {
+            let (nt, ex, _) = complement_phrase(input.clone(), "c");
+            for imd in [InputMode::Fixed, InputMode::Closer] {
+                recombine_to_internal_format(&nt, &ex, imd, 60, DEFAULT_TICK_FOR_ONE_MEASURE);
+            }
+        });
+        if rslt.is_err() {
+            failed.push(input);
+        }
+    }
+    panic::set_hook(dflt_hook);
+    assert!(failed.is_empty(), "panicked with: {:?}", failed);
+}
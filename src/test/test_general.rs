@@ -41,6 +41,9 @@ fn pedal() {
                             trns: 0,
                             each_dur: 0,
                             artic: 100,
+                            orna: 0,
+                            ch: 0,
+                            cond: COND_ALWAYS,
                         }
                     );
                 }
@@ -51,3 +54,1234 @@ fn pedal() {
         }
     }
 }
+#[test]
+fn tuplet() {
+    use crate::lpnlib::ElpsMsg::*;
+
+    let (txmsg, rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+
+    // 一拍三連、一拍七連(端数あり)、入れ子の二拍三連
+    assert_eq!(
+        cmd.put_and_get_responce("[3[d,r,m],7[d,r,m,f,s,l,t],3h[d,2[r,m],f]]")
+            .unwrap()
+            .0,
+        "Set Phrase!".to_string()
+    );
+    let mut checked = false;
+    for msg in rxmsg.try_iter() {
+        if let Phr(_m0, dt) = msg {
+            let ticks: Vec<i16> = dt.evts.iter().map(|x| x.tick).collect();
+            assert_eq!(
+                ticks,
+                vec![0, 160, 320, 480, 548, 617, 685, 754, 822, 891, 960, 1280, 1440, 1600]
+            );
+            // 端数があっても、Phrase 全体の長さは変わらない
+            assert_eq!(dt.whole_tick, 1920);
+            checked = true;
+        }
+    }
+    assert!(checked);
+}
+#[test]
+fn quarantine() {
+    use crate::elapse::elapse_base::*;
+    use crate::elapse::stack_elapse::ElapseStack;
+    use crate::elapse::tickgen::CrntMsrTick;
+    use crate::lpnlib::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // 何度 process されても next() が進まない、壊れた obj
+    struct Faulty;
+    impl Elapse for Faulty {
+        fn id(&self) -> ElapseId {
+            ElapseId {
+                pid: 0,
+                sid: 99,
+                elps_type: ElapseType::TpNote,
+            }
+        }
+        fn prio(&self) -> u32 {
+            PRI_NOTE
+        }
+        fn next(&self) -> (i32, i32) {
+            (0, 0)
+        }
+        fn start(&mut self, _msr: i32) {}
+        fn stop(&mut self, _estk: &mut ElapseStack) {}
+        fn clear(&mut self, _estk: &mut ElapseStack) {}
+        fn process(&mut self, _crnt_: &CrntMsrTick, _estk: &mut ElapseStack) {}
+        fn rcv_sp(&mut self, _msg: ElapseMsg, _msg_data: u8) {}
+        fn destroy_me(&self) -> bool {
+            false
+        }
+    }
+
+    let (txui, rxui) = std::sync::mpsc::channel();
+    let mut estk = ElapseStack::new(txui);
+    let faulty = Rc::new(RefCell::new(Faulty));
+    estk.add_elapse(faulty.clone());
+    estk.periodic(Ok(ElpsMsg::Ctrl(MSG_CTRL_START)));
+
+    // 強制的に取り除かれ、エラーが UI に届き、演奏は続く
+    assert_eq!(Rc::strong_count(&faulty), 1);
+    assert!(rxui.try_iter().any(|x| matches!(x, UiMsg::ErrorUi(_))));
+    assert!(!estk.periodic(Err(std::sync::mpsc::TryRecvError::Empty)));
+}
+#[test]
+fn lpn_bank_diff() {
+    use crate::file::lpn_bank::LpnBank;
+
+    let mut a = LpnBank::new();
+    a.parse("left1\n[d,r,m]\n{I,IV}\nR1.[s,l]\n@1=[d]\nset.bpm(100)\n");
+    let mut b = LpnBank::new();
+    b.parse("L1.[d,r,m]\nL1.{I,V}\n// R1.[x]\nright1\n[s,l]\n[s,l]\n");
+    assert_eq!(
+        a.diff(&b),
+        vec!["L1.@1 only in 1st".to_string(), "L1.{} changed".to_string()]
+    );
+    assert_eq!(
+        b.part_lines("L1"),
+        vec!["L1.[d,r,m]".to_string(), "L1.{I,V}".to_string()]
+    );
+}
+#[test]
+fn midi_monitor_filter() {
+    use crate::lpnlib::*;
+    use crate::midi::monitor::MidiMonitor;
+
+    let mut mon = MidiMonitor::new();
+    assert_eq!(mon.line(false, (0, 0), Some(RIGHT1), 0x90, 60, 100), None);
+    mon.set(MON_OUT | MON_NOTE | (MON_PART << RIGHT1));
+    assert_eq!(
+        mon.line(false, (2, 480), Some(RIGHT1), 0x90, 60, 100),
+        Some("003:0480 OUT R1 90  60 100".to_string())
+    );
+    assert_eq!(mon.line(false, (2, 480), Some(LEFT1), 0x90, 48, 100), None);
+    assert_eq!(mon.line(false, (2, 480), Some(RIGHT1), 0xb0, 64, 127), None);
+    assert_eq!(mon.line(true, (2, 480), None, 0x9b, 60, 100), None);
+    mon.set(MON_IN);
+    assert_eq!(
+        mon.line(true, (0, 10), None, 0xbb, 64, 0),
+        Some("001:0010 IN  -- bb  64   0".to_string())
+    );
+}
+#[test]
+fn indicator_key_spell() {
+    use crate::lpnlib::*;
+    let (txmsg, _rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+
+    cmd.set_spell(SPELL_SHARP);
+    cmd.indicator_key_stock("Eb4".to_string());
+    assert_eq!(cmd.get_indicator_key_stock(), "D#4");
+    cmd.indicator_key_stock("".to_string());
+    assert_eq!(cmd.get_indicator_key_stock(), "");
+    cmd.indicator_key_stock("ド".to_string()); // ASCII でない文字でも落ちない
+    assert_eq!(cmd.get_indicator_key_stock(), "ド");
+}
+#[test]
+fn note_readout_mode() {
+    use crate::lpnlib::*;
+
+    // key: D(2)
+    assert_eq!(note_readout(66, 2, SPELL_AUTO, NOTENAME_NAME), "F#4");
+    assert_eq!(note_readout(66, 2, SPELL_AUTO, NOTENAME_DOREMI), "Mi");
+    assert_eq!(note_readout(65, 2, SPELL_AUTO, NOTENAME_DOREMI), "Ri");
+    assert_eq!(note_readout(65, 2, SPELL_FLAT, NOTENAME_DOREMI), "Me");
+    assert_eq!(note_readout(66, 2, SPELL_AUTO, NOTENAME_FIXED), "Fi");
+    assert_eq!(note_readout(65, 2, SPELL_AUTO, NOTENAME_DEGREE), "b3");
+    assert_eq!(note_readout(36, 0, SPELL_AUTO, NOTENAME_NAME), "C2");
+}
+#[test]
+fn input_debounce() {
+    use crate::file::settings::InputFilter;
+    use crate::midi::debounce::InputConditioner;
+
+    let mut cond = InputConditioner::new(&InputFilter {
+        device: "Pico".to_string(),
+        note_window: 30,
+        cc_threshold: 3,
+        cc_interval: 20,
+    });
+    assert!(cond.pass(0, &[0x9b, 60, 100]));
+    assert!(cond.pass(5_000, &[0x8b, 60, 64]));
+    assert!(!cond.pass(10_000, &[0x9b, 60, 100])); // chatter
+    assert!(!cond.pass(15_000, &[0x8b, 60, 64]));
+    assert!(cond.pass(50_000, &[0x9b, 60, 100]));
+    assert!(cond.pass(0, &[0xbb, 1, 64]));
+    assert!(!cond.pass(30_000, &[0xbb, 1, 65])); // threshold
+    assert!(!cond.pass(10_000, &[0xbb, 1, 80])); // interval
+    assert!(cond.pass(40_000, &[0xbb, 1, 80]));
+    assert!(cond.pass(41_000, &[0xbb, 1, 127]));
+    assert!(!cond.pass(50_000, &[0xbb, 1, 90])); // 速い sweep の最後の値
+    assert!(cond.flush(55_000).is_empty());
+    assert_eq!(cond.flush(61_000), vec![[0xbb, 1, 90]]);
+    assert!(cond.flush(90_000).is_empty());
+}
+#[test]
+fn chord_substitution() {
+    use crate::cmd::txt2seq_cmps::get_table_num;
+    use crate::elapse::chord_subst::ChordSubst;
+    use crate::elapse::rand_seed::RandSeed;
+    use crate::lpnlib::*;
+
+    let chord = |root, kind| ChordEvt {
+        mtype: TYPE_CHORD,
+        tick: 0,
+        root,
+        tbl: get_table_num(kind),
+    };
+    let mut subst = ChordSubst::new();
+    subst.set(100 + ((SUBST_TRITONE | SUBST_RELATIVE) << 8));
+    assert_eq!(subst.substitute(chord(14, "_7")), chord(4, "_7")); // V7 -> IIb7
+    assert_eq!(subst.substitute(chord(2, "_")), chord(17, "_m")); // I -> VIm
+    assert_eq!(subst.substitute(chord(5, "_m7")), chord(11, "_M7")); // IIm7 -> IVM7
+    assert_eq!(subst.substitute(chord(2, "_dim")), chord(2, "_dim"));
+    subst.set(100 + (SUBST_TRITONE << 8));
+    assert_eq!(subst.substitute(chord(2, "_")), chord(2, "_"));
+    let evts = subst.apply(vec![chord(14, "_7")], RandSeed::new(0).rng(LEFT1));
+    assert_eq!(evts, vec![chord(4, "_7")]);
+}
+#[test]
+fn entry_transpose() {
+    use crate::lpnlib::ElpsMsg::*;
+
+    let (txmsg, rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+    let mut notes = |input: &str| -> Vec<i16> {
+        cmd.put_and_get_responce(input).unwrap();
+        let mut ret = Vec::new();
+        for msg in rxmsg.try_iter() {
+            if let Phr(_m0, dt) = msg {
+                ret = dt.evts.iter().map(|x| x.note).collect();
+            }
+        }
+        ret
+    };
+    let org = notes("[d,m,s]");
+    assert_eq!(org.len(), 3);
+    let up5: Vec<i16> = org.iter().map(|x| x + 5).collect();
+    let down2: Vec<i16> = org.iter().map(|x| x - 2).collect();
+    assert_eq!(notes("[d,m,s]^+5"), up5);
+    assert_eq!(notes("[d,m,s]^toKeyBb"), down2);
+    assert_eq!(notes("[d,m,s]^toKeyC"), org);
+
+    // 間違った書き方は受け付けない
+    for wrong in ["[d,m,s]^toKeyQ", "[d,m,s]^", "[d,m,s]^+-", "[d,m,s]^+-3"] {
+        assert_eq!(
+            cmd.put_and_get_responce(wrong).unwrap().0,
+            "what?",
+            "{}",
+            wrong
+        );
+    }
+}
+#[test]
+fn freeze_phrase_text() {
+    use crate::lpnlib::ElpsMsg::*;
+    use crate::lpnlib::*;
+
+    let (txmsg, rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+    let mut phrase = |input: &str| -> (Vec<PhrEvt>, i32) {
+        cmd.put_and_get_responce(input).unwrap();
+        let mut ret = (Vec::new(), 0);
+        for msg in rxmsg.try_iter() {
+            if let Phr(_m0, dt) = msg {
+                ret = (dt.evts, dt.whole_tick as i32);
+            }
+        }
+        ret
+    };
+    let played = |evts: &[PhrEvt]| -> Vec<(i16, i16, i16, i16)> {
+        evts.iter()
+            .map(|x| (x.tick, x.note, x.dur, x.vel))
+            .collect()
+    };
+    let (org, whole) = phrase("R1.[d,-s,e+ms^,x,q.r&/r,l,e--d%,x,h]");
+    assert!(!org.is_empty());
+    let cmd_txt = {
+        let (txmsg, _rxmsg) = std::sync::mpsc::channel();
+        let cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+        cmd.gen_freeze_cmd(RIGHT1, 9, &org, whole)
+    };
+    assert!(cmd_txt.starts_with("R1.@9=["));
+    let (frozen, frozen_whole) = phrase(&cmd_txt);
+    assert_eq!(played(&frozen), played(&org));
+    assert_eq!(frozen_whole, whole);
+}
+#[test]
+fn led_protocol() {
+    use crate::file::settings::LedSetting;
+    use crate::midi::led::LedMap;
+
+    // 従来通り ch.16 の Note
+    let led = LedMap::new(&LedSetting::default());
+    assert_eq!(
+        led.messages(0, Some(2), 0x90, 60, 100),
+        vec![vec![0x9f, 60, 100]]
+    );
+    assert_eq!(led.messages(1, None, 0x80, 60, 64), vec![vec![0x8f, 60, 0]]);
+    assert!(led.messages(0, Some(2), 0xb0, 64, 127).is_empty());
+
+    let color = LedMap::new(&LedSetting {
+        mode: "color".to_string(),
+        color: vec![10, 20, 30, 40, 50],
+        range: vec![[21, 60], [61, 108]],
+        ..Default::default()
+    });
+    assert_eq!(
+        color.messages(0, Some(2), 0x90, 60, 100),
+        vec![vec![0xbf, 20, 30], vec![0x9f, 60, 100]]
+    );
+    assert_eq!(
+        color.messages(0, Some(2), 0x90, 60, 0),
+        vec![vec![0x9f, 60, 0]]
+    );
+    assert!(color.messages(1, Some(2), 0x90, 60, 100).is_empty()); // 音域外
+
+    let sysex = LedMap::new(&LedSetting {
+        mode: "sysex".to_string(),
+        color: vec![10, 20, 30, 40, 50],
+        range: vec![[21, 108]],
+        ..Default::default()
+    });
+    assert_eq!(
+        sysex.messages(0, None, 0x90, 60, 100),
+        vec![vec![0xf0, 0x7d, 0x4c, 0x01, 39, 50, 100, 0xf7]]
+    );
+}
+#[test]
+fn batch_rollback() {
+    use crate::lpnlib::*;
+
+    let (txmsg, rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+    let batch = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    let phr = |cmd: &crate::cmd::cmdparse::LoopianCmd| {
+        cmd.dtstk
+            .get_pdstk(RIGHT1, PhraseAs::Normal)
+            .get_phr()
+            .clone()
+    };
+
+    let answers = cmd.put_batch(&batch(&["[d]", "", "{C}"])).unwrap();
+    assert_eq!(answers.len(), 3);
+    assert!(answers[1].is_none());
+    let good = phr(&cmd);
+    assert!(!good.is_empty());
+    while rxmsg.try_recv().is_ok() {}
+
+    // 3行目で失敗したら、1行目の Phrase も実行しない
+    let err = cmd.put_batch(&batch(&["[e]", "L1.[c]", "ABC", "[f]"]));
+    assert_eq!(err.err(), Some((2, "what?".to_string())));
+    assert_eq!(phr(&cmd), good);
+    assert!(cmd
+        .dtstk
+        .get_pdstk(LEFT1, PhraseAs::Normal)
+        .get_phr()
+        .is_empty());
+    assert!(rxmsg.try_iter().next().is_none()); // Elapse には何も送らない
+
+    // 返事の文字列によらず失敗が分かり、bpm/key も変えない
+    let err = cmd.put_batch(&batch(&["set.bpm(90)", "set.key(D)", "set.bpm(fast)"]));
+    assert_eq!(err.err(), Some((2, "Number is wrong.".to_string())));
+    assert_eq!(cmd.dtstk.get_bpm(), DEFAULT_BPM);
+    assert_eq!(cmd.get_indicator_key_stock(), "C");
+    assert!(rxmsg.try_iter().next().is_none());
+    let err = cmd.put_batch(&batch(&["[d]", "scene.next"]));
+    assert_eq!(err.err().map(|e| e.0), Some(1));
+}
+#[test]
+fn vari_pad_map() {
+    use crate::file::input_txt::InputText;
+    use crate::file::settings::VariPad;
+
+    let pads = InputText::gen_vari_pad(&VariPad {
+        ch: 16,
+        ..Default::default()
+    });
+    assert_eq!(pads.len(), 8);
+    assert_eq!(pads[0], ("note36".to_string(), "R1.vari(1)".to_string()));
+    assert_eq!(pads[7], ("note43".to_string(), "R1.vari(8)".to_string()));
+
+    let grid = InputText::gen_vari_pad(&VariPad {
+        ch: 16,
+        note: 120,
+        part: "grid".to_string(),
+    });
+    assert_eq!(grid.len(), 8); // 127 を超える note は使わない
+    assert_eq!(grid[0].1, "L1.vari(1)");
+}
+#[test]
+fn schedule() {
+    use crate::file::schedule::Scheduler;
+    use crate::file::settings::ScheduleSetting;
+
+    let sch = |time: &str, cmd: &str, fade: i16| ScheduleSetting {
+        time: time.to_string(),
+        cmd: cmd.to_string(),
+        fade,
+    };
+    let mut sc = Scheduler::new(&[
+        sch("10:00", "!l.morning", 0),
+        sch("10:00", "play", 8),
+        sch("18:30", "stop", 10),
+        sch("25:00", "play", 0), // 時刻が違うので使わない
+    ]);
+    assert!(sc.check(599).is_empty());
+    assert_eq!(sc.check(600), vec!["!l.morning", "set.fade(in,8)", "play"]);
+    assert!(sc.check(600).is_empty()); // 同じ分には一度だけ
+    assert_eq!(sc.check(18 * 60 + 30), vec!["set.fade(out,10)"]);
+}
+#[test]
+fn midi_clock() {
+    use crate::elapse::midi_clock::MidiClock;
+    use crate::elapse::tickgen::CrntMsrTick;
+
+    let at = |msr: i32, tick: i32| CrntMsrTick {
+        msr,
+        tick,
+        tick_for_onemsr: 1920,
+    };
+    let mut clk = MidiClock::new();
+    assert_eq!(clk.count(&at(0, 0)), 0); // off の時は送らない
+    clk.set(true);
+    clk.start();
+    assert_eq!(clk.count(&at(0, 0)), 1); // 頭で一つ
+    assert_eq!(clk.count(&at(0, 15)), 0);
+    assert_eq!(clk.count(&at(0, 45)), 2);
+    assert_eq!(clk.count(&at(0, 1910)), 93);
+    assert_eq!(clk.count(&at(1, 10)), 1); // 小節を跨ぐ
+    clk.set(true); // 演奏の途中で on にする
+    assert_eq!(clk.count(&at(2, 1005)), 0);
+    assert_eq!(clk.count(&at(2, 1020)), 1);
+}
+#[test]
+fn midi_watchdog() {
+    use crate::midi::watchdog::MidiWatchdog;
+    use std::time::{Duration, Instant};
+
+    let mut wd = MidiWatchdog::new();
+    let t0 = Instant::now();
+    assert!(wd.is_time(t0));
+    assert!(!wd.is_time(t0 + Duration::from_millis(1000)));
+    assert!(wd.is_time(t0 + Duration::from_millis(2000)));
+    // 状態が変わった時だけ報告する
+    assert!(wd.tx_state(false).is_none());
+    assert!(wd.tx_state(true).is_some());
+    assert!(wd.tx_state(true).is_none());
+    assert_eq!(wd.tx_state(false), Some("MIDI Out reconnected."));
+    // 繋ぎ直せない間は、間隔を倍にしていく
+    assert!(wd.may_reconnect(t0));
+    wd.reconnect_failed(t0);
+    assert!(!wd.may_reconnect(t0 + Duration::from_millis(1999)));
+    assert!(wd.may_reconnect(t0 + Duration::from_millis(2000)));
+    wd.reconnect_failed(t0);
+    assert!(!wd.may_reconnect(t0 + Duration::from_millis(3999)));
+    assert!(wd.tx_state(false).is_none());
+    assert!(wd.may_reconnect(t0));
+}
+#[test]
+fn scroll_text() {
+    use crate::file::scroll_text::ScrollText;
+    use crate::lpnlib::TextAttribute;
+
+    let mut st = ScrollText::new(5);
+    for i in 0..4 {
+        st.push(TextAttribute::Common, "".to_string(), format!("cmd{}", i));
+        st.push(TextAttribute::Answer, "".to_string(), format!("ans{}", i));
+    }
+    assert_eq!(st.len(), 5); // 古い行から捨てる
+    assert_eq!(st.dropped_cmds(), 2);
+    assert_eq!(st[0].2, "ans1");
+
+    assert!(st.search("cmd2"));
+    assert_eq!(st.get_found(), Some(1));
+    assert_eq!(st.get_back(), 3);
+    assert!(!st.search("cmd2")); // さらに前には無い
+    assert_eq!(st.copy_text(2), "ans1\ncmd2");
+
+    st.push(TextAttribute::Common, "".to_string(), "cmd4".to_string());
+    assert_eq!(st.get_found(), Some(0)); // 見ている行はずれない
+    assert_eq!(st.get_back(), 4);
+    st.reset_view();
+    assert_eq!(st.copy_text(1), "cmd4");
+}
+#[test]
+fn light_bridge() {
+    use crate::file::light_bridge::LightBridge;
+    use crate::file::settings::LightSetting;
+
+    let text = LightBridge::new(&LightSetting {
+        protocol: "text".to_string(),
+        target: "127.0.0.1:0".to_string(),
+        degree_value: vec![0, 10, 20, 30, 40, 50],
+        ..Default::default()
+    });
+    assert_eq!(text.encode_msr(2), b"MSR 3\n".to_vec());
+    assert_eq!(text.encode_chord(2, "IV", 5), b"CHORD R1 IV 50\n".to_vec());
+    assert_eq!(text.encode_chord(0, "I", 6), b"CHORD L1 I 139\n".to_vec()); // 既定値
+
+    let osc = LightBridge::new(&LightSetting {
+        protocol: "osc".to_string(),
+        target: "127.0.0.1:0".to_string(),
+        ..Default::default()
+    });
+    let mut beat = b"/loopian/beat\0\0\0,i\0\0".to_vec();
+    beat.extend_from_slice(&2i32.to_be_bytes());
+    assert_eq!(osc.encode_beat(1), beat);
+    let chord = osc.encode_chord(3, "V7", 0);
+    assert!(chord.starts_with(b"/loopian/chord/R2\0\0\0,sf\0V7\0\0"));
+    assert_eq!(chord.len() % 4, 0);
+}
+#[test]
+fn upcoming_events() {
+    use crate::graphic::guiev::GuiEv;
+    use crate::lpnlib::*;
+
+    let mut guiev = GuiEv::new(false);
+    let evs: Vec<UpcomingEv> = (0..4)
+        .map(|i| UpcomingEv {
+            note: 60 + i as u8,
+            tick: i * 120,
+            vel: 100,
+        })
+        .collect();
+    guiev.set_indicator(UiMsg::UpcomingUi(RIGHT1, evs.clone()), "".to_string());
+    assert!(guiev.get_upcoming(RIGHT1, 2).is_empty()); // 停止中は返さない
+
+    guiev.set_indicator(UiMsg::TickUi(true, 1, 1, 0), "".to_string());
+    assert_eq!(guiev.get_upcoming(RIGHT1, 2), &evs[..2]);
+    assert_eq!(guiev.get_upcoming(RIGHT1, 10).len(), 4);
+    assert!(guiev.get_upcoming(LEFT1, 10).is_empty());
+    guiev.set_indicator(UiMsg::UpcomingUi(MAX_KBD_PART, evs), "".to_string());
+    // 譜面は再生中の Variation を表示する
+    let pui = PartUi {
+        exist: true,
+        vari: PhraseAs::Variation(2),
+        ..Default::default()
+    };
+    guiev.set_indicator(UiMsg::PartUi(RIGHT1, pui), "".to_string());
+    assert_eq!(guiev.get_part_vari(RIGHT1), PhraseAs::Variation(2));
+    assert_eq!(guiev.get_part_vari(LEFT1), PhraseAs::Normal);
+}
+#[test]
+fn chord_after() {
+    use crate::elapse::elapse_loop_cmp::CompositionLoop;
+    use crate::elapse::tickgen::CrntMsrTick;
+    use crate::lpnlib::*;
+
+    let chord = |tick, root| ChordEvt {
+        mtype: TYPE_CHORD,
+        tick,
+        root,
+        tbl: 0,
+    };
+    // 2小節の Loop: I | V
+    let cmps = CompositionLoop::new(0, 0, 0, 0, vec![chord(0, 2), chord(1920, 14)], 3840);
+    let at = |msr, tick| CrntMsrTick {
+        msr,
+        tick,
+        tick_for_onemsr: 1920,
+    };
+    let cmps = cmps.borrow();
+    assert_eq!(cmps.get_chord_after(&at(0, 480), 100), (NO_ROOT, NO_TABLE)); // 今の和音
+    assert_eq!(cmps.get_chord_after(&at(0, 480), 1440), (14, 0));
+    assert_eq!(cmps.get_chord_after(&at(1, 960), 960), (2, 0)); // 繰り返しの頭
+    assert_eq!(cmps.get_chord_after(&at(1, 960), 2880), (14, 0));
+}
+#[test]
+fn chord_quality() {
+    use crate::cmd::txt2seq_cmps::{get_chord_quality, get_table_num};
+    use crate::lpnlib::ChordQuality;
+
+    let quality = |kind: &str| get_chord_quality(get_table_num(kind) as usize);
+    assert_eq!(quality("_"), ChordQuality::Major);
+    assert_eq!(quality("_M7"), ChordQuality::Major);
+    assert_eq!(quality("_m7"), ChordQuality::Minor);
+    assert_eq!(quality("_7"), ChordQuality::Dominant);
+    assert_eq!(quality("_7-9"), ChordQuality::Dominant);
+    assert_eq!(quality("_dim7"), ChordQuality::Diminished);
+    assert_eq!(quality("_m7-5"), ChordQuality::Diminished);
+    assert_eq!(quality("_sus4"), ChordQuality::None);
+    assert_eq!(quality("diatonic"), ChordQuality::None);
+    assert_eq!(
+        get_chord_quality((get_table_num("_m") + crate::lpnlib::UPPER) as usize),
+        ChordQuality::Minor
+    );
+}
+#[test]
+fn collab_owner() {
+    use crate::lpnlib::*;
+    use crate::server::collab::*;
+
+    let mut owner = PartOwner::new();
+    let mut alice = CollabUser::default();
+    let mut bob = CollabUser::default();
+    assert_eq!(
+        alice.parse_line(&mut owner, "[d,r,m]"),
+        CollabLine::Reply("Say hello first.".to_string())
+    );
+    alice.parse_line(&mut owner, "hello alice");
+    bob.parse_line(&mut owner, "hello bob");
+    assert_eq!(
+        alice.parse_line(&mut owner, "[d,r,m]"),
+        CollabLine::Reply("Own a part first.".to_string())
+    );
+    alice.parse_line(&mut owner, "own R1");
+    assert_eq!(
+        bob.parse_line(&mut owner, "own R1"),
+        CollabLine::Reply("R1 is owned by alice.".to_string())
+    );
+    bob.parse_line(&mut owner, "own L1");
+    assert_eq!(
+        alice.parse_line(&mut owner, "[d,r,m]"),
+        CollabLine::Command(RIGHT1, "[d,r,m]".to_string())
+    );
+    assert_eq!(
+        alice.parse_line(&mut owner, "L1.[d,r,m]"),
+        CollabLine::Reply("L1 is not yours.".to_string())
+    );
+    assert_eq!(
+        alice.parse_line(&mut owner, "play"),
+        CollabLine::Command(RIGHT1, "play".to_string())
+    );
+    // 全パートを消す clear は、他の人のパートがあれば断る
+    bob.parse_line(&mut owner, "own L2");
+    bob.parse_line(&mut owner, "own R2");
+    assert_eq!(
+        bob.parse_line(&mut owner, "clear"),
+        CollabLine::Reply("R1 is not yours.".to_string())
+    );
+    assert_eq!(
+        bob.parse_line(&mut owner, "clear L1"),
+        CollabLine::Command(RIGHT2, "clear L1".to_string())
+    );
+    bob.parse_line(&mut owner, "release L2");
+    bob.parse_line(&mut owner, "release R2");
+    bob.parse_line(&mut owner, "own L1");
+
+    // 受け渡し
+    alice.parse_line(&mut owner, "give R1 bob");
+    assert_eq!(owner.list(), "L1:bob L2:- R1:bob R2:-");
+    assert_eq!(
+        alice.parse_line(&mut owner, "[d,r,m]"),
+        CollabLine::Reply("Own a part first.".to_string())
+    );
+    assert!(!owner.can_edit(RIGHT1, LOCAL_NAME));
+    assert!(owner.release(RIGHT1, LOCAL_NAME).is_ok()); // この loopian の奏者は手放させられる
+    owner.release_all("bob");
+    assert!(owner.can_edit(LEFT1, LOCAL_NAME));
+
+    assert!(target_parts("@m17 graph.dark", RIGHT1).is_empty());
+    assert_eq!(target_parts("@1=[d,r]", RIGHT1), vec![RIGHT1]);
+    assert_eq!(target_parts("L2.{I}", RIGHT1), vec![LEFT2]);
+    assert_eq!(target_parts("set.route(L1,ch=3)", RIGHT1), vec![LEFT1]);
+    assert_eq!(
+        target_parts("clear", RIGHT1),
+        vec![LEFT1, LEFT2, RIGHT1, RIGHT2]
+    );
+    assert_eq!(target_parts("clear env", RIGHT1).len(), MAX_KBD_PART);
+    assert!(target_parts("scene save A", RIGHT1).is_empty());
+    assert_eq!(
+        target_parts("set.doubling(R2,L2)", RIGHT1),
+        vec![LEFT2, RIGHT2]
+    );
+    assert_eq!(
+        crate::cmd::cmdparse::LoopianCmd::detect_part("あいうえお"),
+        None
+    );
+
+    // token を決めた server には、同じ token で名乗る
+    let mut carol = CollabUser::with_token("secret");
+    assert_eq!(
+        carol.parse_line(&mut owner, "hello carol"),
+        CollabLine::Reply("Token is wrong.".to_string())
+    );
+    assert_eq!(
+        carol.parse_line(&mut owner, "hello carol secret"),
+        CollabLine::Reply("welcome carol".to_string())
+    );
+}
+#[test]
+fn reroll_seed() {
+    use crate::elapse::rand_seed::RandSeed;
+    use crate::lpnlib::*;
+    use rand::Rng;
+
+    let mut seed = RandSeed::new(1234);
+    let first: Vec<u32> = (0..4).map(|_| seed.rng(RIGHT1).random()).collect();
+    assert_ne!(first[0], seed.rng(LEFT1).random::<u32>()); // Part ごとに違う乱数列
+
+    // 次の小節の頭までは、今の乱数列のまま
+    seed.reserve(None, 5678);
+    let before = seed.rng(RIGHT1).random::<u32>();
+    seed.apply();
+    let rerolled: Vec<u32> = (0..4).map(|_| seed.rng(RIGHT1).random()).collect();
+    assert_ne!(before, rerolled[0]);
+    assert_ne!(first, rerolled);
+
+    // 預かった種がなければ、小節の頭でも乱数列は続く
+    seed.apply();
+    assert_ne!(rerolled[0], seed.rng(RIGHT1).random::<u32>());
+
+    // 以前の種を使えば、同じ偶然性が再現される。他の Part の乱数列は続く
+    let mut other = RandSeed::new(5678);
+    other.rng(LEFT1).random::<u32>();
+    let left_next = other.rng(LEFT1).random::<u32>();
+    seed.rng(LEFT1).random::<u32>();
+    seed.reserve(Some(RIGHT1), 1234);
+    seed.apply();
+    let again: Vec<u32> = (0..4).map(|_| seed.rng(RIGHT1).random()).collect();
+    assert_eq!(first, again);
+    assert_eq!(left_next, seed.rng(LEFT1).random::<u32>());
+}
+#[test]
+fn direct_thru() {
+    use crate::midi::midirx::MidiRx;
+
+    assert_eq!(MidiRx::thru_msg(&[0x9b, 40, 100]), Some([0x90, 57, 100]));
+    assert_eq!(MidiRx::thru_msg(&[0x8c, 40, 64]), Some([0x80, 57, 64]));
+    assert_eq!(MidiRx::thru_msg(&[0x9b, 95, 100]), None); // 鍵盤の範囲外
+    assert_eq!(MidiRx::thru_msg(&[0x9a, 40, 100]), None); // External Loopian
+    assert_eq!(MidiRx::thru_msg(&[0xbb, 64, 127]), None);
+}
+#[test]
+fn setup_summary() {
+    use crate::elapse::elapse_part::Part;
+    use crate::file::setup_sheet::SetupSheet;
+    use crate::lpnlib::*;
+
+    let part = Part::new(RIGHT1 as u32, None);
+    assert!(part.borrow().setup_summary(1920).is_empty()); // 設定していない項目は省く
+    part.borrow_mut().set_vel_limit(30 + (110 << 7));
+    part.borrow_mut().set_split(60 + (1 << 11));
+    let rows = part.borrow().setup_summary(1920);
+    assert_eq!(rows[0], ("split", "note 60: ch.1 / ch.2".to_string()));
+    assert_eq!(rows[1], ("velocity limit", "30-110".to_string()));
+
+    let mut sheet = SetupSheet::new();
+    sheet.add_section("R1", rows);
+    sheet.add_section("Flow", Vec::new());
+    let md = sheet.gen_markdown();
+    assert!(md.contains("## R1\n\n| item | value |\n|---|---|\n| split |"));
+    assert!(md.contains("## Flow\n\n(default)\n"));
+}
+#[test]
+fn session_round_trip() {
+    use crate::file::session::Session;
+    use crate::lpnlib::*;
+
+    let (txmsg, _rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+    cmd.put_and_get_responce("set.bpm(132)");
+    cmd.put_and_get_responce("set.meter(3/4)");
+    cmd.put_and_get_responce("set.key(D)");
+    cmd.put_and_get_responce("[d,r,m]");
+    cmd.put_and_get_responce("@2=[s,l,t]");
+    cmd.put_and_get_responce("{I,IV,V}");
+    cmd.put_and_get_responce("@msr(3)=[f,s]");
+    cmd.put_and_get_responce("vari(2)");
+    cmd.put_and_get_responce("set.vellimit(30,110)");
+    let text = cmd.gen_session().gen_text().unwrap();
+
+    let (txmsg2, _rxmsg2) = std::sync::mpsc::channel();
+    let mut cmd2 = crate::cmd::cmdparse::LoopianCmd::new(txmsg2);
+    let sess = Session::from_text(&text).unwrap();
+    assert_eq!(sess.key, "D");
+    assert!(cmd2.apply_session(sess).is_ok());
+    let dt = &cmd2.dtstk;
+    assert_eq!(dt.get_bpm(), 132);
+    assert_eq!(dt.get_meter(), (3, 4));
+    assert_eq!(dt.get_pdstk(RIGHT1, PhraseAs::Normal).get_raw(), "[d,r,m]");
+    assert_eq!(
+        dt.get_pdstk(RIGHT1, PhraseAs::Variation(2)).get_raw(),
+        "[s,l,t]"
+    );
+    assert_eq!(dt.get_cdstk(RIGHT1).get_raw(), "{I,IV,V}");
+    assert_eq!(dt.get_msr_raw(RIGHT1), [(3, "[f,s]".to_string())]);
+    let sess = cmd2.gen_session();
+    assert_eq!(sess.part[RIGHT1].vari, 2);
+    assert_eq!(sess.part[RIGHT1].vellimit, Some([30, 110]));
+    assert_eq!(sess.gen_text().unwrap(), text);
+    assert!(Session::from_text("bpm = \"fast\"").is_err());
+}
+#[test]
+fn semicolon_batch() {
+    use crate::lpnlib::*;
+
+    let (txmsg, rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+
+    // 一つでも失敗したら、何も実行せず、失敗した全てのコマンドを返す
+    let ans = cmd
+        .put_and_get_responce("[d,r,m]; L1.{I/IV}; ABC; set.bpm(fast)")
+        .unwrap();
+    assert_eq!(ans.1, GraphicMsg::What);
+    assert!(ans.0.starts_with("Batch failed! Nothing applied:"));
+    assert!(ans.0.contains("3:ABC (what?)"));
+    assert!(ans.0.contains("4:set.bpm(fast)"));
+    assert!(cmd
+        .dtstk
+        .get_pdstk(RIGHT1, PhraseAs::Normal)
+        .get_raw()
+        .is_empty());
+    assert!(rxmsg.try_recv().is_err()); // Elapse には何も送らない
+
+    // 試す時は、ファイルにも書かない
+    let ans = cmd
+        .put_and_get_responce("save session batch_dry_run; ABC")
+        .unwrap();
+    assert!(ans.0.contains("2:ABC (what?)"));
+    assert!(!std::path::Path::new("session/batch_dry_run.toml").exists());
+    assert_eq!(cmd.try_command("ABC").err(), Some("what?".to_string()));
+    assert!(cmd.try_command("right1").is_ok());
+
+    let ans = cmd.put_and_get_responce("[d,r,m];.L1.{I/IV}").unwrap();
+    assert_eq!(ans.1, GraphicMsg::NoMsg);
+    assert_eq!(
+        cmd.dtstk.get_pdstk(RIGHT1, PhraseAs::Normal).get_raw(),
+        "[d,r,m]"
+    );
+    assert_eq!(cmd.dtstk.get_cdstk(LEFT1).get_raw(), "{I/IV}");
+    assert!(rxmsg.try_recv().is_ok());
+}
+#[test]
+fn set_list_file() {
+    use crate::file::set_list_file::SetListFile;
+
+    let file = SetListFile::from_text("// intro\nverse 8\n\nchorus 16 // 2回目\n").unwrap();
+    assert_eq!(
+        file.entries,
+        vec![("verse".to_string(), 8), ("chorus".to_string(), 16)]
+    );
+    assert!(SetListFile::from_text("verse\n").is_err());
+    assert!(SetListFile::from_text("verse 0\n").is_err());
+    assert!(SetListFile::from_text("// empty\n").is_err());
+}
+#[test]
+fn sigmoid_rit_even() {
+    use crate::elapse::tickgen::{RitType, TickGen};
+    use std::time::{Duration, Instant};
+
+    // rit. 中も tick が途切れずに進み、表の区間ごとにまとめて進まない
+    let mut tg = TickGen::new(RitType::Sigmoid);
+    let mut time = Instant::now();
+    tg.start(time, 100, false);
+    let (mut last, mut stall, mut max_stall) = (0, 0, 0);
+    for i in 0..6000 {
+        time += Duration::from_millis(1);
+        tg.gen_tick(time);
+        if i == 500 {
+            tg.prepare_rit(50, 1, 60);
+        }
+        let crnt = tg.get_crnt_msr_tick();
+        let tick = crnt.msr * crnt.tick_for_onemsr + crnt.tick;
+        assert!(tick >= last);
+        stall = if tick == last { stall + 1 } else { 0 };
+        max_stall = max_stall.max(stall);
+        last = tick;
+    }
+    assert!(max_stall <= 5, "tick stalled for {}ms", max_stall);
+}
+#[test]
+fn sigmoid_rit_min_bpm_above_start() {
+    use crate::elapse::tickgen::{RitType, TickGen};
+    use std::time::{Duration, Instant};
+
+    // 最低テンポが開始テンポより速くても、rit. で速くならず tick は進み続ける
+    let mut tg = TickGen::new(RitType::Sigmoid);
+    tg.set_rit_min_bpm(150);
+    tg.set_rit_steep(200);
+    let mut time = Instant::now();
+    tg.start(time, 100, false);
+    let mut last = 0;
+    for i in 0..6000 {
+        time += Duration::from_millis(1);
+        tg.gen_tick(time);
+        if i == 500 {
+            tg.prepare_rit(50, 1, 60);
+        }
+        assert!(tg.get_real_bpm() <= 100);
+        let crnt = tg.get_crnt_msr_tick();
+        let tick = crnt.msr * crnt.tick_for_onemsr + crnt.tick;
+        assert!(tick >= last);
+        last = tick;
+    }
+    assert!(last > 0);
+}
+#[test]
+fn euclid_rhythm() {
+    use crate::elapse::elapse_pattern::euclid_hit;
+
+    let ptn = |k: i16, n: i16, r: i16| -> String {
+        (0..n)
+            .map(|i| if euclid_hit(k, n, r, i) { 'x' } else { '.' })
+            .collect()
+    };
+    assert_eq!(ptn(3, 8, 0), "x..x..x.");
+    assert_eq!(ptn(5, 8, 0), "x.x.xx.x");
+    assert_eq!(ptn(3, 8, 1), "..x..x.x");
+    assert_eq!(ptn(4, 16, 0), "x...x...x...x...");
+    assert_eq!(ptn(0, 4, 0), "....");
+    assert_eq!(ptn(4, 4, 0), "xxxx");
+
+    // 範囲外や読めない引数の E() は受け付けない
+    let (txmsg, _rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+    for ok in ["[E(3,8)]", "[E(3,8,2,A)]", "[E(5,8,e,low=-2)]"] {
+        assert_ne!(cmd.put_and_get_responce(ok).unwrap().0, "what?", "{}", ok);
+    }
+    for wrong in [
+        "[E(5,0)]",
+        "[E(9,8)]",
+        "[E(3,8,8)]",
+        "[E(3)]",
+        "[E(x,8)]",
+        "[E(3,8,Z)]",
+    ] {
+        assert_eq!(
+            cmd.put_and_get_responce(wrong).unwrap().0,
+            "what?",
+            "{}",
+            wrong
+        );
+    }
+}
+#[test]
+fn edit_lock_jump_back() {
+    use crate::elapse::edit_lock::EditLock;
+    use crate::elapse::tickgen::CrntMsrTick;
+    use crate::lpnlib::*;
+
+    let mut lock = EditLock::new();
+    lock.set(1);
+    let end_of_msr = CrntMsrTick {
+        msr: 8,
+        tick: 1800,
+        tick_for_onemsr: 1920,
+    };
+    assert!(lock.check(ElpsMsg::Sync(0), &end_of_msr, 480).is_none());
+    // loop で 5小節目に戻っても、次の小節の頭で実行する
+    assert_eq!(lock.flush().len(), 1);
+    assert!(lock.flush().is_empty());
+}
+#[test]
+fn dotted_commands() {
+    // 入力画面では space が . になる
+    let (txmsg, _rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+    let mut answer = |input: &str| cmd.put_and_get_responce(input).unwrap().0;
+
+    assert_eq!(answer("export.setup.stage"), "Export setup!");
+    assert_eq!(answer("export setup stage"), "Export setup!");
+    assert_eq!(answer("export.setup"), "File name is wrong.");
+    assert_eq!(answer("save.session"), "File name is wrong.");
+    assert_eq!(answer("load.session.not_exist"), "Session load failed!");
+    assert_eq!(answer("load.setlist"), "File name is wrong.");
+    assert_eq!(
+        answer("play.from.-1"),
+        "Phrase will start after one measure!"
+    );
+    assert_eq!(answer("click.on"), "Click on!");
+    assert_eq!(answer("click.off"), "Click off!");
+}
+#[test]
+fn set_list_count() {
+    use crate::elapse::set_list::SetList;
+
+    let mut sl = SetList::new(vec![
+        (0, "A".to_string(), 2, Vec::new()),
+        (1, "B".to_string(), 1, Vec::new()),
+    ])
+    .unwrap();
+    assert_eq!(sl.indicator(), "A -/2 > B");
+    assert_eq!(sl.msr_top(), None);
+    // 小節番号に依らず、頭を迎えた小節の数で進む
+    assert_eq!(sl.msr_top(), Some(1));
+    sl.jump(1);
+    assert_eq!(sl.indicator(), "B -/1 > end");
+    assert_eq!(sl.msr_top(), None);
+    assert_eq!(sl.indicator(), "B 1/1 > end");
+}
+#[test]
+fn route_split_port() {
+    use crate::lpnlib::*;
+    use crate::midi::route::{MidiRoute, PartRoute};
+
+    let mut rt = MidiRoute::new();
+    rt.set_split(RIGHT1, Some((48, ["Bass".to_string(), String::new()])));
+    let on = |rt: &mut MidiRoute, note: u8, vel: u8| rt.apply_note(Some(RIGHT1), 0x91, note, vel);
+    // 境目より下は Bass、以上は part の port(通常)
+    assert_eq!(on(&mut rt, 40, 100), (0x91, Some("Bass".to_string())));
+    assert_eq!(on(&mut rt, 60, 100), (0x91, None));
+    // 鳴っている間に出力先を変えても、Note Off は Note On と同じ所に送る
+    rt.set(
+        RIGHT1,
+        PartRoute {
+            ch: 3,
+            port: "IAC2".to_string(),
+        },
+    );
+    assert_eq!(on(&mut rt, 60, 0), (0x91, None));
+    assert_eq!(on(&mut rt, 40, 0), (0x91, Some("Bass".to_string())));
+    assert_eq!(on(&mut rt, 60, 100), (0x93, Some("IAC2".to_string())));
+}
+#[test]
+fn rit_target_direction() {
+    let (txmsg, _rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+    let reply = |cmd: &mut crate::cmd::cmdparse::LoopianCmd, input: &str| {
+        cmd.put_and_get_responce(input).unwrap().0
+    };
+    reply(&mut cmd, "set.bpm(100)");
+    assert_eq!(reply(&mut cmd, "accel.to.120"), "accel. has started!");
+    assert_eq!(reply(&mut cmd, "accel.to.100"), "what?");
+    assert_eq!(reply(&mut cmd, "accel.to.80"), "what?");
+    assert_eq!(reply(&mut cmd, "rit.to.80"), "rit. has started!");
+    assert_eq!(reply(&mut cmd, "rit.to.120"), "what?");
+    // 連結した時は、前の到達テンポから向きを調べる
+    assert_eq!(
+        reply(&mut cmd, "accel.to.120.then.rit.to.110"),
+        "Tempo gestures have started!"
+    );
+    assert_eq!(reply(&mut cmd, "rit.to.90.then.accel.to.80"), "what?");
+}
+#[test]
+fn set_roll_range() {
+    let (txmsg, _rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+    let reply = |cmd: &mut crate::cmd::cmdparse::LoopianCmd, input: &str| {
+        cmd.put_and_get_responce(input).unwrap().0
+    };
+    assert_eq!(reply(&mut cmd, "set.roll(0)"), "Roll has changed!");
+    assert_eq!(reply(&mut cmd, "set.roll(120)"), "Roll has changed!");
+    assert_eq!(reply(&mut cmd, "set.roll(121)"), "Number is wrong.");
+    assert_eq!(reply(&mut cmd, "set.roll(-1)"), "Number is wrong.");
+    assert_eq!(reply(&mut cmd, "set.roll(x)"), "Number is wrong.");
+}
+#[test]
+fn keep_that_smf() {
+    use crate::file::smf::SmfWriter;
+    use crate::midi::capture::MidiCapture;
+    use std::time::{Duration, Instant};
+
+    // 何も出力していなければ書き出さない
+    let mut mcap = MidiCapture::new(1);
+    assert_eq!(
+        mcap.keep_that("".to_string()),
+        Err("Nothing to keep!".to_string())
+    );
+
+    // 保持する時間を過ぎたイベントは捨てる
+    let start = Instant::now();
+    mcap.put(start, 0x90, 60, 100);
+    mcap.put(start + Duration::from_secs(30), 0x80, 60, 0);
+    mcap.put(start + Duration::from_secs(90), 0x90, 62, 100);
+    let evts = mcap.events();
+    assert_eq!(evts.len(), 2);
+    assert_eq!(evts[0].1, 0x80);
+
+    // 1tick=1msec の Format 0。PC は 2byte で書く
+    let smf = SmfWriter::gen_smf(&[(1000, 0x90, 60, 100), (1200, 0xc0, 5, 0)]);
+    assert_eq!(&smf[0..4], b"MThd");
+    assert_eq!(&smf[8..14], &[0, 0, 0, 1, 0x03, 0xe8]);
+    assert_eq!(&smf[14..18], b"MTrk");
+    let trk_len = u32::from_be_bytes([smf[18], smf[19], smf[20], smf[21]]) as usize;
+    assert_eq!(smf.len(), 22 + trk_len);
+    assert_eq!(
+        &smf[22..],
+        &[
+            0x00, 0xff, 0x51, 0x03, 0x0f, 0x42, 0x40, // tempo
+            0x00, 0x90, 60, 100, // note on
+            0x81, 0x48, 0xc0, 5, // 200msec 後の PC
+            0x00, 0xff, 0x2f, 0x00, // end of track
+        ]
+    );
+}
+#[test]
+fn tuning_channel_map() {
+    use crate::elapse::elapse_part::MicroTuning;
+    use crate::lpnlib::MAX_KBD_PART;
+
+    // 各 Part の ch は重ならず、ch.1(Manager) と ch.10(drum) を使わない
+    let mut chs: Vec<u8> = (0..MAX_KBD_PART as u32)
+        .flat_map(|part| (0..3).map(move |i| MicroTuning::member_ch(part, i)))
+        .collect();
+    assert_eq!(chs[..6], [1, 2, 3, 4, 5, 6]);
+    assert_eq!(chs[6..], [7, 8, 10, 11, 12, 13]);
+    chs.dedup();
+    assert_eq!(chs.len(), 3 * MAX_KBD_PART);
+    assert!(chs.iter().all(|&ch| ch != 0 && ch != 9 && ch < 16));
+}
+#[test]
+fn note_mark_wrong() {
+    let (txmsg, _rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+    let mut reply = |input: &str| cmd.put_and_get_responce(input).unwrap().0;
+    assert_ne!(reply("[d!tr,r!g,m!mr!2,f@last,s!tn]"), "what?");
+    // 知らない ! の記号は受け付けない
+    for wrong in ["[d!tx]", "[d,r!,m]", "[d!tr!x]", "[<d,r>!mor]"] {
+        assert_eq!(reply(wrong), "what?", "{}", wrong);
+    }
+}
+#[test]
+fn note_mark_ch() {
+    use crate::lpnlib::ElpsMsg::*;
+
+    let (txmsg, rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+    let mut chs = |input: &str| -> Option<Vec<i16>> {
+        if cmd.put_and_get_responce(input).unwrap().0 == "what?" {
+            return None;
+        }
+        let mut ret = Vec::new();
+        for msg in rxmsg.try_iter() {
+            if let Phr(_m0, dt) = msg {
+                ret = dt.evts.iter().map(|x| x.ch).collect();
+            }
+        }
+        Some(ret)
+    };
+    // 0 は Part の ch(指定しない時と同じ)
+    assert_eq!(chs("[d,r!0,m!16,f!tr!2]"), Some(vec![0, 0, 16, 2]));
+    assert_eq!(chs("[d!17]"), None);
+    assert_eq!(chs("[<d,r>!99]"), None);
+}
+#[test]
+fn auto_vari_select() {
+    use crate::cmd::txt2seq_cmps::get_table_num;
+    use crate::elapse::elapse_part::AutoVari;
+    use crate::lpnlib::{NO_ROOT, NO_TABLE};
+
+    let (maj, min, sev, dim) = (
+        get_table_num("_"),
+        get_table_num("_m"),
+        get_table_num("_7"),
+        get_table_num("_dim"),
+    );
+    // root: 2:I, 5:II, 11:IV, 14:V, 20:VII
+    let mut av = AutoVari::new();
+    assert_eq!(av.select(Some((14, maj, true))), None);
+    av.set(3 + 4 * 16 + 5 * 256); // dominant:3, subdominant:4, fill:5
+    assert_eq!(av.select(Some((2, maj, false))), None);
+    assert_eq!(av.select(Some((14, maj, false))), Some(3));
+    assert_eq!(av.select(Some((20, dim, false))), Some(3));
+    assert_eq!(av.select(Some((2, sev, false))), Some(3)); // I7 も属七
+    assert_eq!(av.select(Some((11, maj, false))), Some(4));
+    assert_eq!(av.select(Some((5, min, false))), Some(4));
+    // Loop の最後の小節は、和音より優先する
+    assert_eq!(av.select(Some((14, maj, true))), Some(5));
+    assert_eq!(av.select(Some((NO_ROOT, NO_TABLE, true))), Some(5));
+    assert_eq!(av.select(Some((NO_ROOT, NO_TABLE, false))), None);
+    assert_eq!(av.select(None), None);
+    // fill が 0 なら、最後の小節も和音で選ぶ
+    av.set(3 + 4 * 16);
+    assert_eq!(av.select(Some((11, maj, true))), Some(4));
+}
+#[test]
+fn vari_pad_ch() {
+    use crate::file::settings::VariPad;
+
+    let pad = |ch: u8| VariPad {
+        ch,
+        ..VariPad::default()
+    };
+    assert!(pad(1).is_available());
+    assert!(pad(16).is_available());
+    // 0 は off、ch.12/13 は ORBIT の入力
+    for ch in [0, 12, 13, 17] {
+        assert!(!pad(ch).is_available(), "ch.{}", ch);
+    }
+}
+#[test]
+fn window_args() {
+    use crate::window_arg;
+
+    assert_eq!(window_arg("--full", "--full", 1), Some(1));
+    assert_eq!(window_arg("--full=2", "--full", 1), Some(2));
+    assert_eq!(window_arg("--full=x", "--full", 1), Some(1));
+    assert_eq!(window_arg("--fullscreen", "--full", 1), None);
+    assert_eq!(window_arg("--fullx", "--full", 1), None);
+    assert_eq!(window_arg("--audience", "--audience", 0), Some(0));
+    assert_eq!(window_arg("--audiences=1", "--audience", 0), None);
+}
+#[test]
+fn alias_first_word() {
+    use crate::file::input_txt::expand_alias;
+    use std::collections::HashMap;
+
+    let mut alias = HashMap::new();
+    alias.insert("p".to_string(), "play".to_string());
+    alias.insert("b".to_string(), "set.bpm".to_string());
+    assert_eq!(expand_alias(&alias, "p"), "play");
+    assert_eq!(expand_alias(&alias, "p; stop"), "play; stop");
+    assert_eq!(expand_alias(&alias, "stop; p"), "stop; play");
+    assert_eq!(expand_alias(&alias, "b(120)"), "set.bpm(120)");
+    assert_eq!(expand_alias(&alias, "b(90); p"), "set.bpm(90); play");
+    // 先頭の語と一致しなければ置き換えない
+    assert_eq!(expand_alias(&alias, "play"), "play");
+    assert_eq!(expand_alias(&alias, "L1.[d,r,m]"), "L1.[d,r,m]");
+}
+#[test]
+fn divide_brackets_first_close() {
+    use crate::cmd::txt2seq_phr::complement_phrase;
+
+    // 連符が無ければ、従来通り最初の ] までが音符になる
+    let plain = complement_phrase("[d,r,m]".to_string(), "");
+    assert_eq!(complement_phrase("[d,r,m]]".to_string(), "").0, plain.0);
+    let (ntvec, nevec, _) = complement_phrase("[d,r,m].dyn(f)]".to_string(), "");
+    assert_eq!(ntvec, plain.0);
+    assert_eq!(nevec, vec!["dyn(f)]".to_string()]);
+    // 連符の ] では閉じない
+    let (ntvec, _, _) = complement_phrase("[3[d,r,m],f].dyn(f)".to_string(), "");
+    assert_eq!(ntvec.last().unwrap(), "f");
+}
+#[test]
+fn every_zero() {
+    let (txmsg, _rxmsg) = std::sync::mpsc::channel();
+    let mut cmd = crate::cmd::cmdparse::LoopianCmd::new(txmsg);
+    let mut reply = |input: &str| cmd.put_and_get_responce(input).unwrap().0;
+    assert_ne!(reply("[d@every(3),r!every(1)]"), "what?");
+    // 0 回に一回は無いので、無視せずエラーにする
+    for wrong in [
+        "[d@every(0)]",
+        "[d!every(0)]",
+        "[d@every(-2)]",
+        "[d@every()]",
+    ] {
+        assert_eq!(reply(wrong), "what?", "{}", wrong);
+    }
+}
+#[test]
+fn notation_rebuild() {
+    use crate::graphic::notation::NotationLayout;
+    use crate::lpnlib::*;
+
+    let note = |tick: i16, note: i16| PhrEvt {
+        mtype: TYPE_NOTE,
+        tick,
+        dur: 480,
+        note,
+        vel: 100,
+        ..PhrEvt::default()
+    };
+    let phr = vec![note(0, 60), note(480, 62)];
+    let mut layout = NotationLayout::default();
+    assert!(layout.update(PhraseAs::Normal, &phr, 1920, 1920));
+    assert!(!layout.is_empty());
+    // 同じ Phrase なら作り直さない
+    assert!(!layout.update(PhraseAs::Normal, &phr, 1920, 1920));
+    // Phrase、Variation、長さ、拍子のどれかが変わったら作り直す
+    let phr2 = vec![note(0, 60), note(480, 64)];
+    assert!(layout.update(PhraseAs::Normal, &phr2, 1920, 1920));
+    assert!(layout.update(PhraseAs::Variation(1), &phr2, 1920, 1920));
+    assert!(layout.update(PhraseAs::Variation(1), &phr2, 3840, 1920));
+    assert!(layout.update(PhraseAs::Variation(1), &phr2, 3840, 1440));
+    assert!(!layout.update(PhraseAs::Variation(1), &phr2, 3840, 1440));
+    assert!(layout.update(PhraseAs::Variation(1), &[], 3840, 1440));
+    assert!(layout.is_empty());
+}
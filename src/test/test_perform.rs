@@ -0,0 +1,1005 @@
+//  Created on 2026/10/16.
+//  Released under the MIT license
+//  https://opensource.org/licenses/mit-license.php
+//
+//  演奏全体の結合テスト
+//  コマンド列を擬似的な時刻で ElapseStack に与え、MIDI 出力の順番と timing を
+//  golden file (src/test/golden/*.txt) と比較する
+//  意図して出力が変わったときは LOOPIAN_GOLDEN=update cargo test perform で書き換える
+#![cfg(test)]
+
+use std::fs;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant};
+
+use crate::cmd::cmdparse::LoopianCmd;
+use crate::elapse::stack_elapse::ElapseStack;
+use crate::file::set_list_file::SetListFile;
+use crate::lpnlib::*;
+
+//*******************************************************************
+//          Performance Struct
+//*******************************************************************
+struct Performance {
+    cmd: LoopianCmd,
+    rxmsg: Receiver<ElpsMsg>,
+    est: ElapseStack,
+    txrx: Sender<ElpsMsg>,
+    rxui: Receiver<UiMsg>,
+    _rxctrl: Receiver<ElpsMsg>,
+    time: Instant,
+    log: Vec<String>,
+}
+impl Performance {
+    const STEP_MSEC: u64 = 1; // 擬似時刻の刻み
+
+    fn new() -> Self {
+        let (txmsg, rxmsg) = mpsc::channel();
+        let (txui, rxui) = mpsc::channel();
+        let (txrx, rxrx) = mpsc::channel();
+        let (txctrl, rxctrl) = mpsc::channel();
+        Self {
+            cmd: LoopianCmd::new(txmsg),
+            rxmsg,
+            est: ElapseStack::new_mock(txui, rxrx, txctrl),
+            txrx,
+            rxui,
+            _rxctrl: rxctrl,
+            time: Instant::now(),
+            log: Vec::new(),
+        }
+    }
+    /// コマンドを入力し、返答が None でないことを確かめる
+    fn cmd(&mut self, input: &str) -> &mut Self {
+        let rtn = self.cmd.put_and_get_responce(input);
+        assert!(rtn.is_some(), "no answer: {}", input);
+        self.log.push(format!("# {}", input));
+        self
+    }
+    /// set list のファイルの中身を読み込ませる
+    fn set_list(&mut self, text: &str) -> &mut Self {
+        let file = SetListFile::from_text(text).unwrap();
+        let rtn = self.cmd.apply_set_list_file(file);
+        self.log.push(format!(
+            "# set list: {} ({})",
+            text.replace('\n', " / "),
+            rtn
+        ));
+        self
+    }
+    /// MIDI 入力(MIDI Rx thread から来る形)
+    fn midi_in(&mut self, sts: u8, dt1: u8, dt2: u8, ex: u8) -> &mut Self {
+        self.txrx.send(ElpsMsg::MIDIRx(sts, dt1, dt2, ex)).unwrap();
+        self.log
+            .push(format!("# MIDI In {:02x} {} {} {}", sts, dt1, dt2, ex));
+        self
+    }
+    /// msec だけ擬似時刻を進める
+    fn wait(&mut self, msec: u64) -> &mut Self {
+        for _ in 0..(msec / Self::STEP_MSEC) {
+            self.time += Duration::from_millis(Self::STEP_MSEC);
+            let msg = self.rxmsg.try_recv();
+            self.est.periodic_at(msg, self.time);
+            let crnt_ = self.est.tg().get_crnt_msr_tick();
+            for (sts, dt1, dt2) in self.est.take_midi_log() {
+                self.log.push(format!(
+                    "{:03}:{:04} {:02x} {:3} {:3}",
+                    crnt_.msr + 1,
+                    crnt_.tick,
+                    sts,
+                    dt1,
+                    dt2
+                ));
+            }
+        }
+        self
+    }
+    /// UI に来た punch の Phrase を、main と同じくコマンドにして入力する
+    fn ui_phrase(&mut self) -> &mut Self {
+        let msgs: Vec<UiMsg> = self.rxui.try_iter().collect();
+        for msg in msgs.into_iter() {
+            if let UiMsg::PunchUi(part, evts, whole) = msg {
+                let input = self.cmd.gen_punch_cmd(part, &evts, whole);
+                self.cmd(&input);
+            }
+        }
+        self
+    }
+    /// 今のテンポを記録する
+    fn tempo(&mut self) -> &mut Self {
+        let bpm = self.est.tg().get_real_bpm();
+        self.log.push(format!("# tempo: {}", bpm));
+        self
+    }
+    /// 記録した MIDI 出力を golden file と比べる
+    fn check(&self, name: &str) {
+        let path = format!(
+            "{}/src/test/golden/{}.txt",
+            env!("CARGO_MANIFEST_DIR"),
+            name
+        );
+        let mut result = self.log.join("\n");
+        result.push('\n');
+        if std::env::var("LOOPIAN_GOLDEN").is_ok_and(|x| x == "update") {
+            fs::write(&path, &result).unwrap();
+            return;
+        }
+        let golden = fs::read_to_string(&path).unwrap_or_default();
+        if golden != result {
+            let line = golden
+                .lines()
+                .zip(result.lines())
+                .position(|(g, r)| g != r)
+                .unwrap_or(golden.lines().count().min(result.lines().count()));
+            panic!(
+                "{} differs from golden file at line {}\n golden: {:?}\n result: {:?}",
+                name,
+                line + 1,
+                golden.lines().nth(line),
+                result.lines().nth(line)
+            );
+        }
+    }
+}
+
+#[test]
+fn perform_basic() {
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("L1.[d,r,m,f]")
+        .cmd("R1.[s,-,m,-].dmp(off)")
+        .cmd("L1.{I,IV}")
+        .wait(10)
+        .cmd("play")
+        .wait(4100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("basic");
+}
+#[test]
+fn perform_roll() {
+    // 音価に収まらない roll は、ずれを詰めて鳴らす
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("left1")
+        .cmd("set.roll(120)")
+        .cmd("L1.[Cls(e,4)]")
+        .cmd("L1.{I}")
+        .wait(10)
+        .cmd("play")
+        .wait(2100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("roll");
+}
+#[test]
+fn perform_ornament() {
+    // 装飾音は今の Scale の隣の音を使い、短すぎる音符は装飾しない
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(100)")
+        .cmd("L1.[em!mr,l!tn,qd!tr,wr!tn,m]")
+        .cmd("L1.{aeolian}")
+        .wait(10)
+        .cmd("play")
+        .wait(2500)
+        .cmd("stop")
+        .wait(10);
+    pf.check("ornament");
+}
+#[test]
+fn perform_keysw() {
+    // articulation が変わった音符の Note On の直前に、keyswitch の Note/CC を送る
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("left1")
+        .cmd("set.keysw(stacc,24)")
+        .cmd("set.keysw(legato,cc32,5)")
+        .cmd("set.keysw(norm,cc32,1)")
+        .cmd("L1.[d',r',m~,f]")
+        .wait(10)
+        .cmd("play")
+        .wait(2100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("keysw");
+}
+#[test]
+fn perform_tempo() {
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(100)")
+        .cmd("R1.[d,r,m,f,s,f,m,r]")
+        .wait(10)
+        .cmd("play")
+        .wait(2500)
+        .cmd("set.bpm(150)")
+        .wait(3000)
+        .cmd("rit.bar(1)")
+        .wait(3000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("tempo");
+}
+#[test]
+fn perform_tempo_gesture() {
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(100)")
+        .cmd("R1.[d,r,m,f,s,f,m,r]")
+        .wait(10)
+        .cmd("play")
+        .wait(1000)
+        .cmd("rit.poco.2bars.then.accel.to.132.over.2bars")
+        .wait(14000)
+        .tempo()
+        .cmd("stop")
+        .wait(10);
+    pf.check("tempo_gesture");
+}
+#[test]
+fn perform_rit_type() {
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(100)")
+        .cmd("rit type linear min(60)")
+        .cmd("R1.[d,r,m,f,s,f,m,r]")
+        .wait(10)
+        .cmd("play")
+        .wait(1000)
+        .cmd("rit.molto.bar(2)")
+        .wait(6000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("rit_type");
+}
+#[test]
+fn perform_rate() {
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("set.rate(50)")
+        .cmd("R1.[d,r,m,f]")
+        .wait(10)
+        .cmd("play")
+        .wait(4100)
+        .cmd("set.rate(off)")
+        .wait(2100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("rate");
+}
+#[test]
+fn perform_sustain() {
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("right1")
+        .cmd("set.sustain(legato)")
+        .cmd("R1.{I,VIm,IV,V}")
+        .cmd("right2")
+        .cmd("set.sustain(on)")
+        .cmd("R2.{I,VIm,IV,V}")
+        .wait(10)
+        .cmd("play")
+        .wait(8100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("sustain");
+}
+#[test]
+fn perform_doubling() {
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("set.doubling(R1,L2)")
+        .cmd("R1.[d,m,s,m]")
+        .cmd("left2")
+        .cmd("set.oct(+1)")
+        .cmd("L2.[d,m,r,m]")
+        .cmd("right2")
+        .cmd("set.oct(-1)")
+        .cmd("R2.[d,r,s,d]")
+        .wait(10)
+        .cmd("play")
+        .wait(2100)
+        .cmd("set.doubling(oct,R1,R2)")
+        .wait(2000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("doubling");
+}
+#[test]
+fn perform_variation() {
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,m,s,m]")
+        .cmd("R1.@1=[l,-,s,-]")
+        .cmd("R1.@msr(3)=[d',t,l,s]")
+        .cmd("R1.{I,@1,I,I}")
+        .wait(10)
+        .cmd("play")
+        .wait(8100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("variation");
+}
+#[test]
+fn perform_sync() {
+    let mut pf = Performance::new();
+    pf.cmd("L1.[d,r,m]")
+        .cmd("R1.[s,l,t,d']")
+        .wait(10)
+        .cmd("play")
+        .wait(2600)
+        .cmd("sync.all")
+        .wait(2600)
+        .cmd("L1.[m,-,d,-].dmp(off)")
+        .wait(2000)
+        .cmd("fine")
+        .wait(2100);
+    pf.check("sync");
+}
+#[test]
+fn perform_tuning() {
+    let mut pf = Performance::new();
+    pf.cmd("right1")
+        .cmd("set.tune(meantone)")
+        .cmd("[d,m,s,d']")
+        .wait(10)
+        .cmd("play")
+        .wait(1000)
+        .cmd("stop")
+        .cmd("set.tune(equal)")
+        .wait(20);
+    pf.check("tuning");
+}
+#[test]
+fn perform_tuning_chord() {
+    // 4音の和音では、一番古い音を止めてから、その ch で次の音を鳴らす
+    let mut pf = Performance::new();
+    pf.cmd("right1")
+        .cmd("set.tune(meantone)")
+        .cmd("[dmst,r]")
+        .wait(10)
+        .cmd("play")
+        .wait(1000)
+        .cmd("stop")
+        .cmd("set.tune(equal)")
+        .wait(20);
+    pf.check("tuning_chord");
+}
+#[test]
+fn perform_chordfix() {
+    // 拍頭の直後に来た和音で、既に鳴らした Flow の音を直す
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("set.chordfix(240)")
+        .cmd("L1.[d,r,m,f]")
+        .wait(10)
+        .cmd("play")
+        .wait(2010)
+        .midi_in(0x9b, 40, 100, 0)
+        .wait(150)
+        .midi_in(0xaa, 9, 2, 0)
+        .wait(300)
+        .midi_in(0x8b, 40, 0, 0)
+        .wait(500)
+        .cmd("stop")
+        .wait(10);
+    pf.check("chordfix");
+}
+#[test]
+fn perform_chord_pad() {
+    // Flow の和音と鳴っている音を pad に記憶し、別の和音の後で呼び出す
+    let mut pf = Performance::new();
+    pf.midi_in(0xaa, 2, 2, 0)
+        .wait(10)
+        .midi_in(0x9b, 40, 100, 0)
+        .midi_in(0x9b, 50, 100, 0)
+        .wait(10)
+        .cmd("pad store 1")
+        .wait(10)
+        .midi_in(0x8b, 40, 0, 0)
+        .midi_in(0x8b, 50, 0, 0)
+        .midi_in(0xaa, 9, 2, 0)
+        .wait(10)
+        .cmd("pad 1")
+        .wait(10)
+        .midi_in(0x9b, 40, 100, 0)
+        .wait(10)
+        .midi_in(0x8b, 40, 0, 0)
+        .cmd("pad off")
+        .wait(10)
+        .cmd("pad 1") // 呼び出した音は stop で消える
+        .wait(10)
+        .cmd("play")
+        .wait(100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("chord_pad");
+}
+#[test]
+fn perform_cmps_vari() {
+    // Phrase と Composition の Variation を連動して切り替える
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("R1.[d,m,s,m]")
+        .cmd("R1.@1=[d,-,s,-]")
+        .cmd("R1.{I,IV}")
+        .cmd("R1.@1={V,VI}")
+        .wait(10)
+        .cmd("play")
+        .wait(2500)
+        .cmd("R1.vari(1)")
+        .wait(6000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("cmps_vari");
+}
+#[test]
+fn perform_vari_beat() {
+    // 次の拍から、Variation の同じ位置に切り替える
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("R1.[d,r,m,f|s,f,m,r].dmp(off)")
+        .cmd("R1.@1=[d',t,l,s|f,m,r,d].dmp(off)")
+        .wait(10)
+        .cmd("play")
+        .wait(2700)
+        .cmd("R1.vari.beat(1)")
+        .wait(3500)
+        .cmd("stop")
+        .wait(10);
+    pf.check("vari_beat");
+}
+#[test]
+fn perform_global_phase() {
+    // 長さの違う Phrase が、小節0から鳴っていたかのような位置で入る
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("R1.set.phase(global)")
+        .cmd("R1.[d,-,-,-|r,-,-,-|m,-,-,-].dmp(off)")
+        .wait(10)
+        .cmd("play")
+        .wait(4500)
+        .cmd("R1.[s,-,-,-|l,-,-,-].dmp(off)")
+        .wait(4000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("global_phase");
+}
+#[test]
+fn perform_punch() {
+    // 録音したペダルのうち、punch 区間の小節だけを録り直す
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("L1.[d,r,m,f]")
+        .cmd("set.pedrec(on)")
+        .wait(10)
+        .cmd("play")
+        .wait(100)
+        .midi_in(0xbb, 64, 127, 0)
+        .wait(1000)
+        .midi_in(0xbb, 64, 0, 0)
+        .wait(1000)
+        .midi_in(0xbb, 64, 100, 0)
+        .wait(1500)
+        .midi_in(0xbb, 64, 0, 0)
+        .wait(500)
+        .cmd("set.pedrec(off)")
+        .cmd("punch m4 m5")
+        .cmd("set.pedrec(on)")
+        .wait(6000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("punch");
+}
+#[test]
+fn perform_punch_note() {
+    // punch 区間で弾いた音だけを、Phrase の同じ位置に差し替える
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("[d,r,m,f|s,l,t,d'].dmp(off)")
+        .cmd("punch m2 m3")
+        .wait(10)
+        .cmd("play")
+        .wait(2100)
+        .midi_in(0x9b, 40, 100, 0)
+        .wait(400)
+        .midi_in(0x8b, 40, 0, 0)
+        .wait(1600)
+        .ui_phrase()
+        .wait(4000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("punch_note");
+}
+#[test]
+fn perform_strum() {
+    // ギターの押さえ方で、down/up の Strum を鳴らす
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("L1.[Str(e,d-du-ud)]")
+        .cmd("L1.{I,V}")
+        .wait(10)
+        .cmd("play")
+        .wait(4100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("strum");
+}
+#[test]
+fn perform_arp_updown() {
+    // 和音の構成音を、四音ごとに上下に折り返して鳴らす
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("L1.[Arp(e,ud,0,4)]")
+        .cmd("L1.{I,IV}")
+        .wait(10)
+        .cmd("play")
+        .wait(4100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("arp_updown");
+}
+#[test]
+fn perform_split() {
+    // E4 より下を ch.2、E4 以上を ch.1 で出力する
+    let mut pf = Performance::new();
+    pf.cmd("right1")
+        .cmd("set.split(E4,2,1)")
+        .cmd("[d,r,m,f].dmp(off)")
+        .wait(10)
+        .cmd("play")
+        .wait(2100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("split");
+}
+#[test]
+fn perform_same_note_ch() {
+    // 同じ音でも ch が違えば、それぞれに Note Off を送る
+    let mut pf = Performance::new();
+    pf.cmd("right1")
+        .cmd("[d!2,r,m,f].dmp(off)")
+        .cmd("right2")
+        .cmd("[-d,r,m,f].dmp(off)")
+        .wait(10)
+        .cmd("play")
+        .wait(2100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("same_note_ch");
+}
+#[test]
+fn perform_song_position() {
+    // 再生中に Song Position を受けると、次の小節頭からその小節に移る
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("R1.[d,r,m,f|s,l,t,d'|r',m',f',s'].dmp(off)")
+        .wait(10)
+        .cmd("play")
+        .wait(1000)
+        .midi_in(0xf2, 32, 0, 0)
+        .wait(3100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("song_position");
+}
+#[test]
+fn perform_loop_condition() {
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,<r,m>@first,f]")
+        .cmd("L1.[d,r,m,f@every(2)]")
+        .cmd("play")
+        .wait(6000);
+    pf.check("loop_condition");
+}
+#[test]
+fn perform_mask() {
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,r,m,f/s,l,t,d/d,r,m,f]")
+        .cmd("mask 101")
+        .cmd("play")
+        .wait(4000)
+        .cmd("mask R1 off")
+        .wait(6000);
+    pf.check("mask");
+}
+#[test]
+fn perform_chord_out() {
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,r,m,f]")
+        .cmd("R1.{I,VIm,IV,V7}")
+        .cmd("set.chordout(16)")
+        .cmd("play")
+        .wait(3000);
+    pf.check("chord_out");
+}
+#[test]
+fn perform_velocity_ramp() {
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,r,m,f]")
+        .cmd("set.ramp(50,100,2)")
+        .cmd("play")
+        .wait(5000);
+    pf.check("velocity_ramp");
+}
+#[test]
+fn perform_evolve() {
+    let mut pf = Performance::new();
+    pf.cmd("R1.[wCls(w,3)]")
+        .cmd("R1.{I}")
+        .cmd("set.evolve(2)")
+        .cmd("play")
+        .wait(9000);
+    pf.check("evolve");
+}
+#[test]
+fn perform_pedal_rhythm() {
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,r,m,f]")
+        .cmd("R1.{I/./IV,V,I,VIm/IIm.,V.}")
+        .cmd("set.pedrhythm(on)")
+        .cmd("play")
+        .wait(12500);
+    pf.check("pedal_rhythm");
+}
+#[test]
+fn perform_quantize() {
+    let mut pf = Performance::new();
+    pf.cmd("R1.[3[d,r,m],3[f,s,l],hx]")
+        .cmd("set.quantize(50)")
+        .cmd("L1.[3[d,r,m],3[f,s,l],hx]")
+        .cmd("set.quantize(L1,100,e)")
+        .cmd("play")
+        .wait(2500);
+    pf.check("quantize");
+}
+#[test]
+fn perform_quantize_arp() {
+    // quantize で動いた音も、和音に合わせた分散和音の読み替えは元の音と同じ
+    let mut pf = Performance::new();
+    pf.cmd("R1.[3[d,m,s],3[d',s,m],hx]")
+        .cmd("R1.{IV}")
+        .cmd("set.quantize(R1,100,e)")
+        .wait(10)
+        .cmd("play")
+        .wait(2500)
+        .cmd("stop")
+        .wait(10);
+    pf.check("quantize_arp");
+}
+#[test]
+fn perform_riser() {
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(240)")
+        .cmd("R1.[d,d,d,d]")
+        .cmd("set.riser(1,0/2/-1)")
+        .cmd("play")
+        .wait(3900);
+    pf.check("riser");
+}
+#[test]
+fn perform_vel_limit() {
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(240)")
+        .cmd("R1.[d,d,d,d]")
+        .cmd("set.vellimit(60,90)")
+        .cmd("play")
+        .wait(900);
+    pf.check("vel_limit");
+}
+#[test]
+fn perform_auto_vari() {
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(240)")
+        .cmd("R1.[d,d,d,d]")
+        .cmd("R1.@2=[s,s,s,s]")
+        .cmd("R1.@3=[d',t,l,s]")
+        .cmd("R1.{I/V/IV/I}")
+        .cmd("set.autovari(dom=2,fill=3)")
+        .cmd("play")
+        .wait(3900);
+    pf.check("auto_vari");
+}
+#[test]
+fn perform_fade_out() {
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(240)")
+        .cmd("R1.[d,d,d,d]")
+        .cmd("play")
+        .cmd("set.fade(out,2)")
+        .wait(3000);
+    pf.check("fade_out");
+}
+#[test]
+fn perform_clock_slave() {
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,r,m,f]").cmd("set.clock(ext)").wait(10);
+    pf.midi_in(0xfa, 0, 0, 0);
+    // 125bpm(20ms/Clock) で2小節、62.5bpm(40ms/Clock) で1小節
+    for i in 0..(24 * 12) {
+        pf.midi_in(0xf8, 0, 0, 0)
+            .wait(if i < 24 * 8 { 20 } else { 40 });
+    }
+    pf.midi_in(0xfc, 0, 0, 0).wait(100);
+    pf.check("clock_slave");
+}
+#[test]
+fn perform_route() {
+    let mut pf = Performance::new();
+    pf.cmd("L1.[d,r]")
+        .cmd("R1.[s,l]")
+        .cmd("set.route(R1,ch=3,port=IAC2)")
+        .cmd("set.route(L1,ch=2)")
+        .cmd("set.route(L1,off)")
+        .wait(10)
+        .cmd("play")
+        .wait(1000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("route");
+}
+#[test]
+fn perform_route_held() {
+    // 鳴っている間に出力先を変えても、Note Off は Note On と同じ ch に送る
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,r]")
+        .wait(10)
+        .cmd("play")
+        .wait(100)
+        .cmd("set.route(R1,ch=3)")
+        .wait(700)
+        .cmd("stop")
+        .wait(10);
+    pf.check("route_held");
+}
+#[test]
+fn perform_edit_lock() {
+    // 小節の最後の拍に入力した Phrase は、次の小節の頭から反映される
+    let mut pf = Performance::new();
+    pf.cmd("set.lock(1)")
+        .cmd("R1.[d,r,m,f]")
+        .wait(10)
+        .cmd("play")
+        .wait(2000)
+        .cmd("R1.[s,s,s,s]")
+        .wait(5000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("edit_lock");
+}
+#[test]
+fn perform_play_from_minus1() {
+    // 一小節前から始め、弱起の Phrase は最初の小節に、他の Part は次の小節から鳴る
+    let mut pf = Performance::new();
+    pf.cmd("R1.[A4:s|d,r,m,f]")
+        .cmd("L1.[d,r,m,f]")
+        .wait(10)
+        .cmd("play from -1")
+        .wait(4900)
+        .cmd("stop")
+        .wait(10);
+    pf.check("play_from_minus1");
+}
+#[test]
+fn perform_click() {
+    // 拍ごとに click が鳴り、一拍目は accent の音
+    let mut pf = Performance::new();
+    pf.cmd("set.click(ch=10,note=77,accent=76)")
+        .cmd("click on")
+        .wait(10)
+        .cmd("play")
+        .wait(2700)
+        .cmd("click off")
+        .wait(600)
+        .cmd("stop")
+        .wait(10);
+    pf.check("click");
+}
+#[test]
+fn perform_tempo_map() {
+    // 2小節目の頭から bpm が変わり、もう一度 play すると最初の bpm から始まる
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,r,m,f]")
+        .cmd("bpm@2 200")
+        .wait(10)
+        .cmd("play")
+        .wait(3700)
+        .cmd("stop")
+        .wait(10)
+        .cmd("play")
+        .wait(1300)
+        .cmd("stop")
+        .wait(10);
+    pf.check("tempo_map");
+}
+#[test]
+fn perform_part_speed() {
+    // R1 は double-time、L1 は half-time、L2 は 1.5倍速で、小節線で揃う
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,r,m,f]")
+        .cmd("L1.[d,r,m,f]")
+        .cmd("L2.[d,r,m]")
+        .cmd("set.speed(R1,x2)")
+        .cmd("set.speed(L1,/2)")
+        .cmd("set.speed(L2,x1.5)")
+        .wait(10)
+        .cmd("play")
+        .wait(4850)
+        .cmd("stop")
+        .wait(10);
+    pf.check("part_speed");
+}
+#[test]
+fn perform_gap_filler() {
+    // L1 は Phrase が来るまで和音、L2 は Arpeggio で埋め、Phrase が来たら差し替える
+    let mut pf = Performance::new();
+    pf.cmd("L1.{I/IV}")
+        .cmd("L2.{I/IV}")
+        .cmd("set.filler(L1,chord)")
+        .cmd("set.filler(L2,arp)")
+        .wait(10)
+        .cmd("play")
+        .wait(2600)
+        .cmd("L1.[d,r,m,f]")
+        .wait(2250)
+        .cmd("stop")
+        .wait(10);
+    pf.check("gap_filler");
+}
+#[test]
+fn perform_conduct() {
+    // 2拍目から半分の速さに滑らかに遅くし、3小節目の途中で元に戻す
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,r,m,f]")
+        .wait(10)
+        .cmd("play")
+        .wait(600)
+        .cmd("set.conduct(50)")
+        .wait(3000)
+        .cmd("set.conduct(100)")
+        .wait(2400)
+        .cmd("stop")
+        .wait(10);
+    pf.check("conduct");
+}
+#[test]
+fn perform_nudge() {
+    // 二拍ずつ速く/遅くして拍の位置をずらし、その後は元の bpm に戻る
+    let mut pf = Performance::new();
+    pf.cmd("R1.[d,r,m,f]")
+        .wait(10)
+        .cmd("play")
+        .wait(700)
+        .cmd("nudge +10%")
+        .wait(600)
+        .cmd("nudge +10%")
+        .wait(1600)
+        .cmd("nudge -10%")
+        .wait(2000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("nudge");
+}
+#[test]
+fn perform_note_pan() {
+    // R1 は音高で左右に広げ、L1 は左寄りに固定する
+    let mut pf = Performance::new();
+    pf.cmd("right1")
+        .cmd("set.pan(pitch,40)")
+        .cmd("[l,m,s,d]")
+        .cmd("left1")
+        .cmd("set.pan(30)")
+        .cmd("[d,d,r,r]")
+        .wait(10)
+        .cmd("play")
+        .wait(2100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("note_pan");
+}
+#[test]
+fn perform_scene_switch() {
+    // 2小節の Phrase の途中で scene を変えても、両パートが次の小節の頭で揃って切り替わる
+    let mut pf = Performance::new();
+    pf.cmd("[d,r,m,f,s,l,t,d]")
+        .cmd("L1.[d,d,d,d,d,d,d,d]")
+        .cmd("scene save A")
+        .cmd("[s,s,s,s]")
+        .cmd("L1.[l,l,l,l]")
+        .cmd("set.bpm(120)")
+        .cmd("scene save B")
+        .cmd("scene A")
+        .wait(10)
+        .cmd("play")
+        .wait(1000)
+        .cmd("scene next")
+        .wait(3000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("scene_switch");
+}
+#[test]
+fn perform_scene_vari() {
+    // scene は Variation の選択も覚えていて、切り替えると選び直す
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("R1.[d,d,d,d]")
+        .cmd("R1.@1=[s,s,s,s]")
+        .cmd("scene save A")
+        .cmd("vari(1)") // 最初の小節で鳴る
+        .cmd("scene save B")
+        .cmd("scene saved") // saved という名前の scene は無い
+        .cmd("scene A")
+        .wait(10)
+        .cmd("play")
+        .wait(2100)
+        .cmd("scene B")
+        .wait(4000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("scene_vari");
+}
+#[test]
+fn perform_one_shot_trigger() {
+    // 属和音に変わった時と、Composition の Loop の最後の小節の頭で、ch.10 の音を鳴らす
+    let mut pf = Performance::new();
+    pf.cmd("L1.[d,d,d,d]")
+        .cmd("L1.{I/V/IV/V}")
+        .cmd("set.trigger(dominant,C#2)")
+        .cmd("set.trigger(loopend,49,11,90)")
+        .wait(10)
+        .cmd("play")
+        .wait(9700)
+        .cmd("stop")
+        .wait(10);
+    pf.check("one_shot_trigger");
+}
+#[test]
+fn perform_trigger_loop() {
+    // loop で戻っても、鳴らした音は一小節後に止め、戻った先の属和音でもう一度鳴らす
+    let mut pf = Performance::new();
+    pf.cmd("L1.[d,d,d,d]")
+        .cmd("L1.{I/V/I}")
+        .cmd("set.trigger(dominant,C#2)")
+        .cmd("loop m2 m3")
+        .wait(10)
+        .cmd("play")
+        .wait(7700)
+        .cmd("stop")
+        .wait(10);
+    pf.check("trigger_loop");
+}
+#[test]
+fn perform_set_list_advance() {
+    // A を2小節、B を1小節、A を1小節の順に、小節の頭で自動的に切り替える
+    // 最初の小節での setlist next で、残りの小節を待たずに次の scene に移る
+    let mut pf = Performance::new();
+    pf.cmd("[d,m,s,m]")
+        .cmd("scene save A")
+        .cmd("[l,l,l,l]")
+        .cmd("scene save B")
+        .cmd("[t,t,t,t]")
+        .cmd("scene save C")
+        .set_list("A 2\nB 1\nA 1\nC 4")
+        .wait(10)
+        .cmd("play")
+        .wait(1000)
+        .cmd("setlist next")
+        .wait(10000)
+        .cmd("stop")
+        .wait(10);
+    pf.check("set_list_advance");
+}
+#[test]
+fn perform_euclid_pattern() {
+    // L1 は E(3,8) で root を、R1 は E(5,8) を一つ回して、打点ごとに上昇 Arpeggio を鳴らす
+    // 一 step の音価は後ろに書くか、dur= で指定する
+    let mut pf = Performance::new();
+    pf.cmd("set.bpm(120)")
+        .cmd("L1.[E(3,8,R,e)]")
+        .cmd("L1.{I,V}")
+        .cmd("R1.[E(5,8,1,A,dur=e)]")
+        .cmd("R1.{I,V}")
+        .wait(10)
+        .cmd("play")
+        .wait(4100)
+        .cmd("stop")
+        .wait(10);
+    pf.check("euclid_pattern");
+}